@@ -0,0 +1,429 @@
+//! Benchmarks of the core operations and the persistent collections
+//!
+//! The pool file is `$CORUNDUM_BENCH_POOL`, or `corundum_bench.pool` in the
+//! temporary directory. Put it on a DAX file system to measure persistent
+//! memory; otherwise, it measures the software overheads over DRAM.
+//!
+//! ```text
+//! cargo bench --bench corundum
+//! ```
+//!
+//! The `micro` group measures the latency of the basic operations one at a
+//! time, excluding the transaction which surrounds them. Its benchmark names
+//! are the ones reported by `eval/results.sh`.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use corundum::default::{*, Journal};
+use corundum::open_flags::*;
+use corundum::stl::{HashMap, PArt, PBPlusTree, PBitVec, PImMap, PImVector, PLog, PLruCache, PRope};
+use corundum::stm::{Log, Notifier};
+use corundum::sync::{pchannel, PQueue, PReceiver, PSender};
+use std::time::{Duration, Instant};
+
+type P = Allocator;
+
+/// The number of distinct keys of the collections, so that the pool does
+/// not grow with the number of iterations
+const KEYS: u64 = 4096;
+
+struct Root {
+    bx: Pbox<u64>,
+    rc: Prc<u64>,
+    arc: Parc<u64>,
+    cell: PCell<u64>,
+    refcell: PRefCell<u64>,
+    data: Pbox<[u8; 4096]>,
+    bx_slot: PRefCell<Option<Pbox<u64>>>,
+    rc_slot: PRefCell<Option<Prc<u64>>>,
+    arc_slot: PRefCell<Option<Parc<u64>>>,
+    vec: PRefCell<PVec<u64>>,
+    string: PRefCell<PString>,
+    map: PRefCell<HashMap<u64, u64, P>>,
+    btree: PBPlusTree<u64, u64, P>,
+    art: PArt<u64, P>,
+    lru: PLruCache<u64, u64, P>,
+    bits: PBitVec<P>,
+    imvec: PRefCell<PImVector<u64, P>>,
+    immap: PRefCell<PImMap<u64, u64, P>>,
+    plog: PLog<u64, P>,
+    queue: PQueue<u64, P>,
+    tx: PSender<u64, P>,
+    rx: PReceiver<u64, P>,
+    rope: PRefCell<PRope<P>>,
+}
+
+impl RootObj<P> for Root {
+    fn init(j: &Journal) -> Self {
+        let bits = PBitVec::new();
+        bits.resize(KEYS as usize, j);
+        let (tx, rx) = pchannel(16, j);
+        Self {
+            bx: Pbox::new(0, j),
+            rc: Prc::new(0, j),
+            arc: Parc::new(0, j),
+            cell: PCell::new(0),
+            refcell: PRefCell::new(0),
+            data: Pbox::new([0; 4096], j),
+            bx_slot: PRefCell::new(None),
+            rc_slot: PRefCell::new(None),
+            arc_slot: PRefCell::new(None),
+            vec: PRefCell::new(PVec::new()),
+            string: PRefCell::new(PString::from_str("corundum", j)),
+            map: PRefCell::new(HashMap::new(j)),
+            btree: PBPlusTree::new(),
+            art: PArt::new(),
+            lru: PLruCache::new(KEYS as usize / 2, j),
+            bits,
+            imvec: PRefCell::new(PImVector::from_slice(&vec![0; KEYS as usize], j)),
+            immap: PRefCell::new(PImMap::new()),
+            plog: PLog::new(),
+            queue: PQueue::new(j),
+            tx,
+            rx,
+            rope: PRefCell::new(PRope::from_str("corundum", j)),
+        }
+    }
+}
+
+/// Runs `f` and returns its result and its latency
+fn timed<R, F: FnOnce() -> R>(f: F) -> (R, Duration) {
+    let t = Instant::now();
+    let r = f();
+    (r, t.elapsed())
+}
+
+fn alloc(c: &mut Criterion) {
+    let mut g = c.benchmark_group("alloc");
+    for size in [8, 64, 512, 4096].iter() {
+        g.bench_function(format!("alloc_dealloc({})", size), |b| b.iter(|| unsafe {
+            let (p, _, _) = P::alloc(*size);
+            P::dealloc(black_box(p), *size);
+        }));
+    }
+    g.bench_function("pbox_new", |b| b.iter(|| {
+        P::transaction(|j| {
+            black_box(Pbox::new(1u64, j));
+        }).unwrap()
+    }));
+    g.finish();
+}
+
+fn deref(c: &mut Criterion, root: &Root) {
+    c.bench_function("pbox_deref", |b| b.iter(|| black_box(*root.bx)));
+}
+
+fn log(c: &mut Criterion, root: &Root) {
+    let mut g = c.benchmark_group("log");
+    g.bench_function("inline(8)", |b| b.iter(|| {
+        P::transaction(|j| unsafe {
+            Log::create_inline(&*root.bx, j, Notifier::None);
+            j.ignore();
+        }).unwrap()
+    }));
+    g.bench_function("data(512)", |b| b.iter(|| {
+        P::transaction(|j| unsafe {
+            Log::create_slice(&root.data[..512], j, Notifier::None);
+            j.ignore();
+        }).unwrap()
+    }));
+    g.finish();
+}
+
+fn transaction(c: &mut Criterion, root: &Root) {
+    let mut g = c.benchmark_group("transaction");
+    g.bench_function("nop", |b| b.iter(|| P::transaction(|_| {}).unwrap()));
+    g.bench_function("commit(1 update)", |b| b.iter(|| {
+        P::transaction(|j| root.cell.set(root.cell.get() + 1, j)).unwrap()
+    }));
+    g.bench_function("commit(8 updates)", |b| b.iter(|| {
+        P::transaction(|j| {
+            for _ in 0..8 {
+                root.cell.set(root.cell.get() + 1, j);
+            }
+        }).unwrap()
+    }));
+    g.finish();
+}
+
+fn micro(c: &mut Criterion, root: &Root) {
+    let mut g = c.benchmark_group("micro");
+    g.bench_function("TxNop", |b| b.iter(|| P::transaction(|_| {}).unwrap()));
+    g.bench_function("Deref", |b| b.iter(|| black_box(*root.bx)));
+    g.bench_function("DerefMut(1st)", |b| b.iter_custom(|iters| {
+        let mut total = Duration::ZERO;
+        for _ in 0..iters {
+            total += P::transaction(|j| {
+                let mut r = root.refcell.borrow_mut(j);
+                timed(|| *r = black_box(20)).1
+            }).unwrap();
+        }
+        total
+    }));
+    g.bench_function("DerefMut(!1st)", |b| b.iter_custom(|iters| {
+        let mut total = Duration::ZERO;
+        for _ in 0..iters {
+            total += P::transaction(|j| {
+                let mut r = root.refcell.borrow_mut(j);
+                *r = 10;
+                timed(|| *r = black_box(20)).1
+            }).unwrap();
+        }
+        total
+    }));
+
+    for s in [8, 256, 4096].iter() {
+        g.bench_function(format!("Alloc({})", s), |b| b.iter_custom(|iters| unsafe {
+            let mut total = Duration::ZERO;
+            for _ in 0..iters {
+                let ((p, _, _), d) = timed(|| P::alloc(*s));
+                total += d;
+                P::dealloc(p, *s);
+            }
+            total
+        }));
+        g.bench_function(format!("Dealloc({})", s), |b| b.iter_custom(|iters| unsafe {
+            let mut total = Duration::ZERO;
+            for _ in 0..iters {
+                let (p, _, _) = P::alloc(*s);
+                total += timed(|| P::dealloc(p, *s)).1;
+            }
+            total
+        }));
+    }
+
+    g.bench_function("Pbox:AtomicInit", |b| b.iter_custom(|iters| {
+        let mut total = Duration::ZERO;
+        for _ in 0..iters {
+            total += timed(|| Pbox::initialize(&*root.bx_slot.borrow(), 10)).1;
+            P::transaction(|j| { root.bx_slot.replace(None, j); }).unwrap();
+        }
+        total
+    }));
+    g.bench_function("Prc:AtomicInit", |b| b.iter_custom(|iters| {
+        let mut total = Duration::ZERO;
+        for _ in 0..iters {
+            total += timed(|| Prc::initialize(&*root.rc_slot.borrow(), 10)).1;
+            P::transaction(|j| { root.rc_slot.replace(None, j); }).unwrap();
+        }
+        total
+    }));
+    g.bench_function("Parc:AtomicInit", |b| b.iter_custom(|iters| {
+        let mut total = Duration::ZERO;
+        for _ in 0..iters {
+            total += timed(|| Parc::initialize(&*root.arc_slot.borrow(), 10)).1;
+            P::transaction(|j| { root.arc_slot.replace(None, j); }).unwrap();
+        }
+        total
+    }));
+
+    // The logged objects live in the root, because the objects which are
+    // allocated in the same transaction are not logged
+    for s in [8, 1024, 4096].iter() {
+        g.bench_function(format!("DataLog({})", s), |b| b.iter_custom(|iters| {
+            let mut total = Duration::ZERO;
+            for _ in 0..iters {
+                total += P::transaction(|j| unsafe {
+                    let d = timed(|| Log::create_slice(&root.data[..*s], j, Notifier::None)).1;
+                    j.ignore();
+                    d
+                }).unwrap();
+            }
+            total
+        }));
+    }
+    for s in [8, 32768].iter() {
+        g.bench_function(format!("DropLog({})", s), |b| b.iter_custom(|iters| {
+            let mut total = Duration::ZERO;
+            for _ in 0..iters {
+                total += P::transaction(|j| unsafe {
+                    let (_, off, len) = P::alloc(*s);
+                    timed(|| Log::drop_on_commit(off, len, j)).1
+                }).unwrap();
+            }
+            total
+        }));
+    }
+
+    g.bench_function("Pbox:clone", |b| b.iter_custom(|iters| {
+        let mut total = Duration::ZERO;
+        for _ in 0..iters {
+            total += P::transaction(|j| timed(|| root.bx.pclone(j)).1).unwrap();
+        }
+        total
+    }));
+    g.bench_function("Prc:clone", |b| b.iter_custom(|iters| {
+        let mut total = Duration::ZERO;
+        for _ in 0..iters {
+            total += P::transaction(|j| timed(|| root.rc.pclone(j)).1).unwrap();
+        }
+        total
+    }));
+    g.bench_function("Parc:clone", |b| b.iter_custom(|iters| {
+        let mut total = Duration::ZERO;
+        for _ in 0..iters {
+            total += P::transaction(|j| timed(|| root.arc.pclone(j)).1).unwrap();
+        }
+        total
+    }));
+    g.bench_function("Prc:downgrade", |b| b.iter_custom(|iters| {
+        let mut total = Duration::ZERO;
+        for _ in 0..iters {
+            total += P::transaction(|j| timed(|| Prc::downgrade(&root.rc, j)).1).unwrap();
+        }
+        total
+    }));
+    g.bench_function("Parc:downgrade", |b| b.iter_custom(|iters| {
+        let mut total = Duration::ZERO;
+        for _ in 0..iters {
+            total += P::transaction(|j| timed(|| Parc::downgrade(&root.arc, j)).1).unwrap();
+        }
+        total
+    }));
+    g.bench_function("Prc:upgrade", |b| b.iter_custom(|iters| {
+        let mut total = Duration::ZERO;
+        for _ in 0..iters {
+            total += P::transaction(|j| {
+                let w = Prc::downgrade(&root.rc, j);
+                timed(|| w.upgrade(j)).1
+            }).unwrap();
+        }
+        total
+    }));
+    g.bench_function("Parc:upgrade", |b| b.iter_custom(|iters| {
+        let mut total = Duration::ZERO;
+        for _ in 0..iters {
+            total += P::transaction(|j| {
+                let w = Parc::downgrade(&root.arc, j);
+                timed(|| w.upgrade(j)).1
+            }).unwrap();
+        }
+        total
+    }));
+    g.bench_function("Prc:demote", |b| b.iter(|| black_box(Prc::demote(&root.rc))));
+    g.bench_function("Parc:demote", |b| b.iter(|| black_box(root.arc.demote())));
+    g.bench_function("Prc:promote", |b| b.iter_custom(|iters| {
+        let mut total = Duration::ZERO;
+        let w = Prc::demote(&root.rc);
+        for _ in 0..iters {
+            total += P::transaction(|j| timed(|| w.promote(j)).1).unwrap();
+        }
+        total
+    }));
+    g.bench_function("Parc:promote", |b| b.iter_custom(|iters| {
+        let mut total = Duration::ZERO;
+        let w = root.arc.demote();
+        for _ in 0..iters {
+            total += P::transaction(|j| timed(|| w.promote(j)).1).unwrap();
+        }
+        total
+    }));
+    g.finish();
+}
+
+fn collections(c: &mut Criterion, root: &Root) {
+    let mut g = c.benchmark_group("collections");
+    let mut key = 0u64;
+    let mut next = move || {
+        key = (key + 1) % KEYS;
+        key
+    };
+
+    g.bench_function("pvec_push_pop", |b| b.iter(|| {
+        P::transaction(|j| {
+            let mut vec = root.vec.borrow_mut(j);
+            vec.push(1, j);
+            vec.pop();
+        }).unwrap()
+    }));
+    g.bench_function("pstring_push_truncate", |b| b.iter(|| {
+        P::transaction(|j| {
+            let mut s = root.string.borrow_mut(j);
+            let len = s.len();
+            s.push_str("persistent", j);
+            s.truncate(len);
+        }).unwrap()
+    }));
+    g.bench_function("hashmap_put", |b| b.iter_batched(&mut next, |k| {
+        P::transaction(|j| root.map.borrow_mut(j).put(k, k, j)).unwrap()
+    }, BatchSize::SmallInput));
+    g.bench_function("hashmap_get", |b| b.iter_batched(&mut next, |k| {
+        black_box(root.map.borrow().get(k).copied())
+    }, BatchSize::SmallInput));
+    g.bench_function("bptree_insert", |b| b.iter_batched(&mut next, |k| {
+        P::transaction(|j| { root.btree.insert(k, k, j); }).unwrap()
+    }, BatchSize::SmallInput));
+    g.bench_function("bptree_get", |b| b.iter_batched(&mut next, |k| {
+        black_box(root.btree.get(&k).copied())
+    }, BatchSize::SmallInput));
+    g.bench_function("art_insert", |b| b.iter_batched(&mut next, |k| {
+        P::transaction(|j| { root.art.insert(k.to_be_bytes(), k, j); }).unwrap()
+    }, BatchSize::SmallInput));
+    g.bench_function("art_get", |b| b.iter_batched(&mut next, |k| {
+        black_box(root.art.get(k.to_be_bytes()).copied())
+    }, BatchSize::SmallInput));
+    g.bench_function("lru_put", |b| b.iter_batched(&mut next, |k| {
+        P::transaction(|j| { root.lru.put(k, k, j); }).unwrap()
+    }, BatchSize::SmallInput));
+    g.bench_function("bitvec_flip", |b| b.iter_batched(&mut next, |k| {
+        P::transaction(|j| { root.bits.flip(k as usize, j); }).unwrap()
+    }, BatchSize::SmallInput));
+    g.bench_function("imvector_set", |b| b.iter_batched(&mut next, |k| {
+        P::transaction(|j| {
+            let v = root.imvec.borrow().set(k as usize, k, j);
+            root.imvec.replace(v, j);
+        }).unwrap()
+    }, BatchSize::SmallInput));
+    g.bench_function("imvector_get", |b| b.iter_batched(&mut next, |k| {
+        black_box(root.imvec.borrow().get(k as usize).copied())
+    }, BatchSize::SmallInput));
+    g.bench_function("immap_insert", |b| b.iter_batched(&mut next, |k| {
+        P::transaction(|j| {
+            let m = root.immap.borrow().insert(k, k, j);
+            root.immap.replace(m, j);
+        }).unwrap()
+    }, BatchSize::SmallInput));
+    g.bench_function("immap_get", |b| b.iter_batched(&mut next, |k| {
+        black_box(root.immap.borrow().get(&k).copied())
+    }, BatchSize::SmallInput));
+    g.bench_function("plog_append", |b| b.iter_batched(&mut next, |k| {
+        P::transaction(|j| {
+            let i = root.plog.append(k, j);
+            root.plog.truncate_before(i, j);
+        }).unwrap()
+    }, BatchSize::SmallInput));
+    g.bench_function("pqueue_enqueue_dequeue", |b| b.iter_batched(&mut next, |k| {
+        P::transaction(|j| {
+            root.queue.enqueue(k, j);
+            black_box(root.queue.dequeue(j));
+        }).unwrap()
+    }, BatchSize::SmallInput));
+    g.bench_function("pchannel_send_recv", |b| b.iter_batched(&mut next, |k| {
+        P::transaction(|j| root.tx.try_send(k, j)).unwrap().unwrap();
+        black_box(P::transaction(|j| root.rx.try_recv(j)).unwrap());
+    }, BatchSize::SmallInput));
+    g.bench_function("prope_insert_remove", |b| b.iter(|| {
+        P::transaction(|j| {
+            let mut rope = root.rope.borrow_mut(j);
+            let at = rope.len() / 2;
+            rope.insert_str(at, "persistent", j);
+            rope.remove(at..at + 10, j);
+        }).unwrap()
+    }));
+    g.finish();
+}
+
+fn all(c: &mut Criterion) {
+    let path = std::env::var("CORUNDUM_BENCH_POOL").unwrap_or_else(|_| {
+        std::env::temp_dir().join("corundum_bench.pool").to_string_lossy().into_owned()
+    });
+    let root = P::open::<Root>(&path, O_CF | O_1GB).unwrap();
+    alloc(c);
+    deref(c, &root);
+    log(c, &root);
+    transaction(c, &root);
+    micro(c, &root);
+    collections(c, &root);
+}
+
+criterion_group!(benches, all);
+criterion_main!(benches);
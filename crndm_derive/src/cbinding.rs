@@ -25,7 +25,7 @@ pub struct Contents {
     decl: String,
     alias: String,
     traits: HashMap<PoolName, String>,
-    funcs: Vec<(FuncName, FuncArgs, FuncSig, Template, TypeName, bool, bool, bool, bool)>,
+    funcs: Vec<(FuncName, FuncArgs, FuncSig, Template, TypeName, bool, bool, bool, bool, bool)>,
     pools: std::collections::HashSet<String>,
     generics: Vec<String>,
     attrs: Attributes
@@ -106,6 +106,35 @@ fn check_type(ty: &Type, pool_type: &Ident, gen_idents: &Vec<Ident>, warn: bool)
     }
 }
 
+/// The C type of a primitive Rust type, or `void` for the other types
+fn c_type(ty: &Type) -> &'static str {
+    match quote!(#ty).to_string().as_str() {
+        "u8" => "u_int8_t",
+        "u16" => "u_int16_t",
+        "u32" => "u_int32_t",
+        "u64" => "u_int64_t",
+        "i8" => "int8_t",
+        "i16" => "int16_t",
+        "i32" => "int32_t",
+        "i64" => "int64_t",
+        "usize" => "size_t",
+        "isize" => "ssize_t",
+        "f32" => "float",
+        "f64" => "double",
+        "bool" => "bool",
+        _ => "void"
+    }
+}
+
+/// The C name of a field; the fields of tuple variants are named `_0`, `_1`, ...
+fn c_field(field: &str) -> String {
+    if field.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", field)
+    } else {
+        field.to_owned()
+    }
+}
+
 pub fn derive_cbindgen(input: TokenStream) -> TokenStream {
     // Parse the input tokens into a syntax tree.
     let input = parse_macro_input!(input as DeriveInput);
@@ -225,15 +254,21 @@ pub fn derive_cbindgen(input: TokenStream) -> TokenStream {
     }
     let pool_type = found_pool_generic.expect(&format!("{}", line!()));
 
+    // The variants of an enum and their fields, which are exported as a tagged
+    // union of pointers to the fields
+    let mut variants: Vec<(Ident, Vec<(String, Type)>)> = vec![];
     if let Data::Struct(s) = input.data {
         for f in s.fields {
             check_type(&f.ty, &pool_type, &gen_idents, warn_bare_generics);
         }
     } else if let Data::Enum(e) = input.data {
         for v in e.variants {
-            for f in v.fields {
+            let mut fields = vec![];
+            for (i, f) in v.fields.into_iter().enumerate() {
                 check_type(&f.ty, &pool_type, &gen_idents, warn_bare_generics);
+                fields.push((f.ident.map_or(i.to_string(), |id| id.to_string()), f.ty));
             }
+            variants.push((v.ident, fields));
         }
     } else {
         abort_call_site!("`Export` cannot be derived for `union`")
@@ -327,8 +362,72 @@ pub fn derive_cbindgen(input: TokenStream) -> TokenStream {
         let fn_new = format_ident!("{}_new", name_str);
         let fn_drop = format_ident!("{}_drop", name_str);
         let fn_open = format_ident!("{}_open", name_str);
+        let fn_handle = format_ident!("{}_handle", name_str);
+        let fn_resolve = format_ident!("{}_resolve", name_str);
         let mod_name = format_ident!("{}_{}", name_str, pool);
 
+        let mut enum_fns = vec![];
+        let mut enum_traits = "".to_owned();
+        if !variants.is_empty() {
+            let fn_tag = format_ident!("{}_tag", name_str);
+            let arms = variants.iter().enumerate().map(|(i, (v, _))| {
+                let i = i as u32;
+                quote!(#name::#v { .. } => #i)
+            });
+            enum_fns.push(quote! {
+                #[no_mangle]
+                pub extern "C" fn #fn_tag(obj: u64) -> u32 {
+                    let obj = corundum::gen::from_handle::<#m, #new_name<#m>>(obj);
+                    if obj.is_null() {
+                        return u32::MAX;
+                    }
+                    match unsafe { &*obj } {
+                        #(#arms,)*
+                    }
+                }
+            });
+            enum_traits += &format!("
+    static u_int32_t tag(const {name}<{pool}> *obj) {{
+        return {fn_tag}({fn_handle}(obj));
+    }}",
+                name = new_name,
+                pool = pool,
+                fn_tag = fn_tag,
+                fn_handle = fn_handle
+            );
+            for (v, fields) in &variants {
+                for (f, _) in fields {
+                    let get = format!("{}_{}", v.to_string().to_lowercase(), f);
+                    let fn_get = format_ident!("{}_{}", name_str, get);
+                    let field: Member = parse_str(f).expect(&format!("{}", line!()));
+                    enum_fns.push(quote! {
+                        #[no_mangle]
+                        #[allow(unreachable_patterns)]
+                        pub extern "C" fn #fn_get(obj: u64) -> *const corundum::c_void {
+                            let obj = corundum::gen::from_handle::<#m, #new_name<#m>>(obj);
+                            if obj.is_null() {
+                                return std::ptr::null();
+                            }
+                            match unsafe { &*obj } {
+                                #name::#v { #field: x, .. } => x as *const _ as *const corundum::c_void,
+                                _ => std::ptr::null()
+                            }
+                        }
+                    });
+                    enum_traits += &format!("
+    static const void *{get}(const {name}<{pool}> *obj) {{
+        return {fn_get}({fn_handle}(obj));
+    }}",
+                        get = get,
+                        name = new_name,
+                        pool = pool,
+                        fn_get = fn_get,
+                        fn_handle = fn_handle
+                    );
+                }
+            }
+        }
+
 
         expanded.push(quote! {
             pub mod #mod_name {
@@ -343,29 +442,46 @@ pub fn derive_cbindgen(input: TokenStream) -> TokenStream {
                 type #m = super::#m::Allocator;
 
                 #[no_mangle]
-                pub extern "C" fn #fn_new(#(#new_sizes: usize,)* j: *const c_void) -> *const #new_name<#m> {
+                pub extern "C" fn #fn_handle(obj: *const #new_name<#m>) -> u64 {
+                    corundum::gen::handle_of::<#m, _>(obj)
+                }
+
+                #[no_mangle]
+                pub extern "C" fn #fn_resolve(obj: u64) -> *const #new_name<#m> {
+                    corundum::gen::from_handle::<#m, _>(obj)
+                }
+
+                #[no_mangle]
+                pub extern "C" fn #fn_new(#(#new_sizes: usize,)* j: *const c_void) -> u64 {
                     use corundum::Pbox;
                     use corundum::MemPoolTraits;
 
                     assert!(!j.is_null(), "transactional operation outside a transaction");
                     unsafe {
                         let j = corundum::utils::read::<corundum::stm::Journal<#m>>(j as *mut u8);
-                        #m::new(#new_name::new(#(#new_sizes,)* j), j)
+                        let obj = #m::new(#new_name::new(#(#new_sizes,)* j), j);
+                        corundum::gen::handle_of::<#m, #new_name<#m>>(obj)
                     }
                 }
 
                 #[no_mangle]
-                pub extern "C" fn #fn_drop(obj: *mut #new_name<#m>) {
+                pub extern "C" fn #fn_drop(obj: u64) -> bool {
                     use corundum::Pbox;
+                    use corundum::MemPoolTraits;
 
-                    assert!(!obj.is_null(),);
+                    let ptr = corundum::gen::from_handle::<#m, #new_name<#m>>(obj);
+                    if ptr.is_null() || corundum::gen::is_pinned::<#m, _>(unsafe { &*ptr }) {
+                        return false;
+                    }
+                    #m::unregister(obj);
                     unsafe {
-                        Pbox::<#new_name<#m>,#m>::from_raw(obj); // drops when out of scope
+                        Pbox::<#new_name<#m>,#m>::from_raw(ptr); // drops when out of scope
                     }
+                    true
                 }
 
                 #[no_mangle]
-                pub extern "C" fn #fn_open(p: &#__m, #(#new_sizes: usize,)* name: *const c_char) -> *const #new_name<#m> {
+                pub extern "C" fn #fn_open(p: &#__m, #(#new_sizes: usize,)* name: *const c_char) -> u64 {
                     let name = unsafe { CStr::from_ptr(name).to_str().expect(&format!("{}", line!())) };
                     let mut hasher = DefaultHasher::new();
                     name.hash(&mut hasher);
@@ -383,8 +499,10 @@ pub fn derive_cbindgen(input: TokenStream) -> TokenStream {
                     })).is_err() {
                         res = std::ptr::null();
                     }
-                    res
+                    corundum::gen::handle_of::<#m, _>(res)
                 }
+
+                #(#enum_fns)*
             }
         });
 
@@ -396,16 +514,17 @@ template<>
 struct {small_name}_traits<{pool}> {{
     typedef typename pool_traits<{pool}>::journal journal;
     static const {name}<{pool}>* __create({size_list_arg}const journal *j) {{
-        return {fn_new}({size_list}j);
+        return {fn_resolve}({fn_new}({size_list}j));
     }}
-    static void drop({name}<{pool}> *obj) {{
-        {fn_drop}(obj);
+    static bool drop({name}<{pool}> *obj) {{
+        return {fn_drop}({fn_handle}(obj));
     }}
     static const {name}<{pool}>* open(const {root_name} *p, {size_list_arg}const char *name) {{
-        return {fn_open}(p, {size_list}name);
-    }}
+        return {fn_resolve}({fn_open}(p, {size_list}name));
+    }}{enum_traits}
     // specialized methods
 }};\n",
+enum_traits = enum_traits,
 small_name = small_name,
 name = new_name,
 pool = pool,
@@ -414,10 +533,84 @@ size_list_arg = size_list_arg,
 fn_new = fn_new.to_string(),
 fn_open = fn_open.to_string(),
 fn_drop = fn_drop.to_string(),
+fn_handle = fn_handle.to_string(),
+fn_resolve = fn_resolve.to_string(),
 root_name = __m.to_string()
         ));
     }
 
+    let mut enum_decls = "".to_owned();
+    let mut enum_methods = "".to_owned();
+    if !variants.is_empty() {
+        let tags: Vec<String> = variants.iter().enumerate()
+            .map(|(i, (v, _))| format!("{} = {}", v, i)).collect();
+        let mut union = "".to_owned();
+        let mut cases = "".to_owned();
+        let mut is_fns = "".to_owned();
+        enum_decls += &format!("
+    static u_int32_t tag(const {name}<_P> *obj);", name = new_name);
+        for (v, fields) in &variants {
+            is_fns += &format!("
+
+    inline bool is_{v}() const {{
+        return tag() == tag_t::{v};
+    }}", v = v);
+            if fields.is_empty() {
+                continue;
+            }
+            let mut members = "".to_owned();
+            cases += &format!("
+        case tag_t::{v}:", v = v);
+            for (f, ty) in fields {
+                let get = format!("{}_{}", v.to_string().to_lowercase(), f);
+                enum_decls += &format!("
+    static const void *{get}(const {name}<_P> *obj);", get = get, name = new_name);
+                members += &format!(" const {ty} *{f};", ty = c_type(ty), f = c_field(f));
+                cases += &format!("
+            v.{v}.{f} = (const {ty}*){small_name}_traits<_P>::{get}(self());",
+                    v = v, f = c_field(f), ty = c_type(ty), small_name = small_name, get = get);
+            }
+            union += &format!("
+            struct {{{members} }} {v};", members = members, v = v);
+            cases += "
+            break;";
+        }
+        let union = if union.is_empty() { "".to_owned() } else {
+            format!("
+        union {{{union}
+        }};", union = union)
+        };
+        enum_methods = format!("
+
+    enum class tag_t : u_int32_t {{ {tags} }};
+
+    // A tagged union of pointers to the fields of the variant; it is valid
+    // as long as the object is not modified
+    struct view_t {{
+        tag_t tag;{union}
+    }};
+
+    inline tag_t tag() const {{
+        return (tag_t){small_name}_traits<_P>::tag(self());
+    }}{is_fns}
+
+    inline view_t view() const {{
+        view_t v;
+        v.tag = tag();
+        switch (v.tag) {{{cases}
+        default:
+            break;
+        }}
+        return v;
+    }}",
+            tags = tags.join(", "),
+            union = union,
+            small_name = small_name,
+            is_fns = is_fns,
+            cases = cases
+        );
+    }
+
     entry.contents = format!(
         "// This file is auto-generated by Corundum. Do not modify.
 #pragma once
@@ -435,8 +628,8 @@ template < class _P >
 struct {small_name}_traits {{
     typedef typename pool_traits<_P>::journal journal;
     static const {name}<_P>* __create({size_list_arg}const journal *j);
-    static void drop({name}<_P> *obj);
-    static const {name}<_P>* open(const void *p, {size_list_arg}const char *name);
+    static bool drop({name}<_P> *obj);
+    static const {name}<_P>* open(const void *p, {size_list_arg}const char *name);{enum_decls}
     // template constructor
     // template methods
 }};
@@ -508,14 +701,15 @@ public:
                 assert(objs.find(n) != objs.end(), \"'%s' is not open\", n);
                 objs.erase(n);
             }} else {{
-                {small_name}_traits<_P>::drop(
+                bool dropped = {small_name}_traits<_P>::drop(
                     static_cast<{name}<_P>*>(
                         static_cast<void*>(inner)
                     )
                 );
+                assert(dropped, \"object is borrowed by a live cursor\");
             }}
         }}
-    }}{guard_fn}
+    }}{guard_fn}{enum_methods}
 
     // other methods
 }};
@@ -533,7 +727,9 @@ cname = cname,
 conc_decl = conc_decl,
 lock = lock,
 guard_fn = guard_fn,
-other_lock = other_lock
+other_lock = other_lock,
+enum_decls = enum_decls,
+enum_methods = enum_methods
 );
     entry.decl = format!("{template} class {name};",
             name = name,
@@ -804,7 +1000,59 @@ pub fn cbindgen(_attr: TokenStream, item: TokenStream) -> TokenStream {
                 });
             }
 
-            for fn_item in imp.items {
+            // Public associated constants are exported as functions without
+            // arguments which return their values
+            let mut consts = vec![];
+            // Public `&self` methods returning iterators are exported as a
+            // cursor API: `{method}_iter_new` creates a cursor, and
+            // `{method}_iter_next` and `{method}_iter_free` use it
+            let mut iters: HashMap<Ident, Ident> = HashMap::new();
+            let items: Vec<ImplItem> = imp.items.into_iter().flat_map(|item| match item {
+                ImplItem::Const(c) if matches!(c.vis, Visibility::Public(_)) => {
+                    let (ident, ty) = (&c.ident, &c.ty);
+                    if quote!(#ty).to_string() == "Self" {
+                        emit_error!(ty.span(), "associated constants of type `Self` cannot be exported");
+                    }
+                    consts.push(ident.clone());
+                    vec![ImplItem::Method(parse2(quote!{
+                        pub fn #ident() -> #ty { Self::#ident }
+                    }).expect(&format!("{}", line!())))]
+                }
+                ImplItem::Method(f) if matches!(f.vis, Visibility::Public(_)) && iter_item(&f.sig.output).is_some() => {
+                    let mut item = iter_item(&f.sig.output).expect(&format!("{}", line!()));
+                    if let Type::Reference(r) = &mut item {
+                        r.lifetime = None;
+                    }
+                    if !matches!(f.sig.inputs.first(), Some(FnArg::Receiver(r)) if r.reference.is_some() && r.mutability.is_none()) {
+                        emit_error!(f.sig.span(), "invalid iterator";
+                            note = "a method returning an iterator should take `&self`"
+                        );
+                        return vec![];
+                    }
+                    let ident = &f.sig.ident;
+                    let fn_new = format_ident!("{}_iter_new", ident);
+                    let fn_next = format_ident!("{}_iter_next", ident);
+                    let fn_free = format_ident!("{}_iter_free", ident);
+                    let args = f.sig.inputs.iter().skip(1);
+                    iters.insert(fn_new.clone(), ident.clone());
+                    iters.insert(fn_next.clone(), ident.clone());
+                    iters.insert(fn_free.clone(), ident.clone());
+                    vec![
+                        parse2(quote!{
+                            pub fn #fn_new(&self, #(#args),*) -> *mut corundum::c_void { unreachable!() }
+                        }).expect(&format!("{}", line!())),
+                        parse2(quote!{
+                            pub fn #fn_next(cursor: *mut corundum::c_void, out: *mut #item) -> bool { unreachable!() }
+                        }).expect(&format!("{}", line!())),
+                        parse2(quote!{
+                            pub fn #fn_free(cursor: *mut corundum::c_void) { unreachable!() }
+                        }).expect(&format!("{}", line!()))
+                    ]
+                }
+                item => vec![item]
+            }).collect();
+
+            for fn_item in items {
                 if let ImplItem::Method(func) = fn_item {
                     if let Visibility::Public(_) = &func.vis {
                         let mut spc = func.clone();
@@ -834,8 +1082,9 @@ pub fn cbindgen(_attr: TokenStream, item: TokenStream) -> TokenStream {
                                 gen.push(i.to_string());
                             }
                         }
+                        let is_static = !is_constructor && !matches!(spc.sig.inputs.first(), Some(FnArg::Receiver(_)));
                         {
-                            if is_constructor {
+                            if is_constructor || is_static {
                                 let mut i = spc.sig.inputs.iter_mut();
                                 if let Some(a) = i.nth(0) {
                                     if let FnArg::Typed(PatType { pat, ty, .. }) = a {
@@ -891,7 +1140,7 @@ pub fn cbindgen(_attr: TokenStream, item: TokenStream) -> TokenStream {
                             }
                             continue;
                         }
-                        let mut is_const = true;
+                        let mut is_const = !is_static;
                         if let Some(first) = spc.sig.inputs.first() {
                             if let FnArg::Receiver(rc) = first {
                                 if is_constructor {
@@ -926,7 +1175,8 @@ pub fn cbindgen(_attr: TokenStream, item: TokenStream) -> TokenStream {
                             spc.sig.output != ReturnType::Default,
                             output_has_generics,
                             is_constructor,
-                            is_const
+                            is_const,
+                            is_static
                         ));
     
                         for m in &entry.pools {
@@ -947,11 +1197,16 @@ pub fn cbindgen(_attr: TokenStream, item: TokenStream) -> TokenStream {
                                 ext.sig.abi = Some(abi);
                             }
                             let mut has_receiver = false;
+                            let mut is_mut = false;
                             if let Some(first) = ext.sig.inputs.first_mut() {
                                 if let FnArg::Receiver(rc) = first {
                                     has_receiver = true;
-                                    let mt = rc.mutability;
-                                    if let Ok(arg) = parse2::<FnArg>(quote!(__self: &#mt #new_name<#m>)) {
+                                    is_mut = rc.mutability.is_some();
+                                    // The receiver is taken by its handle, so
+                                    // that a stale handle or a call to a
+                                    // `&mut self` method on a borrowed object
+                                    // can be refused
+                                    if let Ok(arg) = parse2::<FnArg>(quote!(__self: u64)) {
                                         *first = arg;
                                     }
                                 }
@@ -983,14 +1238,15 @@ pub fn cbindgen(_attr: TokenStream, item: TokenStream) -> TokenStream {
                                 expanded.push(quote!{
                                     #[no_mangle]
                                     #[deny(improper_ctypes_definitions)]
-                                    pub extern "C" fn #fname(#(#args,)* j: *const corundum::c_void) -> *mut #new_name<#m> {
+                                    pub extern "C" fn #fname(#(#args,)* j: *const corundum::c_void) -> u64 {
                                         use corundum::Pbox;
                                         use corundum::MemPoolTraits;
 
                                         assert!(!j.is_null(), "transactional operation outside a transaction");
                                         unsafe {
                                             let j = corundum::utils::read::<corundum::stm::Journal<#m>>(j as *mut u8);
-                                            #m::new(#new_name::#ident(#(#vals,)* j), j)
+                                            let obj = #m::new(#new_name::#ident(#(#vals,)* j), j);
+                                            corundum::gen::handle_of::<#m, #new_name<#m>>(obj)
                                         }
                                     }
                                 });
@@ -1010,9 +1266,45 @@ pub fn cbindgen(_attr: TokenStream, item: TokenStream) -> TokenStream {
                                 if let ReturnType::Type(_, ty) = &mut ext.sig.output {
                                     check_generics(&quote!(#m), ty, &gen, &pool_type, &entry.generics, 1, true, &mut None, &func.sig.ident);
                                 }
-                                ext.block = parse2(quote!{{
-                                    __self.#fname(#(#args,)*)
-                                }}).expect(&format!("{}", line!()));
+                                let refused = refused_value(&func.sig.output);
+                                if is_mut && refused.is_none() {
+                                    emit_error!(func.sig.span(), "invalid mutable method";
+                                        note = "a method taking `&mut self` should return nothing, a `bool`, or a raw pointer, so that the call can be refused while the object is borrowed"
+                                    );
+                                    continue;
+                                }
+                                let refuse = match &refused {
+                                    Some(v) => quote!(return #v),
+                                    None => quote!(panic!("stale handle to {}", stringify!(#name)))
+                                };
+                                let resolve = quote! {
+                                    let __self = corundum::gen::from_handle::<#m, #new_name<#m>>(__self);
+                                    if __self.is_null() {
+                                        #refuse;
+                                    }
+                                };
+                                ext.block = if let Some(iter) = iters.get(&fname) {
+                                    parse2(quote!{{
+                                        #resolve
+                                        unsafe {
+                                            corundum::gen::Cursor::new::<#m, _, _, _>(&*__self,
+                                                move |__self| __self.#iter(#(#args,)*))
+                                        }
+                                    }})
+                                } else if is_mut {
+                                    parse2(quote!{{
+                                        #resolve
+                                        if corundum::gen::is_pinned::<#m, _>(unsafe { &*__self }) {
+                                            #refuse;
+                                        }
+                                        unsafe { &mut *__self }.#fname(#(#args,)*)
+                                    }})
+                                } else {
+                                    parse2(quote!{{
+                                        #resolve
+                                        unsafe { &*__self }.#fname(#(#args,)*)
+                                    }})
+                                }.expect(&format!("{}", line!()));
     
                                 expanded.push(quote!{
                                     #[no_mangle]
@@ -1020,8 +1312,44 @@ pub fn cbindgen(_attr: TokenStream, item: TokenStream) -> TokenStream {
                                     #ext
                                 });
                             } else {
-                                emit_error!(func.span(), "external functions should have a receiver (i.e., `self` argument)");
-                                break;
+                                let fname = func.sig.ident.clone();
+                                let mut args = vec![];
+                                for arg in &mut ext.sig.inputs {
+                                    if let FnArg::Typed(PatType { pat, ty, .. }) = arg {
+                                        if let Pat::Ident(PatIdent { ident, .. }) = &mut **pat {
+                                            check_generics(&quote!(#m), ty, &gen, &pool_type, &entry.generics, 1, true, &mut None, ident);
+                                            args.push(quote!(#ident));
+                                        }
+                                    }
+                                }
+                                if let ReturnType::Type(_, ty) = &mut ext.sig.output {
+                                    check_generics(&quote!(#m), ty, &gen, &pool_type, &entry.generics, 1, true, &mut None, &func.sig.ident);
+                                }
+                                ext.block = if consts.contains(&fname) {
+                                    parse2(quote!{{
+                                        #new_name::<#m>::#fname
+                                    }})
+                                } else if iters.contains_key(&fname) {
+                                    if fname.to_string().ends_with("_iter_next") {
+                                        parse2(quote!{{
+                                            unsafe { corundum::gen::Cursor::next(cursor, out) }
+                                        }})
+                                    } else {
+                                        parse2(quote!{{
+                                            unsafe { corundum::gen::free_cursor(cursor) }
+                                        }})
+                                    }
+                                } else {
+                                    parse2(quote!{{
+                                        #new_name::<#m>::#fname(#(#args,)*)
+                                    }})
+                                }.expect(&format!("{}", line!()));
+
+                                expanded.push(quote!{
+                                    #[no_mangle]
+                                    #[deny(improper_ctypes_definitions)]
+                                    #ext
+                                });
                             }
                         }
                     }
@@ -1045,6 +1373,46 @@ pub fn cbindgen(_attr: TokenStream, item: TokenStream) -> TokenStream {
     // item
 }
 
+/// The value returned by a refused call to an exported `&mut self` method
+fn refused_value(output: &ReturnType) -> Option<TokenStream2> {
+    match output {
+        ReturnType::Default => Some(quote!(())),
+        ReturnType::Type(_, ty) => match &**ty {
+            Type::Tuple(t) if t.elems.is_empty() => Some(quote!(())),
+            Type::Path(p) if p.path.is_ident("bool") => Some(quote!(false)),
+            Type::Ptr(p) if p.mutability.is_some() => Some(quote!(std::ptr::null_mut())),
+            Type::Ptr(_) => Some(quote!(std::ptr::null())),
+            _ => None
+        }
+    }
+}
+
+/// The item type of an `impl Iterator<Item = T>` return type
+fn iter_item(output: &ReturnType) -> Option<Type> {
+    if let ReturnType::Type(_, ty) = output {
+        if let Type::ImplTrait(it) = &**ty {
+            for bound in &it.bounds {
+                if let TypeParamBound::Trait(t) = bound {
+                    if let Some(seg) = t.path.segments.last() {
+                        if seg.ident == "Iterator" {
+                            if let PathArguments::AngleBracketed(args) = &seg.arguments {
+                                for arg in &args.args {
+                                    if let GenericArgument::Binding(b) = arg {
+                                        if b.ident == "Item" {
+                                            return Some(b.ty.clone());
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
 fn parse_c_fn(sig: &str, name: &str) -> (String, Vec<String>) {
     fn is_word(c: char) -> bool {
         (c >= 'a' && c <= 'z') ||
@@ -1180,7 +1548,7 @@ pub fn export(dir: PathBuf, span: proc_macro2::Span, overwrite: bool, warning: b
         auto __guard = guard();" } else { "" };
         let mut cbindfile = "".to_owned();
         // let mut funcs = vec!();
-        for (_, _, f, _, _, _, _, _, _) in &mut cnt.funcs {
+        for (_, _, f, _, _, _, _, _, _, _) in &mut cnt.funcs {
             let re = Regex::new(&format!(r"\#\[no_mangle\].*")).expect(&format!("{}", line!()));
             if re.find(f).is_some() {
                 let re = Regex::new(&format!(r"\bGen\b\s*<\s*(\w+)\s*>")).expect(&format!("{}", line!()));
@@ -1213,7 +1581,7 @@ pub fn export(dir: PathBuf, span: proc_macro2::Span, overwrite: bool, warning: b
             bindings.write_to_file("/tmp/___corundum_tmp_file.h");
             let s = read_to_string("/tmp/___corundum_tmp_file.h")?;
 
-            for (name, fn_args, sig, tmp, ty_pool, has_return, _, is_cons, is_const) in &mut cnt.funcs {
+            for (name, fn_args, sig, tmp, ty_pool, has_return, _, is_cons, is_const, is_static) in &mut cnt.funcs {
                 let tmpl = if tmp.is_empty() { "".to_owned() } else {
                     format!("template < class {} > ", tmp.join(", class "))
                 };
@@ -1255,6 +1623,20 @@ pub fn export(dir: PathBuf, span: proc_macro2::Span, overwrite: bool, warning: b
                             args = args,
                             lock = lock,
                         );
+                    } else if *is_static {
+                        cnt.contents = cnt.contents.replace("    // template methods",
+                            &format!("    // template methods\n    {}static {};", tmpl, sig));
+                        append = format!("    // other methods\n    static {sig} {{
+        {ret}{ty}_traits<_P>::{tmp}{fn}{gen}({args});
+    }}\n",
+                            ret = if *has_return { "return " } else { "" },
+                            ty = ty.to_lowercase(),
+                            sig = sig,
+                            tmp = tmpl_kw,
+                            gen = gen,
+                            fn = name,
+                            args = args,
+                        );
                     } else {
                         cnt.contents = cnt.contents.replace("    // template methods",
                             &format!("    // template methods\n    {}static {};",
@@ -1319,7 +1701,7 @@ pub fn export(dir: PathBuf, span: proc_macro2::Span, overwrite: bool, warning: b
         }
 
         for (p, contents) in &mut cnt.traits {
-            for (f, args, sig, tmp, _, ret, ret_gen, is_cons, is_const) in &mut cnt.funcs {
+            for (f, args, sig, tmp, _, ret, ret_gen, is_cons, is_const, is_static) in &mut cnt.funcs {
                 let (cret, cargs) = parse_c_fn(sig, f);
                 let re = Regex::new(r"\bGen\b").expect(&format!("{}", line!()));
                 let cast = if *ret_gen && re.find(&cret).is_none() {
@@ -1359,13 +1741,26 @@ pub fn export(dir: PathBuf, span: proc_macro2::Span, overwrite: bool, warning: b
                                 &format!("{}(", f),
                                 &format!("{fn}(carbide::pointer_t<__{ty}<{pool}>, {pool}>* __self_ptr, ", fn=f, ty=ty, pool=p), 1)
                                 .replace(", )", ")"),
-                            &format!("*__self_ptr = carbide::pointer_t<__{ty}<{pool}>, {pool}>::from_unsafe((void*)__{pool}_{type}_{fn}({args}));",
+                            &format!("*__self_ptr = carbide::pointer_t<__{ty}<{pool}>, {pool}>::from_unsafe((void*)__{pool}_{type}_resolve(__{pool}_{type}_{fn}({args})));",
                                 pool = p,
                                 ty = ty,
                                 type = ty.to_lowercase(),
                                 fn = f,
                                 args = args)
                             ));
+                } else if *is_static {
+                    *contents = contents.replace("    // specialized methods",
+                        &format!("    // specialized methods\n    {}static {} {{\n        {}\n    }}",
+                            tmp,
+                            sig,
+                            &format!("{ret}{cast}__{pool}_{type}_{fn}({args});",
+                                ret = if *ret { "return " } else { "" },
+                                pool = p,
+                                type = ty.to_lowercase(),
+                                fn = f,
+                                cast = cast,
+                                args = args)
+                            ));
                 } else {
                     *contents = contents.replace("    // specialized methods",
                         &format!("    // specialized methods\n    {}static {} {{\n        {}\n    }}",
@@ -1378,7 +1773,7 @@ pub fn export(dir: PathBuf, span: proc_macro2::Span, overwrite: bool, warning: b
                                 p=p,
                                 const = if *is_const { "const " } else { "" }), 1)
                                 .replace(", )", ")"),
-                            &format!("{ret}{cast}__{pool}_{type}_{fn}(__self{comma}{args});",
+                            &format!("{ret}{cast}__{pool}_{type}_{fn}(__{pool}_{type}_handle(__self){comma}{args});",
                                 ret = if *ret { "return " } else { "" },
                                 pool = p,
                                 type = ty.to_lowercase(),
@@ -1556,6 +1951,9 @@ pub fn carbide(input: TokenStream) -> TokenStream {
         let fn_print_info = format_ident!("{}_print_info", name_str);
         let fn_used = format_ident!("{}_used", name_str);
         let fn_read64 = format_ident!("{}_read64", name_str);
+        let fn_register = format_ident!("{}_register", name_str);
+        let fn_resolve = format_ident!("{}_resolve", name_str);
+        let fn_unregister = format_ident!("{}_unregister", name_str);
         let named_open = format_ident!("{}_named_open", name_str);
         let named_data_pointer = format_ident!("{}_named_data_pointer", name_str);
         let named_logged_pointer = format_ident!("{}_named_logged_pointer", name_str);
@@ -1750,6 +2148,27 @@ pub fn carbide(input: TokenStream) -> TokenStream {
                     unsafe { *Allocator::get_unchecked(addr) }
                 }
 
+                #[no_mangle]
+                pub extern "C" fn #fn_register(ptr: *const c_void, size: usize) -> u64 {
+                    match Allocator::off(ptr) {
+                        Ok(off) => Allocator::register_off(off, size).unwrap_or(0),
+                        Err(_) => 0
+                    }
+                }
+
+                #[no_mangle]
+                pub extern "C" fn #fn_resolve(handle: u64) -> *const c_void {
+                    match Allocator::resolve_off(handle) {
+                        Some(off) => unsafe { Allocator::get_unchecked::<c_void>(off) as *const c_void },
+                        None => std::ptr::null()
+                    }
+                }
+
+                #[no_mangle]
+                pub extern "C" fn #fn_unregister(handle: u64) -> bool {
+                    Allocator::unregister(handle)
+                }
+
                 pub struct Named(u8, ByteArray<corundum::c_void, Allocator>);
 
                 #[no_mangle]
@@ -1822,6 +2241,17 @@ struct pool_traits<{pool}> {{
     static const journal* journal_handle() {{
         return (const journal*) {pool_journal}(false);
     }}
+
+    // Opaque handles which remain valid if the pool is mapped elsewhere
+    static u_int64_t register_handle(const void *ptr, size_t size) {{
+        return {pool_register}(ptr, size);
+    }}
+    static const void *resolve_handle(u_int64_t handle) {{
+        return {pool_resolve}(handle);
+    }}
+    static bool unregister_handle(u_int64_t handle) {{
+        return {pool_unregister}(handle);
+    }}
 private:
     static size_t base;
     static u_int32_t gen;
@@ -1953,6 +2383,9 @@ pool_valid = fn_valid.to_string(),
 pool_print_info = fn_print_info.to_string(),
 pool_log = fn_log.to_string(),
 pool_used = fn_used.to_string(),
+pool_register = fn_register.to_string(),
+pool_resolve = fn_resolve.to_string(),
+pool_unregister = fn_unregister.to_string(),
 pool_journal = fn_journal.to_string(),
 pool_txn_running = fn_txn_running.to_string(),
 pool_open = fn_open.to_string(),
@@ -20,6 +20,7 @@ extern crate proc_macro_error;
 mod pclone;
 mod root;
 mod cbinding;
+mod transactional;
 
 #[proc_macro_error]
 #[proc_macro_derive(PClone, attributes(pools))]
@@ -28,23 +29,78 @@ pub fn derive_pclone(input: TokenStream) -> TokenStream {
 }
 
 #[proc_macro_error]
-#[proc_macro_derive(Root, attributes(pools))]
+#[proc_macro_derive(Root, attributes(pools, default))]
 pub fn derive_root(input: TokenStream) -> TokenStream {
     root::derive_root(input)
 }
 
+/// Exports a persistent type to C++ as a class generic over the pool
+///
+/// The C functions take and return objects by their handles (see
+/// `MemPoolTraits::register`) instead of raw pointers, so that they remain
+/// valid if the pool is mapped at a different address. `__{pool}_{type}_handle`
+/// and `__{pool}_{type}_resolve` convert between pointers and handles, and
+/// the C++ class does it internally.
+///
+/// An `enum` is exported as a class with a `tag_t` enum class of its
+/// variants, `tag()` and `is_{Variant}()` methods, and a `view()` method
+/// which returns a tagged union of pointers to the fields of the current
+/// variant. Fields of primitive types have their C types; the others are
+/// `const void*`. For a stale handle, `tag()` returns `u32::MAX` and the field
+/// getters return null.
 #[proc_macro_error]
 #[proc_macro_derive(Export, attributes(mods,attrs))]
 pub fn derive_cbindgen(input: TokenStream) -> TokenStream {
     cbinding::derive_cbindgen(input)
 }
 
+/// Exports the public methods of an `impl` block of an `Export` type to C++
+///
+/// Every public method becomes an `extern "C"` function per pool, named
+/// `__{pool}_{type}_{method}`, and a member of the generated C++ class.
+/// Methods take the receiver by its handle, and constructors return one.
+/// Functions that return `Self` are constructors. The other functions without
+/// a receiver, and the public associated constants, become static members;
+/// a constant `C` is exported as a function `C()` which returns its value.
+///
+/// A `&self` method `m` returning `impl Iterator<Item = T>` is exported as a
+/// cursor: `m_iter_new(...)` returns an opaque cursor, `m_iter_next(cursor,
+/// out)` writes the next item to `out` and returns `false` at the end, and
+/// `m_iter_free(cursor)` frees it. The cursor pins the object until it is
+/// freed; meanwhile, the generated `drop` function and the `&mut self`
+/// methods refuse to run and return `false`, nothing, or a null pointer. For
+/// this reason, an exported `&mut self` method may only return one of these.
+/// A stale handle is refused the same way if the method returns one of these;
+/// otherwise, the call panics.
 #[proc_macro_error]
 #[proc_macro_attribute]
 pub fn export(attr: TokenStream, item: TokenStream) -> TokenStream {
     cbinding::cbindgen(attr, item)
 }
 
+/// Wraps the body of a function or a `&self` method in a transaction
+///
+/// The journal object is injected as `j` (or the name given by the `journal`
+/// argument), and the return type `T` becomes `corundum::result::Result<T>`.
+/// The pool type defaults to `corundum::default::Allocator` and can be changed
+/// using the `pool` argument.
+///
+/// ```ignore
+/// impl Counter {
+///     #[transactional(pool = P)]
+///     fn inc(&self) -> i32 {
+///         let mut v = self.value.borrow_mut(j);
+///         *v += 1;
+///         *v
+///     }
+/// }
+/// ```
+#[proc_macro_error]
+#[proc_macro_attribute]
+pub fn transactional(attr: TokenStream, item: TokenStream) -> TokenStream {
+    transactional::transactional(attr, item)
+}
+
 fn list(attrs: &Vec<Attribute>, name: &str) -> Vec<proc_macro2::TokenStream> {
     let mut ret = vec![];
     for attr in attrs {
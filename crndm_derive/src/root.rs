@@ -1,6 +1,6 @@
 use proc_macro::TokenStream;
 use proc_macro2::TokenStream as TokenStream2;
-use quote::{quote, quote_spanned, format_ident};
+use quote::{quote, quote_spanned, ToTokens};
 use syn::spanned::Spanned;
 use syn::*;
 
@@ -13,14 +13,19 @@ pub fn derive_root(input: TokenStream) -> TokenStream {
     // Used in the quasi-quotation below as `#name`.
     let name = input.ident;
 
+    // The fields which are initialized by `RootObj::init`. For an enum, only
+    // the fields of the default variant are initialized.
+    let fields = init_fields(&input.data);
+
     let mut expanded = vec![];
     for p in &pools {
 
-        // Add a bound `T: RootObj` to every type parameter T.
-        let generics = add_trait_bounds(input.generics.clone(), &pools, &p);
+        // Add a bound `T: RootObj` to every type parameter T which appears in
+        // an initialized field.
+        let generics = add_trait_bounds(input.generics.clone(), &pools, &p, &fields);
         let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
 
-        // Generate an expression to sum up the heap size of each field.
+        // Generate an expression to initialize each field.
         let sum = root_all_fields(&name, &input.data);
 
         expanded.push(quote! {
@@ -41,13 +46,16 @@ pub fn derive_root(input: TokenStream) -> TokenStream {
     TokenStream::from(expanded)
 }
 
-// Add a bound `T: RootObj` to every type parameter T.
-fn add_trait_bounds(mut generics: Generics, pool: &Vec<TokenStream2>, p: &TokenStream2) -> Generics {
+// Add a bound `T: RootObj` to every type parameter T which appears in one of
+// the initialized fields.
+fn add_trait_bounds(mut generics: Generics, pool: &Vec<TokenStream2>, p: &TokenStream2,
+    fields: &[&Field]) -> Generics {
     for param in &mut generics.params {
         if let GenericParam::Type(ref mut type_param) = *param {
             let ident = type_param.ident.clone();
             let me = ident.to_string();
-            if !pool.iter().any(|p| p.to_string() == me) {
+            if !pool.iter().any(|p| p.to_string() == me)
+                && fields.iter().any(|f| mentions(f.ty.to_token_stream(), &ident)) {
                 type_param.bounds.push(parse_quote!(corundum::RootObj<#p>));
             }
         }
@@ -55,7 +63,40 @@ fn add_trait_bounds(mut generics: Generics, pool: &Vec<TokenStream2>, p: &TokenS
     generics
 }
 
-// Generate an expression to sum up the heap size of each field.
+// Checks if `ident` appears in the tokens of a type
+fn mentions(tokens: TokenStream2, ident: &Ident) -> bool {
+    tokens.into_iter().any(|t| match t {
+        proc_macro2::TokenTree::Ident(i) => i == *ident,
+        proc_macro2::TokenTree::Group(g) => mentions(g.stream(), ident),
+        _ => false,
+    })
+}
+
+// Returns the fields which are initialized by `RootObj::init`
+fn init_fields(data: &Data) -> Vec<&Field> {
+    match data {
+        Data::Struct(data) => data.fields.iter().collect(),
+        Data::Enum(data) => match default_variant(data) {
+            Some(v) => v.fields.iter().collect(),
+            None => vec![],
+        },
+        Data::Union(_) => vec![],
+    }
+}
+
+// Finds the variant which is marked with `#[default]`, or the only variant
+fn default_variant(data: &DataEnum) -> Option<&Variant> {
+    let marked: Vec<&Variant> = data.variants.iter()
+        .filter(|v| v.attrs.iter().any(|a| a.path.is_ident("default")))
+        .collect();
+    match marked.len() {
+        0 if data.variants.len() == 1 => data.variants.first(),
+        1 => Some(marked[0]),
+        _ => None,
+    }
+}
+
+// Generate an expression to initialize each field.
 fn root_all_fields(ident: &Ident, data: &Data) -> TokenStream2 {
     match *data {
         Data::Struct(ref data) => {
@@ -76,7 +117,7 @@ fn root_all_fields(ident: &Ident, data: &Data) -> TokenStream2 {
                 Fields::Unnamed(ref fields) => {
                     // Expands to an expression like
                     //
-                    //     0 + self.0.heap_size() + self.1.heap_size() + self.2.heap_size()
+                    //     Self(RootObj::init(j), RootObj::init(j), ...)
                     let recurse = fields.unnamed.iter().enumerate().map(|(_, f)| {
                         quote_spanned! {f.span()=>
                             corundum::RootObj::init(j)
@@ -86,56 +127,40 @@ fn root_all_fields(ident: &Ident, data: &Data) -> TokenStream2 {
                         Self(#(#recurse,)*)
                     }
                 }
-                Fields::Unit => {
-                    // Unit structs cannot own more than 0 bytes of heap memory.
-                    quote!(0)
-                }
+                Fields::Unit => quote!(Self),
             }
         }
-        Data::Enum(DataEnum { ref variants, .. }) => {
-            let res = variants.iter().map(|ref v| {
-                let variant = v.ident.clone();
-                match v.fields {
-                    Fields::Unit => quote! {
-                        #ident::#variant => #ident::#variant
-                    },
-                    Fields::Unnamed(ref fields) => {
-                        let recurse = fields.unnamed.iter().enumerate().map(|(i, f)| {
-                            let varname = format_ident!("__self_{}", i);
-                            quote_spanned! {f.span()=>
-                                #varname
-                            }
-                        });
-                        let ints = recurse.clone();
-                        quote! {
-                            #ident::#variant(#(#recurse,)*) => 
-                                #ident::#variant(#(corundum::RootObj::init(&#ints, j),)*)
-                        }
-                    },
-                    Fields::Named(ref fields) => {
-                        let recurse = fields.named.iter().enumerate().map(|(i,f)| {
-                            let name = &f.ident;
-                            let varname = format_ident!("__self_{}", i);
-                            quote_spanned! {f.span()=>
-                                #name: #varname
-                            }
-                        });
-                        let clones = fields.named.iter().enumerate().map(|(_i,f)| {
-                            let name = &f.ident;
-                            quote_spanned! {f.span()=>
-                                #name: corundum::RootObj::init(j)
-                            }
-                        });
-                        quote! {
-                            #ident::#variant{#(#recurse,)*} => 
-                            #ident::#variant{#(#clones,)*}
+        Data::Enum(ref data) => {
+            let v = match default_variant(data) {
+                Some(v) => v,
+                None => abort!(ident.span(),
+                    "an enum should mark exactly one variant with `#[default]` to derive Root"),
+            };
+            let variant = &v.ident;
+            match v.fields {
+                Fields::Unit => quote! {
+                    #ident::#variant
+                },
+                Fields::Unnamed(ref fields) => {
+                    let recurse = fields.unnamed.iter().map(|f| {
+                        quote_spanned! {f.span()=>
+                            corundum::RootObj::init(j)
                         }
+                    });
+                    quote! {
+                        #ident::#variant(#(#recurse,)*)
                     }
                 }
-            });
-            quote! {
-                match self {
-                    #(#res,)* 
+                Fields::Named(ref fields) => {
+                    let recurse = fields.named.iter().map(|f| {
+                        let name = &f.ident;
+                        quote_spanned! {f.span()=>
+                            #name: corundum::RootObj::init(j)
+                        }
+                    });
+                    quote! {
+                        #ident::#variant{#(#recurse,)*}
+                    }
                 }
             }
         }
@@ -0,0 +1,95 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::*;
+
+pub fn transactional(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut pool: TokenStream2 = quote!{ corundum::default::Allocator };
+    let mut journal = Ident::new("j", proc_macro2::Span::call_site());
+
+    let parser = Punctuated::<Expr, Token![,]>::parse_terminated;
+    let list = match parser.parse(attr) {
+        Ok(list) => list,
+        Err(e) => abort!(e.span(), "invalid arguments";
+            note = "available options are 'pool' and 'journal'"
+        )
+    };
+    for item in list {
+        if let Expr::Assign(ass) = &item {
+            let opt = if let Expr::Path(p) = &*ass.left {
+                p.path.get_ident().map(|i| i.to_string())
+            } else {
+                None
+            };
+            match opt.as_deref() {
+                Some("pool") => {
+                    if let Expr::Path(p) = &*ass.right {
+                        let p = &p.path;
+                        pool = quote!{ #p };
+                    } else {
+                        abort!(ass.right.span(), "invalid value";
+                            help = "specify a pool type, e.g. `pool = P`"
+                        );
+                    }
+                }
+                Some("journal") => {
+                    if let Some(id) = if let Expr::Path(p) = &*ass.right {
+                        p.path.get_ident().cloned()
+                    } else {
+                        None
+                    } {
+                        journal = id;
+                    } else {
+                        abort!(ass.right.span(), "invalid value";
+                            help = "specify an identifier, e.g. `journal = j`"
+                        );
+                    }
+                }
+                _ => abort!(ass.left.span(), "invalid option";
+                    note = "available options are 'pool' and 'journal'"
+                )
+            }
+        } else {
+            abort!(item.span(), "invalid option";
+                note = "available options are 'pool' and 'journal'"
+            );
+        }
+    }
+
+    let func = parse_macro_input!(item as ItemFn);
+    let ItemFn { attrs, vis, mut sig, block } = func;
+
+    if let Some(a) = &sig.asyncness {
+        abort!(a.span(), "async functions cannot be transactional");
+    }
+    if let Some(recv) = sig.inputs.first() {
+        if let FnArg::Receiver(r) = recv {
+            if r.reference.is_none() || r.mutability.is_some() {
+                abort!(r.span(), "transactional methods should take `&self`";
+                    note = "mutable references cannot go inside a transaction"
+                );
+            }
+        }
+    }
+
+    let ret = match &sig.output {
+        ReturnType::Default => quote!{ () },
+        ReturnType::Type(_, ty) => quote!{ #ty },
+    };
+    sig.output = parse_quote!{ -> corundum::result::Result<#ret> };
+
+    let expanded = quote! {
+        #(#attrs)*
+        #vis #sig {
+            <#pool as corundum::MemPoolTraits>::transaction(move |
+                #[allow(unused_variables)]
+                #journal: &'static corundum::stm::Journal<#pool>
+            | -> #ret #block)
+        }
+    };
+
+    TokenStream::from(expanded)
+}
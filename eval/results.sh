@@ -142,33 +142,32 @@ if $all || $scale; then
     done >> $dir_path/outputs/scale.csv
 fi
 
-function avg() {
-    echo $(cat $1 | grep -oP "$2 .+avg\\(ns\\): \\d+\\.\\d{3} " | grep -oP '(\d+\.\d{3}) ')
-}
-
-function std() {
-    echo $(cat $1 | grep -oP "$2 .+std\\(ns\\): \\d+\\.\\d |$2 .+std\\(ns\\): NaN" | grep -oP '(\d+\.\d) |(NaN)')
+# Reads an estimate (in ns) of a benchmark of the `micro` group from the
+# criterion output directory. Criterion replaces ':' with '_' in the paths.
+function estimate() {
+    f=$1/micro/${2//:/_}/new/estimates.json
+    echo $(grep -oP "\"$3\":\{.*?\"point_estimate\":\K[0-9.e+-]+" $f 2>/dev/null | xargs -r printf "%.3f")
 }
 
 tags=(
     "Deref"
-    "DerefMut\(1st\)"
-    "DerefMut\(!1st\)"
-    "Alloc\(8\)"
-    "Alloc\(256\)"
-    "Alloc\(4096\)"
+    "DerefMut(1st)"
+    "DerefMut(!1st)"
+    "Alloc(8)"
+    "Alloc(256)"
+    "Alloc(4096)"
     "Pbox:AtomicInit"
     "Prc:AtomicInit"
     "Parc:AtomicInit"
-    "Dealloc\(8\)"
-    "Dealloc\(256\)"
-    "Dealloc\(4096\)"
+    "Dealloc(8)"
+    "Dealloc(256)"
+    "Dealloc(4096)"
     "TxNop"
-    "DataLog\(8\)"
-    "DataLog\(1024\)"
-    "DataLog\(4096\)"
-    "DropLog\(8\)"
-    "DropLog\(32768\)"
+    "DataLog(8)"
+    "DataLog(1024)"
+    "DataLog(4096)"
+    "DropLog(8)"
+    "DropLog(32768)"
     "Pbox:clone"
     "Prc:clone"
     "Parc:clone"
@@ -183,13 +182,13 @@ tags=(
 )
 
 if $all || $micro; then
-    p=$dir_path/outputs/perf/micro-pmem.out
-    d=$dir_path/outputs/perf/micro-dram.out
+    p=$dir_path/outputs/perf/micro-pmem
+    d=$dir_path/outputs/perf/micro-dram
     m=$dir_path/outputs/micro.csv
     echo ",PMEM,,DRAM," > $m
     echo ",Mean (ns),STD (ns),Mean (ns),STD (ns)" >> $m
-    for t in ${tags[@]}; do
-        echo "${t//\\/},$(avg $p $t),$(std $p $t),$(avg $d $t),$(std $d $t)" >> $m
+    for t in "${tags[@]}"; do
+        echo "$t,$(estimate $p $t mean),$(estimate $p $t std_dev),$(estimate $d $t mean),$(estimate $d $t std_dev)" >> $m
     done 
 fi
 
@@ -203,8 +203,8 @@ if $all || $micro; then
     cd $dir_path/..
     rm -f $pool
     echo "Running microbenchmarks on PMEM ($pool)..."
-    CPUS=1 taskset -c 0 cargo run --release --example microbench --features="$features" -- $pool > $dir_path/outputs/perf/micro-pmem.out
+    CRITERION_HOME=$dir_path/outputs/perf/micro-pmem CORUNDUM_BENCH_POOL=$pool CPUS=1 taskset -c 0 cargo bench --bench corundum --features="$features" -- micro
     echo "Running microbenchmarks on PMEM (/dev/shm/m.pool)..."
     rm -f /dev/shm/m.pool
-    CPUS=1 taskset -c 0 cargo run --release --example microbench --features="$features" -- /dev/shm/m.pool > $dir_path/outputs/perf/micro-dram.out
+    CRITERION_HOME=$dir_path/outputs/perf/micro-dram CORUNDUM_BENCH_POOL=/dev/shm/m.pool CPUS=1 taskset -c 0 cargo bench --bench corundum --features="$features" -- micro
 fi
\ No newline at end of file
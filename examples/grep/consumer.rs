@@ -5,6 +5,7 @@ use corundum::default::*;
 use corundum::sync::VWeak;
 use regex::Regex;
 use corundum::may_crash;
+use corundum::pstring;
 
 type P = Allocator;
 
@@ -28,7 +29,7 @@ impl Consumer {
         j: &Journal,
     ) -> Self {
         Self {
-            pattern: PString::from_str(pattern, j),
+            pattern: pstring!(j, pattern),
             lines,
             data: PMutex::new(
                 ConsumerData {
@@ -3,6 +3,7 @@ use corundum::default::*;
 use corundum::stm::Journal;
 use corundum::vec::Vec;
 use corundum::sync::VWeak;
+use corundum::pstring;
 use std::fs::File;
 use std::io::{BufRead, BufReader, Seek, SeekFrom};
 use std::string::String as StdStr;
@@ -57,14 +58,14 @@ impl Producer {
                                     line.append(&mut buf);
                                     if read >= BATCH_SIZE {
                                         let s = StdStr::from_utf8(line).expect("from_utf8 failed");
-                                        lines.push(PString::from_str(&s, j), j);
+                                        lines.push(pstring!(j, s), j);
                                         pos.1 += read as u64;
                                         break;
                                     }
                                 } else {
                                     if !line.is_empty() {
                                         let s = StdStr::from_utf8(line).expect("from_utf8 failed");
-                                        lines.push(PString::from_str(&s, j), j);
+                                        lines.push(pstring!(j, s), j);
                                     }
                                     pos.1 = 0;
                                     pos.0 += 1;
@@ -0,0 +1,265 @@
+//! bench.rs -- non-interactive mode which runs a generated or scripted
+//! workload against a map and reports the throughput and the latency of
+//! every operation
+
+use crate::map::Map;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Op {
+    Insert,
+    Lookup,
+    Remove,
+}
+
+impl Op {
+    fn name(&self) -> &'static str {
+        match self {
+            Op::Insert => "insert",
+            Op::Lookup => "lookup",
+            Op::Remove => "remove",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Dist {
+    Uniform,
+    Zipf,
+}
+
+pub struct BenchOpts {
+    ops: u64,
+    threads: usize,
+    keys: u64,
+    dist: Dist,
+    mix: [u32; 3],
+    seed: u64,
+    script: Option<String>,
+}
+
+pub fn bench_help() {
+    println!("bench options:");
+    println!("  --ops num         Number of operations (Default 1M)");
+    println!("  --threads num     Number of threads (Default 1)");
+    println!("  --keys num        Size of the key space (Default 100K)");
+    println!("  --dist dist       uniform or zipf (Default uniform)");
+    println!("  --mix i:l:r       Percentage of insert, lookup, and remove (Default 40:50:10)");
+    println!("  --seed num        Seed of the random generators (Default 0)");
+    println!("  --script file     Run the commands in file ('i', 'c', and 'r' with a key");
+    println!("                    per line) instead of a generated workload");
+    println!("Numbers accept K, M, and G suffixes.");
+}
+
+/// Parses a number with an optional `K`, `M`, or `G` suffix
+fn number(s: &str) -> u64 {
+    let (n, scale) = match s.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&s[..s.len() - 1], 1_000),
+        Some('M') => (&s[..s.len() - 1], 1_000_000),
+        Some('G') => (&s[..s.len() - 1], 1_000_000_000),
+        _ => (s, 1),
+    };
+    n.parse::<u64>().unwrap_or_else(|_| panic!("invalid number -- '{}'", s)) * scale
+}
+
+impl BenchOpts {
+    pub fn parse(args: &[String]) -> Self {
+        let mut opts = BenchOpts {
+            ops: 1_000_000,
+            threads: 1,
+            keys: 100_000,
+            dist: Dist::Uniform,
+            mix: [40, 50, 10],
+            seed: 0,
+            script: None,
+        };
+        let mut i = 0;
+        while i < args.len() {
+            let opt = &args[i];
+            let val = args.get(i + 1).unwrap_or_else(|| panic!("{} requires an argument", opt));
+            match opt.as_str() {
+                "--ops" => opts.ops = number(val),
+                "--threads" => opts.threads = (number(val) as usize).max(1),
+                "--keys" => opts.keys = number(val).max(1),
+                "--dist" => opts.dist = match val.as_str() {
+                    "uniform" => Dist::Uniform,
+                    "zipf" => Dist::Zipf,
+                    _ => panic!("invalid distribution -- '{}'", val),
+                },
+                "--mix" => {
+                    let mix: Vec<u32> = val.split(':').map(|p| number(p) as u32).collect();
+                    if mix.len() != 3 || mix.iter().sum::<u32>() == 0 {
+                        panic!("invalid mix -- '{}'", val);
+                    }
+                    opts.mix.copy_from_slice(&mix);
+                }
+                "--seed" => opts.seed = number(val),
+                "--script" => opts.script = Some(val.clone()),
+                _ => panic!("unknown option -- '{}'", opt),
+            }
+            i += 2;
+        }
+        opts
+    }
+}
+
+/// Spreads the key numbers over the key space, so that the popular keys
+/// are not neighbors (`FNV-1a`). Key 0 is reserved by some of the maps.
+fn scramble(n: u64) -> u64 {
+    let mut h = 0xcbf2_9ce4_8422_2325u64;
+    for b in n.to_le_bytes().iter() {
+        h ^= *b as u64;
+        h = h.wrapping_mul(0x100_0000_01b3);
+    }
+    h.max(1)
+}
+
+/// The Zipfian generator of Gray et al. with `theta = 0.99`
+struct Zipf {
+    n: u64,
+    theta: f64,
+    zetan: f64,
+    eta: f64,
+}
+
+impl Zipf {
+    fn new(n: u64) -> Self {
+        let theta = 0.99;
+        let zeta = |n: u64| (1..=n).map(|i| 1.0 / (i as f64).powf(theta)).sum::<f64>();
+        let zetan = zeta(n);
+        let eta = (1.0 - (2.0 / n as f64).powf(1.0 - theta)) / (1.0 - zeta(2) / zetan);
+        Zipf { n, theta, zetan, eta }
+    }
+
+    fn next(&self, rng: &mut StdRng) -> u64 {
+        let u: f64 = rng.gen();
+        let uz = u * self.zetan;
+        if uz < 1.0 {
+            0
+        } else if uz < 1.0 + 0.5f64.powf(self.theta) {
+            1
+        } else {
+            let x = self.n as f64 * (self.eta * u - self.eta + 1.0).powf(1.0 / (1.0 - self.theta));
+            (x as u64).min(self.n - 1)
+        }
+    }
+}
+
+/// Reads the commands of a script
+fn script(path: &str) -> Vec<(Op, u64)> {
+    let file = File::open(path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+    let mut cmds = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line.unwrap();
+        let mut words = line.split_whitespace();
+        let op = match words.next() {
+            Some("i") => Op::Insert,
+            Some("c") => Op::Lookup,
+            Some("r") => Op::Remove,
+            Some(cmd) if cmd.starts_with('#') => continue,
+            Some(cmd) => panic!("unknown command in script -- '{}'", cmd),
+            None => continue,
+        };
+        let key = words.next().expect("script: a key expected").parse().expect("script: invalid key");
+        cmds.push((op, key));
+    }
+    cmds
+}
+
+struct Shared<T>(*const T);
+unsafe impl<T> Send for Shared<T> {}
+
+type Latencies = BTreeMap<Op, Vec<u64>>;
+
+/// Runs the workload of `opts` on `map`
+///
+/// The maps are not thread-safe, so the operations are serialized with a
+/// lock; with more than one thread, the latencies include waiting for it.
+pub fn bench<T: 'static + Map<u64, u64>>(map: &T, opts: &BenchOpts) {
+    let lock = Arc::new(Mutex::new(()));
+    let script = opts.script.as_ref().map(|path| Arc::new(script(path)));
+    let zipf = Arc::new(Zipf::new(opts.keys.max(2)));
+    let ops = script.as_ref().map_or(opts.ops, |s| s.len() as u64);
+    let total = opts.mix.iter().sum::<u32>();
+
+    let start = Instant::now();
+    let handles: Vec<_> = (0..opts.threads).map(|t| {
+        let map = Shared(map as *const T);
+        let lock = lock.clone();
+        let script = script.clone();
+        let zipf = zipf.clone();
+        let (threads, keys, dist, mix, seed) = (opts.threads, opts.keys, opts.dist, opts.mix, opts.seed);
+        thread::spawn(move || {
+            let map = unsafe { &*map.0 };
+            let mut rng = StdRng::seed_from_u64(seed.wrapping_add(t as u64));
+            let mut lat = Latencies::new();
+            let mut i = t as u64;
+            while i < ops {
+                let (op, key) = match &script {
+                    Some(s) => s[i as usize],
+                    None => {
+                        let p = rng.gen_range(0..total);
+                        let op = if p < mix[0] {
+                            Op::Insert
+                        } else if p < mix[0] + mix[1] {
+                            Op::Lookup
+                        } else {
+                            Op::Remove
+                        };
+                        let n = match dist {
+                            Dist::Uniform => rng.gen_range(0..keys),
+                            Dist::Zipf => zipf.next(&mut rng) % keys,
+                        };
+                        (op, scramble(n))
+                    }
+                };
+                let begin = Instant::now();
+                {
+                    let _guard = match lock.lock() {
+                        Ok(g) => g,
+                        Err(p) => p.into_inner(),
+                    };
+                    match op {
+                        Op::Insert => map.insert(key, key),
+                        Op::Lookup => { map.lookup(key); }
+                        Op::Remove => map.remove(key),
+                    }
+                }
+                lat.entry(op).or_insert_with(Vec::new).push(begin.elapsed().as_nanos() as u64);
+                i += threads as u64;
+            }
+            lat
+        })
+    }).collect();
+
+    let mut lat = Latencies::new();
+    for h in handles {
+        for (op, mut v) in h.join().unwrap() {
+            lat.entry(op).or_insert_with(Vec::new).append(&mut v);
+        }
+    }
+    report(start.elapsed(), ops, opts.threads, &mut lat);
+}
+
+fn report(time: Duration, ops: u64, threads: usize, lat: &mut Latencies) {
+    let secs = time.as_secs_f64();
+    println!("{} operations in {:.3} s with {} thread(s): {:.0} ops/s",
+        ops, secs, threads, ops as f64 / secs);
+    println!("{:<8} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+        "op", "count", "avg(us)", "p50(us)", "p90(us)", "p99(us)", "max(us)");
+    for (op, v) in lat.iter_mut() {
+        v.sort_unstable();
+        let us = |ns: u64| ns as f64 / 1000.0;
+        let at = |q: f64| us(v[((v.len() - 1) as f64 * q) as usize]);
+        let avg = v.iter().sum::<u64>() as f64 / v.len() as f64 / 1000.0;
+        println!("{:<8} {:>10} {:>10.2} {:>10.2} {:>10.2} {:>10.2} {:>10.2}",
+            op.name(), v.len(), avg, at(0.5), at(0.9), at(0.99), at(1.0));
+    }
+}
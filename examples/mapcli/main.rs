@@ -1,11 +1,13 @@
 #![feature(maybe_uninit_uninit_array)]
 #![allow(dead_code)]
 
+mod bench;
 mod hashmap;
 mod map;
 mod skiplist;
 
 use crate::map::*;
+use bench::*;
 use hashmap::*;
 use corundum::default::*;
 use corundum::open_flags::*;
@@ -102,11 +104,13 @@ fn vperform<T: Map<u64, u64> + Default>() {
     print!("$ ");
     stdout().flush().unwrap();
     while let Ok(_) = stdin().read_line(&mut buf) {
+        if buf.is_empty() { break }
         match buf.remove(0) as char {
             'i' => str_insert(&map, &mut buf),
             'c' => str_check(&map, &mut buf),
             'r' => str_remove(&map, &mut buf),
             'n' => str_insert_random(&map, &mut buf),
+            'C' => map.clear(),
             'p' => print_all(&map),
             'h' => help(),
             'q' => return,
@@ -119,19 +123,63 @@ fn vperform<T: Map<u64, u64> + Default>() {
     }
 }
 
+fn bench_perform<T: 'static + Map<u64, u64> + RootObj<P> + PSafe>(path: &str, opts: &BenchOpts) {
+    let map = P::open::<T>(path, O_CFNE | O_16GB).unwrap();
+    bench(&*map, opts);
+}
+
+fn bench_vperform<T: 'static + Map<u64, u64> + Default>(opts: &BenchOpts) {
+    bench(&T::default(), opts);
+}
+
+fn bench_main(typ: &str, path: &str, opts: &BenchOpts) {
+    if typ == "hashmap_tx" {
+        bench_perform::<HashmapTx>(path, opts)
+    } else if typ == "hashmap_atomic" {
+        bench_perform::<HashmapAtomic>(path, opts)
+    } else if typ == "hashmap_rp" {
+        bench_perform::<HashmapRp>(path, opts)
+    } else if typ == "ctree" {
+        bench_perform::<CTree>(path, opts)
+    } else if typ == "btree" {
+        bench_perform::<BTree<u64>>(path, opts)
+    } else if typ == "vbtree" {
+        bench_vperform::<VBTree<u64>>(opts)
+    } else if typ == "rtree" {
+        bench_vperform::<RTree<u64, u64>>(opts)
+    } else if typ == "rbtree" {
+        bench_perform::<RbTree>(path, opts)
+    } else if typ == "skiplist" {
+        bench_perform::<Skiplist>(path, opts)
+    } else {
+        panic!("invalid container type -- '{}'", typ);
+    }
+}
+
+fn usage(name: &str) {
+    println!(
+        concat!(
+            "usage: {} [bench] ",
+            "hashmap_tx|hashmap_atomic|hashmap_rp|",
+            "ctree|btree|vbtree|rtree|rbtree|skiplist ",
+            "file-name [bench options]"
+        ),
+        name
+    );
+    println!();
+    bench_help();
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
 
+    if args.len() >= 4 && args[1] == "bench" {
+        let opts = BenchOpts::parse(&args[4..]);
+        bench_main(&args[2], &args[3], &opts);
+        return;
+    }
     if args.len() != 3 {
-        println!(
-            concat!(
-                "usage: {} ",
-                "hashmap_tx|hashmap_atomic|hashmap_rp|",
-                "ctree|btree|rtree|rbtree|skiplist ",
-                "file-name"
-            ),
-            args[0]
-        );
+        usage(&args[0]);
         return;
     }
 
@@ -147,9 +195,8 @@ fn main() {
         perform::<CTree>(path)
     } else if typ == "btree" {
         perform::<BTree<u64>>(path)
-        // unimplemented!()
     } else if typ == "vbtree" {
-        // vperform::<VBTree<u64>>()
+        vperform::<VBTree<u64>>()
     } else if typ == "rtree" {
         vperform::<RTree<u64, u64>>()
     } else if typ == "rbtree" {
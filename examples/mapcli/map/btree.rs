@@ -262,6 +262,24 @@ where
         }
     }
 
+    /// Replaces the value of `item.key` if it is already in the tree
+    fn update(&self, nn: &PmemObj<Node<V>>, item: NodeItem<V>, j: &Journal) -> bool {
+        let n = nn.borrow();
+        for i in 0..n.n + 1 {
+            if i != n.n && n.items[i].key == item.key {
+                drop(n);
+                nn.borrow_mut(j).items[i].val = item.val;
+                return true;
+            } else if i == n.n || n.items[i].key > item.key {
+                return match &n.slots[i] {
+                    Some(slot) => self.update(slot, item, j),
+                    None => false,
+                };
+            }
+        }
+        false
+    }
+
     #[inline]
     fn insert_empty(&self, item: NodeItem<V>, j: &Journal) {
         let mut root = self.root.borrow_mut(j);
@@ -438,7 +456,7 @@ where
             let item = NodeItem::<V> { key, val };
             if self.is_empty() {
                 self.insert_empty(item, j);
-            } else {
+            } else if !self.update(&self.root, item, j) {
                 let mut p = 0;
                 let mut dest = self.find_dest_node(&self.root, None, key, &mut p, j);
                 dest.insert_item(p, item);
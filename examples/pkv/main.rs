@@ -0,0 +1,277 @@
+//! # A persistent key-value server
+//!
+//! `pkv` speaks a subset of the Redis protocol, so `redis-cli`, `redis-benchmark`,
+//! or any Redis client library can talk to it. The data is kept in a
+//! persistent hash map, and every command runs in a transaction. `MULTI` and
+//! `EXEC` group the queued commands into a single transaction, so they are
+//! applied all together or not at all, even if the server crashes.
+//!
+//! Every client is served by its own thread. Killing the server at any point
+//! and starting it again on the same pool file recovers the last committed
+//! state.
+//!
+//! ```text
+//! cargo run --example pkv -- kv.pool 127.0.0.1:6379
+//! redis-cli -p 6379 set hello world
+//! ```
+
+mod resp;
+
+use corundum::default::*;
+use corundum::open_flags::*;
+use corundum::stl::HashMap;
+use resp::*;
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::hash::{Hash, Hasher};
+use std::io::{BufReader, BufWriter, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+type P = Allocator;
+
+/// The values are never removed from the map; a deleted key keeps `None`
+type Store = HashMap<PString, Option<PVec<u8>>, P>;
+
+struct Root {
+    store: Parc<PMutex<Store>>,
+}
+
+impl RootObj<P> for Root {
+    fn init(j: &Journal) -> Self {
+        Self { store: Parc::new(PMutex::new(HashMap::new(j)), j) }
+    }
+}
+
+fn hash(key: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn get<'a>(store: &'a Store, key: &str) -> Option<&'a PVec<u8>> {
+    store.get_with_hash(key, hash(key)).and_then(|v| v.as_ref())
+}
+
+fn set(store: &mut Store, key: &str, val: Option<&[u8]>, j: &Journal) {
+    let val = val.map(|v| PVec::from_slice(v, j));
+    store.put_with_hash(key, hash(key), val, j);
+}
+
+fn int(arg: &[u8]) -> Result<i64, Reply> {
+    std::str::from_utf8(arg).ok().and_then(|s| s.parse().ok())
+        .ok_or_else(|| Reply::err("ERR value is not an integer or out of range"))
+}
+
+fn incr_by(store: &mut Store, key: &str, by: i64, j: &Journal) -> Reply {
+    let old = match get(store, key) {
+        Some(v) => match int(v.as_slice()) {
+            Ok(i) => i,
+            Err(e) => return e,
+        },
+        None => 0,
+    };
+    match old.checked_add(by) {
+        Some(new) => {
+            set(store, key, Some(new.to_string().as_bytes()), j);
+            Reply::Integer(new)
+        }
+        None => Reply::err("ERR increment or decrement would overflow"),
+    }
+}
+
+/// Executes a command which accesses the store, in the running transaction
+fn execute(store: &mut Store, cmd: &[Vec<u8>], j: &Journal) -> Reply {
+    let name = String::from_utf8_lossy(&cmd[0]).to_ascii_uppercase();
+    let args = &cmd[1..];
+    let arity = |min: usize, pairs: bool| {
+        args.len() >= min && (!pairs || args.len() % 2 == 0)
+    };
+    let ok = match name.as_str() {
+        "GET" | "STRLEN" | "INCR" | "DECR" => args.len() == 1,
+        "SET" | "APPEND" | "INCRBY" => args.len() == 2,
+        "DEL" | "EXISTS" | "MGET" => arity(1, false),
+        "MSET" => arity(2, true),
+        "DBSIZE" | "FLUSHDB" => args.is_empty(),
+        "KEYS" => args.len() <= 1,
+        _ => return Reply::err(format!("ERR unknown command '{}'", name)),
+    };
+    if !ok {
+        return Reply::err(format!("ERR wrong number of arguments for '{}' command", name));
+    }
+    // The keys are persistent strings, the values may be binary
+    let is_key = |i: usize| match name.as_str() {
+        "SET" | "APPEND" | "INCRBY" => i == 0,
+        "MSET" => i % 2 == 0,
+        _ => true,
+    };
+    let mut keys = vec![];
+    for (i, a) in args.iter().enumerate() {
+        match std::str::from_utf8(a) {
+            Ok(k) => keys.push(k),
+            Err(_) if is_key(i) => return Reply::err("ERR keys must be UTF-8"),
+            Err(_) => keys.push(""),
+        }
+    }
+
+    match name.as_str() {
+        "GET" => Reply::Bulk(get(store, keys[0]).map(|v| v.as_slice().to_vec())),
+        "STRLEN" => Reply::Integer(get(store, keys[0]).map_or(0, |v| v.len() as i64)),
+        "SET" => {
+            set(store, keys[0], Some(&args[1]), j);
+            Reply::ok()
+        }
+        "APPEND" => {
+            let mut val = get(store, keys[0]).map_or(vec![], |v| v.as_slice().to_vec());
+            val.extend_from_slice(&args[1]);
+            set(store, keys[0], Some(&val), j);
+            Reply::Integer(val.len() as i64)
+        }
+        "INCR" => incr_by(store, keys[0], 1, j),
+        "DECR" => incr_by(store, keys[0], -1, j),
+        "INCRBY" => match int(&args[1]) {
+            Ok(by) => incr_by(store, keys[0], by, j),
+            Err(e) => e,
+        },
+        "DEL" => {
+            let mut n = 0;
+            for k in &keys {
+                if get(store, k).is_some() {
+                    set(store, k, None, j);
+                    n += 1;
+                }
+            }
+            Reply::Integer(n)
+        }
+        "EXISTS" => Reply::Integer(keys.iter().filter(|k| get(store, k).is_some()).count() as i64),
+        "MGET" => Reply::Array(keys.iter()
+            .map(|k| Reply::Bulk(get(store, k).map(|v| v.as_slice().to_vec())))
+            .collect()),
+        "MSET" => {
+            for (k, v) in keys.iter().step_by(2).zip(args.iter().skip(1).step_by(2)) {
+                set(store, k, Some(v), j);
+            }
+            Reply::ok()
+        }
+        "DBSIZE" => Reply::Integer(store.values().filter(|v| v.is_some()).count() as i64),
+        "KEYS" => {
+            let pattern = keys.get(0).copied().unwrap_or("*");
+            Reply::Array(store.iter()
+                .filter(|(k, v)| v.is_some() && matches(pattern, k))
+                .map(|(k, _)| Reply::Bulk(Some(k.as_bytes().to_vec())))
+                .collect())
+        }
+        "FLUSHDB" => {
+            store.clear(j);
+            Reply::ok()
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Matches a key against a glob pattern with `*` and `?`
+fn matches(pattern: &str, key: &str) -> bool {
+    fn m(p: &[u8], k: &[u8]) -> bool {
+        match (p.first(), k.first()) {
+            (None, None) => true,
+            (Some(b'*'), _) => m(&p[1..], k) || (!k.is_empty() && m(p, &k[1..])),
+            (Some(b'?'), Some(_)) => m(&p[1..], &k[1..]),
+            (Some(a), Some(b)) if a == b => m(&p[1..], &k[1..]),
+            _ => false,
+        }
+    }
+    m(pattern.as_bytes(), key.as_bytes())
+}
+
+/// Runs `cmds` in a single transaction. If the transaction fails, all of them
+/// are rolled back and an error is returned.
+fn transaction(store: &VWeak<PMutex<Store>>, cmds: &[Vec<Vec<u8>>]) -> Result<Vec<Reply>, Reply> {
+    P::transaction(|j| {
+        let store = store.promote(j).expect("the store is dropped");
+        let mut store = store.lock(j);
+        cmds.iter().map(|cmd| execute(&mut store, cmd, j)).collect::<Vec<_>>()
+    }).map_err(|e| Reply::err(format!("EXECABORT transaction rolled back: {}", e)))
+}
+
+fn serve(stream: TcpStream, store: VWeak<PMutex<Store>>) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    let mut queue: Option<Vec<Vec<Vec<u8>>>> = None;
+
+    while let Some(cmd) = read_command(&mut reader)? {
+        let name = String::from_utf8_lossy(&cmd[0]).to_ascii_uppercase();
+        let reply = match name.as_str() {
+            "PING" if cmd.len() == 1 => Reply::Simple("PONG"),
+            "PING" | "ECHO" if cmd.len() == 2 => Reply::Bulk(Some(cmd[1].clone())),
+            "COMMAND" | "CLIENT" | "CONFIG" => Reply::Array(vec![]),
+            "QUIT" => {
+                Reply::ok().write(&mut writer)?;
+                writer.flush()?;
+                return Ok(());
+            }
+            "MULTI" if queue.is_some() => Reply::err("ERR MULTI calls can not be nested"),
+            "MULTI" => {
+                queue = Some(vec![]);
+                Reply::ok()
+            }
+            "DISCARD" => match queue.take() {
+                Some(_) => Reply::ok(),
+                None => Reply::err("ERR DISCARD without MULTI"),
+            },
+            "EXEC" => match queue.take() {
+                Some(cmds) => match transaction(&store, &cmds) {
+                    Ok(replies) => Reply::Array(replies),
+                    Err(e) => e,
+                },
+                None => Reply::err("ERR EXEC without MULTI"),
+            },
+            _ => match &mut queue {
+                Some(q) => {
+                    q.push(cmd);
+                    Reply::Simple("QUEUED")
+                }
+                None => match transaction(&store, &[cmd]) {
+                    Ok(mut replies) => replies.pop().unwrap(),
+                    Err(e) => e,
+                },
+            },
+        };
+        reply.write(&mut writer)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        println!("usage: {} pool-file [address (Default 127.0.0.1:6379)]", args[0]);
+        return;
+    }
+    let addr = args.get(2).map_or("127.0.0.1:6379", |a| a.as_str());
+
+    // Opening the pool recovers it, if the server crashed in the middle of a
+    // transaction
+    let root = P::open::<Root>(&args[1], O_CFNE | O_1GB).unwrap();
+    let keys = P::transaction(|j| {
+        root.store.lock(j).values().filter(|v| v.is_some()).count()
+    }).unwrap();
+    println!("Recovered {} keys from {}", keys, args[1]);
+
+    let listener = TcpListener::bind(addr).unwrap();
+    println!("Listening on {}", addr);
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let store = root.store.demote();
+                thread::spawn(move || {
+                    let peer = stream.peer_addr().ok();
+                    if let Err(e) = serve(stream, store) {
+                        eprintln!("{:?}: {}", peer, e);
+                    }
+                });
+            }
+            Err(e) => eprintln!("connection failed: {}", e),
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! A subset of the Redis serialization protocol (RESP2)
+
+use std::io::{self, BufRead, Write};
+
+/// A reply to a command
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reply {
+    Simple(&'static str),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    pub fn ok() -> Self {
+        Reply::Simple("OK")
+    }
+
+    pub fn err(msg: impl Into<String>) -> Self {
+        Reply::Error(msg.into())
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        match self {
+            Reply::Simple(s) => write!(w, "+{}\r\n", s),
+            Reply::Error(e) => write!(w, "-{}\r\n", e),
+            Reply::Integer(i) => write!(w, ":{}\r\n", i),
+            Reply::Bulk(None) => write!(w, "$-1\r\n"),
+            Reply::Bulk(Some(b)) => {
+                write!(w, "${}\r\n", b.len())?;
+                w.write_all(b)?;
+                write!(w, "\r\n")
+            }
+            Reply::Array(a) => {
+                write!(w, "*{}\r\n", a.len())?;
+                for r in a {
+                    r.write(w)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn read_line<R: BufRead>(r: &mut R) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    if r.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    while line.ends_with('\n') || line.ends_with('\r') {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn parse_len(s: &str) -> io::Result<usize> {
+    s.parse().map_err(|_| invalid("Protocol error: invalid length"))
+}
+
+/// Reads the next command, either as an array of bulk strings, or as an
+/// inline command (e.g., from `telnet`). It returns `None` at the end of the
+/// stream.
+pub fn read_command<R: BufRead>(r: &mut R) -> io::Result<Option<Vec<Vec<u8>>>> {
+    loop {
+        let line = match read_line(r)? {
+            Some(line) => line,
+            None => return Ok(None),
+        };
+        if let Some(n) = line.strip_prefix('*') {
+            let n = parse_len(n)?;
+            let mut args = Vec::with_capacity(n);
+            for _ in 0..n {
+                let len = match read_line(r)? {
+                    Some(l) if l.starts_with('$') => parse_len(&l[1..])?,
+                    _ => return Err(invalid("Protocol error: expected '$'")),
+                };
+                let mut arg = vec![0; len + 2];
+                r.read_exact(&mut arg)?;
+                arg.truncate(len);
+                args.push(arg);
+            }
+            return Ok(Some(args));
+        }
+        let args: Vec<Vec<u8>> = line.split_whitespace().map(|s| s.as_bytes().to_vec()).collect();
+        if !args.is_empty() {
+            return Ok(Some(args));
+        }
+    }
+}
+
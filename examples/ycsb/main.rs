@@ -0,0 +1,325 @@
+//! # YCSB-style key-value benchmark
+//!
+//! Runs the YCSB core workloads (A to F) against the persistent maps of
+//! [`corundum::stl`], and reports the throughput and the latency of every
+//! operation. Every operation is a transaction, and the map is protected by a
+//! `PMutex`.
+
+mod workload;
+
+use corundum::default::*;
+use corundum::open_flags::*;
+use corundum::stl::{HashMap, PArt, PBPlusTree, PLruCache};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::BTreeMap;
+use std::env;
+use std::thread;
+use std::time::{Duration, Instant};
+use workload::*;
+
+type P = Allocator;
+
+/// The capacity of the LRU cache
+const LRU_CAPACITY: usize = 1 << 16;
+
+/// The number of records which a scan reads at most
+const MAX_SCAN: usize = 100;
+
+struct Root {
+    btree: PMutex<PBPlusTree<u64, u64, P>>,
+    art: PMutex<PArt<u64, P>>,
+    hash: PMutex<HashMap<u64, u64, P>>,
+    lru: PMutex<PLruCache<u64, u64, P>>,
+}
+
+impl RootObj<P> for Root {
+    fn init(j: &Journal) -> Self {
+        Self {
+            btree: PMutex::new(PBPlusTree::new()),
+            art: PMutex::new(PArt::new()),
+            hash: PMutex::new(HashMap::new(j)),
+            lru: PMutex::new(PLruCache::new(LRU_CAPACITY, j)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Map {
+    BTree,
+    Art,
+    Hash,
+    Lru,
+}
+
+impl Map {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "btree" => Some(Map::BTree),
+            "art" => Some(Map::Art),
+            "hash" => Some(Map::Hash),
+            "lru" => Some(Map::Lru),
+            _ => None,
+        }
+    }
+
+    fn get(&self, root: &Root, key: u64) -> Option<u64> {
+        P::transaction(|j| match self {
+            Map::BTree => root.btree.lock(j).get(&key).copied(),
+            Map::Art => root.art.lock(j).get(key.to_be_bytes()).copied(),
+            Map::Hash => root.hash.lock(j).get(key).copied(),
+            Map::Lru => root.lru.lock(j).get(&key).copied(),
+        }).unwrap()
+    }
+
+    fn put(&self, root: &Root, key: u64, val: u64) {
+        P::transaction(|j| match self {
+            Map::BTree => { root.btree.lock(j).insert(key, val, j); }
+            Map::Art => { root.art.lock(j).insert(key.to_be_bytes(), val, j); }
+            Map::Hash => root.hash.lock(j).put(key, val, j),
+            Map::Lru => { root.lru.lock(j).put(key, val, j); }
+        }).unwrap()
+    }
+
+    fn read_modify_write(&self, root: &Root, key: u64) {
+        P::transaction(|j| match self {
+            Map::BTree => {
+                let map = root.btree.lock(j);
+                let val = map.get(&key).copied().unwrap_or_default();
+                map.insert(key, val + 1, j);
+            }
+            Map::Art => {
+                let map = root.art.lock(j);
+                let val = map.get(key.to_be_bytes()).copied().unwrap_or_default();
+                map.insert(key.to_be_bytes(), val + 1, j);
+            }
+            Map::Hash => {
+                let mut map = root.hash.lock(j);
+                let val = map.get(key).copied().unwrap_or_default();
+                map.put(key, val + 1, j);
+            }
+            Map::Lru => {
+                let map = root.lru.lock(j);
+                let val = map.get(&key).copied().unwrap_or_default();
+                map.put(key, val + 1, j);
+            }
+        }).unwrap()
+    }
+
+    /// Reads up to `len` records from `key`. Only the B+Tree is ordered; the
+    /// other maps read `len` records from the key's record number onward.
+    fn scan(&self, root: &Root, record: u64, len: usize, count: u64) -> usize {
+        P::transaction(|j| match self {
+            Map::BTree => root.btree.lock(j).iter_from(&key_of(record)).take(len).count(),
+            _ => (record..count.min(record + len as u64))
+                .filter(|r| self.get(root, key_of(*r)).is_some())
+                .count(),
+        }).unwrap()
+    }
+}
+
+/// The latencies of the operations of a thread
+#[derive(Default)]
+struct Latencies(BTreeMap<Op, Vec<u64>>);
+
+impl Latencies {
+    fn record(&mut self, op: Op, t: Duration) {
+        self.0.entry(op).or_insert_with(Vec::new).push(t.as_nanos() as u64);
+    }
+
+    fn merge(&mut self, other: Latencies) {
+        for (op, mut v) in other.0 {
+            self.0.entry(op).or_insert_with(Vec::new).append(&mut v);
+        }
+    }
+
+    fn report(&mut self) {
+        println!("{:<18} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+            "operation", "count", "avg(us)", "p50(us)", "p95(us)", "p99(us)", "max(us)");
+        for op in Op::ALL.iter() {
+            if let Some(v) = self.0.get_mut(op) {
+                if v.is_empty() {
+                    continue;
+                }
+                v.sort_unstable();
+                let us = |ns: u64| ns as f64 / 1000.0;
+                let at = |q: f64| v[((v.len() - 1) as f64 * q) as usize];
+                let avg = v.iter().sum::<u64>() as f64 / v.len() as f64;
+                println!("{:<18} {:>10} {:>10.2} {:>10.2} {:>10.2} {:>10.2} {:>10.2}",
+                    op.name(), v.len(), avg / 1000.0, us(at(0.5)), us(at(0.95)),
+                    us(at(0.99)), us(v[v.len() - 1]));
+            }
+        }
+    }
+}
+
+/// The root object shared by the worker threads
+///
+/// The maps are only accessed through their mutexes, and the threads are
+/// joined before the root object goes out of scope.
+#[derive(Clone, Copy)]
+struct Shared(*const Root);
+unsafe impl Send for Shared {}
+unsafe impl Sync for Shared {}
+
+fn help() {
+    println!("usage: ycsb [OPTIONS] pool-file");
+    println!();
+    println!("OPTIONS:");
+    println!("  -w workload   Core workload A to F (Default A)");
+    println!("  -m map        btree, art, hash, or lru (Default btree)");
+    println!("  -t num        Number of threads (Default 1)");
+    println!("  -r num        Number of records to load (Default 100000)");
+    println!("  -o num        Number of operations to run (Default 100000)");
+    println!("  -d dist       Key distribution: zipfian, uniform, or latest");
+    println!("                (Default is the workload's distribution)");
+    println!("  -s seed       Seed of the random generators (Default 0)");
+    println!("  -N            Do not load the records (run on a loaded pool)");
+    println!("  -h            Display help");
+}
+
+fn arg<T: std::str::FromStr>(args: &[String], i: &mut usize, opt: &str) -> T {
+    if *i == args.len() - 1 {
+        panic!("{} requires an argument", opt);
+    }
+    *i += 1;
+    args[*i].parse().unwrap_or_else(|_| panic!("Invalid argument for {}", opt))
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    let mut workload = Workload::core('A').unwrap();
+    let mut map = Map::BTree;
+    let mut threads = 1usize;
+    let mut records = 100_000u64;
+    let mut ops = 100_000u64;
+    let mut dist = None;
+    let mut seed = 0u64;
+    let mut load = true;
+    let mut pool = None;
+    let mut i = 1;
+    while i < args.len() {
+        let s = &args[i];
+        if s == "-h" {
+            help();
+            return;
+        } else if s == "-w" {
+            let w: char = arg(&args, &mut i, "-w");
+            workload = Workload::core(w).expect("Workload should be one of A to F");
+        } else if s == "-m" {
+            let m: String = arg(&args, &mut i, "-m");
+            map = Map::parse(&m).expect("Map should be one of btree, art, hash, or lru");
+        } else if s == "-t" {
+            threads = arg(&args, &mut i, "-t");
+            if threads < 1 {
+                panic!("Number of threads cannot be less than 1");
+            }
+        } else if s == "-r" {
+            records = arg(&args, &mut i, "-r");
+            if records < 1 {
+                panic!("Number of records cannot be less than 1");
+            }
+        } else if s == "-o" {
+            ops = arg(&args, &mut i, "-o");
+        } else if s == "-d" {
+            let d: String = arg(&args, &mut i, "-d");
+            dist = Some(Distribution::parse(&d)
+                .expect("Distribution should be one of zipfian, uniform, or latest"));
+        } else if s == "-s" {
+            seed = arg(&args, &mut i, "-s");
+        } else if s == "-N" {
+            load = false;
+        } else if s.starts_with('-') {
+            panic!("Unknown option {}", s);
+        } else {
+            pool = Some(s.clone());
+        }
+        i += 1;
+    }
+    let pool = match pool {
+        Some(pool) => pool,
+        None => {
+            help();
+            return;
+        }
+    };
+    if let Some(dist) = dist {
+        workload.dist = dist;
+    }
+
+    let root = P::open::<Root>(&pool, O_CFNE | O_16GB).unwrap();
+    let shared = Shared(&*root as *const Root);
+
+    if load {
+        let start = Instant::now();
+        let handles: Vec<_> = (0..threads as u64).map(|t| thread::spawn(move || {
+            let root = unsafe { &*shared.0 };
+            let mut r = t;
+            while r < records {
+                map.put(root, key_of(r), r);
+                r += threads as u64;
+            }
+        })).collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        let t = start.elapsed();
+        println!("Loaded {} records into {:?} in {:.3} s ({:.0} ops/s)",
+            records, map, t.as_secs_f64(), records as f64 / t.as_secs_f64());
+    }
+
+    let inserted = std::sync::Arc::new(Inserted::new(records));
+    let start = Instant::now();
+    let handles: Vec<_> = (0..threads as u64).map(|t| {
+        let workload = workload.clone();
+        let inserted = inserted.clone();
+        thread::spawn(move || {
+            let root = unsafe { &*shared.0 };
+            let mut rng = StdRng::seed_from_u64(seed ^ key_of(t));
+            let keys = KeyChooser::new(workload.dist, records);
+            let mut lat = Latencies::default();
+            let n = ops / threads as u64 + if t < ops % threads as u64 { 1 } else { 0 };
+            for _ in 0..n {
+                let op = workload.next_op(&mut rng);
+                let begin = Instant::now();
+                match op {
+                    Op::Read => {
+                        let r = keys.next(&mut rng, inserted.count());
+                        map.get(root, key_of(r));
+                    }
+                    Op::Update => {
+                        let r = keys.next(&mut rng, inserted.count());
+                        map.put(root, key_of(r), r);
+                    }
+                    Op::Insert => {
+                        let r = inserted.next();
+                        map.put(root, key_of(r), r);
+                    }
+                    Op::Scan => {
+                        let count = inserted.count();
+                        let r = keys.next(&mut rng, count);
+                        let len = rand::Rng::gen_range(&mut rng, 1..=MAX_SCAN);
+                        map.scan(root, r, len, count);
+                    }
+                    Op::ReadModifyWrite => {
+                        let r = keys.next(&mut rng, inserted.count());
+                        map.read_modify_write(root, key_of(r));
+                    }
+                }
+                lat.record(op, begin.elapsed());
+            }
+            lat
+        })
+    }).collect();
+    let mut lat = Latencies::default();
+    for h in handles {
+        lat.merge(h.join().unwrap());
+    }
+    let t = start.elapsed();
+
+    println!("Workload {} on {:?} ({:?}, {} threads)", workload.name, map, workload.dist, threads);
+    println!("Ran {} operations in {:.3} s ({:.0} ops/s)",
+        ops, t.as_secs_f64(), ops as f64 / t.as_secs_f64());
+    lat.report();
+}
@@ -0,0 +1,189 @@
+//! The YCSB core workloads and the key generators
+
+use rand::rngs::StdRng;
+use rand::Rng;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// An operation of a workload
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Op {
+    Read,
+    Update,
+    Insert,
+    Scan,
+    ReadModifyWrite,
+}
+
+impl Op {
+    pub const ALL: [Op; 5] = [Op::Read, Op::Update, Op::Insert, Op::Scan, Op::ReadModifyWrite];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Op::Read => "READ",
+            Op::Update => "UPDATE",
+            Op::Insert => "INSERT",
+            Op::Scan => "SCAN",
+            Op::ReadModifyWrite => "READ-MODIFY-WRITE",
+        }
+    }
+}
+
+/// The distribution of the keys which the operations access
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Distribution {
+    Uniform,
+    Zipfian,
+    Latest,
+}
+
+impl Distribution {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "uniform" => Some(Distribution::Uniform),
+            "zipfian" => Some(Distribution::Zipfian),
+            "latest" => Some(Distribution::Latest),
+            _ => None,
+        }
+    }
+}
+
+/// A workload as the proportions of the operations and the distribution of
+/// the keys
+#[derive(Clone, Debug)]
+pub struct Workload {
+    pub name: char,
+    pub mix: Vec<(Op, f64)>,
+    pub dist: Distribution,
+}
+
+impl Workload {
+    /// Returns the core workload `name` (`A` to `F`)
+    pub fn core(name: char) -> Option<Self> {
+        use Op::*;
+        let (mix, dist) = match name.to_ascii_uppercase() {
+            // Update heavy
+            'A' => (vec![(Read, 0.5), (Update, 0.5)], Distribution::Zipfian),
+            // Read mostly
+            'B' => (vec![(Read, 0.95), (Update, 0.05)], Distribution::Zipfian),
+            // Read only
+            'C' => (vec![(Read, 1.0)], Distribution::Zipfian),
+            // Read latest
+            'D' => (vec![(Read, 0.95), (Insert, 0.05)], Distribution::Latest),
+            // Short ranges
+            'E' => (vec![(Scan, 0.95), (Insert, 0.05)], Distribution::Zipfian),
+            // Read-modify-write
+            'F' => (vec![(Read, 0.5), (ReadModifyWrite, 0.5)], Distribution::Zipfian),
+            _ => return None,
+        };
+        Some(Workload { name: name.to_ascii_uppercase(), mix, dist })
+    }
+
+    pub fn next_op(&self, rng: &mut StdRng) -> Op {
+        let mut p: f64 = rng.gen();
+        for (op, w) in &self.mix {
+            if p < *w {
+                return *op;
+            }
+            p -= w;
+        }
+        self.mix[self.mix.len() - 1].0
+    }
+}
+
+/// Spreads the record numbers over the key space, so that the popular
+/// records are not clustered (`FNV-1a`)
+pub fn key_of(n: u64) -> u64 {
+    let mut h = 0xcbf2_9ce4_8422_2325u64;
+    for b in n.to_le_bytes().iter() {
+        h ^= *b as u64;
+        h = h.wrapping_mul(0x100_0000_01b3);
+    }
+    h
+}
+
+/// The Zipfian generator of Gray et al., as in YCSB
+pub struct Zipfian {
+    items: u64,
+    theta: f64,
+    alpha: f64,
+    zetan: f64,
+    eta: f64,
+}
+
+impl Zipfian {
+    pub const THETA: f64 = 0.99;
+
+    pub fn new(items: u64) -> Self {
+        let theta = Self::THETA;
+        let zetan = Self::zeta(items, theta);
+        let zeta2 = Self::zeta(2, theta);
+        Zipfian {
+            items,
+            theta,
+            alpha: 1.0 / (1.0 - theta),
+            zetan,
+            eta: (1.0 - (2.0 / items as f64).powf(1.0 - theta)) / (1.0 - zeta2 / zetan),
+        }
+    }
+
+    fn zeta(n: u64, theta: f64) -> f64 {
+        (1..=n).map(|i| 1.0 / (i as f64).powf(theta)).sum()
+    }
+
+    /// Returns a number in `0..items`, where the smaller ones are more
+    /// popular
+    pub fn next(&self, rng: &mut StdRng) -> u64 {
+        let u: f64 = rng.gen();
+        let uz = u * self.zetan;
+        if uz < 1.0 {
+            0
+        } else if uz < 1.0 + 0.5f64.powf(self.theta) {
+            1
+        } else {
+            let n = self.items as f64 * (self.eta * u - self.eta + 1.0).powf(self.alpha);
+            (n as u64).min(self.items - 1)
+        }
+    }
+}
+
+/// Chooses the records which the operations access
+pub struct KeyChooser {
+    dist: Distribution,
+    zipf: Zipfian,
+}
+
+impl KeyChooser {
+    pub fn new(dist: Distribution, records: u64) -> Self {
+        KeyChooser { dist, zipf: Zipfian::new(records.max(2)) }
+    }
+
+    /// Returns the number of an existing record, given that `count` records
+    /// are inserted so far
+    pub fn next(&self, rng: &mut StdRng, count: u64) -> u64 {
+        match self.dist {
+            Distribution::Uniform => rng.gen_range(0..count),
+            // The popular records are scattered by `key_of()`
+            Distribution::Zipfian => self.zipf.next(rng) % count,
+            // The newest records are the most popular
+            Distribution::Latest => count - 1 - self.zipf.next(rng) % count,
+        }
+    }
+}
+
+/// The number of the records which are inserted, shared by all threads
+pub struct Inserted(AtomicU64);
+
+impl Inserted {
+    pub fn new(n: u64) -> Self {
+        Inserted(AtomicU64::new(n))
+    }
+
+    pub fn count(&self) -> u64 {
+        self.0.load(Ordering::Acquire)
+    }
+
+    /// Reserves the number of the next record
+    pub fn next(&self) -> u64 {
+        self.0.fetch_add(1, Ordering::AcqRel)
+    }
+}
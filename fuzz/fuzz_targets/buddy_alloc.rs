@@ -0,0 +1,86 @@
+//! Random sequences of allocations and deallocations on the buddy allocator
+//!
+//! The free lists are verified after every operation. Every block is filled
+//! with its own tag, which should be intact when it is freed; otherwise, two
+//! live blocks overlap. The pool should be back to its initial usage after
+//! the remaining blocks are freed.
+//!
+//! ```sh
+//! cargo +nightly fuzz run buddy_alloc
+//! ```
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use corundum::default::*;
+use libfuzzer_sys::fuzz_target;
+use std::sync::Once;
+
+type P = Allocator;
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    /// Allocates a block of the given size
+    Alloc(u16),
+
+    /// Frees the n-th live block, if any
+    Free(u8),
+
+    /// Allocates a large block (up to 1MiB)
+    AllocLarge(u8),
+}
+
+static INIT: Once = Once::new();
+
+fn init() {
+    INIT.call_once(|| {
+        // `verify()` only walks the free lists if `VERIFY` is set
+        std::env::set_var("VERIFY", "1");
+        let path = std::env::temp_dir().join(format!("buddy-fuzz-{}.pool", std::process::id()));
+        let pool = P::open_no_root(path.to_str().unwrap(), O_CF).unwrap();
+        std::mem::forget(pool);
+    });
+}
+
+fuzz_target!(|ops: Vec<Op>| {
+    init();
+    let used = P::used();
+    let mut live: Vec<Block> = vec![];
+    for (i, op) in ops.into_iter().enumerate() {
+        match op {
+            Op::Alloc(size) => alloc(&mut live, size as usize, i as u8),
+            Op::AllocLarge(size) => alloc(&mut live, (size as usize) << 12, i as u8),
+            Op::Free(n) => {
+                if !live.is_empty() {
+                    free(live.swap_remove(n as usize % live.len()));
+                }
+            }
+        }
+        assert!(P::verify(), "the free lists are corrupted");
+    }
+    for block in live {
+        free(block);
+    }
+    assert!(P::verify(), "the free lists are corrupted");
+    assert_eq!(P::used(), used, "leaked memory");
+});
+
+/// A live block: its address, size, and tag
+type Block = (*mut u8, usize, u8);
+
+fn alloc(live: &mut Vec<Block>, size: usize, tag: u8) {
+    if size == 0 {
+        return;
+    }
+    let (ptr, _, len) = unsafe { P::alloc(size) };
+    if !ptr.is_null() {
+        unsafe { std::ptr::write_bytes(ptr, tag, len); }
+        live.push((ptr, len, tag));
+    }
+}
+
+fn free((ptr, len, tag): Block) {
+    let data = unsafe { std::slice::from_raw_parts(ptr, len) };
+    assert!(data.iter().all(|b| *b == tag), "a live block is overwritten");
+    unsafe { P::dealloc(ptr, len); }
+}
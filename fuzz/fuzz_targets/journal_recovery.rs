@@ -0,0 +1,71 @@
+//! Recovery of corrupted pool images
+//!
+//! The seed image is taken in the middle of a transaction, so it has a
+//! journal with data, allocation, and drop logs to recover. Every input is a
+//! list of byte patches (a 4-byte offset and a value) applied to the seed
+//! image before opening it. Opening may fail, but it should neither crash nor
+//! hang; run it with a timeout to catch the latter:
+//!
+//! ```sh
+//! cargo +nightly fuzz run journal_recovery -- -timeout=10
+//! ```
+
+#![no_main]
+
+use corundum::default::*;
+use libfuzzer_sys::fuzz_target;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+type P = Allocator;
+
+static SEED: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// The files are named by the process id so that parallel jobs do not share
+/// them; every file is removed once it is used
+fn pool_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("recovery-fuzz-{}-{}.pool", std::process::id(), name))
+}
+
+/// Makes the seed image by copying the pool file while a transaction runs
+fn seed() -> &'static [u8] {
+    SEED.get_or_init(|| {
+        let path = pool_path("seed");
+        let copy = pool_path("copy");
+        let root = P::open::<PRefCell<PVec<u64>>>(path.to_str().unwrap(), O_CF).unwrap();
+        P::transaction(|j| {
+            root.borrow_mut(j).extend_from_slice(&[1, 2, 3, 4], j);
+        }).unwrap();
+        let _ = P::transaction(|j| {
+            let mut v = root.borrow_mut(j);
+            v.swap_remove(0);
+            v.push(5, j);
+            let b = Pbox::new(v.len() as u64, j);
+            drop(b);
+            std::fs::copy(&path, &copy).unwrap();
+            // Unwinding without a panic, as the fuzzer aborts at panics
+            std::panic::resume_unwind(Box::new(()));
+        });
+        drop(root);
+        let seed = std::fs::read(&copy).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&copy);
+        seed
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut image = seed().to_vec();
+    for patch in data.chunks_exact(5) {
+        let off = u32::from_le_bytes([patch[0], patch[1], patch[2], patch[3]]) as usize;
+        let len = image.len();
+        image[off % len] = patch[4];
+    }
+    let path = pool_path("input");
+    std::fs::write(&path, &image).unwrap();
+    if let Ok(root) = P::open::<PRefCell<PVec<u64>>>(path.to_str().unwrap(), 0) {
+        // The recovered root should be readable
+        let _ = root.borrow().iter().sum::<u64>();
+    }
+    let _ = std::fs::remove_file(&path);
+});
@@ -5,6 +5,19 @@ use std::ops::{Index,IndexMut};
 use std::marker::PhantomData;
 use std::mem;
 
+/// The version of the layout of the pool header which is created by the
+/// [`pool!()`] macro
+///
+/// It is folded into the magic number of the pool, so that an image with a
+/// different layout is rejected with [`InvalidImage`] rather than misparsed.
+/// It should be increased whenever a field of the header is added, removed,
+/// or moved.
+///
+/// [`pool!()`]: ../macro.pool.html
+/// [`InvalidImage`]: ../enum.Error.html#variant.InvalidImage
+#[doc(hidden)]
+pub const BUDDY_LAYOUT_VERSION: u64 = 2;
+
 #[repr(transparent)]
 #[derive(Clone, Debug)]
 /// Buddy memory block
@@ -96,11 +109,11 @@ pub struct BuddyAlg<A: MemPool> {
     /// The stat_footprint of memory usage in bytes
     foot_print: usize,
 
-    #[cfg(not(any(feature = "no_pthread", windows)))]
+    #[cfg(not(any(feature = "no_pthread", windows, miri, loom)))]
     /// A mutex for atomic operations
     mutex: (libc::pthread_mutex_t, libc::pthread_mutexattr_t),
 
-    #[cfg(any(feature = "no_pthread", windows))]
+    #[cfg(any(feature = "no_pthread", windows, miri, loom))]
     /// A mutex for atomic operations
     mutex: u64,
 
@@ -125,27 +138,46 @@ pub fn get_idx(x: usize) -> usize {
 
 impl<A: MemPool> BuddyAlg<A> {
     /// Pool Initialization with a given device size
-    pub fn init(&mut self, base: u64, size: usize) {
-        let mut idx = get_idx(size);
-        if 1 << idx > size {
-            idx -= 1;
-        }
+    ///
+    /// The range `[base, base+size)` is carved into free blocks that are
+    /// naturally aligned to their size, so that `base` and `size` are not
+    /// required to be powers of two, and no space is discarded. The first
+    /// `reserved` bytes of the range (e.g., the pool header) are kept out of
+    /// the free lists, and they are counted as used.
+    pub fn init(&mut self, base: u64, size: usize, reserved: usize) {
+        assert!(reserved <= size, "reserved space exceeds the zone ({} > {})", reserved, size);
+        let min = mem::size_of::<Buddy>() as u64;
+        let end = base + size as u64;
+        let mut tails = [u64::MAX; 64];
+        let mut off = (base + reserved as u64 + min - 1) & !(min - 1);
         self.buddies = [u64::MAX; 64];
-        self.size = 1 << idx;
+        self.size = 0;
+        self.last_idx = 0;
+        while off + min <= end {
+            let fit = 63 - (end - off).leading_zeros() as usize;
+            let idx = if off == 0 { fit } else { fit.min(off.trailing_zeros() as usize) };
+            Self::buddy(off).next = u64::MAX;
+            if tails[idx] == u64::MAX {
+                self.buddies[idx] = off;
+            } else {
+                Self::buddy(tails[idx]).next = off;
+            }
+            tails[idx] = off;
+            self.size += 1 << idx;
+            self.last_idx = self.last_idx.max(idx);
+            off += 1 << idx;
+        }
         self.available = self.size;
-        self.buddies[idx] = base;
-        self.last_idx = idx;
+        self.size += reserved;
         self.log64.clear();
         self.drop_log.clear();
         self.aux.clear();
 
-        Self::buddy(base).next = u64::MAX;
-
-        #[cfg(not(any(feature = "no_pthread", windows)))] unsafe {
+        #[cfg(not(any(feature = "no_pthread", windows, miri, loom)))] unsafe {
         crate::sync::init_lock(&mut self.mutex.0, &mut self.mutex.1);
         }
 
-        #[cfg(any(feature = "no_pthread", windows))] {
+        #[cfg(any(feature = "no_pthread", windows, miri, loom))] {
         self.mutex = 0; }
     }
 
@@ -171,12 +203,14 @@ impl<A: MemPool> BuddyAlg<A> {
         unsafe { 
             // debug_assert!(self.aux.empty(), "locked before: aux is not empty");
 
-            #[cfg(not(any(feature = "no_pthread", windows)))]
+            #[cfg(not(any(feature = "no_pthread", windows, miri, loom)))]
             libc::pthread_mutex_lock(&mut self.mutex.0); 
 
-            #[cfg(any(feature = "no_pthread", windows))] {
+            #[cfg(any(feature = "no_pthread", windows, miri, loom))] {
                 let tid = std::thread::current().id().as_u64().get();
-                while std::intrinsics::atomic_cxchg_acqrel(&mut self.mutex, 0, tid).0 != tid {}
+                while std::intrinsics::atomic_cxchg_acqrel(&mut self.mutex, 0, tid).0 != tid {
+                    crate::ll::model::spin_loop();
+                }
             }
         }
     }
@@ -184,10 +218,10 @@ impl<A: MemPool> BuddyAlg<A> {
     #[inline]
     fn unlock(&mut self) {
         unsafe { 
-            #[cfg(not(any(feature = "no_pthread", windows)))]
+            #[cfg(not(any(feature = "no_pthread", windows, miri, loom)))]
             libc::pthread_mutex_unlock(&mut self.mutex.0); 
 
-            #[cfg(any(feature = "no_pthread", windows))]
+            #[cfg(any(feature = "no_pthread", windows, miri, loom))]
             std::intrinsics::atomic_store_rel(&mut self.mutex, 0);
         }
     }
@@ -224,18 +258,39 @@ impl<A: MemPool> BuddyAlg<A> {
     pub fn drain_aux(&mut self) {
         sfence();
 
+        #[cfg(feature = "wal")]
+        let mut words = if crate::stm::wal::is_active() { Some(vec![]) } else { None };
+
         self.aux_valid = true;
         self.aux.foreach(|(off, next)| {
             let n = Self::buddy(off);
             n.next = next;
+            #[cfg(feature = "wal")] {
+                if let Some(words) = &mut words {
+                    words.push((off, next));
+                }
+            }
         });
         self.aux.clear();
         self.log64.foreach(|(off, data)| unsafe {
             let n = Self::buddy(off);
             std::intrinsics::atomic_store_rel(&mut n.next, data);
+            #[cfg(feature = "wal")] {
+                if let Some(words) = &mut words {
+                    words.push((off, data));
+                }
+            }
         });
         self.log64.clear();
         self.available = self.available_log;
+
+        #[cfg(feature = "wal")] {
+            if let Some(mut words) = words {
+                let available = &self.available as *const usize as u64 - A::start();
+                words.push((available, self.available as u64));
+                crate::stm::wal::record_words::<A>(&words);
+            }
+        }
     }
 
     #[inline(always)]
@@ -288,27 +343,193 @@ impl<A: MemPool> BuddyAlg<A> {
                 res = self.find_free_memory(idx + 1, true)?;
             }
             if idx > 0 && split {
-                let next = res + (1 << (idx - 1));
-                let mut curr = self.buddies[idx - 1];
-                let mut prev: Option<u64> = None;
+                self.insert_free(idx - 1, res + (1 << (idx - 1)));
+            }
+            Some(res)
+        }
+    }
 
-                while let Some(b) = off_to_option(curr) {
-                    if b > next {
-                        break;
-                    }
-                    prev = Some(b);
-                    curr = Self::buddy(b).next;
+    #[inline]
+    /// Inserts a free block into the sorted free list `idx`
+    unsafe fn insert_free(&mut self, idx: usize, off: u64) {
+        let mut curr = self.buddies[idx];
+        let mut prev: Option<u64> = None;
+
+        while let Some(b) = off_to_option(curr) {
+            if b > off {
+                break;
+            }
+            prev = Some(b);
+            curr = Self::buddy(b).next;
+        }
+
+        if let Some(p) = prev {
+            self.aux_push(off, Self::buddy(p).next);
+            self.aux_push(p, off);
+        } else {
+            self.aux_push(off, self.buddies[idx]);
+            self.aux_push(Self::get_off(&self.buddies[idx]), off);
+        }
+    }
+
+    /// Finds a free block in which `pick` finds a sub-block of size `2^idx`.
+    /// `pick` receives the offset and the list index of a free block, and
+    /// returns the offset of a suitable sub-block in it, if any. It returns
+    /// the list index, the block offset, the previous block in the list, and
+    /// the offset of the sub-block.
+    fn find_sub_block<F: Fn(u64, usize) -> Option<u64>>(&self, idx: usize, pick: F)
+        -> Option<(usize, u64, Option<u64>, u64)>
+    {
+        for j in idx..self.last_idx + 1 {
+            let mut curr = self.buddies[j];
+            let mut prev: Option<u64> = None;
+            while let Some(b) = off_to_option(curr) {
+                if let Some(t) = pick(b, j) {
+                    return Some((j, b, prev, t));
                 }
+                prev = Some(b);
+                curr = Self::buddy(b).next;
+            }
+        }
+        None
+    }
 
-                if let Some(p) = prev {
-                    self.aux_push(next, Self::buddy(p).next);
-                    self.aux_push(p, next);
-                } else {
-                    self.aux_push(next, self.buddies[idx - 1]);
-                    self.aux_push(Self::get_off(&self.buddies[idx - 1]), next);
+    /// Finds a free block in which a sub-block of size `2^idx` starts at an
+    /// address aligned to `align`
+    fn find_aligned(&self, idx: usize, align: u64) -> Option<(usize, u64, Option<u64>, u64)> {
+        let len = 1u64 << idx;
+        let g = align.min(len);
+        self.find_sub_block(idx, |b, j| {
+            let addr = A::start() + b;
+            if addr % g == 0 {
+                let t = ((addr + align - 1) & !(align - 1)) - A::start();
+                if t + len <= b + (1 << j) {
+                    return Some(t);
                 }
             }
-            Some(res)
+            None
+        })
+    }
+
+    /// Finds a free block in which a sub-block of size `2^idx` starts on the
+    /// DIMM `set` of the interleave topology `topo`
+    fn find_interleaved(&self, idx: usize, topo: &InterleaveTopology, set: usize)
+        -> Option<(usize, u64, Option<u64>, u64)>
+    {
+        let len = 1u64 << idx;
+
+        // The pattern repeats after `width` bytes
+        let candidates = (topo.width() as u64 / len).max(1);
+        self.find_sub_block(idx, |b, j| {
+            (0..candidates.min(1 << (j - idx)))
+                .map(|k| b + k * len)
+                .find(|t| topo.set_of(*t) == set)
+        })
+    }
+
+    /// Removes the free block `b` of the list `j` and splits it down to the
+    /// sub-block `t` of size `2^idx`. The rest of the block remains free.
+    fn claim_sub_block(&mut self, idx: usize, (j, b, prev, t): (usize, u64, Option<u64>, u64)) {
+        // Remove the free block from its list
+        let next = Self::buddy(b).next;
+        if let Some(p) = prev {
+            self.aux_push(p, next);
+        } else {
+            self.aux_push(Self::get_off(&self.buddies[j]), next);
+        }
+
+        // Split it down to the sub-block
+        let mut curr = b;
+        for k in (idx..j).rev() {
+            let half = 1u64 << k;
+            if t < curr + half {
+                self.insert_free(k, curr + half);
+            } else {
+                self.insert_free(k, curr);
+                curr += half;
+            }
+        }
+        debug_assert_eq!(curr, t);
+    }
+
+    /// Generates required changes to the metadata for allocating a new memory
+    /// block with the size `len` at an address aligned to `align` (a power of
+    /// two), and materialize them by calling [`drain_aux`](#methods.drain_aux)
+    /// according to the `perform` argument. If successful, it returns the
+    /// offset of the block. Otherwise, `u64::MAX` is returned.
+    ///
+    /// Buddy blocks are naturally aligned to their size. If `align` is larger
+    /// than the block size, it splits a larger free block so that the aligned
+    /// sub-block is allocated, and the rest of it remains free.
+    pub unsafe fn alloc_aligned_impl(&mut self, len: usize, align: usize, perform: bool) -> u64 {
+        debug_assert!(align.is_power_of_two(), "alignment should be a power of two");
+        self.lock();
+        let idx = get_idx(len);
+        let len = 1 << idx;
+
+        if len > self.available {
+            self.discard();
+            return u64::MAX;
+        }
+        let found = self.find_aligned(idx, align as u64);
+        self.alloc_sub_block(idx, found, perform)
+    }
+
+    /// Generates required changes to the metadata for allocating a new memory
+    /// block with the size `len` which starts on the DIMM `set` of the
+    /// interleave topology `topo`, and materialize them by calling
+    /// [`drain_aux`](#methods.drain_aux) according to the `perform` argument.
+    /// If successful, it returns the offset of the block. Otherwise,
+    /// `u64::MAX` is returned.
+    pub unsafe fn alloc_interleaved_impl(&mut self, len: usize, topo: &InterleaveTopology,
+        set: usize, perform: bool) -> u64
+    {
+        self.lock();
+        let idx = get_idx(len);
+        let len = 1 << idx;
+
+        if len > self.available {
+            self.discard();
+            return u64::MAX;
+        }
+        let found = self.find_interleaved(idx, topo, set % topo.ways());
+        self.alloc_sub_block(idx, found, perform)
+    }
+
+    /// Claims the sub-block `found` of size `2^idx`, if any, and finishes the
+    /// allocation. The allocator should be locked.
+    unsafe fn alloc_sub_block(&mut self, idx: usize, found: Option<(usize, u64, Option<u64>, u64)>,
+        perform: bool) -> u64
+    {
+        let len = 1 << idx;
+        match found {
+            Some(found) => {
+                let t = found.3;
+                self.claim_sub_block(idx, found);
+
+                #[cfg(feature = "verbose")]
+                debug_alloc::<A>(t, len, self.used(), self.used() + len);
+
+                self.available_log = self.available - len;
+                self.aux.sync_all();
+                if perform {
+                    self.perform();
+                }
+
+                #[cfg(feature = "stat_footprint")]
+                {
+                    let usage = self.size - self.available_log;
+                    if usage > self.foot_print {
+                        self.foot_print = usage;
+                    }
+                }
+
+                t
+            }
+            None => {
+                self.discard();
+                u64::MAX
+            }
         }
     }
 
@@ -350,11 +571,6 @@ impl<A: MemPool> BuddyAlg<A> {
                     off
                 }
                 None => {
-                    eprintln!(
-                        "Cannot find memory slot of size {} (available: {})",
-                        len,
-                        self.available()
-                    );
                     self.discard();
                     u64::MAX
                 }
@@ -384,6 +600,87 @@ impl<A: MemPool> BuddyAlg<A> {
         }
     }
 
+    /// Finds the free block at offset `off` in the free list `idx`. If
+    /// found, it returns the previous block in the list, if any.
+    fn find_free(&self, idx: usize, off: u64) -> Option<Option<u64>> {
+        let mut curr = self.buddies[idx];
+        let mut prev: Option<u64> = None;
+        while let Some(b) = off_to_option(curr) {
+            if b == off {
+                return Some(prev);
+            }
+            if b > off {
+                break;
+            }
+            prev = Some(b);
+            curr = Self::buddy(b).next;
+        }
+        None
+    }
+
+    /// Determines if the allocated block at offset `off` with the size of
+    /// `2^idx` can grow in place to the size of `2^new_idx`. It requires the
+    /// block to be the left buddy at every level in between, and all right
+    /// buddies to be free.
+    pub fn can_grow(&mut self, off: u64, idx: usize, new_idx: usize) -> bool {
+        self.lock();
+        let res = new_idx <= self.last_idx && (idx..new_idx).all(|i| {
+            off & (1 << i) == 0 && self.find_free(i, off + (1 << i)).is_some()
+        });
+        self.unlock();
+        res
+    }
+
+    /// Generates required changes to the metadata for growing the allocated
+    /// block at offset `off` from `2^idx` to `2^new_idx` bytes in place by
+    /// claiming its free right buddies, and materialize them by calling
+    /// [`drain_aux`](#methods.drain_aux) according to the `perform` argument.
+    /// If the block cannot grow in place, it returns false without any change.
+    pub unsafe fn grow_impl(&mut self, off: u64, idx: usize, new_idx: usize, perform: bool) -> bool {
+        self.lock();
+        if new_idx > self.last_idx {
+            self.discard();
+            return false;
+        }
+        for i in idx..new_idx {
+            let b = off + (1 << i);
+            match self.find_free(i, b) {
+                Some(prev) if off & (1 << i) == 0 => {
+                    let next = Self::buddy(b).next;
+                    if let Some(p) = prev {
+                        self.aux_push(p, next);
+                    } else {
+                        self.aux_push(Self::get_off(&self.buddies[i]), next);
+                    }
+                }
+                _ => {
+                    self.discard();
+                    return false;
+                }
+            }
+        }
+
+        #[cfg(feature = "verbose")]
+        debug_alloc::<A>(off + (1 << idx), (1 << new_idx) - (1 << idx),
+            self.used(), self.used() + (1 << new_idx) - (1 << idx));
+
+        self.available_log = self.available - ((1 << new_idx) - (1 << idx));
+        self.aux.sync_all();
+        if perform {
+            self.perform();
+        }
+
+        #[cfg(feature = "stat_footprint")]
+        {
+            let usage = self.size - self.available_log;
+            if usage > self.foot_print {
+                self.foot_print = usage;
+            }
+        }
+
+        true
+    }
+
     #[inline]
     unsafe fn free_impl(&mut self, off: u64, len: usize) {
         let idx = get_idx(len);
@@ -484,11 +781,11 @@ impl<A: MemPool> BuddyAlg<A> {
     /// 
     /// [`DropOnFailure`]: ../alloc/trait.MemPool.html#method.drop_on_failure
     pub fn recover(&mut self) {
-        #[cfg(not(any(feature = "no_pthread", windows)))] unsafe {
+        #[cfg(not(any(feature = "no_pthread", windows, miri, loom)))] unsafe {
         crate::sync::init_lock(&mut self.mutex.0, &mut self.mutex.1);
         }
 
-        #[cfg(any(feature = "no_pthread", windows))] {
+        #[cfg(any(feature = "no_pthread", windows, miri, loom))] {
         self.mutex = 0; }
 
 
@@ -648,6 +945,39 @@ impl<A: MemPool> BuddyAlg<A> {
         true
     }
 
+    /// Calls `f` for every free block with its offset and length
+    pub fn foreach_free<F: FnMut(u64, usize)>(&mut self, mut f: F) {
+        self.lock();
+        for idx in 3..self.last_idx + 1 {
+            let mut curr = self.buddies[idx];
+            while let Some(b) = off_to_option(curr) {
+                f(b, 1 << idx);
+                curr = Self::buddy(b).next;
+            }
+        }
+        self.unlock();
+    }
+
+    /// Returns the size of the largest free block
+    pub fn largest_free(&mut self) -> usize {
+        self.lock();
+        let mut res = 0;
+        for idx in (3..self.last_idx + 1).rev() {
+            if off_to_option(self.buddies[idx]).is_some() {
+                res = 1 << idx;
+                break;
+            }
+        }
+        self.unlock();
+        res
+    }
+
+    /// Indicates that the pool crashed while the allocator was operating
+    #[inline]
+    pub fn crashed(&self) -> bool {
+        self.aux_valid
+    }
+
     /// Prints the free lists
     pub fn print(&self) {
         println!();
@@ -752,6 +1082,8 @@ mod test {
     use crate::open_flags::*;
     type P = Allocator;
 
+    crate::pool!(three_zones, zones = 3);
+
     #[test]
     fn buddy_alg_test() {
         use rand::distributions::Alphanumeric;
@@ -827,6 +1159,102 @@ mod test {
 
         println!("{} -> {}", u, P::used());
     }
+
+    #[test]
+    fn buddy_aligned_alloc() {
+        let _pool = P::open_no_root("buddy_aligned.pool", O_CFNE).unwrap();
+        P::transaction(|j| {
+            for &align in &[8, 64, 4096, 65536] {
+                let b = Pbox::new_aligned([1u8; 24], align, j);
+                assert_eq!(&*b as *const _ as usize % align, 0);
+                assert_eq!(*b, [1u8; 24]);
+                let z = unsafe { P::new_zeroed::<[u64; 4]>(j) };
+                assert_eq!(*z, [0; 4]);
+                unsafe { P::free(z); }
+            }
+        }).unwrap();
+    }
+
+    #[test]
+    fn buddy_zones_use_whole_pool() {
+        type Z = three_zones::Allocator;
+
+        let _pool = Z::open_no_root("buddy_zones.pool", O_CFNE).unwrap();
+        let total = Z::used() + Z::available();
+        assert!(Z::size() - total < 3 * 16 + 3);
+        Z::transaction(|j| {
+            for &align in &[8, 4096, 65536] {
+                let b = three_zones::Pbox::new_aligned([1u8; 24], align, j);
+                assert_eq!(&*b as *const _ as usize % align, 0);
+            }
+        }).unwrap();
+    }
+
+    #[test]
+    fn buddy_header_with_uneven_quota() {
+        type Z = three_zones::Allocator;
+
+        // 8 MiB in 3 zones gives a quota which is not a power of two, and the
+        // zones have mixed alignments
+        let _pool = Z::open_no_root("buddy_uneven.pool", O_CF).unwrap();
+        let used = Z::used();
+        let mut blocks = vec![];
+        for shift in (4..23).rev() {
+            loop {
+                let (p, off, len, z) = unsafe { Z::pre_alloc(1 << shift) };
+                if off == u64::MAX {
+                    break;
+                }
+                unsafe { Z::perform(z); }
+                assert!(off as usize >= used, "block at {} overlaps the header", off);
+                blocks.push((p, len));
+            }
+        }
+        for (p, len) in blocks {
+            unsafe { std::ptr::write_bytes(p, 0xff, len); }
+        }
+        drop(_pool);
+        assert!(Z::open_no_root("buddy_uneven.pool", 0).is_ok());
+    }
+
+    #[test]
+    fn buddy_realloc_pinned() {
+        let _pool = P::open_no_root("buddy_pinned.pool", O_CFNE).unwrap();
+        P::transaction(|j| {
+            let mut v = PVec::<u64>::with_capacity(16, j);
+            v.extend_from_slice(&[1, 2, 3, 4], j);
+            let off = v.off();
+            P::pin(v.as_slice()).unwrap();
+
+            v.shrink_to(4, j);
+            assert_eq!(v.capacity(), 16);
+            match v.try_reserve(1 << 20, j) {
+                Ok(()) => assert_eq!(v.off(), off),
+                Err(_) => assert_eq!(v.capacity(), 16),
+            }
+            assert_eq!(v.as_slice(), &[1, 2, 3, 4]);
+
+            P::unpin(v.as_slice()).unwrap();
+            v.shrink_to(4, j);
+            assert_eq!(v.capacity(), 4);
+            assert_eq!(v.as_slice(), &[1, 2, 3, 4]);
+        }).unwrap();
+    }
+
+    #[test]
+    fn buddy_realloc_in_place() {
+        let _pool = P::open_no_root("buddy_realloc.pool", O_CFNE).unwrap();
+        P::transaction(|j| unsafe {
+            // An aligned block leaves its right buddies free
+            let p = P::new_uninit_aligned(64, 4096, j) as *mut u64;
+            let x = std::slice::from_raw_parts_mut(p, 8);
+            x.copy_from_slice(&[7; 8]);
+            let y = P::realloc(x, 64, j);
+            assert_eq!(y.as_ptr(), p as *const u64);
+            assert_eq!(&y[..8], &[7; 8]);
+            P::free_slice(y);
+        }).unwrap();
+    }
 }
 
 #[cfg(feature = "verbose")]
@@ -966,6 +1394,16 @@ macro_rules! __cfg_delete_history {
 /// # }
 /// ```
 /// 
+/// The generated allocator can be parameterized by `key = value` options
+/// after the module name (and the optional type name). See [`PoolConfig`] for
+/// the available options.
+/// 
+/// ```
+/// # fn main() {
+/// corundum::pool!(big_alloc, zones = 8, min_size = 64 * corundum::MB);
+/// # }
+/// ```
+/// 
 /// If multiple pools are needed, multiple pool modules can be defined and used.
 /// 
 /// ```
@@ -989,6 +1427,7 @@ macro_rules! __cfg_delete_history {
 /// ```
 /// 
 /// [`Allocator`]: ./alloc/default/struct.Allocator.html
+/// [`PoolConfig`]: ./alloc/struct.PoolConfig.html
 /// [`corundum::boxed::Pbox`]: ./boxed/struct.Pbox.html
 /// [`corundum::prc::Prc`]: ./prc/struct.Prc.html
 /// [`corundum::sync::Parc`]: ./sync/struct.Parc.html
@@ -1000,10 +1439,9 @@ macro_rules! __cfg_delete_history {
 /// [`corundum::vec::Vec`]: ./vec/struct.Vec.html
 /// [`corundum::str::String`]: ./str/struct.String.html
 macro_rules! pool {
-    ($mod:ident, $name:ident) => {
+    ($mod:ident, $name:ident $(, $key:ident = $val:expr)*) => {
         /// The default allocator module
         pub mod $mod {
-            use memmap::*;
             use std::collections::hash_map::DefaultHasher;
             use std::collections::{HashMap,HashSet};
             use std::fs::OpenOptions;
@@ -1011,7 +1449,7 @@ macro_rules! pool {
             use std::mem;
             use std::ops::Range;
             use std::path::{Path, PathBuf};
-            use std::sync::atomic::{AtomicBool, Ordering};
+            use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
             use std::sync::{Arc, Mutex};
             use std::thread::ThreadId;
             use $crate::ll::*;
@@ -1036,9 +1474,15 @@ macro_rules! pool {
                 MemPool
             };
     
+            const POOL_CONFIG: $crate::alloc::PoolConfig =
+                $crate::alloc::PoolConfig::new() $(.$key($val))*;
+
             static mut BUDDY_START: u64 = 0;
             static mut BUDDY_VALID_START: u64 = 0;
             static mut BUDDY_END: u64 = 0;
+            static mut BUDDY_INTERLEAVE: Option<InterleaveTopology> = None;
+            static mut BUDDY_DURABILITY: Option<Durability> = None;
+            static BUDDY_VERIFY_FAILURES: AtomicUsize = AtomicUsize::new(0);
     
             #[repr(C)]
             struct BuddyAllocInner {
@@ -1049,6 +1493,9 @@ macro_rules! pool {
                 root_obj: u64,
                 root_type_id: u64,
                 journals: u64,
+                arenas: u64,
+                commit_ts: u64,
+                multi: $crate::stm::multi::Decisions,
                 size: usize,
                 zone: Zones<BuddyAlg<$name>, $name>
             }
@@ -1057,35 +1504,49 @@ macro_rules! pool {
                 filename: String,
                 journals: HashMap<ThreadId, (u64, i32)>,
                 check_double_free: HashSet<u64>,
-                mmap: MmapMut,
+                pins: PinTable,
+                mmap: PoolMapping,
             }
     
             impl VData {
-                fn new(mmap: MmapMut, filename: &str) -> Self {
+                fn new(mmap: PoolMapping, filename: &str) -> Self {
                     Self {
                         filename: filename.to_string(),
                         journals: HashMap::new(),
                         check_double_free: HashSet::new(),
+                        pins: PinTable::new(),
                         mmap,
                     }
                 }
             }
     
             impl BuddyAllocInner {
-                fn init(&mut self, size: usize) {
+                /// The magic number of the pool type and its layout version
+                fn magic() -> u64 {
                     let id = std::any::type_name::<Self>();
                     let mut s = DefaultHasher::new();
                     id.hash(&mut s);
+                    $crate::alloc::BUDDY_LAYOUT_VERSION.hash(&mut s);
+                    s.finish()
+                }
+
+                fn init(&mut self, size: usize) {
+                    self.magic_number = u64::MAX;
                     self.flags = 0;
                     self.gen = 1;
                     self.tx_gen = 0;
                     self.root_obj = u64::MAX;
                     self.root_type_id = 0;
                     self.journals = u64::MAX;
+                    self.arenas = u64::MAX;
+                    self.commit_ts = 0;
+                    self.multi.init();
                     self.size = size;
     
                     type T = BuddyAlg<$name>;
-                    let cpus = if let Some(val) = std::env::var_os("CPUS") {
+                    let cpus = if POOL_CONFIG.get_zones() != 0 {
+                        POOL_CONFIG.get_zones()
+                    } else if let Some(val) = std::env::var_os("CPUS") {
                         val.into_string().unwrap().parse::<usize>().unwrap()
                     } else {
                         num_cpus::get()
@@ -1093,17 +1554,14 @@ macro_rules! pool {
                     assert_ne!(cpus, 0);
                     let quota = size / cpus;
                     self.zone = Zones::new(cpus, mem::size_of::<Self>(), quota);
-                    for i in 0..cpus {
-                        self.zone[i].init((quota * i) as u64, quota);
+                    // The header (this object and the zones) is carved out at
+                    // offset 0 of the first zone, before building free lists
+                    let header = mem::size_of::<Self>() + mem::size_of::<T>() * cpus;
+                    self.zone[0].init(0, quota, header);
+                    for i in 1..cpus {
+                        self.zone[i].init((quota * i) as u64, quota, 0);
                     }
-                    self.magic_number = u64::MAX;
-                    unsafe {
-                        self.zone[0].alloc_impl(
-                            mem::size_of::<Self>() + mem::size_of::<T>() * cpus,
-                            true,
-                        );
-                    }
-                    self.magic_number = s.finish();
+                    self.magic_number = Self::magic();
                 }
     
                 fn as_bytes(&self) -> &[u8] {
@@ -1138,10 +1596,37 @@ macro_rules! pool {
             static mut BUDDY_INNER: Option<*mut BuddyAllocInner> = None;
             static mut OPEN: AtomicBool = AtomicBool::new(false);
             static mut MAX_GEN: u32 = 0;
+            static mut LAST_RECOVERY: Option<RecoveryStatus> = None;
             static mut VDATA: LazyCell<Arc<Mutex<Option<VData>>>> = 
                 LazyCell::new(|| Arc::new(Mutex::new(None)));
     
             impl $name {
+                fn pins<T, F: FnOnce(&mut PinTable) -> T>(f: F) -> Result<T> {
+                    let mut vdata = match unsafe { VDATA.lock() } {
+                        Ok(g) => g,
+                        Err(p) => p.into_inner()
+                    };
+                    match &mut *vdata {
+                        Some(vdata) => Ok(f(&mut vdata.pins)),
+                        None => Err($crate::Error::NotOpen)
+                    }
+                }
+
+                /// Runs `alloc`, and retries it once after running the
+                /// out-of-memory handler if it fails. No zone is locked when
+                /// an allocation fails, so the handler may use the pool.
+                unsafe fn retry_on_oom<F>(size: usize, alloc: F) -> (*mut u8, u64, usize, usize)
+                where
+                    F: Fn() -> (*mut u8, u64, usize, usize)
+                {
+                    let res = alloc();
+                    if res.0.is_null() && $crate::alloc::handle_oom(Self::name(), size) {
+                        alloc()
+                    } else {
+                        res
+                    }
+                }
+
                 fn running_transaction() -> bool {
                     let vdata = match unsafe { VDATA.lock() } {
                         Ok(g) => g,
@@ -1154,6 +1639,56 @@ macro_rules! pool {
                     }
                 }
     
+                /// Opens the pool and creates the root object using `init` if
+                /// it does not exist
+                #[allow(unused_unsafe)]
+                #[track_caller]
+                fn open_root<'a, U: 'a + PSafe, F>(
+                    path: &str,
+                    flags: u32,
+                    init: F,
+                ) -> Result<RootCell<'a, U, Self>>
+                where
+                    F: FnOnce(&$crate::stm::Journal<Self>) -> U + TxInSafe + std::panic::UnwindSafe
+                {
+                    let slf = Self::open_no_root(path, flags)?;
+                    static_inner!(BUDDY_INNER, inner, {
+                        // Replace it with std::any::TypeId::of::<U>() when it
+                        // is available in the future for non-'static types
+                        let id = format!("{} ({})", std::any::type_name::<U>(),
+                            mem::size_of::<U>());
+                        let mut s = DefaultHasher::new();
+                        id.hash(&mut s);
+                        let id = s.finish();
+                        if !inner.has_root() {
+                            if mem::size_of::<U>() == 0 {
+                                Err($crate::Error::ZeroSizedRoot)
+                            } else {
+                                let root_off = Self::transaction(move |j| {
+                                    let ptr = Self::new(init(j), j);
+                                    Self::off_unchecked(ptr)
+                                })
+                                .unwrap();
+                                let ptr = Self::get_unchecked(root_off);
+                                inner.flags |= FLAG_HAS_ROOT;
+                                inner.root_obj = root_off;
+                                inner.root_type_id = id;
+                                persist_obj(inner, true);
+                                Ok(RootCell::new(ptr, Arc::new(slf)))
+                            }
+                        } else {
+                            if inner.root_type_id == id {
+                                Ok(RootCell::new(
+                                    Self::deref::<U>(inner.root_obj)?,
+                                    Arc::new(slf),
+                                ))
+                            } else {
+                                Err($crate::Error::IncompatibleRoot)
+                            }
+                        }
+                    })
+                }
+
                 /// Opens a memory pool file and returns an instance of
                 /// [`Allocator`](#) if success. The pool remains open as long
                 /// as the instance lives.
@@ -1161,12 +1696,18 @@ macro_rules! pool {
                 pub fn open_impl(filename: &str, no_check: bool) -> Result<PoolGuard<Self>> {
                     let metadata = std::fs::metadata(filename);
                     if let Err(e) = &metadata {
-                        Err(format!("{}", e))
+                        if e.kind() == std::io::ErrorKind::NotFound {
+                            Err($crate::Error::FileNotFound(filename.to_string()))
+                        } else {
+                            Err($crate::Error::Io(e.kind(), e.to_string()))
+                        }
                     } else {
                         let metadata = metadata.unwrap();
                         assert!(metadata.is_file());
                         if metadata.len() < 8 {
-                            Err("Invalid pool file".to_string())
+                            Err($crate::Error::InvalidImage(filename.to_string()))
+                        } else if let Err(e) = POOL_CONFIG.check_size(metadata.len()) {
+                            Err(e)
                         } else {
                             let path = PathBuf::from(filename);
                             let file = OpenOptions::new()
@@ -1176,27 +1717,20 @@ macro_rules! pool {
                                 .open(&path)
                                 .unwrap();
     
-                            let mut mmap =
-                                unsafe { memmap::MmapOptions::new().map_mut(&file).unwrap() };
+                            let mmap = PoolMapping::new(&file, Self::map_mode())?;
+                            let raw_offset = unsafe { &mut *mmap.as_mut_ptr() };
     
-                            let raw_offset = mmap.get_mut(0).unwrap();
-    
-                            let id = std::any::type_name::<BuddyAllocInner>();
-                            let mut s = DefaultHasher::new();
-                            id.hash(&mut s);
-                            let id = s.finish();
+                            let id = BuddyAllocInner::magic();
     
                             let inner = unsafe {
                                 read::<BuddyAllocInner>(raw_offset)
                             };
-                            if !no_check {
-                                assert_eq!(
-                                    inner.magic_number, id,
-                                    "Invalid magic number for the pool image file"
-                                );
+                            if !no_check && inner.magic_number != id {
+                                return Err($crate::Error::InvalidImage(filename.to_string()));
                             }
     
                             let base = raw_offset as *mut _ as u64;
+                            apply_placements(Self::name(), base, inner.zone.quota(), inner.zone.count())?;
                             unsafe {
                                 inner.gen = MAX_GEN.max(inner.gen + 1);
                                 inner.tx_gen = 0;
@@ -1212,6 +1746,12 @@ macro_rules! pool {
                                     Err(p) => p.into_inner()
                                 };
                                 *vdata = Some(VData::new(mmap, filename));
+                                BUDDY_DURABILITY = Some(mmap.durability());
+                                if mmap.durability() == Durability::Msync {
+                                    $crate::ll::enable_msync();
+                                }
+                                $crate::alloc::register_media_range(BUDDY_START, BUDDY_END);
+                                BUDDY_INTERLEAVE = InterleaveTopology::detect(filename);
                             }
     
                             Ok(PoolGuard::<Self>::new())
@@ -1221,6 +1761,10 @@ macro_rules! pool {
             }
     
             unsafe impl MemPoolTraits for $name {
+                const METADATA_SIZE: usize = mem::size_of::<BuddyAllocInner>()
+                    + mem::size_of::<BuddyAlg<Self>>();
+                const CONFIG: $crate::alloc::PoolConfig = POOL_CONFIG;
+
                 #[inline]
                 fn name() -> &'static str {
                     stringify!($mod)
@@ -1235,7 +1779,7 @@ macro_rules! pool {
                             .create(true)
                             .open(filename);
                         if let Err(e) = &file {
-                            Err(format!("{}", e))
+                            Err($crate::Error::Io(e.kind(), e.to_string()))
                         } else {
                             let file = file.unwrap();
                             let mut len = file.metadata().unwrap().len() as usize;
@@ -1256,10 +1800,23 @@ macro_rules! pool {
                             Ok(())
                         }
                     } else {
-                        Err("Image file does not exist".to_string())
+                        Err($crate::Error::FileNotFound(filename.to_string()))
                     }
                 }
     
+                fn is_valid_image(path: &str) -> bool {
+                    use std::io::Read;
+
+                    let id = BuddyAllocInner::magic();
+
+                    let mut magic = [0u8; 8];
+                    if let Ok(mut file) = std::fs::File::open(path) {
+                        file.read_exact(&mut magic).is_ok() && u64::from_ne_bytes(magic) == id
+                    } else {
+                        false
+                    }
+                }
+
                 #[inline]
                 #[track_caller]
                 fn gen() -> u32 {
@@ -1323,7 +1880,7 @@ macro_rules! pool {
                 unsafe fn pre_alloc(size: usize) -> (*mut u8, u64, usize, usize) {
                     let _perf = $crate::__cfg_stat_perf!($crate::stat::Measure::<Self>::Alloc(std::time::Instant::now()));
     
-                    static_inner!(BUDDY_INNER, inner, {
+                    Self::retry_on_oom(size, || static_inner!(BUDDY_INNER, inner, {
                         let cpu = cpu();
                         let cnt = inner.zone.count();
                         for i in 0..cnt {
@@ -1333,14 +1890,53 @@ macro_rules! pool {
                                 return (Self::get_mut_unchecked(a), a, size, z);
                             }
                         }
-                        eprintln!(
-                            "No space left (requested = {}, available= {})",
-                            size, Self::available()
-                        );
                         (std::ptr::null_mut(), u64::MAX, 0, 0)
-                    })
+                    }))
                 }
     
+                #[allow(unused_unsafe)]
+                unsafe fn pre_alloc_aligned(size: usize, align: usize) -> (*mut u8, u64, usize, usize) {
+                    let _perf = $crate::__cfg_stat_perf!($crate::stat::Measure::<Self>::Alloc(std::time::Instant::now()));
+
+                    Self::retry_on_oom(size, || static_inner!(BUDDY_INNER, inner, {
+                        let cpu = cpu();
+                        let cnt = inner.zone.count();
+                        for i in 0..cnt {
+                            let z = (cpu+i)%cnt;
+                            let a = inner.zone[z].alloc_aligned_impl(size, align, false);
+                            if a != u64::MAX {
+                                return (Self::get_mut_unchecked(a), a, size, z);
+                            }
+                        }
+                        (std::ptr::null_mut(), u64::MAX, 0, 0)
+                    }))
+                }
+
+                #[allow(unused_unsafe)]
+                unsafe fn pre_alloc_hinted(size: usize, hint: AllocHint) -> (*mut u8, u64, usize, usize) {
+                    let (stripe, topo) = match (hint, BUDDY_INTERLEAVE) {
+                        (AllocHint::Interleave(stripe), Some(topo)) => (stripe, topo),
+                        _ => return Self::pre_alloc(size)
+                    };
+                    let _perf = $crate::__cfg_stat_perf!($crate::stat::Measure::<Self>::Alloc(std::time::Instant::now()));
+
+                    static_inner!(BUDDY_INNER, inner, {
+                        let cpu = cpu();
+                        let cnt = inner.zone.count();
+                        for i in 0..cnt {
+                            let z = (cpu+i)%cnt;
+                            let a = inner.zone[z].alloc_interleaved_impl(size, &topo, stripe, false);
+                            if a != u64::MAX {
+                                return (Self::get_mut_unchecked(a), a, size, z);
+                            }
+                        }
+
+                        // Fall back to a local allocation if no block is
+                        // available on the requested DIMM
+                        Self::pre_alloc(size)
+                    })
+                }
+
                 #[allow(unused_unsafe)]
                 #[track_caller]
                 unsafe fn pre_dealloc(ptr: *mut u8, size: usize) -> usize {
@@ -1362,6 +1958,46 @@ macro_rules! pool {
                     })
                 }
     
+                #[allow(unused_unsafe)]
+                unsafe fn grow_in_place(off: u64, size: usize, new_size: usize, j: &Journal) -> bool {
+                    let idx = get_idx(size);
+                    let new_idx = get_idx(new_size);
+                    if new_idx <= idx {
+                        return true;
+                    }
+
+                    // Each claimed buddy needs a log which takes two entries
+                    // of the `log64` ring
+                    if new_idx - idx > 4 {
+                        return false;
+                    }
+
+                    let can_grow = static_inner!(BUDDY_INNER, inner, {
+                        inner.zone.from_off(off).0.can_grow(off, idx, new_idx)
+                    });
+                    if !can_grow {
+                        return false;
+                    }
+
+                    let mut logs: Vec<_> = (idx..new_idx)
+                        .map(|_| $crate::stm::Log::drop_on_abort(u64::MAX, 1, j))
+                        .collect();
+
+                    static_inner!(BUDDY_INNER, inner, {
+                        let (zone, z) = inner.zone.from_off(off);
+                        if !zone.grow_impl(off, idx, new_idx, false) {
+                            return false;
+                        }
+                        for (i, log) in (idx..new_idx).zip(logs.iter_mut()) {
+                            let b = off + (1 << i);
+                            Self::drop_on_failure(b, 1 << i, z);
+                            log.set(b, 1 << i, z);
+                        }
+                        Self::perform(z);
+                        true
+                    })
+                }
+
                 #[inline]
                 #[allow(unused_unsafe)]
                 #[track_caller]
@@ -1439,12 +2075,17 @@ macro_rules! pool {
                     static_inner!(BUDDY_INNER, inner, {
                         for i in 0..inner.zone.count() {
                             if !inner.zone[i].verify() {
+                                BUDDY_VERIFY_FAILURES.fetch_add(1, Ordering::Relaxed);
                                 return false;
                             }
                         }
                         true
                     })
                 }
+
+                fn verify_failures() -> usize {
+                    BUDDY_VERIFY_FAILURES.load(Ordering::Relaxed)
+                }
     
                 #[inline]
                 #[allow(unused_unsafe)]
@@ -1454,6 +2095,22 @@ macro_rules! pool {
                         &inner.journals
                     })
                 }
+
+                #[inline]
+                #[allow(unused_unsafe)]
+                unsafe fn arenas_head() -> &'static u64 {
+                    static_inner!(BUDDY_INNER, inner, {
+                        &inner.arenas
+                    })
+                }
+
+                #[inline]
+                #[allow(unused_unsafe)]
+                unsafe fn multi_decisions() -> &'static mut $crate::stm::multi::Decisions {
+                    static_inner!(BUDDY_INNER, inner, {
+                        &mut inner.multi
+                    })
+                }
     
                 #[allow(unused_unsafe)]
                 #[track_caller]
@@ -1512,6 +2169,13 @@ macro_rules! pool {
                 #[allow(unused_unsafe,unused_braces)]
                 unsafe fn recover() {
                     static_inner!(BUDDY_INNER, inner, {
+                        let start = std::time::Instant::now();
+                        let mut status = RecoveryStatus {
+                            allocator_crashed: (0..inner.zone.count())
+                                .any(|i| inner.zone[i].crashed()),
+                            journals: 0,
+                            duration: Default::default(),
+                        };
                         let info_level = std::env::var("RECOVERY_INFO")
                             .unwrap_or("0".to_string())
                             .parse::<u32>()
@@ -1542,9 +2206,39 @@ macro_rules! pool {
                         let mut check_double_free = __cfg_delete_history!({
                             std::collections::HashSet::<u64>::new()
                         }, { () });
+
+                        // The relaxed transactions which were not flushed
+                        // roll back in the reverse order of their commits,
+                        // after the running ones
+                        let (mut running, mut pending) = (vec![], vec![]);
+                        let mut curr = inner.journals;
+                        while let Ok(j) = Self::deref_mut::<Journal>(curr) {
+                            if !j.is_committed() {
+                                if j.raw_commit_ts() == 0 {
+                                    running.push(curr);
+                                } else {
+                                    pending.push((j.raw_commit_ts(), curr));
+                                }
+                            }
+                            curr = j.next_off();
+                        }
+                        if !pending.is_empty() {
+                            pending.sort_unstable_by(|a, b| b.0.cmp(&a.0));
+                            running.extend(pending.into_iter().map(|(_, off)| off));
+                            for off in running {
+                                if let Ok(logs) = Self::deref_mut::<Journal>(off) {
+                                    __cfg_delete_history!({
+                                        logs.recover(&mut check_double_free);
+                                    }, {
+                                        logs.recover();
+                                    });
+                                }
+                            }
+                        }
                         
     
                         while let Ok(logs) = Self::deref_mut::<Journal>(inner.journals) {
+                            status.journals += 1;
     
                             $crate::__cfg_verbose!({
                                 if *utils::VERBOSE {
@@ -1580,51 +2274,32 @@ macro_rules! pool {
                                 Self::drop_journal(logs);
                             });
                         }
+
+                        $crate::stm::arena::reset::<Self>();
+
+                        status.duration = start.elapsed();
+                        LAST_RECOVERY = Some(status);
                     })
                 }
     
-                #[allow(unused_unsafe)]
                 #[track_caller]
                 fn open<'a, U: 'a + PSafe + RootObj<Self>>(
                     path: &str,
                     flags: u32,
                 ) -> Result<RootCell<'a, U, Self>> {
-                    let slf = Self::open_no_root(path, flags)?;
-                    static_inner!(BUDDY_INNER, inner, {
-                        // Replace it with std::any::TypeId::of::<U>() when it
-                        // is available in the future for non-'static types
-                        let id = format!("{} ({})", std::any::type_name::<U>(),
-                            mem::size_of::<U>());
-                        let mut s = DefaultHasher::new();
-                        id.hash(&mut s);
-                        let id = s.finish();
-                        if !inner.has_root() {
-                            if mem::size_of::<U>() == 0 {
-                                Err("root type cannot be a ZST".to_string())
-                            } else {
-                                let root_off = Self::transaction(move |j| {
-                                    let ptr = Self::new(U::init(j), j);
-                                    Self::off_unchecked(ptr)
-                                })
-                                .unwrap();
-                                let ptr = Self::get_unchecked(root_off);
-                                inner.flags |= FLAG_HAS_ROOT;
-                                inner.root_obj = root_off;
-                                inner.root_type_id = id;
-                                persist_obj(inner, true);
-                                Ok(RootCell::new(ptr, Arc::new(slf)))
-                            }
-                        } else {
-                            if inner.root_type_id == id {
-                                Ok(RootCell::new(
-                                    Self::deref::<U>(inner.root_obj)?,
-                                    Arc::new(slf),
-                                ))
-                            } else {
-                                Err("Incompatible root type".to_string())
-                            }
-                        }
-                    })
+                    Self::open_root(path, flags, |j| U::init(j))
+                }
+
+                #[track_caller]
+                fn open_with<'a, U: 'a + PSafe + RootObjWith<Self>>(
+                    path: &str,
+                    flags: u32,
+                    args: U::Args,
+                ) -> Result<RootCell<'a, U, Self>>
+                where
+                    U::Args: TxInSafe + std::panic::UnwindSafe
+                {
+                    Self::open_root(path, flags, move |j| U::init_with(args, j))
                 }
     
                 #[inline]
@@ -1638,22 +2313,24 @@ macro_rules! pool {
                     unsafe {
                         while OPEN.compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed).is_err() {}
                         if !Self::running_transaction() {
-                            if flags == open_flags::O_READINFO {
+                            let res = if flags == open_flags::O_READINFO {
                                 Self::open_impl(path, true)
-                            } else if let Ok(_) = Self::apply_flags(path, flags) {
+                            } else if let Err(e) = Self::apply_flags(path, flags) {
+                                Err(e)
+                            } else {
                                 let res = Self::open_impl(path, false);
                                 if res.is_ok() {
                                     Self::recover();
                                 }
                                 res
-                            } else {
+                            };
+                            if res.is_err() {
                                 OPEN.store(false, Ordering::Release);
-                                Err("Could not open file".to_string())
                             }
+                            res
                         } else {
                             OPEN.store(false, Ordering::Release);
-                            Err("An uncommitted transaction exists in the pool"
-                                .to_string())
+                            Err($crate::Error::UncommittedTransaction)
                         }
                     }
                 }
@@ -1661,16 +2338,27 @@ macro_rules! pool {
                 #[allow(unused_unsafe)]
                 unsafe fn close() -> Result<()> {
                     if OPEN.load(Ordering::Acquire) {
+                        $crate::stm::on_close::<Self>();
+                        // The pending ranges are synchronized while mapped
+                        let synced = if BUDDY_DURABILITY == Some(Durability::Msync) {
+                            $crate::ll::disable_msync()
+                        } else {
+                            Ok(())
+                        };
                         let mut vdata = match VDATA.lock() {
                             Ok(g) => g,
                             Err(p) => p.into_inner()
                         };
+                        $crate::alloc::unregister_media_range(BUDDY_START);
                         *vdata = None;
                         BUDDY_INNER = None;
+                        LAST_RECOVERY = None;
+                        BUDDY_INTERLEAVE = None;
+                        BUDDY_DURABILITY = None;
                         OPEN.store(false, Ordering::Release);
-                        Ok(())
+                        synced.map_err(|e| $crate::Error::Io(e.kind(), format!("msync failed: {}", e)))
                     } else {
-                        Err("Pool was already closed".to_string())
+                        Err($crate::Error::NotOpen)
                     }
                 }
     
@@ -1682,6 +2370,124 @@ macro_rules! pool {
                     })
                 }
     
+                fn interleave_topology() -> Option<InterleaveTopology> {
+                    unsafe { BUDDY_INTERLEAVE }
+                }
+
+                fn durability() -> Option<Durability> {
+                    unsafe { BUDDY_DURABILITY }
+                }
+
+                fn path() -> Option<String> {
+                    let vdata = match unsafe { VDATA.lock() } {
+                        Ok(g) => g,
+                        Err(p) => p.into_inner()
+                    };
+                    vdata.as_ref().map(|v| v.filename.clone())
+                }
+
+                fn commit_ts() -> u64 {
+                    static_inner!(BUDDY_INNER, inner, {
+                        std::intrinsics::atomic_load_acq(&inner.commit_ts)
+                    })
+                }
+
+                unsafe fn next_commit_ts() -> Option<u64> {
+                    static_inner!(BUDDY_INNER, inner, {
+                        let ts = std::intrinsics::atomic_xadd_acqrel(&mut inner.commit_ts, 1) + 1;
+                        $crate::ll::persist_obj(&inner.commit_ts, false);
+                        Some(ts)
+                    })
+                }
+
+                fn set_interleave_topology(topology: Option<InterleaveTopology>) -> Result<()> {
+                    unsafe {
+                        if !OPEN.load(Ordering::Acquire) {
+                            return Err($crate::Error::NotOpen);
+                        }
+                        BUDDY_INTERLEAVE = topology;
+                    }
+                    Ok(())
+                }
+
+                fn set_placement(zone: Option<usize>, placement: Placement) -> Result<()> {
+                    // The placement is validated before it is kept, as it is
+                    // applied again every time the pool is opened
+                    placement.validate()?;
+                    if !OPEN.load(Ordering::Acquire) {
+                        set_zone_placement(Self::name(), zone, placement);
+                        return Ok(());
+                    }
+                    static_inner!(BUDDY_INNER, inner, {
+                        let (quota, count) = (inner.zone.quota(), inner.zone.count());
+                        let zones = match zone {
+                            Some(z) if z >= count => return Err($crate::Error::InvalidArgument(
+                                format!("no zone index {} (max = {})", z, count - 1))),
+                            Some(z) => z..z + 1,
+                            None => 0..count
+                        };
+                        for z in zones {
+                            let p = match zone {
+                                Some(_) => placement,
+                                None => own_zone_placement(Self::name(), z).unwrap_or(placement)
+                            };
+                            p.apply(BUDDY_START + (z * quota) as u64, quota)?;
+                        }
+                        set_zone_placement(Self::name(), zone, placement);
+                        Ok(())
+                    })
+                }
+
+                fn space_map(granularity: u64) -> Result<SpaceMap> {
+                    if !granularity.is_power_of_two() {
+                        return Err($crate::Error::InvalidArgument(
+                            "granularity should be a power of two".to_string()));
+                    }
+                    static_inner!(BUDDY_INNER, inner, {
+                        let mut map = SpaceMap::new(inner.size as u64, granularity);
+                        for i in 0..inner.zone.count() {
+                            inner.zone[i].foreach_free(|off, len| map.set_free(off, len as u64));
+                        }
+                        Ok(map)
+                    })
+                }
+    
+                fn largest_free_block() -> usize {
+                    static_inner!(BUDDY_INNER, inner, {
+                        let mut res = 0;
+                        for i in 0..inner.zone.count() {
+                            res = res.max(inner.zone[i].largest_free());
+                        }
+                        res
+                    })
+                }
+
+                fn last_recovery() -> Option<RecoveryStatus> {
+                    unsafe { LAST_RECOVERY }
+                }
+
+                fn pin<T: ?Sized>(obj: &T) -> Result<()> {
+                    let off = Self::off(obj)?;
+                    Self::pins(|pins| pins.pin(off, mem::size_of_val(obj)))
+                }
+
+                fn unpin<T: ?Sized>(obj: &T) -> Result<()> {
+                    let off = Self::off(obj)?;
+                    if Self::pins(|pins| pins.unpin(off))? {
+                        Ok(())
+                    } else {
+                        Err($crate::Error::InvalidArgument(format!("@{} is not pinned", off)))
+                    }
+                }
+
+                fn is_pinned(off: u64, len: usize) -> bool {
+                    Self::pins(|pins| pins.is_pinned(off, len)).unwrap_or(false)
+                }
+
+                fn pinned_bytes() -> usize {
+                    Self::pins(|pins| pins.pinned_bytes()).unwrap_or(0)
+                }
+
                 fn print_info() {
                     println!("{:=^80}", " All Zones ");
                     println!("      Total: {} bytes", Self::size());
@@ -1715,6 +2521,22 @@ macro_rules! pool {
             /// Compact form of [`PMutex`](../../sync/struct.PMutex.html)
             /// `<T,`[`Allocator`](./struct.Allocator.html)`>`.
             pub type PMutex<T> = $crate::sync::PMutex<T, $name>;
+
+            /// Compact form of [`PRwLock`](../../sync/struct.PRwLock.html)
+            /// `<T,`[`Allocator`](./struct.Allocator.html)`>`.
+            pub type PRwLock<T> = $crate::sync::PRwLock<T, $name>;
+
+            /// Compact form of [`PSeqCell`](../../sync/struct.PSeqCell.html)
+            /// `<T,`[`Allocator`](./struct.Allocator.html)`>`.
+            pub type PSeqCell<T> = $crate::sync::PSeqCell<T, $name>;
+
+            /// Compact form of [`PQueue`](../../sync/struct.PQueue.html)
+            /// `<T,`[`Allocator`](./struct.Allocator.html)`>`.
+            pub type PQueue<T> = $crate::sync::PQueue<T, $name>;
+
+            /// Compact form of [`PArcSwap`](../../sync/struct.PArcSwap.html)
+            /// `<T,`[`Allocator`](./struct.Allocator.html)`>`.
+            pub type PArcSwap<T> = $crate::sync::PArcSwap<T, $name>;
     
             /// Compact form of [`PCell`](../../cell/struct.PCell.html)
             /// `<T,`[`Allocator`](./struct.Allocator.html)`>`.
@@ -1723,6 +2545,10 @@ macro_rules! pool {
             /// Compact form of [`LogNonNull`](../../ptr/struct.LogNonNull.html)
             /// `<T,`[`Allocator`](./struct.Allocator.html)`>`.
             pub type PNonNull<T> = $crate::ptr::LogNonNull<T, $name>;
+
+            /// Compact form of [`PIndex`](../../ptr/struct.PIndex.html)
+            /// `<T,`[`Allocator`](./struct.Allocator.html)`, I>`.
+            pub type PIndex<T, I = u64> = $crate::ptr::PIndex<T, $name, I>;
     
             /// Compact form of [`PRefCell`](../../cell/struct.PRefCell.html)
             /// `<T,`[`Allocator`](./struct.Allocator.html)`>`.
@@ -1735,6 +2561,14 @@ macro_rules! pool {
             /// Compact form of [`RefMut`](../../cell/struct.Mut.html)
             /// `<'b, T, `[`Allocator`](./struct.Allocator.html)`>`.
             pub type PRefMut<'b, T> = $crate::RefMut<'b, T, $name>;
+
+            /// Compact form of [`MappedRef`](../../cell/struct.MappedRef.html)
+            /// `<'b, T, `[`Allocator`](./struct.Allocator.html)`>`.
+            pub type PMappedRef<'b, T> = $crate::MappedRef<'b, T, $name>;
+
+            /// Compact form of [`MappedRefMut`](../../cell/struct.MappedRefMut.html)
+            /// `<'b, T, `[`Allocator`](./struct.Allocator.html)`>`.
+            pub type PMappedRefMut<'b, T> = $crate::MappedRefMut<'b, T, $name>;
     
             /// Compact form of [`VCell`](../../cell/struct.VCell.html)
             /// `<T,`[`Allocator`](./struct.Allocator.html)`>`.
@@ -1777,8 +2611,8 @@ macro_rules! pool {
             }
         }
     };
-    ($mod:ident) => {
-        $crate::pool!($mod, Allocator);
+    ($mod:ident $(, $key:ident = $val:expr)*) => {
+        $crate::pool!($mod, Allocator $(, $key = $val)*);
     };
 }
 
@@ -0,0 +1,159 @@
+use crate::alloc::*;
+use crate::cell::{RootCell, RootObj};
+use crate::result::Result;
+use crate::PSafe;
+use std::marker::PhantomData;
+
+/// One kilobyte (1024 bytes)
+pub const KB: u64 = 1 << 10;
+
+/// One megabyte (1024 KB)
+pub const MB: u64 = 1 << 20;
+
+/// One gigabyte (1024 MB)
+pub const GB: u64 = 1 << 30;
+
+/// One terabyte (1024 GB)
+pub const TB: u64 = 1 << 40;
+
+/// An entry point to typed pool configuration
+///
+/// See [`PoolBuilder`](./struct.PoolBuilder.html) for more details.
+pub struct Pool<P>(PhantomData<P>);
+
+impl<P> Pool<P> {
+    /// Returns a new [`PoolBuilder`](./struct.PoolBuilder.html) for pool type
+    /// `P` with the default configuration
+    pub const fn builder() -> PoolBuilder<P> {
+        PoolBuilder::new()
+    }
+}
+
+/// A typed alternative to the bitflag-based [`open`] functions
+///
+/// Unlike the [`open_flags`], the builder can express arbitrary pool sizes.
+/// The builder is a typed wrapper of [`OpenOptions`] for pool type `P`, and
+/// compiles down to the same open path: it prepares the pool file according
+/// to the options, and then opens it without any flag.
+///
+/// | Builder                        | Flags equivalent  |
+/// |--------------------------------|-------------------|
+/// | `create_if_missing(true)`      | `O_CFNE`          |
+/// | `create_new(true)`             | `O_CF`            |
+/// | `format(true)`                 | `O_F`             |
+/// | `capacity(8 * GB)`             | `O_8GB`           |
+///
+/// The builder methods are `const fn`, so a configuration can be defined and
+/// validated at compile time.
+///
+/// # Examples
+///
+/// ```
+/// use corundum::default::*;
+/// use corundum::{Pool, PoolBuilder, MB};
+///
+/// const CONFIG: PoolBuilder<Allocator> = Pool::<Allocator>::builder()
+///     .capacity(12 * MB)
+///     .create_if_missing(true)
+///     .format_on_corruption(false);
+///
+/// let root = CONFIG.open::<PCell<i32>>("builder.pool").unwrap();
+/// assert_eq!(root.get(), 0);
+/// ```
+///
+/// [`open`]: ./trait.MemPoolTraits.html#method.open
+/// [`open_flags`]: ./open_flags/index.html
+/// [`OpenOptions`]: ./struct.OpenOptions.html
+pub struct PoolBuilder<P> {
+    opts: OpenOptions,
+    phantom: PhantomData<P>,
+}
+
+impl<P> Clone for PoolBuilder<P> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<P> Copy for PoolBuilder<P> {}
+
+impl<P> PoolBuilder<P> {
+    /// Creates a new builder with the default configuration: it opens an
+    /// existing pool, and creates a pool of size [`DEFAULT_POOL_SIZE`] if
+    /// requested.
+    ///
+    /// [`DEFAULT_POOL_SIZE`]: ./constant.DEFAULT_POOL_SIZE.html
+    pub const fn new() -> Self {
+        Self::from_options(OpenOptions::new())
+    }
+
+    /// Creates a new builder with the given open options
+    pub const fn from_options(opts: OpenOptions) -> Self {
+        Self { opts, phantom: PhantomData }
+    }
+
+    /// Sets the capacity of the pool file in bytes; it requires either
+    /// [`create_if_missing()`](#method.create_if_missing) or
+    /// [`create_new()`](#method.create_new)
+    pub const fn capacity(mut self, capacity: u64) -> Self {
+        self.opts = self.opts.capacity(capacity);
+        self
+    }
+
+    /// Creates and formats the pool file if it does not exist
+    pub const fn create_if_missing(mut self, create: bool) -> Self {
+        self.opts = self.opts.create_if_missing(create);
+        self
+    }
+
+    /// Always creates and formats a new pool file, replacing the existing one
+    pub const fn create_new(mut self, create: bool) -> Self {
+        self.opts = self.opts.create(create);
+        self
+    }
+
+    /// Formats the existing pool file
+    pub const fn format(mut self, format: bool) -> Self {
+        self.opts = self.opts.format(format);
+        self
+    }
+
+    /// Formats the existing pool file if it does not contain a valid image of
+    /// this pool type
+    pub const fn format_on_corruption(mut self, format: bool) -> Self {
+        self.opts = self.opts.format_on_corruption(format);
+        self
+    }
+
+    /// Returns the configured capacity in bytes
+    ///
+    /// # Errors
+    ///
+    /// See [`OpenOptions::get_capacity()`](./struct.OpenOptions.html#method.get_capacity).
+    pub const fn get_capacity(&self) -> std::result::Result<u64, &'static str> {
+        self.opts.get_capacity()
+    }
+
+    /// Returns the open options of the builder
+    pub const fn options(&self) -> OpenOptions {
+        self.opts
+    }
+}
+
+impl<P: MemPool> PoolBuilder<P> {
+    /// Opens the pool and retrieves the root object
+    ///
+    /// See [`MemPoolTraits::open()`](./trait.MemPoolTraits.html#method.open)
+    /// for more details.
+    pub fn open<'a, U: 'a + PSafe + RootObj<P>>(&self, path: &str) -> Result<RootCell<'a, U, P>> {
+        P::open_with_options::<U>(path, self.opts)
+    }
+
+    /// Opens the pool without any root object
+    ///
+    /// See [`MemPoolTraits::open_no_root()`](./trait.MemPoolTraits.html#method.open_no_root)
+    /// for more details.
+    pub fn open_no_root(&self, path: &str) -> Result<PoolGuard<P>> {
+        P::open_no_root_with_options(path, self.opts)
+    }
+}
@@ -0,0 +1,173 @@
+/// The maximum number of log slots in a journal page
+pub const MAX_PAGE_LOG_SLOTS: usize = crate::PAGE_LOG_SLOTS;
+
+/// The maximum number of journal pages in a per-thread arena
+pub const MAX_JOURNAL_ARENA_PAGES: usize = 64;
+
+/// Compile-time parameters of a pool type
+///
+/// A configuration is given to the [`pool!()`] macro as a list of
+/// `key = value` options, each of which calls the builder method with the
+/// same name. The configuration of a pool type is available through
+/// [`MemPoolTraits::CONFIG`].
+///
+/// | Option           | Default                                            |
+/// |------------------|----------------------------------------------------|
+/// | `zones`          | `CPUS` environment variable, or the number of cpus |
+/// | `min_size`       | 0 (no minimum)                                     |
+/// | `max_size`       | 0 (no maximum)                                     |
+/// | `page_log_slots` | [`MAX_PAGE_LOG_SLOTS`]                             |
+/// | `journal_arena`  | 0 (journal pages come from the allocator)          |
+///
+/// # Examples
+///
+/// ```
+/// # fn main() {
+/// use corundum::MB;
+///
+/// corundum::pool!(tuned, zones = 2, min_size = 16 * MB, page_log_slots = 32);
+/// use tuned::*;
+///
+/// type P = tuned::Allocator;
+///
+/// assert_eq!(P::CONFIG.get_zones(), 2);
+///
+/// // The default size is raised to the minimum size
+/// let _pool = P::open_no_root("tuned.pool", O_CF).unwrap();
+/// assert_eq!(std::fs::metadata("tuned.pool").unwrap().len(), 16 * MB);
+/// # }
+/// ```
+///
+/// [`pool!()`]: ../macro.pool.html
+/// [`MemPoolTraits::CONFIG`]: ./trait.MemPoolTraits.html#associatedconstant.CONFIG
+/// [`MAX_PAGE_LOG_SLOTS`]: ./constant.MAX_PAGE_LOG_SLOTS.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolConfig {
+    zones: usize,
+    min_size: u64,
+    max_size: u64,
+    page_log_slots: usize,
+    journal_arena: usize,
+}
+
+impl PoolConfig {
+    /// Creates the default configuration
+    pub const fn new() -> Self {
+        Self {
+            zones: 0,
+            min_size: 0,
+            max_size: 0,
+            page_log_slots: MAX_PAGE_LOG_SLOTS,
+            journal_arena: 0,
+        }
+    }
+
+    /// Sets the number of allocation zones of a new pool. Zero means the
+    /// value of the `CPUS` environment variable, or the number of cpus.
+    pub const fn zones(mut self, zones: usize) -> Self {
+        self.zones = zones;
+        self
+    }
+
+    /// Sets the minimum size of a new pool file in bytes. A smaller size
+    /// which is requested by the open flags is raised to this size.
+    pub const fn min_size(mut self, size: u64) -> Self {
+        assert!(self.max_size == 0 || size <= self.max_size, "min_size is larger than max_size");
+        self.min_size = size;
+        self
+    }
+
+    /// Sets the maximum size of the pool file in bytes. Zero means no
+    /// maximum.
+    ///
+    /// A pool file larger than this size is neither created nor opened. With
+    /// a maximum of at most 4 GiB, a 32-bit [`PIndex`] addresses the whole
+    /// pool.
+    ///
+    /// # Panics
+    ///
+    /// It panics (at compile time, if used in a constant) if `size` is
+    /// smaller than the minimum size.
+    ///
+    /// [`PIndex`]: ../ptr/struct.PIndex.html
+    pub const fn max_size(mut self, size: u64) -> Self {
+        assert!(size == 0 || size >= self.min_size, "max_size is smaller than min_size");
+        self.max_size = size;
+        self
+    }
+
+    /// Sets the number of log slots in a journal page
+    ///
+    /// # Panics
+    ///
+    /// It panics (at compile time, if used in a constant) if `slots` is zero
+    /// or larger than [`MAX_PAGE_LOG_SLOTS`](./constant.MAX_PAGE_LOG_SLOTS.html).
+    pub const fn page_log_slots(mut self, slots: usize) -> Self {
+        assert!(slots > 0 && slots <= MAX_PAGE_LOG_SLOTS, "page_log_slots is out of range");
+        self.page_log_slots = slots;
+        self
+    }
+
+    /// Sets the number of journal pages which are reserved for every thread
+    /// at its first transaction. Zero disables the arenas.
+    ///
+    /// The journal of a thread takes its pages from the arena of the thread
+    /// without going through the allocator, so that writing logs does not
+    /// contend with the allocations of the other threads. The arena of a
+    /// thread is reused by another thread when it exits. See
+    /// [`stm::arena`](../stm/arena/index.html) for more details.
+    ///
+    /// # Panics
+    ///
+    /// It panics (at compile time, if used in a constant) if `pages` is
+    /// larger than [`MAX_JOURNAL_ARENA_PAGES`](./constant.MAX_JOURNAL_ARENA_PAGES.html).
+    pub const fn journal_arena(mut self, pages: usize) -> Self {
+        assert!(pages <= MAX_JOURNAL_ARENA_PAGES, "journal_arena is out of range");
+        self.journal_arena = pages;
+        self
+    }
+
+    /// Returns the configured number of zones; zero means the default
+    pub const fn get_zones(&self) -> usize {
+        self.zones
+    }
+
+    /// Returns the minimum size of a new pool file in bytes
+    pub const fn get_min_size(&self) -> u64 {
+        self.min_size
+    }
+
+    /// Returns the maximum size of the pool file in bytes; zero means no
+    /// maximum
+    pub const fn get_max_size(&self) -> u64 {
+        self.max_size
+    }
+
+    /// Returns the number of log slots in a journal page
+    pub const fn get_page_log_slots(&self) -> usize {
+        self.page_log_slots
+    }
+
+    /// Returns the number of journal pages in a per-thread arena
+    pub const fn get_journal_arena(&self) -> usize {
+        self.journal_arena
+    }
+}
+
+impl PoolConfig {
+    /// Checks if a pool file with the size of `size` bytes is allowed
+    pub fn check_size(&self, size: u64) -> crate::Result<()> {
+        if self.max_size != 0 && size > self.max_size {
+            Err(crate::Error::InvalidArgument(format!(
+                "pool size ({} bytes) exceeds max_size ({} bytes)", size, self.max_size)))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
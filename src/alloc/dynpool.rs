@@ -0,0 +1,189 @@
+use std::any::Any;
+use crate::alloc::MemPool;
+use crate::result::Result;
+use crate::AssertTxInSafe;
+
+/// An object-safe facade over [`MemPool`] types
+///
+/// Pools in Corundum are types rather than objects, which makes every utility
+/// generic over the pool type. `MemPoolDyn` exposes the key pool operations
+/// through `&self` methods so that plugin systems, CLIs, and other tools can
+/// operate over whatever pool the application configured using a
+/// `&dyn MemPoolDyn` without being monomorphized for it.
+///
+/// Every [`MemPool`] implements `MemPoolDyn`. A trait object may be obtained
+/// using [`dyn_pool`].
+///
+/// # Examples
+///
+/// ```
+/// use corundum::default::*;
+/// use corundum::{MemPoolDyn, dyn_pool};
+///
+/// fn report(pool: &dyn MemPoolDyn) -> usize {
+///     let mut count = 0;
+///     pool.transaction(&mut || count += 1).unwrap();
+///     count
+/// }
+///
+/// let pool = dyn_pool::<Allocator>();
+/// let _guard = pool.open_no_root("foo.pool", O_CF).unwrap();
+///
+/// assert!(pool.is_open());
+/// assert_eq!(report(pool), 1);
+/// ```
+///
+/// [`MemPool`]: ./trait.MemPool.html
+/// [`dyn_pool`]: ./fn.dyn_pool.html
+pub trait MemPoolDyn: Any {
+    /// Returns the name of the pool type
+    fn name(&self) -> &'static str;
+
+    /// Opens the pool without a root object. The pool remains open until the
+    /// returned [`DynPoolGuard`](./struct.DynPoolGuard.html) is dropped.
+    ///
+    /// See [`MemPoolTraits::open_no_root`] for the list of flags.
+    ///
+    /// [`MemPoolTraits::open_no_root`]: ./trait.MemPoolTraits.html#method.open_no_root
+    fn open_no_root(&self, path: &str, flags: u32) -> Result<DynPoolGuard>;
+
+    /// Commits all changes and closes the pool
+    ///
+    /// # Safety
+    ///
+    /// No persistent object of this pool should be alive after closing it.
+    unsafe fn close(&self) -> Result<()>;
+
+    /// Returns true if the pool is open
+    fn is_open(&self) -> bool;
+
+    /// Total size of the memory pool
+    fn size(&self) -> usize;
+
+    /// Available space in the pool
+    fn available(&self) -> usize;
+
+    /// Total occupied space
+    fn used(&self) -> usize;
+
+    /// Indicates if there the pool is in a good shape
+    fn verify(&self) -> bool;
+
+    /// Prints memory information
+    fn print_info(&self);
+
+    /// Executes `body` in a failure-atomic transaction of this pool
+    ///
+    /// The journal object is not passed to the callback because its type
+    /// depends on the pool type. Allocations should be done in the monomorphic
+    /// parts of the application; the callback is useful for driving the
+    /// transaction boundaries (e.g. running a plugin's update procedure
+    /// atomically).
+    fn transaction(&self, body: &mut dyn FnMut()) -> Result<()>;
+
+    /// Returns `self` as `&dyn Any` for downcasting to the concrete pool type
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<P: MemPool> MemPoolDyn for P {
+    #[inline]
+    fn name(&self) -> &'static str {
+        P::name()
+    }
+
+    #[inline]
+    fn open_no_root(&self, path: &str, flags: u32) -> Result<DynPoolGuard> {
+        Ok(DynPoolGuard(Box::new(P::open_no_root(path, flags)?)))
+    }
+
+    #[inline]
+    unsafe fn close(&self) -> Result<()> {
+        P::close()
+    }
+
+    #[inline]
+    fn is_open(&self) -> bool {
+        P::is_open()
+    }
+
+    #[inline]
+    fn size(&self) -> usize {
+        P::size()
+    }
+
+    #[inline]
+    fn available(&self) -> usize {
+        P::available()
+    }
+
+    #[inline]
+    fn used(&self) -> usize {
+        P::used()
+    }
+
+    #[inline]
+    fn verify(&self) -> bool {
+        P::verify()
+    }
+
+    #[inline]
+    fn print_info(&self) {
+        P::print_info()
+    }
+
+    fn transaction(&self, body: &mut dyn FnMut()) -> Result<()> {
+        let mut body = body;
+        let body = AssertTxInSafe(&mut body);
+        P::transaction(move |_| {
+            let body = body;
+            (**body.0)()
+        })
+    }
+
+    #[inline]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A type-erased [`PoolGuard`](./struct.PoolGuard.html)
+///
+/// The pool is closed when the guard is dropped.
+pub struct DynPoolGuard(Box<dyn Any>);
+
+impl DynPoolGuard {
+    /// Returns true if the guard belongs to pool type `P`
+    pub fn is<P: MemPool>(&self) -> bool {
+        self.0.is::<crate::alloc::PoolGuard<P>>()
+    }
+}
+
+/// Returns a `'static` trait object for pool type `P`
+///
+/// Pool types do not carry any data, so the returned reference is shared
+/// between all callers.
+pub fn dyn_pool<P: MemPool>() -> &'static dyn MemPoolDyn {
+    assert_eq!(std::mem::size_of::<P>(), 0, "pool types should not carry data");
+    // A zero-sized value needs no storage; a dangling aligned pointer is a
+    // valid reference to it.
+    unsafe { &*std::ptr::NonNull::<P>::dangling().as_ptr() }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alloc::heap::Heap;
+    use crate::alloc::*;
+
+    #[test]
+    fn dyn_heap_transaction() {
+        let pools: Vec<&dyn MemPoolDyn> = vec![dyn_pool::<Heap>(), &Heap {}];
+        let mut count = 0;
+        for p in &pools {
+            assert!(p.is_open());
+            assert!(p.as_any().is::<Heap>());
+            p.transaction(&mut || count += 1).unwrap();
+        }
+        assert_eq!(count, 2);
+        assert!(pools[0].transaction(&mut || panic!("intentional")).is_err());
+    }
+}
@@ -0,0 +1,215 @@
+use crate::alloc::Durability;
+use std::fmt;
+use std::time::Duration;
+
+/// Status of the last recovery procedure of a pool
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecoveryStatus {
+    /// Indicates that the pool crashed while the allocator was operating
+    pub allocator_crashed: bool,
+
+    /// Number of journals which were recovered
+    pub journals: usize,
+
+    /// Time spent on recovery
+    pub duration: Duration,
+}
+
+/// Health thresholds (watermarks) used by
+/// [`HealthReport::is_healthy_with()`](./struct.HealthReport.html#method.is_healthy_with)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HealthThresholds {
+    /// Maximum ratio of used space to the pool size (default: 0.9)
+    pub max_usage: f64,
+
+    /// Maximum fragmentation ratio (default: 0.9)
+    pub max_fragmentation: f64,
+
+    /// Minimum size of the largest free block in bytes (default: 0)
+    pub min_free_block: usize,
+
+    /// Maximum number of running transactions (default: unlimited)
+    pub max_journals: usize,
+}
+
+impl Default for HealthThresholds {
+    fn default() -> Self {
+        Self {
+            max_usage: 0.9,
+            max_fragmentation: 0.9,
+            min_free_block: 0,
+            max_journals: usize::MAX,
+        }
+    }
+}
+
+/// A snapshot of the health of a pool
+///
+/// It bundles the introspection APIs of a pool into a single report which can
+/// be wired into readiness/liveness probes of services. It is obtained by
+/// [`MemPoolTraits::health()`].
+///
+/// # Examples
+///
+/// ```
+/// use corundum::default::*;
+///
+/// let _pool = Allocator::open_no_root("foo.pool", O_CF).unwrap();
+/// let report = Allocator::health();
+///
+/// assert!(report.is_healthy());
+/// assert!(report.used <= report.size);
+/// println!("{}", report);
+/// ```
+///
+/// [`MemPoolTraits::health()`]: ./trait.MemPoolTraits.html#method.health
+#[derive(Clone, Debug)]
+pub struct HealthReport {
+    /// Name of the pool type
+    pub pool: &'static str,
+
+    /// Indicates if the pool is open
+    pub open: bool,
+
+    /// Indicates if the allocator metadata is consistent
+    pub verified: bool,
+
+    /// Total size of the pool in bytes
+    pub size: usize,
+
+    /// Number of used bytes
+    pub used: usize,
+
+    /// Number of available bytes
+    pub available: usize,
+
+    /// Size of the largest contiguous free block in bytes
+    pub largest_free_block: usize,
+
+    /// Number of running transactions
+    pub journals: usize,
+
+    /// Status of the last recovery, if the pool has been recovered since it
+    /// was opened
+    pub last_recovery: Option<RecoveryStatus>,
+
+    /// Number of pinned bytes which cannot be moved by reallocation
+    pub pinned: usize,
+
+    /// Durability of the mapping, i.e. DAX with `MAP_SYNC` or page cache
+    pub durability: Option<Durability>,
+
+    /// Number of bad 512-byte sectors which the kernel reports for the
+    /// device of the pool file, or `None` if it is not known (e.g., the file
+    /// is not on a pmem namespace)
+    pub badblocks: Option<u64>,
+
+    /// Number of bad ranges which are recorded by the media error handler in
+    /// this session (see [`install_media_error_handler()`])
+    ///
+    /// [`install_media_error_handler()`]: ./fn.install_media_error_handler.html
+    pub media_errors: usize,
+
+    /// Number of failed consistency checks of the allocator metadata in this
+    /// session, including the one of this report
+    pub verify_failures: usize,
+}
+
+impl HealthReport {
+    /// Returns the ratio of used space to the pool size
+    pub fn usage(&self) -> f64 {
+        if self.size == 0 {
+            0.0
+        } else {
+            self.used as f64 / self.size as f64
+        }
+    }
+
+    /// Returns the fragmentation ratio, i.e. the portion of the available
+    /// space which is not in the largest free block
+    pub fn fragmentation(&self) -> f64 {
+        if self.available == 0 {
+            0.0
+        } else {
+            1.0 - self.largest_free_block as f64 / self.available as f64
+        }
+    }
+
+    /// Indicates if the pool is healthy according to the default
+    /// [`HealthThresholds`](./struct.HealthThresholds.html)
+    pub fn is_healthy(&self) -> bool {
+        self.is_healthy_with(&HealthThresholds::default())
+    }
+
+    /// Indicates if the pool is open, consistent, and within the given
+    /// thresholds
+    pub fn is_healthy_with(&self, t: &HealthThresholds) -> bool {
+        self.open
+            && self.verified
+            && self.usage() <= t.max_usage
+            && self.fragmentation() <= t.max_fragmentation
+            && self.largest_free_block >= t.min_free_block
+            && self.journals <= t.max_journals
+            && self.badblocks.unwrap_or(0) == 0
+            && self.media_errors == 0
+            && self.verify_failures == 0
+    }
+}
+
+/// Returns the number of bad sectors of the block device which contains
+/// `path`, as reported by the kernel in `/sys/dev/block/<dev>/badblocks`
+///
+/// Each line of the file is a `<first sector> <count>` pair. For a partition,
+/// the list of the whole device is used.
+#[cfg(target_os = "linux")]
+pub(crate) fn badblocks(path: &str) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    let dev = std::fs::metadata(path).ok()?.dev();
+    let (major, minor) = unsafe { (libc::major(dev), libc::minor(dev)) };
+    let dir = std::path::PathBuf::from(format!("/sys/dev/block/{}:{}", major, minor));
+    let list = std::fs::read_to_string(dir.join("badblocks"))
+        .or_else(|_| std::fs::read_to_string(dir.join("../badblocks")))
+        .ok()?;
+    Some(list.lines()
+        .filter_map(|l| l.split_whitespace().nth(1)?.parse::<u64>().ok())
+        .sum())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn badblocks(_path: &str) -> Option<u64> {
+    None
+}
+
+impl fmt::Display for HealthReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:=^60}", format!(" Health of {} ", self.pool))?;
+        writeln!(f, "            Healthy: {}", self.is_healthy())?;
+        writeln!(f, "               Open: {}", self.open)?;
+        writeln!(f, "           Verified: {}", self.verified)?;
+        writeln!(f, "              Usage: {} of {} bytes ({:.1}%)",
+            self.used, self.size, self.usage() * 100.0)?;
+        writeln!(f, " Largest Free Block: {} bytes", self.largest_free_block)?;
+        writeln!(f, "      Fragmentation: {:.1}%", self.fragmentation() * 100.0)?;
+        writeln!(f, "           Journals: {}", self.journals)?;
+        writeln!(f, "             Pinned: {} bytes", self.pinned)?;
+        writeln!(f, "            Mapping: {}", match self.durability {
+            Some(Durability::Sync) => "DAX (MAP_SYNC)",
+            Some(Durability::PageCache) => "page cache",
+            Some(Durability::Msync) => "page cache (msync)",
+            None => "unknown",
+        })?;
+        match self.badblocks {
+            Some(n) => writeln!(f, "         Bad Blocks: {} sector(s)", n)?,
+            None => writeln!(f, "         Bad Blocks: unknown")?,
+        }
+        writeln!(f, "       Media Errors: {}", self.media_errors)?;
+        writeln!(f, "    Verify Failures: {}", self.verify_failures)?;
+        match &self.last_recovery {
+            Some(r) => write!(f, "      Last Recovery: {} journal(s) in {:?}{}",
+                r.journals, r.duration,
+                if r.allocator_crashed { ", allocator crashed" } else { "" }),
+            None => write!(f, "      Last Recovery: none"),
+        }
+    }
+}
@@ -44,6 +44,12 @@ unsafe impl MemPoolTraits for Heap {
         (x, off, size, 0)
     }
 
+    unsafe fn pre_alloc_aligned(size: usize, align: usize) -> (*mut u8, u64, usize, usize) {
+        Self::discard(0);
+        let x = alloc(Layout::from_size_align_unchecked(size, align));
+        (x, x as u64, size, 0)
+    }
+
     unsafe fn pre_dealloc(ptr: *mut u8, size: usize) -> usize {
         Self::discard(0);
         let _start = ptr as u64;
@@ -102,7 +108,7 @@ unsafe impl MemPoolTraits for Heap {
     ) -> Result<RootCell<'a, U, Self>> {
         let slf = Self::open_no_root(path, flags)?;
         if std::mem::size_of::<U>() == 0 {
-            Err("root type cannot be a ZST".to_string())
+            Err(crate::Error::ZeroSizedRoot)
         } else {
             unsafe {
                 let root_off = Self::transaction(move |j| {
@@ -173,6 +179,22 @@ pub type Parc<T> = crate::sync::Parc<T, Heap>;
 /// `<T,`[`Heap`](./struct.Heap.html)`>`.
 pub type PMutex<T> = crate::sync::PMutex<T, Heap>;
 
+/// Compact form of [`PRwLock`](../../sync/struct.PRwLock.html)
+/// `<T,`[`Heap`](./struct.Heap.html)`>`.
+pub type PRwLock<T> = crate::sync::PRwLock<T, Heap>;
+
+/// Compact form of [`PSeqCell`](../../sync/struct.PSeqCell.html)
+/// `<T,`[`Heap`](./struct.Heap.html)`>`.
+pub type PSeqCell<T> = crate::sync::PSeqCell<T, Heap>;
+
+/// Compact form of [`PQueue`](../../sync/struct.PQueue.html)
+/// `<T,`[`Heap`](./struct.Heap.html)`>`.
+pub type PQueue<T> = crate::sync::PQueue<T, Heap>;
+
+/// Compact form of [`PArcSwap`](../../sync/struct.PArcSwap.html)
+/// `<T,`[`Heap`](./struct.Heap.html)`>`.
+pub type PArcSwap<T> = crate::sync::PArcSwap<T, Heap>;
+
 /// Compact form of [`PCell`](../../cell/struct.PCell.html)
 /// `<T,`[`Heap`](./struct.Heap.html)`>`.
 pub type PCell<T> = crate::cell::PCell<T, Heap>;
@@ -181,6 +203,10 @@ pub type PCell<T> = crate::cell::PCell<T, Heap>;
 /// `<T,`[`Heap`](./struct.Heap.html)`>`.
 pub type PNonNull<T> = crate::ptr::LogNonNull<T, Heap>;
 
+/// Compact form of [`PIndex`](../../ptr/struct.PIndex.html)
+/// `<T,`[`Heap`](./struct.Heap.html)`, I>`.
+pub type PIndex<T, I = u64> = crate::ptr::PIndex<T, Heap, I>;
+
 /// Compact form of [`PRefCell`](../../cell/struct.PRefCell.html)
 /// `<T,`[`Heap`](./struct.Heap.html)`>`.
 pub type PRefCell<T> = crate::cell::PRefCell<T, Heap>;
@@ -193,6 +219,14 @@ pub type PRef<'b, T> = crate::cell::Ref<'b, T, Heap>;
 /// `<'b, T, `[`Heap`](./struct.Heap.html)`>`.
 pub type PRefMut<'b, T> = crate::cell::RefMut<'b, T, Heap>;
 
+/// Compact form of [`MappedRef`](../../cell/struct.MappedRef.html)
+/// `<'b, T, `[`Heap`](./struct.Heap.html)`>`.
+pub type PMappedRef<'b, T> = crate::cell::MappedRef<'b, T, Heap>;
+
+/// Compact form of [`MappedRefMut`](../../cell/struct.MappedRefMut.html)
+/// `<'b, T, `[`Heap`](./struct.Heap.html)`>`.
+pub type PMappedRefMut<'b, T> = crate::cell::MappedRefMut<'b, T, Heap>;
+
 /// Compact form of `[VCell](../../cell/struct.VCell.html)
 /// `<T,`[`Heap`](./struct.Heap.html)`>`.
 pub type VCell<T> = crate::cell::VCell<T, Heap>;
@@ -0,0 +1,164 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A placement hint for new allocations
+///
+/// It is passed to [`MemPoolTraits::new_with_hint()`]. The hint is honored
+/// if the pool knows its [`InterleaveTopology`]; otherwise, it is ignored and
+/// the allocation falls back to the zone of the current cpu.
+///
+/// [`MemPoolTraits::new_with_hint()`]: ./trait.MemPoolTraits.html#method.new_with_hint
+/// [`InterleaveTopology`]: ./struct.InterleaveTopology.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AllocHint {
+    /// Allocates from the zone of the current cpu. It is suitable for
+    /// latency-bound data, such as metadata.
+    Local,
+
+    /// Places the allocation on the interleave set `stripe % ways`, so that
+    /// consecutive stripes of a bandwidth-bound structure are spread across
+    /// DIMMs
+    Interleave(usize),
+}
+
+impl Default for AllocHint {
+    fn default() -> Self {
+        AllocHint::Local
+    }
+}
+
+/// The interleave topology of the NVDIMM region in which a pool resides
+///
+/// An interleaved region stripes its address space over `ways` DIMMs, such
+/// that every `granularity` bytes go to the next DIMM. It is assumed that the
+/// pool file is laid out contiguously on the region, which holds for DAX
+/// files whose extents are aligned to `ways * granularity` bytes.
+///
+/// The topology is detected from the ndctl-style sysfs data of the region
+/// when a pool is opened, and it can be overridden by
+/// [`MemPoolTraits::set_interleave_topology()`].
+///
+/// [`MemPoolTraits::set_interleave_topology()`]: ./trait.MemPoolTraits.html#method.set_interleave_topology
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InterleaveTopology {
+    ways: usize,
+    granularity: usize,
+}
+
+/// The default interleave granularity of the NVDIMM regions which do not
+/// report it
+pub const DEFAULT_INTERLEAVE_GRANULARITY: usize = 4096;
+
+impl InterleaveTopology {
+    /// Creates a new topology with `ways` DIMMs and the given `granularity`
+    /// (a power of two) in bytes
+    pub fn new(ways: usize, granularity: usize) -> Self {
+        assert!(ways > 0, "an interleave set needs at least one DIMM");
+        assert!(granularity.is_power_of_two(), "granularity should be a power of two");
+        Self { ways, granularity }
+    }
+
+    /// Returns the number of interleaved DIMMs
+    pub fn ways(&self) -> usize {
+        self.ways
+    }
+
+    /// Returns the number of contiguous bytes on each DIMM
+    pub fn granularity(&self) -> usize {
+        self.granularity
+    }
+
+    /// Returns the number of bytes after which the DIMM pattern repeats
+    pub fn width(&self) -> usize {
+        self.ways * self.granularity
+    }
+
+    /// Returns the DIMM on which the byte at pool offset `off` resides
+    pub fn set_of(&self, off: u64) -> usize {
+        (off / self.granularity as u64) as usize % self.ways
+    }
+
+    /// Reads the topology of an NVDIMM region from its sysfs directory
+    /// (e.g., `/sys/bus/nd/devices/region0`)
+    ///
+    /// It reads the number of DIMMs from the `mappings` attribute, and the
+    /// granularity from `interleave_granularity`, if exists. Each
+    /// `mapping<N>` attribute (`<nmem>,<offset>,<length>,<position>`) is
+    /// required to exist. It returns `None` if the region is not interleaved.
+    pub fn from_region<P: AsRef<Path>>(dir: P) -> Option<Self> {
+        let dir = dir.as_ref();
+        let read = |name: &str| fs::read_to_string(dir.join(name)).ok();
+
+        let ways: usize = read("mappings")?.trim().parse().ok()?;
+        if ways < 2 {
+            return None;
+        }
+        for i in 0..ways {
+            let mapping = read(&format!("mapping{}", i))?;
+            if mapping.trim().split(',').count() < 3 {
+                return None;
+            }
+        }
+        let granularity = read("interleave_granularity")
+            .and_then(|g| g.trim().parse().ok())
+            .filter(|g: &usize| g.is_power_of_two())
+            .unwrap_or(DEFAULT_INTERLEAVE_GRANULARITY);
+
+        Some(Self::new(ways, granularity))
+    }
+
+    /// Detects the topology of the NVDIMM region which hosts the file at
+    /// `path`
+    ///
+    /// It finds the block device of the file in `/sys/dev/block`, and looks
+    /// for the region among its ancestors in the device hierarchy. It returns
+    /// `None` if the file is not on an interleaved NVDIMM region.
+    #[cfg(unix)]
+    pub fn detect<P: AsRef<Path>>(path: P) -> Option<Self> {
+        use std::os::unix::fs::MetadataExt;
+
+        let dev = fs::metadata(path).ok()?.dev();
+        let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+        let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+        let dev = PathBuf::from(format!("/sys/dev/block/{}:{}", major, minor));
+
+        fs::canonicalize(dev).ok()?.ancestors()
+            .find(|d| d.file_name()
+                .and_then(|n| n.to_str())
+                .map_or(false, |n| n.starts_with("region")))
+            .and_then(Self::from_region)
+    }
+
+    /// Detects the topology of the NVDIMM region which hosts the file at
+    /// `path`. It is not supported on this platform.
+    #[cfg(not(unix))]
+    pub fn detect<P: AsRef<Path>>(_path: P) -> Option<Self> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn interleave_from_sysfs() {
+        let dir = std::env::temp_dir().join(format!("corundum-region-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("mappings"), "2\n").unwrap();
+        fs::write(dir.join("mapping0"), "nmem0,0,1073741824,0\n").unwrap();
+        fs::write(dir.join("mapping1"), "nmem1,0,1073741824,1\n").unwrap();
+
+        let t = InterleaveTopology::from_region(&dir).unwrap();
+        assert_eq!(t, InterleaveTopology::new(2, DEFAULT_INTERLEAVE_GRANULARITY));
+        assert_eq!(t.set_of(4096), 1);
+        assert_eq!(t.set_of(8192), 0);
+
+        fs::write(dir.join("interleave_granularity"), "256\n").unwrap();
+        assert_eq!(InterleaveTopology::from_region(&dir).unwrap().granularity(), 256);
+
+        fs::remove_file(dir.join("mapping1")).unwrap();
+        assert!(InterleaveTopology::from_region(&dir).is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,245 @@
+use crate::cell::LazyCell;
+use crate::result::Result;
+use crate::Error;
+use std::collections::HashMap;
+use std::fs::File;
+use std::sync::Mutex;
+
+/// How a pool file is mapped into memory
+///
+/// Stores to a pool reach the media through cache-line flushes only if the
+/// file is on a DAX file system and it is mapped with `MAP_SYNC`, which
+/// makes the file system keep its metadata durable on page faults. A shared
+/// mapping of a file on a regular file system, or of a DAX file without
+/// `MAP_SYNC`, goes through the page cache (or depends on the file system
+/// metadata), and the cache-line flushes do not make the data durable.
+///
+/// The mode of a pool type is set by [`MemPoolTraits::set_map_mode()`]
+/// before the pool is opened, and the [`Durability`] which is obtained is
+/// reported by [`MemPoolTraits::durability()`].
+///
+/// [`MemPoolTraits::set_map_mode()`]: ./trait.MemPoolTraits.html#method.set_map_mode
+/// [`MemPoolTraits::durability()`]: ./trait.MemPoolTraits.html#method.durability
+/// [`Durability`]: ./enum.Durability.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MapMode {
+    /// Tries `MAP_SHARED_VALIDATE | MAP_SYNC`, and falls back to a plain
+    /// shared mapping if the file system does not support it. Check
+    /// [`MemPoolTraits::durability()`] to see which one was used.
+    ///
+    /// [`MemPoolTraits::durability()`]: ./trait.MemPoolTraits.html#method.durability
+    Auto,
+
+    /// Requires `MAP_SHARED_VALIDATE | MAP_SYNC`. Opening the pool fails if
+    /// the file is not on a DAX file system.
+    Sync,
+
+    /// Uses a plain shared mapping, without attempting `MAP_SYNC`
+    ///
+    /// This mode is unsafe in the sense of durability: a committed
+    /// transaction is crash-consistent only with respect to process crashes,
+    /// unless the pool is on a DAX file system which does not need `MAP_SYNC`.
+    /// It is meant for testing on machines without persistent memory.
+    UnsafeNonDax,
+
+    /// Uses a plain shared mapping, and replaces cache-line flushes with
+    /// ranged `msync(MS_SYNC)` calls at every fence
+    ///
+    /// It keeps the crash-consistency contract of persistent memory on
+    /// regular file systems (e.g., in CI or on laptops), at the cost of a
+    /// system call per ordering point. It is supported only on Unix; opening
+    /// a pool in this mode fails elsewhere. See [`ll::msync_mode()`] for more
+    /// details.
+    ///
+    /// [`ll::msync_mode()`]: ../ll/fn.msync_mode.html
+    Msync,
+}
+
+impl Default for MapMode {
+    fn default() -> Self {
+        MapMode::Auto
+    }
+}
+
+/// The durability guarantee of an open pool
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Durability {
+    /// The pool is mapped with `MAP_SYNC`; flushed stores are durable
+    Sync,
+
+    /// The pool is mapped without `MAP_SYNC`; flushed stores may stay in
+    /// the page cache until the file is synchronized
+    PageCache,
+
+    /// The pool is mapped without `MAP_SYNC`, and flushed stores are
+    /// synchronized with `msync` at every fence
+    Msync,
+}
+
+static mut MAP_MODES: LazyCell<Mutex<HashMap<&'static str, MapMode>>> =
+    LazyCell::new(|| Mutex::new(HashMap::new()));
+
+fn map_modes() -> std::sync::MutexGuard<'static, HashMap<&'static str, MapMode>> {
+    match unsafe { MAP_MODES.lock() } {
+        Ok(g) => g,
+        Err(p) => p.into_inner()
+    }
+}
+
+/// Returns the mapping mode of pool `pool`
+#[doc(hidden)]
+pub fn pool_map_mode(pool: &'static str) -> MapMode {
+    map_modes().get(pool).copied().unwrap_or_default()
+}
+
+/// Sets the mapping mode of pool `pool`
+#[doc(hidden)]
+pub fn set_pool_map_mode(pool: &'static str, mode: MapMode) {
+    map_modes().insert(pool, mode);
+}
+
+/// A shared writable mapping of a whole pool file
+#[doc(hidden)]
+pub struct PoolMapping {
+    ptr: *mut u8,
+    len: usize,
+    durability: Durability,
+    #[cfg(not(unix))]
+    _inner: memmap::MmapMut,
+}
+
+unsafe impl Send for PoolMapping {}
+unsafe impl Sync for PoolMapping {}
+
+#[cfg(target_os = "linux")]
+const MAP_SHARED_VALIDATE: i32 = 0x03;
+
+#[cfg(target_os = "linux")]
+const MAP_SYNC: i32 = 0x80000;
+
+impl PoolMapping {
+    /// Maps `file` according to `mode`
+    #[cfg(unix)]
+    pub fn new(file: &File, mode: MapMode) -> Result<Self> {
+        use std::os::unix::io::AsRawFd;
+
+        let len = file.metadata()?.len() as usize;
+        let map = |flags: i32| unsafe {
+            let ptr = libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                flags,
+                file.as_raw_fd(),
+                0,
+            );
+            if ptr == libc::MAP_FAILED {
+                Err(std::io::Error::last_os_error())
+            } else {
+                Ok(ptr as *mut u8)
+            }
+        };
+
+        #[cfg(target_os = "linux")]
+        let synced = if mode == MapMode::UnsafeNonDax || mode == MapMode::Msync {
+            None
+        } else {
+            match map(MAP_SHARED_VALIDATE | MAP_SYNC) {
+                Ok(ptr) => Some(ptr),
+                Err(e) if mode == MapMode::Sync => {
+                    return Err(Error::Io(e.kind(),
+                        format!("the pool file does not support MAP_SYNC ({}); \
+                            it should be on a DAX file system", e)));
+                }
+                Err(_) => None,
+            }
+        };
+
+        #[cfg(not(target_os = "linux"))]
+        let synced = if mode == MapMode::Sync {
+            return Err(Error::Io(std::io::ErrorKind::Other,
+                "MAP_SYNC is not supported on this platform".to_string()));
+        } else {
+            None
+        };
+
+        let durability = if mode == MapMode::Msync { Durability::Msync } else { Durability::PageCache };
+        Ok(match synced {
+            Some(ptr) => Self { ptr, len, durability: Durability::Sync },
+            None => Self { ptr: map(libc::MAP_SHARED)?, len, durability },
+        })
+    }
+
+    /// Maps `file` according to `mode`
+    #[cfg(not(unix))]
+    pub fn new(file: &File, mode: MapMode) -> Result<Self> {
+        if mode == MapMode::Sync {
+            return Err(Error::Io(std::io::ErrorKind::Other,
+                "MAP_SYNC is not supported on this platform".to_string()));
+        }
+        if mode == MapMode::Msync {
+            return Err(Error::Io(std::io::ErrorKind::Other,
+                "msync durability is not supported on this platform".to_string()));
+        }
+        let mut inner = unsafe { memmap::MmapOptions::new().map_mut(file)? };
+        Ok(Self {
+            ptr: inner.as_mut_ptr(),
+            len: inner.len(),
+            durability: Durability::PageCache,
+            _inner: inner,
+        })
+    }
+
+    /// Returns the address of the first byte of the mapping
+    pub fn as_mut_ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    /// Returns the length of the mapping in bytes
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Indicates if the mapping is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the durability guarantee of the mapping
+    pub fn durability(&self) -> Durability {
+        self.durability
+    }
+}
+
+#[cfg(unix)]
+impl Drop for PoolMapping {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr as *mut libc::c_void, self.len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alloc::{Durability, MapMode};
+    use crate::default::*;
+
+    #[test]
+    fn non_dax_mappings() {
+        let _ = Allocator::open_no_root("mapping.pool", O_CF).unwrap();
+        Allocator::set_map_mode(MapMode::UnsafeNonDax);
+        let _pool = Allocator::open_no_root("mapping.pool", 0).unwrap();
+        assert_eq!(Allocator::durability(), Some(Durability::PageCache));
+        drop(_pool);
+
+        Allocator::set_map_mode(MapMode::Msync);
+        let root = Allocator::open::<PCell<u64>>("mapping.pool", O_CF).unwrap();
+        assert!(crate::ll::msync_mode());
+        Allocator::transaction(|j| root.set(7, j)).unwrap();
+        assert_eq!(root.get(), 7);
+        drop(root);
+        assert_eq!(crate::ll::msync_mode(), cfg!(feature = "use_msync"));
+        Allocator::set_map_mode(MapMode::Auto);
+    }
+}
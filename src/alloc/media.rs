@@ -0,0 +1,314 @@
+use crate::result::Result;
+#[cfg(not(target_os = "linux"))]
+use crate::Error;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+const MAX_RANGES: usize = 64;
+const MAX_FAULTS: usize = 256;
+
+const ZERO: AtomicU64 = AtomicU64::new(0);
+
+/// The address ranges of the open pools, as `[start, end)` pairs. A slot is
+/// free if its start is zero.
+static RANGE_START: [AtomicU64; MAX_RANGES] = [ZERO; MAX_RANGES];
+static RANGE_END: [AtomicU64; MAX_RANGES] = [ZERO; MAX_RANGES];
+
+/// The address of the first fault in each range, or zero. A pool with a fault
+/// is poisoned until it is closed.
+static RANGE_POISON: [AtomicU64; MAX_RANGES] = [ZERO; MAX_RANGES];
+
+/// The recorded faults as a ring of page addresses and faulting threads
+static FAULT_ADDR: [AtomicU64; MAX_FAULTS] = [ZERO; MAX_FAULTS];
+static FAULT_THREAD: [AtomicU64; MAX_FAULTS] = [ZERO; MAX_FAULTS];
+static FAULT_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+static PAGE_SIZE: AtomicU64 = AtomicU64::new(4096);
+
+#[cfg(target_os = "linux")]
+static mut OLD_ACTION: Option<libc::sigaction> = None;
+
+/// Registers the address range of an open pool, so that the media errors in
+/// it are recovered by the `SIGBUS` handler
+#[doc(hidden)]
+pub fn register_media_range(start: u64, end: u64) {
+    for i in 0..MAX_RANGES {
+        if RANGE_START[i].compare_exchange(0, u64::MAX, Ordering::AcqRel, Ordering::Relaxed).is_ok() {
+            RANGE_END[i].store(end, Ordering::Release);
+            RANGE_POISON[i].store(0, Ordering::Release);
+            RANGE_START[i].store(start, Ordering::Release);
+            return;
+        }
+    }
+}
+
+/// Unregisters the address range of a pool which starts at `start`
+#[doc(hidden)]
+pub fn unregister_media_range(start: u64) {
+    for i in 0..MAX_RANGES {
+        if RANGE_START[i].load(Ordering::Acquire) == start {
+            RANGE_END[i].store(0, Ordering::Release);
+            RANGE_POISON[i].store(0, Ordering::Release);
+            RANGE_START[i].store(0, Ordering::Release);
+            return;
+        }
+    }
+}
+
+fn range_of(addr: u64) -> Option<usize> {
+    (0..MAX_RANGES).find(|i| {
+        let start = RANGE_START[*i].load(Ordering::Acquire);
+        start != 0 && start != u64::MAX
+            && addr >= start && addr < RANGE_END[*i].load(Ordering::Acquire)
+    })
+}
+
+/// Returns the address of the first fault in the pool which starts at
+/// `start`, if the pool is poisoned
+#[doc(hidden)]
+pub fn media_poison(start: u64) -> Option<u64> {
+    (0..MAX_RANGES).find_map(|i| {
+        if RANGE_START[i].load(Ordering::Acquire) == start {
+            match RANGE_POISON[i].load(Ordering::Acquire) {
+                0 => None,
+                addr => Some(addr)
+            }
+        } else {
+            None
+        }
+    })
+}
+
+fn thread_id() -> u64 {
+    #[cfg(unix)] unsafe {
+        libc::pthread_self() as u64
+    }
+    #[cfg(not(unix))] {
+        0
+    }
+}
+
+/// Returns the number of faults recorded so far; it is used as a mark for
+/// [`media_fault_since()`](./fn.media_fault_since.html)
+#[doc(hidden)]
+pub fn media_faults() -> usize {
+    FAULT_COUNT.load(Ordering::Acquire)
+}
+
+/// Returns the address of the first fault in `[start, end)` which is raised by
+/// the current thread after `mark` was taken
+#[doc(hidden)]
+pub fn media_fault_since(mark: usize, start: u64, end: u64) -> Option<u64> {
+    let count = FAULT_COUNT.load(Ordering::Acquire);
+    if count == mark {
+        return None;
+    }
+    let tid = thread_id();
+    (mark.max(count.saturating_sub(MAX_FAULTS))..count).find_map(|i| {
+        let addr = FAULT_ADDR[i % MAX_FAULTS].load(Ordering::Acquire);
+        if FAULT_THREAD[i % MAX_FAULTS].load(Ordering::Acquire) == tid
+            && addr >= start && addr < end {
+            Some(addr)
+        } else {
+            None
+        }
+    })
+}
+
+/// Returns the recorded bad pages in `[start, end)` as offsets from `start`
+#[doc(hidden)]
+pub fn bad_ranges_in(start: u64, end: u64) -> Vec<Range<u64>> {
+    let count = FAULT_COUNT.load(Ordering::Acquire);
+    let page = PAGE_SIZE.load(Ordering::Relaxed);
+    let mut pages: Vec<u64> = (count.saturating_sub(MAX_FAULTS)..count)
+        .map(|i| FAULT_ADDR[i % MAX_FAULTS].load(Ordering::Acquire))
+        .filter(|a| *a >= start && *a < end)
+        .collect();
+    pages.sort_unstable();
+    pages.dedup();
+    let mut ranges: Vec<Range<u64>> = vec![];
+    for p in pages {
+        let off = p - start;
+        match ranges.last_mut() {
+            Some(r) if r.end == off => r.end += page,
+            _ => ranges.push(off..off + page),
+        }
+    }
+    ranges
+}
+
+fn record_fault(page: u64) {
+    let i = FAULT_COUNT.fetch_add(1, Ordering::AcqRel);
+    FAULT_THREAD[i % MAX_FAULTS].store(thread_id(), Ordering::Release);
+    FAULT_ADDR[i % MAX_FAULTS].store(page, Ordering::Release);
+}
+
+#[cfg(target_os = "linux")]
+extern "C" fn on_sigbus(sig: i32, info: *mut libc::siginfo_t, ctx: *mut libc::c_void) {
+    unsafe {
+        let addr = (*info).si_addr() as u64;
+        if let Some(range) = range_of(addr) {
+            // Replaces the bad page with a zero page, so that the faulting
+            // instruction can continue. The transaction observes the fault
+            // when it finishes, and rolls back. The pool is poisoned, as the
+            // later changes to the page would go to the volatile zero page.
+            let page = PAGE_SIZE.load(Ordering::Relaxed);
+            let base = addr & !(page - 1);
+            let p = libc::mmap(
+                base as *mut libc::c_void,
+                page as usize,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_FIXED | libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            if p != libc::MAP_FAILED {
+                record_fault(base);
+                let _ = RANGE_POISON[range].compare_exchange(0, base, Ordering::AcqRel, Ordering::Relaxed);
+                return;
+            }
+        }
+
+        // Not a pool access; chains to the previous handler
+        match &OLD_ACTION {
+            Some(old) if old.sa_sigaction != libc::SIG_DFL && old.sa_sigaction != libc::SIG_IGN => {
+                if old.sa_flags & libc::SA_SIGINFO != 0 {
+                    let f: extern "C" fn(i32, *mut libc::siginfo_t, *mut libc::c_void) =
+                        std::mem::transmute(old.sa_sigaction);
+                    f(sig, info, ctx);
+                } else {
+                    let f: extern "C" fn(i32) = std::mem::transmute(old.sa_sigaction);
+                    f(sig);
+                }
+            }
+            Some(old) => {
+                // The fault repeats with the default action
+                libc::sigaction(libc::SIGBUS, old, std::ptr::null_mut());
+            }
+            None => {
+                libc::signal(libc::SIGBUS, libc::SIG_DFL);
+            }
+        }
+    }
+}
+
+/// Installs a `SIGBUS` handler which recovers from media errors in the pools
+///
+/// Reading a bad block of persistent memory, or a page beyond the end of a
+/// truncated pool file, raises `SIGBUS`, which terminates the program by
+/// default. With this handler, a fault within an open pool replaces the page
+/// with a zero-filled page, and records it as a bad range. The transaction
+/// in which the fault occurs is rolled back, and it returns
+/// [`Error::MediaError`]. The recorded ranges of a pool are available through
+/// [`MemPoolTraits::bad_ranges()`]. The contents of a bad page are lost for
+/// the rest of the session; the rollback cannot restore them on the media.
+///
+/// The zero page is volatile, so the pool is poisoned after the first fault:
+/// every later transaction on it fails with [`Error::MediaError`] without
+/// running, until the pool is closed. The reads outside transactions are not
+/// checked; they see zeros in the bad page. The pool should be closed and
+/// repaired (e.g., restored from a backup) before it is used again.
+///
+/// Faults outside the pools are passed to the previous handler. Calling this
+/// function more than once has no effect.
+///
+/// # Errors
+///
+/// It returns an [`Io`] error if the handler cannot be installed, or the
+/// platform is not supported.
+///
+/// # Examples
+///
+/// ```
+/// use corundum::default::*;
+/// use corundum::alloc::install_media_error_handler;
+///
+/// install_media_error_handler().unwrap();
+/// let _pool = Allocator::open_no_root("media.pool", O_CF).unwrap();
+/// assert!(Allocator::bad_ranges().is_empty());
+/// ```
+///
+/// [`Error::MediaError`]: ../enum.Error.html#variant.MediaError
+/// [`MemPoolTraits::bad_ranges()`]: ./trait.MemPoolTraits.html#method.bad_ranges
+/// [`Io`]: ../enum.Error.html#variant.Io
+#[cfg(target_os = "linux")]
+pub fn install_media_error_handler() -> Result<()> {
+    if INSTALLED.swap(true, Ordering::AcqRel) {
+        return Ok(());
+    }
+    unsafe {
+        PAGE_SIZE.store(libc::sysconf(libc::_SC_PAGESIZE) as u64, Ordering::Relaxed);
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_sigbus as usize;
+        action.sa_flags = libc::SA_SIGINFO | libc::SA_NODEFER;
+        libc::sigemptyset(&mut action.sa_mask);
+        let mut old: libc::sigaction = std::mem::zeroed();
+        if libc::sigaction(libc::SIGBUS, &action, &mut old) != 0 {
+            INSTALLED.store(false, Ordering::Release);
+            return Err(std::io::Error::last_os_error().into());
+        }
+        OLD_ACTION = Some(old);
+    }
+    Ok(())
+}
+
+/// Installs a `SIGBUS` handler which recovers from media errors in the pools
+///
+/// It is not supported on this platform.
+#[cfg(not(target_os = "linux"))]
+pub fn install_media_error_handler() -> Result<()> {
+    Err(Error::Io(std::io::ErrorKind::Other, "media error handling is not supported".to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    crate::pool!(media_test);
+
+    #[test]
+    fn bad_range_records() {
+        let base = 0x7f00_0000_0000u64;
+        let mark = media_faults();
+        record_fault(base + 4096);
+        record_fault(base + 8192);
+        assert_eq!(media_fault_since(mark, base, base + (1 << 20)), Some(base + 4096));
+        assert_eq!(media_fault_since(mark, 0, base), None);
+        let page = PAGE_SIZE.load(Ordering::Relaxed);
+        if page == 4096 {
+            assert_eq!(bad_ranges_in(base, base + (1 << 20)), vec![4096..12288]);
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn poisoned_after_fault() {
+        use crate::Error;
+        use media_test::*;
+
+        type P = Allocator;
+
+        let path = "media_test.pool";
+        let _ = std::fs::remove_file(path);
+        install_media_error_handler().unwrap();
+        let root = P::open::<PRefCell<PVec<u8>>>(path, O_CF).unwrap();
+        P::transaction(|j| {
+            root.borrow_mut(j).extend_from_slice(&vec![1u8; 1 << 20], j);
+        }).unwrap();
+
+        // Truncating the file makes the last page of the vector a bad page
+        let last = root.borrow().as_slice().as_ptr() as u64 + (1 << 20) - 1;
+        let page = PAGE_SIZE.load(Ordering::Relaxed);
+        let off = (last - P::start()) & !(page - 1);
+        std::fs::OpenOptions::new().write(true).open(path).unwrap().set_len(off).unwrap();
+
+        let res = P::transaction(|_| root.borrow()[(1 << 20) - 1]);
+        assert_eq!(res, Err(Error::MediaError(off)));
+        assert!(!P::bad_ranges().is_empty());
+
+        // The later transactions fail without running
+        let res = P::transaction(|j| root.borrow_mut(j).push(2, j));
+        assert_eq!(res, Err(Error::MediaError(off)));
+    }
+}
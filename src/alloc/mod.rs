@@ -2,11 +2,35 @@
 
 mod alg;
 mod pool;
+mod dynpool;
+mod space;
+mod builder;
+mod config;
+mod options;
+mod health;
+mod pin;
+mod interleave;
+mod mapping;
+mod placement;
+mod oom;
+mod media;
 
 pub mod heap;
 
 pub use alg::buddy::*;
 pub use pool::*;
+pub use dynpool::*;
+pub use space::*;
+pub use builder::*;
+pub use config::*;
+pub use options::*;
+pub use health::*;
+pub use pin::*;
+pub use interleave::*;
+pub use mapping::*;
+pub use placement::*;
+pub use oom::*;
+pub use media::*;
 
 /// Determines how much of the `MemPool` is used for the trait object.
 ///
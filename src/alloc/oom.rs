@@ -0,0 +1,57 @@
+use crate::cell::LazyCell;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// A callback which is invoked when an allocation of the given number of
+/// bytes cannot be satisfied
+pub type OomHandler = Arc<dyn Fn(usize) + Send + Sync>;
+
+/// The out-of-memory handlers of all pool types. It outlives the pools, so
+/// that a handler can be installed before a pool is opened.
+static mut OOM_HANDLERS: LazyCell<Mutex<HashMap<&'static str, OomHandler>>> =
+    LazyCell::new(|| Mutex::new(HashMap::new()));
+
+thread_local! {
+    /// Indicates if the current thread is running an out-of-memory handler
+    static IN_HANDLER: Cell<bool> = Cell::new(false);
+}
+
+fn handlers() -> std::sync::MutexGuard<'static, HashMap<&'static str, OomHandler>> {
+    match unsafe { OOM_HANDLERS.lock() } {
+        Ok(g) => g,
+        Err(p) => p.into_inner()
+    }
+}
+
+/// Sets or removes the out-of-memory handler of pool `pool`
+#[doc(hidden)]
+pub fn set_pool_oom_handler(pool: &'static str, handler: Option<OomHandler>) {
+    match handler {
+        Some(h) => { handlers().insert(pool, h); }
+        None => { handlers().remove(pool); }
+    }
+}
+
+/// Runs the out-of-memory handler of pool `pool` for an allocation of `size`
+/// bytes, and returns `true` if the allocation should be retried
+///
+/// The handler runs without holding any lock of the registry, and it is not
+/// reentered if it allocates and runs out of memory itself.
+#[doc(hidden)]
+pub fn handle_oom(pool: &'static str, size: usize) -> bool {
+    if IN_HANDLER.with(|h| h.get()) {
+        return false;
+    }
+    let handler = match handlers().get(pool) {
+        Some(h) => h.clone(),
+        None => return false,
+    };
+    IN_HANDLER.with(|h| h.set(true));
+    let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| handler(size)));
+    IN_HANDLER.with(|h| h.set(false));
+    if let Err(e) = res {
+        std::panic::resume_unwind(e);
+    }
+    true
+}
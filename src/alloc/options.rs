@@ -0,0 +1,169 @@
+use crate::alloc::open_flags::*;
+
+/// Typed open options of a pool
+///
+/// It is a typed alternative to the raw [`open_flags`], and can be converted
+/// from them for compatibility. Unlike the size flags (`O_1GB` to `O_64TB`),
+/// [`capacity()`](#method.capacity) accepts any size. [`PoolBuilder`] is
+/// built on these options, so both follow the same rules:
+///
+/// * A pool file which is created by the options is always formatted.
+/// * [`format()`](#method.format) formats an existing pool file.
+/// * A capacity without a create option is an error, since it cannot apply
+///   to an existing file.
+///
+/// | Options                          | Flags equivalent  |
+/// |----------------------------------|-------------------|
+/// | `create(true)`                   | `O_CF`            |
+/// | `create_if_missing(true)`        | `O_CFNE`          |
+/// | `format(true)`                   | `O_F`             |
+/// | `capacity(8 * GB)`               | `O_8GB`           |
+///
+/// # Examples
+///
+/// ```
+/// use corundum::default::*;
+/// use corundum::{OpenOptions, MB};
+///
+/// type P = Allocator;
+///
+/// let opts = OpenOptions::new().create(true).capacity(12 * MB);
+/// let root = P::open_with_options::<PCell<i32>>("opts.pool", opts).unwrap();
+/// assert_eq!(root.get(), 0);
+/// drop(root);
+///
+/// // The raw flags still work
+/// let _ = P::open_with_options::<PCell<i32>>("opts.pool", O_CF.into());
+///
+/// // A capacity needs a create option
+/// assert!(P::open_no_root_with_options("opts.pool",
+///     OpenOptions::new().capacity(12 * MB)).is_err());
+/// ```
+///
+/// [`open_flags`]: ./open_flags/index.html
+/// [`PoolBuilder`]: ./struct.PoolBuilder.html
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OpenOptions {
+    create: bool,
+    create_if_missing: bool,
+    format: bool,
+    format_on_corruption: bool,
+    capacity: Option<u64>,
+    size_flags: u32,
+}
+
+impl OpenOptions {
+    /// Creates options which open an existing pool
+    pub const fn new() -> Self {
+        Self {
+            create: false,
+            create_if_missing: false,
+            format: false,
+            format_on_corruption: false,
+            capacity: None,
+            size_flags: 0,
+        }
+    }
+
+    /// Converts raw open flags to options
+    ///
+    /// `O_C` and `O_CNE` are converted to [`create()`](#method.create) and
+    /// [`create_if_missing()`](#method.create_if_missing), which format the
+    /// new file. `O_F` is converted to [`format()`](#method.format) only if
+    /// it is not combined with a create flag. The size flags are validated by
+    /// [`get_capacity()`](#method.get_capacity).
+    pub const fn from_flags(flags: u32) -> Self {
+        let creates = flags & (O_C | O_CNE) != 0;
+        Self {
+            create: flags & O_C != 0,
+            create_if_missing: flags & O_CNE != 0,
+            format: !creates && flags & O_F != 0,
+            format_on_corruption: false,
+            capacity: None,
+            size_flags: flags & !(O_C | O_F | O_CNE),
+        }
+    }
+
+    /// Always creates and formats a new pool file, replacing the existing
+    /// one (`O_CF`)
+    pub const fn create(mut self, create: bool) -> Self {
+        self.create = create;
+        self
+    }
+
+    /// Creates and formats the pool file only if it does not exist
+    /// (`O_CFNE`)
+    pub const fn create_if_missing(mut self, create: bool) -> Self {
+        self.create_if_missing = create;
+        self
+    }
+
+    /// Formats the existing pool file (`O_F`)
+    pub const fn format(mut self, format: bool) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Formats the existing pool file if it does not contain a valid image
+    /// of the pool type
+    pub const fn format_on_corruption(mut self, format: bool) -> Self {
+        self.format_on_corruption = format;
+        self
+    }
+
+    /// Sets the size of a new pool file in bytes
+    ///
+    /// It overrides the size flags, if any. It requires either
+    /// [`create()`](#method.create) or
+    /// [`create_if_missing()`](#method.create_if_missing).
+    pub const fn capacity(mut self, capacity: u64) -> Self {
+        self.size_flags = 0;
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /// Indicates if the options always create a new pool file
+    pub const fn creates_new(&self) -> bool {
+        self.create
+    }
+
+    /// Indicates if the options create a new pool file, if it is missing
+    pub const fn creates(&self) -> bool {
+        self.create || self.create_if_missing
+    }
+
+    /// Indicates if the options format the existing pool file
+    pub const fn formats(&self) -> bool {
+        self.format
+    }
+
+    /// Indicates if the options format the existing pool file if it is not
+    /// valid
+    pub const fn formats_on_corruption(&self) -> bool {
+        self.format_on_corruption
+    }
+
+    /// Returns the size of a new pool file in bytes
+    ///
+    /// # Errors
+    ///
+    /// It returns an error if a capacity or a size flag is given without a
+    /// create option, or if the size flags are invalid (see
+    /// [`pool_size()`](./open_flags/fn.pool_size.html)).
+    pub const fn get_capacity(&self) -> Result<u64, &'static str> {
+        let given = self.capacity.is_some() || self.size_flags != 0;
+        if given && !self.creates() {
+            return Err("Cannot set the capacity without a create option");
+        }
+        match self.capacity {
+            Some(capacity) => Ok(capacity),
+            None => pool_size(self.size_flags | O_C),
+        }
+    }
+}
+
+impl From<u32> for OpenOptions {
+    fn from(flags: u32) -> Self {
+        Self::from_flags(flags)
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+/// A volatile table of pinned regions of a pool
+///
+/// Objects whose addresses are exported out of the pool (e.g., FFI handles
+/// or RDMA registrations) are pinned so that they are never moved by
+/// reallocation. Pins are reference counted: a region remains pinned until it
+/// is unpinned as many times as it was pinned. The table is volatile and is
+/// cleared when the pool is closed, as are the exported addresses.
+///
+/// Pool implementations keep one table per pool, which is accessed through
+/// [`MemPoolTraits::pin()`] and [`MemPoolTraits::unpin()`].
+///
+/// [`MemPoolTraits::pin()`]: ./trait.MemPoolTraits.html#method.pin
+/// [`MemPoolTraits::unpin()`]: ./trait.MemPoolTraits.html#method.unpin
+#[derive(Clone, Debug, Default)]
+pub struct PinTable {
+    /// Maps offsets to `(len, count)` pairs
+    regions: BTreeMap<u64, (usize, usize)>,
+}
+
+impl PinTable {
+    /// Creates an empty table
+    pub fn new() -> Self {
+        Self { regions: BTreeMap::new() }
+    }
+
+    /// Pins the region at offset `off` with the size of `len` bytes
+    pub fn pin(&mut self, off: u64, len: usize) {
+        let e = self.regions.entry(off).or_insert((0, 0));
+        e.0 = e.0.max(len.max(1));
+        e.1 += 1;
+    }
+
+    /// Unpins the region at offset `off`. It returns false if the region is
+    /// not pinned.
+    pub fn unpin(&mut self, off: u64) -> bool {
+        match self.regions.get_mut(&off) {
+            Some(e) => {
+                e.1 -= 1;
+                if e.1 == 0 {
+                    self.regions.remove(&off);
+                }
+                true
+            }
+            None => false
+        }
+    }
+
+    /// Indicates if any pinned region overlaps the given range
+    pub fn is_pinned(&self, off: u64, len: usize) -> bool {
+        let end = off + len.max(1) as u64;
+        self.regions.range(..end).any(|(o, (l, _))| o + *l as u64 > off)
+    }
+
+    /// Returns the total number of pinned bytes
+    pub fn pinned_bytes(&self) -> usize {
+        self.regions().map(|r| (r.end - r.start) as usize).sum()
+    }
+
+    /// Returns an iterator over the pinned regions sorted by their offsets
+    pub fn regions(&self) -> impl Iterator<Item = Range<u64>> + '_ {
+        self.regions.iter().map(|(o, (l, _))| *o..*o + *l as u64)
+    }
+
+    /// Indicates if no region is pinned
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PinTable;
+
+    #[test]
+    fn pin_table() {
+        let mut t = PinTable::new();
+        t.pin(64, 32);
+        t.pin(64, 32);
+        t.pin(256, 8);
+        assert_eq!(t.pinned_bytes(), 40);
+        assert!(t.is_pinned(90, 10));
+        assert!(!t.is_pinned(96, 160));
+        assert!(t.unpin(64));
+        assert!(t.is_pinned(64, 1));
+        assert!(t.unpin(64));
+        assert!(!t.is_pinned(64, 1));
+        assert!(!t.unpin(64));
+        assert_eq!(t.regions().collect::<Vec<_>>(), vec![256..264]);
+    }
+}
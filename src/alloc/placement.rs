@@ -0,0 +1,293 @@
+use crate::cell::LazyCell;
+use crate::result::Result;
+use crate::Error;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Mutex;
+
+/// A set of NUMA nodes (up to 64)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct NodeMask(u64);
+
+impl NodeMask {
+    /// Creates a mask of the given nodes
+    ///
+    /// # Panics
+    ///
+    /// It panics if a node number is 64 or larger. See
+    /// [`try_new()`](#method.try_new) for a non-panicking version.
+    pub fn new(nodes: &[usize]) -> Self {
+        match Self::try_new(nodes) {
+            Ok(mask) => mask,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Creates a mask of the given nodes, or returns [`InvalidArgument`] if
+    /// a node number is 64 or larger
+    ///
+    /// [`InvalidArgument`]: ../enum.Error.html#variant.InvalidArgument
+    pub fn try_new(nodes: &[usize]) -> Result<Self> {
+        let mut mask = 0;
+        for n in nodes {
+            if *n >= 64 {
+                return Err(Error::InvalidArgument(
+                    format!("node {} is out of the supported range", n)));
+            }
+            mask |= 1 << n;
+        }
+        Ok(Self(mask))
+    }
+
+    /// Returns the raw bitmask
+    pub fn bits(&self) -> u64 {
+        self.0
+    }
+
+    /// Indicates if `node` is in the mask
+    pub fn contains(&self, node: usize) -> bool {
+        node < 64 && self.0 & (1 << node) != 0
+    }
+
+    /// Returns the nodes in the mask in ascending order
+    pub fn nodes(&self) -> Vec<usize> {
+        (0..64).filter(|n| self.contains(*n)).collect()
+    }
+
+    /// Indicates if the mask is empty
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// The memory placement policy of a range of a pool
+///
+/// Tiered memory, such as CXL memory expanders or persistent memory in
+/// `kmem` DAX mode (which memkind exposes as `MEMKIND_DAX_KMEM`), appears as
+/// NUMA nodes without cpus. A placement binds the pages of a zone of the pool
+/// to such nodes using `mbind(2)`. It takes effect for the memory that the
+/// kernel allocates for the mapping, e.g., the page cache of a pool file on a
+/// regular file system. The placement of a file on an `fsdax` or `devdax`
+/// namespace is determined by the device, and the policy is ignored.
+///
+/// See [`MemPoolTraits::set_placement()`] for applying it to a pool.
+///
+/// [`MemPoolTraits::set_placement()`]: ./trait.MemPoolTraits.html#method.set_placement
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Placement {
+    /// The system default policy (first touch)
+    Default,
+
+    /// Allocates only from the given nodes
+    Bind(NodeMask),
+
+    /// Prefers the given node, and falls back to the others
+    Preferred(usize),
+
+    /// Interleaves the pages over the given nodes
+    Interleave(NodeMask),
+}
+
+impl Default for Placement {
+    fn default() -> Self {
+        Placement::Default
+    }
+}
+
+const MPOL_DEFAULT: i32 = 0;
+const MPOL_PREFERRED: i32 = 1;
+const MPOL_BIND: i32 = 2;
+const MPOL_INTERLEAVE: i32 = 3;
+const MPOL_MF_MOVE: u32 = 1 << 1;
+
+impl Placement {
+    /// Checks if the placement can be applied
+    ///
+    /// # Errors
+    ///
+    /// It returns [`InvalidArgument`] if a node mask is empty, or the
+    /// preferred node is 64 or larger.
+    ///
+    /// [`InvalidArgument`]: ../enum.Error.html#variant.InvalidArgument
+    pub fn validate(&self) -> Result<()> {
+        match *self {
+            Placement::Default => Ok(()),
+            Placement::Preferred(n) => NodeMask::try_new(&[n]).map(|_| ()),
+            Placement::Bind(m) | Placement::Interleave(m) => if m.is_empty() {
+                Err(Error::InvalidArgument("empty node mask".to_string()))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    /// Applies the placement to `len` bytes at `addr`
+    ///
+    /// The range is extended to page boundaries, and the pages which are
+    /// already in memory are moved, if possible.
+    ///
+    /// # Errors
+    ///
+    /// * [`InvalidArgument`] if the placement is invalid (see
+    ///   [`validate()`](#method.validate)).
+    /// * [`Io`] if the kernel rejects the policy, or the platform does not
+    ///   support it.
+    ///
+    /// [`InvalidArgument`]: ../enum.Error.html#variant.InvalidArgument
+    /// [`Io`]: ../enum.Error.html#variant.Io
+    pub fn apply(&self, addr: u64, len: usize) -> Result<()> {
+        self.validate()?;
+        let (mode, mask) = match *self {
+            Placement::Default => (MPOL_DEFAULT, NodeMask::default()),
+            Placement::Bind(m) => (MPOL_BIND, m),
+            Placement::Preferred(n) => (MPOL_PREFERRED, NodeMask::new(&[n])),
+            Placement::Interleave(m) => (MPOL_INTERLEAVE, m),
+        };
+        if len == 0 {
+            return Ok(());
+        }
+        Self::mbind(addr, len, mode, mask)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn mbind(addr: u64, len: usize, mode: i32, mask: NodeMask) -> Result<()> {
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as u64;
+        let start = addr & !(page - 1);
+        let len = (addr + len as u64 - start + page - 1) & !(page - 1);
+        let bits = mask.bits();
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_mbind,
+                start as *mut libc::c_void,
+                len as libc::c_ulong,
+                mode,
+                if mode == MPOL_DEFAULT { std::ptr::null() } else { &bits as *const u64 },
+                65 as libc::c_ulong,
+                MPOL_MF_MOVE,
+            )
+        };
+        if res != 0 {
+            Err(std::io::Error::last_os_error().into())
+        } else {
+            Ok(())
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn mbind(_addr: u64, _len: usize, mode: i32, _mask: NodeMask) -> Result<()> {
+        if mode == MPOL_DEFAULT {
+            Ok(())
+        } else {
+            Err(Error::Io(std::io::ErrorKind::Other, "memory placement is not supported".to_string()))
+        }
+    }
+}
+
+/// Returns the NUMA nodes which have memory but no cpus, such as CXL memory
+/// expanders and `kmem` DAX devices
+pub fn cpuless_nodes() -> Vec<usize> {
+    let mut nodes: Vec<usize> = match fs::read_dir("/sys/devices/system/node") {
+        Ok(dir) => dir.filter_map(|e| {
+            let e = e.ok()?;
+            let n = e.file_name().to_str()?.strip_prefix("node")?.parse().ok()?;
+            let cpus = fs::read_to_string(e.path().join("cpulist")).ok()?;
+            if cpus.trim().is_empty() { Some(n) } else { None }
+        }).collect(),
+        Err(_) => vec![],
+    };
+    nodes.sort_unstable();
+    nodes
+}
+
+#[derive(Default)]
+struct PoolPlacement {
+    default: Placement,
+    zones: HashMap<usize, Placement>,
+}
+
+/// The placement of the zones of every pool type. It outlives the pools, so
+/// that it can be configured before they are opened.
+static mut PLACEMENTS: LazyCell<Mutex<HashMap<&'static str, PoolPlacement>>> =
+    LazyCell::new(|| Mutex::new(HashMap::new()));
+
+fn with_placement<R>(pool: &'static str, f: impl FnOnce(&mut PoolPlacement) -> R) -> R {
+    let mut all = match unsafe { PLACEMENTS.lock() } {
+        Ok(g) => g,
+        Err(p) => p.into_inner()
+    };
+    f(all.entry(pool).or_default())
+}
+
+/// Returns the placement of zone `zone` of pool `pool`
+#[doc(hidden)]
+pub fn zone_placement(pool: &'static str, zone: usize) -> Placement {
+    with_placement(pool, |p| p.zones.get(&zone).copied().unwrap_or(p.default))
+}
+
+/// Returns the placement of zone `zone` of pool `pool` if it is set for the
+/// zone itself rather than by default
+#[doc(hidden)]
+pub fn own_zone_placement(pool: &'static str, zone: usize) -> Option<Placement> {
+    with_placement(pool, |p| p.zones.get(&zone).copied())
+}
+
+/// Sets the placement of zone `zone`, or the default placement of all zones
+/// if `zone` is `None`
+#[doc(hidden)]
+pub fn set_zone_placement(pool: &'static str, zone: Option<usize>, placement: Placement) {
+    with_placement(pool, |p| match zone {
+        Some(z) => { p.zones.insert(z, placement); }
+        None => p.default = placement,
+    })
+}
+
+/// Applies the configured placement of pool `pool` to its `count` zones of
+/// `quota` bytes each starting at `base`. The zones without a configured
+/// placement are left untouched.
+#[doc(hidden)]
+pub fn apply_placements(pool: &'static str, base: u64, quota: usize, count: usize) -> Result<()> {
+    let zones: Vec<(usize, Placement)> = with_placement(pool, |p| {
+        if p.default == Placement::Default {
+            p.zones.iter().map(|(z, pl)| (*z, *pl)).filter(|(z, _)| *z < count).collect()
+        } else {
+            (0..count).map(|z| (z, p.zones.get(&z).copied().unwrap_or(p.default))).collect()
+        }
+    });
+    for (z, placement) in zones {
+        placement.apply(base + (z * quota) as u64, quota)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn placement_config() {
+        let mask = NodeMask::new(&[0, 3]);
+        assert_eq!(mask.nodes(), vec![0, 3]);
+        assert!(Placement::Bind(NodeMask::default()).apply(0, 4096).is_err());
+
+        set_zone_placement("placement-test", None, Placement::Interleave(mask));
+        set_zone_placement("placement-test", Some(1), Placement::Preferred(2));
+        assert_eq!(zone_placement("placement-test", 0), Placement::Interleave(mask));
+        assert_eq!(zone_placement("placement-test", 1), Placement::Preferred(2));
+    }
+
+    #[test]
+    fn invalid_placement() {
+        assert!(NodeMask::try_new(&[64]).is_err());
+        assert!(Placement::Preferred(64).validate().is_err());
+        assert!(Placement::Preferred(64).apply(0, 4096).is_err());
+        assert!(Placement::Interleave(NodeMask::default()).validate().is_err());
+        assert!(Placement::Default.validate().is_ok());
+
+        // A rejected placement is not kept for the next open
+        use crate::alloc::MemPoolTraits;
+        type P = crate::default::Allocator;
+        assert!(P::set_placement(None, Placement::Bind(NodeMask::default())).is_err());
+        assert!(P::set_placement(Some(0), Placement::Preferred(64)).is_err());
+        assert_eq!(P::placement(0), Placement::Default);
+    }
+}
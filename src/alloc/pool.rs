@@ -1,12 +1,12 @@
 use std::marker::PhantomData;
 use std::panic::RefUnwindSafe;
-use crate::cell::{RootCell, RootObj};
+use crate::cell::{RootCell, RootObj, RootObjWith};
 use crate::result::Result;
 use crate::stm::*;
 use crate::utils::*;
 use crate::*;
 use std::collections::HashMap;
-use std::fs::OpenOptions;
+use std::fs;
 use std::ops::Range;
 use std::panic::UnwindSafe;
 use std::path::Path;
@@ -86,10 +86,85 @@ pub mod open_flags {
 
     /// Open Flag: Open only to read info
     pub const O_READINFO: u32 = u32::MAX;
+
+    /// Validates the open flags and returns the requested pool size in bytes
+    ///
+    /// It is a `const fn`, so the pool configuration can be checked at compile
+    /// time (see [`assert_pool_config!`](../macro.assert_pool_config.html)).
+    /// If no size flag is given, the default pool size is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::open_flags::*;
+    ///
+    /// const SIZE: u64 = match pool_size(O_CF | O_2GB) {
+    ///     Ok(s) => s,
+    ///     Err(_) => 0
+    /// };
+    /// assert_eq!(SIZE, 2 << 30);
+    /// assert!(pool_size(O_1GB).is_err());
+    /// ```
+    pub const fn pool_size(flags: u32) -> Result<u64, &'static str> {
+        let size: u64 = flags as u64 >> 4;
+        if size.count_ones() > 1 {
+            Err("Cannot have multiple size flags")
+        } else if size == 0 {
+            Ok(super::DEFAULT_POOL_SIZE)
+        } else if flags & (O_C | O_CNE) == 0 {
+            Err("Cannot use size flag without a create flag")
+        } else {
+            Ok(size << 30)
+        }
+    }
 }
 
 use open_flags::*;
 
+/// The number of bytes of a `T` which are known before dereferencing it
+#[cfg(any(feature = "check_access_violation", debug_assertions))]
+trait AccessSize {
+    fn access_size() -> usize;
+}
+
+#[cfg(any(feature = "check_access_violation", debug_assertions))]
+impl<T: ?Sized> AccessSize for T {
+    default fn access_size() -> usize {
+        1
+    }
+}
+
+#[cfg(any(feature = "check_access_violation", debug_assertions))]
+impl<T> AccessSize for T {
+    fn access_size() -> usize {
+        mem::size_of::<T>().max(1)
+    }
+}
+
+/// Panics if a `T` at `off` is not entirely in an allocated block of `A`
+#[cfg(any(feature = "check_access_violation", debug_assertions))]
+#[inline]
+#[track_caller]
+fn check_access<A: MemPoolTraits + ?Sized, T: ?Sized>(off: u64) {
+    let len = T::access_size();
+    assert!(
+        A::allocated(off, len),
+        "Access Violation (0x{:x}..0x{:x}): the object is not allocated or it \
+        is already freed",
+        off,
+        off.saturating_add(len as u64 - 1)
+    );
+}
+
+/// Returns the error of a failed allocation of `size` bytes in `A`
+#[inline]
+fn out_of_memory<A: MemPoolTraits + ?Sized>(size: usize) -> AllocError {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(pool = A::name(), size, "allocation failed");
+
+    AllocError::new(size)
+}
+
 /// Shows that the pool has a root object
 pub const FLAG_HAS_ROOT: u64 = 0x0000_0001;
 
@@ -98,6 +173,7 @@ pub const FLAG_HAS_ROOT: u64 = 0x0000_0001;
 macro_rules! static_inner {
     ($id:ident, $inner:ident, $body:block) => {
         unsafe {
+            let _trusted = $crate::stm::wcheck::Trusted::enter();
             if let Some($inner) = $id {
                 let $inner = &mut *$inner;
                 $body
@@ -199,6 +275,15 @@ pub unsafe trait MemPoolTraits
 where
     Self: 'static + Sized,
 {
+    /// Minimum space (in bytes) reserved for the pool metadata, assuming a
+    /// single zone. It is used for compile-time validation of the pool
+    /// capacity (see [`assert_pool_config!`](../macro.assert_pool_config.html)).
+    const METADATA_SIZE: usize = 0;
+
+    /// The compile-time configuration of the pool type, which is given to
+    /// the [`pool!()`](../macro.pool.html) macro
+    const CONFIG: PoolConfig = PoolConfig::new();
+
     /// Returns the name of the pool type
     fn name() -> &'static str {
         std::any::type_name::<Self>()
@@ -344,6 +429,58 @@ where
         unimplemented!()
     }
 
+    /// Opens a pool and retrieves the root object, which is created using
+    /// `args` if it does not exist
+    ///
+    /// It is similar to [`open()`](#method.open), except that the root object
+    /// is created by [`RootObjWith::init_with()`] which receives the given
+    /// arguments. See [`RootObjWith`] for an example.
+    ///
+    /// # Errors
+    ///
+    /// * A volatile memory pool (e.g. `Heap`) doesn't have a root object.
+    /// * The pool should be open before accessing the root object.
+    ///
+    /// [`RootObjWith`]: ../cell/trait.RootObjWith.html
+    /// [`RootObjWith::init_with()`]: ../cell/trait.RootObjWith.html#tymethod.init_with
+    fn open_with<'a, U: 'a + PSafe + RootObjWith<Self>> (
+        _path: &str,
+        _flags: u32,
+        _args: U::Args,
+    ) -> Result<RootCell<'a, U, Self>>
+    where
+        Self: MemPool,
+        U::Args: TxInSafe + UnwindSafe
+    {
+        unimplemented!()
+    }
+
+    /// Opens a pool using typed [`OpenOptions`] and retrieves the root object
+    ///
+    /// It is similar to [`open()`](#method.open), and additionally accepts
+    /// arbitrary pool capacities.
+    ///
+    /// [`OpenOptions`]: ./struct.OpenOptions.html
+    fn open_with_options<'a, U: 'a + PSafe + RootObj<Self>> (
+        path: &str,
+        opts: OpenOptions,
+    ) -> Result<RootCell<'a, U, Self>> where Self: MemPool {
+        // The file is prepared here, and then it is opened without any flag
+        unsafe { Self::apply_options(path, &opts)?; }
+        Self::open::<U>(path, 0)
+    }
+
+    /// Opens a pool using typed [`OpenOptions`] without any root object
+    ///
+    /// See [`open_no_root()`](#method.open_no_root) for more details.
+    ///
+    /// [`OpenOptions`]: ./struct.OpenOptions.html
+    fn open_no_root_with_options(path: &str, opts: OpenOptions) -> Result<PoolGuard<Self>>
+    where Self: MemPool {
+        unsafe { Self::apply_options(path, &opts)?; }
+        Self::open_no_root(path, 0)
+    }
+
     /// Returns true if the pool is open
     fn is_open() -> bool {
         unimplemented!()
@@ -356,19 +493,11 @@ where
 
     /// Applies open pool flags
     unsafe fn apply_flags(path: &str, flags: u32) -> Result<()> {
-        let mut size: u64 = flags as u64 >> 4;
-        if size.count_ones() > 1 {
-            return Err("Cannot have multiple size flags".to_string());
-        } else if size == 0 {
-            size = DEFAULT_POOL_SIZE;
-        } else {
-            if flags & (O_C | O_CNE) == 0 {
-                return Err("Cannot use size flag without a create flag".to_string());
-            }
-            size <<= 30;
-        }
+        let size = pool_size(flags).map_err(crate::Error::InvalidFlags)?
+            .max(Self::CONFIG.get_min_size());
         let mut format = !Path::new(path).exists() && ((flags & O_F) != 0);
         if ((flags & O_C) != 0) || ((flags & O_CNE != 0) && !Path::new(path).exists()) {
+            Self::CONFIG.check_size(size)?;
             let _=std::fs::remove_file(path);
             create_file(path, size)?;
             format = (flags & O_F) != 0;
@@ -379,6 +508,41 @@ where
         Ok(())
     }
 
+    /// Creates or formats the pool file according to the typed open options
+    ///
+    /// It is shared by [`open_with_options()`](#method.open_with_options) and
+    /// [`PoolBuilder`](./struct.PoolBuilder.html).
+    unsafe fn apply_options(path: &str, opts: &OpenOptions) -> Result<()>
+    where Self: MemPool {
+        let size = opts.get_capacity().map_err(crate::Error::InvalidFlags)?
+            .max(Self::CONFIG.get_min_size());
+        let exists = Path::new(path).exists();
+        if opts.creates_new() || (!exists && opts.creates()) {
+            if (size as usize) < Self::METADATA_SIZE + Journal::<Self>::FOOTPRINT {
+                return Err(crate::Error::InvalidArgument(
+                    format!("pool capacity is too small ({} bytes)", size)));
+            }
+            Self::CONFIG.check_size(size)?;
+            let _ = std::fs::remove_file(path);
+            create_file(path, size)?;
+            Self::format(path)
+        } else if !exists {
+            Err(crate::Error::FileNotFound(path.to_string()))
+        } else if opts.formats() ||
+            (opts.formats_on_corruption() && !Self::is_valid_image(path)) {
+            Self::format(path)
+        } else if !Self::is_valid_image(path) {
+            Err(crate::Error::InvalidImage(path.to_string()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks if the given file contains a valid image of this pool type
+    fn is_valid_image(_path: &str) -> bool {
+        true
+    }
+
     /// Indicates if the given offset is allocated
     #[inline]
     fn allocated(_off: u64, _len: usize) -> bool {
@@ -391,6 +555,13 @@ where
         true
     }
 
+    /// Returns the number of times [`verify()`](#method.verify) has found
+    /// the pool inconsistent in this session
+    #[inline]
+    fn verify_failures() -> usize {
+        0
+    }
+
     /// Translates raw pointers to memory offsets
     ///
     /// # Safety
@@ -403,16 +574,25 @@ where
 
     /// Acquires a reference pointer to the object
     ///
+    /// If the `check_access_violation` feature is enabled, or in debug
+    /// builds, it panics if the object is not entirely in an allocated block.
+    /// As every persistent pointer (e.g., [`Pbox`] and [`Prc`]) is
+    /// dereferenced through this function, the check catches use-after-free
+    /// and wild offsets where they happen.
+    ///
     /// # Safety
     ///
     /// The offset should be in the valid address range
+    ///
+    /// [`Pbox`]: ../struct.Pbox.html
+    /// [`Prc`]: ../prc/struct.Prc.html
     #[inline]
     unsafe fn get_unchecked<'a, T: 'a + ?Sized>(off: u64) -> &'a T {
         #[cfg(feature = "stat_perf")]
         let _perf = crate::stat::Measure::<Self>::Deref(std::time::Instant::now());
 
         #[cfg(any(feature = "check_access_violation", debug_assertions))]
-        assert!( Self::allocated(off, 1), "Access Violation (0x{:x})", off );
+        check_access::<Self, T>(off);
 
         utils::read_addr(Self::start() + off)
     }
@@ -429,7 +609,7 @@ where
         let _perf = crate::stat::Measure::<Self>::Deref(std::time::Instant::now());
 
         #[cfg(any(feature = "check_access_violation", debug_assertions))]
-        assert!( Self::allocated(off, 1), "Access Violation (0x{:x})", off );
+        check_access::<Self, T>(off);
 
         utils::read_addr(Self::start() + off)
     }
@@ -496,7 +676,7 @@ where
         if Self::allocated(off, mem::size_of::<T>()) {
             Ok(Self::get_unchecked(off))
         } else {
-            Err(format!("Access Violation (0x{:x})", off))
+            Err(crate::Error::AccessViolation(off))
         }
     }
 
@@ -506,7 +686,7 @@ where
         if Self::allocated(off, mem::size_of::<T>()) {
             Ok(Self::get_mut_unchecked(off))
         } else {
-            Err(format!("Access Violation (0x{:x})", off))
+            Err(crate::Error::AccessViolation(off))
         }
     }
 
@@ -516,7 +696,7 @@ where
         if Self::valid(x) {
             Ok(x as *const u8 as u64 - Self::start())
         } else {
-            Err(format!("out of valid range ({:p})", x).to_string())
+            Err(crate::Error::OutOfRange(x as *const u8 as u64))
         }
     }
 
@@ -580,6 +760,59 @@ where
         addr >= rng.start && addr < rng.end
     }
 
+    /// Translates a memory offset to its virtual address
+    ///
+    /// It is the inverse of [`off()`](#method.off). The offset of a byte is
+    /// also its index in [`raw_bytes()`](#method.raw_bytes).
+    ///
+    /// # Errors
+    ///
+    /// It returns [`OutOfRange`] if `off` is not in the pool.
+    ///
+    /// [`OutOfRange`]: ../enum.Error.html#variant.OutOfRange
+    #[inline]
+    fn addr_of(off: u64) -> Result<u64> {
+        let addr = Self::start().wrapping_add(off);
+        if off < Self::size() as u64 {
+            Ok(addr)
+        } else {
+            Err(crate::Error::OutOfRange(off))
+        }
+    }
+
+    /// Returns a read-only view of the whole pool as a byte slice
+    ///
+    /// It is intended for diagnostic tools, such as hex dumps and checksums,
+    /// which need to read the pool image as is. The view includes the pool
+    /// metadata, the journals, and free blocks, and byte `i` of the slice is
+    /// at offset `i`.
+    ///
+    /// # Safety
+    ///
+    /// * The slice is not valid after the pool is closed.
+    /// * The bytes may change while the slice is alive, if other threads run
+    ///   transactions in the meantime. Readers should not rely on a
+    ///   consistent snapshot unless no transaction is running.
+    /// * The contents of the pool should never be modified through it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::default::*;
+    ///
+    /// type P = Allocator;
+    ///
+    /// let root = P::open::<PCell<u64>>("foo.pool", O_CF).unwrap();
+    /// let off = P::off(&*root).unwrap();
+    ///
+    /// let bytes = unsafe { P::raw_bytes() };
+    /// assert_eq!(bytes.len(), P::size());
+    /// assert_eq!(P::addr_of(off).unwrap(), &*root as *const _ as u64);
+    /// ```
+    unsafe fn raw_bytes() -> &'static [u8] {
+        std::slice::from_raw_parts(Self::start() as *const u8, Self::size())
+    }
+
     /// Allocate memory as described by the given `size`.
     ///
     /// Returns a pointer to newly-allocated memory.
@@ -653,6 +886,38 @@ where
     /// 
     unsafe fn pre_alloc(size: usize) -> (*mut u8, u64, usize, usize);
 
+    /// Prepares allocation of a block at an address aligned to `align` (a
+    /// power of two) without performing it
+    ///
+    /// It is similar to [`pre_alloc`](#tymethod.pre_alloc), but the returned
+    /// pointer is aligned to `align`, even if `align` is larger than `size`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::default::*;
+    /// # type P = Allocator;
+    /// # let _p = P::open_no_root("foo.pool", O_CF).unwrap();
+    /// unsafe {
+    ///     let (ptr, _, _, z) = P::pre_alloc_aligned(64, 4096);
+    ///     assert_eq!(ptr as usize % 4096, 0);
+    ///     P::perform(z);
+    /// }
+    /// ```
+    unsafe fn pre_alloc_aligned(_size: usize, _align: usize) -> (*mut u8, u64, usize, usize) {
+        unimplemented!()
+    }
+
+    /// Prepares allocation with a placement hint without performing it
+    ///
+    /// It is similar to [`pre_alloc`](#tymethod.pre_alloc), but it places
+    /// the block according to `hint` if the pool knows its
+    /// [`interleave_topology()`](#method.interleave_topology). The default
+    /// implementation ignores the hint.
+    unsafe fn pre_alloc_hinted(size: usize, _hint: AllocHint) -> (*mut u8, u64, usize, usize) {
+        Self::pre_alloc(size)
+    }
+
     /// Prepares deallocation without performing it
     /// 
     /// This function is used internally for low-level atomicity in memory
@@ -680,6 +945,16 @@ where
     /// 
     unsafe fn pre_dealloc(ptr: *mut u8, size: usize) -> usize;
 
+    /// Grows the allocated block at offset `off` from `size` to `new_size`
+    /// bytes in place with `DropOnAbort` logs for the claimed space
+    ///
+    /// It returns true if the block could grow without relocation. The
+    /// default implementation never grows in place.
+    unsafe fn grow_in_place(_off: u64, _size: usize, _new_size: usize, _j: &Journal<Self>) -> bool
+    where Self: MemPool {
+        false
+    }
+
     /// Adds a low-level log to update as 64-bit `obj` to `val` when 
     /// [`perform()`] is called. As an example, please see [`Log::set()`].
     /// 
@@ -850,19 +1125,113 @@ where
         &mut *utils::read(p)
     }
 
+    /// Allocates new memory at an address aligned to `align` (a power of two)
+    /// and then places `x` into it with `DropOnFailure` log
+    ///
+    /// The alignment is at least the alignment of `T`.
+    unsafe fn new_aligned<'a, T: PSafe + 'a>(x: T, align: usize, j: &Journal<Self>) -> &'a mut T
+    where Self: MemPool {
+        let p = Self::new_uninit_aligned(mem::size_of::<T>(), align.max(mem::align_of::<T>()), j)
+            as *mut T;
+        ptr::write(p, x);
+        &mut *p
+    }
+
+    /// Allocates new memory according to the placement `hint` and then places
+    /// `x` into it with `DropOnFailure` log
+    unsafe fn new_with_hint<'a, T: PSafe + 'a>(x: T, hint: AllocHint, j: &Journal<Self>) -> &'a mut T
+    where Self: MemPool {
+        let p = Self::new_uninit_with_hint(mem::size_of::<T>(), hint, j) as *mut T;
+        ptr::write(p, x);
+        &mut *p
+    }
+
+    /// Allocates new memory for `T` with `DropOnFailure` log, and fills it
+    /// with `0` bytes
+    ///
+    /// # Safety
+    ///
+    /// An all-zero byte-pattern should be a valid value of type `T`.
+    unsafe fn new_zeroed<'a, T: PSafe + 'a>(j: &Journal<Self>) -> &'a mut T where Self: MemPool {
+        let p = Self::new_uninit::<T>(j);
+        ptr::write_bytes(p as *mut T, 0, 1);
+        p
+    }
+
+    /// Allocates `size` bytes of uninitialized memory at an address aligned
+    /// to `align` (a power of two) with `DropOnFailure` log
+    unsafe fn new_uninit_aligned(size: usize, align: usize, j: &Journal<Self>) -> *mut u8
+    where Self: MemPool {
+        debug_assert!(size != 0, "Cannot allocated ZST");
+        assert!(align.is_power_of_two(), "alignment should be a power of two");
+
+        let mut log = Log::drop_on_failure(u64::MAX, 1, j);
+        let (p, off, len, z) = Self::pre_alloc_aligned(size, align);
+        if p.is_null() {
+            panic!("{}", out_of_memory::<Self>(size));
+        }
+        Self::drop_on_failure(off, len, z);
+        log.set(off, len, z);
+        Self::perform(z);
+        p
+    }
+
+    /// Allocates `size` bytes of uninitialized memory according to the
+    /// placement `hint` with `DropOnFailure` log
+    unsafe fn new_uninit_with_hint(size: usize, hint: AllocHint, j: &Journal<Self>) -> *mut u8
+    where Self: MemPool {
+        debug_assert!(size != 0, "Cannot allocated ZST");
+
+        let mut log = Log::drop_on_failure(u64::MAX, 1, j);
+        let (p, off, len, z) = Self::pre_alloc_hinted(size, hint);
+        if p.is_null() {
+            panic!("{}", out_of_memory::<Self>(size));
+        }
+        Self::drop_on_failure(off, len, z);
+        log.set(off, len, z);
+        Self::perform(z);
+        p
+    }
+
+    /// Allocates new memory and then places `x` into it with `DropOnFailure`
+    /// log, or returns an [`AllocError`] if the pool is exhausted
+    ///
+    /// [`AllocError`]: ./struct.AllocError.html
+    unsafe fn try_new<'a, T: PSafe + 'a>(x: T, j: &Journal<Self>) -> std::result::Result<&'a mut T, AllocError>
+    where Self: MemPool {
+        debug_assert!(mem::size_of::<T>() != 0, "Cannot allocated ZST");
+
+        let mut log = Log::drop_on_failure(u64::MAX, 1, j);
+        let (p, off, len, z) = Self::try_atomic_new(x)?;
+        log.set(off, len, z);
+        Self::perform(z);
+        Ok(p)
+    }
+
     /// Allocates new memory and then places `x` into it without realizing the allocation
     unsafe fn atomic_new<'a, T: 'a>(x: T) -> (&'a mut T, u64, usize, usize) {
+        match Self::try_atomic_new(x) {
+            Ok(res) => res,
+            Err(e) => panic!("{}", e)
+        }
+    }
+
+    /// Allocates new memory and then places `x` into it without realizing the
+    /// allocation, or returns an [`AllocError`] if the pool is exhausted
+    ///
+    /// [`AllocError`]: ./struct.AllocError.html
+    unsafe fn try_atomic_new<'a, T: 'a>(x: T) -> std::result::Result<(&'a mut T, u64, usize, usize), AllocError> {
         log!(Self, White, "ALLOC", "TYPE: {}", std::any::type_name::<T>());
 
         let size = mem::size_of::<T>();
         let (raw, off, len, z) = Self::pre_alloc(size);
         if raw.is_null() {
-            panic!("Memory exhausted");
+            return Err(out_of_memory::<Self>(size));
         }
         Self::drop_on_failure(off, len, z);
         let p = &mut *utils::read(raw);
         mem::forget(ptr::replace(p, x));
-        (p, off, size, z)
+        Ok((p, off, size, z))
     }
 
     /// Allocates new memory and then places `x` into it without realizing the allocation
@@ -874,10 +1243,11 @@ where
             panic!("Memory exhausted");
         }
         Self::drop_on_failure(off, size, z);
-        ptr::copy_nonoverlapping(
-            x as *const _ as *const u8,
+        crate::ll::memcpy_persist(
             ptr,
+            x as *const _ as *const u8,
             x.len() * mem::size_of::<T>().max(1),
+            false,
         );
         (
             std::slice::from_raw_parts_mut(ptr.cast(), x.len()),
@@ -898,17 +1268,80 @@ where
 
     /// Allocates new memory without copying data
     unsafe fn new_uninit_for_layout(size: usize, journal: &Journal<Self>) -> *mut u8 where Self: MemPool {
+        match Self::try_new_uninit_for_layout(size, journal) {
+            Ok(p) => p,
+            Err(e) => panic!("{}", e)
+        }
+    }
+
+    /// Allocates new memory without copying data, or returns an
+    /// [`AllocError`] if the pool is exhausted
+    ///
+    /// [`AllocError`]: ./struct.AllocError.html
+    unsafe fn try_new_uninit_for_layout(size: usize, journal: &Journal<Self>) -> std::result::Result<*mut u8, AllocError>
+    where Self: MemPool {
         log!(Self, White, "ALLOC", "{:?}", size);
 
         let mut log = Log::drop_on_abort(u64::MAX, 1, journal);
         let (p, off, len, z) = Self::pre_alloc(size);
         if p.is_null() {
-            panic!("Memory exhausted");
+            return Err(out_of_memory::<Self>(size));
         }
         Self::drop_on_failure(off, len, z);
         log.set(off, len, z);
         Self::perform(z);
-        p
+        Ok(p)
+    }
+
+    /// Resizes the allocated slice `x` to `new_len` elements, and returns the
+    /// resized slice
+    ///
+    /// If the pool can grow the block in place (e.g., the buddy neighbor is
+    /// free), only the allocator metadata is logged. Otherwise, it allocates
+    /// a new block, copies the elements, and frees the old block on commit.
+    /// The elements beyond `x.len()` are uninitialized.
+    unsafe fn realloc<'a, T: PSafe>(x: &'a mut [T], new_len: usize, j: &Journal<Self>) -> &'a mut [T]
+    where Self: MemPool {
+        match Self::try_realloc(x, new_len, j) {
+            Ok(res) => res,
+            Err(e) => panic!("{}", e)
+        }
+    }
+
+    /// Resizes the allocated slice `x` to `new_len` elements, or returns an
+    /// [`AllocError`] if the pool is exhausted, in which case `x` is left
+    /// unchanged
+    ///
+    /// A [pinned](#method.pin) slice is never moved: it either grows in place,
+    /// or an [`AllocError`] is returned.
+    ///
+    /// [`AllocError`]: ./struct.AllocError.html
+    unsafe fn try_realloc<'a, T: PSafe>(x: &'a mut [T], new_len: usize, j: &Journal<Self>)
+        -> std::result::Result<&'a mut [T], AllocError>
+    where Self: MemPool {
+        let size = mem::size_of::<T>();
+        debug_assert!(size != 0, "Cannot allocated ZST");
+
+        if new_len == 0 {
+            Self::free_slice(x);
+            return Ok(&mut []);
+        }
+        if !x.is_empty() {
+            let off = Self::off_unchecked(x);
+            if new_len > x.len() &&
+                Self::grow_in_place(off, x.len() * size, new_len * size, j) {
+                log!(Self, White, "REALLOC", "IN PLACE: {} -> {}", x.len() * size, new_len * size);
+                return Ok(std::slice::from_raw_parts_mut(x.as_mut_ptr(), new_len));
+            }
+            if Self::is_pinned(off, x.len() * size) {
+                return Err(out_of_memory::<Self>(new_len * size));
+            }
+        }
+        let p = Self::try_new_uninit_for_layout(new_len * size, j)? as *mut T;
+        crate::ll::memcpy_persist(p as *mut u8, x.as_ptr() as *const u8,
+            x.len().min(new_len) * size, false);
+        Self::free_slice(x);
+        Ok(std::slice::from_raw_parts_mut(p, new_len))
     }
 
     /// Allocates new memory without copying data and realizing the allocation
@@ -936,7 +1369,9 @@ where
         let off = Self::off_unchecked(x);
         let len = mem::size_of_val(x);
         if std::thread::panicking() {
-            Log::drop_on_abort(off, len, &*Journal::<Self>::current(true).unwrap().0);
+            if !crate::stm::fresh::keep::<Self>(off, len) {
+                Log::drop_on_abort(off, len, &*Journal::<Self>::current(true).unwrap().0);
+            }
         } else {
             Log::drop_on_commit(off, len, &*Journal::<Self>::current(true).unwrap().0);
         }
@@ -968,6 +1403,18 @@ where
     /// Returns a reference to the offset of the first journal
     unsafe fn journals_head() -> &'static u64 { unimplemented!() }
 
+    /// Returns a reference to the offset of the first [journal
+    /// arena](../stm/arena/index.html)
+    #[doc(hidden)]
+    unsafe fn arenas_head() -> &'static u64 { unimplemented!() }
+
+    /// Returns the decision table of the multi-pool transactions which the
+    /// pool coordinates
+    #[doc(hidden)]
+    unsafe fn multi_decisions() -> &'static mut crate::stm::multi::Decisions {
+        unimplemented!()
+    }
+
     /// Runs a closure with a mutable reference to a thread->journal HashMap
     unsafe fn journals<T, F: Fn(&mut HashMap<ThreadId, (u64, i32)>)->T>(_: F)->T {
         unimplemented!()
@@ -996,12 +1443,24 @@ where
 
             if *journal.1 == 0 {
                 log!(Self, White, "COMMIT", "JRNL: {:?}", journal.0);
+                crate::stat::count_transaction();
+
+                #[cfg(feature = "stat_perf")]
+                let _timer = crate::stat::PhaseTimer::start(crate::stat::TxPhase::Commit);
+
+                #[cfg(feature = "tracing")]
+                tracing::debug!(pool = Self::name(), "commit");
 
                 let journal = as_mut(journal.0);
-                journal.commit(
-                    #[cfg(feature = "check_double_free")]
-                    &mut *Self::dealloc_history()
-                );
+                if crate::stm::relaxed::commit(journal) {
+                    return;
+                }
+                if !crate::stm::group::commit(journal) {
+                    journal.commit(
+                        #[cfg(feature = "check_double_free")]
+                        &mut *Self::dealloc_history()
+                    );
+                }
                 journal.clear(
                     #[cfg(feature = "check_double_free")]
                     &mut *Self::dealloc_history()
@@ -1027,6 +1486,10 @@ where
 
             if *journal.1 == 0 {
                 log!(Self, White, "COMMIT_NC", "JRNL: {:?}", journal.0);
+                crate::stat::count_transaction();
+
+                #[cfg(feature = "tracing")]
+                tracing::debug!(pool = Self::name(), "commit");
 
                 as_mut(journal.0).commit(
                     #[cfg(feature = "check_double_free")]
@@ -1079,6 +1542,10 @@ where
 
             if *journal.1 == 0 {
                 log!(Self, White, "ROLLBACK", "JRNL: {:?}", journal.0);
+                crate::stat::count_transaction();
+
+                #[cfg(feature = "tracing")]
+                tracing::debug!(pool = Self::name(), "abort");
 
                 let journal = as_mut(journal.0);
                 journal.rollback(
@@ -1177,12 +1644,28 @@ where
     {
         #[cfg(feature = "stat_perf")]
         let _perf = crate::stat::Measure::<Self>::Transaction;
+
+        #[cfg(feature = "stat_perf")]
+        let _timer = crate::stat::PhaseTimer::start(crate::stat::TxPhase::Transaction);
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("transaction", pool = Self::name()).entered();
         
         #[cfg(feature = "check_allocator_cyclic_links")]
         debug_assert!(Self::verify());
 
+        // A pool with a bad page is poisoned, as the changes to that page are
+        // not persistent anymore
+        if let Some(addr) = crate::alloc::media_poison(Self::start()) {
+            return Err(crate::Error::MediaError(addr - Self::start()));
+        }
+
+        let _watchdog = crate::stm::watchdog::TxGuard::enter::<Self>();
+
+        let media_mark = crate::alloc::media_faults();
         let mut chaperoned = false;
         let cptr = &mut chaperoned as *mut bool;
+        let protection = crate::stm::wcheck::Protection::enter::<Self>();
         let res = std::panic::catch_unwind(|| {
             let chaperon = Chaperon::current();
             if let Some(ptr) = chaperon {
@@ -1221,6 +1704,7 @@ where
                 })
             }
         });
+        drop(protection);
 
         #[cfg(feature = "stat_perf")]
         let _perf = crate::stat::Measure::<Self>::Logging(std::time::Instant::now());
@@ -1231,15 +1715,45 @@ where
         unsafe {
             crate::ll::sfence();
 
-            if let Ok(res) = res {
-                if !chaperoned {
-                    Self::commit();
+            let media = crate::alloc::media_fault_since(media_mark, Self::start(), Self::end());
+            match res {
+                Ok(res) if media.is_none() => {
+                    if !crate::stm::cancel::observed(Self::name()) {
+                        if !chaperoned {
+                            match crate::stm::isolation::validate::<Self>() {
+                                Ok(_pinned) => Self::commit(),
+                                Err(()) => {
+                                    Self::rollback();
+                                    return Err(crate::Error::SerializationFailure);
+                                }
+                            }
+                        }
+                        Ok(res)
+                    } else if !chaperoned {
+                        Self::rollback();
+                        Err(crate::Error::Cancelled)
+                    } else {
+                        // Propagates the panic to the top level in enforce rollback
+                        panic!("Cancelled chaperoned transaction");
+                    }
                 }
-                Ok(res)
-            } else {
-                if !chaperoned {
+                _ if !chaperoned && media.is_some() => {
+                    Self::rollback();
+                    Err(crate::Error::MediaError(media.unwrap_or_default() - Self::start()))
+                }
+                Ok(_) => {
+                    // Propagates the panic to the top level in enforce rollback
+                    panic!("Media error in chaperoned transaction");
+                }
+                Err(e) => if !chaperoned {
                     Self::rollback();
-                    Err("Unsuccessful transaction".to_string())
+                    if crate::stm::cancel::observed(Self::name()) {
+                        Err(crate::Error::Cancelled)
+                    } else if let Some(limit) = crate::stm::limits::exceeded(Self::name()) {
+                        Err(crate::Error::LimitExceeded(limit))
+                    } else {
+                        Err(crate::Error::from_panic(&*e))
+                    }
                 } else {
                     // Propagates the panic to the top level in enforce rollback
                     panic!("Unsuccessful chaperoned transaction");
@@ -1248,6 +1762,201 @@ where
         }
     }
 
+    /// Executes a transaction which can be cancelled by `token`
+    ///
+    /// The transaction (and its nested transactions on the same pool) poll
+    /// the token by calling [`Journal::is_cancelled()`]. If the cancellation
+    /// is observed, all changes are rolled back once the closure returns, and
+    /// it returns [`Error::Cancelled`]. See
+    /// [`CancellationToken`](../stm/cancel/struct.CancellationToken.html)
+    /// for an example.
+    ///
+    /// [`Journal::is_cancelled()`]: ../stm/struct.Journal.html#method.is_cancelled
+    /// [`Error::Cancelled`]: ./enum.Error.html#variant.Cancelled
+    #[inline]
+    #[track_caller]
+    fn cancellable_transaction<T, F: FnOnce(&'static Journal<Self>) -> T>(
+        token: &crate::stm::CancellationToken,
+        body: F
+    ) -> Result<T>
+    where
+        F: TxInSafe + UnwindSafe,
+        T: TxOutSafe, Self: alloc::pool::MemPool
+    {
+        let _token = crate::stm::cancel::TokenGuard::enter::<Self>(token);
+        Self::transaction(body)
+    }
+
+    /// Executes a transaction with the given isolation level
+    ///
+    /// Under [`Isolation::Serializable`], the shared reads of the transaction
+    /// are validated when it commits. If any of them is changed by a
+    /// concurrent transaction, the transaction is rolled back and it returns
+    /// [`Error::SerializationFailure`]. The level is ignored if the
+    /// transaction is nested in another transaction on the same pool. See the
+    /// [`isolation`](../stm/isolation/index.html) module for an example.
+    ///
+    /// [`Isolation::Serializable`]: ../stm/isolation/enum.Isolation.html#variant.Serializable
+    /// [`Error::SerializationFailure`]: ../enum.Error.html#variant.SerializationFailure
+    #[inline]
+    #[track_caller]
+    fn transaction_with_isolation<T, F: FnOnce(&'static Journal<Self>) -> T>(
+        level: crate::stm::isolation::Isolation,
+        body: F
+    ) -> Result<T>
+    where
+        F: TxInSafe + UnwindSafe,
+        T: TxOutSafe, Self: alloc::pool::MemPool
+    {
+        let _scope = crate::stm::isolation::IsolationGuard::enter::<Self>(level);
+        Self::transaction(body)
+    }
+
+    /// Executes a transaction which is bounded by the limits of `config`
+    ///
+    /// If the transaction exceeds a limit, it is rolled back, and it returns
+    /// [`Error::LimitExceeded`]. The nested transactions on the same pool
+    /// count towards the limits. See the [`limits`](../stm/limits/index.html)
+    /// module for an example.
+    ///
+    /// [`Error::LimitExceeded`]: ../enum.Error.html#variant.LimitExceeded
+    #[inline]
+    #[track_caller]
+    fn transaction_with_config<T, F: FnOnce(&'static Journal<Self>) -> T>(
+        config: crate::stm::TxConfig,
+        body: F
+    ) -> Result<T>
+    where
+        F: TxInSafe + UnwindSafe,
+        T: TxOutSafe, Self: alloc::pool::MemPool
+    {
+        let _limits = crate::stm::limits::LimitGuard::enter::<Self>(config);
+        Self::transaction(body)
+    }
+
+    /// Executes a transaction and returns its result along with its durable
+    /// commit timestamp
+    ///
+    /// The timestamp is not available if the transaction is nested in
+    /// another transaction on the same pool. See
+    /// [`timestamp`](../stm/timestamp/index.html) for an example.
+    #[inline]
+    #[track_caller]
+    fn timestamped_transaction<T, F: FnOnce(&'static Journal<Self>) -> T>(
+        body: F
+    ) -> Result<crate::stm::Committed<T>>
+    where
+        F: TxInSafe + UnwindSafe,
+        T: TxOutSafe, Self: alloc::pool::MemPool
+    {
+        let _ = crate::stm::timestamp::take(Self::name());
+        let res = Self::transaction(body)?;
+        Ok(crate::stm::Committed::new(res, crate::stm::timestamp::take(Self::name())))
+    }
+
+    /// Returns the latest commit timestamp of the pool
+    ///
+    /// It returns 0 if no transaction is committed yet, or if the pool does
+    /// not support [timestamps](../stm/timestamp/index.html).
+    fn commit_ts() -> u64 {
+        0
+    }
+
+    /// Starts the write-ahead log of the pool
+    ///
+    /// It writes a base image of the pool to `base`, and appends the redo
+    /// records of the committed transactions to `wal`. See the
+    /// [`wal`](../stm/wal/index.html) module for more details.
+    #[cfg(feature = "wal")]
+    fn start_wal(wal: &str, base: &str) -> Result<()> where Self: MemPool {
+        crate::stm::wal::start::<Self>(wal, base)
+    }
+
+    /// Stops the write-ahead log of the pool
+    #[cfg(feature = "wal")]
+    fn stop_wal() -> Result<()> where Self: MemPool {
+        crate::stm::wal::stop::<Self>()
+    }
+
+    /// Reconstructs a pool file at `out` as of `point` from a base image and
+    /// a write-ahead log
+    ///
+    /// The pool itself does not need to be open. See
+    /// [`wal::restore()`](../stm/wal/fn.restore.html) for more details.
+    #[cfg(feature = "wal")]
+    fn restore_to(
+        base: &str,
+        wal: &str,
+        point: crate::stm::wal::RestorePoint,
+        out: &str,
+    ) -> Result<crate::stm::wal::RestoreInfo> where Self: MemPool {
+        crate::stm::wal::restore(base, wal, point, out)
+    }
+
+    /// Sets the batching window of group commit for the pool
+    ///
+    /// The transactions which commit within the window share their final
+    /// flushes and fences (see the [`group`](../stm/group/index.html)
+    /// module). A zero window disables group commit, which is the default.
+    fn set_group_commit_window(window: std::time::Duration) where Self: MemPool {
+        crate::stm::group::set_window::<Self>(window)
+    }
+
+    /// Returns the batching window of group commit for the pool; zero means
+    /// that group commit is disabled
+    fn group_commit_window() -> std::time::Duration where Self: MemPool {
+        crate::stm::group::window::<Self>()
+    }
+
+    /// Sets the durability mode of the transactions of the pool
+    ///
+    /// In the [`Relaxed`] mode, a transaction skips the synchronous flushes
+    /// at commit, and a background thread makes it durable within about the
+    /// given interval. Switching back to [`Strict`] stops the background
+    /// thread and [quiesces](#method.quiesce) the pool. See the
+    /// [`relaxed`](../stm/relaxed/index.html) module for more details.
+    ///
+    /// [`Relaxed`]: ../stm/relaxed/enum.TxDurability.html#variant.Relaxed
+    /// [`Strict`]: ../stm/relaxed/enum.TxDurability.html#variant.Strict
+    fn set_tx_durability(mode: crate::stm::relaxed::TxDurability) where Self: MemPool {
+        crate::stm::relaxed::set_mode::<Self>(mode)
+    }
+
+    /// Returns the durability mode of the transactions of the pool
+    fn tx_durability() -> crate::stm::relaxed::TxDurability where Self: MemPool {
+        crate::stm::relaxed::mode::<Self>()
+    }
+
+    /// Makes all committed transactions durable, and returns the stable
+    /// epoch
+    ///
+    /// It has no effect if the pool is in the strict durability mode.
+    fn quiesce() -> u64 where Self: MemPool {
+        crate::stm::relaxed::quiesce::<Self>()
+    }
+
+    /// Returns the latest epoch whose relaxed transactions are durable
+    fn stable_epoch() -> u64 where Self: MemPool {
+        crate::stm::relaxed::stable_epoch::<Self>()
+    }
+
+    /// Returns the epoch which the next relaxed transaction belongs to
+    ///
+    /// A transaction which is committed before this call is durable once
+    /// [`stable_epoch()`](#method.stable_epoch) reaches the returned value.
+    fn current_epoch() -> u64 where Self: MemPool {
+        crate::stm::relaxed::current_epoch::<Self>()
+    }
+
+    /// Durably assigns the next commit timestamp, if supported
+    ///
+    /// # Safety
+    ///
+    /// This function is for internal use and should not be called elsewhere.
+    unsafe fn next_commit_ts() -> Option<u64> {
+        None
+    }
+
     fn gen() -> u32 {
         0
     }
@@ -1256,6 +1965,368 @@ where
         0
     }
 
+    /// Registers `obj` in the handle table of the pool, and returns an
+    /// opaque handle to it
+    ///
+    /// A handle is a stable `u64` which refers to the object by its offset,
+    /// so it remains valid if the pool is mapped at a different address. It
+    /// is meant to be passed across FFI boundaries instead of raw pointers.
+    /// The table is volatile; handles are valid until they are
+    /// [unregistered](#method.unregister), the pool is reopened, or the
+    /// process exits, and they resolve to `None` once the object is
+    /// deallocated. An object has one handle per type, so registering it
+    /// again returns the same handle. `0` is never a valid handle.
+    ///
+    /// # Errors
+    ///
+    /// * [`OutOfRange`] if `obj` is not in the pool.
+    /// * [`AccessViolation`] if `obj` is not in an allocated block.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::default::*;
+    ///
+    /// type P = Allocator;
+    ///
+    /// let root = P::open::<PCell<i32>>("foo.pool", O_CF).unwrap();
+    /// let handle = P::register(&*root).unwrap();
+    ///
+    /// P::transaction(|j| {
+    ///     let obj = P::resolve::<PCell<i32>>(handle, j).unwrap();
+    ///     obj.set(10, j);
+    /// }).unwrap();
+    ///
+    /// assert_eq!(root.get(), 10);
+    /// assert!(P::unregister(handle));
+    /// ```
+    ///
+    /// [`OutOfRange`]: ../enum.Error.html#variant.OutOfRange
+    /// [`AccessViolation`]: ../enum.Error.html#variant.AccessViolation
+    fn register<T: PSafe + ?Sized>(obj: &T) -> Result<u64>
+    where
+        Self: MemPool
+    {
+        let off = Self::off(obj)?;
+        let len = mem::size_of_val(obj);
+        if !Self::allocated(off, len) {
+            return Err(crate::Error::AccessViolation(off));
+        }
+        Ok(crate::ptr::register_handle::<Self>(off, len, Some(std::any::type_name::<T>())))
+    }
+
+    /// Registers `len` bytes at offset `off` in the handle table of the pool
+    /// without a type, and returns an opaque handle to them
+    ///
+    /// It is the untyped variant of [`register()`](#method.register) for
+    /// foreign callers.
+    ///
+    /// # Errors
+    ///
+    /// It returns [`AccessViolation`] if the range is not allocated.
+    ///
+    /// [`AccessViolation`]: ../enum.Error.html#variant.AccessViolation
+    fn register_off(off: u64, len: usize) -> Result<u64>
+    where
+        Self: MemPool
+    {
+        if !Self::allocated(off, len) {
+            return Err(crate::Error::AccessViolation(off));
+        }
+        Ok(crate::ptr::register_handle::<Self>(off, len, None))
+    }
+
+    /// Resolves a handle obtained from [`register()`](#method.register) to a
+    /// shared reference which lives as long as the transaction
+    ///
+    /// It returns `None` if the handle is unregistered, the object is
+    /// deallocated, or it was registered with a different type.
+    fn resolve<'a, T: PSafe>(handle: u64, _journal: &'a Journal<Self>) -> Option<&'a T>
+    where
+        Self: MemPool
+    {
+        let off = crate::ptr::lookup_handle::<Self>(handle, Some(std::any::type_name::<T>()))?;
+        Some(unsafe { Self::get_unchecked(off) })
+    }
+
+    /// Resolves a handle to the offset of its object without checking the
+    /// type, or `None` if the handle is stale
+    fn resolve_off(handle: u64) -> Option<u64>
+    where
+        Self: MemPool
+    {
+        crate::ptr::lookup_handle::<Self>(handle, None)
+    }
+
+    /// Removes a handle from the handle table of the pool
+    ///
+    /// It returns `false` if the handle is not registered.
+    fn unregister(handle: u64) -> bool
+    where
+        Self: MemPool
+    {
+        crate::ptr::unregister_handle::<Self>(handle)
+    }
+
+    /// Returns a map of free and allocated regions of the pool with the given
+    /// `granularity` in bytes (a power of two)
+    ///
+    /// See [`SpaceMap`](./struct.SpaceMap.html) for more details.
+    fn space_map(_granularity: u64) -> Result<SpaceMap> {
+        unimplemented!()
+    }
+
+    /// Returns the size of the largest contiguous free block in bytes
+    fn largest_free_block() -> usize {
+        Self::available()
+    }
+
+    /// Returns the status of the last recovery since the pool was opened
+    fn last_recovery() -> Option<RecoveryStatus> {
+        None
+    }
+
+    /// Pins the object `obj` so that it is never relocated
+    ///
+    /// Objects whose addresses are exported out of the pool (e.g., FFI
+    /// handles or RDMA registrations) should be pinned until the exported
+    /// address is no longer in use. Pins are reference counted and volatile;
+    /// they are released when the pool is closed. A pinned block is never
+    /// moved by [`try_realloc()`](#method.try_realloc) (e.g., when a `PVec`
+    /// grows or shrinks); it either grows in place, or the reallocation fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::default::*;
+    ///
+    /// let root = Allocator::open::<PCell<[u64; 8]>>("foo.pool", O_CF).unwrap();
+    /// Allocator::pin(&*root).unwrap();
+    /// assert!(Allocator::pinned_bytes() >= 64);
+    /// // ... export the address of `root`
+    /// Allocator::unpin(&*root).unwrap();
+    /// ```
+    fn pin<T: ?Sized>(_obj: &T) -> Result<()> {
+        unimplemented!()
+    }
+
+    /// Releases a pin taken by [`pin()`](#method.pin)
+    ///
+    /// It returns an [`InvalidArgument`] error if `obj` is not pinned.
+    ///
+    /// [`InvalidArgument`]: ./enum.Error.html#variant.InvalidArgument
+    fn unpin<T: ?Sized>(_obj: &T) -> Result<()> {
+        unimplemented!()
+    }
+
+    /// Indicates if any part of the given range is pinned, and therefore,
+    /// it cannot be relocated
+    fn is_pinned(_off: u64, _len: usize) -> bool {
+        false
+    }
+
+    /// Returns the number of pinned bytes which cannot be moved by
+    /// reallocation
+    fn pinned_bytes() -> usize {
+        0
+    }
+
+    /// Returns the interleave topology of the NVDIMM region in which the
+    /// pool resides, if it is known
+    ///
+    /// It is detected when the pool is opened. See
+    /// [`InterleaveTopology`](./struct.InterleaveTopology.html) for more
+    /// details.
+    fn interleave_topology() -> Option<InterleaveTopology> {
+        None
+    }
+
+    /// Overrides the detected interleave topology of the pool which is used
+    /// for placing allocations with [`AllocHint::Interleave`]. Passing `None`
+    /// makes all allocations local.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::default::*;
+    /// use corundum::alloc::{AllocHint, InterleaveTopology};
+    ///
+    /// let _pool = Allocator::open_no_root("foo.pool", O_CF).unwrap();
+    /// let topo = InterleaveTopology::new(4, 4096);
+    /// Allocator::set_interleave_topology(Some(topo)).unwrap();
+    ///
+    /// Allocator::transaction(|j| {
+    ///     for stripe in 0..4 {
+    ///         let chunk = Pbox::new_with_hint([0u8; 4096], AllocHint::Interleave(stripe), j);
+    ///         assert_eq!(topo.set_of(chunk.off()), stripe);
+    ///     }
+    /// }).unwrap();
+    /// ```
+    ///
+    /// [`AllocHint::Interleave`]: ./enum.AllocHint.html#variant.Interleave
+    fn set_interleave_topology(_topology: Option<InterleaveTopology>) -> Result<()> {
+        unimplemented!()
+    }
+
+    /// Sets how the pool file is mapped the next time it is opened
+    ///
+    /// See [`MapMode`](./enum.MapMode.html) for the durability guarantee of
+    /// each mode.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use corundum::default::*;
+    /// use corundum::alloc::{Durability, MapMode};
+    ///
+    /// // Fails to open unless the pool is on a DAX file system
+    /// Allocator::set_map_mode(MapMode::Sync);
+    /// let _pool = Allocator::open_no_root("/mnt/pmem0/foo.pool", O_CF).unwrap();
+    /// assert_eq!(Allocator::durability(), Some(Durability::Sync));
+    /// ```
+    fn set_map_mode(mode: MapMode) {
+        set_pool_map_mode(Self::name(), mode)
+    }
+
+    /// Returns the mapping mode of the pool
+    fn map_mode() -> MapMode {
+        pool_map_mode(Self::name())
+    }
+
+    /// Sets a callback which is invoked when an allocation cannot be
+    /// satisfied
+    ///
+    /// The handler receives the requested size in bytes. It runs outside the
+    /// allocator lock, so it may release memory, e.g., by dropping caches or
+    /// compacting data structures in a transaction. The allocation is retried
+    /// once after the handler returns. The handler is not reentered if it
+    /// runs out of memory itself.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::default::*;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// type P = Allocator;
+    ///
+    /// static CALLS: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// let _pool = P::open_no_root("oom.pool", O_CF).unwrap();
+    /// P::set_oom_handler(|_size| { CALLS.fetch_add(1, Ordering::Relaxed); });
+    ///
+    /// // The pool is too small for this allocation
+    /// let (ptr, _, _, _) = unsafe { P::pre_alloc(1 << 30) };
+    /// assert!(ptr.is_null());
+    /// assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+    /// P::clear_oom_handler();
+    /// ```
+    fn set_oom_handler<F: Fn(usize) + Send + Sync + 'static>(handler: F) {
+        set_pool_oom_handler(Self::name(), Some(std::sync::Arc::new(handler)))
+    }
+
+    /// Removes the out-of-memory handler of the pool
+    fn clear_oom_handler() {
+        set_pool_oom_handler(Self::name(), None)
+    }
+
+    /// Returns the bad ranges of the pool as offsets, which are recorded by
+    /// the media error handler in this session
+    ///
+    /// See [`install_media_error_handler()`] for more details.
+    ///
+    /// [`install_media_error_handler()`]: ./fn.install_media_error_handler.html
+    fn bad_ranges() -> Vec<Range<u64>> {
+        if Self::is_open() {
+            bad_ranges_in(Self::start(), Self::end())
+        } else {
+            vec![]
+        }
+    }
+
+    /// Returns the path of the pool file, or `None` if it is not open or it is
+    /// not file-backed
+    fn path() -> Option<String> {
+        None
+    }
+
+    /// Returns the durability guarantee of the mapping of the pool, or
+    /// `None` if it is not open or it is not file-backed
+    fn durability() -> Option<Durability> {
+        None
+    }
+
+    /// Returns the memory placement of zone `zone`
+    ///
+    /// See [`Placement`](./enum.Placement.html) for more details.
+    fn placement(zone: usize) -> Placement {
+        zone_placement(Self::name(), zone)
+    }
+
+    /// Sets the memory placement of zone `zone`, or the default placement
+    /// of all zones without their own placement if `zone` is `None`
+    ///
+    /// The placement is kept for the pool type, and it is applied every time
+    /// the pool is opened. If the pool is already open, it is also applied
+    /// immediately. It can be used to put the pool on a specific memory tier
+    /// (e.g., a CXL memory expander) in tiered-memory deployments.
+    ///
+    /// # Errors
+    ///
+    /// * [`InvalidArgument`] if `zone` does not exist, or the placement is
+    ///   invalid (see [`Placement::validate()`]).
+    /// * [`Io`] if the kernel rejects the placement.
+    ///
+    /// The placement is kept only if it is valid and, if the pool is open,
+    /// the kernel accepts it.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use corundum::default::*;
+    /// use corundum::alloc::{cpuless_nodes, NodeMask, Placement};
+    ///
+    /// let tier = cpuless_nodes();
+    /// if !tier.is_empty() {
+    ///     Allocator::set_placement(None, Placement::Bind(NodeMask::new(&tier))).unwrap();
+    /// }
+    /// let _pool = Allocator::open_no_root("foo.pool", O_CF).unwrap();
+    /// ```
+    ///
+    /// [`InvalidArgument`]: ../enum.Error.html#variant.InvalidArgument
+    /// [`Io`]: ../enum.Error.html#variant.Io
+    /// [`Placement::validate()`]: ./enum.Placement.html#method.validate
+    fn set_placement(_zone: Option<usize>, _placement: Placement) -> Result<()> {
+        unimplemented!()
+    }
+
+    /// Returns a snapshot of the health of the pool
+    ///
+    /// See [`HealthReport`](./struct.HealthReport.html) for more details.
+    fn health() -> HealthReport {
+        let open = Self::is_open();
+        HealthReport {
+            pool: Self::name(),
+            open,
+            verified: open && Self::verify(),
+            size: if open { Self::size() } else { 0 },
+            used: if open { Self::used() } else { 0 },
+            available: if open { Self::available() } else { 0 },
+            largest_free_block: if open { Self::largest_free_block() } else { 0 },
+            journals: if open {
+                unsafe { Self::journals(|j| j.values().filter(|(_, c)| *c > 0).count()) }
+            } else {
+                0
+            },
+            last_recovery: if open { Self::last_recovery() } else { None },
+            pinned: if open { Self::pinned_bytes() } else { 0 },
+            durability: Self::durability(),
+            badblocks: Self::path().and_then(|p| super::health::badblocks(&p)),
+            media_errors: Self::bad_ranges().len(),
+            verify_failures: Self::verify_failures(),
+        }
+    }
+
     /// Prints memory information
     fn print_info() {}
 
@@ -1268,6 +2339,49 @@ where
     }
 }
 
+/// Statically asserts that a pool configuration is valid
+///
+/// The first argument is the pool type and the second one is the open flags.
+/// Optionally, the number of journals (i.e. concurrent transactions) that
+/// should fit in the pool together with the metadata may be specified. A
+/// misconfiguration results in a compilation error.
+///
+/// # Examples
+///
+/// ```
+/// use corundum::default::*;
+///
+/// corundum::assert_pool_config!(Allocator, O_CF | O_1GB, journals = 64);
+/// ```
+///
+/// ```compile_fail
+/// use corundum::default::*;
+///
+/// // Size flags cannot be used without a create flag
+/// corundum::assert_pool_config!(Allocator, O_F | O_1GB);
+/// ```
+#[macro_export]
+macro_rules! assert_pool_config {
+    ($pool:ty, $flags:expr) => {
+        $crate::assert_pool_config!($pool, $flags, journals = 1);
+    };
+    ($pool:ty, $flags:expr, journals = $n:expr) => {
+        const _: () = {
+            let size = match $crate::open_flags::pool_size($flags) {
+                Ok(size) => size,
+                Err(e) => panic!("{}", e)
+            };
+            assert!(
+                <$pool as $crate::MemPoolTraits>::METADATA_SIZE +
+                ($n) * $crate::stm::Journal::<$pool>::FOOTPRINT <= size as usize,
+                "journals and metadata do not fit in the requested capacity"
+            );
+            let max = <$pool as $crate::MemPoolTraits>::CONFIG.get_max_size();
+            assert!(max == 0 || size <= max, "the requested capacity exceeds max_size");
+        };
+    };
+}
+
 pub struct PoolGuard<P: MemPoolTraits>(pub PhantomData<P>);
 
 impl<P: MemPoolTraits> PoolGuard<P> {
@@ -1302,16 +2416,9 @@ pub unsafe trait MemPool:
     UnwindSafe {}
 
 pub(crate) fn create_file(filename: &str, size: u64) -> Result<()> {
-    let file = OpenOptions::new().write(true).create(true).open(filename);
-    if file.is_err() {
-        Err(format!("{}", file.err().unwrap()))
-    } else {
-        if let Some(e) = file.unwrap().set_len(size).err() {
-            Err(format!("{}", e))
-        } else {
-            Ok(())
-        }
-    }
+    let file = fs::OpenOptions::new().write(true).create(true).open(filename)?;
+    file.set_len(size)?;
+    Ok(())
 }
 
 #[cfg(test)]
@@ -1320,6 +2427,18 @@ mod test {
     use crate::alloc::pool::MemPoolTraits;
     use crate::default::*;
 
+    #[test]
+    fn raw_bytes_view() {
+        let root = Allocator::open::<PCell<u64>>("raw_bytes.pool", O_CF).unwrap();
+        Allocator::transaction(|j| root.set(0x0123456789abcdef, j)).unwrap();
+
+        let off = Allocator::off(&*root).unwrap() as usize;
+        let bytes = unsafe { Allocator::raw_bytes() };
+        let val = u64::from_ne_bytes(bytes[off..off + 8].try_into().unwrap());
+        assert_eq!(val, 0x0123456789abcdef);
+        assert!(Allocator::addr_of(bytes.len() as u64).is_err());
+    }
+
     #[test]
     #[ignore]
     fn nested_transactions() {
@@ -0,0 +1,200 @@
+use crate::result::Result;
+use crate::Error;
+use std::ops::Range;
+
+/// A compact map of free and allocated regions of a pool
+///
+/// The pool is divided into granules of equal size and each granule is
+/// represented by a single bit. A set bit shows that at least one byte of the
+/// granule may be in use, so the map is conservative: copying all allocated
+/// granules is enough to restore the contents of the pool. It is obtained by
+/// [`MemPoolTraits::space_map()`] and can be serialized using
+/// [`to_bytes()`](#method.to_bytes), e.g. for external backup tools which
+/// copy only the allocated ranges of huge sparse pools.
+///
+/// All offsets are relative to the beginning of the pool file.
+///
+/// # Examples
+///
+/// ```
+/// use corundum::default::*;
+///
+/// let _pool = Allocator::open_no_root("foo.pool", O_CF).unwrap();
+/// let map = Allocator::space_map(4096).unwrap();
+///
+/// let mut copy = 0;
+/// for range in map.allocated_ranges() {
+///     copy += range.end - range.start;
+/// }
+/// assert!(copy <= map.size());
+/// ```
+///
+/// [`MemPoolTraits::space_map()`]: ./trait.MemPoolTraits.html#method.space_map
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpaceMap {
+    granularity: u64,
+    size: u64,
+    bits: Vec<u64>,
+}
+
+const SPACE_MAP_MAGIC: &[u8; 8] = b"CRNDMSPM";
+
+impl SpaceMap {
+    /// Creates a new map of a region of `size` bytes in which everything is
+    /// marked as allocated
+    pub fn new(size: u64, granularity: u64) -> Self {
+        assert!(granularity.is_power_of_two(), "granularity should be a power of two");
+        let granules = (size + granularity - 1) / granularity;
+        let mut bits = vec![u64::MAX; ((granules + 63) / 64) as usize];
+        if granules % 64 != 0 {
+            if let Some(last) = bits.last_mut() {
+                *last = (1 << (granules % 64)) - 1;
+            }
+        }
+        Self { granularity, size, bits }
+    }
+
+    /// Marks all granules which are completely covered by the given range as
+    /// free
+    pub fn set_free(&mut self, off: u64, len: u64) {
+        let g = self.granularity;
+        let first = (off + g - 1) / g;
+        let last = (off + len).min(self.size) / g;
+        for i in first..last {
+            self.bits[(i / 64) as usize] &= !(1 << (i % 64));
+        }
+    }
+
+    /// Marks all granules overlapping the given range as allocated
+    pub fn set_allocated(&mut self, off: u64, len: u64) {
+        if len == 0 {
+            return;
+        }
+        let g = self.granularity;
+        let first = off / g;
+        let last = ((off + len).min(self.size) + g - 1) / g;
+        for i in first..last {
+            self.bits[(i / 64) as usize] |= 1 << (i % 64);
+        }
+    }
+
+    /// Returns the size of each granule in bytes
+    #[inline]
+    pub fn granularity(&self) -> u64 {
+        self.granularity
+    }
+
+    /// Returns the size of the mapped region in bytes
+    #[inline]
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the number of granules
+    #[inline]
+    pub fn len(&self) -> usize {
+        ((self.size + self.granularity - 1) / self.granularity) as usize
+    }
+
+    /// Indicates if the map is empty
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Returns true if the `i`-th granule may be in use
+    #[inline]
+    pub fn is_allocated(&self, i: usize) -> bool {
+        self.bits[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    /// Returns the total number of bytes in allocated granules
+    pub fn allocated_bytes(&self) -> u64 {
+        self.allocated_ranges().map(|r| r.end - r.start).sum()
+    }
+
+    /// Returns an iterator over maximal ranges of allocated bytes
+    pub fn allocated_ranges(&self) -> impl Iterator<Item = Range<u64>> + '_ {
+        self.ranges(true)
+    }
+
+    /// Returns an iterator over maximal ranges of free bytes
+    pub fn free_ranges(&self) -> impl Iterator<Item = Range<u64>> + '_ {
+        self.ranges(false)
+    }
+
+    fn ranges(&self, allocated: bool) -> impl Iterator<Item = Range<u64>> + '_ {
+        let len = self.len();
+        let mut i = 0;
+        std::iter::from_fn(move || {
+            while i < len && self.is_allocated(i) != allocated {
+                i += 1;
+            }
+            if i == len {
+                return None;
+            }
+            let start = i;
+            while i < len && self.is_allocated(i) == allocated {
+                i += 1;
+            }
+            Some(start as u64 * self.granularity
+                ..(i as u64 * self.granularity).min(self.size))
+        })
+    }
+
+    /// Serializes the map into a compact little-endian binary format
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut res = Vec::with_capacity(24 + self.bits.len() * 8);
+        res.extend_from_slice(SPACE_MAP_MAGIC);
+        res.extend_from_slice(&self.granularity.to_le_bytes());
+        res.extend_from_slice(&self.size.to_le_bytes());
+        for w in &self.bits {
+            res.extend_from_slice(&w.to_le_bytes());
+        }
+        res
+    }
+
+    /// Deserializes a map previously serialized by
+    /// [`to_bytes()`](#method.to_bytes)
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let word = |i: usize| {
+            let mut w = [0u8; 8];
+            w.copy_from_slice(&bytes[i..i + 8]);
+            u64::from_le_bytes(w)
+        };
+        if bytes.len() < 24 || &bytes[..8] != SPACE_MAP_MAGIC {
+            return Err(Error::InvalidArgument("invalid space map header".to_string()));
+        }
+        let granularity = word(8);
+        let size = word(16);
+        if !granularity.is_power_of_two() {
+            return Err(Error::InvalidArgument("invalid space map granularity".to_string()));
+        }
+        let words = ((size + granularity - 1) / granularity + 63) / 64;
+        if (bytes.len() - 24) as u64 != words * 8 {
+            return Err(Error::InvalidArgument("invalid space map length".to_string()));
+        }
+        let bits = (0..words as usize).map(|i| word(24 + i * 8)).collect();
+        Ok(Self { granularity, size, bits })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SpaceMap;
+
+    #[test]
+    fn space_map_ranges() {
+        let mut map = SpaceMap::new(1000, 64);
+        assert_eq!(map.allocated_bytes(), 1000);
+        map.set_free(100, 500);
+        assert!(map.is_allocated(1));
+        assert!(!map.is_allocated(2));
+        assert!(map.is_allocated(9));
+        assert_eq!(map.free_ranges().collect::<Vec<_>>(), vec![128..576]);
+        assert_eq!(map.allocated_ranges().collect::<Vec<_>>(), vec![0..128, 576..1000]);
+        let bytes = map.to_bytes();
+        assert_eq!(SpaceMap::from_bytes(&bytes).unwrap(), map);
+        assert!(SpaceMap::from_bytes(&bytes[1..]).is_err());
+    }
+}
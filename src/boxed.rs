@@ -1,11 +1,11 @@
 //! A persistent pointer type for persistent memory allocation
 
-use crate::alloc::MemPool;
+use crate::alloc::{AllocHint, MemPool};
 use crate::cell::RootObj;
 use crate::clone::*;
 use crate::ptr::Ptr;
 use crate::stm::*;
-use crate::{PSafe, VSafe, TxOutSafe};
+use crate::{PSafe, VSafe, TxOutSafe, AllocError};
 use std::cmp::Ordering;
 use std::convert::From;
 use std::fmt;
@@ -128,11 +128,94 @@ impl<T: PSafe, A: MemPool> Pbox<T, A> {
     /// }).unwrap();
     /// ```
     pub fn new(x: T, journal: &Journal<A>) -> Pbox<T, A> {
+        match Self::try_new(x, journal) {
+            Ok(b) => b,
+            Err(e) => panic!("{}", e)
+        }
+    }
+
+    /// Tries to allocate memory on the persistent heap and then place `x`
+    /// into it. It returns an [`AllocError`] instead of panicking if the pool
+    /// is exhausted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::alloc::heap::*;
+    /// # use corundum::boxed::Pbox;
+    /// Heap::transaction(|j| {
+    ///     let five = Pbox::try_new(5, j).unwrap();
+    ///     assert_eq!(*five, 5);
+    /// }).unwrap();
+    /// ```
+    ///
+    /// [`AllocError`]: ./struct.AllocError.html
+    pub fn try_new(x: T, journal: &Journal<A>) -> Result<Pbox<T, A>, AllocError> {
+        if mem::size_of::<T>() == 0 {
+            Ok(Pbox(Ptr::dangling(), 0))
+        } else {
+            unsafe {
+                let p = A::try_new(x, journal)?;
+                Ok(Pbox(Ptr::from_mut(p), 0))
+            }
+        }
+    }
+
+    /// Allocates memory on the persistent heap at an address aligned to
+    /// `align` (a power of two) and then places `x` into it.
+    ///
+    /// The alignment is at least the alignment of `T`. It is useful for
+    /// placing cache-line-sensitive structures or DMA buffers.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::default::*;
+    /// # type P = Allocator;
+    /// # let _p = P::open_no_root("foo.pool", O_CF).unwrap();
+    /// P::transaction(|j| {
+    ///     let buf = Pbox::new_aligned([0u8; 64], 4096, j);
+    ///     assert_eq!(&*buf as *const _ as usize % 4096, 0);
+    /// }).unwrap();
+    /// ```
+    pub fn new_aligned(x: T, align: usize, journal: &Journal<A>) -> Pbox<T, A> {
         if mem::size_of::<T>() == 0 {
             Pbox(Ptr::dangling(), 0)
         } else {
             unsafe {
-                let p = A::new(x, journal);
+                let p = A::new_aligned(x, align, journal);
+                Pbox(Ptr::from_mut(p), 0)
+            }
+        }
+    }
+
+    /// Allocates memory on the persistent heap according to the placement
+    /// `hint` and then places `x` into it.
+    ///
+    /// It is useful for spreading the chunks of bandwidth-bound structures
+    /// across interleaved DIMMs. See [`AllocHint`] for more details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::default::*;
+    /// use corundum::alloc::AllocHint;
+    /// # type P = Allocator;
+    /// # let _p = P::open_no_root("foo.pool", O_CF).unwrap();
+    /// P::transaction(|j| {
+    ///     let chunks: Vec<_> = (0..4)
+    ///         .map(|i| Pbox::new_with_hint([0u64; 512], AllocHint::Interleave(i), j))
+    ///         .collect();
+    /// }).unwrap();
+    /// ```
+    ///
+    /// [`AllocHint`]: ../alloc/enum.AllocHint.html
+    pub fn new_with_hint(x: T, hint: AllocHint, journal: &Journal<A>) -> Pbox<T, A> {
+        if mem::size_of::<T>() == 0 {
+            Pbox(Ptr::dangling(), 0)
+        } else {
+            unsafe {
+                let p = A::new_with_hint(x, hint, journal);
                 Pbox(Ptr::from_mut(p), 0)
             }
         }
@@ -255,7 +338,7 @@ impl<T: PSafe, A: MemPool> Pbox<T, A> {
             "Pbox::initialize() cannot be used inside a transaction"
         );
         match boxed {
-            Some(_) => Err("already initialized".to_string()),
+            Some(_) => Err(crate::Error::AlreadyInitialized),
             None => if A::valid(boxed) {
                 unsafe {
                     let new = A::atomic_new(value);
@@ -270,7 +353,7 @@ impl<T: PSafe, A: MemPool> Pbox<T, A> {
                 }
                 Ok(())
             } else {
-                Err("The object is not in the PM".to_string())
+                Err(crate::Error::OutOfRange(boxed as *const _ as u64))
             }
         }
     }
@@ -423,6 +506,115 @@ impl<T: PSafe + ?Sized, A: MemPool> Pbox<T, A> {
     }
 }
 
+impl<T: PSafe, A: MemPool> Pbox<T, A> {
+    /// Moves the value out of the box, and deallocates the box when the
+    /// transaction commits
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::alloc::heap::*;
+    /// Heap::transaction(|j| {
+    ///     let five = Pbox::new(5, j);
+    ///     assert_eq!(Pbox::into_inner(five, j), 5);
+    /// }).unwrap();
+    /// ```
+    pub fn into_inner(b: Pbox<T, A>, _journal: &Journal<A>) -> T {
+        let mut b = mem::ManuallyDrop::new(b);
+        unsafe {
+            if b.0.is_dangling() {
+                ptr::read(NonNull::<T>::dangling().as_ptr())
+            } else {
+                let value = ptr::read(b.0.as_ref());
+                A::free(b.0.as_mut());
+                value
+            }
+        }
+    }
+
+    /// Converts the box into a [`Prc`] by moving the value without cloning
+    ///
+    /// The value is moved into a new allocation, because a `Prc` keeps its
+    /// counters alongside the value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::alloc::heap::*;
+    /// use corundum::prc::Prc;
+    ///
+    /// Heap::transaction(|j| {
+    ///     let five = Pbox::new(5, j);
+    ///     let five = Pbox::into_prc(five, j);
+    ///     assert_eq!(Prc::strong_count(&five), 1);
+    /// }).unwrap();
+    /// ```
+    ///
+    /// [`Prc`]: ../prc/struct.Prc.html
+    pub fn into_prc(b: Pbox<T, A>, journal: &Journal<A>) -> crate::prc::Prc<T, A> {
+        crate::prc::Prc::new(Pbox::into_inner(b, journal), journal)
+    }
+
+    /// Converts the box into a [`Parc`] by moving the value without cloning
+    ///
+    /// [`Parc`]: ../sync/struct.Parc.html
+    pub fn into_parc(b: Pbox<T, A>, journal: &Journal<A>) -> crate::sync::Parc<T, A> {
+        crate::sync::Parc::new(Pbox::into_inner(b, journal), journal)
+    }
+
+    /// Updates the value through a shadow copy instead of an undo log
+    ///
+    /// It allocates a copy of the value, lets `f` build the new version in
+    /// the copy, and points the box to it. Only the pointer is logged: if
+    /// the transaction rolls back, the box points to the old version again
+    /// and the copy is freed; otherwise, the old version is freed when the
+    /// transaction commits. The old version is not dropped, since its
+    /// resources are moved to the new version.
+    ///
+    /// It suits large objects, for which logging the old data before
+    /// modifying it would double the write traffic. The copy is flushed
+    /// once after `f` returns, so all changes should be made inside `f`; the
+    /// later changes in the same transaction are logged as usual. If the
+    /// value is already logged by the transaction, `f` updates it in place.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::default::*;
+    ///
+    /// type P = Allocator;
+    ///
+    /// let root = P::open::<PRefCell<Option<Pbox<[u64; 4096]>>>>("shadow.pool", O_CF).unwrap();
+    ///
+    /// P::transaction(|j| {
+    ///     let mut b = root.borrow_mut(j);
+    ///     let b = b.get_or_insert_with(|| Pbox::new([0; 4096], j));
+    ///     b.update_shadow(j, |new| {
+    ///         for (i, v) in new.iter_mut().enumerate() {
+    ///             *v = i as u64;
+    ///         }
+    ///     });
+    /// }).unwrap();
+    ///
+    /// assert_eq!(root.borrow().as_ref().unwrap()[4095], 4095);
+    /// ```
+    pub fn update_shadow<R, F: FnOnce(&mut T) -> R>(&mut self, journal: &Journal<A>, f: F) -> R {
+        if mem::size_of::<T>() == 0 || self.1 != 0 || !A::valid(&self.1) {
+            return f(self.0.as_mut());
+        }
+        unsafe {
+            let old = self.0.as_mut();
+            let shadow = A::new_copy(old, journal);
+            let res = f(shadow);
+            crate::ll::persist_obj(shadow, false);
+            self.0.create_log(journal, Notifier::None);
+            self.0 = Ptr::from_mut(shadow);
+            A::free(old);
+            res
+        }
+    }
+}
+
 unsafe impl<#[may_dangle] T: PSafe + ?Sized, A: MemPool> Drop for Pbox<T, A> {
     fn drop(&mut self) {
         unsafe {
@@ -654,3 +846,33 @@ impl<T: PSafe + ?Sized, A: MemPool> AsRef<T> for Pbox<T, A> {
 // }
 
 impl<T: PSafe + ?Sized, A: MemPool> Unpin for Pbox<T, A> {}
+
+/// Allocates the given values in persistent memory and wraps them in [`Pbox`]
+///
+/// The first argument is the journal object. For multiple values, a tuple of
+/// `Pbox`es is returned.
+///
+/// # Examples
+///
+/// ```
+/// # use corundum::alloc::heap::*;
+/// # use corundum::pbox;
+/// Heap::transaction(|j| {
+///     let b = pbox!(j, 5);
+///     let (x, y) = pbox!(j, 1, 2.5);
+///     assert_eq!(*b + *x, 6);
+///     assert_eq!(*y, 2.5);
+/// }).unwrap();
+/// ```
+///
+/// [`Pbox`]: ./struct.Pbox.html
+#[macro_export]
+macro_rules! pbox {
+    ($j:expr, $x:expr $(,)?) => {
+        $crate::Pbox::new($x, $j)
+    };
+    ($j:expr, $($x:expr),+ $(,)?) => {{
+        let __j = $j;
+        ($($crate::Pbox::new($x, __j)),+)
+    }};
+}
@@ -20,6 +20,9 @@ use crate::cell::TCell;
 /// Using [`get()`](#method.get) function, you can obtain a copy of data. To 
 /// update data, you can use [`set()`](#method.set) which writes a log to the
 /// given journal before mutation.
+/// Values which are not [`Copy`] can be moved in and out of the cell with
+/// [`replace()`](#method.replace), [`take()`](#method.take), and
+/// [`update_with()`](#method.update_with).
 ///
 /// It does not implement [`Sync`], so it is not possible to share `PCell`
 /// between threads. To provide thread-safe interior mutability, use
@@ -29,6 +32,7 @@ use crate::cell::TCell;
 /// module.
 ///
 /// [`Sync`]: std::marker::Sync
+/// [`Copy`]: std::marker::Copy
 /// [`PMutex`]: ../sync/mutex/struct.PMutex.html
 /// [`PCell`]: ../alloc/default/type.PCell.html
 /// 
@@ -200,8 +204,12 @@ impl<T: PSafe, A: MemPool> PCell<T, A> {
         if ptr::eq(this, that) {
             return;
         }
-        self.create_log(journal);
-        other.create_log(journal);
+        unsafe {
+            journal.batch(|| {
+                self.create_log(journal);
+                other.create_log(journal);
+            });
+        }
 
         // SAFETY: This can be risky if called from separate threads, but `PCell`
         // is `!Sync` so this won't happen. This also won't invalidate any
@@ -343,6 +351,40 @@ impl<T: PSafe, A: MemPool> PCell<T, A> {
         self.set(new, journal);
         new
     }
+
+    /// Updates the contained value using a function which takes the value by
+    /// move. Unlike [`update()`](#method.update), it does not require `T` to
+    /// be [`Copy`].
+    ///
+    /// The cell is logged first, and then its value is moved into `f`. If `f`
+    /// panics, the transaction rolls back and the old value is restored; the
+    /// memory that the old value owned is not dropped. `f` should not access
+    /// the cell itself.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::alloc::heap::*;
+    /// use corundum::boxed::Pbox;
+    ///
+    /// Heap::transaction(|j| {
+    ///     let c = Pbox::new(PCell::<Option<Pbox<i32>>>::default(), j);
+    ///     c.update_with(|v| v.or_else(|| Some(Pbox::new(1, j))), j);
+    ///     c.update_with(|v| v.map(|b| Pbox::new(*b + 1, j)), j);
+    ///
+    ///     assert_eq!(c.take(j).map(|b| *b), Some(2));
+    /// }).unwrap();
+    /// ```
+    ///
+    /// [`Copy`]: std::marker::Copy
+    #[track_caller]
+    pub fn update_with<F: FnOnce(T) -> T>(&self, f: F, journal: &Journal<A>) {
+        self.create_log(journal);
+        let slot = unsafe { self.as_mut() as *mut T };
+        let _keep = crate::stm::fresh::Keep::enter();
+        let new = f(unsafe { ptr::read(slot) });
+        unsafe { ptr::write(slot, new); }
+    }
 }
 
 impl<T: PSafe + ?Sized, A: MemPool> PCell<T, A> {
@@ -507,4 +549,35 @@ impl<T: PSafe + Logger<A> + Copy, A: MemPool> Clone for PCell<T, A> {
             unsafe { PCell::new((*self.value.get()).1.clone()) }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use crate::default::*;
+
+    type P = Allocator;
+    type Cell = PCell<Option<Pbox<i32, P>>, P>;
+
+    #[test]
+    fn update_with_rollback() {
+        let _pool = P::open_no_root("update_with.pool", O_CF).unwrap();
+        let off = P::transaction(|j| unsafe {
+            let c = Pbox::new(Cell::new(Some(Pbox::new(1, j))), j);
+            P::off_unchecked(Pbox::into_raw(c))
+        }).unwrap();
+        let c = unsafe { P::get_unchecked::<Cell>(off) };
+        let inner = unsafe { P::off_unchecked(&**c.as_mut().as_ref().unwrap()) };
+
+        // The moved value is dropped while unwinding, but its block is kept
+        let res = P::transaction(|j| {
+            c.update_with(|v| { let _v = v; panic!("abort") }, j);
+        });
+        assert!(res.is_err());
+        assert!(P::allocated(inner, std::mem::size_of::<i32>()));
+
+        P::transaction(|j| {
+            c.update_with(|v| v.map(|b| Pbox::new(*b + 1, j)), j);
+            assert_eq!(c.take(j).map(|b| *b), Some(2));
+        }).unwrap();
+    }
+}
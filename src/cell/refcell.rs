@@ -2,6 +2,7 @@ use crate::ptr::{LogNonNull,NonNull};
 use crate::convert::PFrom;
 use crate::alloc::MemPool;
 use crate::stm::Journal;
+use crate::stm::isolation;
 use crate::*;
 use std::cell::UnsafeCell;
 use std::fmt::{self, Debug, Display};
@@ -33,6 +34,12 @@ use crate::cell::VCell;
 /// * Borrowing the value mutably twice
 /// * Borrowing the value immutably while it was already borrowed mutably
 ///
+/// The non-panicking variants, [`try_borrow()`](#method.try_borrow) and
+/// [`try_borrow_mut()`](#method.try_borrow_mut), return an error instead.
+/// A borrow can be narrowed down to a component of the value (e.g., a field)
+/// using [`Ref::map`](./struct.Ref.html#method.map) and
+/// [`RefMut::map`](./struct.RefMut.html#method.map).
+///
 /// It does not implement [`Sync`], so it is not possible to share `PRefCell`
 /// between threads. To provide thread-safe interior mutability, use
 /// [`PMutex`].
@@ -99,10 +106,24 @@ impl<T: PSafe, A: MemPool> PRefCell<T, A> {
         }
     }
 
+    /// Consumes the `PRefCell`, returning the wrapped value.
+    pub(crate) fn into_inner(self) -> T {
+
+        #[cfg(any(feature = "use_pspd", feature = "use_vspd"))] {
+            self.value.into_inner()
+        }
+
+        #[cfg(not(any(feature = "use_pspd", feature = "use_vspd")))] {
+            self.value.into_inner().1
+        }
+    }
+
     /// Replaces the wrapped value with a new one, returning the old value,
     /// without deinitializing either one.
     ///
     /// This function corresponds to [`std::mem::replace`](../mem/fn.replace.html).
+    /// The old value is moved out without cloning, and the cell is logged
+    /// once.
     ///
     /// # Panics
     ///
@@ -157,6 +178,8 @@ impl<T: PSafe, A: MemPool> PRefCell<T, A> {
     /// without deinitializing either one.
     ///
     /// This function corresponds to [`std::mem::swap`](../mem/fn.swap.html).
+    /// The values are moved without cloning, and each cell is logged once.
+    /// Swapping a cell with itself does nothing.
     ///
     /// # Panics
     ///
@@ -179,6 +202,9 @@ impl<T: PSafe, A: MemPool> PRefCell<T, A> {
     /// ```
     #[inline]
     pub fn swap(&self, other: &Self, j: &Journal<A>) {
+        if std::ptr::eq(self, other) {
+            return;
+        }
         std::mem::swap(&mut *self.borrow_mut(j), &mut *other.borrow_mut(j))
     }
 }
@@ -333,11 +359,45 @@ impl<T: PSafe + ?Sized, A: MemPool> PRefCell<T, A> {
         #[cfg(not(feature = "no_dyn_borrow_checking"))] {
             let borrow = self.borrow.as_mut();
             assert!(*borrow <= 0, "Value was already mutably borrowed ({})", *borrow);
-            *borrow = -1;
+            assert!(*borrow > i8::MIN, "Too many immutable borrows");
+            *borrow -= 1;
         }
+        isolation::read_cell::<A>(self as *const Self as *const u8);
         Ref { value: self, phantom: PhantomData }
     }
 
+    #[inline]
+    /// Immutably borrows the wrapped value, returning an error if the value
+    /// is currently mutably borrowed.
+    ///
+    /// This is the non-panicking variant of [`borrow`](#method.borrow).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::alloc::heap::*;
+    ///
+    /// Heap::transaction(|j| {
+    ///     let cell = Pbox::new(PRefCell::new(5), j);
+    ///     {
+    ///         let _m = cell.borrow_mut(j);
+    ///         assert!(cell.try_borrow().is_err());
+    ///     }
+    ///     assert_eq!(*cell.try_borrow().unwrap(), 5);
+    /// }).unwrap();
+    /// ```
+    pub fn try_borrow(&self) -> Result<Ref<'_, T, A>, BorrowError> {
+        #[cfg(not(feature = "no_dyn_borrow_checking"))] {
+            let borrow = self.borrow.as_mut();
+            if *borrow > 0 || *borrow == i8::MIN {
+                return Err(BorrowError { _private: () });
+            }
+            *borrow -= 1;
+        }
+        isolation::read_cell::<A>(self as *const Self as *const u8);
+        Ok(Ref { value: self, phantom: PhantomData })
+    }
+
     #[inline]
     /// Returns a clone of the underlying data
     /// 
@@ -356,6 +416,7 @@ impl<T: PSafe + ?Sized, A: MemPool> PRefCell<T, A> {
     where
         T: std::clone::Clone,
     {
+        isolation::read_cell::<A>(self as *const Self as *const u8);
         self.as_ref().clone()
     }
 
@@ -475,6 +536,7 @@ impl<T: PSafe, A: MemPool> PRefCell<T, A> {
             assert!(*borrow == 0, "Value was already mutably borrowed ({})", *borrow);
             *borrow = 1;
         }
+        isolation::write_cell::<A>(self as *const Self as *const u8);
         RefMut {
             value: unsafe { &mut *(self as *const Self as *mut Self) },
             journal,
@@ -482,6 +544,45 @@ impl<T: PSafe, A: MemPool> PRefCell<T, A> {
         }
     }
 
+    /// Mutably borrows the wrapped value, returning an error if the value is
+    /// currently borrowed.
+    ///
+    /// This is the non-panicking variant of [`borrow_mut`](#method.borrow_mut).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::alloc::heap::*;
+    ///
+    /// Heap::transaction(|j| {
+    ///     let cell = Pbox::new(PRefCell::new(5), j);
+    ///     {
+    ///         let _r = cell.borrow();
+    ///         assert!(cell.try_borrow_mut(j).is_err());
+    ///     }
+    ///     *cell.try_borrow_mut(j).unwrap() = 10;
+    ///     assert_eq!(*cell.borrow(), 10);
+    /// }).unwrap();
+    /// ```
+    #[inline]
+    pub fn try_borrow_mut<'a>(&'a self, journal: &'a Journal<A>)
+        -> Result<RefMut<'a, T, A>, BorrowMutError>
+    {
+        #[cfg(not(feature = "no_dyn_borrow_checking"))] {
+            let borrow = self.borrow.as_mut();
+            if *borrow != 0 {
+                return Err(BorrowMutError { _private: () });
+            }
+            *borrow = 1;
+        }
+        isolation::write_cell::<A>(self as *const Self as *const u8);
+        Ok(RefMut {
+            value: unsafe { &mut *(self as *const Self as *mut Self) },
+            journal,
+            phantom: PhantomData
+        })
+    }
+
     /// Returns a `LogNonNull` pointer to the data
     /// 
     /// # Safety
@@ -491,6 +592,7 @@ impl<T: PSafe, A: MemPool> PRefCell<T, A> {
     /// recommended to use this function without necessary manual checks.
     /// 
     pub unsafe fn as_non_null_mut(&self, journal: &Journal<A>) -> LogNonNull<T, A> {
+        isolation::write_cell::<A>(self as *const Self as *const u8);
         let inner = &mut *self.value.get();
         #[cfg(any(feature = "use_pspd", feature = "use_vspd"))] {
             LogNonNull::new_unchecked(inner, journal)
@@ -572,6 +674,44 @@ impl<T: PSafe + Ord + ?Sized, A: MemPool> Ord for PRefCell<T, A> {
     }
 }
 
+/// An error returned by [`PRefCell::try_borrow`](./struct.PRefCell.html#method.try_borrow)
+pub struct BorrowError {
+    _private: (),
+}
+
+impl Debug for BorrowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BorrowError").finish()
+    }
+}
+
+impl Display for BorrowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt("already mutably borrowed", f)
+    }
+}
+
+impl std::error::Error for BorrowError {}
+
+/// An error returned by [`PRefCell::try_borrow_mut`](./struct.PRefCell.html#method.try_borrow_mut)
+pub struct BorrowMutError {
+    _private: (),
+}
+
+impl Debug for BorrowMutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BorrowMutError").finish()
+    }
+}
+
+impl Display for BorrowMutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt("already borrowed", f)
+    }
+}
+
+impl std::error::Error for BorrowMutError {}
+
 pub struct Ref<'b, T: 'b + PSafe + ?Sized, A: MemPool> {
     value: *const PRefCell<T, A>,
     phantom: PhantomData<&'b T>
@@ -633,6 +773,41 @@ impl<'b, T: PSafe + ?Sized, A: MemPool> Ref<'b, T, A> {
         // from the original cell.
         unsafe {(*orig.value).as_ref()}
     }
+
+    /// Makes a new [`MappedRef`] for a component of the borrowed data, e.g.,
+    /// a field of a struct.
+    ///
+    /// The `PRefCell` remains immutably borrowed until the returned guard
+    /// goes out of scope.
+    ///
+    /// This is an associated function that needs to be used as
+    /// `Ref::map(...)`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::alloc::heap::*;
+    ///
+    /// Heap::transaction(|j| {
+    ///     let cell = Pbox::new(PRefCell::new((5, 'b')), j);
+    ///     let b1 = cell.borrow();
+    ///     let b2 = PRef::map(b1, |t| &t.0);
+    ///     assert_eq!(*b2, 5);
+    /// }).unwrap();
+    /// ```
+    ///
+    /// [`MappedRef`]: ./struct.MappedRef.html
+    #[inline]
+    pub fn map<U: ?Sized, F: FnOnce(&T) -> &U>(orig: Ref<'b, T, A>, f: F) -> MappedRef<'b, U, A> {
+        let cell: &'b PRefCell<T, A> = unsafe { &*orig.value };
+        std::mem::forget(orig);
+        MappedRef {
+            value: f(cell.as_ref()),
+            #[cfg(not(feature = "no_dyn_borrow_checking"))]
+            borrow: &cell.borrow,
+            phantom: PhantomData
+        }
+    }
 }
 
 #[cfg(feature = "refcell_lifetime_change")]
@@ -721,11 +896,56 @@ impl<T: PSafe + ?Sized, A: MemPool> RefMut<'_, T, A> {
     }
 }
 
-impl<T: PSafe + ?Sized, A: MemPool> RefMut<'_, T, A> {
+impl<'b, T: PSafe + ?Sized, A: MemPool> RefMut<'b, T, A> {
     /// Converts `RefMut` into a mutable reference within the same lifetime
     pub fn into_mut<'a>(r: RefMut<'a, T, A>) -> &'a mut T {
         unsafe { (*r.value).as_mut() }
     }
+
+    /// Makes a new [`MappedRefMut`] for a component of the borrowed data,
+    /// e.g., a field of a struct.
+    ///
+    /// Since the component may be modified through the returned guard, the
+    /// whole `PRefCell` is logged before calling `f`, if it was not already
+    /// logged. For a read-only projection, use [`Ref::map`] instead. The
+    /// `PRefCell` remains mutably borrowed until the returned guard goes out
+    /// of scope.
+    ///
+    /// This is an associated function that needs to be used as
+    /// `RefMut::map(...)`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::alloc::heap::*;
+    ///
+    /// Heap::transaction(|j| {
+    ///     let cell = Pbox::new(PRefCell::new((5, 'b')), j);
+    ///     {
+    ///         let b1 = cell.borrow_mut(j);
+    ///         let mut b2 = PRefMut::map(b1, |t| &mut t.0);
+    ///         *b2 = 42;
+    ///     }
+    ///     assert_eq!(*cell.borrow(), (42, 'b'));
+    /// }).unwrap();
+    /// ```
+    ///
+    /// [`MappedRefMut`]: ./struct.MappedRefMut.html
+    /// [`Ref::map`]: ./struct.Ref.html#method.map
+    #[inline]
+    pub fn map<U: ?Sized, F: FnOnce(&mut T) -> &mut U>(orig: RefMut<'b, T, A>, f: F) -> MappedRefMut<'b, U, A> {
+        let cell: &'b mut PRefCell<T, A> = unsafe { &mut *orig.value };
+        let journal: &'b Journal<A> = unsafe { &*orig.journal };
+        std::mem::forget(orig);
+        #[cfg(not(feature = "no_dyn_borrow_checking"))]
+        let borrow: *const VCell<i8, A> = &cell.borrow;
+        MappedRefMut {
+            value: f(cell.get_mut(journal)),
+            #[cfg(not(feature = "no_dyn_borrow_checking"))]
+            borrow,
+            phantom: PhantomData
+        }
+    }
 }
 
 impl<T: PSafe + ?Sized, A: MemPool> Deref for RefMut<'_, T, A> {
@@ -765,3 +985,151 @@ impl<T: PSafe + ?Sized, A: MemPool> Drop for RefMut<'_, T, A> {
         }
     }
 }
+
+/// A wrapper type for an immutably borrowed component of a value in a
+/// [`PRefCell`]
+///
+/// It is obtained from [`Ref::map`].
+///
+/// [`PRefCell`]: ./struct.PRefCell.html
+/// [`Ref::map`]: ./struct.Ref.html#method.map
+pub struct MappedRef<'b, T: 'b + ?Sized, A: MemPool> {
+    value: *const T,
+
+    #[cfg(not(feature = "no_dyn_borrow_checking"))]
+    borrow: *const VCell<i8, A>,
+
+    phantom: PhantomData<(&'b T, A)>
+}
+
+impl<T: ?Sized, A: MemPool> !TxOutSafe for MappedRef<'_, T, A> {}
+impl<T: ?Sized, A: MemPool> !Send for MappedRef<'_, T, A> {}
+impl<T: ?Sized, A: MemPool> !Sync for MappedRef<'_, T, A> {}
+
+impl<'b, T: ?Sized, A: MemPool> MappedRef<'b, T, A> {
+    /// Makes a new `MappedRef` for a component of the borrowed data
+    ///
+    /// This is an associated function that needs to be used as
+    /// `MappedRef::map(...)`.
+    #[inline]
+    pub fn map<U: ?Sized, F: FnOnce(&T) -> &U>(orig: MappedRef<'b, T, A>, f: F) -> MappedRef<'b, U, A> {
+        let value: &'b T = unsafe { &*orig.value };
+        #[cfg(not(feature = "no_dyn_borrow_checking"))]
+        let borrow = orig.borrow;
+        std::mem::forget(orig);
+        MappedRef {
+            value: f(value),
+            #[cfg(not(feature = "no_dyn_borrow_checking"))]
+            borrow,
+            phantom: PhantomData
+        }
+    }
+}
+
+impl<T: ?Sized, A: MemPool> Deref for MappedRef<'_, T, A> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        unsafe { &*self.value }
+    }
+}
+
+impl<T: fmt::Display + ?Sized, A: MemPool> fmt::Display for MappedRef<'_, T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<T: fmt::Debug + ?Sized, A: MemPool> fmt::Debug for MappedRef<'_, T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized, A: MemPool> Drop for MappedRef<'_, T, A> {
+    fn drop(&mut self) {
+        #[cfg(not(feature = "no_dyn_borrow_checking"))] {
+            let borrow = unsafe { (*self.borrow).as_mut() };
+            *borrow += 1;
+        }
+    }
+}
+
+/// A wrapper type for a mutably borrowed component of a value in a
+/// [`PRefCell`]
+///
+/// It is obtained from [`RefMut::map`]. The `PRefCell` is already logged when
+/// this guard is created, so dereferencing it mutably does not take any log.
+///
+/// [`PRefCell`]: ./struct.PRefCell.html
+/// [`RefMut::map`]: ./struct.RefMut.html#method.map
+pub struct MappedRefMut<'b, T: 'b + ?Sized, A: MemPool> {
+    value: *mut T,
+
+    #[cfg(not(feature = "no_dyn_borrow_checking"))]
+    borrow: *const VCell<i8, A>,
+
+    phantom: PhantomData<(&'b mut T, A)>
+}
+
+impl<T: ?Sized, A: MemPool> !TxOutSafe for MappedRefMut<'_, T, A> {}
+impl<T: ?Sized, A: MemPool> !Send for MappedRefMut<'_, T, A> {}
+impl<T: ?Sized, A: MemPool> !Sync for MappedRefMut<'_, T, A> {}
+
+impl<'b, T: ?Sized, A: MemPool> MappedRefMut<'b, T, A> {
+    /// Makes a new `MappedRefMut` for a component of the borrowed data
+    ///
+    /// This is an associated function that needs to be used as
+    /// `MappedRefMut::map(...)`.
+    #[inline]
+    pub fn map<U: ?Sized, F: FnOnce(&mut T) -> &mut U>(orig: MappedRefMut<'b, T, A>, f: F) -> MappedRefMut<'b, U, A> {
+        let value: &'b mut T = unsafe { &mut *orig.value };
+        #[cfg(not(feature = "no_dyn_borrow_checking"))]
+        let borrow = orig.borrow;
+        std::mem::forget(orig);
+        MappedRefMut {
+            value: f(value),
+            #[cfg(not(feature = "no_dyn_borrow_checking"))]
+            borrow,
+            phantom: PhantomData
+        }
+    }
+}
+
+impl<T: ?Sized, A: MemPool> Deref for MappedRefMut<'_, T, A> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        unsafe { &*self.value }
+    }
+}
+
+impl<T: ?Sized, A: MemPool> DerefMut for MappedRefMut<'_, T, A> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.value }
+    }
+}
+
+impl<T: fmt::Display + ?Sized, A: MemPool> fmt::Display for MappedRefMut<'_, T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<T: fmt::Debug + ?Sized, A: MemPool> fmt::Debug for MappedRefMut<'_, T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized, A: MemPool> Drop for MappedRefMut<'_, T, A> {
+    fn drop(&mut self) {
+        #[cfg(not(feature = "no_dyn_borrow_checking"))] {
+            let borrow = unsafe { (*self.borrow).as_mut() };
+            *borrow -= 1;
+        }
+    }
+}
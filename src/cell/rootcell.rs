@@ -95,11 +95,90 @@ impl<T: Display + PSafe, A: MemPool> Display for RootCell<'_, T, A> {
 /// initialize the root object for the first time. Every type implementing
 /// [`Default`] is already implementing `RootObj`, by default.
 /// 
+/// `RootObj` can be derived using `#[derive(Root)]`. A struct is initialized
+/// field by field, and an enum is initialized to its variant which is marked
+/// with `#[default]`. A type parameter is bounded by `RootObj` only if it is
+/// used in an initialized field.
+/// 
+/// # Examples
+/// 
+/// ```
+/// use corundum::default::*;
+/// 
+/// type P = Allocator;
+/// 
+/// #[derive(Root)]
+/// enum State<T> where T: PSafe {
+///     #[default]
+///     Idle,
+///     Running(T),
+///     Done { code: i32 }
+/// }
+/// 
+/// #[derive(Root)]
+/// struct Machine {
+///     state: PRefCell<State<u64>>
+/// }
+/// 
+/// let root = P::open::<Machine>("foo.pool", O_CF).unwrap();
+/// P::transaction(|j| {
+///     let mut state = root.state.borrow_mut(j);
+///     assert!(matches!(*state, State::Idle));
+///     *state = State::Running(10);
+/// }).unwrap();
+/// ```
+/// 
 /// [`Default`]: std::default::Default
 pub trait RootObj<A: MemPool> {
     fn init(journal: &Journal<A>) -> Self;
 }
 
+/// Creates the root object from user arguments
+/// 
+/// [`RootObj`] cannot receive runtime configuration, such as the capacity of
+/// a hash map or the number of shards. A root type implementing this trait
+/// can be created by [`MemPoolTraits::open_with()`] which passes the given
+/// arguments to `init_with` the first time the root object is created. When
+/// the root object already exists, the arguments are ignored.
+/// 
+/// # Examples
+/// 
+/// ```
+/// use corundum::default::*;
+/// use corundum::cell::RootObjWith;
+/// 
+/// type P = Allocator;
+/// 
+/// struct Shards {
+///     shards: PVec<PRefCell<PVec<u64>>>
+/// }
+/// 
+/// impl RootObjWith<P> for Shards {
+///     type Args = usize;
+/// 
+///     fn init_with(count: usize, j: &Journal) -> Self {
+///         let mut shards = PVec::with_capacity(count, j);
+///         for _ in 0..count {
+///             shards.push(PRefCell::new(PVec::new()), j);
+///         }
+///         Self { shards }
+///     }
+/// }
+/// 
+/// let root = P::open_with::<Shards>("foo.pool", O_CF, 4).unwrap();
+/// assert_eq!(root.shards.len(), 4);
+/// ```
+/// 
+/// [`RootObj`]: ./trait.RootObj.html
+/// [`MemPoolTraits::open_with()`]: ../alloc/trait.MemPoolTraits.html#method.open_with
+pub trait RootObjWith<A: MemPool>: Sized {
+    /// The type of the arguments
+    type Args;
+
+    /// Creates the root object using `args`
+    fn init_with(args: Self::Args, journal: &Journal<A>) -> Self;
+}
+
 impl<T: Default, A: MemPool> RootObj<A> for T {
     default fn init(_journal: &Journal<A>) -> Self {
         T::default()
@@ -100,6 +100,7 @@ impl<T: Default + VSafe, A: MemPool> TCell<T, A> {
     }
 
     fn force(&mut self) -> &mut T {
+        crate::stm::wcheck::allow(self as *const Self as *const u8 as u64, size_of_val(self));
         let gen = A::gen();
         unsafe {
             if let Some((j, _)) = Journal::<A>::current(false) {
@@ -79,6 +79,7 @@ impl<T: Default + VSafe, A: MemPool> VCell<T, A> {
     }
 
     fn force(&mut self) -> &mut T {
+        crate::stm::wcheck::allow(self as *const Self as *const u8 as u64, size_of_val(self));
         unsafe {
             let gen = A::gen();
             if self.gen != gen {
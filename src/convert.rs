@@ -8,4 +8,41 @@ use crate::alloc::MemPool;
 /// [`Journal`]: ../stm/journal/struct.Journal.html
 pub trait PFrom<T, A: MemPool> {
     fn pfrom(_: T, j: &Journal<A>) -> Self;
-}
\ No newline at end of file
+}
+
+/// An equivalent to [`FromIterator`] for persistent memory which requires a
+/// [`Journal`] to operate
+///
+/// [`FromIterator`]: std::iter::FromIterator
+/// [`Journal`]: ../stm/journal/struct.Journal.html
+pub trait PFromIterator<T, A: MemPool>: Sized {
+    fn pfrom_iter<I: IntoIterator<Item = T>>(iter: I, j: &Journal<A>) -> Self;
+}
+
+/// Builds persistent collections from volatile iterators
+///
+/// It is implemented for all iterators, similar to [`Iterator::collect`].
+///
+/// # Examples
+///
+/// ```
+/// # use corundum::alloc::heap::*;
+/// use corundum::{PVec, PCollect};
+/// use corundum::stl::PBPlusTree;
+///
+/// Heap::transaction(|j| {
+///     let v: PVec<i32, Heap> = (1..=3).collect_pinto(j);
+///     assert_eq!(v.as_slice(), [1, 2, 3]);
+///
+///     let m: PBPlusTree<i32, i32, Heap> = v.iter().map(|x| (*x, x * x)).collect_pinto(j);
+///     assert_eq!(m.values().copied().collect::<Vec<_>>(), vec![1, 4, 9]);
+/// }).unwrap();
+/// ```
+pub trait PCollect: Iterator + Sized {
+    /// Transforms the iterator into a persistent collection
+    fn collect_pinto<C: PFromIterator<Self::Item, A>, A: MemPool>(self, j: &Journal<A>) -> C {
+        C::pfrom_iter(self, j)
+    }
+}
+
+impl<I: Iterator> PCollect for I {}
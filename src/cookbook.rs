@@ -0,0 +1,326 @@
+//! Recipes for common persistent programming patterns
+//!
+//! Every item in this module implements one of the recommended patterns for
+//! building data structures with Corundum. They are small enough to be copied
+//! and adapted, and their examples are compiled and run as doc tests, so they
+//! stay in sync with the API.
+//!
+//! * [Hybrid DRAM index](#hybrid-dram-index)
+//! * [Producer/consumer](#producerconsumer)
+//! * [Schema migration](#schema-migration)
+//! * [Weak back-pointers](#weak-back-pointers)
+//!
+//! # Hybrid DRAM index
+//!
+//! Persistent memory is slower than DRAM, and every update to a persistent
+//! index costs logs and flushes. If an index can be derived from the data, it
+//! is cheaper to keep only the data in the pool, and to rebuild the index in
+//! DRAM every time the pool is opened. See [`build_index()`] and
+//! [`lookup()`].
+//!
+//! # Producer/consumer
+//!
+//! Threads share a persistent object through a [`Parc`]. Each thread takes a
+//! [`VWeak`] to it, and promotes it in its own transactions. A [`WorkQueue`]
+//! is a FIFO queue protected by a [`PMutex`]; an item is either in the queue
+//! or taken by a committed transaction of a consumer, even if the program
+//! crashes in between.
+//!
+//! # Schema migration
+//!
+//! The layout of a root object cannot change in place. Instead, the root is
+//! a [`Schema`] from the first version, and [`migrate()`] converts the old
+//! layout to the new one in a transaction when the pool is opened.
+//!
+//! # Weak back-pointers
+//!
+//! Reference-counted cycles are never deallocated, and leak persistent
+//! memory. In a tree, children are owned by their parents through [`Prc`]s,
+//! and a [`Node`] refers to its parent through a [`prc::Weak`] pointer. See
+//! [`add_child()`] and [`parent_of()`].
+//!
+//! [`build_index()`]: ./fn.build_index.html
+//! [`lookup()`]: ./fn.lookup.html
+//! [`Parc`]: ../sync/struct.Parc.html
+//! [`VWeak`]: ../sync/struct.VWeak.html
+//! [`WorkQueue`]: ./struct.WorkQueue.html
+//! [`PMutex`]: ../sync/struct.PMutex.html
+//! [`Schema`]: ./enum.Schema.html
+//! [`migrate()`]: ./fn.migrate.html
+//! [`Prc`]: ../prc/struct.Prc.html
+//! [`Node`]: ./struct.Node.html
+//! [`prc::Weak`]: ../prc/struct.Weak.html
+//! [`add_child()`]: ./fn.add_child.html
+//! [`parent_of()`]: ./fn.parent_of.html
+
+use crate::alloc::MemPool;
+use crate::prc::{Prc, Weak};
+use crate::stm::Journal;
+use crate::sync::PMutex;
+use crate::vec::Vec as PVec;
+use crate::*;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Builds a volatile index of `records` by the given `key`
+///
+/// The index maps every key to the position of its record. It is not
+/// persistent, so it should be built every time the pool is opened, and
+/// updated alongside the records.
+///
+/// # Examples
+///
+/// ```
+/// use corundum::default::*;
+/// use corundum::cookbook;
+///
+/// type P = Allocator;
+///
+/// let root = P::open::<PRefCell<PVec<(u64, u64)>>>("foo.pool", O_CF).unwrap();
+///
+/// P::transaction(|j| {
+///     let mut records = root.borrow_mut(j);
+///     records.push((3, 30), j);
+///     records.push((1, 10), j);
+/// }).unwrap();
+///
+/// // After opening the pool
+/// let records = root.borrow();
+/// let index = cookbook::build_index(&records, |r| r.0);
+/// assert_eq!(cookbook::lookup(&records, &index, &1), Some(&(1, 10)));
+/// assert_eq!(cookbook::lookup(&records, &index, &2), None);
+/// ```
+pub fn build_index<T, K: Hash + Eq>(records: &[T], key: impl Fn(&T) -> K) -> HashMap<K, usize> {
+    records.iter().enumerate().map(|(i, r)| (key(r), i)).collect()
+}
+
+/// Finds the record of `key` using an index built by
+/// [`build_index()`](./fn.build_index.html)
+pub fn lookup<'a, T, K: Hash + Eq>(
+    records: &'a [T],
+    index: &HashMap<K, usize>,
+    key: &K
+) -> Option<&'a T> {
+    index.get(key).and_then(|i| records.get(*i))
+}
+
+/// A persistent FIFO queue shared by producers and consumers
+///
+/// Items are appended to a vector, and a head index points to the oldest
+/// item. The vector is released when the queue is drained.
+///
+/// # Examples
+///
+/// ```
+/// use corundum::default::*;
+/// use corundum::cookbook::WorkQueue;
+/// use std::thread;
+///
+/// type P = Allocator;
+///
+/// let queue = P::open::<Parc<WorkQueue<u64, P>>>("foo.pool", O_CF).unwrap();
+///
+/// let producer = {
+///     let queue = queue.demote();
+///     thread::spawn(move || {
+///         P::transaction(|j| {
+///             if let Some(queue) = queue.promote(j) {
+///                 for i in 0..10 {
+///                     queue.push(i, j);
+///                 }
+///             }
+///         }).unwrap();
+///     })
+/// };
+/// producer.join().unwrap();
+///
+/// let items = P::transaction(|j| {
+///     let mut items = vec![];
+///     while let Some(item) = queue.pop(j) {
+///         items.push(item);
+///     }
+///     items
+/// }).unwrap();
+/// assert_eq!(items, (0..10).collect::<Vec<u64>>());
+/// ```
+pub struct WorkQueue<T: PSafe + Copy, A: MemPool> {
+    inner: PMutex<(PVec<T, A>, usize), A>,
+}
+
+impl<T: PSafe + Copy, A: MemPool> WorkQueue<T, A> {
+    /// Creates an empty queue
+    pub fn new() -> Self {
+        Self { inner: PMutex::new((PVec::new(), 0)) }
+    }
+
+    /// Appends `item` to the queue
+    pub fn push(&self, item: T, journal: &Journal<A>) {
+        self.inner.lock(journal).0.push(item, journal);
+    }
+
+    /// Takes the oldest item, if any
+    pub fn pop(&self, journal: &Journal<A>) -> Option<T> {
+        let mut inner = self.inner.lock(journal);
+        let (items, head) = &mut *inner;
+        let item = *items.get(*head)?;
+        *head += 1;
+        if *head == items.len() {
+            *items = PVec::new();
+            *head = 0;
+        }
+        Some(item)
+    }
+
+    /// Returns the number of items in the queue
+    pub fn len(&self, journal: &Journal<A>) -> usize {
+        let inner = self.inner.lock(journal);
+        inner.0.len() - inner.1
+    }
+}
+
+impl<T: PSafe + Copy, A: MemPool> Default for WorkQueue<T, A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A versioned root object
+///
+/// A new pool starts with the latest version.
+pub enum Schema<Old, New> {
+    /// The previous layout
+    V1(Old),
+
+    /// The current layout
+    V2(New),
+}
+
+impl<Old, New: RootObj<A>, A: MemPool> RootObj<A> for Schema<Old, New> {
+    fn init(journal: &Journal<A>) -> Self {
+        Schema::V2(New::init(journal))
+    }
+}
+
+/// Converts the root object to the new layout using `upgrade`, if it is in
+/// the old layout
+///
+/// It returns `true` if the root object was migrated. The old object is
+/// dropped when the transaction commits, and it is left intact if the
+/// transaction fails.
+///
+/// # Examples
+///
+/// ```
+/// use corundum::default::*;
+/// use corundum::cookbook::{self, Schema};
+///
+/// type P = Allocator;
+///
+/// struct Point { x: i32, y: i32 }
+///
+/// #[derive(Default)]
+/// struct Point3 { x: i32, y: i32, z: i32 }
+///
+/// let root = P::open::<PRefCell<Schema<Point, Point3>>>("foo.pool", O_CF).unwrap();
+///
+/// P::transaction(|j| {
+///     // As if the pool was created by the previous version
+///     *root.borrow_mut(j) = Schema::V1(Point { x: 1, y: 2 });
+///
+///     assert!(cookbook::migrate(&root, |p, _| Point3 { x: p.x, y: p.y, z: 0 }, j));
+///     assert!(!cookbook::migrate(&root, |p, _| Point3 { x: p.x, y: p.y, z: 0 }, j));
+///     if let Schema::V2(p) = &*root.borrow() {
+///         assert_eq!((p.x, p.y, p.z), (1, 2, 0));
+///     }
+/// }).unwrap();
+/// ```
+pub fn migrate<Old: PSafe, New: PSafe, A: MemPool>(
+    root: &PRefCell<Schema<Old, New>, A>,
+    upgrade: impl FnOnce(&Old, &Journal<A>) -> New,
+    journal: &Journal<A>
+) -> bool {
+    let mut root = root.borrow_mut(journal);
+    let new = match &*root {
+        Schema::V1(old) => upgrade(old, journal),
+        Schema::V2(_) => return false,
+    };
+    *root = Schema::V2(new);
+    true
+}
+
+/// A tree node which owns its children and weakly refers to its parent
+pub struct Node<A: MemPool> {
+    /// The value of the node
+    pub value: u64,
+
+    /// The parent of the node
+    pub parent: PRefCell<Weak<Node<A>, A>, A>,
+
+    /// The children of the node
+    pub children: PRefCell<PVec<Prc<Node<A>, A>, A>, A>,
+}
+
+impl<A: MemPool> Node<A> {
+    /// Creates a node without parent and children
+    pub fn new(value: u64) -> Self {
+        Self {
+            value,
+            parent: PRefCell::new(Weak::new()),
+            children: PRefCell::new(PVec::new()),
+        }
+    }
+}
+
+impl<A: MemPool> RootObj<A> for Node<A> {
+    fn init(_journal: &Journal<A>) -> Self {
+        Self::new(0)
+    }
+}
+
+/// Creates a new child of `parent` with the given `value`
+///
+/// The parent keeps the child alive, and the child only has a weak pointer
+/// to its parent, so the tree is deallocated once the last strong pointer to
+/// its root is dropped.
+///
+/// # Examples
+///
+/// ```
+/// use corundum::default::*;
+/// use corundum::cookbook::{self, Node};
+///
+/// type P = Allocator;
+///
+/// let _pool = P::open_no_root("foo.pool", O_CF).unwrap();
+///
+/// P::transaction(|j| {
+///     let root = Prc::new(Node::new(0), j);
+///     let child = cookbook::add_child(&root, 1, j);
+///     assert_eq!(cookbook::parent_of(&child, j).unwrap().value, 0);
+///
+///     let leaf = {
+///         let grandchild = cookbook::add_child(&child, 2, j);
+///         assert_eq!(Prc::strong_count(&child), 2);
+///         Prc::downgrade(&grandchild, j)
+///     };
+///
+///     drop(child);
+///     drop(root);
+///     assert!(leaf.upgrade(j).is_none());
+/// }).unwrap();
+/// ```
+pub fn add_child<A: MemPool>(
+    parent: &Prc<Node<A>, A>,
+    value: u64,
+    journal: &Journal<A>
+) -> Prc<Node<A>, A> {
+    let child = Prc::new(Node::new(value), journal);
+    *child.parent.borrow_mut(journal) = Prc::downgrade(parent, journal);
+    parent.children.borrow_mut(journal).push(child.pclone(journal), journal);
+    child
+}
+
+/// Returns the parent of `node`, if it is not dropped
+pub fn parent_of<A: MemPool>(node: &Node<A>, journal: &Journal<A>) -> Option<Prc<Node<A>, A>> {
+    node.parent.borrow().upgrade(journal)
+}
@@ -0,0 +1,243 @@
+//! Structured errors
+
+use std::fmt;
+
+/// Errors returned by pool, transaction, and allocation operations
+///
+/// Each variant has a stable numerical [`code()`](#method.code) which can be
+/// passed through FFI boundaries.
+///
+/// # Examples
+///
+/// ```
+/// use corundum::default::*;
+/// use corundum::Error;
+///
+/// match Allocator::open::<PCell<i32>>("does-not-exist.pool", 0) {
+///     Err(Error::FileNotFound(path)) => assert_eq!(path, "does-not-exist.pool"),
+///     _ => unreachable!()
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The pool file does not exist
+    FileNotFound(String),
+
+    /// An I/O error occurred while accessing the pool file
+    Io(std::io::ErrorKind, String),
+
+    /// The file does not contain a valid image of the pool type (e.g., magic
+    /// number mismatch)
+    InvalidImage(String),
+
+    /// Invalid combination of open flags
+    InvalidFlags(&'static str),
+
+    /// An argument is out of its valid range
+    InvalidArgument(String),
+
+    /// The root object type differs from the one stored in the pool
+    IncompatibleRoot,
+
+    /// The root object type is a zero-sized type
+    ZeroSizedRoot,
+
+    /// The pool cannot be opened while a transaction is running on it
+    UncommittedTransaction,
+
+    /// The pool is not open
+    NotOpen,
+
+    /// The transaction did not commit and was rolled back. It contains the
+    /// panic message, if any.
+    TransactionAborted(String),
+
+    /// Another chaperoned session is open in the current thread
+    ChaperonBusy,
+
+    /// The pool does not have enough space for an allocation of the given
+    /// size
+    OutOfMemory(usize),
+
+    /// The offset is not within an allocated block
+    AccessViolation(u64),
+
+    /// The address is out of the pool's valid range
+    OutOfRange(u64),
+
+    /// The object is already initialized
+    AlreadyInitialized,
+
+    /// The transaction observed a cancellation request and was rolled back
+    Cancelled,
+
+    /// The transaction accessed a bad page of the pool at the given offset
+    /// and was rolled back, or the pool has a bad page and is poisoned (see
+    /// [`install_media_error_handler()`])
+    ///
+    /// [`install_media_error_handler()`]: ./alloc/fn.install_media_error_handler.html
+    MediaError(u64),
+
+    /// The transaction read data which a concurrent transaction changed
+    /// before it committed, under the [serializable] isolation level, and
+    /// was rolled back
+    ///
+    /// [serializable]: ./stm/isolation/index.html
+    SerializationFailure,
+
+    /// The transaction exceeded a limit of its [configuration] and was
+    /// rolled back
+    ///
+    /// [configuration]: ./stm/limits/index.html
+    LimitExceeded(crate::stm::TxLimit),
+
+    /// Any other error
+    Other(String),
+}
+
+impl Error {
+    /// Returns a numerical error code which is stable across versions
+    ///
+    /// Zero is reserved for success, and all error codes are negative.
+    pub fn code(&self) -> i32 {
+        match self {
+            Error::FileNotFound(_) => -1,
+            Error::Io(..) => -2,
+            Error::InvalidImage(_) => -3,
+            Error::InvalidFlags(_) => -4,
+            Error::InvalidArgument(_) => -5,
+            Error::IncompatibleRoot => -6,
+            Error::ZeroSizedRoot => -7,
+            Error::UncommittedTransaction => -8,
+            Error::NotOpen => -9,
+            Error::TransactionAborted(_) => -10,
+            Error::ChaperonBusy => -11,
+            Error::OutOfMemory(_) => -12,
+            Error::AccessViolation(_) => -13,
+            Error::OutOfRange(_) => -14,
+            Error::AlreadyInitialized => -15,
+            Error::Cancelled => -16,
+            Error::MediaError(_) => -17,
+            Error::SerializationFailure => -18,
+            Error::LimitExceeded(_) => -19,
+            Error::Other(_) => -255,
+        }
+    }
+
+    /// Creates a [`TransactionAborted`] error out of a panic payload
+    ///
+    /// [`TransactionAborted`]: #variant.TransactionAborted
+    pub(crate) fn from_panic(payload: &(dyn std::any::Any + Send)) -> Self {
+        if let Some(s) = payload.downcast_ref::<&str>() {
+            Error::TransactionAborted(s.to_string())
+        } else if let Some(s) = payload.downcast_ref::<String>() {
+            Error::TransactionAborted(s.clone())
+        } else {
+            Error::TransactionAborted(String::new())
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::FileNotFound(path) => write!(f, "pool file `{}` does not exist", path),
+            Error::Io(_, msg) => write!(f, "{}", msg),
+            Error::InvalidImage(path) => write!(f, "`{}` is not a valid pool image", path),
+            Error::InvalidFlags(msg) => write!(f, "invalid open flags: {}", msg),
+            Error::InvalidArgument(msg) => write!(f, "invalid argument: {}", msg),
+            Error::IncompatibleRoot => write!(f, "incompatible root type"),
+            Error::ZeroSizedRoot => write!(f, "root type cannot be a ZST"),
+            Error::UncommittedTransaction => {
+                write!(f, "an uncommitted transaction exists in the pool")
+            }
+            Error::NotOpen => write!(f, "pool is not open"),
+            Error::TransactionAborted(msg) => if msg.is_empty() {
+                write!(f, "unsuccessful transaction")
+            } else {
+                write!(f, "unsuccessful transaction: {}", msg)
+            },
+            Error::ChaperonBusy => write!(f, "another chaperoned transaction is open"),
+            Error::OutOfMemory(size) => write!(f, "out of memory (requested {} bytes)", size),
+            Error::AccessViolation(off) => write!(f, "access violation (0x{:x})", off),
+            Error::OutOfRange(addr) => write!(f, "out of valid range (0x{:x})", addr),
+            Error::AlreadyInitialized => write!(f, "already initialized"),
+            Error::Cancelled => write!(f, "transaction cancelled"),
+            Error::MediaError(off) => write!(f, "media error in the pool (0x{:x})", off),
+            Error::SerializationFailure => write!(f, "serialization failure"),
+            Error::LimitExceeded(limit) => write!(f, "transaction exceeded the limit of {}", limit),
+            Error::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e.kind(), e.to_string())
+    }
+}
+
+impl From<String> for Error {
+    fn from(msg: String) -> Self {
+        Error::Other(msg)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(msg: &str) -> Self {
+        Error::Other(msg.to_string())
+    }
+}
+
+/// The error type for fallible allocations, such as
+/// [`Pbox::try_new()`](./struct.Pbox.html#method.try_new)
+///
+/// It indicates that the pool does not have enough free space for the
+/// requested allocation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AllocError {
+    size: usize,
+}
+
+impl AllocError {
+    pub(crate) fn new(size: usize) -> Self {
+        Self { size }
+    }
+
+    /// Returns the requested allocation size in bytes
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+impl fmt::Display for AllocError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "memory exhausted (requested {} bytes)", self.size)
+    }
+}
+
+impl std::error::Error for AllocError {}
+
+impl From<AllocError> for Error {
+    fn from(e: AllocError) -> Self {
+        Error::OutOfMemory(e.size)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alloc::heap::*;
+    use crate::Error;
+
+    #[test]
+    fn transaction_error() {
+        let res = Heap::transaction(|_| {
+            panic!("intentional");
+        });
+        assert_eq!(res, Err(Error::TransactionAborted("intentional".to_string())));
+        assert_eq!(res.unwrap_err().code(), -10);
+    }
+}
@@ -352,3 +352,139 @@ impl<T, P: MemPool> Deref for Gen<T, P> {
 //         }
 //     }
 // }
+
+/// An opaque cursor over an iterator for FFI
+///
+/// It is created by the `*_iter_new` functions generated for the exported
+/// methods which return iterators. The iterator borrows an object in the
+/// pool, and the object stays pinned (see [`MemPoolTraits::pin()`]) while the
+/// cursor is live. The generated functions refuse to drop a pinned object or
+/// to call its `&mut self` methods, so the cursor should be freed using
+/// [`free_cursor()`] before then.
+///
+/// [`MemPoolTraits::pin()`]: ../alloc/trait.MemPoolTraits.html#method.pin
+/// [`free_cursor()`]: ./fn.free_cursor.html
+#[repr(C)]
+pub struct Cursor<T> {
+    // The first field, so that the cursor can be freed without its type
+    drop: unsafe fn(*mut c_void),
+    iter: Box<dyn Iterator<Item = T>>,
+    obj: *const u8,
+    unpin: unsafe fn(*const u8)
+}
+
+impl<T> Cursor<T> {
+    /// Pins `obj`, moves the iterator that `iter` creates over it to the heap,
+    /// and returns an opaque pointer to the cursor
+    ///
+    /// It returns a null pointer if `obj` cannot be pinned (e.g., it is not
+    /// in pool `A`).
+    ///
+    /// # Safety
+    ///
+    /// The lifetime of the borrow is erased; the object is protected from
+    /// being dropped or mutated only through the generated functions.
+    pub unsafe fn new<'a, A: MemPool, O: ?Sized, I, F>(obj: &'a O, iter: F) -> *mut c_void
+    where
+        I: Iterator<Item = T> + 'a,
+        F: FnOnce(&'a O) -> I
+    {
+        if A::pin(obj).is_err() {
+            return std::ptr::null_mut();
+        }
+        let iter: Box<dyn Iterator<Item = T> + 'a> = Box::new(iter(obj));
+        let iter: Box<dyn Iterator<Item = T>> = std::mem::transmute(iter);
+        Box::into_raw(Box::new(Self {
+            drop: Self::free,
+            iter,
+            obj: obj as *const O as *const u8,
+            unpin: unpin::<A>
+        })) as *mut c_void
+    }
+
+    /// Writes the next item to `out` and returns `true`, or returns `false`
+    /// if the iterator is exhausted or either pointer is null
+    ///
+    /// # Safety
+    ///
+    /// `cursor` should be a live cursor of items of type `T`, or null.
+    pub unsafe fn next(cursor: *mut c_void, out: *mut T) -> bool {
+        if cursor.is_null() || out.is_null() {
+            return false;
+        }
+        let cursor = &mut *(cursor as *mut Self);
+        if let Some(item) = cursor.iter.next() {
+            std::ptr::write(out, item);
+            true
+        } else {
+            false
+        }
+    }
+
+    unsafe fn free(cursor: *mut c_void) {
+        let cursor = Box::from_raw(cursor as *mut Self);
+        let Self { iter, obj, unpin, .. } = *cursor;
+        // The borrow ends before the object is released
+        drop(iter);
+        unpin(obj);
+    }
+}
+
+unsafe fn unpin<A: MemPool>(obj: *const u8) {
+    let _ = A::unpin(&*obj);
+}
+
+/// Indicates if `obj` is pinned in pool `A`, e.g., by a live [`Cursor`]
+///
+/// The generated functions use it to refuse dropping or mutating an object
+/// which is borrowed outside Rust.
+///
+/// [`Cursor`]: ./struct.Cursor.html
+pub fn is_pinned<A: MemPool, T: ?Sized>(obj: &T) -> bool {
+    A::off(obj).map_or(false, |off| A::is_pinned(off, std::mem::size_of_val(obj)))
+}
+
+/// Returns the handle of the `T` at `obj` in pool `A`, or 0 if `obj` is null
+/// or it is not in an allocated block
+///
+/// The generated C functions take objects by their handles (see
+/// [`MemPoolTraits::register()`]), so that they remain valid if the pool is
+/// mapped at a different address. An object has one handle, so it can be
+/// called on every access.
+///
+/// [`MemPoolTraits::register()`]: ../alloc/trait.MemPoolTraits.html#method.register
+pub fn handle_of<A: MemPool, T>(obj: *const T) -> u64 {
+    if obj.is_null() {
+        return 0;
+    }
+    let len = size_of::<T>();
+    match A::off(obj) {
+        Ok(off) if A::allocated(off, len) => {
+            crate::ptr::register_handle::<A>(off, len, Some(std::any::type_name::<T>()))
+        }
+        _ => 0
+    }
+}
+
+/// Resolves a handle of a `T` in pool `A` to a pointer, or null if it is
+/// stale or it does not refer to a `T`
+pub fn from_handle<A: MemPool, T>(handle: u64) -> *mut T {
+    match crate::ptr::lookup_handle::<A>(handle, Some(std::any::type_name::<T>())) {
+        Some(off) => unsafe { A::get_mut_unchecked::<T>(off) as *mut T },
+        None => std::ptr::null_mut()
+    }
+}
+
+/// Frees a cursor created by [`Cursor::new()`]
+///
+/// # Safety
+///
+/// `cursor` should be a live cursor, or null.
+///
+/// [`Cursor::new()`]: ./struct.Cursor.html#method.new
+pub unsafe fn free_cursor(cursor: *mut c_void) {
+    if !cursor.is_null() {
+        let drop = *(cursor as *const unsafe fn(*mut c_void));
+        drop(cursor);
+    }
+}
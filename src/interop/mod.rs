@@ -0,0 +1,3 @@
+//! Interoperability with other persistent memory libraries
+
+pub mod pmdk;
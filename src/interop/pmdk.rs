@@ -0,0 +1,478 @@
+//! Migration from and to PMDK's libpmemobj pools
+//!
+//! The heap layouts of PMDK and Corundum differ, so objects are migrated
+//! through an intermediate object stream rather than by opening the PMDK pool
+//! file. A small C program on the PMDK side iterates the objects (e.g., using
+//! `POBJ_FOREACH`) and writes them in the [stream format](#object-stream).
+//! Then, [`import()`] allocates them in a Corundum pool in the caller's
+//! transaction, and translates the `PMEMoid` fields of every object to the
+//! new offsets using a [`Layout`] description. The other way around,
+//! [`export()`] writes objects of a Corundum pool in the same format, so that
+//! they can be loaded with `pmemobj_alloc`. Both directions work on any
+//! subset of the objects, which allows an incremental migration.
+//!
+//! # Layout description
+//!
+//! A layout description is a text file with one declaration per line, and
+//! `#` comments:
+//!
+//! ```text
+//! layout <name>
+//! type <type_num> <name> <size> [oid <field offset>]...
+//! ```
+//!
+//! `type_num` is the PMDK type number of the objects, and every `oid` names
+//! the byte offset of a `PMEMoid` field in the object. A `PMEMoid` is 16
+//! bytes: `pool_uuid_lo` followed by `off`. After the import, its `off` is the
+//! offset of the target object in the Corundum pool, and its `pool_uuid_lo`
+//! is 0. Null ids (`off == 0`) are kept null.
+//!
+//! # Object stream
+//!
+//! All integers are little-endian `u64`s. The stream starts with the magic
+//! `CRNDMPOB`, followed by the length and bytes of the layout name. Then,
+//! every object is written as its type number, its offset in the source
+//! pool, its length, and its bytes.
+//!
+//! # Examples
+//!
+//! ```
+//! use corundum::default::*;
+//! use corundum::interop::pmdk::{self, Layout, Object};
+//!
+//! type P = Allocator;
+//!
+//! let layout = Layout::parse("
+//!     layout list
+//!     type 1 node 24 oid 8
+//! ").unwrap();
+//!
+//! // Two nodes as written by the PMDK side: (value, next)
+//! let mut head = 1u64.to_le_bytes().to_vec();
+//! head.extend_from_slice(&7u64.to_le_bytes());    // pool_uuid_lo
+//! head.extend_from_slice(&4096u64.to_le_bytes()); // off
+//! let tail = [2u64.to_le_bytes(), [0; 8], [0; 8]].concat();
+//! let objects = vec![
+//!     Object { type_num: 1, off: 1024, data: head },
+//!     Object { type_num: 1, off: 4096, data: tail },
+//! ];
+//!
+//! let _pool = P::open_no_root("foo.pool", O_CF).unwrap();
+//! let imported = P::transaction(|j| pmdk::import(&layout, &objects, j)).unwrap().unwrap();
+//!
+//! let (head, tail) = (imported.get(1024).unwrap(), imported.get(4096).unwrap());
+//! let next: &[u64; 3] = unsafe { P::get_unchecked(head) };
+//! assert_eq!(*next, [1, 0, tail]);
+//! ```
+//!
+//! [`import()`]: ./fn.import.html
+//! [`export()`]: ./fn.export.html
+//! [`Layout`]: ./struct.Layout.html
+
+use crate::alloc::MemPool;
+use crate::result::Result;
+use crate::stm::Journal;
+use crate::*;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::slice;
+
+/// The magic number of an object stream
+pub const STREAM_MAGIC: &[u8; 8] = b"CRNDMPOB";
+
+/// The size of a `PMEMoid` in bytes
+pub const OID_SIZE: usize = 16;
+
+/// The description of an object type in a [`Layout`](./struct.Layout.html)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TypeDesc {
+    /// The PMDK type number
+    pub num: u64,
+
+    /// The name of the type
+    pub name: String,
+
+    /// The size of the object in bytes
+    pub size: usize,
+
+    /// The byte offsets of the `PMEMoid` fields
+    pub oids: Vec<usize>,
+}
+
+/// A libpmemobj layout description
+///
+/// See the [module-level documentation](./index.html#layout-description) for
+/// the format.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Layout {
+    name: String,
+    types: HashMap<u64, TypeDesc>,
+}
+
+impl Layout {
+    /// Parses a layout description
+    ///
+    /// # Errors
+    ///
+    /// It returns [`InvalidArgument`] if a line is malformed, a type number
+    /// is declared twice, or an `oid` field does not fit in the object.
+    ///
+    /// [`InvalidArgument`]: ../../enum.Error.html#variant.InvalidArgument
+    pub fn parse(desc: &str) -> Result<Self> {
+        let mut layout = Self::default();
+        for (i, line) in desc.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            let mut words = line.split_whitespace();
+            let err = || Error::InvalidArgument(format!("layout line {}: `{}`", i + 1, line.trim()));
+            match words.next() {
+                None => continue,
+                Some("layout") => {
+                    layout.name = words.next().ok_or_else(err)?.to_string();
+                }
+                Some("type") => {
+                    let num = words.next().and_then(|w| w.parse().ok()).ok_or_else(err)?;
+                    let name = words.next().ok_or_else(err)?.to_string();
+                    let size: usize = words.next().and_then(|w| w.parse().ok()).ok_or_else(err)?;
+                    let mut oids = vec![];
+                    while let Some(w) = words.next() {
+                        let off: usize = match (w, words.next()) {
+                            ("oid", Some(off)) => off.parse().map_err(|_| err())?,
+                            _ => return Err(err()),
+                        };
+                        if off + OID_SIZE > size {
+                            return Err(err());
+                        }
+                        oids.push(off);
+                    }
+                    if layout.types.insert(num, TypeDesc { num, name, size, oids }).is_some() {
+                        return Err(err());
+                    }
+                }
+                Some(_) => return Err(err()),
+            }
+        }
+        Ok(layout)
+    }
+
+    /// Reads and parses a layout description file
+    pub fn from_file(path: &str) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Returns the name of the layout
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the description of type number `num`
+    pub fn get(&self, num: u64) -> Option<&TypeDesc> {
+        self.types.get(&num)
+    }
+
+    /// Returns an iterator over the types of the layout
+    pub fn types(&self) -> impl Iterator<Item = &TypeDesc> {
+        self.types.values()
+    }
+}
+
+/// An object in an object stream
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Object {
+    /// The PMDK type number
+    pub type_num: u64,
+
+    /// The offset of the object in the source pool, which identifies it
+    pub off: u64,
+
+    /// The content of the object
+    pub data: Vec<u8>,
+}
+
+/// The offsets of the imported objects in the Corundum pool
+#[derive(Clone, Debug, Default)]
+pub struct Imported {
+    map: HashMap<u64, u64>,
+}
+
+impl Imported {
+    /// Returns the offset of the object which was at `src_off` in the source
+    /// pool
+    pub fn get(&self, src_off: u64) -> Option<u64> {
+        self.map.get(&src_off).copied()
+    }
+
+    /// Returns the number of imported objects
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Indicates if no object was imported
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Returns an iterator over pairs of source and new offsets
+    pub fn iter(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.map.iter().map(|(s, d)| (*s, *d))
+    }
+}
+
+fn read_u64(r: &mut impl Read) -> std::io::Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// Reads a `u64`, or returns `None` if the stream ends before it. A partial
+/// `u64` is an `UnexpectedEof` error.
+fn read_u64_or_eof(r: &mut impl Read) -> std::io::Result<Option<u64>> {
+    let mut buf = [0; 8];
+    let mut read = 0;
+    while read < buf.len() {
+        match r.read(&mut buf[read..]) {
+            Ok(0) if read == 0 => return Ok(None),
+            Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(Some(u64::from_le_bytes(buf)))
+}
+
+/// Reads `len` bytes. The buffer grows with the bytes actually read, so a
+/// corrupt length does not cause a large allocation.
+fn read_bytes(r: &mut impl Read, len: u64) -> std::io::Result<Vec<u8>> {
+    let mut data = vec![];
+    r.take(len).read_to_end(&mut data)?;
+    if (data.len() as u64) < len {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(data)
+}
+
+fn field(data: &[u8], off: usize) -> u64 {
+    let mut buf = [0; 8];
+    buf.copy_from_slice(&data[off..off + 8]);
+    u64::from_le_bytes(buf)
+}
+
+/// Reads an object stream, and returns the layout name and the objects
+///
+/// # Errors
+///
+/// It returns [`InvalidImage`] if the stream does not start with
+/// [`STREAM_MAGIC`], or [`Io`] if it is truncated, including in the middle
+/// of a record or when a length exceeds the rest of the stream.
+///
+/// [`InvalidImage`]: ../../enum.Error.html#variant.InvalidImage
+/// [`STREAM_MAGIC`]: ./constant.STREAM_MAGIC.html
+/// [`Io`]: ../../enum.Error.html#variant.Io
+pub fn read_objects(mut r: impl Read) -> Result<(String, Vec<Object>)> {
+    let mut magic = [0; 8];
+    r.read_exact(&mut magic)?;
+    if &magic != STREAM_MAGIC {
+        return Err(Error::InvalidImage("not a PMDK object stream".to_string()));
+    }
+    let len = read_u64(&mut r)?;
+    let name = read_bytes(&mut r, len)?;
+    let name = String::from_utf8(name)
+        .map_err(|_| Error::InvalidImage("invalid layout name".to_string()))?;
+
+    let mut objects = vec![];
+    loop {
+        let type_num = match read_u64_or_eof(&mut r)? {
+            Some(v) => v,
+            None => break,
+        };
+        let off = read_u64(&mut r)?;
+        let len = read_u64(&mut r)?;
+        let data = read_bytes(&mut r, len)?;
+        objects.push(Object { type_num, off, data });
+    }
+    Ok((name, objects))
+}
+
+/// Writes `objects` as an object stream of layout `name`
+pub fn write_objects(mut w: impl Write, name: &str, objects: &[Object]) -> Result<()> {
+    w.write_all(STREAM_MAGIC)?;
+    w.write_all(&(name.len() as u64).to_le_bytes())?;
+    w.write_all(name.as_bytes())?;
+    for obj in objects {
+        w.write_all(&obj.type_num.to_le_bytes())?;
+        w.write_all(&obj.off.to_le_bytes())?;
+        w.write_all(&(obj.data.len() as u64).to_le_bytes())?;
+        w.write_all(&obj.data)?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+/// Imports `objects` into pool `A` as part of the current transaction
+///
+/// Every object is copied into a new allocation of its type's size, and its
+/// `PMEMoid` fields are translated to the new offsets. All objects are
+/// validated before anything is allocated, so an error leaves the pool
+/// unchanged. The new objects are not reachable from the root object; the
+/// caller should link them (e.g., using the returned offsets) before the
+/// transaction commits, or they are leaked.
+///
+/// # Errors
+///
+/// * [`InvalidArgument`] if an object has an unknown type, it is shorter
+///   than its type, two objects have the same offset, or a `PMEMoid` refers
+///   to an object which is not in `objects`.
+/// * [`OutOfMemory`] if the pool cannot fit the objects. The objects
+///   allocated so far are freed.
+///
+/// [`InvalidArgument`]: ../../enum.Error.html#variant.InvalidArgument
+/// [`OutOfMemory`]: ../../enum.Error.html#variant.OutOfMemory
+pub fn import<A: MemPool>(layout: &Layout, objects: &[Object], journal: &Journal<A>) -> Result<Imported> {
+    let mut descs = Vec::with_capacity(objects.len());
+    let mut srcs = HashSet::with_capacity(objects.len());
+    for obj in objects {
+        let desc = layout.get(obj.type_num).ok_or_else(|| Error::InvalidArgument(
+            format!("object at {} has unknown type {}", obj.off, obj.type_num)))?;
+        if obj.data.len() < desc.size {
+            return Err(Error::InvalidArgument(format!(
+                "object at {} is shorter than type `{}`", obj.off, desc.name)));
+        }
+        if !srcs.insert(obj.off) {
+            return Err(Error::InvalidArgument(format!("duplicate object at {}", obj.off)));
+        }
+        descs.push(desc);
+    }
+    for (obj, desc) in objects.iter().zip(&descs) {
+        for oid in &desc.oids {
+            let target = field(&obj.data, oid + 8);
+            if target != 0 && !srcs.contains(&target) {
+                return Err(Error::InvalidArgument(format!(
+                    "object at {} refers to missing object at {}", obj.off, target)));
+            }
+        }
+    }
+
+    let mut imported = Imported::default();
+    let mut ptrs = Vec::with_capacity(objects.len());
+    for (obj, desc) in objects.iter().zip(&descs) {
+        let p = match unsafe { A::try_new_uninit_for_layout(desc.size, journal) } {
+            Ok(p) => p,
+            Err(e) => {
+                for (p, desc) in ptrs.iter().zip(&descs) {
+                    unsafe { A::free_slice(slice::from_raw_parts(*p, desc.size)); }
+                }
+                return Err(e.into());
+            }
+        };
+        imported.map.insert(obj.off, unsafe { A::off_unchecked(p) });
+        ptrs.push(p);
+    }
+    for ((obj, desc), p) in objects.iter().zip(&descs).zip(ptrs) {
+        let mut data = obj.data[..desc.size].to_vec();
+        for oid in &desc.oids {
+            let target = field(&data, oid + 8);
+            let target = if target == 0 { 0 } else { imported.map[&target] };
+            data[*oid..oid + 8].copy_from_slice(&0u64.to_le_bytes());
+            data[oid + 8..oid + OID_SIZE].copy_from_slice(&target.to_le_bytes());
+        }
+        unsafe {
+            let dst = slice::from_raw_parts_mut(p, desc.size);
+            dst.copy_from_slice(&data);
+            ll::persist(dst, desc.size, false);
+        }
+    }
+    ll::sfence();
+    Ok(imported)
+}
+
+/// Exports the objects of pool `A` at the given `(type_num, offset)` pairs
+/// to an object stream
+///
+/// The `PMEMoid` fields are written as they are, so they hold the offsets of
+/// their targets in the Corundum pool, which are also the identifiers of the
+/// exported objects in the stream.
+///
+/// # Errors
+///
+/// * [`InvalidArgument`] if a type number is not in `layout`.
+/// * [`AccessViolation`] if an object is not allocated.
+///
+/// [`InvalidArgument`]: ../../enum.Error.html#variant.InvalidArgument
+/// [`AccessViolation`]: ../../enum.Error.html#variant.AccessViolation
+pub fn export<A: MemPool, W: Write>(layout: &Layout, objects: &[(u64, u64)], w: W) -> Result<()> {
+    let mut out = Vec::with_capacity(objects.len());
+    for (type_num, off) in objects {
+        let desc = layout.get(*type_num).ok_or_else(|| Error::InvalidArgument(
+            format!("unknown type {}", type_num)))?;
+        if !A::allocated(*off, desc.size) {
+            return Err(Error::AccessViolation(*off));
+        }
+        let data = unsafe {
+            slice::from_raw_parts(A::get_unchecked::<u8>(*off) as *const u8, desc.size)
+        };
+        out.push(Object { type_num: *type_num, off: *off, data: data.to_vec() });
+    }
+    write_objects(w, layout.name(), &out)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::default::*;
+    use crate::interop::pmdk::*;
+
+    type P = Allocator;
+
+    #[test]
+    fn pmdk_roundtrip() {
+        let layout = Layout::parse("layout pair # comment\ntype 2 pair 32 oid 0 oid 16").unwrap();
+        assert_eq!(layout.name(), "pair");
+        assert!(Layout::parse("type 1 small 8 oid 0").is_err());
+
+        let a = Object { type_num: 2, off: 64, data: [[0u8; 8], 128u64.to_le_bytes(), [0; 8], [0; 8]].concat() };
+        let b = Object { type_num: 2, off: 128, data: [[0u8; 8], 64u64.to_le_bytes(), [0; 8], [0; 8]].concat() };
+        let mut stream = vec![];
+        write_objects(&mut stream, "pair", &[a, b]).unwrap();
+        let (name, objects) = read_objects(&stream[..]).unwrap();
+        assert_eq!(name, "pair");
+        assert_eq!(objects.len(), 2);
+
+        let _pool = P::open_no_root("pmdk.pool", O_CF).unwrap();
+        assert!(P::transaction(|j| import(&layout, &objects[..1], j)).unwrap().is_err());
+
+        let exported = P::transaction(|j| {
+            let imported = import(&layout, &objects, j).unwrap();
+            let (a, b) = (imported.get(64).unwrap(), imported.get(128).unwrap());
+            let mut out = vec![];
+            export::<P, _>(&layout, &[(2, a), (2, b)], &mut out).unwrap();
+            let (_, exported) = read_objects(&out[..]).unwrap();
+            assert_eq!(&exported[0].data[8..16], &b.to_le_bytes()[..]);
+            assert_eq!(&exported[1].data[8..16], &a.to_le_bytes()[..]);
+            unsafe {
+                P::free_slice(std::slice::from_raw_parts(P::get_unchecked::<u8>(a), 32));
+                P::free_slice(std::slice::from_raw_parts(P::get_unchecked::<u8>(b), 32));
+            }
+            exported.len()
+        }).unwrap();
+        assert_eq!(exported, 2);
+    }
+
+    #[test]
+    fn truncated_stream() {
+        let obj = Object { type_num: 1, off: 8, data: vec![1; 16] };
+        let mut stream = vec![];
+        write_objects(&mut stream, "t", &[obj]).unwrap();
+
+        // A partial record header is an error rather than the end
+        let mut partial = stream.clone();
+        partial.extend_from_slice(&[0; 4]);
+        assert!(read_objects(&partial[..]).is_err());
+
+        // A length beyond the end of the stream is rejected without
+        // allocating it
+        let mut huge = stream[..stream.len() - 24].to_vec();
+        huge.extend_from_slice(&u64::MAX.to_le_bytes());
+        assert!(read_objects(&huge[..]).is_err());
+        assert!(read_objects(&stream[..stream.len() - 1]).is_err());
+        assert_eq!(read_objects(&stream[..]).unwrap().1.len(), 1);
+    }
+}
@@ -0,0 +1,161 @@
+//! Streaming I/O into persistent buffers
+
+use crate::alloc::MemPool;
+use crate::ll;
+use crate::stm::{Journal, Logger, Notifier};
+use crate::vec::Vec as PVec;
+use std::io;
+
+/// The default number of bytes which a [`PWriter`] writes at once
+///
+/// [`PWriter`]: ./struct.PWriter.html
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// A [`Write`] implementation over a persistent byte vector
+///
+/// Writing a large blob into a persistent buffer with a single call logs
+/// the whole overwritten range at once, and leaves the written bytes in the
+/// cache until the transaction commits. A `PWriter` takes the data in
+/// chunks of a fixed size instead: every chunk which overwrites existing
+/// bytes gets its own log, and every chunk is flushed as soon as it is
+/// written. The journal grows with the number of chunks rather than with a
+/// single log of the size of the whole input, and the write-back overlaps
+/// with the streaming.
+///
+/// A writer which is created with [`new()`] appends to the vector. A writer
+/// which is created with [`overwrite()`] starts from the beginning of the
+/// vector, and extends it when it reaches the end. The changes are a part of
+/// the transaction of the journal, and they roll back with it.
+///
+/// # Examples
+///
+/// ```
+/// use corundum::default::*;
+/// use corundum::io::PWriter;
+/// use std::io::Write;
+///
+/// type P = Allocator;
+///
+/// let root = P::open::<PRefCell<PVec<u8>>>("pwriter.pool", O_CF).unwrap();
+///
+/// P::transaction(|j| {
+///     let mut blob = root.borrow_mut(j);
+///     let mut w = PWriter::new(&mut blob, j).with_chunk_size(4096);
+///     for _ in 0..16 {
+///         w.write_all(&[7u8; 1000]).unwrap();
+///     }
+///     w.flush().unwrap();
+/// }).unwrap();
+///
+/// assert_eq!(root.borrow().len(), 16000);
+/// ```
+///
+/// [`Write`]: std::io::Write
+/// [`new()`]: #method.new
+/// [`overwrite()`]: #method.overwrite
+pub struct PWriter<'a, A: MemPool> {
+    vec: &'a mut PVec<u8, A>,
+    pos: usize,
+    chunk: usize,
+    journal: &'a Journal<A>,
+}
+
+impl<'a, A: MemPool> PWriter<'a, A> {
+    /// Creates a writer which appends to `vec`
+    pub fn new(vec: &'a mut PVec<u8, A>, journal: &'a Journal<A>) -> Self {
+        Self {
+            pos: vec.len(),
+            vec,
+            chunk: DEFAULT_CHUNK_SIZE,
+            journal,
+        }
+    }
+
+    /// Creates a writer which overwrites `vec` from the beginning
+    pub fn overwrite(vec: &'a mut PVec<u8, A>, journal: &'a Journal<A>) -> Self {
+        Self {
+            pos: 0,
+            vec,
+            chunk: DEFAULT_CHUNK_SIZE,
+            journal,
+        }
+    }
+
+    /// Sets the number of bytes to write at once
+    ///
+    /// # Panics
+    ///
+    /// It panics if `size` is zero.
+    pub fn with_chunk_size(mut self, size: usize) -> Self {
+        assert_ne!(size, 0, "chunk size should be non-zero");
+        self.chunk = size;
+        self
+    }
+
+    /// Returns the position of the next byte to write
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+}
+
+impl<A: MemPool> io::Write for PWriter<'_, A> {
+    /// Writes at most one chunk of `buf`
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.vec.len();
+        let n = if self.pos < len {
+            // Overwrite the existing bytes up to the end of the vector
+            let n = buf.len().min(self.chunk).min(len - self.pos);
+            let dst = &mut self.vec.to_slice_mut()[self.pos..self.pos + n];
+            unsafe {
+                dst.create_log(self.journal, Notifier::None);
+            }
+            dst.copy_from_slice(&buf[..n]);
+            ll::persist_obj(dst, false);
+            n
+        } else {
+            let n = buf.len().min(self.chunk);
+            self.vec.extend_from_slice(&buf[..n], self.journal);
+            ll::persist_obj(&self.vec.to_slice_mut()[len..len + n], false);
+            n
+        };
+        self.pos += n;
+        Ok(n)
+    }
+
+    /// Waits for the written chunks to reach the persistent memory
+    fn flush(&mut self) -> io::Result<()> {
+        ll::sfence();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PWriter;
+    use crate::default::*;
+    use std::io::Write;
+
+    type P = Allocator;
+
+    #[test]
+    fn pwriter_overwrite_and_rollback() {
+        let root = P::open::<PRefCell<PVec<u8>>>("pwriter_test.pool", O_CF).unwrap();
+        P::transaction(|j| {
+            let mut v = root.borrow_mut(j);
+            v.clear();
+            PWriter::new(&mut v, j).write_all(&[1; 10]).unwrap();
+        }).unwrap();
+
+        assert!(P::transaction(|j| {
+            let mut v = root.borrow_mut(j);
+            let mut w = PWriter::overwrite(&mut v, j).with_chunk_size(3);
+            w.write_all(&[2; 15]).unwrap();
+            assert_eq!(w.position(), 15);
+            drop(w);
+            assert_eq!(v.as_slice(), &[2; 15]);
+            panic!("abort");
+        }).is_err());
+
+        assert_eq!(root.borrow().as_slice(), &[1; 10]);
+    }
+}
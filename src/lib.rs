@@ -129,6 +129,7 @@
 #![feature(rustc_attrs)]
 #![feature(allocator_api)]
 #![feature(associated_type_bounds)]
+#![feature(avx512_target_feature)]
 // #![feature(async_stream)]
 
 #![allow(dead_code)]
@@ -149,6 +150,9 @@ pub mod stat;
 pub mod utils;
 pub mod stl;
 pub mod gen;
+pub mod cookbook;
+pub mod interop;
+pub mod io;
 
 mod alloc;
 mod boxed;
@@ -157,9 +161,16 @@ mod clone;
 mod str;
 pub mod vec;
 mod convert;
+mod error;
 mod marker;
 mod tests;
 
+#[cfg(feature = "rayon")]
+mod par;
+
+#[cfg(feature = "testing")]
+pub mod testing;
+
 pub use cell::RootObj;
 pub use stm::transaction;
 pub use marker::*;
@@ -173,13 +184,14 @@ pub use self::str::{String as PString, ToPString, ToPStringSlice};
 pub use cell::*;
 pub use alloc::*;
 pub use convert::*;
+pub use error::{Error, AllocError};
 pub use stm::Journal;
 
 // This is an example of defining a new buddy allocator type
 // `Allocator` is the default allocator with Buddy Allocation algorithm
 crate::pool!(default);
 
-/// A `Result` type with string error messages
+/// A `Result` type with structured [`Error`](../enum.Error.html)s
 pub mod result {
-    pub type Result<T: ?Sized> = std::result::Result<T, String>;
+    pub type Result<T: ?Sized> = std::result::Result<T, crate::Error>;
 }
\ No newline at end of file
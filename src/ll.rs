@@ -1,8 +1,21 @@
 //! Low-level utils
+//!
+//! Under Miri and loom (`RUSTFLAGS="--cfg loom"`), the cache-line flushes,
+//! the fences, and the other inline assembly are no-ops, and the locks use the
+//! spinning path of the `no_pthread` feature instead of the pthread mutexes.
+//! Hence, the journal, the cells, and the reference-counted pointers run
+//! under these tools on a volatile pool, such as
+//! [`Heap`](../alloc/heap/struct.Heap.html). The volatile synchronization
+//! (see [`model`]), including the lock words of `PMutex` and the
+//! transaction locks, is replaced by loom's models, so that `loom::model`
+//! explores its interleavings. The journals are looked up by the OS thread,
+//! which loom does not model, so whole transactions are not run under loom.
 #![allow(unused)]
 
 use crate::alloc::MemPool;
 use std::arch::asm;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 
 #[inline(always)]
 pub fn cpu() -> usize {
@@ -34,27 +47,7 @@ pub fn persist<T: ?Sized>(ptr: *const T, len: usize, fence: bool) {
     let _perf = crate::stat::Measure::<crate::default::Allocator>::Sync(std::time::Instant::now());
 
     #[cfg(not(feature = "no_persist"))]
-    {   
-        #[cfg(not(feature = "use_msync"))]
-        clflush(ptr, len, fence);
-
-        #[cfg(feature = "use_msync")]
-        unsafe {
-            let off = ptr as *const T as *const u8 as usize;
-            let end = off + len;
-            let off = (off >> 12) << 12;
-            let len = end - off;
-            let ptr = off as *const u8;
-            if libc::persist(
-                ptr as *mut libc::c_void,
-                len,
-                libc::MS_SYNC | libc::MS_INVALIDATE,
-            ) != 0
-            {
-                panic!("persist failed");
-            }
-        }
-    }
+    clflush(ptr, len, fence);
 }
 
 #[inline(always)]
@@ -81,18 +74,22 @@ pub fn persist_obj<T: ?Sized>(obj: &T, fence: bool) {
 #[inline(always)]
 pub fn clflush<T: ?Sized>(ptr: *const T, len: usize, fence: bool) {
     #[cfg(not(feature = "no_persist"))]
-    {
+    if msync_mode() {
+        msync_mark(ptr as *const u8 as usize, len);
+    } else {
+        let line = cache_line_size();
         let ptr = ptr as *const u8 as *mut u8;
-        let mut start = ptr as usize;
-        start = (start >> 9) << 9;
-        let end = start + len;
+        let mut start = ptr as usize & !(line - 1);
+        let end = ptr as usize + len;
 
         #[cfg(feature = "stat_print_flushes")]
         println!("flush {:x} ({})", start, len);
 
+        crate::stat::count_flushes(((end - start + line - 1) / line) as u64);
+
         while start < end {
             unsafe {
-                #[cfg(not(any(feature = "use_clflushopt", feature = "use_clwb")))]
+                #[cfg(not(any(feature = "use_clflushopt", feature = "use_clwb", miri, loom)))]
                 {
                     #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
                     asm!("clflush [{}]", in(reg) (start as *const u8), options(nostack));
@@ -100,11 +97,11 @@ pub fn clflush<T: ?Sized>(ptr: *const T, len: usize, fence: bool) {
                     #[cfg(target_arch = "aarch64")]
                     asm!("dc cvau, {}", in(reg) (start as *const u8))
                 }
-                #[cfg(all(feature = "use_clflushopt", not(feature = "use_clwb")))]
+                #[cfg(all(feature = "use_clflushopt", not(feature = "use_clwb"), not(any(miri, loom))))]
                 {
                     asm!("clflushopt [{}]", in(reg) (start as *const u8), options(nostack));
                 }
-                #[cfg(all(feature = "use_clwb", not(feature = "use_clflushopt")))]
+                #[cfg(all(feature = "use_clwb", not(feature = "use_clflushopt"), not(any(miri, loom))))]
                 {
                     asm!("clwb [{}]", in(reg) (start as *const u8), options(nostack));
                 }
@@ -113,20 +110,185 @@ pub fn clflush<T: ?Sized>(ptr: *const T, len: usize, fence: bool) {
                     compile_error!("Please Select only one from clflushopt and clwb")
                 }
             }
-            start += 64;
+            start += line;
         }
+
+        #[cfg(feature = "emulate_latency")]
+        emulate_delay(((len + line - 1) / line) as u64 * flush_latency());
     }
     if (fence) {
         sfence();
     }
 }
 
+/// The flush granularity in bytes, or zero if it is not detected yet
+static CACHE_LINE_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the granularity of the cache-line flushes in bytes
+///
+/// It is detected from the CPU (`CPUID` on x86-64 and `CTR_EL0` on AArch64)
+/// the first time it is needed, or it is 64 bytes if the detection fails. It
+/// may be lowered by the `CACHE_LINE_SIZE` environment variable or by
+/// [`set_cache_line_size()`].
+///
+/// [`set_cache_line_size()`]: ./fn.set_cache_line_size.html
+#[inline]
+pub fn cache_line_size() -> usize {
+    let size = CACHE_LINE_SIZE.load(Ordering::Relaxed);
+    if size != 0 {
+        return size;
+    }
+    let detected = detect_cache_line_size();
+    let size = std::env::var("CACHE_LINE_SIZE").ok()
+        .map(|s| s.parse::<usize>().ok()
+            .filter(|s| s.is_power_of_two() && *s <= detected)
+            .unwrap_or_else(|| panic!("CACHE_LINE_SIZE should be a power of two \
+                no larger than {}", detected)))
+        .unwrap_or(detected);
+    CACHE_LINE_SIZE.store(size, Ordering::Relaxed);
+    size
+}
+
+/// Sets the granularity of the cache-line flushes in bytes
+///
+/// It may only lower the detected size. A smaller stride flushes the same
+/// lines more than once, which is safe but slower; a larger one would skip
+/// the lines in between, and their updates would not become durable.
+///
+/// # Errors
+///
+/// It returns [`InvalidArgument`] if `bytes` is not a power of two, or it is
+/// larger than the detected line size.
+///
+/// [`InvalidArgument`]: ../enum.Error.html#variant.InvalidArgument
+pub fn set_cache_line_size(bytes: usize) -> crate::result::Result<()> {
+    let detected = detect_cache_line_size();
+    if !bytes.is_power_of_two() || bytes > detected {
+        return Err(crate::Error::InvalidArgument(format!(
+            "the cache line size should be a power of two no larger than {}", detected)));
+    }
+    CACHE_LINE_SIZE.store(bytes, Ordering::Relaxed);
+    Ok(())
+}
+
+fn detect_cache_line_size() -> usize {
+    #[cfg(all(target_arch = "x86_64", not(any(miri, loom))))] {
+        // CPUID.01H:EBX[15:8] is the CLFLUSH line size in 8-byte units
+        let size = unsafe { std::arch::x86_64::__cpuid(1).ebx >> 8 & 0xff } as usize * 8;
+        if size.is_power_of_two() {
+            return size;
+        }
+    }
+    #[cfg(all(target_arch = "aarch64", not(any(miri, loom))))] {
+        // CTR_EL0.DminLine is log2 of the smallest data cache line in words
+        let ctr: u64;
+        unsafe { asm!("mrs {}, ctr_el0", out(reg) ctr, options(nomem, nostack)); }
+        return 4 << ((ctr >> 16) & 0xf);
+    }
+    #[allow(unreachable_code)]
+    64
+}
+
+/// The default size in bytes from which [`memcpy_persist()`] uses
+/// non-temporal stores
+///
+/// [`memcpy_persist()`]: ./fn.memcpy_persist.html
+pub const NT_THRESHOLD: usize = 4096;
+
+static NT_COPY_THRESHOLD: AtomicUsize = AtomicUsize::new(NT_THRESHOLD);
+
+/// Sets the size in bytes from which [`memcpy_persist()`] uses non-temporal
+/// stores; `usize::MAX` disables them
+///
+/// [`memcpy_persist()`]: ./fn.memcpy_persist.html
+pub fn set_nt_threshold(bytes: usize) {
+    NT_COPY_THRESHOLD.store(bytes, Ordering::Relaxed);
+}
+
+/// Copies `len` bytes from `src` to `dst` and makes them persistent
+///
+/// Small copies use `memcpy` followed by cache-line flushes. From the
+/// threshold set by [`set_nt_threshold()`] (4KiB by default) on x86-64, the
+/// aligned cache lines of `dst` are written with non-temporal stores, which
+/// bypass the caches and need no flushes: 64 bytes per instruction with
+/// AVX-512, if the CPU supports it, or 16 bytes otherwise. The non-temporal
+/// stores are always followed by a store fence, as they are weakly ordered;
+/// `fence` only applies to the flushed parts.
+///
+/// # Safety
+///
+/// `src` and `dst` should be valid for `len` bytes and should not overlap.
+///
+/// [`set_nt_threshold()`]: ./fn.set_nt_threshold.html
+pub unsafe fn memcpy_persist(dst: *mut u8, src: *const u8, len: usize, fence: bool) {
+    #[cfg(all(target_arch = "x86_64", not(feature = "no_persist"), not(any(miri, loom))))]
+    if len >= NT_COPY_THRESHOLD.load(Ordering::Relaxed) && !msync_mode() {
+        let head = (dst.align_offset(64)).min(len);
+        let body = (len - head) & !63;
+        let tail = len - head - body;
+        std::ptr::copy_nonoverlapping(src, dst, head);
+        nt_copy(dst.add(head), src.add(head), body);
+        std::ptr::copy_nonoverlapping(src.add(head + body), dst.add(head + body), tail);
+        if head != 0 {
+            clflush(dst, head, false);
+        }
+        if tail != 0 {
+            clflush(dst.add(head + body), tail, false);
+        }
+        _mm_sfence();
+        return;
+    }
+    std::ptr::copy_nonoverlapping(src, dst, len);
+    persist(dst, len, fence);
+}
+
+/// Copies `len` bytes, a multiple of 64, from `src` to the 64-byte aligned
+/// `dst` with non-temporal stores
+#[cfg(target_arch = "x86_64")]
+unsafe fn nt_copy(dst: *mut u8, src: *const u8, len: usize) {
+    use std::arch::x86_64::{__m128i, _mm_loadu_si128, _mm_stream_si128};
+
+    if is_x86_feature_detected!("avx512f") {
+        nt_copy_avx512(dst, src, len);
+    } else {
+        let (src, dst) = (src as *const __m128i, dst as *mut __m128i);
+        for i in 0..len / 16 {
+            _mm_stream_si128(dst.add(i), _mm_loadu_si128(src.add(i)));
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512f")]
+unsafe fn nt_copy_avx512(dst: *mut u8, src: *const u8, len: usize) {
+    let mut i = 0;
+    while i < len {
+        asm!(
+            "vmovdqu64 zmm0, [{src}]",
+            "vmovntdq [{dst}], zmm0",
+            src = in(reg) src.add(i),
+            dst = in(reg) dst.add(i),
+            out("zmm0") _,
+            options(nostack),
+        );
+        i += 64;
+    }
+}
+
 /// Store fence
 #[inline(always)]
 pub fn sfence() {
-    #[cfg(any(feature = "use_clwb", feature = "use_clflushopt"))] unsafe {
+    crate::stat::count_fence();
+
+    #[cfg(all(any(feature = "use_clwb", feature = "use_clflushopt"), not(any(miri, loom))))] unsafe {
         _mm_sfence();
     }
+    #[cfg(not(feature = "no_persist"))]
+    if msync_mode() {
+        msync_fence();
+    }
+    #[cfg(all(feature = "emulate_latency", not(feature = "no_persist")))]
+    emulate_delay(fence_latency());
 }
 
 /// Memory fence
@@ -135,4 +297,242 @@ pub fn mfence() {
     unsafe {
         std::intrinsics::atomic_fence()
     }
+    #[cfg(not(feature = "no_persist"))]
+    if msync_mode() {
+        msync_fence();
+    }
+}
+
+/// The number of open pools which are persisted with `msync`
+static MSYNC_POOLS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// The page-aligned ranges which are flushed by the current thread since
+    /// its last fence
+    static DIRTY: RefCell<Vec<(usize, usize)>> = RefCell::new(Vec::new());
+}
+
+/// Indicates if flushes are replaced by `msync` at fences
+///
+/// It is enabled while a pool which is mapped with
+/// [`MapMode::Msync`](../alloc/enum.MapMode.html#variant.Msync) is open, or
+/// always with the `use_msync` feature. In this mode, a flush only records
+/// the range, and the next fence of the same thread synchronizes all recorded
+/// ranges with `msync(MS_SYNC)`. Therefore, everything flushed before a fence
+/// is durable after the fence, the same as with cache-line flushes on
+/// persistent memory. If `msync` fails, the fence panics with the error, so
+/// that the running transaction rolls back. It is supported only on Unix.
+#[inline(always)]
+pub fn msync_mode() -> bool {
+    cfg!(feature = "use_msync") || MSYNC_POOLS.load(Ordering::Relaxed) != 0
+}
+
+#[doc(hidden)]
+pub fn enable_msync() {
+    MSYNC_POOLS.fetch_add(1, Ordering::AcqRel);
+}
+
+#[doc(hidden)]
+pub fn disable_msync() -> std::io::Result<()> {
+    let res = msync_drain();
+    MSYNC_POOLS.fetch_sub(1, Ordering::AcqRel);
+    res
+}
+
+#[cfg(all(feature = "use_msync", not(unix)))]
+compile_error!("the `use_msync` feature is supported only on Unix");
+
+fn page_size() -> usize {
+    #[cfg(unix)] unsafe {
+        libc::sysconf(libc::_SC_PAGESIZE) as usize
+    }
+    #[cfg(not(unix))] {
+        4096
+    }
+}
+
+/// Records `len` bytes at `addr` to be synchronized at the next fence
+fn msync_mark(addr: usize, len: usize) {
+    if len == 0 {
+        return;
+    }
+    let page = page_size();
+    let start = addr & !(page - 1);
+    let end = (addr + len + page - 1) & !(page - 1);
+    DIRTY.with(|d| {
+        let mut d = d.borrow_mut();
+        match d.last_mut() {
+            Some(last) if start <= last.1 && end >= last.0 => {
+                *last = (last.0.min(start), last.1.max(end));
+            }
+            _ => d.push((start, end)),
+        }
+    })
+}
+
+/// Synchronizes the recorded ranges at a fence, and panics if it fails
+fn msync_fence() {
+    if let Err(e) = msync_drain() {
+        panic!("msync failed: {}", e);
+    }
+}
+
+/// Synchronizes the ranges which are recorded by the current thread, and
+/// returns the first error
+fn msync_drain() -> std::io::Result<()> {
+    let mut ranges = DIRTY.with(|d| std::mem::take(&mut *d.borrow_mut()));
+    if ranges.is_empty() {
+        return Ok(());
+    }
+    ranges.sort_unstable();
+    let mut merged: Vec<(usize, usize)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    let mut res = Ok(());
+    for (start, end) in merged {
+        #[cfg(unix)] unsafe {
+            if libc::msync(start as *mut libc::c_void, end - start, libc::MS_SYNC) != 0 {
+                let e = std::io::Error::last_os_error();
+                // Ranges outside file mappings (e.g., the volatile heap) fail
+                // with ENOMEM, and they do not need to be synchronized
+                if e.raw_os_error() != Some(libc::ENOMEM) && res.is_ok() {
+                    res = Err(e);
+                }
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = (start, end);
+    }
+    res
+}
+
+/// The emulated latencies in nanoseconds; `u64::MAX` means that they are not
+/// read from the environment yet
+#[cfg(feature = "emulate_latency")]
+static FLUSH_LATENCY: AtomicU64 = AtomicU64::new(u64::MAX);
+
+#[cfg(feature = "emulate_latency")]
+static FENCE_LATENCY: AtomicU64 = AtomicU64::new(u64::MAX);
+
+#[cfg(feature = "emulate_latency")]
+fn latency_from_env(var: &AtomicU64, name: &str) -> u64 {
+    let val = var.load(Ordering::Relaxed);
+    if val != u64::MAX {
+        return val;
+    }
+    let val = std::env::var(name)
+        .unwrap_or("0".to_string())
+        .parse::<u64>()
+        .unwrap_or_else(|_| panic!("{} should be a non-negative integer", name));
+    var.store(val, Ordering::Relaxed);
+    val
+}
+
+/// Returns the emulated latency of flushing a cache line in nanoseconds
+///
+/// It is read from the `FLUSH_LATENCY` environment variable, unless it is
+/// set by [`set_emulated_latency()`](./fn.set_emulated_latency.html).
+#[cfg(feature = "emulate_latency")]
+pub fn flush_latency() -> u64 {
+    latency_from_env(&FLUSH_LATENCY, "FLUSH_LATENCY")
+}
+
+/// Returns the emulated latency of a store fence in nanoseconds
+///
+/// It is read from the `FENCE_LATENCY` environment variable, unless it is
+/// set by [`set_emulated_latency()`](./fn.set_emulated_latency.html).
+#[cfg(feature = "emulate_latency")]
+pub fn fence_latency() -> u64 {
+    latency_from_env(&FENCE_LATENCY, "FENCE_LATENCY")
+}
+
+/// Sets the emulated latencies of a cache-line flush and of a store fence
+/// in nanoseconds
+///
+/// With the `emulate_latency` feature, every flushed cache line and every
+/// fence busy-waits for the given time, so that the cost of persistence on
+/// a slower memory can be estimated on DRAM. Reasonable values are about
+/// 100ns per flush and 500ns per fence for Optane DC persistent memory, and
+/// 200-300ns per flush for CXL-attached memory. The latencies can also be set
+/// with the `FLUSH_LATENCY` and `FENCE_LATENCY` environment variables.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "emulate_latency")] {
+/// use corundum::ll;
+///
+/// ll::set_emulated_latency(100, 500);
+/// assert_eq!(ll::flush_latency(), 100);
+/// assert_eq!(ll::fence_latency(), 500);
+/// # }
+/// ```
+#[cfg(feature = "emulate_latency")]
+pub fn set_emulated_latency(flush_ns: u64, fence_ns: u64) {
+    FLUSH_LATENCY.store(flush_ns, Ordering::Relaxed);
+    FENCE_LATENCY.store(fence_ns, Ordering::Relaxed);
+}
+
+/// Busy-waits for `ns` nanoseconds; sleeping is too coarse for this purpose
+#[cfg(feature = "emulate_latency")]
+#[inline]
+fn emulate_delay(ns: u64) {
+    if ns == 0 {
+        return;
+    }
+    let start = std::time::Instant::now();
+    let dur = std::time::Duration::from_nanos(ns);
+    while start.elapsed() < dur {
+        std::hint::spin_loop();
+    }
+}
+
+/// The volatile synchronization primitives of the library
+///
+/// They are the `std` ones, or loom's models with `--cfg loom`.
+pub(crate) mod model {
+    #[cfg(not(loom))]
+    pub use std::sync::{atomic, Mutex};
+
+    #[cfg(not(loom))]
+    pub use std::thread;
+
+    #[cfg(loom)]
+    pub use loom::sync::{atomic, Mutex};
+
+    #[cfg(loom)]
+    pub use loom::thread;
+
+    /// Returns a non-zero id of the current thread, which the spinning locks
+    /// store as their owner
+    #[cfg(not(loom))]
+    #[inline]
+    pub fn thread_id() -> u64 {
+        std::thread::current().id().as_u64().get()
+    }
+
+    /// Under loom, the model threads share one OS thread, so the id comes
+    /// from loom
+    #[cfg(loom)]
+    pub fn thread_id() -> u64 {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        loom::thread::current().id().hash(&mut hasher);
+        hasher.finish() | 1
+    }
+
+    /// The body of a spinning loop; under loom, it yields to the other
+    /// threads, as loom does not preempt a spinning thread
+    #[inline(always)]
+    pub fn spin_loop() {
+        #[cfg(not(loom))]
+        std::hint::spin_loop();
+
+        #[cfg(loom)]
+        loom::thread::yield_now();
+    }
 }
@@ -0,0 +1,22 @@
+//! Shared access for the parallel iterators of the persistent collections
+
+/// A shared reference which may be sent to other threads
+///
+/// The persistent collections are not `Sync`, because they are updated
+/// through shared references inside transactions. A parallel iterator holds
+/// the collection borrowed while the worker threads read it; the caller's
+/// thread waits for them and no transaction can reach the collection from
+/// the workers, as their closures should be `Send` and `Sync`. The items
+/// should be `Sync` themselves.
+pub(crate) struct Scoped<'a, T: ?Sized>(pub &'a T);
+
+unsafe impl<T: ?Sized> Send for Scoped<'_, T> {}
+unsafe impl<T: ?Sized> Sync for Scoped<'_, T> {}
+
+impl<T: ?Sized> Clone for Scoped<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: ?Sized> Copy for Scoped<'_, T> {}
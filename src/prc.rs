@@ -153,6 +153,7 @@ use crate::clone::*;
 use crate::ptr::Ptr;
 use crate::stm::*;
 use crate::*;
+use crate::AllocError;
 use std::fmt::{self,Debug};
 use std::cmp::Ordering;
 use std::hash::Hash;
@@ -202,10 +203,23 @@ pub struct PrcBox<T: ?Sized, A: MemPool> {
     #[cfg(not(feature = "no_volatile_pointers"))]
     vlist: VCell<VWeakList, A>,
 
+    #[cfg(feature = "alloc_tags")]
+    tag: u64,
+
     dummy: [A; 0],
     value: T,
 }
 
+/// Returns a new allocation tag for pool `A`
+///
+/// The upper half is the generation of the pool, and the lower half is a
+/// volatile counter, so that the tag is not reused when the pool is reopened.
+#[cfg(feature = "alloc_tags")]
+pub(crate) fn new_tag<A: MemPool>() -> u64 {
+    static NEXT: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+    ((A::gen() as u64) << 32) | NEXT.fetch_add(1, Relaxed) as u64
+}
+
 unsafe impl<T: ?Sized, A: MemPool> PSafe for PrcBox<T, A> {}
 unsafe impl<T: ?Sized, A: MemPool> TxInSafe for PrcBox<T, A> {}
 impl<T: ?Sized, A: MemPool> UnwindSafe for PrcBox<T, A> {}
@@ -316,8 +330,31 @@ impl<T: PSafe, A: MemPool> Prc<T, A> {
     /// }).unwrap();
     /// ```
     pub fn new(value: T, journal: &Journal<A>) -> Prc<T, A> {
+        match Self::try_new(value, journal) {
+            Ok(rc) => rc,
+            Err(e) => panic!("{}", e)
+        }
+    }
+
+    /// Tries to construct a new `Prc<T>`. It returns an [`AllocError`]
+    /// instead of panicking if the pool is exhausted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::alloc::heap::*;
+    /// use corundum::prc::Prc;
+    ///
+    /// Heap::transaction(|j| {
+    ///     let five = Prc::try_new(5, j).unwrap();
+    ///     assert_eq!(*five, 5);
+    /// }).unwrap();
+    /// ```
+    ///
+    /// [`AllocError`]: ../struct.AllocError.html
+    pub fn try_new(value: T, journal: &Journal<A>) -> Result<Prc<T, A>, AllocError> {
         unsafe {
-            let ptr = Ptr::new_unchecked(A::new(
+            let ptr = Ptr::new_unchecked(A::try_new(
                 PrcBox::<T, A> {
                     counter: Counter {
                         strong: 1,
@@ -339,12 +376,15 @@ impl<T: PSafe, A: MemPool> Prc<T, A> {
                     #[cfg(not(feature = "no_volatile_pointers"))]
                     vlist: VCell::new(VWeakList::default()),
 
+                    #[cfg(feature = "alloc_tags")]
+                    tag: new_tag::<A>(),
+
                     dummy: [],
                     value,
                 },
                 journal,
-            ));
-            Self::from_inner(ptr)
+            )?);
+            Ok(Self::from_inner(ptr))
         }
     }
 
@@ -396,6 +436,9 @@ impl<T: PSafe, A: MemPool> Prc<T, A> {
                     #[cfg(not(feature = "no_volatile_pointers"))]
                     vlist: VCell::new(VWeakList::default()),
 
+                    #[cfg(feature = "alloc_tags")]
+                    tag: new_tag::<A>(),
+
                     dummy: [],
                     value: MaybeUninit::<T>::uninit(),
                 },
@@ -439,6 +482,78 @@ impl<T: PSafe, A: MemPool> Prc<T, A> {
         mem::forget(p);
         res
     }
+
+    /// Returns the inner value, if the `Prc` has exactly one strong reference
+    /// and no `Weak` reference.
+    ///
+    /// Otherwise, an [`Err`] is returned with the same `Prc` that was passed
+    /// in. The allocation is freed when the transaction commits.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::alloc::heap::*;
+    /// # type P = Heap;
+    /// use corundum::prc::Prc;
+    /// use corundum::clone::PClone;
+    ///
+    /// P::transaction(|j| {
+    ///     let x = Prc::new(3, j);
+    ///     assert_eq!(Prc::try_unwrap(x, j), Ok(3));
+    ///
+    ///     let x = Prc::new(4, j);
+    ///     let _y = Prc::pclone(&x, j);
+    ///     assert_eq!(*Prc::try_unwrap(x, j).unwrap_err(), 4);
+    /// }).unwrap();
+    /// ```
+    ///
+    /// [`Err`]: std::result::Result::Err
+    pub fn try_unwrap(this: Self, _journal: &Journal<A>) -> Result<T, Self> {
+        if !Prc::is_unique(&this) {
+            return Err(this);
+        }
+        unsafe {
+            let value = std::ptr::read(&this.ptr.value);
+
+            // Drops the counters and the allocation, but not the value
+            let uninit: Prc<MaybeUninit<T>, A> =
+                Prc::from_inner(mem::ManuallyDrop::new(this).ptr.cast());
+            drop(uninit);
+            Ok(value)
+        }
+    }
+
+    /// Converts the `Prc` into a [`Pbox`] by moving the value without
+    /// cloning, if it is uniquely owned (see [`try_unwrap`])
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::alloc::heap::*;
+    /// # type P = Heap;
+    /// use corundum::prc::Prc;
+    ///
+    /// P::transaction(|j| {
+    ///     let five = Prc::new(5, j);
+    ///     let five = Prc::try_into_pbox(five, j).unwrap();
+    ///     assert_eq!(*five, 5);
+    /// }).unwrap();
+    /// ```
+    ///
+    /// [`Pbox`]: ../struct.Pbox.html
+    /// [`try_unwrap`]: #method.try_unwrap
+    pub fn try_into_pbox(this: Self, journal: &Journal<A>) -> Result<Pbox<T, A>, Self> {
+        Prc::try_unwrap(this, journal).map(|v| Pbox::new(v, journal))
+    }
+
+    /// Converts the `Prc` into a [`Parc`] by moving the value without
+    /// cloning, if it is uniquely owned (see [`try_unwrap`])
+    ///
+    /// [`Parc`]: ../sync/struct.Parc.html
+    /// [`try_unwrap`]: #method.try_unwrap
+    pub fn try_into_parc(this: Self, journal: &Journal<A>) -> Result<crate::sync::Parc<T, A>, Self> {
+        Prc::try_unwrap(this, journal).map(|v| crate::sync::Parc::new(v, journal))
+    }
 }
 
 impl<T: PSafe + ?Sized, A: MemPool> Prc<T, A> {
@@ -599,6 +714,12 @@ impl<T: PSafe + ?Sized, A: MemPool> Prc<T, A> {
     ///     let _weak_five = Prc::downgrade(&five, j);
     /// }).unwrap()
     /// ```
+    ///
+    /// Since the weak count is persistent, it is updated in the journal. If
+    /// the weak pointer is not stored in the pool, use [`demote`] instead,
+    /// which neither needs a journal nor changes the counters.
+    ///
+    /// [`demote`]: #method.demote
     pub fn downgrade(this: &Self, journal: &Journal<A>) -> Weak<T, A> {
         this.inc_weak(journal);
         debug_assert!(!this.ptr.is_dangling());
@@ -685,6 +806,29 @@ impl<T: PSafe + ?Sized, A: MemPool> Prc<T, A> {
         this.strong()
     }
 
+    #[inline]
+    /// Gets the number of `Prc` and `Weak` pointers to this allocation as a
+    /// `(strong, weak)` pair.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::alloc::heap::*;
+    /// # type P = Heap;
+    /// use corundum::prc::Prc;
+    /// use corundum::clone::PClone;
+    ///
+    /// P::transaction(|j| {
+    ///     let five = Prc::new(5, j);
+    ///     let _also_five = Prc::pclone(&five, j);
+    ///     let _weak_five = Prc::downgrade(&five, j);
+    ///     assert_eq!((2, 1), Prc::counts(&five));
+    /// }).unwrap();
+    /// ```
+    pub fn counts(this: &Self) -> (usize, usize) {
+        (Prc::strong_count(this), Prc::weak_count(this))
+    }
+
     #[inline]
     fn is_unique(this: &Self) -> bool {
         Prc::weak_count(this) == 0 && Prc::strong_count(this) == 1
@@ -771,7 +915,7 @@ impl<T: PSafe, A: MemPool> Prc<T, A> {
             "Prc::initialize() cannot be used inside a transaction"
         );
         match rc {
-            Some(_) => Err("already initialized".to_string()),
+            Some(_) => Err(crate::Error::AlreadyInitialized),
             None => if A::valid(rc) {
                 unsafe {
                     let new = A::atomic_new(
@@ -795,6 +939,9 @@ impl<T: PSafe, A: MemPool> Prc<T, A> {
         
                             #[cfg(not(feature = "no_volatile_pointers"))]
                             vlist: VCell::new(VWeakList::default()),
+
+                            #[cfg(feature = "alloc_tags")]
+                            tag: new_tag::<A>(),
         
                             dummy: [],
                             value,
@@ -810,7 +957,7 @@ impl<T: PSafe, A: MemPool> Prc<T, A> {
                 }
                 Ok(())
             } else {
-                Err("The object is not in the PM".to_string())
+                Err(crate::Error::OutOfRange(rc as *const _ as u64))
             }
         }
     }
@@ -854,6 +1001,12 @@ unsafe impl<#[may_dangle] T: PSafe + ?Sized, A: MemPool> Drop for Prc<T, A> {
             let j = &*journal.0;
             self.dec_strong(j);
             if self.strong() == 0 { // TODO: Add "or it is unreachable from the root"
+                // A second panic while unwinding would abort; the transaction
+                // is rolled back in that case anyway
+                #[cfg(not(feature = "no_volatile_pointers"))]
+                assert!(self.ptr.vlist.pins == 0 || std::thread::panicking(),
+                    "cannot drop the last `Prc` while its value is being peeked");
+
                 // destroy the contained object
                 std::ptr::drop_in_place(&mut self.ptr.as_mut().value);
 
@@ -1357,6 +1510,11 @@ fn data_offset_align<A: MemPool>(align: usize) -> isize {
 ///
 /// The typical way to obtain a `VWeak` pointer is to call [`Prc::demote`].
 ///
+/// With the `alloc_tags` feature, every allocation is tagged, and a `VWeak`
+/// is promoted only if the tag is unchanged. It covers the blocks which are
+/// freed without being dropped, e.g., when the allocating transaction rolls
+/// back, so that the pointer never aliases a new object at the same offset.
+///
 /// [`Prc`]: struct.Prc.html
 /// [`Weak`]: struct.Weak.html
 /// [`Prc::downgrade`]: ./struct.Prc.html#method.downgrade
@@ -1367,6 +1525,9 @@ pub struct VWeak<T: ?Sized, A: MemPool> {
     ptr: *const PrcBox<T, A>,
     valid: *mut VWeakValid,
     gen: u32,
+
+    #[cfg(feature = "alloc_tags")]
+    tag: u64,
 }
 
 impl<T: ?Sized, A: MemPool> !Send for VWeak<T, A> {}
@@ -1385,6 +1546,9 @@ impl<T: PSafe + ?Sized, A: MemPool> VWeak<T, A> {
             ptr: prc.ptr.as_ref(),
             valid: list.append(),
             gen: A::gen(),
+
+            #[cfg(feature = "alloc_tags")]
+            tag: prc.ptr.tag,
         }
     }
 
@@ -1393,6 +1557,9 @@ impl<T: PSafe + ?Sized, A: MemPool> VWeak<T, A> {
             ptr: std::ptr::null(),
             valid: std::ptr::null_mut(),
             gen: u32::MAX,
+
+            #[cfg(feature = "alloc_tags")]
+            tag: 0,
         }
     }
 
@@ -1406,10 +1573,76 @@ impl<T: PSafe + ?Sized, A: MemPool> VWeak<T, A> {
         }
     }
 
+    /// Calls `f` with a shared reference to the inner value without a
+    /// transaction, if the value is not dropped yet
+    ///
+    /// It is useful for read-only access, as it neither needs a journal nor
+    /// changes the reference counters. The value is pinned while `f` is
+    /// running, and dropping the last [`Prc`] in the meantime (e.g., in a
+    /// transaction inside `f`) panics. Any modification should still be done
+    /// in a transaction after [`promote`]ing the pointer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::default::*;
+    ///
+    /// type P = Allocator;
+    ///
+    /// let root = P::open::<Prc<PCell<i32>>>("foo.pool", O_CF).unwrap();
+    /// let vweak = Prc::demote(&root);
+    ///
+    /// assert_eq!(vweak.peek(|v| v.get()), Some(0));
+    ///
+    /// P::transaction(|j| {
+    ///     if let Some(root) = vweak.promote(j) {
+    ///         root.set(10, j);
+    ///     }
+    /// }).unwrap();
+    ///
+    /// assert_eq!(vweak.peek(|v| v.get()), Some(10));
+    /// ```
+    ///
+    /// [`Prc`]: ./struct.Prc.html
+    /// [`promote`]: #method.promote
+    pub fn peek<R, F: FnOnce(&T) -> R>(&self, f: F) -> Option<R> {
+        let inner = self.inner()?;
+        if inner.counter.strong == 0 {
+            return None;
+        }
+        let vlist = inner.vlist.as_mut();
+        vlist.pins += 1;
+        let _pin = PeekPin(&mut vlist.pins);
+        Some(f(&inner.value))
+    }
+
+    /// Returns `true` if the two `VWeak`s point to the same allocation, or
+    /// if both are null
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::alloc::heap::*;
+    /// # type P = Heap;
+    /// use corundum::prc::Prc;
+    ///
+    /// P::transaction(|j| {
+    ///     let five = Prc::new(5, j);
+    ///     let other_five = Prc::new(5, j);
+    ///
+    ///     assert!(Prc::demote(&five).ptr_eq(&Prc::demote(&five)));
+    ///     assert!(!Prc::demote(&five).ptr_eq(&Prc::demote(&other_five)));
+    /// }).unwrap();
+    /// ```
+    #[inline]
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.ptr as *const u8, other.ptr as *const u8)
+    }
+
     #[inline]
     fn inner(&self) -> Option<&PrcBox<T, A>> {
         unsafe {
-            if self.gen != A::gen() {
+            if self.gen != A::gen() || !self.same_tag() {
                 None
             } else if !(*self.valid).valid {
                 None
@@ -1420,9 +1653,25 @@ impl<T: PSafe + ?Sized, A: MemPool> VWeak<T, A> {
     }
 }
 
+impl<T: ?Sized, A: MemPool> VWeak<T, A> {
+    /// Indicates if the block is still tagged the same as when the `VWeak`
+    /// was created. A block may be freed without dropping its value, e.g., by
+    /// rolling back the transaction which allocated it; then, the `VWeak`
+    /// would still be valid, but the block may belong to a new object.
+    #[inline]
+    fn same_tag(&self) -> bool {
+        #[cfg(feature = "alloc_tags")] {
+            unsafe { (*self.ptr).tag == self.tag }
+        }
+        #[cfg(not(feature = "alloc_tags"))] {
+            true
+        }
+    }
+}
+
 impl<T: PSafe + ?Sized, A: MemPool> Clone for VWeak<T, A> {
     fn clone(&self) -> Self {
-        if self.gen == A::gen() {
+        if self.gen == A::gen() && self.same_tag() {
             unsafe { 
                 if (*self.valid).valid {
                     let list = (*self.ptr).vlist.as_mut();
@@ -1430,6 +1679,9 @@ impl<T: PSafe + ?Sized, A: MemPool> Clone for VWeak<T, A> {
                         ptr: self.ptr,
                         valid: list.append(),
                         gen: self.gen,
+
+                        #[cfg(feature = "alloc_tags")]
+                        tag: self.tag,
                     };  
                 }
             }
@@ -1438,6 +1690,9 @@ impl<T: PSafe + ?Sized, A: MemPool> Clone for VWeak<T, A> {
             ptr: self.ptr,
             valid: self.valid,
             gen: self.gen,
+
+            #[cfg(feature = "alloc_tags")]
+            tag: self.tag,
         }
     }
 }
@@ -1448,7 +1703,7 @@ impl<T: ?Sized, A: MemPool> Drop for VWeak<T, A> {
         unsafe {
             let this = &mut *self.valid;
             if A::is_open() {
-                if self.gen == A::gen() {
+                if self.gen == A::gen() && self.same_tag() {
                     if !this.list.is_null() {
                         let head = &mut (*this.list).head;
                         if this.prev.is_null() {
@@ -1475,6 +1730,17 @@ struct VWeakValid {
 
 struct VWeakList {
     head: *mut VWeakValid,
+
+    /// The number of ongoing `VWeak::peek` calls
+    pins: usize,
+}
+
+struct PeekPin<'a>(&'a mut usize);
+
+impl Drop for PeekPin<'_> {
+    fn drop(&mut self) {
+        *self.0 -= 1;
+    }
 }
 
 impl VWeakList {
@@ -1499,6 +1765,7 @@ impl Default for VWeakList {
     fn default() -> Self {
         VWeakList {
             head: std::ptr::null_mut(),
+            pins: 0,
         }
     }
 }
@@ -0,0 +1,339 @@
+use crate::alloc::MemPool;
+use crate::cell::LazyCell;
+use crate::result::Result;
+use crate::stm::Journal;
+use crate::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::mem;
+use std::sync::Mutex;
+
+/// A generation-tagged handle to a `T` in pool `A`
+///
+/// A `Handle` is a plain value which can be kept out of the pool, e.g., in
+/// volatile indices or on the other side of an FFI boundary. Unlike a raw
+/// pointer, it remembers the [generation] of the pool. It is validated
+/// whenever it is resolved, so a stale handle resolves to `None` if
+///
+/// * the pool is closed, or it is reopened since the handle was created; or
+/// * the block is deallocated.
+///
+/// A `Handle` does not own the object or keep it alive, and it cannot tell
+/// if the block is allocated again to another object. [`VWeak`] pointers
+/// are notified when the object is dropped; with the `alloc_tags` feature,
+/// they also check the allocation tag stored next to the object, so that
+/// they never alias a new object.
+///
+/// # Examples
+///
+/// ```
+/// use corundum::default::*;
+/// use corundum::ptr::Handle;
+///
+/// type P = Allocator;
+///
+/// let root = P::open::<PCell<i32>>("foo.pool", O_CF).unwrap();
+/// let handle = Handle::new(&*root).unwrap();
+///
+/// P::transaction(|j| {
+///     assert_eq!(handle.get(j).map(|v| v.get()), Some(0));
+/// }).unwrap();
+/// ```
+///
+/// [generation]: ../alloc/trait.MemPoolTraits.html#method.gen
+/// [`VWeak`]: ../prc/struct.VWeak.html
+#[repr(C)]
+pub struct Handle<T: PSafe, A: MemPool> {
+    off: u64,
+    gen: u32,
+    phantom: PhantomData<(*const T, A)>,
+}
+
+unsafe impl<T: PSafe, A: MemPool> Send for Handle<T, A> {}
+unsafe impl<T: PSafe, A: MemPool> Sync for Handle<T, A> {}
+unsafe impl<T: PSafe, A: MemPool> TxInSafe for Handle<T, A> {}
+unsafe impl<T: PSafe, A: MemPool> TxOutSafe for Handle<T, A> {}
+impl<T: PSafe, A: MemPool> !PSafe for Handle<T, A> {}
+
+impl<T: PSafe, A: MemPool> Handle<T, A> {
+    /// Creates a handle to `obj`
+    ///
+    /// # Errors
+    ///
+    /// * [`OutOfRange`] if `obj` is not in the pool.
+    /// * [`AccessViolation`] if `obj` is not in an allocated block.
+    ///
+    /// [`OutOfRange`]: ../enum.Error.html#variant.OutOfRange
+    /// [`AccessViolation`]: ../enum.Error.html#variant.AccessViolation
+    pub fn new(obj: &T) -> Result<Self> {
+        let off = A::off(obj)?;
+        if !A::allocated(off, mem::size_of::<T>()) {
+            return Err(Error::AccessViolation(off));
+        }
+        Ok(Self {
+            off,
+            gen: A::gen(),
+            phantom: PhantomData,
+        })
+    }
+
+    /// Returns the offset of the object in the pool
+    #[inline]
+    pub fn off(&self) -> u64 {
+        self.off
+    }
+
+    /// Returns the generation of the pool in which the handle was created
+    #[inline]
+    pub fn gen(&self) -> u32 {
+        self.gen
+    }
+
+    /// Indicates if the handle still refers to the same object
+    pub fn is_valid(&self) -> bool {
+        A::is_open()
+            && self.gen == A::gen()
+            && A::allocated(self.off, mem::size_of::<T>())
+    }
+
+    /// Resolves the handle to a shared reference which lives as long as the
+    /// transaction, or `None` if the handle is stale
+    pub fn get<'a>(&self, _journal: &'a Journal<A>) -> Option<&'a T> {
+        if self.is_valid() {
+            Some(unsafe { A::get_unchecked(self.off) })
+        } else {
+            None
+        }
+    }
+}
+
+impl<T: PSafe, A: MemPool> Copy for Handle<T, A> {}
+
+impl<T: PSafe, A: MemPool> Clone for Handle<T, A> {
+    #[inline]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: PSafe, A: MemPool> PartialEq for Handle<T, A> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.off == other.off && self.gen == other.gen
+    }
+}
+
+impl<T: PSafe, A: MemPool> Eq for Handle<T, A> {}
+
+impl<T: PSafe, A: MemPool> Hash for Handle<T, A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.off, self.gen).hash(state)
+    }
+}
+
+impl<T: PSafe, A: MemPool> fmt::Debug for Handle<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handle(@{}, gen: {})", self.off, self.gen)
+    }
+}
+
+struct Slot {
+    off: u64,
+    len: usize,
+    version: u32,
+    ty: Option<&'static str>,
+    used: bool,
+}
+
+#[derive(Default)]
+struct HandleTable {
+    /// The generation of the pool in which the handles were registered
+    gen: u32,
+    slots: Vec<Slot>,
+    free: Vec<usize>,
+
+    /// The slot of every registered object, so that an object has only one
+    /// handle
+    objs: HashMap<(u64, usize, Option<&'static str>), usize>,
+}
+
+impl HandleTable {
+    /// Invalidates all handles if the pool is reopened since they were
+    /// registered. The objects may have been freed and their blocks reused
+    /// while the pool was closed, so the handles cannot be trusted anymore.
+    fn check_gen(&mut self, gen: u32) {
+        if self.gen != gen {
+            for (idx, slot) in self.slots.iter_mut().enumerate() {
+                if slot.used {
+                    slot.used = false;
+                    self.free.push(idx);
+                }
+                slot.version = slot.version.wrapping_add(1);
+            }
+            self.objs.clear();
+            self.gen = gen;
+        }
+    }
+
+    fn release(&mut self, idx: usize) {
+        let slot = &mut self.slots[idx];
+        slot.used = false;
+        self.objs.remove(&(slot.off, slot.len, slot.ty));
+        self.free.push(idx);
+    }
+}
+
+/// The handle table of every pool. Handles are kept in volatile memory, and
+/// they are valid until the process exits, they are unregistered, or the
+/// pool is reopened.
+static mut HANDLES: LazyCell<Mutex<HashMap<&'static str, HandleTable>>> =
+    LazyCell::new(|| Mutex::new(HashMap::new()));
+
+fn with_table<A: MemPool, R>(f: impl FnOnce(&mut HandleTable) -> R) -> R {
+    let mut tables = match unsafe { HANDLES.lock() } {
+        Ok(g) => g,
+        Err(p) => p.into_inner()
+    };
+    let table = tables.entry(A::name()).or_default();
+    if A::is_open() {
+        table.check_gen(A::gen());
+    }
+    f(table)
+}
+
+/// Splits an opaque handle into its slot index and version. Handle 0 is
+/// never assigned, so that it can be used as a null handle.
+fn split(handle: u64) -> Option<(usize, u32)> {
+    let idx = (handle & 0xffff_ffff) as usize;
+    if idx == 0 {
+        None
+    } else {
+        Some((idx - 1, (handle >> 32) as u32))
+    }
+}
+
+fn join(idx: usize, version: u32) -> u64 {
+    ((version as u64) << 32) | (idx as u64 + 1)
+}
+
+/// Registers the `len` bytes at offset `off` of pool `A` in its handle
+/// table, and returns an opaque handle to them. If they are already
+/// registered with the same type, the existing handle is returned.
+pub(crate) fn register_handle<A: MemPool>(off: u64, len: usize, ty: Option<&'static str>) -> u64 {
+    with_table::<A, _>(|t| {
+        if let Some(&idx) = t.objs.get(&(off, len, ty)) {
+            return join(idx, t.slots[idx].version);
+        }
+        let idx = if let Some(idx) = t.free.pop() {
+            let slot = &mut t.slots[idx];
+            slot.version = slot.version.wrapping_add(1);
+            idx
+        } else {
+            assert!(t.slots.len() < u32::MAX as usize, "handle table is full");
+            t.slots.push(Slot { off, len, version: 0, ty, used: false });
+            t.slots.len() - 1
+        };
+        let slot = &mut t.slots[idx];
+        slot.off = off;
+        slot.len = len;
+        slot.ty = ty;
+        slot.used = true;
+        t.objs.insert((off, len, ty), idx);
+        join(idx, slot.version)
+    })
+}
+
+/// Looks up `handle` in the handle table of pool `A`, and returns the offset
+/// of the object if it is still allocated. If `ty` is given, it should match
+/// the type with which the object was registered.
+pub(crate) fn lookup_handle<A: MemPool>(handle: u64, ty: Option<&'static str>) -> Option<u64> {
+    let (idx, version) = split(handle)?;
+    if !A::is_open() {
+        return None;
+    }
+    with_table::<A, _>(|t| {
+        let slot = t.slots.get(idx)?;
+        if !slot.used || slot.version != version || !A::allocated(slot.off, slot.len) {
+            return None;
+        }
+        if let (Some(expected), Some(actual)) = (ty, slot.ty) {
+            if expected != actual {
+                return None;
+            }
+        }
+        Some(slot.off)
+    })
+}
+
+/// Removes `handle` from the handle table of pool `A`
+pub(crate) fn unregister_handle<A: MemPool>(handle: u64) -> bool {
+    let (idx, version) = match split(handle) {
+        Some(v) => v,
+        None => return false,
+    };
+    with_table::<A, _>(|t| match t.slots.get(idx) {
+        Some(slot) if slot.used && slot.version == version => {
+            t.release(idx);
+            true
+        }
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::default::*;
+    use crate::ptr::Handle;
+
+    type P = Allocator;
+
+    #[test]
+    fn stale_handle() {
+        let _pool = P::open_no_root("handle.pool", O_CF).unwrap();
+        let handle = P::transaction(|j| {
+            let b = Pbox::new(42u64, j);
+            let handle = Handle::new(&*b).unwrap();
+            assert_eq!(handle.get(j), Some(&42));
+            handle
+        }).unwrap();
+
+        // The box is dropped at the end of the transaction
+        assert!(!handle.is_valid());
+        P::transaction(|j| assert!(handle.get(j).is_none())).unwrap();
+    }
+
+    #[test]
+    fn handle_table() {
+        let root = P::open::<PCell<u64>>("handle_table.pool", O_CF).unwrap();
+        let h = P::register(&*root).unwrap();
+        P::transaction(|j| {
+            assert_eq!(P::resolve::<PCell<u64>>(h, j).map(|v| v.get()), Some(0));
+            assert!(P::resolve::<u32>(h, j).is_none());
+        }).unwrap();
+        assert_eq!(P::resolve_off(h), P::off(&*root).ok());
+
+        assert!(P::unregister(h));
+        assert!(!P::unregister(h));
+        assert_eq!(P::resolve_off(h), None);
+        assert_ne!(P::register(&*root).unwrap(), h);
+
+        // The box is dropped at the end of the transaction
+        let stale = P::transaction(|j| P::register(&*Pbox::new(1u64, j)).unwrap()).unwrap();
+        assert_eq!(P::resolve_off(stale), None);
+    }
+
+    #[test]
+    fn reopen_invalidates_handles() {
+        let root = P::open::<PCell<u64>>("handle_reopen.pool", O_CF).unwrap();
+        let h = P::register(&*root).unwrap();
+        assert_eq!(P::register(&*root).unwrap(), h);
+        drop(root);
+
+        let root = P::open::<PCell<u64>>("handle_reopen.pool", 0).unwrap();
+        assert_eq!(P::resolve_off(h), None);
+        assert!(!P::unregister(h));
+        assert_ne!(P::register(&*root).unwrap(), h);
+    }
+}
@@ -0,0 +1,345 @@
+use crate::alloc::MemPool;
+use crate::clone::PClone;
+use crate::result::Result;
+use crate::stm::{Journal, Logger, Notifier};
+use crate::*;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::mem;
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for u32 {}
+    impl Sealed for u64 {}
+}
+
+/// The integer types which can hold a [`PIndex`]
+///
+/// It is implemented for `u32` and `u64`.
+///
+/// [`PIndex`]: ./struct.PIndex.html
+pub trait IndexWidth: Copy + Eq + Ord + Hash + fmt::Debug + private::Sealed {
+    /// The value reserved for the null index
+    const NULL: Self;
+
+    /// Converts `v` to this width, if it fits
+    fn from_u64(v: u64) -> Option<Self>;
+
+    /// Converts the index to `u64`
+    fn to_u64(self) -> u64;
+}
+
+impl IndexWidth for u32 {
+    const NULL: Self = u32::MAX;
+
+    #[inline]
+    fn from_u64(v: u64) -> Option<Self> {
+        if v < u32::MAX as u64 { Some(v as u32) } else { None }
+    }
+
+    #[inline]
+    fn to_u64(self) -> u64 {
+        self as u64
+    }
+}
+
+impl IndexWidth for u64 {
+    const NULL: Self = u64::MAX;
+
+    #[inline]
+    fn from_u64(v: u64) -> Option<Self> {
+        if v < u64::MAX { Some(v) } else { None }
+    }
+
+    #[inline]
+    fn to_u64(self) -> u64 {
+        self
+    }
+}
+
+/// A typed offset of a `T` in pool `A`
+///
+/// `PIndex` is a compact alternative to persistent pointers for building
+/// index structures (e.g., arrays of indices or intrusive links). It does not
+/// own the object and does not count references; it only remembers where the
+/// object is. The offset is stored in units of the alignment of `T` in an
+/// integer of type `I` (`u64` by default), so a `PIndex<T, A, u32>` takes 4
+/// bytes and can address `4 GiB * align_of::<T>()` of the pool.
+///
+/// An index is obtained from a reference to an object in the pool using
+/// [`from_ref()`], and is resolved back to a reference inside a transaction
+/// using [`resolve()`], which checks that the index still points to an
+/// allocated block. It is up to the data structure to keep the object alive
+/// while it is indexed.
+///
+/// # Compact pointers
+///
+/// [`Ptr`] and the smart pointers built on it keep 64-bit offsets, and so do
+/// the reference counters and the journal entries. The allocator updates them
+/// with 64-bit atomic redo logs, so their width cannot be reduced without
+/// changing the pool layout. For pointer-heavy structures, `PIndex<T, A, u32>`
+/// is the compact alternative. It can address up to [`addressable()`] bytes
+/// of any pool, and fails at runtime beyond that; it never fails for a pool
+/// type whose [`max_size`] is at most 4 GiB.
+///
+/// # Examples
+///
+/// ```
+/// use corundum::default::*;
+/// use corundum::ptr::PIndex;
+///
+/// type P = Allocator;
+///
+/// let _pool = P::open_no_root("foo.pool", O_CF).unwrap();
+///
+/// P::transaction(|j| {
+///     let mut items = PVec::new();
+///     for v in &[30, 10, 20] {
+///         items.push(Pbox::new(PCell::new(*v), j), j);
+///     }
+///
+///     // 4 bytes per entry
+///     let mut index: PVec<PIndex<PCell<u64>, P, u32>> = PVec::new();
+///     for item in items.iter() {
+///         index.push(PIndex::from_ref(&**item).unwrap(), j);
+///     }
+///
+///     let second = index[1].resolve(j).unwrap();
+///     assert_eq!(second.get(), 10);
+///     second.set(15, j); // mutation goes through interior mutability
+///     assert_eq!(items[1].get(), 15);
+/// }).unwrap();
+/// ```
+///
+/// [`from_ref()`]: #method.from_ref
+/// [`resolve()`]: #method.resolve
+/// [`Ptr`]: ./struct.Ptr.html
+/// [`max_size`]: ../alloc/struct.PoolConfig.html#method.max_size
+/// [`addressable()`]: #method.addressable
+#[repr(transparent)]
+pub struct PIndex<T: PSafe, A: MemPool, I: IndexWidth = u64> {
+    raw: I,
+    phantom: PhantomData<(*const T, A)>,
+}
+
+unsafe impl<T: PSafe, A: MemPool, I: IndexWidth> PSafe for PIndex<T, A, I> {}
+unsafe impl<T: PSafe, A: MemPool, I: IndexWidth> TxInSafe for PIndex<T, A, I> {}
+impl<T: PSafe, A: MemPool, I: IndexWidth> !VSafe for PIndex<T, A, I> {}
+impl<T: PSafe, A: MemPool, I: IndexWidth> !Send for PIndex<T, A, I> {}
+impl<T: PSafe, A: MemPool, I: IndexWidth> !Sync for PIndex<T, A, I> {}
+
+impl<T: PSafe, A: MemPool, I: IndexWidth> PIndex<T, A, I> {
+    /// Returns the null index which does not point to any object
+    #[inline]
+    pub fn null() -> Self {
+        Self { raw: I::NULL, phantom: PhantomData }
+    }
+
+    /// Indicates if the index is null
+    #[inline]
+    pub fn is_null(&self) -> bool {
+        self.raw == I::NULL
+    }
+
+    /// Creates an index of `obj`
+    ///
+    /// # Errors
+    ///
+    /// * [`OutOfRange`] if `obj` is not in the pool, or its offset does not
+    ///   fit in `I`.
+    /// * [`AccessViolation`] if `obj` is not in an allocated block.
+    ///
+    /// [`OutOfRange`]: ../enum.Error.html#variant.OutOfRange
+    /// [`AccessViolation`]: ../enum.Error.html#variant.AccessViolation
+    pub fn from_ref(obj: &T) -> Result<Self> {
+        let off = A::off(obj)?;
+        if !A::allocated(off, mem::size_of::<T>()) {
+            return Err(Error::AccessViolation(off));
+        }
+        match I::from_u64(off / Self::unit()) {
+            Some(raw) => Ok(Self { raw, phantom: PhantomData }),
+            None => Err(Error::OutOfRange(off))
+        }
+    }
+
+    /// Creates an index from its raw value
+    ///
+    /// # Safety
+    ///
+    /// `raw` should be obtained from [`raw()`](#method.raw) of an index to a
+    /// `T` in the same pool.
+    #[inline]
+    pub unsafe fn from_raw(raw: I) -> Self {
+        Self { raw, phantom: PhantomData }
+    }
+
+    /// Returns the raw value of the index
+    #[inline]
+    pub fn raw(&self) -> I {
+        self.raw
+    }
+
+    /// Returns the offset of the object in the pool, or `None` if the index
+    /// is null
+    #[inline]
+    pub fn off(&self) -> Option<u64> {
+        if self.is_null() {
+            None
+        } else {
+            Some(self.raw.to_u64() * Self::unit())
+        }
+    }
+
+    /// Returns the number of bytes from the beginning of the pool in which
+    /// every `T` can be indexed
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::default::*;
+    /// use corundum::ptr::PIndex;
+    ///
+    /// assert_eq!(PIndex::<u64, Allocator, u32>::addressable(), 8 * (u32::MAX as u64));
+    /// ```
+    #[inline]
+    pub fn addressable() -> u64 {
+        I::NULL.to_u64().saturating_mul(Self::unit())
+    }
+
+    #[inline]
+    fn unit() -> u64 {
+        mem::align_of::<T>() as u64
+    }
+
+    fn checked_off(&self) -> Result<u64> {
+        let off = self.off()
+            .ok_or_else(|| Error::InvalidArgument("null index".to_string()))?;
+        if !A::allocated(off, mem::size_of::<T>()) {
+            return Err(Error::AccessViolation(off));
+        }
+        Ok(off)
+    }
+
+    /// Resolves the index to a shared reference which lives as long as the
+    /// transaction
+    ///
+    /// # Errors
+    ///
+    /// * [`InvalidArgument`] if the index is null.
+    /// * [`AccessViolation`] if the index does not point to an allocated
+    ///   block.
+    ///
+    /// [`InvalidArgument`]: ../enum.Error.html#variant.InvalidArgument
+    /// [`AccessViolation`]: ../enum.Error.html#variant.AccessViolation
+    pub fn resolve<'a>(&self, _journal: &'a Journal<A>) -> Result<&'a T> {
+        let off = self.checked_off()?;
+        Ok(unsafe { A::get_unchecked(off) })
+    }
+
+    /// Resolves the index to a mutable reference after taking a log of the
+    /// object
+    ///
+    /// # Safety
+    ///
+    /// There should be no other reference to the object while the returned
+    /// reference is alive. For safe mutation, use types with interior
+    /// mutability (e.g., [`PCell`]) and [`resolve()`](#method.resolve).
+    ///
+    /// [`PCell`]: ../cell/struct.PCell.html
+    pub unsafe fn resolve_mut<'a>(&self, journal: &'a Journal<A>) -> Result<&'a mut T> {
+        let off = self.checked_off()?;
+        let obj = A::get_mut_unchecked::<T>(off);
+        obj.create_log(journal, Notifier::None);
+        Ok(obj)
+    }
+}
+
+impl<T: PSafe, A: MemPool, I: IndexWidth> Copy for PIndex<T, A, I> {}
+
+impl<T: PSafe, A: MemPool, I: IndexWidth> Clone for PIndex<T, A, I> {
+    #[inline]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: PSafe, A: MemPool, I: IndexWidth> PClone<A> for PIndex<T, A, I> {
+    #[inline]
+    fn pclone(&self, _j: &Journal<A>) -> Self {
+        *self
+    }
+}
+
+impl<T: PSafe, A: MemPool, I: IndexWidth> Default for PIndex<T, A, I> {
+    fn default() -> Self {
+        Self::null()
+    }
+}
+
+impl<T: PSafe, A: MemPool, I: IndexWidth> PartialEq for PIndex<T, A, I> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.raw == other.raw
+    }
+}
+
+impl<T: PSafe, A: MemPool, I: IndexWidth> Eq for PIndex<T, A, I> {}
+
+impl<T: PSafe, A: MemPool, I: IndexWidth> PartialOrd for PIndex<T, A, I> {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: PSafe, A: MemPool, I: IndexWidth> Ord for PIndex<T, A, I> {
+    #[inline]
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.raw.cmp(&other.raw)
+    }
+}
+
+impl<T: PSafe, A: MemPool, I: IndexWidth> Hash for PIndex<T, A, I> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.raw.hash(state)
+    }
+}
+
+impl<T: PSafe, A: MemPool, I: IndexWidth> fmt::Debug for PIndex<T, A, I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.off() {
+            Some(off) => write!(f, "PIndex(@{})", off),
+            None => write!(f, "PIndex(null)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::default::*;
+    use crate::ptr::PIndex;
+    use crate::Error;
+
+    type P = Allocator;
+
+    #[test]
+    fn pindex_resolve() {
+        let _pool = P::open_no_root("pindex.pool", O_CF).unwrap();
+        P::transaction(|j| {
+            let b = Pbox::new(42u64, j);
+            let i = PIndex::<u64, P, u32>::from_ref(&*b).unwrap();
+            assert_eq!(std::mem::size_of_val(&i), 4);
+            assert_eq!(i.off(), Some(b.off()));
+            assert_eq!(*i.resolve(j).unwrap(), 42);
+            unsafe { *i.resolve_mut(j).unwrap() = 7; }
+            assert_eq!(*b, 7);
+
+            let null = PIndex::<u64, P>::default();
+            assert!(null.is_null());
+            assert!(matches!(null.resolve(j), Err(Error::InvalidArgument(_))));
+            assert!(PIndex::<u64, P>::from_ref(&0u64).is_err());
+        }).unwrap();
+    }
+}
@@ -0,0 +1,287 @@
+use crate::alloc::MemPool;
+use crate::clone::PClone;
+use crate::result::Result;
+use crate::stm::{Journal, Logger, Notifier};
+use crate::*;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::mem;
+use std::num::NonZeroU64;
+
+/// A non-null persistent pointer to a `T` in pool `A`
+///
+/// `PLink` keeps the offset of an object in the pool. Offset `0` belongs
+/// to the pool header and never holds an object, so it is used as the niche
+/// of the pointer: `Option<PLink<T, A>>` has the same size as `PLink`
+/// (8 bytes), and `None` is the null link. This makes it the canonical
+/// nullable link of user data structures, such as the `next` pointer of a
+/// list node.
+///
+/// Like [`PIndex`], it neither owns the object nor counts references. It is
+/// up to the data structure to keep the object alive while it is linked, and
+/// to free it (e.g., by keeping the owning [`Pbox`] elsewhere, or by turning
+/// the link back into a `Pbox` when it is unlinked).
+///
+/// The links stored in persistent memory are updated with [`replace()`],
+/// [`swap()`], and [`take()`], which take a log of the link before writing
+/// it. Only the 8-byte link is logged, not the object it points to.
+///
+/// Since the link does not keep the object alive, it does not dereference
+/// implicitly. [`as_ref()`] and [`as_mut()`] are `unsafe`, and the caller
+/// should make sure that the object is not freed. For a volatile pointer
+/// with logging capability, see [`LogNonNull`] (the `PNonNull` alias in the
+/// pool modules).
+///
+/// # Examples
+///
+/// ```
+/// use corundum::default::*;
+/// use corundum::ptr::PLink;
+///
+/// type P = Allocator;
+/// type Link = Option<PLink<Node, P>>;
+///
+/// struct Node {
+///     val: u64,
+///     next: Link,
+/// }
+///
+/// assert_eq!(std::mem::size_of::<Link>(), 8);
+///
+/// let _pool = P::open_no_root("foo.pool", O_CF).unwrap();
+///
+/// P::transaction(|j| {
+///     let a = Pbox::new(Node { val: 1, next: None }, j);
+///     let mut b = Pbox::new(Node { val: 2, next: None }, j);
+///
+///     let old = PLink::replace(&mut b.next, PLink::from_ref(&*a).ok(), j);
+///     assert!(old.is_none());
+///     // `a` is still alive
+///     assert_eq!(unsafe { b.next.unwrap().as_ref(j) }.val, 1);
+/// }).unwrap();
+/// ```
+///
+/// [`PIndex`]: ./struct.PIndex.html
+/// [`Pbox`]: ../boxed/struct.Pbox.html
+/// [`replace()`]: #method.replace
+/// [`swap()`]: #method.swap
+/// [`take()`]: #method.take
+/// [`as_ref()`]: #method.as_ref
+/// [`as_mut()`]: #method.as_mut
+/// [`LogNonNull`]: ./struct.LogNonNull.html
+#[repr(transparent)]
+pub struct PLink<T: PSafe, A: MemPool> {
+    off: NonZeroU64,
+    phantom: PhantomData<(*const T, A)>,
+}
+
+unsafe impl<T: PSafe, A: MemPool> PSafe for PLink<T, A> {}
+unsafe impl<T: PSafe, A: MemPool> TxInSafe for PLink<T, A> {}
+impl<T: PSafe, A: MemPool> !VSafe for PLink<T, A> {}
+impl<T: PSafe, A: MemPool> !Send for PLink<T, A> {}
+impl<T: PSafe, A: MemPool> !Sync for PLink<T, A> {}
+
+impl<T: PSafe, A: MemPool> PLink<T, A> {
+    /// Creates a pointer to `obj`
+    ///
+    /// # Errors
+    ///
+    /// * [`OutOfRange`] if `obj` is not in the pool.
+    /// * [`AccessViolation`] if `obj` is not in an allocated block.
+    ///
+    /// [`OutOfRange`]: ../enum.Error.html#variant.OutOfRange
+    /// [`AccessViolation`]: ../enum.Error.html#variant.AccessViolation
+    pub fn from_ref(obj: &T) -> Result<Self> {
+        let off = A::off(obj)?;
+        if !A::allocated(off, mem::size_of::<T>()) {
+            return Err(Error::AccessViolation(off));
+        }
+        match NonZeroU64::new(off) {
+            Some(off) => Ok(Self { off, phantom: PhantomData }),
+            None => Err(Error::OutOfRange(off)),
+        }
+    }
+
+    /// Creates a pointer from the offset of the object in the pool
+    ///
+    /// # Safety
+    ///
+    /// `off` should be the non-zero offset of a `T` in pool `A`.
+    #[inline]
+    pub const unsafe fn from_off_unchecked(off: u64) -> Self {
+        Self { off: NonZeroU64::new_unchecked(off), phantom: PhantomData }
+    }
+
+    /// Returns the offset of the object in the pool
+    #[inline]
+    pub fn off(&self) -> u64 {
+        self.off.get()
+    }
+
+    /// Returns a reference to the object which lives as long as the
+    /// transaction
+    ///
+    /// # Safety
+    ///
+    /// The object should not be freed while the returned reference is alive.
+    #[inline]
+    pub unsafe fn as_ref<'a>(&self, _journal: &'a Journal<A>) -> &'a T {
+        A::get_unchecked(self.off())
+    }
+
+    /// Returns a mutable reference to the object after taking a log of it
+    ///
+    /// # Safety
+    ///
+    /// The object should not be freed, and there should be no other reference
+    /// to it while the returned reference is alive.
+    pub unsafe fn as_mut<'a>(&self, journal: &'a Journal<A>) -> &'a mut T {
+        let obj = A::get_mut_unchecked::<T>(self.off());
+        obj.create_log(journal, Notifier::None);
+        obj
+    }
+
+    /// Casts to a pointer of another type
+    ///
+    /// # Safety
+    ///
+    /// The object should be a valid `U`.
+    #[inline]
+    pub unsafe fn cast<U: PSafe>(&self) -> PLink<U, A> {
+        PLink { off: self.off, phantom: PhantomData }
+    }
+
+    #[inline]
+    fn log(link: &Option<Self>, journal: &Journal<A>) {
+        if A::valid(link) {
+            unsafe { link.create_log(journal, Notifier::None); }
+        }
+    }
+
+    /// Replaces the link with `new` and returns the old one. If the link is
+    /// in the pool, it takes a log of it first.
+    #[inline]
+    pub fn replace(link: &mut Option<Self>, new: Option<Self>, journal: &Journal<A>) -> Option<Self> {
+        Self::log(link, journal);
+        mem::replace(link, new)
+    }
+
+    /// Takes the link out, leaving `None` in its place. If the link is in the
+    /// pool, it takes a log of it first.
+    #[inline]
+    pub fn take(link: &mut Option<Self>, journal: &Journal<A>) -> Option<Self> {
+        Self::replace(link, None, journal)
+    }
+
+    /// Swaps two links. The links that are in the pool are logged first.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::default::*;
+    /// use corundum::ptr::PLink;
+    ///
+    /// type P = Allocator;
+    ///
+    /// let _pool = P::open_no_root("foo.pool", O_CF).unwrap();
+    ///
+    /// P::transaction(|j| {
+    ///     let x = Pbox::new(10u64, j);
+    ///     let mut links = Pbox::new([PLink::from_ref(&*x).ok(), None], j);
+    ///     let (a, b) = links.split_at_mut(1);
+    ///     PLink::swap(&mut a[0], &mut b[0], j);
+    ///     assert!(links[0].is_none());
+    ///     assert_eq!(unsafe { *links[1].unwrap().as_ref(j) }, 10);
+    /// }).unwrap();
+    /// ```
+    #[inline]
+    pub fn swap(a: &mut Option<Self>, b: &mut Option<Self>, journal: &Journal<A>) {
+        Self::log(a, journal);
+        Self::log(b, journal);
+        mem::swap(a, b);
+    }
+}
+
+impl<T: PSafe, A: MemPool> Copy for PLink<T, A> {}
+
+impl<T: PSafe, A: MemPool> Clone for PLink<T, A> {
+    #[inline]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: PSafe, A: MemPool> PClone<A> for PLink<T, A> {
+    #[inline]
+    fn pclone(&self, _j: &Journal<A>) -> Self {
+        *self
+    }
+}
+
+impl<T: PSafe, A: MemPool> PartialEq for PLink<T, A> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.off == other.off
+    }
+}
+
+impl<T: PSafe, A: MemPool> Eq for PLink<T, A> {}
+
+impl<T: PSafe, A: MemPool> Hash for PLink<T, A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.off.hash(state)
+    }
+}
+
+impl<T: PSafe, A: MemPool> fmt::Debug for PLink<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PLink(@{})", self.off)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::default::*;
+    use crate::ptr::PLink;
+
+    type P = Allocator;
+
+    struct Node {
+        val: u64,
+        next: Option<PLink<Node, P>>,
+    }
+
+    fn node(off: u64) -> &'static mut Node {
+        unsafe { P::get_mut_unchecked(off) }
+    }
+
+    #[test]
+    fn plink_links() {
+        assert_eq!(std::mem::size_of::<Option<PLink<Node, P>>>(), 8);
+        let _pool = P::open_no_root("plink.pool", O_CF).unwrap();
+        let (a, b) = P::transaction(|j| unsafe {
+            let a = Pbox::new(Node { val: 1, next: None }, j);
+            let b = Pbox::new(Node { val: 2, next: None }, j);
+            (P::off_unchecked(Pbox::into_raw(a)), P::off_unchecked(Pbox::into_raw(b)))
+        }).unwrap();
+
+        P::transaction(|j| {
+            let pa = PLink::from_ref(&*node(a)).unwrap();
+            assert_eq!(unsafe { pa.as_ref(j) }.val, 1);
+            PLink::replace(&mut node(b).next, Some(pa), j);
+            assert_eq!(node(b).next.map(|n| n.off()), Some(a));
+            assert!(PLink::<u64, P>::from_ref(&0u64).is_err());
+        }).unwrap();
+        assert_eq!(node(b).next.map(|n| n.off()), Some(a));
+
+        // The links are restored when the transaction fails
+        let _ = P::transaction(|j| {
+            assert!(PLink::take(&mut node(b).next, j).is_some());
+            PLink::swap(&mut node(a).next, &mut node(b).next, j);
+            panic!("abort");
+        });
+        assert_eq!(node(b).next.map(|n| n.off()), Some(a));
+        assert!(node(a).next.is_none());
+    }
+}
@@ -3,7 +3,13 @@
 mod slice;
 mod ptr;
 mod non_null;
+mod link;
+mod index;
+mod handle;
 
 pub use slice::*;
 pub use ptr::*;
 pub use non_null::*;
+pub use link::*;
+pub use index::*;
+pub use handle::*;
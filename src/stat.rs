@@ -6,7 +6,8 @@ use std::marker::PhantomData;
 use std::collections::HashMap;
 use std::fmt::Display;
 use std::ops::AddAssign;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::thread::{current, ThreadId};
 use std::time::Instant;
 use std::io::*;
@@ -451,27 +452,234 @@ Logging       {:>14} ns    avg(ns): {:<8}    cnt: {}",
     }
 }
 
-pub fn report() -> String {
+/// The statistics of an operation
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OpStat {
+    /// The name of the operation, or the tag of a custom measurement
+    pub name: String,
+
+    /// The number of times the operation is measured
+    pub count: u64,
+
+    /// The total time in nanoseconds
+    pub total_ns: u64,
+
+    /// The average time in nanoseconds
+    pub mean_ns: f64,
+
+    /// The minimum time in nanoseconds, if it is recorded
+    pub min_ns: Option<u64>,
+
+    /// The maximum time in nanoseconds, if it is recorded
+    pub max_ns: Option<u64>,
+
+    /// The median in nanoseconds, if the histograms are enabled (`HIST=1`)
+    pub p50_ns: Option<u64>,
+
+    /// The 90th percentile in nanoseconds, if the histograms are enabled
+    pub p90_ns: Option<u64>,
+
+    /// The 99th percentile in nanoseconds, if the histograms are enabled
+    pub p99_ns: Option<u64>,
+}
+
+/// The statistics of a pool type
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PoolReport {
+    /// The type name of the pool
+    pub pool: String,
+
+    /// The operations sorted by their names
+    pub ops: Vec<OpStat>,
+}
+
+/// A snapshot of the gathered statistics
+///
+/// It is returned by [`report()`]. It is printed in the human-readable
+/// format, and [`to_json()`] and [`to_csv()`] serialize it, so that the
+/// results of the benchmarks can be tracked over time.
+///
+/// [`report()`]: ./fn.report.html
+/// [`to_json()`]: #method.to_json
+/// [`to_csv()`]: #method.to_csv
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    /// The operations of all threads and pool types
+    pub total: Vec<OpStat>,
+
+    /// The breakdown by the pool types
+    pub pools: Vec<PoolReport>,
+
+    text: String,
+}
+
+impl Report {
+    /// Serializes the report into a JSON object with a `total` list of the
+    /// operations and a `pools` list of the pool breakdowns
+    pub fn to_json(&self) -> String {
+        fn ops(ops: &[OpStat]) -> String {
+            let ops: Vec<String> = ops.iter().map(|op| format!(
+                "{{\"name\":\"{}\",\"count\":{},\"total_ns\":{},\"mean_ns\":{},\
+                \"min_ns\":{},\"max_ns\":{},\"p50_ns\":{},\"p90_ns\":{},\"p99_ns\":{}}}",
+                escape(&op.name), op.count, op.total_ns, op.mean_ns,
+                json_opt(op.min_ns), json_opt(op.max_ns),
+                json_opt(op.p50_ns), json_opt(op.p90_ns), json_opt(op.p99_ns)
+            )).collect();
+            format!("[{}]", ops.join(","))
+        }
+        let pools: Vec<String> = self.pools.iter().map(|p| format!(
+            "{{\"pool\":\"{}\",\"ops\":{}}}", escape(&p.pool), ops(&p.ops)
+        )).collect();
+        format!("{{\"total\":{},\"pools\":[{}]}}", ops(&self.total), pools.join(","))
+    }
+
+    /// Serializes the report into CSV with one row per operation of each pool
+    /// type; the rows of all pool types have `*` in the `pool` column
+    pub fn to_csv(&self) -> String {
+        let mut res = "pool,op,count,total_ns,mean_ns,min_ns,max_ns,p50_ns,p90_ns,p99_ns\n".to_string();
+        let all = std::iter::once(("*", &self.total))
+            .chain(self.pools.iter().map(|p| (p.pool.as_str(), &p.ops)));
+        for (pool, ops) in all {
+            for op in ops {
+                res += &format!("{},{},{},{},{},{},{},{},{},{}\n",
+                    csv_field(pool), csv_field(&op.name), op.count, op.total_ns, op.mean_ns,
+                    csv_opt(op.min_ns), csv_opt(op.max_ns),
+                    csv_opt(op.p50_ns), csv_opt(op.p90_ns), csv_opt(op.p99_ns));
+            }
+        }
+        res
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut res = String::new();
+    for c in s.chars() {
+        match c {
+            '"' => res += "\\\"",
+            '\\' => res += "\\\\",
+            c if (c as u32) < 0x20 => res += &format!("\\u{:04x}", c as u32),
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+fn json_opt(v: Option<u64>) -> String {
+    v.map_or("null".to_string(), |v| v.to_string())
+}
+
+fn csv_opt(v: Option<u64>) -> String {
+    v.map_or(String::new(), |v| v.to_string())
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains(|c| c == ',' || c == '"' || c == '\n') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// Returns the value below which `q` of the points fall
+fn percentile(points: &HashMap<u64, u64>, q: f64) -> Option<u64> {
+    let total: u64 = points.values().sum();
+    if total == 0 {
+        return None;
+    }
+    let mut keys: Vec<&u64> = points.keys().collect();
+    keys.sort();
+    let rank = ((total as f64 * q).ceil() as u64).max(1);
+    let mut seen = 0;
+    for k in keys {
+        seen += points[k];
+        if seen >= rank {
+            return Some(*k);
+        }
+    }
+    None
+}
+
+impl Stat {
+    fn ops(&self) -> Vec<OpStat> {
+        let op = |name: &str, total: u64, count: u64| OpStat {
+            name: name.to_string(),
+            count,
+            total_ns: total,
+            mean_ns: div(total, count),
+            ..Default::default()
+        };
+        let mut ops: Vec<OpStat> = vec![
+            op("Sync", self.sync, self.cnt_sync),
+            op("Alloc", self.alloc, self.cnt_alloc),
+            op("Dealloc", self.dealloc, self.cnt_dealloc),
+            op("AdrTrans", self.deref, self.cnt_deref),
+            op("DropLog", self.drop_log, self.cnt_drop_log),
+            op("DataLog", self.data_log, self.cnt_data_log),
+            op("MutexLog", self.mutex_log, self.cnt_mutex_log),
+            op("Commit", self.commit, self.cnt_commit),
+            op("Rollback", self.rollback, self.cnt_rollback),
+            op("DelLog", self.clear, self.cnt_clear),
+            op("NewPage", self.new_page, self.cnt_new_page),
+            op("NewJournal", self.new_jrnl, self.cnt_new_jrnl),
+            op("Logging", self.logging, self.cnt_logging),
+        ].into_iter().filter(|op| op.count != 0).collect();
+        let mut custom: Vec<OpStat> = self.custom.iter().map(|(k, v)| OpStat {
+            name: k.to_string(),
+            count: v.cnt,
+            total_ns: v.sum,
+            mean_ns: div(v.sum, v.cnt),
+            min_ns: if v.min <= v.max { Some(v.min) } else { None },
+            max_ns: if v.min <= v.max { Some(v.max) } else { None },
+            p50_ns: percentile(&v.points, 0.5),
+            p90_ns: percentile(&v.points, 0.9),
+            p99_ns: percentile(&v.points, 0.99),
+        }).collect();
+        custom.sort_by(|x, y| x.name.cmp(&y.name));
+        ops.extend(custom);
+        ops
+    }
+}
+
+/// Returns a snapshot of the statistics of all threads, with a breakdown by
+/// the pool types
+pub fn report() -> Report {
     let stat = match unsafe { STAT.lock() } {
         Ok(g) => g,
         Err(p) => p.into_inner(),
     };
     let mut total = Stat::default();
+    let mut pools = HashMap::<&'static str, Stat>::new();
     let mut res = String::new();
     let print_all_threads = stat.len() > 1;
-    for (tid, stat) in stat.iter() {
+    for ((tid, pool), stat) in stat.iter() {
         if print_all_threads {
             res += &format!(
                 "\n{:-^113}\n{}",
-                format!(" Performance Details {:?} ", tid), stat
+                format!(" Performance Details {:?} ", (tid, pool)), stat
             );
         }
         total += stat;
+        *pools.entry(*pool).or_default() += stat;
+    }
+    let mut pools: Vec<PoolReport> = pools.iter().map(|(pool, stat)| PoolReport {
+        pool: pool.to_string(),
+        ops: stat.ops(),
+    }).collect();
+    pools.sort_by(|x, y| x.pool.cmp(&y.pool));
+    Report {
+        total: total.ops(),
+        pools,
+        text: format!(
+            "{}\n{:=^113}\n{}",
+            res, " All Threads and Pool Types ", total
+        ),
     }
-    format!(
-        "{}\n{:=^113}\n{}",
-        res, " All Threads and Pool Types ", total
-    )
 }
 
 pub fn save_histograms(_path: &'static str) -> Result<()> {
@@ -538,6 +746,608 @@ fn plot(data: &HashMap<u64, u64>, x: f32, freq_thr: u64) -> Option<(Vec<String>,
     }
 }
 
+/// The costs of the transactions of all threads
+///
+/// The counters are always gathered, regardless of the `stat_perf` feature.
+/// Each thread counts into its own atomic counters, and [`tx_metrics()`]
+/// aggregates them on demand.
+///
+/// [`tx_metrics()`]: ./fn.tx_metrics.html
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TxMetrics {
+    /// The number of top-level transactions which are committed or rolled
+    /// back
+    pub transactions: u64,
+
+    /// The number of flushed cache lines (`clflush`, `clflushopt`, or `clwb`)
+    pub flushes: u64,
+
+    /// The number of store fences
+    pub fences: u64,
+
+    /// The bytes which the logs take in the journals, including the copies
+    /// of the old data
+    pub logged_bytes: u64,
+
+    /// The bytes which are allocated by the transactions
+    pub allocated_bytes: u64,
+}
+
+impl TxMetrics {
+    fn per_tx(&self, v: u64) -> f64 {
+        div(v, self.transactions)
+    }
+
+    /// Returns the average number of flushed cache lines per transaction
+    pub fn flushes_per_tx(&self) -> f64 {
+        self.per_tx(self.flushes)
+    }
+
+    /// Returns the average number of store fences per transaction
+    pub fn fences_per_tx(&self) -> f64 {
+        self.per_tx(self.fences)
+    }
+
+    /// Returns the average number of logged bytes per transaction
+    pub fn logged_bytes_per_tx(&self) -> f64 {
+        self.per_tx(self.logged_bytes)
+    }
+
+    /// Returns the average number of allocated bytes per transaction
+    pub fn allocated_bytes_per_tx(&self) -> f64 {
+        self.per_tx(self.allocated_bytes)
+    }
+}
+
+impl Display for TxMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Transactions  {:>14}", self.transactions)?;
+        writeln!(f, "Flushes       {:>14}    per tx: {:.2}", self.flushes, self.flushes_per_tx())?;
+        writeln!(f, "Fences        {:>14}    per tx: {:.2}", self.fences, self.fences_per_tx())?;
+        writeln!(f, "Logged Bytes  {:>14}    per tx: {:.2}", self.logged_bytes, self.logged_bytes_per_tx())?;
+        write!(f, "Alloc Bytes   {:>14}    per tx: {:.2}", self.allocated_bytes, self.allocated_bytes_per_tx())
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    transactions: AtomicU64,
+    flushes: AtomicU64,
+    fences: AtomicU64,
+    logged_bytes: AtomicU64,
+    allocated_bytes: AtomicU64,
+}
+
+impl Counters {
+    fn load(&self) -> TxMetrics {
+        TxMetrics {
+            transactions: self.transactions.load(Ordering::Relaxed),
+            flushes: self.flushes.load(Ordering::Relaxed),
+            fences: self.fences.load(Ordering::Relaxed),
+            logged_bytes: self.logged_bytes.load(Ordering::Relaxed),
+            allocated_bytes: self.allocated_bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    fn reset(&self) {
+        self.transactions.store(0, Ordering::Relaxed);
+        self.flushes.store(0, Ordering::Relaxed);
+        self.fences.store(0, Ordering::Relaxed);
+        self.logged_bytes.store(0, Ordering::Relaxed);
+        self.allocated_bytes.store(0, Ordering::Relaxed);
+    }
+}
+
+impl AddAssign<TxMetrics> for TxMetrics {
+    fn add_assign(&mut self, d: TxMetrics) {
+        self.transactions += d.transactions;
+        self.flushes += d.flushes;
+        self.fences += d.fences;
+        self.logged_bytes += d.logged_bytes;
+        self.allocated_bytes += d.allocated_bytes;
+    }
+}
+
+/// The counters of the live threads, and the sum of the counters of the
+/// finished threads
+static mut COUNTERS: LazyCell<Mutex<(Vec<Arc<Counters>>, TxMetrics)>> =
+    LazyCell::new(|| Mutex::new((Vec::new(), TxMetrics::default())));
+
+fn counters() -> std::sync::MutexGuard<'static, (Vec<Arc<Counters>>, TxMetrics)> {
+    match unsafe { COUNTERS.lock() } {
+        Ok(g) => g,
+        Err(p) => p.into_inner(),
+    }
+}
+
+struct LocalCounters(Arc<Counters>);
+
+impl Drop for LocalCounters {
+    fn drop(&mut self) {
+        let mut c = counters();
+        c.1 += self.0.load();
+        c.0.retain(|x| !Arc::ptr_eq(x, &self.0));
+    }
+}
+
+thread_local! {
+    static LOCAL: LocalCounters = {
+        let local = Arc::new(Counters::default());
+        counters().0.push(local.clone());
+        LocalCounters(local)
+    };
+}
+
+#[inline]
+fn count<F: FnOnce(&Counters)>(f: F) {
+    let _ = LOCAL.try_with(|c| f(&c.0));
+}
+
+#[inline]
+pub(crate) fn count_transaction() {
+    count(|c| { c.transactions.fetch_add(1, Ordering::Relaxed); });
+}
+
+#[inline]
+pub(crate) fn count_flushes(lines: u64) {
+    count(|c| { c.flushes.fetch_add(lines, Ordering::Relaxed); });
+}
+
+#[inline]
+pub(crate) fn count_fence() {
+    count(|c| { c.fences.fetch_add(1, Ordering::Relaxed); });
+}
+
+/// Returns the number of store fences of the current thread
+#[cfg(test)]
+pub(crate) fn local_fences() -> u64 {
+    LOCAL.with(|c| c.0.fences.load(Ordering::Relaxed))
+}
+
+#[inline]
+pub(crate) fn count_logged(bytes: usize) {
+    count(|c| { c.logged_bytes.fetch_add(bytes as u64, Ordering::Relaxed); });
+}
+
+#[inline]
+pub(crate) fn count_allocated(bytes: usize) {
+    count(|c| { c.allocated_bytes.fetch_add(bytes as u64, Ordering::Relaxed); });
+}
+
+/// Returns the sum of the [`TxMetrics`] of all threads since the start of
+/// the program or the last [`reset_tx_metrics()`]
+///
+/// # Examples
+///
+/// ```
+/// # use corundum::alloc::heap::*;
+/// use corundum::stat::tx_metrics;
+///
+/// let before = tx_metrics();
+/// Heap::transaction(|j| {
+///     let _ = Pbox::new(10u64, j);
+/// }).unwrap();
+/// let after = tx_metrics();
+/// assert!(after.transactions > before.transactions);
+/// assert!(after.allocated_bytes > before.allocated_bytes);
+/// ```
+///
+/// [`TxMetrics`]: ./struct.TxMetrics.html
+/// [`reset_tx_metrics()`]: ./fn.reset_tx_metrics.html
+pub fn tx_metrics() -> TxMetrics {
+    let c = counters();
+    let mut res = c.1;
+    for local in &c.0 {
+        res += local.load();
+    }
+    res
+}
+
+/// Resets the [`TxMetrics`](./struct.TxMetrics.html) of all threads
+pub fn reset_tx_metrics() {
+    let mut c = counters();
+    c.1 = TxMetrics::default();
+    for local in &c.0 {
+        local.reset();
+    }
+}
+
+/// A phase of the transactions whose latency is recorded in a [`Histogram`]
+///
+/// [`Histogram`]: ./struct.Histogram.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TxPhase {
+    /// The whole transaction, from the start of the body to the end of the
+    /// commit or rollback; nested transactions are recorded separately
+    Transaction,
+
+    /// The commit of a top-level transaction
+    Commit,
+
+    /// The time which is spent waiting for a lock
+    LockWait,
+}
+
+impl TxPhase {
+    const ALL: [TxPhase; 3] = [TxPhase::Transaction, TxPhase::Commit, TxPhase::LockWait];
+
+    fn index(self) -> usize {
+        match self {
+            TxPhase::Transaction => 0,
+            TxPhase::Commit => 1,
+            TxPhase::LockWait => 2,
+        }
+    }
+}
+
+/// The number of bits of the sub-buckets of every power of two; the
+/// recorded values are precise to within 1/16
+const SUB_BITS: u32 = 4;
+const SUB_BUCKETS: usize = 1 << SUB_BITS;
+const BUCKETS: usize = SUB_BUCKETS + (64 - SUB_BITS as usize) * SUB_BUCKETS;
+
+fn bucket_of(v: u64) -> usize {
+    if v < SUB_BUCKETS as u64 {
+        v as usize
+    } else {
+        let e = 63 - v.leading_zeros();
+        let sub = (v >> (e - SUB_BITS)) as usize & (SUB_BUCKETS - 1);
+        SUB_BUCKETS + (e - SUB_BITS) as usize * SUB_BUCKETS + sub
+    }
+}
+
+/// Returns the smallest and the largest values of bucket `i`
+fn bucket_bounds(i: usize) -> (u64, u64) {
+    if i < SUB_BUCKETS {
+        (i as u64, i as u64)
+    } else {
+        let shift = ((i - SUB_BUCKETS) / SUB_BUCKETS) as u32;
+        let sub = ((i - SUB_BUCKETS) % SUB_BUCKETS) as u64;
+        let low = (SUB_BUCKETS as u64 + sub) << shift;
+        (low, low + ((1u64 << shift) - 1))
+    }
+}
+
+struct PhaseData {
+    buckets: [AtomicU64; BUCKETS],
+    count: AtomicU64,
+    sum: AtomicU64,
+    min: AtomicU64,
+    max: AtomicU64,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU64 = AtomicU64::new(0);
+
+#[allow(clippy::declare_interior_mutable_const)]
+const PHASE: PhaseData = PhaseData {
+    buckets: [ZERO; BUCKETS],
+    count: ZERO,
+    sum: ZERO,
+    min: AtomicU64::new(u64::MAX),
+    max: ZERO,
+};
+
+static PHASES: [PhaseData; 3] = [PHASE; 3];
+
+/// Records a latency of `phase`
+pub(crate) fn record_phase(phase: TxPhase, ns: u64) {
+    let d = &PHASES[phase.index()];
+    d.buckets[bucket_of(ns)].fetch_add(1, Ordering::Relaxed);
+    d.count.fetch_add(1, Ordering::Relaxed);
+    d.sum.fetch_add(ns, Ordering::Relaxed);
+    d.min.fetch_min(ns, Ordering::Relaxed);
+    d.max.fetch_max(ns, Ordering::Relaxed);
+}
+
+/// Records the time from its creation to its drop as a latency of a phase
+pub(crate) struct PhaseTimer(TxPhase, Instant);
+
+impl PhaseTimer {
+    #[inline]
+    pub(crate) fn start(phase: TxPhase) -> Self {
+        PhaseTimer(phase, Instant::now())
+    }
+}
+
+impl Drop for PhaseTimer {
+    #[inline]
+    fn drop(&mut self) {
+        record_phase(self.0, self.1.elapsed().as_nanos() as u64);
+    }
+}
+
+/// A snapshot of the latencies of a [`TxPhase`] in nanoseconds
+///
+/// The latencies are counted in log-linear buckets: every power of two is
+/// divided into 16 buckets, so the percentiles are precise to within about
+/// 6% at any scale.
+///
+/// [`TxPhase`]: ./enum.TxPhase.html
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    buckets: Vec<u64>,
+    count: u64,
+    sum: u64,
+    min: u64,
+    max: u64,
+}
+
+impl Histogram {
+    /// Returns the number of recorded latencies
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the smallest recorded latency, or zero if there is none
+    pub fn min(&self) -> u64 {
+        if self.count == 0 { 0 } else { self.min }
+    }
+
+    /// Returns the largest recorded latency
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    /// Returns the average latency
+    pub fn mean(&self) -> f64 {
+        div(self.sum, self.count)
+    }
+
+    /// Returns the latency below which `percentile` percent of the recorded
+    /// latencies fall, or zero if there is none
+    pub fn value_at_percentile(&self, percentile: f64) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+        let rank = ((self.count as f64 * percentile / 100.0).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return bucket_bounds(i).1.min(self.max).max(self.min);
+            }
+        }
+        self.max
+    }
+
+    /// Returns an iterator over the non-empty buckets as the smallest value,
+    /// the largest value, and the number of the latencies of each bucket
+    pub fn iter(&self) -> impl Iterator<Item = (u64, u64, u64)> + '_ {
+        self.buckets.iter().enumerate()
+            .filter(|(_, n)| **n != 0)
+            .map(|(i, n)| {
+                let (low, high) = bucket_bounds(i);
+                (low, high, *n)
+            })
+    }
+}
+
+impl Display for Histogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cnt: {}  min(ns): {}  avg(ns): {:.1}  p50(ns): {}  p90(ns): {}  \
+            p99(ns): {}  p99.9(ns): {}  max(ns): {}",
+            self.count, self.min(), self.mean(),
+            self.value_at_percentile(50.0), self.value_at_percentile(90.0),
+            self.value_at_percentile(99.0), self.value_at_percentile(99.9),
+            self.max)
+    }
+}
+
+/// Returns the histogram of the latencies of `phase` of all threads
+///
+/// The latencies are recorded with the `stat_perf` feature; otherwise, the
+/// histograms are empty.
+///
+/// # Examples
+///
+/// ```
+/// use corundum::stat::{histogram, TxPhase};
+///
+/// let commits = histogram(TxPhase::Commit);
+/// println!("p99 commit latency: {} ns", commits.value_at_percentile(99.0));
+/// ```
+pub fn histogram(phase: TxPhase) -> Histogram {
+    let d = &PHASES[phase.index()];
+    Histogram {
+        buckets: d.buckets.iter().map(|b| b.load(Ordering::Relaxed)).collect(),
+        count: d.count.load(Ordering::Relaxed),
+        sum: d.sum.load(Ordering::Relaxed),
+        min: d.min.load(Ordering::Relaxed),
+        max: d.max.load(Ordering::Relaxed),
+    }
+}
+
+/// Clears the histograms of all phases
+pub fn reset_histograms() {
+    for phase in TxPhase::ALL.iter() {
+        let d = &PHASES[phase.index()];
+        for b in d.buckets.iter() {
+            b.store(0, Ordering::Relaxed);
+        }
+        d.count.store(0, Ordering::Relaxed);
+        d.sum.store(0, Ordering::Relaxed);
+        d.min.store(u64::MAX, Ordering::Relaxed);
+        d.max.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(feature = "stat_flamegraph")]
+static mut STACKS: LazyCell<Mutex<HashMap<String, u64>>> =
+    LazyCell::new(|| Mutex::new(HashMap::new()));
+
+/// Runtime frames at the bottom of every stack which carry no information
+#[cfg(any(feature = "stat_flamegraph", feature = "stat_alloc_sites"))]
+const RUNTIME_FRAMES: [&str; 7] = [
+    "_start", "__libc_start", "clone", "start_thread",
+    "std::rt::", "std::sys", "core::ops::function::",
+];
+
+#[cfg(any(feature = "stat_flamegraph", feature = "stat_alloc_sites"))]
+fn strip_hash(sym: &str) -> &str {
+    match sym.rsplit_once("::h") {
+        Some((name, h)) if h.len() == 16 && h.bytes().all(|b| b.is_ascii_hexdigit()) => name,
+        _ => sym,
+    }
+}
+
+/// Converts a captured backtrace into a list of frames, from the outermost
+/// to the innermost, skipping the runtime and the sampler frames
+#[cfg(any(feature = "stat_flamegraph", feature = "stat_alloc_sites"))]
+fn folded_frames(bt: &str) -> Vec<String> {
+    let mut frames: Vec<String> = bt.lines()
+        .filter_map(|ln| {
+            let (idx, sym) = ln.trim().split_once(": ")?;
+            if idx.bytes().all(|b| b.is_ascii_digit()) {
+                // `;` separates frames in the folded format
+                Some(strip_hash(sym).replace(';', ":"))
+            } else {
+                None
+            }
+        })
+        .skip_while(|sym| sym.starts_with("std::backtrace") || sym.starts_with("corundum::stat::"))
+        .collect();
+    frames.reverse();
+    let outer = frames.iter()
+        .take_while(|sym| *sym == "main" || RUNTIME_FRAMES.iter().any(|r| sym.starts_with(r)))
+        .count();
+    frames.drain(..outer);
+    frames
+}
+
+/// Samples the call stack at the creation of a log entry of type `kind`
+#[cfg(feature = "stat_flamegraph")]
+pub(crate) fn sample_log_stack(kind: &str) {
+    let bt = std::backtrace::Backtrace::force_capture().to_string();
+    let mut frames = folded_frames(&bt);
+    frames.push(kind.to_string());
+    let mut stacks = match unsafe { STACKS.lock() } {
+        Ok(g) => g,
+        Err(p) => p.into_inner(),
+    };
+    *stacks.entry(frames.join(";")).or_default() += 1;
+}
+
+/// Returns the sampled call stacks of log entry creations in the folded-stack
+/// format, i.e., one `frame;frame;...;LogType count` line per distinct stack
+///
+/// The output can be fed to flamegraph tools (e.g., `flamegraph.pl` or
+/// `inferno-flamegraph`) to see which code paths generate the most logs.
+/// Sampling is enabled by the `stat_flamegraph` feature. Building with debug
+/// symbols gives more accurate stacks.
+#[cfg(feature = "stat_flamegraph")]
+pub fn folded_stacks() -> String {
+    let stacks = match unsafe { STACKS.lock() } {
+        Ok(g) => g,
+        Err(p) => p.into_inner(),
+    };
+    let mut lns: Vec<String> = stacks.iter()
+        .map(|(stack, cnt)| format!("{} {}", stack, cnt))
+        .collect();
+    lns.sort();
+    lns.iter().fold(String::new(), |res, ln| res + ln + "\n")
+}
+
+/// Writes the [`folded_stacks()`] into the file at `path`
+///
+/// [`folded_stacks()`]: ./fn.folded_stacks.html
+#[cfg(feature = "stat_flamegraph")]
+pub fn save_flamegraph(path: &str) -> Result<()> {
+    std::fs::write(path, folded_stacks())
+}
+
+/// The allocations of every call site, keyed by the call chain
+#[cfg(feature = "stat_alloc_sites")]
+static mut ALLOC_SITES: LazyCell<Mutex<HashMap<String, (u64, u64)>>> =
+    LazyCell::new(|| Mutex::new(HashMap::new()));
+
+/// The maximum number of frames which name a call site
+#[cfg(feature = "stat_alloc_sites")]
+const SITE_FRAMES: usize = 4;
+
+/// Returns the call chain of an allocation from the innermost frame which is
+/// not a part of the allocator or the journal, up to the first frame out of
+/// this crate
+#[cfg(feature = "stat_alloc_sites")]
+fn alloc_site(bt: &str) -> String {
+    let internal = |sym: &str| sym.contains("corundum::alloc::") ||
+        sym.contains("corundum::stm::log::") || sym.starts_with("corundum::stat::") ||
+        sym.starts_with("core::") || sym.starts_with("alloc::") || sym.starts_with("std::");
+    let mut site = vec![];
+    for sym in folded_frames(bt).iter().rev().skip_while(|sym| internal(sym)) {
+        site.push(sym.clone());
+        if site.len() == SITE_FRAMES || !sym.contains("corundum::") {
+            break;
+        }
+    }
+    site.join(" <- ")
+}
+
+/// Records an allocation of `bytes` at the call site of the current thread
+#[cfg(feature = "stat_alloc_sites")]
+pub(crate) fn record_alloc_site(bytes: usize) {
+    let bt = std::backtrace::Backtrace::force_capture().to_string();
+    let site = alloc_site(&bt);
+    let mut sites = match unsafe { ALLOC_SITES.lock() } {
+        Ok(g) => g,
+        Err(p) => p.into_inner(),
+    };
+    let e = sites.entry(site).or_default();
+    e.0 += 1;
+    e.1 += bytes as u64;
+}
+
+/// The allocations of a call site
+#[cfg(feature = "stat_alloc_sites")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AllocSite {
+    /// The call chain of the site, from the innermost frame, e.g.,
+    /// `corundum::boxed::Pbox<T,A>::new <- app::Node::new`
+    pub site: String,
+
+    /// The number of allocations
+    pub count: u64,
+
+    /// The allocated bytes
+    pub bytes: u64,
+}
+
+/// Returns the `n` call sites which have allocated the most bytes in
+/// transactions, in descending order
+///
+/// Every transactional allocation captures a backtrace when the
+/// `stat_alloc_sites` feature is enabled, which is slow; hence, it is meant
+/// for finding out which data structure takes the pool, not for
+/// measurements. The counts are cumulative: they include the allocations
+/// which are freed or rolled back. Building with debug symbols gives more
+/// accurate call sites.
+#[cfg(feature = "stat_alloc_sites")]
+pub fn top_allocators(n: usize) -> Vec<AllocSite> {
+    let sites = match unsafe { ALLOC_SITES.lock() } {
+        Ok(g) => g,
+        Err(p) => p.into_inner(),
+    };
+    let mut res: Vec<AllocSite> = sites.iter()
+        .map(|(site, (count, bytes))| AllocSite {
+            site: site.clone(),
+            count: *count,
+            bytes: *bytes,
+        })
+        .collect();
+    res.sort_by(|x, y| y.bytes.cmp(&x.bytes).then_with(|| x.site.cmp(&y.site)));
+    res.truncate(n);
+    res
+}
+
+/// Forgets the recorded allocation sites
+#[cfg(feature = "stat_alloc_sites")]
+pub fn reset_alloc_sites() {
+    match unsafe { ALLOC_SITES.lock() } {
+        Ok(mut g) => g.clear(),
+        Err(p) => p.into_inner().clear(),
+    }
+}
+
 #[macro_export]
 macro_rules! measure {
     ($tag:expr,$n:expr,$f:block) => {
@@ -576,4 +1386,100 @@ macro_rules! measure {
             }
         }
     };
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn report_serializes() {
+        let mut points = HashMap::new();
+        for t in 1..=100 {
+            points.insert(t, 1);
+        }
+        assert_eq!(percentile(&points, 0.5), Some(50));
+        assert_eq!(percentile(&points, 0.99), Some(99));
+        let op = OpStat {
+            name: "a,\"b\"".to_string(),
+            count: 2,
+            total_ns: 30,
+            mean_ns: 15.0,
+            min_ns: Some(10),
+            max_ns: Some(20),
+            ..Default::default()
+        };
+        let report = Report {
+            total: vec![op.clone()],
+            pools: vec![PoolReport { pool: "P".to_string(), ops: vec![op] }],
+            text: String::new(),
+        };
+        let csv = report.to_csv();
+        assert_eq!(csv.lines().count(), 3);
+        assert_eq!(csv.lines().nth(2), Some("P,\"a,\"\"b\"\"\",2,30,15,10,20,,,"));
+        assert!(report.to_json().starts_with(
+            "{\"total\":[{\"name\":\"a,\\\"b\\\"\",\"count\":2,\"total_ns\":30,\"mean_ns\":15,"));
+        assert!(report.to_json().contains("\"p50_ns\":null"));
+    }
+
+    #[test]
+    fn histogram_buckets() {
+        for v in (0..100_000).chain(vec![u64::MAX / 3, u64::MAX]) {
+            let (low, high) = bucket_bounds(bucket_of(v));
+            assert!(low <= v && v <= high, "{} not in [{}, {}]", v, low, high);
+            assert!(high - low <= low / SUB_BUCKETS as u64);
+        }
+        let mut h = Histogram {
+            buckets: vec![0; BUCKETS],
+            count: 0,
+            sum: 0,
+            min: u64::MAX,
+            max: 0,
+        };
+        for v in 1..=1000 {
+            h.buckets[bucket_of(v)] += 1;
+            h.count += 1;
+            h.sum += v;
+            h.min = h.min.min(v);
+            h.max = h.max.max(v);
+        }
+        assert_eq!(h.value_at_percentile(100.0), 1000);
+        let p50 = h.value_at_percentile(50.0);
+        assert!(500 <= p50 && p50 <= 500 + 500 / 16, "p50 = {}", p50);
+        assert_eq!(h.iter().map(|(_, _, n)| n).sum::<u64>(), 1000);
+    }
+
+    #[test]
+    #[cfg(feature = "stat_alloc_sites")]
+    fn alloc_site_of_backtrace() {
+        let bt = "   0: std::backtrace::Backtrace::force_capture
+   1: corundum::stat::record_alloc_site
+   2: corundum::stm::log::Log<A>::set
+   3: <corundum::alloc::default::BuddyAlloc as corundum::alloc::pool::MemPoolTraits>::new
+   4: corundum::boxed::Pbox<T,A>::new
+   5: app::Node::new
+   6: app::main
+   7: main";
+        assert_eq!(alloc_site(bt), "corundum::boxed::Pbox<T,A>::new <- app::Node::new");
+    }
+
+    #[test]
+    #[cfg(feature = "stat_flamegraph")]
+    fn fold_backtrace() {
+        let bt = "   0: std::backtrace::Backtrace::force_capture
+             at /rustc/library/std/src/backtrace.rs:310:9
+   1: corundum::stat::sample_log_stack
+   2: corundum::stm::log::Log<A>::create::h0123456789abcdef
+             at ./src/stm/log.rs:343:9
+   3: app::update<[u8; 4]>
+   4: core::ops::function::FnOnce::call_once
+   5: std::rt::lang_start::{{closure}}
+   6: main
+   7: __libc_start_main
+   8: _start";
+        assert_eq!(folded_frames(bt), vec![
+            "app::update<[u8: 4]>".to_string(),
+            "corundum::stm::log::Log<A>::create".to_string(),
+        ]);
+    }
+}
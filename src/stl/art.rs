@@ -0,0 +1,770 @@
+use std::fmt::{self, Debug};
+use std::marker::PhantomData;
+use std::{mem, ptr};
+
+use crate::*;
+use crate::alloc::*;
+use crate::vec::Vec as PVec;
+use crate::stm::{Journal, Logger, Notifier};
+use super::Entry;
+
+/// The offset of no node
+const NONE: u64 = u64::MAX;
+
+const LEAF: u8 = 0;
+const N4: u8 = 1;
+const N16: u8 = 2;
+const N48: u8 = 3;
+const N256: u8 = 4;
+
+/// A key and its value
+#[repr(C)]
+struct Leaf<V, P: MemPool> {
+    kind: u8,
+    key: PVec<u8, P>,
+    value: V,
+}
+
+/// The common part of the inner nodes
+#[repr(C)]
+struct Header<P: MemPool> {
+    kind: u8,
+    count: u16,
+
+    /// The leaf of the key which ends at this node
+    term: u64,
+
+    /// The compressed path below the byte which leads to this node
+    prefix: PVec<u8, P>,
+}
+
+/// An inner node with up to `N` children, sorted by their bytes
+#[repr(C)]
+struct Small<P: MemPool, const N: usize> {
+    h: Header<P>,
+    keys: [u8; N],
+    children: [u64; N],
+}
+
+#[repr(C)]
+struct Node48<P: MemPool> {
+    h: Header<P>,
+
+    /// The position of the child of every byte plus one, or zero
+    index: [u8; 256],
+    children: [u64; 48],
+}
+
+#[repr(C)]
+struct Node256<P: MemPool> {
+    h: Header<P>,
+    children: [u64; 256],
+}
+
+type Node4<P> = Small<P, 4>;
+type Node16<P> = Small<P, 16>;
+
+/// Logs `slot` and points it to `off`
+#[inline]
+fn set_slot<P: MemPool>(slot: &u64, off: u64, j: &Journal<P>) {
+    unsafe {
+        let slot = utils::as_mut(slot);
+        slot.create_log(j, Notifier::None);
+        *slot = off;
+    }
+}
+
+#[inline]
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+impl<P: MemPool> Header<P> {
+    fn new(kind: u8, prefix: &[u8], j: &Journal<P>) -> Self {
+        Header { kind, count: 0, term: NONE, prefix: PVec::from_slice(prefix, j) }
+    }
+}
+
+impl<P: MemPool, const N: usize> Small<P, N> {
+    fn new(h: Header<P>) -> Self {
+        Small { h: Header { kind: if N == 4 { N4 } else { N16 }, ..h }, keys: [0; N], children: [NONE; N] }
+    }
+
+    /// Inserts a child in the order of the bytes, assuming there is room
+    fn insert(&mut self, byte: u8, child: u64) {
+        let n = self.h.count as usize;
+        let i = self.keys[..n].iter().position(|k| *k > byte).unwrap_or(n);
+        self.keys.copy_within(i..n, i + 1);
+        self.children.copy_within(i..n, i + 1);
+        self.keys[i] = byte;
+        self.children[i] = child;
+        self.h.count += 1;
+    }
+
+    /// Removes the child of `byte`, which should exist
+    fn remove(&mut self, byte: u8) {
+        let n = self.h.count as usize;
+        let i = self.keys[..n].iter().position(|k| *k == byte).unwrap();
+        self.keys.copy_within(i + 1..n, i);
+        self.children.copy_within(i + 1..n, i);
+        self.h.count -= 1;
+    }
+}
+
+/// A persistent adaptive radix tree
+///
+/// It maps byte strings, such as the bytes of [`str`] keys, to values, and
+/// keeps them in the lexicographic order of the keys. Every inner node
+/// stores the path which it compresses, and it takes one of four sizes
+/// (4, 16, 48, or 256 children) depending on how many children it has. A
+/// node which runs out of room is replaced by a larger copy in the same
+/// transaction, so a crash never leaves a half-grown node behind.
+///
+/// Besides the point lookups, it answers ordered queries by prefix, which
+/// a hash map cannot do. The nodes do not shrink when the keys are removed.
+///
+/// # Examples
+///
+/// ```
+/// # use corundum::alloc::heap::*;
+/// use corundum::stl::PArt;
+///
+/// Heap::transaction(|j| {
+///     let art = PArt::<u32, Heap>::new();
+///     for (i, w) in ["romane", "romanus", "romulus", "rubens", "ruber"].iter().enumerate() {
+///         art.insert(w, i as u32, j);
+///     }
+///     assert_eq!(art.get("rubens"), Some(&3));
+///     let rom: Vec<_> = art.iter_prefix("rom").map(|(k, _)| k.to_vec()).collect();
+///     assert_eq!(rom, vec![b"romane".to_vec(), b"romanus".to_vec(), b"romulus".to_vec()]);
+/// }).unwrap();
+/// ```
+///
+/// [`str`]: std::str
+pub struct PArt<V: PSafe, P: MemPool> {
+    root: u64,
+    len: usize,
+    phantom: PhantomData<(V, P)>,
+}
+
+impl<V: PSafe, P: MemPool> PArt<V, P> {
+    /// Creates a new empty tree
+    pub fn new() -> Self {
+        Self { root: NONE, len: 0, phantom: PhantomData }
+    }
+
+    /// Returns the number of keys
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if there is no key
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    fn kind(off: u64) -> u8 {
+        unsafe { *P::get_unchecked::<u8>(off) }
+    }
+
+    #[inline]
+    fn leaf<'a>(off: u64) -> &'a mut Leaf<V, P> {
+        unsafe { P::get_mut_unchecked(off) }
+    }
+
+    #[inline]
+    fn header<'a>(off: u64) -> &'a mut Header<P> {
+        unsafe { P::get_mut_unchecked(off) }
+    }
+
+    /// Returns the slot of the child of `byte`, if any
+    fn child<'a>(off: u64, byte: u8) -> Option<&'a u64> {
+        unsafe {
+            match Self::kind(off) {
+                N4 => {
+                    let n = P::get_unchecked::<Node4<P>>(off);
+                    n.keys[..n.h.count as usize].iter().position(|k| *k == byte)
+                        .map(|i| &n.children[i])
+                }
+                N16 => {
+                    let n = P::get_unchecked::<Node16<P>>(off);
+                    n.keys[..n.h.count as usize].iter().position(|k| *k == byte)
+                        .map(|i| &n.children[i])
+                }
+                N48 => {
+                    let n = P::get_unchecked::<Node48<P>>(off);
+                    match n.index[byte as usize] {
+                        0 => None,
+                        i => Some(&n.children[i as usize - 1]),
+                    }
+                }
+                _ => {
+                    let n = P::get_unchecked::<Node256<P>>(off);
+                    Some(&n.children[byte as usize]).filter(|c| **c != NONE)
+                }
+            }
+        }
+    }
+
+    /// Returns the children of a node in the order of their bytes
+    fn children(off: u64) -> Vec<u64> {
+        unsafe {
+            match Self::kind(off) {
+                N4 => {
+                    let n = P::get_unchecked::<Node4<P>>(off);
+                    n.children[..n.h.count as usize].to_vec()
+                }
+                N16 => {
+                    let n = P::get_unchecked::<Node16<P>>(off);
+                    n.children[..n.h.count as usize].to_vec()
+                }
+                N48 => {
+                    let n = P::get_unchecked::<Node48<P>>(off);
+                    n.index.iter().filter(|i| **i != 0)
+                        .map(|i| n.children[*i as usize - 1]).collect()
+                }
+                _ => {
+                    let n = P::get_unchecked::<Node256<P>>(off);
+                    n.children.iter().copied().filter(|c| *c != NONE).collect()
+                }
+            }
+        }
+    }
+
+    /// Adds the child of `byte` to the node at `off`, which `slot` points
+    /// to; the node is replaced by a larger one if it is full
+    fn add_child(slot: &u64, off: u64, byte: u8, child: u64, j: &Journal<P>) {
+        unsafe {
+            match Self::kind(off) {
+                N4 | N16 => {
+                    let (count, cap) = (Self::header(off).count as usize,
+                        if Self::kind(off) == N4 { 4 } else { 16 });
+                    if count < cap {
+                        if cap == 4 {
+                            let n = P::get_mut_unchecked::<Node4<P>>(off);
+                            n.create_log(j, Notifier::None);
+                            n.insert(byte, child);
+                        } else {
+                            let n = P::get_mut_unchecked::<Node16<P>>(off);
+                            n.create_log(j, Notifier::None);
+                            n.insert(byte, child);
+                        }
+                        return;
+                    }
+                    let new = if cap == 4 {
+                        let old = P::get_mut_unchecked::<Node4<P>>(off);
+                        let mut new = Node16::<P>::new(ptr::read(&old.h));
+                        new.h.count = 4;
+                        new.keys[..4].copy_from_slice(&old.keys);
+                        new.children[..4].copy_from_slice(&old.children);
+                        new.insert(byte, child);
+                        let new = P::off_unchecked(P::new(new, j));
+                        P::free(old);
+                        new
+                    } else {
+                        let old = P::get_mut_unchecked::<Node16<P>>(off);
+                        let mut new = Node48::<P> {
+                            h: Header { kind: N48, ..ptr::read(&old.h) },
+                            index: [0; 256],
+                            children: [NONE; 48],
+                        };
+                        for i in 0..16 {
+                            new.index[old.keys[i] as usize] = i as u8 + 1;
+                            new.children[i] = old.children[i];
+                        }
+                        new.index[byte as usize] = 17;
+                        new.children[16] = child;
+                        new.h.count = 17;
+                        let new = P::off_unchecked(P::new(new, j));
+                        P::free(old);
+                        new
+                    };
+                    set_slot(slot, new, j);
+                }
+                N48 => {
+                    let n = P::get_mut_unchecked::<Node48<P>>(off);
+                    if (n.h.count as usize) < 48 {
+                        let i = n.children.iter().position(|c| *c == NONE).unwrap();
+                        set_slot(&n.children[i], child, j);
+                        j.batch(|| {
+                            n.index[byte as usize].create_log(j, Notifier::None);
+                            n.h.count.create_log(j, Notifier::None);
+                        });
+                        n.index[byte as usize] = i as u8 + 1;
+                        n.h.count += 1;
+                        return;
+                    }
+                    let mut new = Node256::<P> {
+                        h: Header { kind: N256, ..ptr::read(&n.h) },
+                        children: [NONE; 256],
+                    };
+                    for b in 0..256 {
+                        if n.index[b] != 0 {
+                            new.children[b] = n.children[n.index[b] as usize - 1];
+                        }
+                    }
+                    new.children[byte as usize] = child;
+                    new.h.count = 49;
+                    let new = P::off_unchecked(P::new(new, j));
+                    P::free(n);
+                    set_slot(slot, new, j);
+                }
+                _ => {
+                    let n = P::get_mut_unchecked::<Node256<P>>(off);
+                    set_slot(&n.children[byte as usize], child, j);
+                    n.h.count.create_log(j, Notifier::None);
+                    n.h.count += 1;
+                }
+            }
+        }
+    }
+
+    /// Removes the child of `byte` from the node at `off`
+    fn remove_child(off: u64, byte: u8, j: &Journal<P>) {
+        unsafe {
+            match Self::kind(off) {
+                N4 => {
+                    let n = P::get_mut_unchecked::<Node4<P>>(off);
+                    n.create_log(j, Notifier::None);
+                    n.remove(byte);
+                }
+                N16 => {
+                    let n = P::get_mut_unchecked::<Node16<P>>(off);
+                    n.create_log(j, Notifier::None);
+                    n.remove(byte);
+                }
+                N48 => {
+                    let n = P::get_mut_unchecked::<Node48<P>>(off);
+                    let i = n.index[byte as usize] as usize - 1;
+                    set_slot(&n.children[i], NONE, j);
+                    j.batch(|| {
+                        n.index[byte as usize].create_log(j, Notifier::None);
+                        n.h.count.create_log(j, Notifier::None);
+                    });
+                    n.index[byte as usize] = 0;
+                    n.h.count -= 1;
+                }
+                _ => {
+                    let n = P::get_mut_unchecked::<Node256<P>>(off);
+                    set_slot(&n.children[byte as usize], NONE, j);
+                    n.h.count.create_log(j, Notifier::None);
+                    n.h.count -= 1;
+                }
+            }
+        }
+    }
+
+    fn new_leaf(key: &[u8], value: V, j: &Journal<P>) -> u64 {
+        unsafe {
+            P::off_unchecked(P::new(Leaf { kind: LEAF, key: PVec::from_slice(key, j), value }, j))
+        }
+    }
+
+    /// Creates a node with the compressed path `prefix` and two keys below
+    /// it, each either ending at the node or starting with a new byte
+    fn new_fork(prefix: &[u8], a: (Option<u8>, u64), b: (Option<u8>, u64), j: &Journal<P>) -> u64 {
+        let mut node = Node4::<P>::new(Header::new(N4, prefix, j));
+        for (byte, child) in [a, b].iter() {
+            match byte {
+                Some(byte) => node.insert(*byte, *child),
+                None => node.h.term = *child,
+            }
+        }
+        unsafe { P::off_unchecked(P::new(node, j)) }
+    }
+
+    /// Returns a reference to the value of `key`
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Option<&V> {
+        let key = key.as_ref();
+        let mut off = self.root;
+        let mut depth = 0;
+        while off != NONE {
+            if Self::kind(off) == LEAF {
+                let leaf = Self::leaf(off);
+                return if leaf.key.as_slice() == key { Some(&leaf.value) } else { None };
+            }
+            let h = Self::header(off);
+            if !key[depth..].starts_with(h.prefix.as_slice()) {
+                return None;
+            }
+            depth += h.prefix.len();
+            if depth == key.len() {
+                off = h.term;
+            } else {
+                off = *Self::child(off, key[depth])?;
+                depth += 1;
+            }
+        }
+        None
+    }
+
+    /// Returns true if the tree has `key`
+    pub fn contains_key<K: AsRef<[u8]>>(&self, key: K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Inserts or replaces the value of `key`, and returns the old value, if
+    /// any
+    pub fn insert<K: AsRef<[u8]>>(&self, key: K, value: V, j: &Journal<P>) -> Option<V> {
+        match self.lookup(key) {
+            Entry::Occupied(mut e) => Some(e.insert(value, j)),
+            Entry::Vacant(e) => {
+                e.insert(value, j);
+                None
+            }
+        }
+    }
+
+    /// Returns the entry of `key` for in-place update or insertion
+    pub fn entry<K: AsRef<[u8]>>(&mut self, key: K) -> Entry<'_, K, V, P> {
+        self.lookup(key)
+    }
+
+    /// Finds the entry of `key`; the caller consumes the entry before the
+    /// tree is accessed again
+    fn lookup<K: AsRef<[u8]>>(&self, key: K) -> Entry<'_, K, V, P> {
+        let k = key.as_ref();
+        let mut slot = &self.root;
+        let mut depth = 0;
+        loop {
+            let off = *slot;
+            if off == NONE {
+                break;
+            }
+            if Self::kind(off) == LEAF {
+                let leaf = Self::leaf(off);
+                if leaf.key.as_slice() == k {
+                    return Entry::occupied(key, unsafe { utils::as_mut(&leaf.value) });
+                }
+                break;
+            }
+            let h = Self::header(off);
+            if !k[depth..].starts_with(h.prefix.as_slice()) {
+                break;
+            }
+            let d = depth + h.prefix.len();
+            if d == k.len() {
+                if h.term == NONE {
+                    break;
+                }
+                slot = &h.term;
+            } else if let Some(child) = Self::child(off, k[d]) {
+                slot = child;
+                depth = d + 1;
+                continue;
+            } else {
+                break;
+            }
+            depth = d;
+        }
+        Entry::vacant(key, move |key, value, j| {
+            self.insert_at(slot, depth, key.as_ref(), value, j)
+        })
+    }
+
+    /// Inserts a new leaf for `key` below `slot`, where the search for the
+    /// key stops after `depth` bytes, and returns a reference to its value
+    fn insert_at(&self, slot: &u64, depth: usize, key: &[u8], value: V, j: &Journal<P>) -> &V {
+        let off = *slot;
+        let new = Self::new_leaf(key, value, j);
+        if off == NONE {
+            set_slot(slot, new, j);
+        } else if Self::kind(off) == LEAF {
+            let old = &Self::leaf(off).key.as_slice()[depth..];
+            let m = common_prefix(old, &key[depth..]);
+            let fork = Self::new_fork(&old[..m],
+                (old.get(m).copied(), off), (key.get(depth + m).copied(), new), j);
+            set_slot(slot, fork, j);
+        } else {
+            let h = Self::header(off);
+            let m = common_prefix(h.prefix.as_slice(), &key[depth..]);
+            if m < h.prefix.len() {
+                // Split the compressed path
+                let prefix = h.prefix.as_slice().to_vec();
+                unsafe {
+                    h.prefix.create_log(j, Notifier::None);
+                    h.prefix = PVec::from_slice(&prefix[m + 1..], j);
+                }
+                let fork = Self::new_fork(&prefix[..m],
+                    (Some(prefix[m]), off), (key.get(depth + m).copied(), new), j);
+                set_slot(slot, fork, j);
+            } else if depth + m == key.len() {
+                set_slot(&h.term, new, j);
+            } else {
+                Self::add_child(slot, off, key[depth + m], new, j);
+            }
+        }
+        unsafe {
+            utils::as_mut(&self.len).create_log(j, Notifier::None);
+            utils::as_mut(self).len += 1;
+        }
+        &Self::leaf(new).value
+    }
+
+    /// Removes `key`, and returns its value
+    pub fn remove<K: AsRef<[u8]>>(&self, key: K, j: &Journal<P>) -> Option<V> {
+        let key = key.as_ref();
+        let mut slot = &self.root;
+        let mut parent = None;
+        let mut depth = 0;
+        loop {
+            let off = *slot;
+            if off == NONE {
+                return None;
+            }
+            if Self::kind(off) == LEAF {
+                let leaf = Self::leaf(off);
+                if leaf.key.as_slice() != key {
+                    return None;
+                }
+                match parent {
+                    Some((p, byte)) => Self::remove_child(p, byte, j),
+                    None => set_slot(slot, NONE, j),
+                }
+                unsafe {
+                    utils::as_mut(&self.len).create_log(j, Notifier::None);
+                    utils::as_mut(self).len -= 1;
+                    let value = ptr::read(&leaf.value);
+                    ptr::drop_in_place(&mut leaf.key);
+                    P::free(leaf);
+                    return Some(value);
+                }
+            }
+            let h = Self::header(off);
+            if !key[depth..].starts_with(h.prefix.as_slice()) {
+                return None;
+            }
+            depth += h.prefix.len();
+            if depth == key.len() {
+                slot = &h.term;
+                parent = None;
+            } else {
+                slot = Self::child(off, key[depth])?;
+                parent = Some((off, key[depth]));
+                depth += 1;
+            }
+        }
+    }
+
+    /// Returns an iterator over the keys and values in the order of the keys
+    pub fn iter(&self) -> Iter<'_, V, P> {
+        Iter::new(self.root, &[])
+    }
+
+    /// Returns an iterator over the keys which start with `prefix` and their
+    /// values, in the order of the keys
+    pub fn iter_prefix<K: AsRef<[u8]>>(&self, prefix: K) -> Iter<'_, V, P> {
+        let prefix = prefix.as_ref();
+        let mut off = self.root;
+        let mut depth = 0;
+        while off != NONE && Self::kind(off) != LEAF && depth < prefix.len() {
+            let p = Self::header(off).prefix.as_slice();
+            let m = common_prefix(p, &prefix[depth..]);
+            if depth + m == prefix.len() {
+                // The prefix ends within the compressed path
+                break;
+            }
+            if m < p.len() {
+                off = NONE;
+                break;
+            }
+            depth += m;
+            off = Self::child(off, prefix[depth]).copied().unwrap_or(NONE);
+            depth += 1;
+        }
+        Iter::new(off, prefix)
+    }
+
+    /// Returns an iterator over the keys and values in the order of the keys
+    /// which allows modifying the values; every value is logged when it is
+    /// visited
+    pub fn iter_mut<'a>(&'a mut self, j: &'a Journal<P>) -> impl Iterator<Item = (&'a [u8], &'a mut V)> + 'a {
+        self.iter().map(move |(k, v)| unsafe {
+            let v = utils::as_mut(v);
+            v.create_log(j, Notifier::None);
+            (k, v)
+        })
+    }
+
+    /// Returns an iterator over the keys in order
+    pub fn keys(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        self.iter().map(|(k, _)| k)
+    }
+
+    /// Returns an iterator over the values in the order of their keys
+    pub fn values(&self) -> impl Iterator<Item = &V> + '_ {
+        self.iter().map(|(_, v)| v)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<V: PSafe + Sync, P: MemPool> PArt<V, P> {
+    /// Returns a parallel iterator over the keys and values, which visits
+    /// the subtrees of the root on different threads
+    ///
+    /// It is available with the `rayon` feature. The entries are produced
+    /// in the order of the keys if they are collected.
+    pub fn par_iter(&self) -> impl rayon::iter::ParallelIterator<Item = (&[u8], &V)> + '_ {
+        use rayon::prelude::*;
+        let mut roots = Vec::new();
+        if self.root != NONE {
+            if Self::kind(self.root) == LEAF {
+                roots.push(self.root);
+            } else {
+                let term = Self::header(self.root).term;
+                if term != NONE {
+                    roots.push(term);
+                }
+                roots.extend(Self::children(self.root));
+            }
+        }
+        roots.into_par_iter().flat_map_iter(|off| Iter::<V, P>::new(off, &[]))
+    }
+}
+
+impl<K: AsRef<[u8]>, V: PSafe, P: MemPool> PFromIterator<(K, V), P> for PArt<V, P> {
+    fn pfrom_iter<I: IntoIterator<Item = (K, V)>>(iter: I, j: &Journal<P>) -> Self {
+        let res = Self::new();
+        for (k, v) in iter {
+            res.insert(k, v, j);
+        }
+        res
+    }
+}
+
+/// An iterator over the keys and values of a [`PArt`] in the order of the
+/// keys
+///
+/// [`PArt`]: ./struct.PArt.html
+pub struct Iter<'a, V: PSafe, P: MemPool> {
+    stack: Vec<std::vec::IntoIter<u64>>,
+    prefix: Vec<u8>,
+    phantom: PhantomData<&'a PArt<V, P>>,
+}
+
+impl<'a, V: PSafe, P: MemPool> Iter<'a, V, P> {
+    fn new(off: u64, prefix: &[u8]) -> Self {
+        let stack = if off == NONE { vec![] } else { vec![vec![off].into_iter()] };
+        Iter { stack, prefix: prefix.to_vec(), phantom: PhantomData }
+    }
+}
+
+impl<'a, V: PSafe, P: MemPool> Iterator for Iter<'a, V, P> {
+    type Item = (&'a [u8], &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(top) = self.stack.last_mut() {
+            let off = match top.next() {
+                Some(off) => off,
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            if PArt::<V, P>::kind(off) == LEAF {
+                let leaf = PArt::<V, P>::leaf(off);
+                if leaf.key.as_slice().starts_with(&self.prefix) {
+                    return Some((leaf.key.as_slice(), &leaf.value));
+                }
+            } else {
+                let mut next = Vec::new();
+                let term = PArt::<V, P>::header(off).term;
+                if term != NONE {
+                    next.push(term);
+                }
+                next.extend(PArt::<V, P>::children(off));
+                self.stack.push(next.into_iter());
+            }
+        }
+        None
+    }
+}
+
+impl<V: PSafe, P: MemPool> Drop for PArt<V, P> {
+    fn drop(&mut self) {
+        fn drop_node<V: PSafe, P: MemPool>(off: u64) {
+            if off == NONE {
+                return;
+            }
+            unsafe {
+                if PArt::<V, P>::kind(off) == LEAF {
+                    let leaf = PArt::<V, P>::leaf(off);
+                    ptr::drop_in_place(leaf);
+                    P::free(leaf);
+                    return;
+                }
+                let h = PArt::<V, P>::header(off);
+                drop_node::<V, P>(h.term);
+                for c in PArt::<V, P>::children(off) {
+                    drop_node::<V, P>(c);
+                }
+                ptr::drop_in_place(&mut h.prefix);
+                match h.kind {
+                    N4 => P::free(P::get_mut_unchecked::<Node4<P>>(off)),
+                    N16 => P::free(P::get_mut_unchecked::<Node16<P>>(off)),
+                    N48 => P::free(P::get_mut_unchecked::<Node48<P>>(off)),
+                    _ => P::free(P::get_mut_unchecked::<Node256<P>>(off)),
+                }
+            }
+        }
+        drop_node::<V, P>(self.root);
+    }
+}
+
+impl<V: PSafe, P: MemPool> Default for PArt<V, P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V: PSafe, P: MemPool> RootObj<P> for PArt<V, P> {
+    fn init(_: &Journal<P>) -> Self {
+        Self::new()
+    }
+}
+
+impl<V: PSafe + Debug, P: MemPool> Debug for PArt<V, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.iter().map(|(k, v)| (String::from_utf8_lossy(k), v)))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alloc::heap::*;
+    use super::PArt;
+
+    #[test]
+    fn art_grows_and_keeps_order() {
+        Heap::transaction(|j| {
+            let art = PArt::<usize, Heap>::new();
+            let mut keys: Vec<String> = (0..300).map(|i| format!("k{}", i)).collect();
+            keys.push("k".to_string());
+            for (i, k) in keys.iter().enumerate() {
+                assert_eq!(art.insert(k, i, j), None);
+            }
+            assert_eq!(art.insert("k7", 0, j), Some(7));
+            assert_eq!(art.len(), keys.len());
+            keys.sort();
+            assert!(art.iter().map(|(k, _)| k.to_vec()).eq(keys.iter().map(|k| k.as_bytes().to_vec())));
+            assert_eq!(art.iter_prefix("k29").count(), 11);
+
+            assert_eq!(art.remove("k", j), Some(300));
+            assert_eq!(art.remove("k29", j), Some(29));
+            assert_eq!(art.remove("k29", j), None);
+            assert_eq!(art.get("k290"), Some(&290));
+            assert_eq!(art.iter_prefix("k29").count(), 10);
+
+            // One node grows through all sizes
+            for b in (0..=255u8).rev() {
+                art.insert(vec![b'x', b], b as usize, j);
+            }
+            assert!(art.iter_prefix("x").map(|(k, _)| k[1] as usize).eq(0..256));
+            assert_eq!(art.remove(vec![b'x', 7], j), Some(7));
+            assert_eq!(art.iter_prefix("x").count(), 255);
+        }).unwrap();
+    }
+}
@@ -0,0 +1,358 @@
+use std::fmt::{self, Debug};
+
+use crate::*;
+use crate::alloc::*;
+use crate::vec::Vec as PVec;
+use crate::cell::{PCell, PRefCell};
+use crate::stm::{Journal, Logger, Notifier};
+
+/// The number of bits in a word
+const WORD_BITS: usize = 64;
+
+/// The number of words which share a counter in the rank index
+const BLOCK_WORDS: usize = 8;
+
+/// A persistent vector of bits
+///
+/// The bits are packed in 64-bit words. [`set()`] and [`flip()`] log only
+/// the word which they change, so updating a few bits of a large bitmap in
+/// a transaction takes a few small logs.
+///
+/// [`rank()`] and [`select()`] count the set bits by scanning the words.
+/// With [`enable_index()`], the bit vector also keeps the number of set bits
+/// of every block of 512 bits, which the updates maintain with one more
+/// word-sized log, and the queries skip the blocks using these counters.
+///
+/// # Examples
+///
+/// ```
+/// # use corundum::alloc::heap::*;
+/// use corundum::stl::PBitVec;
+///
+/// Heap::transaction(|j| {
+///     let bits = PBitVec::<Heap>::new();
+///     bits.resize(1000, j);
+///     bits.enable_index(j);
+///     for i in (0..1000).step_by(10) {
+///         bits.set(i, true, j);
+///     }
+///     bits.flip(10, j);
+///     assert_eq!(bits.count_ones(), 99);
+///     assert_eq!(bits.rank(100), 9);
+///     assert_eq!(bits.select(1), Some(20));
+/// }).unwrap();
+/// ```
+///
+/// [`set()`]: #method.set
+/// [`flip()`]: #method.flip
+/// [`rank()`]: #method.rank
+/// [`select()`]: #method.select
+/// [`enable_index()`]: #method.enable_index
+pub struct PBitVec<P: MemPool> {
+    len: PCell<usize, P>,
+    words: PRefCell<PVec<u64, P>, P>,
+
+    /// The number of set bits of every block, if the index is enabled
+    blocks: PRefCell<Option<PVec<u64, P>>, P>,
+}
+
+impl<P: MemPool> PBitVec<P> {
+    /// Creates a new empty bit vector
+    pub fn new() -> Self {
+        Self {
+            len: PCell::new(0),
+            words: PRefCell::new(PVec::new()),
+            blocks: PRefCell::new(None),
+        }
+    }
+
+    /// Returns the number of bits
+    pub fn len(&self) -> usize {
+        self.len.get()
+    }
+
+    /// Returns true if there is no bit
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the bit at `index`, or `None` if it is out of bounds
+    pub fn get(&self, index: usize) -> Option<bool> {
+        if index >= self.len() {
+            None
+        } else {
+            let word = self.words.as_ref()[index / WORD_BITS];
+            Some(word & (1 << (index % WORD_BITS)) != 0)
+        }
+    }
+
+    /// Sets the bit at `index` to `value`
+    ///
+    /// # Panics
+    ///
+    /// It panics if `index` is out of bounds.
+    pub fn set(&self, index: usize, value: bool, j: &Journal<P>) {
+        let mask = 1 << (index % WORD_BITS);
+        self.update(index, |w| if value { w | mask } else { w & !mask }, j);
+    }
+
+    /// Flips the bit at `index`, and returns its new value
+    ///
+    /// # Panics
+    ///
+    /// It panics if `index` is out of bounds.
+    pub fn flip(&self, index: usize, j: &Journal<P>) -> bool {
+        let mask = 1 << (index % WORD_BITS);
+        self.update(index, |w| w ^ mask, j) & mask != 0
+    }
+
+    /// Appends a bit to the end
+    pub fn push(&self, value: bool, j: &Journal<P>) {
+        let len = self.len();
+        self.resize(len + 1, j);
+        if value {
+            self.set(len, true, j);
+        }
+    }
+
+    /// Resizes the bit vector to `len` bits; the new bits are unset
+    pub fn resize(&self, len: usize, j: &Journal<P>) {
+        let old = self.len();
+        if len == old {
+            return;
+        }
+        let nwords = (len + WORD_BITS - 1) / WORD_BITS;
+        {
+            let mut words = self.words.borrow_mut(j);
+            if len < old {
+                words.truncate(nwords);
+                if len % WORD_BITS != 0 {
+                    let last = &mut words.as_slice_mut(j)[nwords - 1];
+                    *last &= (1 << (len % WORD_BITS)) - 1;
+                }
+            } else {
+                for _ in words.len()..nwords {
+                    words.push(0, j);
+                }
+            }
+        }
+        self.len.set(len, j);
+        if self.has_index() {
+            self.resize_index(nwords, j);
+        }
+    }
+
+    /// Returns the number of set bits
+    pub fn count_ones(&self) -> usize {
+        match self.blocks.as_ref() {
+            Some(blocks) => blocks.iter().sum::<u64>() as usize,
+            None => self.words.as_ref().iter().map(|w| w.count_ones() as usize).sum(),
+        }
+    }
+
+    /// Returns the number of set bits before `index`
+    ///
+    /// # Panics
+    ///
+    /// It panics if `index` is greater than the length.
+    pub fn rank(&self, index: usize) -> usize {
+        assert!(index <= self.len(), "index out of bounds");
+        let words = self.words.as_ref();
+        let w = index / WORD_BITS;
+        let (mut rank, first) = match self.blocks.as_ref() {
+            Some(blocks) => {
+                let b = w / BLOCK_WORDS;
+                (blocks[..b].iter().sum::<u64>() as usize, b * BLOCK_WORDS)
+            }
+            None => (0, 0),
+        };
+        rank += words[first..w].iter().map(|w| w.count_ones() as usize).sum::<usize>();
+        if index % WORD_BITS != 0 {
+            rank += (words[w] & ((1 << (index % WORD_BITS)) - 1)).count_ones() as usize;
+        }
+        rank
+    }
+
+    /// Returns the index of the set bit which has `n` set bits before it, or
+    /// `None` if there are not enough set bits
+    pub fn select(&self, n: usize) -> Option<usize> {
+        let words = self.words.as_ref();
+        let mut n = n;
+        let mut first = 0;
+        if let Some(blocks) = self.blocks.as_ref() {
+            let mut b = 0;
+            while b < blocks.len() && n >= blocks[b] as usize {
+                n -= blocks[b] as usize;
+                b += 1;
+            }
+            first = b * BLOCK_WORDS;
+        }
+        for (w, word) in words.iter().enumerate().skip(first) {
+            let ones = word.count_ones() as usize;
+            if n < ones {
+                let mut word = *word;
+                for _ in 0..n {
+                    word &= word - 1;
+                }
+                return Some(w * WORD_BITS + word.trailing_zeros() as usize);
+            }
+            n -= ones;
+        }
+        None
+    }
+
+    /// Returns an iterator over the indices of the set bits
+    pub fn iter_ones(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.as_ref().iter().enumerate().flat_map(|(w, word)| {
+            let mut word = *word;
+            std::iter::from_fn(move || {
+                if word == 0 {
+                    None
+                } else {
+                    let i = word.trailing_zeros() as usize;
+                    word &= word - 1;
+                    Some(w * WORD_BITS + i)
+                }
+            })
+        })
+    }
+
+    /// Builds the rank index, or rebuilds it if it exists
+    pub fn enable_index(&self, j: &Journal<P>) {
+        let counts: Vec<u64> = self.words.as_ref().chunks(BLOCK_WORDS)
+            .map(|b| b.iter().map(|w| w.count_ones() as u64).sum())
+            .collect();
+        *self.blocks.borrow_mut(j) = Some(PVec::from_slice(&counts, j));
+    }
+
+    /// Drops the rank index
+    pub fn disable_index(&self, j: &Journal<P>) {
+        *self.blocks.borrow_mut(j) = None;
+    }
+
+    /// Returns true if the rank index is enabled
+    pub fn has_index(&self) -> bool {
+        self.blocks.as_ref().is_some()
+    }
+
+    /// Resizes the rank index to cover `nwords` words, and recounts the last
+    /// block, which is the only one whose words may have changed
+    fn resize_index(&self, nwords: usize, j: &Journal<P>) {
+        let nblocks = (nwords + BLOCK_WORDS - 1) / BLOCK_WORDS;
+        let len = self.blocks.as_ref().as_ref().map_or(nblocks, |b| b.len());
+        if len != nblocks {
+            if let Some(blocks) = &mut *self.blocks.borrow_mut(j) {
+                blocks.truncate(nblocks);
+                for _ in len..nblocks {
+                    blocks.push(0, j);
+                }
+            }
+        }
+        if let (Some(blocks), true) = (self.blocks.as_ref(), nblocks > 0) {
+            let ones = self.words.as_ref()[(nblocks - 1) * BLOCK_WORDS..].iter()
+                .map(|w| w.count_ones() as u64).sum::<u64>();
+            let count = unsafe { utils::as_mut(&blocks[nblocks - 1]) };
+            if *count != ones {
+                unsafe { count.create_log(j, Notifier::None); }
+                *count = ones;
+            }
+        }
+    }
+
+    /// Changes the word of the bit at `index` with a log, updates the index,
+    /// and returns the new word
+    fn update<F: FnOnce(u64) -> u64>(&self, index: usize, f: F, j: &Journal<P>) -> u64 {
+        assert!(index < self.len(), "index out of bounds");
+        let w = index / WORD_BITS;
+        let word = unsafe { utils::as_mut(&self.words.as_ref()[w]) };
+        let old = *word;
+        let new = f(old);
+        if new != old {
+            unsafe { word.create_log(j, Notifier::None); }
+            *word = new;
+            if let Some(blocks) = self.blocks.as_ref() {
+                let count = unsafe { utils::as_mut(&blocks[w / BLOCK_WORDS]) };
+                unsafe { count.create_log(j, Notifier::None); }
+                *count = *count + new.count_ones() as u64 - old.count_ones() as u64;
+            }
+        }
+        new
+    }
+}
+
+impl<P: MemPool> Default for PBitVec<P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P: MemPool> RootObj<P> for PBitVec<P> {
+    fn init(_: &Journal<P>) -> Self {
+        Self::new()
+    }
+}
+
+impl<P: MemPool> Debug for PBitVec<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for i in 0..self.len() {
+            f.write_str(if self.get(i) == Some(true) { "1" } else { "0" })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alloc::heap::*;
+    use super::PBitVec;
+
+    #[test]
+    fn rank_select_with_and_without_index() {
+        Heap::transaction(|j| {
+            let bits = PBitVec::<Heap>::new();
+            for i in 0..2000 {
+                bits.push(i % 3 == 0, j);
+            }
+            for indexed in &[false, true] {
+                if *indexed {
+                    bits.enable_index(j);
+                }
+                assert_eq!(bits.count_ones(), 667);
+                assert_eq!(bits.rank(0), 0);
+                assert_eq!(bits.rank(1000), 334);
+                assert_eq!(bits.rank(2000), 667);
+                assert_eq!(bits.select(334), Some(1002));
+                assert_eq!(bits.select(667), None);
+            }
+            bits.set(1002, false, j);
+            assert_eq!(bits.select(334), Some(1005));
+            bits.resize(1000, j);
+            assert_eq!(bits.count_ones(), 334);
+            assert_eq!(bits.iter_ones().last(), Some(999));
+        }).unwrap();
+    }
+
+    #[test]
+    fn index_follows_resize() {
+        Heap::transaction(|j| {
+            let bits = PBitVec::<Heap>::new();
+            bits.enable_index(j);
+            for i in 0..1500 {
+                bits.push(i % 5 != 0, j);
+            }
+            bits.resize(700, j);
+            bits.resize(1100, j);
+            bits.push(true, j);
+            bits.resize(513, j);
+            let mut ones = 0;
+            for i in 0..bits.len() {
+                assert_eq!(bits.rank(i), ones);
+                if bits.get(i) == Some(true) {
+                    assert_eq!(bits.select(ones), Some(i));
+                    ones += 1;
+                }
+            }
+            assert_eq!(bits.count_ones(), ones);
+        }).unwrap();
+    }
+}
@@ -0,0 +1,776 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::ptr;
+
+use crate::*;
+use crate::alloc::*;
+use crate::cell::PCell;
+use crate::ll;
+use crate::stm::{Journal, Logger, Notifier};
+use super::Entry;
+
+/// The number of entries in a leaf
+const LEAF_SLOTS: usize = 32;
+
+/// The maximum number of keys in an inner node
+const INNER_KEYS: usize = 32;
+
+/// The offset of no node
+const NONE: u64 = u64::MAX;
+
+/// A leaf node
+///
+/// The entries are not sorted; a slot is in use if its bit in `bitmap` is
+/// set. `fps` has a one-byte hash of the key of every slot, so a lookup
+/// compares only the keys whose fingerprints match. `hint` is a permutation
+/// of the slots in the order of their keys, which is written without a log
+/// and validated whenever it is used.
+#[repr(C)]
+struct Leaf<K, V> {
+    bitmap: u64,
+    next: u64,
+    fps: [u8; LEAF_SLOTS],
+    hint: [u8; LEAF_SLOTS],
+    slots: [MaybeUninit<(K, V)>; LEAF_SLOTS],
+}
+
+/// An inner node with `len` sorted keys and `len + 1` children
+#[repr(C)]
+struct Inner<K> {
+    len: usize,
+    keys: [MaybeUninit<K>; INNER_KEYS],
+    children: [u64; INNER_KEYS + 1],
+}
+
+#[inline]
+fn fingerprint<K: Hash>(key: &K) -> u8 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish() as u8
+}
+
+impl<K: Ord, V> Leaf<K, V> {
+    fn empty() -> Self {
+        Leaf {
+            bitmap: 0,
+            next: NONE,
+            fps: [0; LEAF_SLOTS],
+            hint: [0; LEAF_SLOTS],
+            slots: unsafe { MaybeUninit::uninit().assume_init() },
+        }
+    }
+
+    #[inline]
+    fn entry(&self, s: usize) -> &(K, V) {
+        unsafe { &*self.slots[s].as_ptr() }
+    }
+
+    #[inline]
+    fn is_full(&self) -> bool {
+        self.bitmap.count_ones() as usize == LEAF_SLOTS
+    }
+
+    /// Returns the slot of `key`
+    fn find(&self, key: &K, fp: u8) -> Option<usize> {
+        let mut bits = self.bitmap;
+        while bits != 0 {
+            let s = bits.trailing_zeros() as usize;
+            bits &= bits - 1;
+            if self.fps[s] == fp && self.entry(s).0 == *key {
+                return Some(s);
+            }
+        }
+        None
+    }
+
+    /// Returns the slots in use in the order of their keys, and their number
+    ///
+    /// The hint is used if it is valid; otherwise, it is rebuilt in place.
+    fn sorted(&self) -> ([u8; LEAF_SLOTS], usize) {
+        let n = self.bitmap.count_ones() as usize;
+        let valid = (0..n).all(|i| {
+            let s = self.hint[i] as usize;
+            s < LEAF_SLOTS && self.bitmap & (1 << s) != 0
+                && (i == 0 || self.entry(self.hint[i - 1] as usize).0 < self.entry(s).0)
+        });
+        if !valid {
+            let mut order = [0u8; LEAF_SLOTS];
+            let mut bits = self.bitmap;
+            for o in order.iter_mut().take(n) {
+                *o = bits.trailing_zeros() as u8;
+                bits &= bits - 1;
+            }
+            order[..n].sort_unstable_by(|a, b| {
+                self.entry(*a as usize).0.cmp(&self.entry(*b as usize).0)
+            });
+            // The hint has relaxed durability; it needs no log or flush
+            unsafe { utils::as_mut(self).hint = order; }
+        }
+        (self.hint, n)
+    }
+}
+
+impl<K: Ord, V> Inner<K> {
+    #[inline]
+    fn key(&self, i: usize) -> &K {
+        unsafe { &*self.keys[i].as_ptr() }
+    }
+
+    /// Returns the index of the child which may contain `key`
+    fn child_index(&self, key: &K) -> usize {
+        let (mut lo, mut hi) = (0, self.len);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if self.key(mid) <= key {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        lo
+    }
+}
+
+/// A persistent B+-tree tuned for persistent memory
+///
+/// The inner nodes are sorted as usual, but the leaves are not: a new entry
+/// goes to any free slot of its leaf, which is written and flushed without
+/// a log because no one can see it yet, and then it is published by setting
+/// one bit of the leaf's bitmap, which is the only log of the insert. The
+/// leaves keep a one-byte fingerprint of every key, so a lookup compares
+/// about one key per leaf, and a sorted-order hint, which the ordered scans
+/// and splits use and which is rebuilt when it is out of date.
+///
+/// The keys are [`Copy`] values, as the separators are copied into the inner
+/// nodes. The leaves are not merged when their entries are removed.
+///
+/// # Examples
+///
+/// ```
+/// # use corundum::alloc::heap::*;
+/// use corundum::stl::PBPlusTree;
+///
+/// Heap::transaction(|j| {
+///     let tree = PBPlusTree::<u64, u64, Heap>::new();
+///     for i in (0..1000).rev() {
+///         tree.insert(i, i * 2, j);
+///     }
+///     assert_eq!(tree.get(&500), Some(&1000));
+///     assert_eq!(tree.remove(&500, j), Some(1000));
+///     assert_eq!(tree.iter_from(&499).map(|(k, _)| *k).take(3).collect::<Vec<_>>(),
+///         vec![499, 501, 502]);
+/// }).unwrap();
+/// ```
+///
+/// [`Copy`]: std::marker::Copy
+pub struct PBPlusTree<K: PSafe, V: PSafe, P: MemPool> {
+    /// The offset of the root node, or `NONE` if the tree is empty
+    root: PCell<u64, P>,
+
+    /// The number of inner levels
+    height: PCell<usize, P>,
+
+    phantom: PhantomData<(K, V)>,
+}
+
+impl<K: PSafe + Ord + Copy + Hash, V: PSafe, P: MemPool> PBPlusTree<K, V, P> {
+    /// Creates a new empty tree
+    pub fn new() -> Self {
+        Self {
+            root: PCell::new(NONE),
+            height: PCell::new(0),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Fills an empty tree with the entries of `iter`, which are sorted by
+    /// their keys
+    ///
+    /// The nodes are built bottom-up, filled up to three quarters, and
+    /// flushed once when they are allocated; they become visible at once
+    /// when the root is set at the end. It logs only the allocations and the
+    /// root, rather than every entry. The nodes of a tree whose entries are
+    /// all removed are freed first.
+    ///
+    /// # Panics
+    ///
+    /// It panics if the tree has any entry or the keys are not strictly
+    /// ascending.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::alloc::heap::*;
+    /// use corundum::stl::PBPlusTree;
+    ///
+    /// Heap::transaction(|j| {
+    ///     let tree = PBPlusTree::<u64, u64, Heap>::new();
+    ///     tree.bulk_load((0..10000).map(|i| (i, i * 2)), j);
+    ///     assert_eq!(tree.get(&5000), Some(&10000));
+    ///     assert_eq!(tree.len(), 10000);
+    /// }).unwrap();
+    /// ```
+    pub fn bulk_load<I: IntoIterator<Item = (K, V)>>(&self, iter: I, j: &Journal<P>) {
+        assert!(self.is_empty(), "the tree should be empty");
+        let mut items: Vec<(K, V)> = iter.into_iter().collect();
+        if items.is_empty() {
+            return;
+        }
+        assert!(items.windows(2).all(|w| w[0].0 < w[1].0),
+            "the keys should be strictly ascending");
+        if self.root.get() != NONE {
+            unsafe { free_nodes::<K, V, P>(self.root.get(), self.height.get()); }
+        }
+
+        // Build the leaves from the last one to link each to the next
+        let sizes = Self::chunks(items.len(), LEAF_SLOTS * 3 / 4);
+        let mut level = Vec::with_capacity(sizes.len());
+        let mut next = NONE;
+        let mut end = items.len();
+        unsafe {
+            items.set_len(0);
+            for n in sizes.iter().rev() {
+                let start = end - n;
+                let mut leaf = Leaf::<K, V>::empty();
+                for (s, i) in (start..end).enumerate() {
+                    let e = ptr::read(items.as_ptr().add(i));
+                    leaf.fps[s] = fingerprint(&e.0);
+                    leaf.hint[s] = s as u8;
+                    leaf.slots[s] = MaybeUninit::new(e);
+                }
+                leaf.bitmap = (1u64 << n) - 1;
+                leaf.next = next;
+                let first = leaf.entry(0).0;
+                next = P::off_unchecked(P::new(leaf, j));
+                level.push((first, next));
+                end = start;
+            }
+        }
+        level.reverse();
+
+        let mut height = 0;
+        while level.len() > 1 {
+            let sizes = Self::chunks(level.len(), (INNER_KEYS + 1) * 3 / 4);
+            let mut upper = Vec::with_capacity(sizes.len());
+            let mut start = 0;
+            for n in sizes {
+                let mut node = Inner::<K> {
+                    len: n - 1,
+                    keys: unsafe { MaybeUninit::uninit().assume_init() },
+                    children: [NONE; INNER_KEYS + 1],
+                };
+                for (c, (key, off)) in level[start..start + n].iter().enumerate() {
+                    if c > 0 {
+                        node.keys[c - 1] = MaybeUninit::new(*key);
+                    }
+                    node.children[c] = *off;
+                }
+                let off = unsafe { P::off_unchecked(P::new(node, j)) };
+                upper.push((level[start].0, off));
+                start += n;
+            }
+            level = upper;
+            height += 1;
+        }
+        self.height.set(height, j);
+        self.root.set(level[0].1, j);
+    }
+
+    /// Splits `n` items into the least number of nearly equal chunks of at
+    /// most `max` items
+    fn chunks(n: usize, max: usize) -> Vec<usize> {
+        let count = (n + max - 1) / max;
+        (0..count).map(|i| n / count + (i < n % count) as usize).collect()
+    }
+
+    #[inline]
+    unsafe fn leaf<'a>(off: u64) -> &'a mut Leaf<K, V> {
+        P::get_mut_unchecked(off)
+    }
+
+    #[inline]
+    unsafe fn inner<'a>(off: u64) -> &'a mut Inner<K> {
+        P::get_mut_unchecked(off)
+    }
+
+    /// Returns the offset of the leaf which may contain `key`, and the
+    /// offsets of the inner nodes on the way
+    fn descend(&self, key: &K, path: &mut Vec<u64>) -> u64 {
+        let mut off = self.root.get();
+        for _ in 0..self.height.get() {
+            path.push(off);
+            let node = unsafe { Self::inner(off) };
+            off = node.children[node.child_index(key)];
+        }
+        off
+    }
+
+    /// Returns the offset of the leftmost leaf
+    fn first_leaf(&self) -> u64 {
+        let mut off = self.root.get();
+        if off != NONE {
+            for _ in 0..self.height.get() {
+                off = unsafe { Self::inner(off).children[0] };
+            }
+        }
+        off
+    }
+
+    /// Returns a reference to the value of `key`
+    pub fn get(&self, key: &K) -> Option<&V> {
+        if self.root.get() == NONE {
+            return None;
+        }
+        let leaf = unsafe { Self::leaf(self.descend(key, &mut Vec::new())) };
+        leaf.find(key, fingerprint(key)).map(|s| &leaf.entry(s).1)
+    }
+
+    /// Returns true if the tree has an entry for `key`
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns the number of entries
+    ///
+    /// It visits all leaves.
+    pub fn len(&self) -> usize {
+        let mut n = 0;
+        let mut off = self.first_leaf();
+        while off != NONE {
+            let leaf = unsafe { Self::leaf(off) };
+            n += leaf.bitmap.count_ones() as usize;
+            off = leaf.next;
+        }
+        n
+    }
+
+    /// Returns true if there is no entry
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Inserts or replaces the value of `key`, and returns the old value, if
+    /// any
+    pub fn insert(&self, key: K, value: V, j: &Journal<P>) -> Option<V> {
+        match self.lookup(key) {
+            Entry::Occupied(mut e) => Some(e.insert(value, j)),
+            Entry::Vacant(e) => {
+                e.insert(value, j);
+                None
+            }
+        }
+    }
+
+    /// Returns the entry of `key` for in-place update or insertion
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, P> {
+        self.lookup(key)
+    }
+
+    /// Finds the entry of `key`; the caller consumes the entry before the
+    /// tree is accessed again
+    fn lookup(&self, key: K) -> Entry<'_, K, V, P> {
+        let fp = fingerprint(&key);
+        let mut path = Vec::new();
+        let mut off = NONE;
+        if self.root.get() != NONE {
+            off = self.descend(&key, &mut path);
+            let leaf = unsafe { Self::leaf(off) };
+            if let Some(s) = leaf.find(&key, fp) {
+                return Entry::occupied(key, unsafe { utils::as_mut(&leaf.entry(s).1) });
+            }
+        }
+        Entry::vacant(key, move |key, value, j| {
+            self.insert_new(path, off, key, fp, value, j)
+        })
+    }
+
+    /// Inserts a new entry into leaf `off`, which is found by `descend()`
+    /// along `path`, and returns a reference to its value
+    fn insert_new(&self, mut path: Vec<u64>, mut off: u64, key: K, fp: u8, value: V,
+        j: &Journal<P>) -> &V {
+        if off == NONE {
+            let leaf = unsafe { P::new(Leaf::<K, V>::empty(), j) };
+            off = unsafe { P::off_unchecked(leaf) };
+            self.root.set(off, j);
+        }
+        let mut leaf = unsafe { Self::leaf(off) };
+        if leaf.is_full() {
+            let (sep, right) = self.split_leaf(leaf, j);
+            self.insert_separator(&mut path, sep, right, off, j);
+            if key >= sep {
+                off = right;
+                leaf = unsafe { Self::leaf(off) };
+            }
+        }
+
+        // The free slot is invisible until its bit is set
+        let s = (!leaf.bitmap).trailing_zeros() as usize;
+        unsafe {
+            ptr::write(leaf.slots[s].as_mut_ptr(), (key, value));
+            leaf.fps[s] = fp;
+            ll::persist_obj(&leaf.slots[s], false);
+            ll::persist_obj(&leaf.fps[s], false);
+            leaf.bitmap.create_log(j, Notifier::None);
+        }
+        leaf.bitmap |= 1 << s;
+        &leaf.entry(s).1
+    }
+
+    /// Removes the entry of `key`, and returns its value
+    pub fn remove(&self, key: &K, j: &Journal<P>) -> Option<V> {
+        if self.root.get() == NONE {
+            return None;
+        }
+        let leaf = unsafe { Self::leaf(self.descend(key, &mut Vec::new())) };
+        let s = leaf.find(key, fingerprint(key))?;
+        unsafe {
+            // The slot may be reused later in this transaction, so it should
+            // be restored if the transaction rolls back
+            j.batch(|| {
+                leaf.slots[s].create_log(j, Notifier::None);
+                leaf.fps[s].create_log(j, Notifier::None);
+                leaf.bitmap.create_log(j, Notifier::None);
+            });
+            leaf.bitmap &= !(1 << s);
+            let (_, v) = ptr::read(leaf.slots[s].as_ptr());
+            Some(v)
+        }
+    }
+
+    /// Moves the upper half of a full leaf to a new leaf, and returns the
+    /// first key and the offset of the new leaf
+    fn split_leaf(&self, leaf: &mut Leaf<K, V>, j: &Journal<P>) -> (K, u64) {
+        let (order, n) = leaf.sorted();
+        let mid = n / 2;
+        let sep = leaf.entry(order[mid] as usize).0;
+        unsafe {
+            let right = P::new(Leaf::<K, V>::empty(), j);
+
+            // The leaf is only read until all of its logs are taken
+            j.batch(|| {
+                leaf.fps.create_log(j, Notifier::None);
+                leaf.bitmap.create_log(j, Notifier::None);
+                leaf.next.create_log(j, Notifier::None);
+                for (i, s) in order[mid..n].iter().enumerate() {
+                    let s = *s as usize;
+                    leaf.slots[s].create_log(j, Notifier::None);
+                    ptr::copy_nonoverlapping(leaf.slots[s].as_ptr(), right.slots[i].as_mut_ptr(), 1);
+                    right.fps[i] = leaf.fps[s];
+                    right.hint[i] = i as u8;
+                    right.bitmap |= 1 << i;
+                }
+            });
+            right.next = leaf.next;
+            ll::persist_obj(right, false);
+            let right_off = P::off_unchecked(right);
+
+            for s in &order[mid..n] {
+                leaf.bitmap &= !(1 << *s);
+            }
+            leaf.next = right_off;
+            (sep, right_off)
+        }
+    }
+
+    /// Inserts `sep` and the node at `right` after the node at `left` into
+    /// the parent at the end of `path`
+    fn insert_separator(&self, path: &mut Vec<u64>, sep: K, right: u64, left: u64, j: &Journal<P>) {
+        let parent_off = match path.pop() {
+            Some(off) => off,
+            None => {
+                // A new root
+                let mut root = Inner::<K> {
+                    len: 1,
+                    keys: unsafe { MaybeUninit::uninit().assume_init() },
+                    children: [NONE; INNER_KEYS + 1],
+                };
+                root.keys[0] = MaybeUninit::new(sep);
+                root.children[0] = left;
+                root.children[1] = right;
+                let root = unsafe { P::new(root, j) };
+                self.root.set(unsafe { P::off_unchecked(root) }, j);
+                self.height.set(self.height.get() + 1, j);
+                return;
+            }
+        };
+        let node = unsafe { Self::inner(parent_off) };
+        unsafe { node.create_log(j, Notifier::None); }
+        let i = node.child_index(&sep);
+        if node.len < INNER_KEYS {
+            node.keys.copy_within(i..node.len, i + 1);
+            node.children.copy_within(i + 1..node.len + 1, i + 2);
+            node.keys[i] = MaybeUninit::new(sep);
+            node.children[i + 1] = right;
+            node.len += 1;
+            return;
+        }
+
+        // Split the inner node around its middle key
+        let mut keys: Vec<K> = (0..node.len).map(|k| *node.key(k)).collect();
+        let mut children = node.children[..node.len + 1].to_vec();
+        keys.insert(i, sep);
+        children.insert(i + 1, right);
+        let mid = keys.len() / 2;
+        let mut new = Inner::<K> {
+            len: keys.len() - mid - 1,
+            keys: unsafe { MaybeUninit::uninit().assume_init() },
+            children: [NONE; INNER_KEYS + 1],
+        };
+        for (k, key) in keys[mid + 1..].iter().enumerate() {
+            new.keys[k] = MaybeUninit::new(*key);
+        }
+        new.children[..new.len + 1].copy_from_slice(&children[mid + 1..]);
+        node.len = mid;
+        for (k, key) in keys[..mid].iter().enumerate() {
+            node.keys[k] = MaybeUninit::new(*key);
+        }
+        node.children[..mid + 1].copy_from_slice(&children[..mid + 1]);
+        let new = unsafe { P::new(new, j) };
+        self.insert_separator(path, keys[mid], unsafe { P::off_unchecked(new) }, parent_off, j);
+    }
+
+    /// Returns an iterator over the entries in the order of their keys
+    pub fn iter(&self) -> Iter<'_, K, V, P> {
+        Iter::new(self.first_leaf(), None)
+    }
+
+    /// Returns an iterator over the entries with keys greater than or equal
+    /// to `key`, in the order of their keys
+    pub fn iter_from(&self, key: &K) -> Iter<'_, K, V, P> {
+        if self.root.get() == NONE {
+            Iter::new(NONE, None)
+        } else {
+            Iter::new(self.descend(key, &mut Vec::new()), Some(*key))
+        }
+    }
+
+    /// Returns an iterator over the entries in the order of their keys which
+    /// allows modifying the values; every value is logged when it is visited
+    pub fn iter_mut<'a>(&'a mut self, j: &'a Journal<P>) -> impl Iterator<Item = (&'a K, &'a mut V)> + 'a {
+        self.iter().map(move |(k, v)| unsafe {
+            let v = utils::as_mut(v);
+            v.create_log(j, Notifier::None);
+            (k, v)
+        })
+    }
+
+    /// Returns an iterator over the keys in order
+    pub fn keys(&self) -> impl Iterator<Item = &K> + '_ {
+        self.iter().map(|(k, _)| k)
+    }
+
+    /// Returns an iterator over the values in the order of their keys
+    pub fn values(&self) -> impl Iterator<Item = &V> + '_ {
+        self.iter().map(|(_, v)| v)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<K, V, P> PBPlusTree<K, V, P>
+where
+    K: PSafe + Ord + Copy + Hash + Sync,
+    V: PSafe + Sync,
+    P: MemPool,
+{
+    /// Returns a parallel iterator over the entries, which visits the leaves
+    /// on different threads
+    ///
+    /// It is available with the `rayon` feature. The leaves are listed
+    /// before the iteration starts; the entries are produced in the order
+    /// of their keys if they are collected.
+    pub fn par_iter(&self) -> impl rayon::iter::ParallelIterator<Item = (&K, &V)> + '_ {
+        use rayon::prelude::*;
+        let mut leaves = Vec::new();
+        let mut off = self.first_leaf();
+        while off != NONE {
+            leaves.push(off);
+            off = unsafe { Self::leaf(off).next };
+        }
+        leaves.into_par_iter().flat_map_iter(|off| {
+            let leaf = unsafe { P::get_unchecked::<Leaf<K, V>>(off) };
+            let (order, n) = leaf.sorted();
+            (0..n).map(move |i| {
+                let (k, v) = leaf.entry(order[i] as usize);
+                (k, v)
+            })
+        })
+    }
+}
+
+impl<K: PSafe + Ord + Copy + Hash, V: PSafe, P: MemPool> PFromIterator<(K, V), P> for PBPlusTree<K, V, P> {
+    fn pfrom_iter<I: IntoIterator<Item = (K, V)>>(iter: I, j: &Journal<P>) -> Self {
+        let res = Self::new();
+        for (k, v) in iter {
+            res.insert(k, v, j);
+        }
+        res
+    }
+}
+
+/// An iterator over the entries of a [`PBPlusTree`] in the order of their
+/// keys
+///
+/// [`PBPlusTree`]: ./struct.PBPlusTree.html
+pub struct Iter<'a, K, V, P: MemPool> {
+    leaf: u64,
+    order: [u8; LEAF_SLOTS],
+    len: usize,
+    pos: usize,
+    from: Option<K>,
+    phantom: PhantomData<&'a (K, V, P)>,
+}
+
+impl<'a, K: Ord + Copy, V, P: MemPool> Iter<'a, K, V, P> {
+    fn new(leaf: u64, from: Option<K>) -> Self {
+        let mut iter = Iter {
+            leaf: NONE,
+            order: [0; LEAF_SLOTS],
+            len: 0,
+            pos: 0,
+            from,
+            phantom: PhantomData,
+        };
+        iter.load(leaf);
+        iter
+    }
+
+    fn load(&mut self, off: u64) {
+        self.leaf = off;
+        self.pos = 0;
+        self.len = 0;
+        if off != NONE {
+            let leaf = unsafe { P::get_unchecked::<Leaf<K, V>>(off) };
+            let (order, len) = leaf.sorted();
+            self.order = order;
+            self.len = len;
+        }
+    }
+}
+
+impl<'a, K: Ord + Copy, V, P: MemPool> Iterator for Iter<'a, K, V, P> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.leaf == NONE {
+                return None;
+            }
+            let leaf = unsafe { P::get_unchecked::<Leaf<K, V>>(self.leaf) };
+            if self.pos == self.len {
+                self.load(leaf.next);
+                continue;
+            }
+            let (k, v) = leaf.entry(self.order[self.pos] as usize);
+            self.pos += 1;
+            if let Some(from) = &self.from {
+                if k < from {
+                    continue;
+                }
+                self.from = None;
+            }
+            return Some((k, v));
+        }
+    }
+}
+
+/// Drops the entries of the subtree at `off` with `height` inner levels,
+/// and frees its nodes
+unsafe fn free_nodes<K: PSafe, V: PSafe, P: MemPool>(off: u64, height: usize) {
+    if height == 0 {
+        let leaf = P::get_mut_unchecked::<Leaf<K, V>>(off);
+        let mut bits = leaf.bitmap;
+        while bits != 0 {
+            let s = bits.trailing_zeros() as usize;
+            bits &= bits - 1;
+            ptr::drop_in_place(leaf.slots[s].as_mut_ptr());
+        }
+        P::free(leaf);
+    } else {
+        let node = P::get_mut_unchecked::<Inner<K>>(off);
+        for c in &node.children[..node.len + 1] {
+            free_nodes::<K, V, P>(*c, height - 1);
+        }
+        P::free(node);
+    }
+}
+
+impl<K: PSafe, V: PSafe, P: MemPool> Drop for PBPlusTree<K, V, P> {
+    fn drop(&mut self) {
+        if self.root.get() != NONE {
+            unsafe { free_nodes::<K, V, P>(self.root.get(), self.height.get()); }
+        }
+    }
+}
+
+impl<K: PSafe + Ord + Copy + Hash, V: PSafe, P: MemPool> Default for PBPlusTree<K, V, P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: PSafe + Ord + Copy + Hash, V: PSafe, P: MemPool> RootObj<P> for PBPlusTree<K, V, P> {
+    fn init(_: &Journal<P>) -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, P> Debug for PBPlusTree<K, V, P>
+where
+    K: PSafe + Ord + Copy + Hash + Debug,
+    V: PSafe + Debug,
+    P: MemPool,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alloc::heap::*;
+    use super::{PBPlusTree, LEAF_SLOTS, INNER_KEYS};
+
+    #[test]
+    fn bptree_splits_and_rolls_back() {
+        let n = (LEAF_SLOTS * INNER_KEYS * 2) as u64;
+        Heap::transaction(|j| {
+            let tree = PBPlusTree::<u64, u64, Heap>::new();
+            for i in 0..n {
+                // A scattered insertion order
+                let k = (i * 7919) % n;
+                assert_eq!(tree.insert(k, k, j), None);
+            }
+            assert_eq!(tree.len(), n as usize);
+            assert!(tree.iter().map(|(k, _)| *k).eq(0..n));
+
+            for k in (0..n).step_by(3) {
+                assert_eq!(tree.remove(&k, j), Some(k));
+            }
+            assert_eq!(tree.insert(1, 10, j), Some(1));
+            assert_eq!(tree.get(&1), Some(&10));
+            assert_eq!(tree.get(&3), None);
+            assert_eq!(tree.iter_from(&3).next(), Some((&4, &4)));
+        }).unwrap();
+    }
+
+    #[test]
+    fn bptree_bulk_load_after_removals() {
+        Heap::transaction(|j| {
+            let tree = PBPlusTree::<u64, u64, Heap>::new();
+            for k in 0..100 {
+                tree.insert(k, k, j);
+            }
+            for k in 0..100 {
+                tree.remove(&k, j);
+            }
+            assert!(tree.is_empty());
+            tree.bulk_load((0..1000).map(|k| (k, k + 1)), j);
+            assert_eq!(tree.len(), 1000);
+            assert_eq!(tree.get(&999), Some(&1000));
+            assert!(tree.keys().copied().eq(0..1000));
+        }).unwrap();
+    }
+}
@@ -0,0 +1,381 @@
+use std::fmt::{self, Debug};
+
+use crate::*;
+use crate::alloc::*;
+use crate::vec::Vec as PVec;
+use crate::cell::{PCell, PRefCell};
+use crate::stm::Journal;
+use crate::clone::PClone;
+
+/// A persistent grow-only counter (G-Counter)
+///
+/// Each replica increments its own slot, and the value of the counter is the
+/// sum of all slots. Merging two counters takes the maximum of each slot, so
+/// replicas converge regardless of the order of merges.
+///
+/// # Examples
+///
+/// ```
+/// # use corundum::alloc::heap::*;
+/// use corundum::stl::GCounter;
+///
+/// Heap::transaction(|j| {
+///     let a = GCounter::<Heap>::new();
+///     let b = GCounter::<Heap>::new();
+///     a.increment(1, 5, j);
+///     b.increment(2, 3, j);
+///     b.increment(1, 2, j);
+///     a.merge(&b, j);
+///     assert_eq!(a.value(), 8);
+/// }).unwrap();
+/// ```
+pub struct GCounter<P: MemPool> {
+    counts: PRefCell<PVec<(u64, u64), P>, P>,
+}
+
+impl<P: MemPool> GCounter<P> {
+    /// Creates a new counter with value `0`
+    pub fn new() -> Self {
+        Self { counts: PRefCell::new(PVec::new()) }
+    }
+
+    /// Returns the value of the counter
+    pub fn value(&self) -> u64 {
+        self.counts.borrow().iter().map(|e| e.1).sum()
+    }
+
+    /// Returns the contribution of the given replica
+    pub fn get(&self, replica: u64) -> u64 {
+        let counts = self.counts.borrow();
+        match counts.binary_search_by_key(&replica, |e| e.0) {
+            Ok(i) => counts[i].1,
+            Err(_) => 0
+        }
+    }
+
+    /// Increments the counter by `n` on behalf of `replica`
+    pub fn increment(&self, replica: u64, n: u64, j: &Journal<P>) {
+        let v = self.get(replica) + n;
+        self.set(replica, v, j);
+    }
+
+    fn set(&self, replica: u64, v: u64, j: &Journal<P>) {
+        let mut counts = self.counts.borrow_mut(j);
+        match counts.binary_search_by_key(&replica, |e| e.0) {
+            Ok(i) => counts.as_slice_mut(j)[i].1 = v,
+            Err(i) => counts.insert(i, (replica, v), j)
+        }
+    }
+
+    /// Merges the state of `other` into `self`
+    pub fn merge(&self, other: &Self, j: &Journal<P>) {
+        for (replica, v) in other.counts.borrow().iter() {
+            if *v > self.get(*replica) {
+                self.set(*replica, *v, j);
+            }
+        }
+    }
+}
+
+impl<P: MemPool> Default for GCounter<P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P: MemPool> RootObj<P> for GCounter<P> {
+    fn init(_: &Journal<P>) -> Self {
+        Self::new()
+    }
+}
+
+impl<P: MemPool> PClone<P> for GCounter<P> {
+    fn pclone(&self, j: &Journal<P>) -> Self {
+        Self { counts: PRefCell::new(self.counts.borrow().pclone(j)) }
+    }
+}
+
+impl<P: MemPool> Debug for GCounter<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GCounter({})", self.value())
+    }
+}
+
+/// A persistent last-writer-wins register (LWW-Register)
+///
+/// Each assignment is tagged with a timestamp and the id of the writing
+/// replica. The assignment with the greatest `(timestamp, replica)` pair wins,
+/// so concurrent assignments are resolved deterministically.
+///
+/// # Examples
+///
+/// ```
+/// # use corundum::alloc::heap::*;
+/// use corundum::stl::LwwRegister;
+///
+/// Heap::transaction(|j| {
+///     let a = LwwRegister::<i32, Heap>::new(0);
+///     let b = LwwRegister::<i32, Heap>::new(0);
+///     a.set(10, 5, 1, j);
+///     b.set(20, 7, 2, j);
+///     a.merge(&b, j);
+///     b.merge(&a, j);
+///     assert_eq!(a.get(), 20);
+///     assert_eq!(b.get(), 20);
+/// }).unwrap();
+/// ```
+pub struct LwwRegister<T: PSafe, P: MemPool> {
+    value: PRefCell<T, P>,
+    stamp: PCell<(u64, u64), P>,
+}
+
+impl<T: PSafe, P: MemPool> LwwRegister<T, P> {
+    /// Creates a new register with an initial value which loses against any
+    /// assignment
+    pub fn new(value: T) -> Self {
+        Self {
+            value: PRefCell::new(value),
+            stamp: PCell::new((0, 0)),
+        }
+    }
+
+    /// Returns the `(timestamp, replica)` pair of the current value
+    pub fn stamp(&self) -> (u64, u64) {
+        self.stamp.get()
+    }
+
+    /// Assigns `value` if `(timestamp, replica)` is greater than the stamp
+    /// of the current value. It returns true if the value is assigned.
+    pub fn set(&self, value: T, timestamp: u64, replica: u64, j: &Journal<P>) -> bool {
+        if (timestamp, replica) > self.stamp.get() {
+            *self.value.borrow_mut(j) = value;
+            self.stamp.set((timestamp, replica), j);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl<T: PSafe + Copy, P: MemPool> LwwRegister<T, P> {
+    /// Returns the current value
+    pub fn get(&self) -> T {
+        *self.value.borrow()
+    }
+}
+
+impl<T: PSafe + PClone<P>, P: MemPool> LwwRegister<T, P> {
+    /// Merges the state of `other` into `self`
+    pub fn merge(&self, other: &Self, j: &Journal<P>) {
+        let (timestamp, replica) = other.stamp();
+        self.set(other.value.borrow().pclone(j), timestamp, replica, j);
+    }
+}
+
+impl<T: PSafe + Default, P: MemPool> Default for LwwRegister<T, P> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: PSafe + Default, P: MemPool> RootObj<P> for LwwRegister<T, P> {
+    fn init(_: &Journal<P>) -> Self {
+        Self::default()
+    }
+}
+
+impl<T: PSafe + PClone<P>, P: MemPool> PClone<P> for LwwRegister<T, P> {
+    fn pclone(&self, j: &Journal<P>) -> Self {
+        Self {
+            value: PRefCell::new(self.value.borrow().pclone(j)),
+            stamp: PCell::new(self.stamp()),
+        }
+    }
+}
+
+impl<T: PSafe + Debug, P: MemPool> Debug for LwwRegister<T, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LwwRegister({:?} @ {:?})", *self.value.borrow(), self.stamp())
+    }
+}
+
+/// A unique tag of an add operation: `(replica, sequence)`
+type Tag = (u64, u64);
+
+/// A persistent observed-remove set (OR-Set)
+///
+/// Each add operation is tagged uniquely, and a remove operation only removes
+/// the tags it has observed. Therefore, an add which is concurrent with a
+/// remove of the same element wins after merging.
+///
+/// # Examples
+///
+/// ```
+/// # use corundum::alloc::heap::*;
+/// use corundum::stl::OrSet;
+///
+/// Heap::transaction(|j| {
+///     let a = OrSet::<u32, Heap>::new(1);
+///     let b = OrSet::<u32, Heap>::new(2);
+///     a.add(7, j);
+///     b.merge(&a, j);
+///     b.remove(&7, j); // removes the observed add
+///     a.add(7, j);     // a concurrent add
+///     a.merge(&b, j);
+///     b.merge(&a, j);
+///     assert!(a.contains(&7));
+///     assert!(b.contains(&7));
+/// }).unwrap();
+/// ```
+pub struct OrSet<T: PSafe, P: MemPool> {
+    replica: u64,
+    seq: PCell<u64, P>,
+    adds: PRefCell<PVec<(T, Tag), P>, P>,
+    removes: PRefCell<PVec<Tag, P>, P>,
+}
+
+impl<T: PSafe, P: MemPool> OrSet<T, P> {
+    /// Creates a new empty set owned by `replica`
+    pub fn new(replica: u64) -> Self {
+        Self {
+            replica,
+            seq: PCell::new(0),
+            adds: PRefCell::new(PVec::new()),
+            removes: PRefCell::new(PVec::new()),
+        }
+    }
+
+    /// Returns the id of the owning replica
+    pub fn replica(&self) -> u64 {
+        self.replica
+    }
+
+    /// Calls `f` for every element in the set
+    pub fn foreach<F: FnMut(&T)>(&self, mut f: F) where T: PartialEq {
+        let adds = self.adds.borrow();
+        for (i, (x, _)) in adds.iter().enumerate() {
+            if adds[..i].iter().all(|(y, _)| y != x) {
+                f(x);
+            }
+        }
+    }
+
+    /// Returns the number of elements in the set
+    pub fn len(&self) -> usize where T: PartialEq {
+        let mut len = 0;
+        self.foreach(|_| len += 1);
+        len
+    }
+
+    /// Returns true if the set contains no elements
+    pub fn is_empty(&self) -> bool {
+        self.adds.borrow().is_empty()
+    }
+}
+
+impl<T: PSafe + PartialEq, P: MemPool> OrSet<T, P> {
+    /// Returns true if the set contains `x`
+    pub fn contains(&self, x: &T) -> bool {
+        self.adds.borrow().iter().any(|(y, _)| y == x)
+    }
+
+    /// Adds `x` to the set
+    pub fn add(&self, x: T, j: &Journal<P>) {
+        let seq = self.seq.get() + 1;
+        self.seq.set(seq, j);
+        self.adds.borrow_mut(j).push((x, (self.replica, seq)), j);
+    }
+
+    /// Removes all observed additions of `x` from the set
+    pub fn remove(&self, x: &T, j: &Journal<P>) {
+        let mut adds = self.adds.borrow_mut(j);
+        let mut removes = self.removes.borrow_mut(j);
+        adds.as_slice_mut(j); // logs the elements before they are reordered
+        let mut i = 0;
+        while i < adds.len() {
+            if adds[i].0 == *x {
+                removes.push(adds.swap_remove(i).1, j);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+impl<T: PSafe + PartialEq + PClone<P>, P: MemPool> OrSet<T, P> {
+    /// Merges the state of `other` into `self`
+    pub fn merge(&self, other: &Self, j: &Journal<P>) {
+        let mut adds = self.adds.borrow_mut(j);
+        let mut removes = self.removes.borrow_mut(j);
+        for tag in other.removes.borrow().iter() {
+            if !removes.contains(tag) {
+                removes.push(*tag, j);
+            }
+        }
+        for (x, tag) in other.adds.borrow().iter() {
+            if !adds.iter().any(|(_, t)| t == tag) {
+                adds.push((x.pclone(j), *tag), j);
+            }
+        }
+        adds.as_slice_mut(j); // logs the elements before they are reordered
+        let mut i = 0;
+        while i < adds.len() {
+            if removes.contains(&adds[i].1) {
+                adds.swap_remove(i);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+impl<T: PSafe + PClone<P>, P: MemPool> PClone<P> for OrSet<T, P> {
+    fn pclone(&self, j: &Journal<P>) -> Self {
+        Self {
+            replica: self.replica,
+            seq: PCell::new(self.seq.get()),
+            adds: PRefCell::new(self.adds.borrow().pclone(j)),
+            removes: PRefCell::new(self.removes.borrow().pclone(j)),
+        }
+    }
+}
+
+impl<T: PSafe + PartialEq + Debug, P: MemPool> Debug for OrSet<T, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut s = f.debug_set();
+        self.foreach(|x| { s.entry(x); });
+        s.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alloc::heap::*;
+    use crate::stl::{GCounter, OrSet};
+
+    #[test]
+    fn crdt_convergence() {
+        Heap::transaction(|j| {
+            let a = GCounter::<Heap>::new();
+            let b = GCounter::<Heap>::new();
+            a.increment(1, 1, j);
+            b.increment(2, 2, j);
+            a.merge(&b, j);
+            b.merge(&a, j);
+            a.merge(&b, j);
+            assert_eq!(a.value(), 3);
+            assert_eq!(b.value(), 3);
+
+            let x = OrSet::<u8, Heap>::new(1);
+            let y = OrSet::<u8, Heap>::new(2);
+            x.add(1, j);
+            x.add(2, j);
+            y.merge(&x, j);
+            y.remove(&1, j);
+            x.merge(&y, j);
+            assert!(!x.contains(&1));
+            assert!(x.contains(&2));
+            assert_eq!(x.len(), 1);
+        }).unwrap();
+    }
+}
@@ -0,0 +1,260 @@
+use std::fmt::{self, Debug};
+use std::marker::PhantomData;
+
+use crate::*;
+use crate::alloc::*;
+use crate::cell::PRefCell;
+use crate::stm::{Journal, Logger, Notifier};
+
+/// A view into a single entry of a map, which is either occupied or vacant
+///
+/// It is returned by the `entry()` method of the maps in [`stl`], which
+/// looks up the key once; the entry then either updates the value in place,
+/// logging it only once, or inserts a new value at the position which the
+/// lookup has found.
+///
+/// The map stays mutably borrowed while the entry is alive, so there is at
+/// most one mutable reference to the value.
+///
+/// # Examples
+///
+/// ```
+/// # use corundum::alloc::heap::*;
+/// use corundum::stl::PBPlusTree;
+///
+/// Heap::transaction(|j| {
+///     let mut counts = PBPlusTree::<u8, u64, Heap>::new();
+///     for b in b"hello" {
+///         counts.entry(*b).and_modify(|c| *c += 1, j).or_insert(1, j);
+///     }
+///     assert_eq!(counts.get(&b'l'), Some(&2));
+///     assert_eq!(counts.get(&b'o'), Some(&1));
+/// }).unwrap();
+/// ```
+///
+/// [`stl`]: index.html
+pub enum Entry<'a, K, V: PSafe, P: MemPool> {
+    Occupied(OccupiedEntry<'a, K, V, P>),
+    Vacant(VacantEntry<'a, K, V, P>),
+}
+
+/// An occupied entry; it is a part of the [`Entry`] enum
+///
+/// [`Entry`]: enum.Entry.html
+pub struct OccupiedEntry<'a, K, V: PSafe, P: MemPool> {
+    key: K,
+    value: Value<'a, V, P>,
+}
+
+/// The value of an occupied entry
+enum Value<'a, V: PSafe, P: MemPool> {
+    /// A value stored in the map itself, which is logged directly
+    Plain(&'a mut V, PhantomData<P>),
+
+    /// A value in a cell, which is logged through the cell
+    Cell(&'a mut PRefCell<V, P>),
+}
+
+/// A vacant entry; it is a part of the [`Entry`] enum
+///
+/// [`Entry`]: enum.Entry.html
+pub struct VacantEntry<'a, K, V: PSafe, P: MemPool> {
+    key: K,
+
+    /// Inserts the key and the value at the position found by the lookup
+    insert: Box<dyn FnOnce(K, V, &Journal<P>) -> &'a V + 'a>,
+}
+
+impl<'a, K, V: PSafe, P: MemPool> Entry<'a, K, V, P> {
+    pub(crate) fn occupied(key: K, value: &'a mut V) -> Self {
+        Entry::Occupied(OccupiedEntry { key, value: Value::Plain(value, PhantomData) })
+    }
+
+    pub(crate) fn occupied_cell(key: K, cell: &'a mut PRefCell<V, P>) -> Self {
+        Entry::Occupied(OccupiedEntry { key, value: Value::Cell(cell) })
+    }
+
+    pub(crate) fn vacant<F>(key: K, insert: F) -> Self
+    where F: FnOnce(K, V, &Journal<P>) -> &'a V + 'a {
+        Entry::Vacant(VacantEntry { key, insert: Box::new(insert) })
+    }
+
+    /// Returns a reference to the key of this entry
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(e) => e.key(),
+            Entry::Vacant(e) => e.key(),
+        }
+    }
+
+    /// Inserts `default` if the entry is vacant, and returns a reference to
+    /// the value
+    pub fn or_insert(self, default: V, j: &Journal<P>) -> &'a V {
+        match self {
+            Entry::Occupied(e) => e.into_ref(),
+            Entry::Vacant(e) => e.insert(default, j),
+        }
+    }
+
+    /// Inserts the result of `default` if the entry is vacant, and returns a
+    /// reference to the value
+    ///
+    /// `default` is not called if the entry is occupied.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F, j: &Journal<P>) -> &'a V {
+        match self {
+            Entry::Occupied(e) => e.into_ref(),
+            Entry::Vacant(e) => e.insert(default(), j),
+        }
+    }
+
+    /// Inserts the default value if the entry is vacant, and returns a
+    /// reference to the value
+    pub fn or_default(self, j: &Journal<P>) -> &'a V
+    where V: Default {
+        self.or_insert_with(V::default, j)
+    }
+
+    /// Updates the value in place if the entry is occupied
+    ///
+    /// The value is logged once before `f` is called.
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F, j: &Journal<P>) -> Self {
+        match self {
+            Entry::Occupied(mut e) => {
+                f(e.value_mut(j));
+                Entry::Occupied(e)
+            }
+            Entry::Vacant(e) => Entry::Vacant(e),
+        }
+    }
+}
+
+impl<'a, K, V: PSafe, P: MemPool> OccupiedEntry<'a, K, V, P> {
+    /// Returns a reference to the key of this entry
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Returns a reference to the value
+    pub fn get(&self) -> &V {
+        match &self.value {
+            Value::Plain(v, _) => v,
+            Value::Cell(c) => c.as_ref(),
+        }
+    }
+
+    /// Converts the entry into a reference to the value with the lifetime of
+    /// the map
+    pub fn into_ref(self) -> &'a V {
+        match self.value {
+            Value::Plain(v, _) => v,
+            Value::Cell(c) => c.as_ref(),
+        }
+    }
+
+    /// Converts the entry into a mutable reference to the value after
+    /// logging it
+    pub fn into_mut(self, j: &Journal<P>) -> &'a mut V {
+        match self.value {
+            Value::Plain(v, _) => {
+                unsafe { (*v).create_log(j, Notifier::None); }
+                v
+            }
+            Value::Cell(c) => c.get_mut(j),
+        }
+    }
+
+    /// Replaces the value, and returns the old one
+    pub fn insert(&mut self, value: V, j: &Journal<P>) -> V {
+        std::mem::replace(self.value_mut(j), value)
+    }
+
+    /// Returns a mutable reference to the value after logging it
+    pub fn value_mut(&mut self, j: &Journal<P>) -> &mut V {
+        match &mut self.value {
+            Value::Plain(v, _) => {
+                unsafe { (**v).create_log(j, Notifier::None); }
+                v
+            }
+            Value::Cell(c) => c.get_mut(j),
+        }
+    }
+}
+
+impl<'a, K, V: PSafe, P: MemPool> VacantEntry<'a, K, V, P> {
+    /// Returns a reference to the key of this entry
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Takes the ownership of the key
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Inserts `value` with the key of this entry, and returns a reference
+    /// to it
+    pub fn insert(self, value: V, j: &Journal<P>) -> &'a V {
+        (self.insert)(self.key, value, j)
+    }
+}
+
+impl<K: Debug, V: PSafe + Debug, P: MemPool> Debug for Entry<'_, K, V, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Entry::Occupied(e) => f.debug_tuple("Occupied").field(e).finish(),
+            Entry::Vacant(e) => f.debug_tuple("Vacant").field(e).finish(),
+        }
+    }
+}
+
+impl<K: Debug, V: PSafe + Debug, P: MemPool> Debug for OccupiedEntry<'_, K, V, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OccupiedEntry")
+            .field("key", &self.key)
+            .field("value", self.get())
+            .finish()
+    }
+}
+
+impl<K: Debug, V: PSafe, P: MemPool> Debug for VacantEntry<'_, K, V, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("VacantEntry").field(&self.key).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alloc::heap::*;
+    use crate::stl::{Entry, HashMap, PArt, PLruCache};
+
+    #[test]
+    fn entries_update_in_place() {
+        Heap::transaction(|j| {
+            let mut map = HashMap::<u32, u32, Heap>::new(j);
+            let mut art = PArt::<u32, Heap>::new();
+            let mut cache = PLruCache::<u32, u32, Heap>::new(4, j);
+            for w in &["a", "b", "a", "ab", "a"] {
+                map.entry(w.len() as u32).and_modify(|c| *c += 1, j).or_insert(1, j);
+                art.entry(w).and_modify(|c| *c += 1, j).or_insert(1, j);
+                cache.entry(w.len() as u32).and_modify(|c| *c += 1, j).or_default(j);
+            }
+            assert_eq!(map.get(1), Some(&4));
+            assert_eq!(map.get(2), Some(&1));
+            assert_eq!(art.get("a"), Some(&3));
+            assert_eq!(art.get("ab"), Some(&1));
+            assert_eq!(art.len(), 3);
+            assert_eq!(cache.peek(&1), Some(&3));
+            assert_eq!(*art.entry("c").or_insert_with(|| 7, j), 7);
+            assert!(art.entry("c").key() == &"c");
+
+            if let Entry::Occupied(mut e) = map.entry(1) {
+                assert_eq!(e.insert(10, j), 4);
+                *e.value_mut(j) += 1;
+                assert_eq!(*e.into_mut(j), 11);
+            } else {
+                unreachable!()
+            }
+            assert_eq!(map.get(1), Some(&11));
+        }).unwrap();
+    }
+}
@@ -11,6 +11,7 @@ use crate::cell::PRefCell;
 use crate::stm::Journal;
 use crate::clone::PClone;
 use crate::gen::Allocatable;
+use super::Entry;
 
 const BUCKETS_MAX: usize = 16;
 
@@ -19,6 +20,9 @@ type Bucket<K, P: MemPool> = PVec<PRefCell<(K, usize),P>,P>;
 pub struct HashMap<K: PSafe, V: PSafe, P: MemPool> {
     buckets: PVec<PRefCell<Bucket<K,P>,P>,P>,
     values: PVec<PRefCell<V,P>,P>,
+
+    /// The bucket of the key of each value
+    owners: PVec<u8,P>,
 }
 
 impl<K: PartialEq + Hash + PSafe, V: PSafe, P: MemPool> RootObj<P> for HashMap<K, V, P> {
@@ -36,6 +40,70 @@ impl<K: PSafe, V: PSafe, P: MemPool> HashMap<K, V, P> {
             }
         }
     }
+
+    /// Returns an iterator over the keys and values
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> + '_ {
+        self.buckets.iter().flat_map(move |b| {
+            b.as_ref().iter().map(move |e| {
+                let e = e.as_ref();
+                (&e.0, self.values[e.1].as_ref())
+            })
+        })
+    }
+
+    /// Returns an iterator over the keys and values which allows modifying
+    /// the values; every value is logged when it is visited
+    pub fn iter_mut<'a>(&'a mut self, j: &'a Journal<P>) -> impl Iterator<Item = (&'a K, &'a mut V)> + 'a {
+        let values = &self.values;
+        self.buckets.iter().flat_map(move |b| {
+            b.as_ref().iter().map(move |e| {
+                let e = e.as_ref();
+                let v = &values[e.1];
+                v.create_log(j);
+                (&e.0, unsafe { v.as_mut() })
+            })
+        })
+    }
+
+    /// Returns an iterator over the keys
+    pub fn keys(&self) -> impl Iterator<Item = &K> + '_ {
+        self.iter().map(|(k, _)| k)
+    }
+
+    /// Returns an iterator over the values in the order of [`keys()`]
+    ///
+    /// [`keys()`]: #method.keys
+    pub fn values(&self) -> impl Iterator<Item = &V> + '_ {
+        self.iter().map(|(_, v)| v)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<K: PSafe + Sync, V: PSafe + Sync, P: MemPool> HashMap<K, V, P> {
+    /// Returns a parallel iterator over the keys and values, which visits
+    /// the buckets on different threads
+    ///
+    /// It is available with the `rayon` feature.
+    pub fn par_iter(&self) -> impl rayon::iter::ParallelIterator<Item = (&K, &V)> + '_ {
+        use rayon::prelude::*;
+        let map = crate::par::Scoped(self);
+        (0..BUCKETS_MAX).into_par_iter().flat_map_iter(move |b| {
+            map.0.buckets[b].as_ref().iter().map(move |e| {
+                let e = e.as_ref();
+                (&e.0, map.0.values[e.1].as_ref())
+            })
+        })
+    }
+}
+
+impl<K: PartialEq + Hash + PSafe, V: PSafe, P: MemPool> PFromIterator<(K, V), P> for HashMap<K, V, P> {
+    fn pfrom_iter<I: IntoIterator<Item = (K, V)>>(iter: I, j: &Journal<P>) -> Self {
+        let mut res = Self::new(j);
+        for (k, v) in iter {
+            res.put(k, v, j);
+        }
+        res
+    }
 }
 
 impl<K: PSafe, V: PSafe, P: MemPool> HashMap<K, V, P>
@@ -50,6 +118,7 @@ where
         Self {
             buckets,
             values: PVec::new(),
+            owners: PVec::new(),
         }
     }
 
@@ -95,9 +164,37 @@ where
         }
 
         self.values.push(PRefCell::new(val), j);
+        self.owners.push(index as u8, j);
         bucket.push(PRefCell::new((key, self.values.len() - 1)), j);
     }
 
+    /// Returns the entry of `key` for in-place update or insertion
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, P> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let index = (hasher.finish() as usize) % BUCKETS_MAX;
+
+        let mut found = None;
+        for e in &*self.buckets[index].borrow() {
+            let e = e.borrow();
+            if e.0 == key {
+                found = Some(e.1);
+                break;
+            }
+        }
+        match found {
+            Some(i) => Entry::occupied_cell(key, &mut self.values.to_slice_mut()[i]),
+            None => Entry::vacant(key, move |key, val, j| {
+                let this = self;
+                this.values.push(PRefCell::new(val), j);
+                this.owners.push(index as u8, j);
+                this.buckets[index].borrow_mut(j)
+                    .push(PRefCell::new((key, this.values.len() - 1)), j);
+                this.values.last().unwrap().as_ref()
+            }),
+        }
+    }
+
     pub fn put_with_hash<Key>(&mut self, key: Key, key_hash: u64, val: V, j: &Journal<P>)
     where K: PartialEq<Key> + PFrom<Key, P> {
         let index = (key_hash as usize) % BUCKETS_MAX;
@@ -112,6 +209,7 @@ where
         }
 
         self.values.push(PRefCell::new(val), j);
+        self.owners.push(index as u8, j);
         bucket.push(PRefCell::new((K::pfrom(key, j), self.values.len() - 1)), j);
     }
 
@@ -149,6 +247,7 @@ where
         let index = (hasher.finish() as usize) % BUCKETS_MAX;
         let mut bucket = self.buckets[index].borrow_mut(j);
         self.values.push(PRefCell::new(val), j);
+        self.owners.push(index as u8, j);
         bucket.push(PRefCell::new((key, self.values.len() - 1)), j);
         let new = self.values.last().unwrap().borrow();
         unsafe { &*(&*new as *const V) }
@@ -175,6 +274,7 @@ where
         let mut new = V::default();
         f(&mut new);
         self.values.push(PRefCell::new(new), j);
+        self.owners.push(index as u8, j);
         bucket.push(
             PRefCell::new((key.pclone(j), self.values.len() - 1)),
             j,
@@ -201,17 +301,51 @@ where
         let mut new = unsafe { V::alloc_zeroed(value_size, j) };
         f(new.as_mut());
         self.values.push(PRefCell::new(new), j);
+        self.owners.push(index as u8, j);
         bucket.push(
             PRefCell::new((K::pfrom(key.clone(), j), self.values.len() - 1)),
             j,
         );
     }
 
+    /// Removes `key` from the map and returns its value, if it was present
+    ///
+    /// The last value takes the place of the removed one, and its key is
+    /// redirected in the bucket recorded for it in `owners`.
+    pub(crate) fn remove(&mut self, key: &K, j: &Journal<P>) -> Option<V> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let index = (hasher.finish() as usize) % BUCKETS_MAX;
+
+        let i = {
+            let mut bucket = self.buckets[index].borrow_mut(j);
+            let pos = bucket.iter().position(|e| e.borrow().0 == *key)?;
+            bucket.log_range(pos..pos + 1, j);
+            bucket.swap_remove(pos).into_inner().1
+        };
+
+        let last = self.values.len() - 1;
+        if i != last {
+            let owner = self.owners[last] as usize;
+            for e in &*self.buckets[owner].borrow() {
+                if e.borrow().1 == last {
+                    e.borrow_mut(j).1 = i;
+                    break;
+                }
+            }
+        }
+        self.owners.log_range(i..i + 1, j);
+        self.owners.swap_remove(i);
+        self.values.log_range(i..i + 1, j);
+        Some(self.values.swap_remove(i).into_inner())
+    }
+
     pub fn clear(&mut self, j: &Journal<P>) {
         for i in 0..BUCKETS_MAX {
             self.buckets[i].borrow_mut(j).clear();
         }
         self.values.clear();
+        self.owners.clear();
     }
 
     pub fn is_empty(&self) -> bool {
@@ -0,0 +1,486 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::{self, Debug};
+use std::borrow::Borrow;
+use std::hash::{Hash, Hasher};
+
+use crate::*;
+use crate::alloc::*;
+use crate::vec::Vec as PVec;
+use crate::stm::Journal;
+use crate::clone::PClone;
+use crate::prc::Prc;
+
+const BITS: u32 = 5;
+const MASK: u64 = (1 << BITS) - 1;
+
+enum Node<K: PSafe, V: PSafe, P: MemPool> {
+    /// An array-mapped node; the `i`-th bit of the bitmap shows that the
+    /// child for the `i`-th chunk of hash bits exists
+    Branch(u32, PVec<Prc<Node<K, V, P>, P>, P>),
+
+    /// A bucket of entries with the same hash value
+    Leaf(u64, PVec<(K, V), P>),
+}
+
+type NodePtr<K, V, P> = Prc<Node<K, V, P>, P>;
+
+/// A persistent immutable hash map with structural sharing
+///
+/// `PImMap` is a hash array mapped trie (HAMT). It is immutable: every update
+/// returns a new version of the map which shares all untouched nodes with the
+/// old version. Therefore, [`pclone`] is O(1) and updates copy only
+/// O(log<sub>32</sub> n) nodes, which makes it cheap to keep snapshots
+/// (versions) of large datasets within a pool.
+///
+/// # Examples
+///
+/// ```
+/// # use corundum::alloc::heap::*;
+/// use corundum::stl::PImMap;
+/// use corundum::PClone;
+///
+/// Heap::transaction(|j| {
+///     let m = PImMap::<u32, u64, Heap>::new();
+///     let m = m.insert(1, 10, j).insert(2, 20, j);
+///     let snapshot = m.pclone(j);
+///     let m = m.remove(&1, j).insert(2, 200, j);
+///
+///     assert_eq!(m.get(&1), None);
+///     assert_eq!(m.get(&2), Some(&200));
+///     assert_eq!(snapshot.get(&1), Some(&10));
+///     assert_eq!(snapshot.get(&2), Some(&20));
+/// }).unwrap();
+/// ```
+///
+/// [`pclone`]: ../clone/trait.PClone.html#tymethod.pclone
+pub struct PImMap<K: PSafe, V: PSafe, P: MemPool> {
+    root: Option<NodePtr<K, V, P>>,
+    len: usize,
+}
+
+fn hash_of<K: Hash + ?Sized>(key: &K) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[inline]
+fn chunk(hash: u64, shift: u32) -> u32 {
+    ((hash >> shift) & MASK) as u32
+}
+
+#[inline]
+fn index(bitmap: u32, bit: u32) -> usize {
+    (bitmap & (bit - 1)).count_ones() as usize
+}
+
+impl<K: PSafe, V: PSafe, P: MemPool> PImMap<K, V, P> {
+    /// Creates a new empty map
+    pub const fn new() -> Self {
+        Self { root: None, len: 0 }
+    }
+
+    /// Returns the number of entries
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the map contains no entries
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Calls `f` for every entry in the map
+    pub fn foreach<F: FnMut(&K, &V)>(&self, mut f: F) {
+        fn visit<K: PSafe, V: PSafe, P: MemPool, F: FnMut(&K, &V)>(n: &Node<K, V, P>, f: &mut F) {
+            match n {
+                Node::Branch(_, ch) => for c in ch.as_slice() {
+                    visit(&**c, f)
+                },
+                Node::Leaf(_, entries) => for (k, v) in entries.as_slice() {
+                    f(k, v)
+                }
+            }
+        }
+        if let Some(root) = &self.root {
+            visit(&**root, &mut f);
+        }
+    }
+
+    /// Returns an iterator over the entries in no particular order
+    pub fn iter(&self) -> Iter<'_, K, V, P> {
+        Iter {
+            stack: vec![self.root.as_ref().map_or(&[][..], std::slice::from_ref).iter()],
+            leaf: [].iter(),
+        }
+    }
+
+    /// Returns an iterator over the keys in no particular order
+    pub fn keys(&self) -> impl Iterator<Item = &K> + '_ {
+        self.iter().map(|(k, _)| k)
+    }
+
+    /// Returns an iterator over the values in the order of [`keys()`]
+    ///
+    /// [`keys()`]: #method.keys
+    pub fn values(&self) -> impl Iterator<Item = &V> + '_ {
+        self.iter().map(|(_, v)| v)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<K: PSafe + Sync, V: PSafe + Sync, P: MemPool> PImMap<K, V, P> {
+    /// Returns a parallel iterator over the entries in no particular order,
+    /// which visits the children of the root on different threads
+    ///
+    /// It is available with the `rayon` feature.
+    pub fn par_iter(&self) -> impl rayon::iter::ParallelIterator<Item = (&K, &V)> + '_ {
+        use rayon::prelude::*;
+        let roots = crate::par::Scoped(match self.root.as_ref() {
+            None => &[][..],
+            Some(root) => match &**root {
+                Node::Branch(_, ch) => ch.as_slice(),
+                Node::Leaf(..) => std::slice::from_ref(root),
+            }
+        });
+        (0..roots.0.len()).into_par_iter().flat_map_iter(move |i| Iter {
+            stack: vec![std::slice::from_ref(&roots.0[i]).iter()],
+            leaf: [].iter(),
+        })
+    }
+}
+
+/// An iterator over the entries of a [`PImMap`]
+///
+/// [`PImMap`]: ./struct.PImMap.html
+pub struct Iter<'a, K: PSafe, V: PSafe, P: MemPool> {
+    stack: Vec<std::slice::Iter<'a, NodePtr<K, V, P>>>,
+    leaf: std::slice::Iter<'a, (K, V)>,
+}
+
+impl<'a, K: PSafe, V: PSafe, P: MemPool> Iterator for Iter<'a, K, V, P> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((k, v)) = self.leaf.next() {
+                return Some((k, v));
+            }
+            match self.stack.last_mut()?.next() {
+                None => { self.stack.pop(); }
+                Some(n) => match &**n {
+                    Node::Branch(_, ch) => self.stack.push(ch.as_slice().iter()),
+                    Node::Leaf(_, entries) => self.leaf = entries.as_slice().iter(),
+                }
+            }
+        }
+    }
+}
+
+impl<K: PSafe + Hash + Eq, V: PSafe, P: MemPool> PImMap<K, V, P> {
+    /// Returns a reference to the value corresponding to the key
+    ///
+    /// The key may be any borrowed form of the map's key type (e.g., `&str`
+    /// for `PString` keys).
+    pub fn get<Q: ?Sized + Hash + Eq>(&self, key: &Q) -> Option<&V> where K: Borrow<Q> {
+        let hash = hash_of(key);
+        let mut node = &**self.root.as_ref()?;
+        let mut shift = 0;
+        loop {
+            match node {
+                Node::Branch(bitmap, ch) => {
+                    let bit = 1 << chunk(hash, shift);
+                    if bitmap & bit == 0 {
+                        return None;
+                    }
+                    node = &*ch[index(*bitmap, bit)];
+                    shift += BITS;
+                }
+                Node::Leaf(h, entries) => {
+                    return if *h == hash {
+                        entries.as_slice().iter().find(|e| e.0.borrow() == key).map(|e| &e.1)
+                    } else {
+                        None
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns true if the map contains a value for the specified key
+    pub fn contains_key<Q: ?Sized + Hash + Eq>(&self, key: &Q) -> bool where K: Borrow<Q> {
+        self.get(key).is_some()
+    }
+}
+
+impl<K: PSafe + Hash + Eq + PClone<P>, V: PSafe + PClone<P>, P: MemPool> PImMap<K, V, P> {
+    /// Returns a new version of the map in which `key` is mapped to `value`
+    pub fn insert(&self, key: K, value: V, j: &Journal<P>) -> Self {
+        let hash = hash_of(&key);
+        match &self.root {
+            None => Self {
+                root: Some(Self::new_leaf(hash, key, value, j)),
+                len: 1,
+            },
+            Some(root) => {
+                let (root, added) = Self::insert_into(root, 0, hash, key, value, j);
+                Self {
+                    root: Some(root),
+                    len: self.len + added as usize,
+                }
+            }
+        }
+    }
+
+    /// Returns a new version of the map without `key`
+    pub fn remove<Q: ?Sized + Hash + Eq>(&self, key: &Q, j: &Journal<P>) -> Self where K: Borrow<Q> {
+        if let Some(root) = &self.root {
+            match Self::remove_from(root, 0, hash_of(key), key, j) {
+                None => self.pclone(j),
+                Some(root) => Self {
+                    root,
+                    len: self.len - 1,
+                },
+            }
+        } else {
+            Self::new()
+        }
+    }
+
+    fn new_leaf(hash: u64, key: K, value: V, j: &Journal<P>) -> NodePtr<K, V, P> {
+        let mut entries = PVec::with_capacity(1, j);
+        entries.push((key, value), j);
+        Prc::new(Node::Leaf(hash, entries), j)
+    }
+
+    /// Creates a branch containing two leaves with different hash values
+    fn merge(a: NodePtr<K, V, P>, ha: u64, b: NodePtr<K, V, P>, hb: u64, shift: u32, j: &Journal<P>)
+        -> NodePtr<K, V, P>
+    {
+        let (ia, ib) = (chunk(ha, shift), chunk(hb, shift));
+        let mut ch = PVec::with_capacity(2, j);
+        if ia == ib {
+            ch.push(Self::merge(a, ha, b, hb, shift + BITS, j), j);
+            Prc::new(Node::Branch(1 << ia, ch), j)
+        } else {
+            if ia < ib {
+                ch.push(a, j);
+                ch.push(b, j);
+            } else {
+                ch.push(b, j);
+                ch.push(a, j);
+            }
+            Prc::new(Node::Branch((1 << ia) | (1 << ib), ch), j)
+        }
+    }
+
+    fn insert_into(node: &NodePtr<K, V, P>, shift: u32, hash: u64, key: K, value: V, j: &Journal<P>)
+        -> (NodePtr<K, V, P>, bool)
+    {
+        match &**node {
+            Node::Leaf(h, entries) => {
+                if *h == hash {
+                    let mut ne = PVec::with_capacity(entries.len() + 1, j);
+                    let mut entry = Some((key, value));
+                    for e in entries.as_slice() {
+                        if entry.as_ref().map_or(false, |n| n.0 == e.0) {
+                            ne.push(entry.take().unwrap(), j);
+                        } else {
+                            ne.push(e.pclone(j), j);
+                        }
+                    }
+                    let added = entry.is_some();
+                    if let Some(entry) = entry {
+                        ne.push(entry, j);
+                    }
+                    (Prc::new(Node::Leaf(hash, ne), j), added)
+                } else {
+                    let leaf = Self::new_leaf(hash, key, value, j);
+                    (Self::merge(node.pclone(j), *h, leaf, hash, shift, j), true)
+                }
+            }
+            Node::Branch(bitmap, ch) => {
+                let bit = 1 << chunk(hash, shift);
+                let idx = index(*bitmap, bit);
+                let exists = bitmap & bit != 0;
+                let mut nch = PVec::with_capacity(ch.len() + 1, j);
+                for c in &ch[..idx] {
+                    nch.push(c.pclone(j), j);
+                }
+                let added = if exists {
+                    let (n, added) = Self::insert_into(&ch[idx], shift + BITS, hash, key, value, j);
+                    nch.push(n, j);
+                    added
+                } else {
+                    nch.push(Self::new_leaf(hash, key, value, j), j);
+                    true
+                };
+                let rest = if exists { idx + 1 } else { idx };
+                for c in &ch[rest..] {
+                    nch.push(c.pclone(j), j);
+                }
+                (Prc::new(Node::Branch(bitmap | bit, nch), j), added)
+            }
+        }
+    }
+
+    /// Returns `None` if the key is not found. Otherwise, it returns the new
+    /// node, or `Some(None)` if the node became empty.
+    fn remove_from<Q: ?Sized + Eq>(node: &NodePtr<K, V, P>, shift: u32, hash: u64, key: &Q, j: &Journal<P>)
+        -> Option<Option<NodePtr<K, V, P>>>
+    where K: Borrow<Q>
+    {
+        match &**node {
+            Node::Leaf(h, entries) => {
+                if *h != hash {
+                    return None;
+                }
+                let pos = entries.as_slice().iter().position(|e| e.0.borrow() == key)?;
+                if entries.len() == 1 {
+                    Some(None)
+                } else {
+                    let mut ne = PVec::with_capacity(entries.len() - 1, j);
+                    for (i, e) in entries.as_slice().iter().enumerate() {
+                        if i != pos {
+                            ne.push(e.pclone(j), j);
+                        }
+                    }
+                    Some(Some(Prc::new(Node::Leaf(hash, ne), j)))
+                }
+            }
+            Node::Branch(bitmap, ch) => {
+                let bit = 1 << chunk(hash, shift);
+                if bitmap & bit == 0 {
+                    return None;
+                }
+                let idx = index(*bitmap, bit);
+                let child = Self::remove_from(&ch[idx], shift + BITS, hash, key, j)?;
+                let bitmap = if child.is_none() { bitmap & !bit } else { *bitmap };
+                if bitmap == 0 {
+                    return Some(None);
+                }
+                let mut nch = PVec::with_capacity(ch.len(), j);
+                let mut child = Some(child);
+                for (i, c) in ch.as_slice().iter().enumerate() {
+                    if i == idx {
+                        if let Some(n) = child.take().unwrap() {
+                            nch.push(n, j);
+                        }
+                    } else {
+                        nch.push(c.pclone(j), j);
+                    }
+                }
+                // Collapse a branch with a single leaf
+                if nch.len() == 1 {
+                    if let Node::Leaf(..) = &*nch[0] {
+                        return Some(Some(nch[0].pclone(j)));
+                    }
+                }
+                Some(Some(Prc::new(Node::Branch(bitmap, nch), j)))
+            }
+        }
+    }
+}
+
+impl<K: PSafe, V: PSafe, P: MemPool> PClone<P> for PImMap<K, V, P> {
+    /// Creates a new version of the map in O(1) by sharing the root
+    fn pclone(&self, j: &Journal<P>) -> Self {
+        Self {
+            root: self.root.as_ref().map(|r| r.pclone(j)),
+            len: self.len,
+        }
+    }
+}
+
+impl<K: PSafe + Hash + Eq + PClone<P>, V: PSafe + PClone<P>, P: MemPool> PFromIterator<(K, V), P>
+for PImMap<K, V, P> {
+    fn pfrom_iter<I: IntoIterator<Item = (K, V)>>(iter: I, j: &Journal<P>) -> Self {
+        let mut res = Self::new();
+        for (k, v) in iter {
+            res = res.insert(k, v, j);
+        }
+        res
+    }
+}
+
+impl<K: PSafe, V: PSafe, P: MemPool> Default for PImMap<K, V, P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: PSafe, V: PSafe, P: MemPool> RootObj<P> for PImMap<K, V, P> {
+    fn init(_: &Journal<P>) -> Self {
+        Self::new()
+    }
+}
+
+impl<K: PSafe + Debug, V: PSafe + Debug, P: MemPool> Debug for PImMap<K, V, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut m = f.debug_map();
+        self.foreach(|k, v| { m.entry(k, v); });
+        m.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alloc::heap::*;
+    use crate::stl::PImMap;
+    use crate::PClone;
+
+    #[test]
+    fn immap_versions() {
+        Heap::transaction(|j| {
+            let mut m = PImMap::<u64, u64, Heap>::new();
+            for i in 0..500 {
+                m = m.insert(i, i * 2, j);
+            }
+            let snapshot = m.pclone(j);
+            for i in 0..250 {
+                m = m.remove(&i, j);
+            }
+            m = m.insert(499, 0, j);
+            assert_eq!(m.len(), 250);
+            assert_eq!(snapshot.len(), 500);
+            assert_eq!(m.get(&10), None);
+            assert_eq!(m.get(&499), Some(&0));
+            assert_eq!(snapshot.get(&10), Some(&20));
+            assert_eq!(snapshot.get(&499), Some(&998));
+            let mut count = 0;
+            m.foreach(|_, _| count += 1);
+            assert_eq!(count, 250);
+        }).unwrap();
+    }
+
+    #[test]
+    fn immap_iterates_versions() {
+        Heap::transaction(|j| {
+            let mut m = PImMap::<u64, u64, Heap>::new();
+            for i in 0..500 {
+                m = m.insert(i, i * 2, j);
+            }
+            let snapshot = m.pclone(j);
+            for i in 0..250 {
+                m = m.remove(&i, j);
+            }
+            assert_eq!(m.iter().count(), 250);
+            assert_eq!(m.keys().min(), Some(&250));
+            assert_eq!(snapshot.keys().sum::<u64>(), 499 * 500 / 2);
+        }).unwrap();
+    }
+
+    #[test]
+    fn immap_collects_from_iterator() {
+        use crate::PCollect;
+
+        Heap::transaction(|j| {
+            let m: PImMap<u64, u64, Heap> = (0..100).map(|i| (i, i + 1)).collect_pinto(j);
+            assert_eq!(m.len(), 100);
+            assert_eq!(m.get(&42), Some(&43));
+            assert_eq!(m.values().sum::<u64>(), 100 * 101 / 2);
+        }).unwrap();
+    }
+}
@@ -0,0 +1,305 @@
+use std::fmt::{self, Debug};
+
+use crate::*;
+use crate::alloc::*;
+use crate::vec::Vec as PVec;
+use crate::stm::Journal;
+use crate::clone::PClone;
+use crate::prc::Prc;
+
+const BITS: u32 = 5;
+const WIDTH: usize = 1 << BITS;
+const MASK: usize = WIDTH - 1;
+
+enum Node<T: PSafe, P: MemPool> {
+    Branch(PVec<Prc<Node<T, P>, P>, P>),
+    Leaf(PVec<T, P>),
+}
+
+/// A persistent immutable vector with structural sharing
+///
+/// `PImVector` is a bit-partitioned trie with a branching factor of 32. It is
+/// immutable: every update returns a new version of the vector which shares
+/// all untouched nodes with the old version. Therefore, [`pclone`] is O(1) and
+/// updates copy only O(log<sub>32</sub> n) nodes, which makes it cheap to keep
+/// snapshots (versions) of large datasets within a pool.
+///
+/// # Examples
+///
+/// ```
+/// # use corundum::alloc::heap::*;
+/// use corundum::stl::PImVector;
+/// use corundum::PClone;
+///
+/// Heap::transaction(|j| {
+///     let mut v = PImVector::<i32, Heap>::new();
+///     for i in 0..100 {
+///         v = v.push_back(i, j);
+///     }
+///     let snapshot = v.pclone(j);
+///     let v = v.set(10, -1, j);
+///
+///     assert_eq!(v.get(10), Some(&-1));
+///     assert_eq!(snapshot.get(10), Some(&10));
+///     assert_eq!(v.len(), 100);
+/// }).unwrap();
+/// ```
+///
+/// [`pclone`]: ../clone/trait.PClone.html#tymethod.pclone
+pub struct PImVector<T: PSafe, P: MemPool> {
+    root: Option<Prc<Node<T, P>, P>>,
+    len: usize,
+    shift: u32,
+}
+
+impl<T: PSafe, P: MemPool> PImVector<T, P> {
+    /// Creates a new empty vector
+    pub const fn new() -> Self {
+        Self { root: None, len: 0, shift: 0 }
+    }
+
+    /// Returns the number of elements
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the vector contains no elements
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns a reference to the element at position `index`, or `None` if
+    /// it is out of bounds
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len {
+            return None;
+        }
+        let mut node = &**self.root.as_ref()?;
+        let mut level = self.shift;
+        loop {
+            match node {
+                Node::Branch(ch) => {
+                    node = &*ch[(index >> level) & MASK];
+                    level -= BITS;
+                }
+                Node::Leaf(v) => return v.get(index & MASK),
+            }
+        }
+    }
+
+    /// Returns the first element
+    pub fn first(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    /// Returns the last element
+    pub fn last(&self) -> Option<&T> {
+        if self.len == 0 { None } else { self.get(self.len - 1) }
+    }
+
+    /// Returns an iterator over the elements
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        (0..self.len).map(move |i| self.get(i).unwrap())
+    }
+}
+
+impl<T: PSafe + PClone<P>, P: MemPool> PImVector<T, P> {
+    /// Returns a new version of the vector with `value` appended to the end
+    pub fn push_back(&self, value: T, j: &Journal<P>) -> Self {
+        match &self.root {
+            None => Self {
+                root: Some(Self::new_path(0, value, j)),
+                len: 1,
+                shift: 0,
+            },
+            Some(root) => {
+                if self.len == 1 << (self.shift + BITS) {
+                    // The tree is full; grow it by one level
+                    let mut ch = PVec::with_capacity(2, j);
+                    ch.push(root.pclone(j), j);
+                    ch.push(Self::new_path(self.shift, value, j), j);
+                    Self {
+                        root: Some(Prc::new(Node::Branch(ch), j)),
+                        len: self.len + 1,
+                        shift: self.shift + BITS,
+                    }
+                } else {
+                    Self {
+                        root: Some(Self::push_into(root, self.shift, self.len, value, j)),
+                        len: self.len + 1,
+                        shift: self.shift,
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns a new version of the vector in which the element at position
+    /// `index` is replaced with `value`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn set(&self, index: usize, value: T, j: &Journal<P>) -> Self {
+        assert!(index < self.len, "index {} out of bounds (len = {})", index, self.len);
+        Self {
+            root: Some(Self::set_into(self.root.as_ref().unwrap(), self.shift, index, value, j)),
+            len: self.len,
+            shift: self.shift,
+        }
+    }
+
+    /// Creates a new vector from a slice
+    pub fn from_slice(items: &[T], j: &Journal<P>) -> Self {
+        let mut res = Self::new();
+        for x in items {
+            res = res.push_back(x.pclone(j), j);
+        }
+        res
+    }
+
+    fn new_path(level: u32, value: T, j: &Journal<P>) -> Prc<Node<T, P>, P> {
+        if level == 0 {
+            let mut v = PVec::with_capacity(1, j);
+            v.push(value, j);
+            Prc::new(Node::Leaf(v), j)
+        } else {
+            let mut ch = PVec::with_capacity(1, j);
+            ch.push(Self::new_path(level - BITS, value, j), j);
+            Prc::new(Node::Branch(ch), j)
+        }
+    }
+
+    fn push_into(node: &Prc<Node<T, P>, P>, level: u32, index: usize, value: T, j: &Journal<P>)
+        -> Prc<Node<T, P>, P>
+    {
+        match &**node {
+            Node::Leaf(v) => {
+                let mut nv = PVec::with_capacity(v.len() + 1, j);
+                for x in v.as_slice() {
+                    nv.push(x.pclone(j), j);
+                }
+                nv.push(value, j);
+                Prc::new(Node::Leaf(nv), j)
+            }
+            Node::Branch(ch) => {
+                let idx = (index >> level) & MASK;
+                let mut nch = PVec::with_capacity(ch.len() + 1, j);
+                for c in &ch[..idx.min(ch.len())] {
+                    nch.push(c.pclone(j), j);
+                }
+                if idx < ch.len() {
+                    nch.push(Self::push_into(&ch[idx], level - BITS, index, value, j), j);
+                } else {
+                    nch.push(Self::new_path(level - BITS, value, j), j);
+                }
+                Prc::new(Node::Branch(nch), j)
+            }
+        }
+    }
+
+    fn set_into(node: &Prc<Node<T, P>, P>, level: u32, index: usize, value: T, j: &Journal<P>)
+        -> Prc<Node<T, P>, P>
+    {
+        match &**node {
+            Node::Leaf(v) => {
+                let idx = index & MASK;
+                let mut nv = PVec::with_capacity(v.len(), j);
+                let mut value = Some(value);
+                for (i, x) in v.as_slice().iter().enumerate() {
+                    if i == idx {
+                        nv.push(value.take().unwrap(), j);
+                    } else {
+                        nv.push(x.pclone(j), j);
+                    }
+                }
+                Prc::new(Node::Leaf(nv), j)
+            }
+            Node::Branch(ch) => {
+                let idx = (index >> level) & MASK;
+                let mut nch = PVec::with_capacity(ch.len(), j);
+                let mut value = Some(value);
+                for (i, c) in ch.as_slice().iter().enumerate() {
+                    if i == idx {
+                        nch.push(Self::set_into(c, level - BITS, index, value.take().unwrap(), j), j);
+                    } else {
+                        nch.push(c.pclone(j), j);
+                    }
+                }
+                Prc::new(Node::Branch(nch), j)
+            }
+        }
+    }
+}
+
+impl<T: PSafe, P: MemPool> PClone<P> for PImVector<T, P> {
+    /// Creates a new version of the vector in O(1) by sharing the root
+    fn pclone(&self, j: &Journal<P>) -> Self {
+        Self {
+            root: self.root.as_ref().map(|r| r.pclone(j)),
+            len: self.len,
+            shift: self.shift,
+        }
+    }
+}
+
+impl<T: PSafe + PClone<P>, P: MemPool> PFromIterator<T, P> for PImVector<T, P> {
+    fn pfrom_iter<I: IntoIterator<Item = T>>(iter: I, j: &Journal<P>) -> Self {
+        let mut res = Self::new();
+        for x in iter {
+            res = res.push_back(x, j);
+        }
+        res
+    }
+}
+
+impl<T: PSafe, P: MemPool> Default for PImVector<T, P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: PSafe, P: MemPool> RootObj<P> for PImVector<T, P> {
+    fn init(_: &Journal<P>) -> Self {
+        Self::new()
+    }
+}
+
+impl<T: PSafe + Debug, P: MemPool> Debug for PImVector<T, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alloc::heap::*;
+    use crate::stl::PImVector;
+    use crate::PClone;
+
+    #[test]
+    fn imvector_versions() {
+        Heap::transaction(|j| {
+            let mut v = PImVector::<usize, Heap>::new();
+            let mut versions = vec![];
+            for i in 0..1100 {
+                v = v.push_back(i, j);
+                if i % 100 == 0 {
+                    versions.push(v.pclone(j));
+                }
+            }
+            assert_eq!(v.len(), 1100);
+            assert!(v.iter().enumerate().all(|(i, x)| i == *x));
+            for (k, s) in versions.iter().enumerate() {
+                assert_eq!(s.len(), k * 100 + 1);
+                assert_eq!(s.last(), Some(&(k * 100)));
+            }
+            let w = v.set(1050, 0, j);
+            assert_eq!(w.get(1050), Some(&0));
+            assert_eq!(v.get(1050), Some(&1050));
+            assert_eq!(w.get(1100), None);
+        }).unwrap();
+    }
+}
@@ -0,0 +1,395 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+
+use crate::*;
+use crate::alloc::*;
+use crate::vec::Vec as PVec;
+use crate::cell::{PCell, PRefCell};
+use crate::stm::{Journal, Logger, Notifier};
+use crate::clone::PClone;
+use super::Entry;
+
+/// The end of a bucket chain
+const NIL: usize = usize::MAX;
+
+struct Slot<K, V> {
+    key: K,
+    value: V,
+
+    /// The next entry in the same bucket
+    next: usize,
+
+    /// The recency bit, which is written without a log
+    referenced: bool,
+}
+
+/// A persistent cache which evicts the least recently used entries beyond
+/// its capacity
+///
+/// The entries are kept in a slab of `capacity` slots, and they are indexed
+/// by a chained hash table; both are updated transactionally, so the cache
+/// is always consistent after a crash. Eviction follows the CLOCK
+/// (second-chance) approximation of LRU: every entry has a recency bit,
+/// which [`get()`] sets, and [`put()`] sweeps the slots in a circle,
+/// clearing the bits until it finds an entry which has not been used since
+/// the last sweep. A new entry starts without the bit, so the entries which
+/// are never read are evicted before the ones which are.
+///
+/// The recency bits have relaxed durability: they are written in place
+/// without a log or a flush, so [`get()`] does not need a journal, and it
+/// costs no more than a lookup. A crash may lose some of them, which only
+/// changes the choice of the next victims.
+///
+/// # Examples
+///
+/// ```
+/// # use corundum::alloc::heap::*;
+/// use corundum::stl::PLruCache;
+///
+/// Heap::transaction(|j| {
+///     let cache = PLruCache::<u64, u64, Heap>::new(2, j);
+///     cache.put(1, 10, j);
+///     cache.put(2, 20, j);
+///     assert_eq!(cache.get(&1), Some(&10)); // 1 is used more recently than 2
+///     cache.put(3, 30, j);                  // evicts 2
+///     assert_eq!(cache.get(&2), None);
+///     assert_eq!(cache.len(), 2);
+/// }).unwrap();
+/// ```
+///
+/// [`get()`]: #method.get
+/// [`put()`]: #method.put
+pub struct PLruCache<K: PSafe, V: PSafe, P: MemPool> {
+    capacity: usize,
+
+    /// The next slot to visit for eviction
+    hand: PCell<usize, P>,
+
+    entries: PRefCell<PVec<Slot<K, V>, P>, P>,
+    buckets: PRefCell<PVec<usize, P>, P>,
+}
+
+/// Returns a mutable reference to `x` after logging it
+#[inline]
+#[allow(clippy::mut_from_ref)]
+unsafe fn logged<T: PSafe, P: MemPool>(x: &T, j: &Journal<P>) -> &mut T {
+    let x = utils::as_mut(x);
+    x.create_log(j, Notifier::None);
+    x
+}
+
+impl<K: PSafe + Hash + Eq, V: PSafe, P: MemPool> PLruCache<K, V, P> {
+    /// Creates an empty cache which holds at most `capacity` entries
+    ///
+    /// # Panics
+    ///
+    /// It panics if `capacity` is zero.
+    pub fn new(capacity: usize, j: &Journal<P>) -> Self {
+        assert_ne!(capacity, 0, "capacity should be non-zero");
+        let nbuckets = capacity.next_power_of_two();
+        let mut buckets = PVec::with_capacity(nbuckets, j);
+        for _ in 0..nbuckets {
+            buckets.push(NIL, j);
+        }
+        Self {
+            capacity,
+            hand: PCell::new(0),
+            entries: PRefCell::new(PVec::with_capacity(capacity, j)),
+            buckets: PRefCell::new(buckets),
+        }
+    }
+
+    /// Returns the maximum number of entries
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of entries
+    pub fn len(&self) -> usize {
+        self.entries.as_ref().len()
+    }
+
+    /// Returns true if there is no entry
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns true if the cache has an entry for `key`, without updating its
+    /// recency
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.find(key).is_some()
+    }
+
+    /// Returns a reference to the value of `key`, and marks it as recently
+    /// used
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let i = self.find(key)?;
+        let e = &self.entries.as_ref()[i];
+        if !e.referenced {
+            unsafe { utils::as_mut(e).referenced = true; }
+        }
+        Some(&e.value)
+    }
+
+    /// Returns a reference to the value of `key` without updating its
+    /// recency
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.find(key).map(|i| &self.entries.as_ref()[i].value)
+    }
+
+    /// Inserts or replaces the value of `key`, and returns the old value, if
+    /// any
+    ///
+    /// A replaced entry is marked as recently used. If the cache is full, a
+    /// new entry takes the place of one which is not recently used.
+    pub fn put(&self, key: K, value: V, j: &Journal<P>) -> Option<V> {
+        match self.lookup(key) {
+            Entry::Occupied(mut e) => Some(e.insert(value, j)),
+            Entry::Vacant(e) => {
+                e.insert(value, j);
+                None
+            }
+        }
+    }
+
+    /// Returns the entry of `key` for in-place update or insertion, and
+    /// marks it as recently used if it exists
+    ///
+    /// If the cache is full, inserting into a vacant entry evicts an entry
+    /// which is not recently used, the same as [`put()`].
+    ///
+    /// [`put()`]: #method.put
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, P> {
+        self.lookup(key)
+    }
+
+    /// Finds the entry of `key`; the caller consumes the entry before the
+    /// cache is accessed again
+    fn lookup(&self, key: K) -> Entry<'_, K, V, P> {
+        let b = self.bucket(&key);
+        match self.find(&key) {
+            Some(i) => {
+                let e = &self.entries.as_ref()[i];
+                if !e.referenced {
+                    unsafe { utils::as_mut(e).referenced = true; }
+                }
+                Entry::occupied(key, unsafe { utils::as_mut(&e.value) })
+            }
+            None => Entry::vacant(key, move |key, value, j| self.insert_new(b, key, value, j)),
+        }
+    }
+
+    /// Inserts a new entry into bucket `b`, and returns a reference to its
+    /// value
+    fn insert_new(&self, b: usize, key: K, value: V, j: &Journal<P>) -> &V {
+        let head = self.buckets.as_ref()[b];
+        let slot = Slot { key, value, next: head, referenced: false };
+        let i = if self.len() < self.capacity {
+            let mut entries = self.entries.borrow_mut(j);
+            entries.push(slot, j);
+            entries.len() - 1
+        } else {
+            let i = self.victim(j);
+            self.unlink(i, j);
+            // The head of the bucket may be the victim itself
+            let head = self.buckets.as_ref()[b];
+            let e = unsafe { logged(&self.entries.as_ref()[i], j) };
+            *e = Slot { next: head, ..slot };
+            i
+        };
+        unsafe { *logged(&self.buckets.as_ref()[b], j) = i; }
+        &self.entries.as_ref()[i].value
+    }
+
+    /// Removes the entry of `key`, and returns its value
+    pub fn remove(&self, key: &K, j: &Journal<P>) -> Option<V> {
+        let i = self.find(key)?;
+        self.unlink(i, j);
+        let last = self.len() - 1;
+        if i != last {
+            // The last entry moves into the hole
+            self.relink(last, i, j);
+        }
+        let e = unsafe {
+            logged(&self.entries.as_ref()[i], j);
+            self.entries.borrow_mut(j).swap_remove(i)
+        };
+        if self.hand.get() >= self.len() {
+            self.hand.set(0, j);
+        }
+        Some(e.value)
+    }
+
+    /// Returns an iterator over the entries in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> + '_ {
+        self.entries.as_ref().iter().map(|e| (&e.key, &e.value))
+    }
+
+    /// Returns an iterator over the entries in no particular order which
+    /// allows modifying the values; every value is logged when it is visited
+    ///
+    /// It does not update the recency of the entries.
+    pub fn iter_mut<'a>(&'a mut self, j: &'a Journal<P>) -> impl Iterator<Item = (&'a K, &'a mut V)> + 'a {
+        self.iter().map(move |(k, v)| unsafe { (k, logged(v, j)) })
+    }
+
+    /// Returns an iterator over the keys in no particular order
+    pub fn keys(&self) -> impl Iterator<Item = &K> + '_ {
+        self.iter().map(|(k, _)| k)
+    }
+
+    /// Returns an iterator over the values in the order of [`keys()`]
+    ///
+    /// [`keys()`]: #method.keys
+    pub fn values(&self) -> impl Iterator<Item = &V> + '_ {
+        self.iter().map(|(_, v)| v)
+    }
+
+    fn bucket(&self, key: &K) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        (hasher.finish() as usize) & (self.buckets.as_ref().len() - 1)
+    }
+
+    fn find(&self, key: &K) -> Option<usize> {
+        let entries = self.entries.as_ref();
+        let mut i = self.buckets.as_ref()[self.bucket(key)];
+        while i != NIL {
+            if entries[i].key == *key {
+                return Some(i);
+            }
+            i = entries[i].next;
+        }
+        None
+    }
+
+    /// Removes entry `i` from its bucket chain
+    fn unlink(&self, i: usize, j: &Journal<P>) {
+        let entries = self.entries.as_ref();
+        let b = self.bucket(&entries[i].key);
+        let next = entries[i].next;
+        let mut p = &self.buckets.as_ref()[b];
+        while *p != i {
+            p = &entries[*p].next;
+        }
+        unsafe { *logged(p, j) = next; }
+    }
+
+    /// Points the reference to entry `from` in its bucket chain to `to`
+    fn relink(&self, from: usize, to: usize, j: &Journal<P>) {
+        let entries = self.entries.as_ref();
+        let b = self.bucket(&entries[from].key);
+        let mut p = &self.buckets.as_ref()[b];
+        while *p != from {
+            p = &entries[*p].next;
+        }
+        unsafe { *logged(p, j) = to; }
+    }
+
+    /// Sweeps the slots from the hand, and returns the first one which is
+    /// not recently used
+    fn victim(&self, j: &Journal<P>) -> usize {
+        let entries = self.entries.as_ref();
+        let mut i = self.hand.get();
+        while entries[i].referenced {
+            unsafe { utils::as_mut(&entries[i]).referenced = false; }
+            i = (i + 1) % entries.len();
+        }
+        self.hand.set((i + 1) % entries.len(), j);
+        i
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<K: PSafe + Sync, V: PSafe + Sync, P: MemPool> PLruCache<K, V, P> {
+    /// Returns a parallel iterator over the entries in no particular order
+    ///
+    /// It is available with the `rayon` feature. It does not update the
+    /// recency of the entries.
+    pub fn par_iter(&self) -> impl rayon::iter::ParallelIterator<Item = (&K, &V)> + '_ {
+        use rayon::prelude::*;
+        self.entries.as_ref().as_slice().par_iter().map(|e| (&e.key, &e.value))
+    }
+}
+
+impl<K: PSafe + Hash + Eq, V: PSafe, P: MemPool> PClone<P> for PLruCache<K, V, P>
+where K: PClone<P>, V: PClone<P> {
+    fn pclone(&self, j: &Journal<P>) -> Self {
+        let res = Self::new(self.capacity, j);
+        for (k, v) in self.iter() {
+            res.put(k.pclone(j), v.pclone(j), j);
+        }
+        res
+    }
+}
+
+impl<K: PSafe + Debug, V: PSafe + Debug, P: MemPool> Debug for PLruCache<K, V, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.entries.as_ref().iter().map(|e| (&e.key, &e.value)))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alloc::heap::*;
+    use super::PLruCache;
+
+    #[test]
+    fn lru_evicts() {
+        Heap::transaction(|j| {
+            let cache = PLruCache::<i32, i32, Heap>::new(3, j);
+            for i in 0..3 {
+                assert_eq!(cache.put(i, i * 10, j), None);
+            }
+            assert_eq!(cache.put(1, 11, j), Some(10));
+
+            // 0 is not read since it is added, but 1 is replaced
+            cache.put(3, 30, j);
+            assert!(!cache.contains_key(&0));
+            cache.put(4, 40, j);
+            assert!(!cache.contains_key(&2));
+            assert_eq!(cache.remove(&1, j), Some(11));
+            assert_eq!(cache.remove(&1, j), None);
+            let mut keys: Vec<_> = cache.iter().map(|(k, _)| *k).collect();
+            keys.sort();
+            assert_eq!(keys, vec![3, 4]);
+            assert_eq!(cache.get(&4), Some(&40));
+        }).unwrap();
+    }
+
+    #[test]
+    fn lru_aborted_eviction() {
+        use crate::default::*;
+
+        type P = Allocator;
+        type Cache = PLruCache<i32, i32, P>;
+
+        let root = P::open::<PRefCell<Option<Pbox<Cache>>>>("lru_test.pool", O_CF).unwrap();
+        P::transaction(|j| {
+            let cache = Cache::new(3, j);
+            for i in 0..3 {
+                cache.put(i, i * 10, j);
+            }
+            *root.borrow_mut(j) = Some(Pbox::new(cache, j));
+        }).unwrap();
+
+        assert!(P::transaction(|j| {
+            let root = root.borrow();
+            let cache = root.as_ref().unwrap();
+            cache.put(3, 30, j);
+            cache.put(1, 11, j);
+            cache.remove(&2, j);
+            panic!("abort");
+        }).is_err());
+
+        let root = root.borrow();
+        let cache = root.as_ref().unwrap();
+        let mut entries: Vec<_> = cache.iter().map(|(k, v)| (*k, *v)).collect();
+        entries.sort();
+        assert_eq!(entries, vec![(0, 0), (1, 10), (2, 20)]);
+        assert!(!cache.contains_key(&3));
+    }
+}
@@ -1,2 +1,26 @@
 mod hashmap;
-pub use hashmap::HashMap;
\ No newline at end of file
+mod entry;
+mod imvec;
+mod immap;
+mod crdt;
+mod plog;
+mod bitvec;
+mod lru;
+mod bptree;
+mod art;
+mod rope;
+
+#[cfg(feature = "session_store")]
+pub mod session;
+
+pub use hashmap::HashMap;
+pub use entry::{Entry, OccupiedEntry, VacantEntry};
+pub use imvec::PImVector;
+pub use immap::PImMap;
+pub use crdt::{GCounter, LwwRegister, OrSet};
+pub use plog::PLog;
+pub use bitvec::PBitVec;
+pub use lru::PLruCache;
+pub use bptree::PBPlusTree;
+pub use art::PArt;
+pub use rope::PRope;
@@ -0,0 +1,229 @@
+use std::fmt::{self, Debug};
+
+use crate::*;
+use crate::alloc::*;
+use crate::vec::Vec as PVec;
+use crate::cell::{PCell, PRefCell};
+use crate::stm::Journal;
+
+/// The number of entries in a segment
+const SEGMENT_LEN: usize = 256;
+
+type Segment<T, P> = PRefCell<PVec<T, P>, P>;
+
+/// A persistent append-only log
+///
+/// `PLog` keeps a sequence of entries which are numbered from zero in the
+/// order of their appends. The entries are stored in fixed-size segments, so
+/// an append writes only the new entry and the length of the last segment,
+/// and it never moves the existing entries. The oldest entries can be
+/// dropped with [`truncate_before()`], which frees the segments that fall
+/// entirely before the given index; the indices of the remaining entries do
+/// not change.
+///
+/// Every operation is transactional, so there is nothing to scan or repair
+/// after a crash: opening the pool recovers the log in constant time,
+/// independent of its length. It is a building block for event-sourced
+/// applications, where the state is rebuilt by replaying the log from a
+/// snapshot.
+///
+/// # Examples
+///
+/// ```
+/// use corundum::default::*;
+/// use corundum::stl::PLog;
+///
+/// type P = Allocator;
+///
+/// let events = P::open::<PLog<u64, P>>("plog.pool", O_CF).unwrap();
+///
+/// P::transaction(|j| {
+///     for e in 0..1000 {
+///         events.append(e, j);
+///     }
+///     events.truncate_before(990, j);
+/// }).unwrap();
+///
+/// assert_eq!(events.first_index(), 990);
+/// assert_eq!(events.iter().map(|(i, _)| i).last(), Some(999));
+/// assert_eq!(events.get(995), Some(&995));
+/// assert_eq!(events.get(10), None);
+/// ```
+///
+/// [`truncate_before()`]: #method.truncate_before
+pub struct PLog<T: PSafe, P: MemPool> {
+    /// The index of the first entry of the first segment
+    base: PCell<u64, P>,
+
+    /// The index of the first entry which is not truncated
+    first: PCell<u64, P>,
+
+    segments: PRefCell<PVec<Segment<T, P>, P>, P>,
+}
+
+impl<T: PSafe, P: MemPool> PLog<T, P> {
+    /// Creates a new empty log
+    pub fn new() -> Self {
+        Self {
+            base: PCell::new(0),
+            first: PCell::new(0),
+            segments: PRefCell::new(PVec::new()),
+        }
+    }
+
+    /// Returns the index of the first entry which is not truncated
+    pub fn first_index(&self) -> u64 {
+        self.first.get()
+    }
+
+    /// Returns the index of the next entry to append
+    pub fn next_index(&self) -> u64 {
+        let segs = self.segments.as_ref();
+        match segs.as_slice().last() {
+            Some(last) => {
+                self.base.get() + ((segs.len() - 1) * SEGMENT_LEN
+                    + last.as_ref().len()) as u64
+            }
+            None => self.base.get(),
+        }
+    }
+
+    /// Returns the number of entries which are not truncated
+    pub fn len(&self) -> usize {
+        (self.next_index() - self.first_index()) as usize
+    }
+
+    /// Returns true if there is no entry after the truncation point
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns a reference to the entry at `index`, or `None` if it is
+    /// truncated or not appended yet
+    pub fn get(&self, index: u64) -> Option<&T> {
+        if index < self.first_index() {
+            return None;
+        }
+        let off = (index - self.base.get()) as usize;
+        self.segments.as_ref().get(off / SEGMENT_LEN)?
+            .as_ref().get(off % SEGMENT_LEN)
+    }
+
+    /// Returns an iterator over the entries which are not truncated and their
+    /// indices, in the order of their appends
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &T)> + '_ {
+        let base = self.base.get();
+        let skip = (self.first_index() - base) as usize;
+        self.segments.as_ref().iter()
+            .flat_map(|s| s.as_ref().iter())
+            .enumerate()
+            .skip(skip)
+            .map(move |(i, e)| (base + i as u64, e))
+    }
+
+    /// Appends `value` to the end of the log, and returns its index
+    pub fn append(&self, value: T, j: &Journal<P>) -> u64 {
+        let index = self.next_index();
+        let full = self.segments.as_ref().as_slice().last()
+            .map_or(true, |s| s.as_ref().len() == SEGMENT_LEN);
+        if full {
+            self.segments.borrow_mut(j)
+                .push(PRefCell::new(PVec::with_capacity(SEGMENT_LEN, j)), j);
+        }
+        let segs = self.segments.as_ref();
+        segs[segs.len() - 1].borrow_mut(j).push(value, j);
+        index
+    }
+
+    /// Drops the entries before `index`
+    ///
+    /// The segments which only contain dropped entries are freed. If `index`
+    /// is beyond the end of the log, all entries are dropped, and the next
+    /// append takes the next index as usual.
+    pub fn truncate_before(&self, index: u64, j: &Journal<P>) {
+        let index = index.min(self.next_index());
+        if index <= self.first_index() {
+            return;
+        }
+        self.first.set(index, j);
+
+        let drop = ((index - self.base.get()) as usize / SEGMENT_LEN)
+            .min(self.segments.as_ref().len().saturating_sub(1));
+        if drop > 0 {
+            let mut segs = self.segments.borrow_mut(j);
+            let rest = segs.split_off(drop, j);
+            *segs = rest;
+            self.base.set(self.base.get() + (drop * SEGMENT_LEN) as u64, j);
+        }
+    }
+}
+
+impl<T: PSafe, P: MemPool> Default for PLog<T, P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: PSafe, P: MemPool> RootObj<P> for PLog<T, P> {
+    fn init(_: &Journal<P>) -> Self {
+        Self::new()
+    }
+}
+
+impl<T: PSafe + Debug, P: MemPool> Debug for PLog<T, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alloc::heap::*;
+    use super::{PLog, SEGMENT_LEN};
+
+    #[test]
+    fn plog_truncate() {
+        Heap::transaction(|j| {
+            let log = PLog::<usize, Heap>::new();
+            for i in 0..3 * SEGMENT_LEN {
+                assert_eq!(log.append(i, j), i as u64);
+            }
+            log.truncate_before(SEGMENT_LEN as u64 + 1, j);
+            assert_eq!(log.len(), 2 * SEGMENT_LEN - 1);
+            assert_eq!(log.iter().next(), Some((SEGMENT_LEN as u64 + 1, &(SEGMENT_LEN + 1))));
+
+            // Truncating everything keeps the numbering
+            log.truncate_before(u64::MAX, j);
+            assert!(log.is_empty());
+            assert_eq!(log.append(7, j), 3 * SEGMENT_LEN as u64);
+            assert_eq!(log.iter().collect::<Vec<_>>(), vec![(3 * SEGMENT_LEN as u64, &7)]);
+        }).unwrap();
+    }
+
+    #[test]
+    fn plog_aborted_truncate() {
+        use crate::default::*;
+
+        type P = Allocator;
+
+        let log = P::open::<PLog<usize, P>>("plog_test.pool", O_CF).unwrap();
+        let first = log.first_index();
+        P::transaction(|j| {
+            for i in 0..2 * SEGMENT_LEN {
+                log.append(i, j);
+            }
+        }).unwrap();
+        let len = log.len();
+        let next = log.next_index();
+
+        assert!(P::transaction(|j| {
+            log.truncate_before(next - 1, j);
+            log.append(0, j);
+            panic!("abort");
+        }).is_err());
+        assert_eq!(log.len(), len);
+        assert_eq!(log.first_index(), first);
+        assert_eq!(log.next_index(), next);
+        assert_eq!(log.get(next - 1), Some(&(2 * SEGMENT_LEN - 1)));
+    }
+}
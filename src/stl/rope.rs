@@ -0,0 +1,510 @@
+use std::cmp;
+use std::fmt::{self, Debug, Display};
+use std::ops::{Bound, RangeBounds};
+
+use crate::*;
+use crate::alloc::*;
+use crate::boxed::Pbox;
+use crate::cell::PRefCell;
+use crate::str::String as PString;
+use crate::stm::Journal;
+
+/// The maximum number of bytes in a leaf
+const CHUNK: usize = 1024;
+
+enum Node<P: MemPool> {
+    Leaf(PString<P>),
+    Branch {
+        len: usize,
+        height: u8,
+        left: Pbox<Node<P>, P>,
+        right: Pbox<Node<P>, P>,
+    },
+}
+
+use Node::*;
+
+impl<P: MemPool> Node<P> {
+    #[inline]
+    fn len(&self) -> usize {
+        match self {
+            Leaf(s) => s.len(),
+            Branch { len, .. } => *len,
+        }
+    }
+
+    #[inline]
+    fn height(&self) -> u8 {
+        match self {
+            Leaf(_) => 0,
+            Branch { height, .. } => *height,
+        }
+    }
+
+    /// Builds a balanced tree of the chunks of `s`, which is not empty
+    fn from_str(s: &str, j: &Journal<P>) -> Self {
+        let mut chunks = vec![];
+        let mut rest = s;
+        while rest.len() > CHUNK {
+            let mut at = CHUNK;
+            while !rest.is_char_boundary(at) {
+                at -= 1;
+            }
+            chunks.push(&rest[..at]);
+            rest = &rest[at..];
+        }
+        chunks.push(rest);
+        Self::build(&chunks, j)
+    }
+
+    fn build(chunks: &[&str], j: &Journal<P>) -> Self {
+        if chunks.len() == 1 {
+            Leaf(PString::from_str(chunks[0], j))
+        } else {
+            let mid = chunks.len() / 2;
+            Self::branch(Self::build(&chunks[..mid], j), Self::build(&chunks[mid..], j), j)
+        }
+    }
+
+    fn branch(left: Self, right: Self, j: &Journal<P>) -> Self {
+        Branch {
+            len: left.len() + right.len(),
+            height: 1 + cmp::max(left.height(), right.height()),
+            left: Pbox::new(left, j),
+            right: Pbox::new(right, j),
+        }
+    }
+
+    /// Moves the children out of a branch; the old node is freed when the
+    /// transaction commits
+    fn into_children(self, j: &Journal<P>) -> (Self, Self) {
+        match self {
+            Branch { left, right, .. } => (Pbox::into_inner(left, j), Pbox::into_inner(right, j)),
+            Leaf(_) => unreachable!("a leaf has no children"),
+        }
+    }
+
+    /// Makes a branch of two subtrees whose heights differ by up to two, and
+    /// rotates it if they differ by two
+    fn balance(left: Self, right: Self, j: &Journal<P>) -> Self {
+        let (hl, hr) = (left.height(), right.height());
+        if hl > hr + 1 {
+            let (ll, lr) = left.into_children(j);
+            if lr.height() > ll.height() {
+                let (lrl, lrr) = lr.into_children(j);
+                Self::branch(Self::branch(ll, lrl, j), Self::branch(lrr, right, j), j)
+            } else {
+                Self::branch(ll, Self::branch(lr, right, j), j)
+            }
+        } else if hr > hl + 1 {
+            let (rl, rr) = right.into_children(j);
+            if rl.height() > rr.height() {
+                let (rll, rlr) = rl.into_children(j);
+                Self::branch(Self::branch(left, rll, j), Self::branch(rlr, rr, j), j)
+            } else {
+                Self::branch(Self::branch(left, rl, j), rr, j)
+            }
+        } else {
+            Self::branch(left, right, j)
+        }
+    }
+
+    /// Concatenates two trees in O(|h<sub>a</sub> - h<sub>b</sub>|)
+    ///
+    /// A leaf which is joined to a tree is joined to the nearest leaf of it,
+    /// so that small pieces are merged into the neighboring chunks.
+    fn join(a: Self, b: Self, j: &Journal<P>) -> Self {
+        let (ha, hb) = (a.height(), b.height());
+        if ha > hb + 1 || (hb == 0 && ha > 0) {
+            let (l, r) = a.into_children(j);
+            let r = Self::join(r, b, j);
+            Self::balance(l, r, j)
+        } else if hb > ha + 1 || (ha == 0 && hb > 0) {
+            let (l, r) = b.into_children(j);
+            let l = Self::join(a, l, j);
+            Self::balance(l, r, j)
+        } else {
+            match (a, b) {
+                (Leaf(x), Leaf(y)) if x.len() + y.len() <= CHUNK => {
+                    let mut s = PString::with_capacity(x.len() + y.len(), j);
+                    s.push_str(&x, j);
+                    s.push_str(&y, j);
+                    Leaf(s)
+                }
+                (a, b) => Self::branch(a, b, j),
+            }
+        }
+    }
+
+    fn join_opt(a: Option<Self>, b: Option<Self>, j: &Journal<P>) -> Option<Self> {
+        match (a, b) {
+            (Some(a), Some(b)) => Some(Self::join(a, b, j)),
+            (a, None) => a,
+            (None, b) => b,
+        }
+    }
+
+    /// Splits the tree at byte `at` in O(log n)
+    fn split(self, at: usize, j: &Journal<P>) -> (Option<Self>, Option<Self>) {
+        if at == 0 {
+            return (None, Some(self));
+        } else if at >= self.len() {
+            return (Some(self), None);
+        }
+        match self {
+            Leaf(s) => {
+                assert!(s.is_char_boundary(at), "byte index {} is not a char boundary", at);
+                (Some(Leaf(PString::from_str(&s[..at], j))), Some(Leaf(PString::from_str(&s[at..], j))))
+            }
+            Branch { left, right, .. } => {
+                let (l, r) = (Pbox::into_inner(left, j), Pbox::into_inner(right, j));
+                let n = l.len();
+                if at < n {
+                    let (a, b) = l.split(at, j);
+                    (a, Self::join_opt(b, Some(r), j))
+                } else {
+                    let (a, b) = r.split(at - n, j);
+                    (Self::join_opt(Some(l), a, j), b)
+                }
+            }
+        }
+    }
+
+    /// Returns the leaf which contains byte `at` and the offset of `at` in it
+    fn leaf_at(&self, mut at: usize) -> (&str, usize) {
+        let mut node = self;
+        loop {
+            match node {
+                Leaf(s) => return (s.as_str(), at),
+                Branch { left, right, .. } => {
+                    if at < left.len() {
+                        node = &**left;
+                    } else {
+                        at -= left.len();
+                        node = &**right;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A persistent rope for large text
+///
+/// `PRope` keeps the text in chunks of up to 1KB, which are the leaves of a
+/// height-balanced (AVL) tree. Appending, inserting, removing, and slicing
+/// take O(log n) time and allocations regardless of the length of the text,
+/// whereas [`PString`] copies the whole string when it grows past its
+/// capacity. The tree is rebalanced in the same transaction, so a crash never
+/// leaves it half-rotated.
+///
+/// The positions are byte offsets into the UTF-8 text, as in [`str`], and
+/// they have to lie on `char` boundaries. Small pieces of text are merged
+/// into the neighboring chunks, so that the rope does not degenerate into
+/// tiny leaves when it is built by many small appends, e.g., in a log.
+///
+/// The methods which change the text take `&mut self`, so the iterators
+/// returned by [`slice()`] and [`chunks()`], which borrow the chunks, keep
+/// the rope from changing while they are alive. To share a rope, wrap it in
+/// a [`PRefCell`].
+///
+/// ```compile_fail,E0502
+/// # use corundum::alloc::heap::*;
+/// # use corundum::stl::PRope;
+/// Heap::transaction(|j| {
+///     let mut rope = PRope::<Heap>::from_str("Hello", j);
+///     let mut chunks = rope.chunks();
+///     rope.clear(j); // the chunks are still borrowed
+///     assert_eq!(chunks.next(), Some("Hello"));
+/// }).unwrap();
+/// ```
+///
+/// # Examples
+///
+/// ```
+/// # use corundum::alloc::heap::*;
+/// use corundum::stl::PRope;
+///
+/// Heap::transaction(|j| {
+///     let mut rope = PRope::<Heap>::from_str("Hello world", j);
+///     rope.insert_str(5, ",", j);
+///     rope.push_str("!", j);
+///     assert_eq!(rope.to_string(), "Hello, world!");
+///
+///     rope.remove(0..7, j);
+///     assert_eq!(rope.slice(..5).collect::<String>(), "world");
+///     assert_eq!(rope.len(), 6);
+/// }).unwrap();
+/// ```
+///
+/// [`PString`]: crate::str::String
+/// [`str`]: std::str
+/// [`slice()`]: #method.slice
+/// [`chunks()`]: #method.chunks
+/// [`PRefCell`]: crate::cell::PRefCell
+pub struct PRope<P: MemPool> {
+    root: PRefCell<Option<Pbox<Node<P>, P>>, P>,
+}
+
+impl<P: MemPool> PRope<P> {
+    /// Creates a new empty rope
+    pub fn new() -> Self {
+        Self { root: PRefCell::new(None) }
+    }
+
+    /// Creates a new rope with the contents of `s`
+    pub fn from_str(s: &str, j: &Journal<P>) -> Self {
+        let root = if s.is_empty() { None } else { Some(Pbox::new(Node::from_str(s, j), j)) };
+        Self { root: PRefCell::new(root) }
+    }
+
+    /// Returns the length of the text in bytes
+    pub fn len(&self) -> usize {
+        self.root.borrow().as_ref().map_or(0, |r| r.len())
+    }
+
+    /// Returns true if the rope has no text
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns true if byte `at` is the start of a `char` or the end of the
+    /// text
+    pub fn is_char_boundary(&self, at: usize) -> bool {
+        if at == 0 || at == self.len() {
+            return true;
+        } else if at > self.len() {
+            return false;
+        }
+        let root = self.root.borrow();
+        let (s, at) = root.as_ref().unwrap().leaf_at(at);
+        s.is_char_boundary(at)
+    }
+
+    fn take(&mut self, j: &Journal<P>) -> Option<Node<P>> {
+        self.root.borrow_mut(j).take().map(|r| Pbox::into_inner(r, j))
+    }
+
+    fn put(&mut self, root: Option<Node<P>>, j: &Journal<P>) {
+        *self.root.borrow_mut(j) = root.map(|r| Pbox::new(r, j));
+    }
+
+    fn range<R: RangeBounds<usize>>(&self, range: R) -> (usize, usize) {
+        let len = self.len();
+        let start = match range.start_bound() {
+            Bound::Included(s) => *s,
+            Bound::Excluded(s) => *s + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(e) => *e + 1,
+            Bound::Excluded(e) => *e,
+            Bound::Unbounded => len,
+        };
+        assert!(start <= end, "slice index starts at {} but ends at {}", start, end);
+        assert!(end <= len, "range end index {} out of range for rope of length {}", end, len);
+        assert!(self.is_char_boundary(start) && self.is_char_boundary(end),
+            "range {}..{} is not on char boundaries", start, end);
+        (start, end)
+    }
+
+    /// Appends `s` to the end of the text
+    pub fn push_str(&mut self, s: &str, j: &Journal<P>) {
+        if !s.is_empty() {
+            let root = self.take(j);
+            self.put(Node::join_opt(root, Some(Node::from_str(s, j)), j), j);
+        }
+    }
+
+    /// Inserts `s` at byte `at`
+    ///
+    /// # Panics
+    ///
+    /// It panics if `at` is larger than the length, or it does not lie on a
+    /// `char` boundary.
+    pub fn insert_str(&mut self, at: usize, s: &str, j: &Journal<P>) {
+        assert!(at <= self.len(), "index {} out of range for rope of length {}", at, self.len());
+        assert!(self.is_char_boundary(at), "byte index {} is not a char boundary", at);
+        if !s.is_empty() {
+            let (l, r) = match self.take(j) {
+                Some(root) => root.split(at, j),
+                None => (None, None),
+            };
+            let l = Node::join_opt(l, Some(Node::from_str(s, j)), j);
+            self.put(Node::join_opt(l, r, j), j);
+        }
+    }
+
+    /// Removes the bytes in `range`
+    ///
+    /// # Panics
+    ///
+    /// It panics if the range is out of bounds, or its ends do not lie on
+    /// `char` boundaries.
+    pub fn remove<R: RangeBounds<usize>>(&mut self, range: R, j: &Journal<P>) {
+        let (start, end) = self.range(range);
+        if start < end {
+            let (l, r) = self.take(j).unwrap().split(end, j);
+            let (l, _) = l.unwrap().split(start, j);
+            self.put(Node::join_opt(l, r, j), j);
+        }
+    }
+
+    /// Splits the rope at byte `at`, and returns the text after it as a new
+    /// rope
+    ///
+    /// # Panics
+    ///
+    /// It panics if `at` is larger than the length, or it does not lie on a
+    /// `char` boundary.
+    pub fn split_off(&mut self, at: usize, j: &Journal<P>) -> PRope<P> {
+        let (at, _) = self.range(at..);
+        let (l, r) = match self.take(j) {
+            Some(root) => root.split(at, j),
+            None => (None, None),
+        };
+        self.put(l, j);
+        PRope { root: PRefCell::new(r.map(|r| Pbox::new(r, j))) }
+    }
+
+    /// Moves the text of `other` to the end of this rope in O(log n)
+    pub fn append(&mut self, mut other: PRope<P>, j: &Journal<P>) {
+        let root = self.take(j);
+        self.put(Node::join_opt(root, other.take(j), j), j);
+    }
+
+    /// Removes all text
+    pub fn clear(&mut self, j: &Journal<P>) {
+        self.put(None, j);
+    }
+
+    /// Returns an iterator over the chunks of the text in `range`
+    ///
+    /// It finds the first chunk in O(log n) time, and the pieces at the ends
+    /// are sliced to the range.
+    ///
+    /// # Panics
+    ///
+    /// It panics if the range is out of bounds, or its ends do not lie on
+    /// `char` boundaries.
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> Chunks<'_, P> {
+        let (start, end) = self.range(range);
+        let mut stack = vec![];
+        if let Some(root) = self.root.borrow().as_ref() {
+            // The rope is borrowed for the lifetime of the iterator, and it is
+            // changed only through `&mut self`; so the nodes outlive the guard
+            stack.push((unsafe { &*(&**root as *const Node<P>) }, 0));
+        }
+        Chunks { stack, start, end }
+    }
+
+    /// Returns an iterator over the chunks of the whole text
+    pub fn chunks(&self) -> Chunks<'_, P> {
+        self.slice(..)
+    }
+}
+
+/// An iterator over the chunks of a [`PRope`]
+///
+/// This is created by [`PRope::slice`] and [`PRope::chunks`].
+pub struct Chunks<'a, P: MemPool> {
+    stack: Vec<(&'a Node<P>, usize)>,
+    start: usize,
+    end: usize,
+}
+
+impl<'a, P: MemPool> Iterator for Chunks<'a, P> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        while let Some((node, off)) = self.stack.pop() {
+            if off >= self.end || off + node.len() <= self.start {
+                continue;
+            }
+            match node {
+                Leaf(s) => {
+                    let from = self.start.saturating_sub(off);
+                    let to = cmp::min(self.end - off, s.len());
+                    return Some(&s.as_str()[from..to]);
+                }
+                Branch { left, right, .. } => {
+                    self.stack.push((&**right, off + left.len()));
+                    self.stack.push((&**left, off));
+                }
+            }
+        }
+        None
+    }
+}
+
+impl<P: MemPool> Default for PRope<P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P: MemPool> RootObj<P> for PRope<P> {
+    fn init(_: &Journal<P>) -> Self {
+        Self::new()
+    }
+}
+
+impl<P: MemPool> Display for PRope<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for s in self.chunks() {
+            f.write_str(s)?;
+        }
+        Ok(())
+    }
+}
+
+impl<P: MemPool> Debug for PRope<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.to_string(), f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alloc::heap::*;
+    use super::PRope;
+
+    #[test]
+    fn rope_edits_like_a_string() {
+        Heap::transaction(|j| {
+            let floor = |s: &str, mut at: usize| {
+                while !s.is_char_boundary(at) {
+                    at -= 1;
+                }
+                at
+            };
+            let mut rope = PRope::<Heap>::new();
+            let mut s = String::new();
+            for i in 0..2000 {
+                let piece = format!("{}é,", i);
+                rope.push_str(&piece, j);
+                s.push_str(&piece);
+            }
+
+            // Appends are merged into the last chunk
+            assert!(rope.chunks().count() <= s.len() / 512 + 1);
+            for i in 0..200 {
+                let at = floor(&s, (i * 7919) % s.len());
+                rope.insert_str(at, "«inserted»", j);
+                s.insert_str(at, "«inserted»");
+            }
+            assert_eq!(rope.len(), s.len());
+            assert_eq!(rope.to_string(), s);
+
+            let (a, b, c) = (floor(&s, 100), floor(&s, 4000), floor(&s, 5000));
+            let tail = rope.split_off(c, j);
+            assert_eq!(tail.to_string(), &s[c..]);
+            rope.remove(a..b, j);
+            rope.append(tail, j);
+            s.replace_range(a..b, "");
+            assert_eq!(rope.to_string(), s);
+            let (a, b) = (floor(&s, 50), floor(&s, 3000));
+            assert_eq!(rope.slice(a..b).collect::<String>(), &s[a..b]);
+        }).unwrap();
+    }
+}
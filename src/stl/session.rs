@@ -0,0 +1,262 @@
+//! A durable HTTP session store (reference implementation)
+//!
+//! This module is a template for web-service users. It shows the recommended
+//! patterns for building a durable service state with Corundum:
+//!
+//! * The persistent state is a [`PImMap`] from session ids to [`Session`]s.
+//!   Updates replace the map version inside a [`PRefCell`], so each request
+//!   is one small transaction.
+//! * Derived data that can be recomputed (here, the expiration order) is kept
+//!   in a [`VCell`]. It is not logged, and it is rebuilt lazily after the pool
+//!   is reopened.
+//! * Every mutation takes a [`Journal`], so a crash in the middle of a request
+//!   never leaves a half-written session behind.
+//!
+//! It is enabled by the `session_store` feature.
+//!
+//! # Examples
+//!
+//! ```
+//! use corundum::default::*;
+//! use corundum::stl::session::SessionStore;
+//! use std::time::Duration;
+//!
+//! type P = Allocator;
+//!
+//! let store = P::open::<SessionStore<u64, P>>("sessions.pool", O_CF).unwrap();
+//!
+//! P::transaction(|j| {
+//!     store.insert("b3f1c2", 42, Duration::from_secs(1800), j);
+//! }).unwrap();
+//!
+//! assert_eq!(store.get_with("b3f1c2", |user| *user), Some(42));
+//!
+//! // A periodic task removes the expired sessions
+//! let purged = P::transaction(|j| store.purge_expired(j)).unwrap();
+//! assert_eq!(purged, 0);
+//! ```
+//!
+//! [`PImMap`]: ../struct.PImMap.html
+//! [`Session`]: ./struct.Session.html
+//! [`PRefCell`]: ../../struct.PRefCell.html
+//! [`VCell`]: ../../struct.VCell.html
+//! [`Journal`]: ../../stm/struct.Journal.html
+
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::*;
+use crate::alloc::*;
+use crate::cell::{PRefCell, VCell};
+use crate::stm::Journal;
+use crate::clone::PClone;
+use crate::str::String as PString;
+use crate::stl::PImMap;
+
+/// Returns the current time in seconds since the Unix epoch
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// A session entry with an expiration time
+pub struct Session<V: PSafe> {
+    value: V,
+    expires_at: u64,
+}
+
+impl<V: PSafe> Session<V> {
+    /// Returns the session data
+    pub fn value(&self) -> &V {
+        &self.value
+    }
+
+    /// Returns the expiration time in seconds since the Unix epoch
+    pub fn expires_at(&self) -> u64 {
+        self.expires_at
+    }
+
+    /// Indicates if the session is expired at time `now`
+    pub fn is_expired_at(&self, now: u64) -> bool {
+        now >= self.expires_at
+    }
+}
+
+impl<V: PSafe + PClone<P>, P: MemPool> PClone<P> for Session<V> {
+    fn pclone(&self, j: &Journal<P>) -> Self {
+        Self {
+            value: self.value.pclone(j),
+            expires_at: self.expires_at,
+        }
+    }
+}
+
+/// The volatile expiration index
+///
+/// It is a hint: entries may be stale (e.g., after a touch or an aborted
+/// transaction), so the persistent session is always checked before removal.
+#[derive(Default)]
+struct ExpiryIndex {
+    built: bool,
+    queue: BTreeSet<(u64, std::string::String)>,
+}
+
+/// A durable session store mapping string ids to sessions with a TTL
+///
+/// See the [module-level documentation](./index.html) for more details.
+pub struct SessionStore<V: PSafe, P: MemPool> {
+    sessions: PRefCell<PImMap<PString<P>, Session<V>, P>, P>,
+    expiry: VCell<RefCell<ExpiryIndex>, P>,
+}
+
+impl<V: PSafe, P: MemPool> SessionStore<V, P> {
+    /// Creates an empty store
+    pub fn new() -> Self {
+        Self {
+            sessions: PRefCell::new(PImMap::new()),
+            expiry: VCell::default(),
+        }
+    }
+
+    /// Returns the number of stored sessions, including the expired ones
+    /// which are not purged yet
+    pub fn len(&self) -> usize {
+        self.sessions.borrow().len()
+    }
+
+    /// Indicates if the store is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Calls `f` with the data of the session `id` if it exists and is not
+    /// expired
+    pub fn get_with<R, F: FnOnce(&V) -> R>(&self, id: &str, f: F) -> Option<R> {
+        let sessions = self.sessions.borrow();
+        match sessions.get(id) {
+            Some(s) if !s.is_expired_at(now()) => Some(f(&s.value)),
+            _ => None
+        }
+    }
+
+    /// Indicates if the session `id` exists and is not expired
+    pub fn contains(&self, id: &str) -> bool {
+        self.get_with(id, |_| ()).is_some()
+    }
+
+    fn index(&self) -> std::cell::RefMut<'_, ExpiryIndex> {
+        let mut index = self.expiry.borrow_mut();
+        if !index.built {
+            // The pool was reopened; rebuild the index from the persistent map
+            let mut queue = BTreeSet::new();
+            self.sessions.borrow().foreach(|id, s| {
+                queue.insert((s.expires_at, id.to_string()));
+            });
+            index.queue = queue;
+            index.built = true;
+        }
+        index
+    }
+}
+
+impl<V: PSafe + PClone<P>, P: MemPool> SessionStore<V, P> {
+    /// Creates or replaces the session `id` which expires after `ttl`
+    pub fn insert(&self, id: &str, value: V, ttl: Duration, j: &Journal<P>) {
+        let expires_at = now() + ttl.as_secs();
+        {
+            let mut sessions = self.sessions.borrow_mut(j);
+            *sessions = sessions.insert(PString::from_str(id, j), Session { value, expires_at }, j);
+        }
+        self.index().queue.insert((expires_at, id.to_string()));
+    }
+
+    /// Extends the expiration time of the session `id` to `ttl` from now. It
+    /// returns false if the session does not exist or is expired.
+    pub fn touch(&self, id: &str, ttl: Duration, j: &Journal<P>) -> bool {
+        let value = {
+            let sessions = self.sessions.borrow();
+            match sessions.get(id) {
+                Some(s) if !s.is_expired_at(now()) => s.value.pclone(j),
+                _ => return false
+            }
+        };
+        self.insert(id, value, ttl, j);
+        true
+    }
+
+    /// Removes the session `id`. It returns false if it does not exist.
+    pub fn remove(&self, id: &str, j: &Journal<P>) -> bool {
+        if !self.sessions.borrow().contains_key(id) {
+            return false;
+        }
+        let mut sessions = self.sessions.borrow_mut(j);
+        *sessions = sessions.remove(id, j);
+        true
+    }
+
+    /// Removes all expired sessions, and returns the number of removed
+    /// sessions
+    pub fn purge_expired(&self, j: &Journal<P>) -> usize {
+        let now = now();
+        let mut index = self.index();
+        let mut purged = 0;
+        while let Some((t, id)) = index.queue.iter().next().cloned() {
+            if t > now {
+                break;
+            }
+            index.queue.remove(&(t, id.clone()));
+            let expired = self.sessions.borrow().get(id.as_str())
+                .map_or(false, |s| s.is_expired_at(now));
+            if expired {
+                let mut sessions = self.sessions.borrow_mut(j);
+                *sessions = sessions.remove(id.as_str(), j);
+                purged += 1;
+            }
+        }
+        purged
+    }
+}
+
+impl<V: PSafe, P: MemPool> Default for SessionStore<V, P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V: PSafe, P: MemPool> RootObj<P> for SessionStore<V, P> {
+    fn init(_: &Journal<P>) -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SessionStore;
+    use crate::default::*;
+    use std::time::Duration;
+
+    #[test]
+    fn session_store() {
+        let store = Allocator::open::<SessionStore<u32, Allocator>>("sessions.pool", O_CF).unwrap();
+        Allocator::transaction(|j| {
+            store.insert("alive", 1, Duration::from_secs(3600), j);
+            store.insert("expired", 2, Duration::from_secs(0), j);
+            store.insert("removed", 3, Duration::from_secs(3600), j);
+        }).unwrap();
+
+        assert_eq!(store.get_with("alive", |v| *v), Some(1));
+        assert!(!store.contains("expired"));
+        assert_eq!(store.len(), 3);
+
+        let purged = Allocator::transaction(|j| {
+            assert!(store.remove("removed", j));
+            assert!(!store.remove("removed", j));
+            assert!(store.touch("alive", Duration::from_secs(7200), j));
+            store.purge_expired(j)
+        }).unwrap();
+
+        assert_eq!(purged, 1);
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.get_with("alive", |v| *v), Some(1));
+    }
+}
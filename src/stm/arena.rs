@@ -0,0 +1,239 @@
+//! Per-thread arenas of journal pages
+//!
+//! A journal takes a new page from the allocator whenever its last page is
+//! full, which goes through the same zone locks as the allocations of the
+//! transactions. With many threads, the log-append path spends most of its
+//! time waiting for them. If the pool is configured with a non-zero
+//! [`journal_arena`], every thread reserves a block of journal pages at its
+//! first transaction, and its journal takes the pages from there with a
+//! single atomic operation. The pages return to the arena when the journal
+//! is cleared, and go to the allocator as usual only if the arena is full.
+//!
+//! The arenas are linked together in the pool, so that they are not lost in
+//! a crash. They are never freed; when a thread exits, another thread takes
+//! over its arena, and after recovery, all arenas are available again.
+//!
+//! # Examples
+//!
+//! ```
+//! # fn main() {
+//! corundum::pool!(arena_pool, journal_arena = 8);
+//! use arena_pool::*;
+//!
+//! type P = arena_pool::Allocator;
+//!
+//! let root = P::open::<PCell<u64>>("arena.pool", O_CF).unwrap();
+//!
+//! let threads: Vec<_> = (0..4).map(|_| std::thread::spawn(|| {
+//!     for _ in 0..100 {
+//!         P::transaction(|j| {
+//!             let _ = Pbox::new(1u64, j); // logs go to the arena of the thread
+//!         }).unwrap();
+//!     }
+//! })).collect();
+//! for t in threads {
+//!     t.join().unwrap();
+//! }
+//! # drop(root);
+//! # }
+//! ```
+//!
+//! [`journal_arena`]: ../../alloc/struct.PoolConfig.html#method.journal_arena
+
+use crate::alloc::MemPool;
+use crate::cell::LazyCell;
+use crate::ll::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// The header of an arena, which is followed by the pages
+#[repr(C)]
+struct Arena {
+    /// The offset of the next arena in the pool
+    next: u64,
+
+    /// The number of pages
+    pages: u64,
+
+    /// The pages which are taken; it is volatile and reset after recovery
+    used: AtomicU64,
+}
+
+impl Arena {
+    #[inline]
+    fn mask(&self) -> u64 {
+        if self.pages as usize >= 64 {
+            u64::MAX
+        } else {
+            (1 << self.pages) - 1
+        }
+    }
+}
+
+/// The arena of the current thread in every pool, and the generation of the
+/// pool when it was taken
+struct Local(HashMap<&'static str, (u64, u32)>);
+
+impl Drop for Local {
+    fn drop(&mut self) {
+        // The arenas of the exiting thread are given to the next threads
+        let mut idle = lock();
+        for (pool, (off, gen)) in self.0.drain() {
+            if let Some((g, free)) = idle.get_mut(pool) {
+                if *g == gen {
+                    free.push(off);
+                }
+            }
+        }
+    }
+}
+
+thread_local! {
+    static LOCAL: RefCell<Local> = RefCell::new(Local(HashMap::new()));
+}
+
+/// The arenas which are not taken by any thread, and the generation of the
+/// pool which they belong to. The lock also serializes the allocation of
+/// new arenas.
+static mut IDLE: LazyCell<Mutex<HashMap<&'static str, (u32, Vec<u64>)>>> =
+    LazyCell::new(|| Mutex::new(HashMap::new()));
+
+fn lock() -> std::sync::MutexGuard<'static, HashMap<&'static str, (u32, Vec<u64>)>> {
+    match unsafe { IDLE.lock() } {
+        Ok(g) => g,
+        Err(p) => p.into_inner()
+    }
+}
+
+/// Takes an idle arena, or allocates a new one, and returns its offset
+unsafe fn acquire<A: MemPool>(page_size: usize) -> Option<u64> {
+    let mut idle = lock();
+    let gen = A::gen();
+    let entry = idle.entry(A::name()).or_insert((gen, vec![]));
+    if entry.0 != gen {
+        *entry = (gen, vec![]);
+    }
+    if let Some(off) = entry.1.pop() {
+        return Some(off);
+    }
+
+    let pages = A::CONFIG.get_journal_arena();
+    let size = std::mem::size_of::<Arena>() + pages * page_size;
+    let (ptr, off, _, z) = A::pre_alloc(size);
+    if ptr.is_null() {
+        return None;
+    }
+    let head = A::arenas_head();
+    let arena = &mut *(ptr as *mut Arena);
+    arena.next = *head;
+    arena.pages = pages as u64;
+    arena.used = AtomicU64::new(0);
+    persist_obj_with_log::<_,A>(arena, false);
+    A::log64(A::off_unchecked(head), off, z);
+    A::perform(z);
+    Some(off)
+}
+
+/// Takes a free page from the arena of the current thread, and returns the
+/// offset of the arena with a pointer to the page. It returns `None` if the
+/// arenas are disabled or the arena is full.
+pub(crate) unsafe fn take<A: MemPool>(page_size: usize) -> Option<(u64, *mut u8)> {
+    if A::CONFIG.get_journal_arena() == 0 {
+        return None;
+    }
+    let gen = A::gen();
+    let off = LOCAL.try_with(|l| {
+        let mut l = l.borrow_mut();
+        match l.0.get(A::name()) {
+            Some((off, g)) if *g == gen => Some(*off),
+            _ => {
+                let off = acquire::<A>(page_size)?;
+                l.0.insert(A::name(), (off, gen));
+                Some(off)
+            }
+        }
+    }).ok()??;
+
+    let base = A::get_mut_unchecked::<u8>(off) as *mut u8;
+    let arena = &*(base as *const Arena);
+    let mask = arena.mask();
+    let mut used = arena.used.load(Ordering::Acquire);
+    loop {
+        let free = !used & mask;
+        if free == 0 {
+            return None;
+        }
+        let i = free.trailing_zeros() as usize;
+        match arena.used.compare_exchange_weak(used, used | (1 << i),
+            Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => {
+                let page = base.add(std::mem::size_of::<Arena>() + i * page_size);
+                return Some((off, page));
+            }
+            Err(u) => used = u,
+        }
+    }
+}
+
+/// Returns a page to the arena at offset `home`; it may be called from any
+/// thread
+pub(crate) unsafe fn give<A: MemPool>(home: u64, page: u64, page_size: usize) {
+    let arena = A::get_mut_unchecked::<Arena>(home);
+    let i = (page - home - std::mem::size_of::<Arena>() as u64) / page_size as u64;
+    arena.used.fetch_and(!(1 << i), Ordering::AcqRel);
+}
+
+/// Frees all pages of the arenas of pool `A` and makes the arenas idle
+///
+/// It is called after recovery, when no journal is left.
+#[doc(hidden)]
+pub unsafe fn reset<A: MemPool>() {
+    let mut free = vec![];
+    let mut curr = *A::arenas_head();
+    while curr != u64::MAX {
+        let arena = A::get_mut_unchecked::<Arena>(curr);
+        arena.used.store(0, Ordering::Release);
+        free.push(curr);
+        curr = arena.next;
+    }
+    lock().insert(A::name(), (A::gen(), free));
+}
+
+#[cfg(test)]
+mod test {
+    use super::Arena;
+
+    crate::pool!(arena_test, journal_arena = 2);
+
+    type P = arena_test::Allocator;
+
+    fn arenas() -> usize {
+        use crate::alloc::MemPoolTraits;
+        let mut cnt = 0;
+        let mut curr = unsafe { *P::arenas_head() };
+        while curr != u64::MAX {
+            cnt += 1;
+            curr = unsafe { P::get_mut_unchecked::<Arena>(curr).next };
+        }
+        cnt
+    }
+
+    #[test]
+    fn arena_is_reused() {
+        use arena_test::*;
+
+        let _pool = P::open_no_root("arena_test.pool", O_CF).unwrap();
+        for _ in 0..3 {
+            std::thread::spawn(|| {
+                P::transaction(|j| {
+                    let _ = Pbox::new(1u64, j);
+                }).unwrap();
+            }).join().unwrap();
+        }
+
+        // The threads run one after another, so they share one arena
+        assert_eq!(arenas(), 1);
+    }
+}
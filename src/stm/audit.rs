@@ -0,0 +1,335 @@
+//! Transaction audit log
+//!
+//! An [`AuditRing`] is a durable, append-only ring buffer of [`AuditRecord`]s
+//! which tells when the persistent state of a pool changed. Once a ring is
+//! [attached] to its pool, every committing top-level transaction appends a
+//! record with its transaction id (the [commit timestamp]), the wall-clock
+//! time, the committing thread, and the number of logs and logged bytes. The
+//! record is written as part of the same transaction; hence, the ring never
+//! contains a transaction that is rolled back.
+//!
+//! The retention is bounded by the capacity of the ring: when it is full, the
+//! oldest record is overwritten. Every record has a sequence number which is
+//! contiguous, so a reader can tell if it missed some records. The sequence
+//! numbers are reserved without a lock when the transactions commit, so the
+//! records of concurrent transactions may not be in the order of their ids. Unlike the
+//! [change data capture](../cdc/index.html) ring, the records are not taken
+//! out by reading them.
+//!
+//! It is enabled by the `audit` feature.
+//!
+//! # Examples
+//!
+//! ```
+//! use corundum::default::*;
+//! use corundum::stm::audit::AuditRing;
+//!
+//! type P = Allocator;
+//!
+//! let ring = P::open::<AuditRing<P>>("audit.pool", O_CF).unwrap();
+//! ring.attach();
+//!
+//! P::transaction(|j| {
+//!     let _ = Pbox::new(10u64, j);
+//! }).unwrap();
+//!
+//! let records = P::transaction(|j| ring.records(j)).unwrap();
+//! let last = records.last().unwrap();
+//! assert!(last.time_ns > 0);
+//! assert!(last.logs > 0);
+//! ```
+//!
+//! [attached]: ./struct.AuditRing.html#method.attach
+//! [commit timestamp]: ../timestamp/index.html
+
+use crate::alloc::MemPool;
+use crate::cell::{LazyCell, PCell, RootObjWith};
+use crate::stm::Journal;
+use crate::vec::Vec as PVec;
+use crate::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The default capacity of an [`AuditRing`](./struct.AuditRing.html) which
+/// is created as a root object
+pub const DEFAULT_AUDIT_CAPACITY: usize = 4096;
+
+/// A record of a committed transaction
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct AuditRecord {
+    /// The sequence number of the record in the ring
+    pub seq: u64,
+
+    /// The transaction id, which is its commit timestamp, or 0 if the pool
+    /// does not support timestamps
+    pub tx: u64,
+
+    /// The wall-clock time of the commit in nanoseconds since the UNIX epoch
+    pub time_ns: u64,
+
+    /// The id of the committing thread
+    pub thread: u64,
+
+    /// The number of logs which the transaction wrote on its journal
+    pub logs: usize,
+
+    /// The number of bytes which the transaction logged
+    pub bytes: usize,
+}
+
+/// The volatile state of an attached ring
+///
+/// Committing transactions reserve sequence numbers from `head`, write their
+/// slots in their own journals, and then advance `done` in the order of the
+/// reservations. Records below `done` are visible to the readers.
+struct RingState {
+    head: AtomicU64,
+    done: AtomicU64,
+}
+
+/// Advances `done` past a reservation even if writing the record panics, so
+/// that the later reservations are not blocked
+struct Publish<'a> {
+    state: &'a RingState,
+    seq: u64,
+}
+
+impl Drop for Publish<'_> {
+    fn drop(&mut self) {
+        while self.state.done.load(Ordering::Acquire) != self.seq {
+            std::hint::spin_loop();
+        }
+        self.state.done.store(self.seq + 1, Ordering::Release);
+    }
+}
+
+/// A durable ring of transaction records
+///
+/// See the [module-level documentation](./index.html) for more details.
+pub struct AuditRing<A: MemPool> {
+    /// The records; the sequence number of an empty slot is `u64::MAX`
+    slots: PVec<PCell<AuditRecord, A>, A>,
+}
+
+impl<A: MemPool> AuditRing<A> {
+    /// Creates a new ring which keeps up to `capacity` records
+    pub fn new(capacity: usize, journal: &Journal<A>) -> Self {
+        assert!(capacity > 0, "an audit ring needs a non-zero capacity");
+        let mut slots = PVec::with_capacity(capacity, journal);
+        for _ in 0..capacity {
+            slots.push(PCell::new(AuditRecord { seq: u64::MAX, ..Default::default() }), journal);
+        }
+        Self { slots }
+    }
+
+    /// Registers the ring to record the transactions of pool `A`
+    ///
+    /// The registration is volatile, so it should be done every time the
+    /// pool is opened. A pool has at most one audit ring; attaching a new
+    /// ring replaces the previous one. The ring should reside in the pool.
+    pub fn attach(&self) {
+        assert!(A::valid(self), "The audit ring is not in the pool's valid range");
+        let off = unsafe { A::off_unchecked(self) };
+        let mut registry = registry();
+        if let Some(e) = registry.get(A::name()) {
+            if e.0 == off && e.1 == A::gen() {
+                return;
+            }
+        }
+        // Every record in the slots is committed when the pool is opened
+        let head = self.head();
+        let state = Arc::new(RingState { head: AtomicU64::new(head), done: AtomicU64::new(head) });
+        if registry.insert(A::name(), (off, A::gen(), state)).is_none() {
+            ATTACHED.fetch_add(1, Ordering::Release);
+        }
+    }
+
+    /// Stops recording the transactions of pool `A`, if this ring is attached
+    pub fn detach(&self) {
+        let off = unsafe { A::off_unchecked(self) };
+        let mut registry = registry();
+        if registry.get(A::name()).map_or(false, |e| e.0 == off) {
+            registry.remove(A::name());
+            ATTACHED.fetch_sub(1, Ordering::Release);
+        }
+    }
+
+    /// Returns the retained records, from the oldest to the newest
+    pub fn records(&self, journal: &Journal<A>) -> Vec<AuditRecord> {
+        self.since(0, journal)
+    }
+
+    /// Returns the retained records with a sequence number of at least
+    /// `seq`, from the oldest to the newest
+    ///
+    /// A record of a transaction that panicked while appending it is rolled
+    /// back, and leaves a gap in the sequence numbers.
+    pub fn since(&self, seq: u64, _journal: &Journal<A>) -> Vec<AuditRecord> {
+        let cap = self.slots.len() as u64;
+        let done = self.done();
+        let first = done.saturating_sub(cap).max(seq);
+        (first..done)
+            .map(|seq| self.slots[(seq % cap) as usize].get())
+            .enumerate()
+            .filter(|(i, r)| r.seq == first + *i as u64)
+            .map(|(_, r)| r)
+            .collect()
+    }
+
+    /// Returns the number of retained records
+    pub fn len(&self, _journal: &Journal<A>) -> usize {
+        self.done().min(self.slots.len() as u64) as usize
+    }
+
+    /// Returns the sequence number of the next record
+    pub fn next_seq(&self, _journal: &Journal<A>) -> u64 {
+        self.done()
+    }
+
+    /// Returns the maximum number of records the ring keeps
+    pub fn capacity(&self, _journal: &Journal<A>) -> usize {
+        self.slots.len()
+    }
+
+    /// The sequence number after the newest record in the slots
+    fn head(&self) -> u64 {
+        self.slots.iter()
+            .map(|s| s.get().seq)
+            .filter(|seq| *seq != u64::MAX)
+            .max()
+            .map_or(0, |seq| seq + 1)
+    }
+
+    /// The sequence number after the newest visible record
+    fn done(&self) -> u64 {
+        let off = unsafe { A::off_unchecked(self) };
+        match registry().get(A::name()) {
+            Some((o, gen, state)) if *o == off && *gen == A::gen() => {
+                state.done.load(Ordering::Acquire)
+            }
+            _ => self.head(),
+        }
+    }
+
+    /// Appends `record` with a sequence number which is reserved atomically,
+    /// so that the committing transactions write their own slots without
+    /// holding a lock
+    fn append(&self, mut record: AuditRecord, state: &RingState, journal: &Journal<A>) {
+        let cap = self.slots.len() as u64;
+        let seq = state.head.fetch_add(1, Ordering::AcqRel);
+        let _publish = Publish { state, seq };
+
+        // The slots of the unpublished reservations are not overwritten
+        while state.done.load(Ordering::Acquire) + cap <= seq {
+            std::hint::spin_loop();
+        }
+        record.seq = seq;
+        self.slots[(seq % cap) as usize].set(record, journal);
+    }
+}
+
+impl<A: MemPool> RootObj<A> for AuditRing<A> {
+    fn init(journal: &Journal<A>) -> Self {
+        Self::new(DEFAULT_AUDIT_CAPACITY, journal)
+    }
+}
+
+impl<A: MemPool> RootObjWith<A> for AuditRing<A> {
+    /// The capacity of the ring
+    type Args = usize;
+
+    fn init_with(capacity: usize, journal: &Journal<A>) -> Self {
+        Self::new(capacity, journal)
+    }
+}
+
+/// The number of attached rings, to quickly skip recording
+static ATTACHED: AtomicUsize = AtomicUsize::new(0);
+
+type Registry = HashMap<&'static str, (u64, u32, Arc<RingState>)>;
+
+/// The offset of the attached ring of every pool along with the pool's
+/// generation and the volatile state of the ring
+static mut RINGS: LazyCell<Mutex<Registry>> =
+    LazyCell::new(|| Mutex::new(HashMap::new()));
+
+fn registry() -> std::sync::MutexGuard<'static, Registry> {
+    match unsafe { RINGS.lock() } {
+        Ok(g) => g,
+        Err(p) => p.into_inner()
+    }
+}
+
+/// Appends a record of the committing transaction to the attached ring of
+/// the pool. It is called while the journal can still take logs.
+pub(crate) unsafe fn record<A: MemPool>(journal: &Journal<A>, ts: u64) {
+    if ATTACHED.load(Ordering::Acquire) == 0 {
+        return;
+    }
+    let (ring, state) = match registry().get(A::name()) {
+        Some((off, gen, state)) if *gen == A::gen() => (*off, state.clone()),
+        _ => return,
+    };
+    let (logs, bytes) = journal.logged();
+    let time_ns = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64);
+    let record = AuditRecord {
+        seq: 0,
+        tx: ts,
+        time_ns,
+        thread: std::thread::current().id().as_u64().get(),
+        logs,
+        bytes,
+    };
+    A::get_unchecked::<AuditRing<A>>(ring).append(record, &state, journal);
+}
+
+#[cfg(test)]
+mod test {
+    use crate::default::*;
+    use crate::stm::audit::AuditRing;
+
+    type P = Allocator;
+
+    #[test]
+    fn audit_bounded_retention() {
+        let ring = P::open_with::<AuditRing<P>>("audit.pool", O_CF, 4).unwrap();
+        ring.attach();
+        for i in 0..6u64 {
+            P::transaction(|j| { let _ = Pbox::new(i, j); }).unwrap();
+        }
+        assert!(P::transaction(|j| {
+            let _ = Pbox::new(0u64, j);
+            panic!("abort");
+        }).is_err());
+
+        let records = P::transaction(|j| ring.records(j)).unwrap();
+        assert_eq!(records.len(), 4);
+        assert_eq!(records.iter().map(|r| r.seq).collect::<Vec<_>>(), vec![2, 3, 4, 5]);
+        assert!(records.windows(2).all(|w| w[0].tx < w[1].tx && w[0].time_ns <= w[1].time_ns));
+        assert!(records.iter().all(|r| r.logs > 0));
+        assert_eq!(P::transaction(|j| ring.since(5, j)).unwrap()[0].seq, 5);
+        ring.detach();
+    }
+
+    #[test]
+    fn audit_concurrent_commits() {
+        let ring = P::open_with::<AuditRing<P>>("audit_concurrent.pool", O_CF, 64).unwrap();
+        ring.attach();
+        let threads: Vec<_> = (0..4).map(|i| std::thread::spawn(move || {
+            for _ in 0..10 {
+                P::transaction(|j| { let _ = Pbox::new(i as u64, j); }).unwrap();
+            }
+        })).collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        let records = P::transaction(|j| ring.records(j)).unwrap();
+        assert_eq!(records.iter().map(|r| r.seq).collect::<Vec<_>>(), (0..40).collect::<Vec<_>>());
+        ring.detach();
+    }
+}
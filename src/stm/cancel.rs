@@ -0,0 +1,159 @@
+//! Cooperative cancellation of transactions
+//!
+//! A [`CancellationToken`] can be shared with other threads (e.g., a shutdown
+//! handler) to request a running transaction to stop. The transaction is not
+//! interrupted; instead, it polls [`Journal::is_cancelled()`] at convenient
+//! points. Once the transaction has observed the cancellation, it is rolled
+//! back as soon as the closure returns, and the transaction returns
+//! [`Error::Cancelled`].
+//!
+//! # Examples
+//!
+//! ```
+//! use corundum::default::*;
+//! use corundum::stm::CancellationToken;
+//! use corundum::Error;
+//!
+//! let root = Allocator::open::<PCell<i32>>("foo.pool", O_CF).unwrap();
+//! let token = CancellationToken::new();
+//!
+//! let res = Allocator::cancellable_transaction(&token, |j| {
+//!     for i in 1.. {
+//!         if j.is_cancelled() {
+//!             return;
+//!         }
+//!         root.set(i, j);
+//!         if i == 10 {
+//!             token.cancel(); // e.g., by a shutdown handler
+//!         }
+//!     }
+//! });
+//!
+//! assert_eq!(res, Err(Error::Cancelled));
+//! assert_eq!(root.get(), 0);
+//! ```
+//!
+//! [`Journal::is_cancelled()`]: ../struct.Journal.html#method.is_cancelled
+//! [`Error::Cancelled`]: ../../enum.Error.html#variant.Cancelled
+
+use crate::alloc::MemPool;
+use crate::TxInSafe;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A shareable flag to request cancellation of transactions
+///
+/// See the [module-level documentation](./index.html) for more details.
+#[derive(Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Creates a new token which is not cancelled
+    pub fn new() -> Self {
+        Self(Arc::new(AtomicBool::new(false)))
+    }
+
+    /// Requests cancellation of all transactions using this token
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    /// Returns true if cancellation is requested
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+
+    /// Clears the cancellation request, so that the token can be reused
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+// The token is volatile and does not point to any pool
+unsafe impl TxInSafe for CancellationToken {}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("CancellationToken").field(&self.is_cancelled()).finish()
+    }
+}
+
+struct Scope {
+    pool: &'static str,
+    token: CancellationToken,
+    observed: Cell<bool>,
+}
+
+thread_local! {
+    static CURRENT: RefCell<Vec<Scope>> = RefCell::new(Vec::new());
+}
+
+/// Associates `token` with the transactions of the current thread on pool
+/// `A` while it lives
+pub(crate) struct TokenGuard;
+
+impl TokenGuard {
+    pub(crate) fn enter<A: MemPool>(token: &CancellationToken) -> Self {
+        CURRENT.with(|c| c.borrow_mut().push(Scope {
+            pool: A::name(),
+            token: token.clone(),
+            observed: Cell::new(false),
+        }));
+        TokenGuard
+    }
+}
+
+impl Drop for TokenGuard {
+    fn drop(&mut self) {
+        let _ = CURRENT.try_with(|c| c.borrow_mut().pop());
+    }
+}
+
+/// Polls the token of the current thread's transaction on pool `pool`, and
+/// remembers if the cancellation is observed
+pub(crate) fn poll(pool: &'static str) -> bool {
+    CURRENT.with(|c| {
+        if let Some(s) = c.borrow().iter().rev().find(|s| s.pool == pool) {
+            if s.token.is_cancelled() {
+                s.observed.set(true);
+                return true;
+            }
+        }
+        false
+    })
+}
+
+/// Returns true if the current thread's transaction on pool `pool` has
+/// observed a cancellation
+pub(crate) fn observed(pool: &'static str) -> bool {
+    CURRENT.with(|c| {
+        c.borrow().iter().rev().find(|s| s.pool == pool)
+            .map_or(false, |s| s.observed.get())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alloc::heap::*;
+    use crate::stm::CancellationToken;
+    use crate::Error;
+
+    #[test]
+    fn nested_cancellation() {
+        let token = CancellationToken::new();
+        let res = Heap::cancellable_transaction(&token, |j| {
+            let _ = Heap::transaction(|j| j.is_cancelled());
+            j.is_cancelled()
+        });
+        assert_eq!(res, Ok(false));
+
+        token.cancel();
+        let res = Heap::cancellable_transaction(&token, |_| {
+            let _ = Heap::transaction(|j| j.is_cancelled());
+        });
+        assert_eq!(res, Err(Error::Cancelled));
+        assert_eq!(res.unwrap_err().code(), -16);
+    }
+}
@@ -0,0 +1,436 @@
+//! Change data capture
+//!
+//! A [`ChangeRing`] is a durable ring buffer of [`Change`] records describing
+//! the committed transactions of a pool. Once a ring is [attached] to its
+//! pool, every top-level transaction collects a compact description of what
+//! it modifies: the offset and length of the logged objects, the new
+//! allocations, the deallocations, and the user-defined events added by
+//! [`tag()`]. When the transaction commits, the records are appended to the
+//! ring together with the [commit timestamp] of the transaction as part of
+//! the same transaction. Therefore, the ring never contains the changes of
+//! a transaction that is rolled back, and no committed change is missed
+//! unless the ring overflows.
+//!
+//! A consumer (e.g., an indexing or a replication pipeline) takes the records
+//! in order using [`poll()`] in its own transaction, so that it can atomically
+//! update its own persistent state alongside. Every record has a sequence
+//! number which is contiguous unless older records are overwritten because
+//! the consumer is too slow.
+//!
+//! It is enabled by the `cdc` feature.
+//!
+//! # Examples
+//!
+//! ```
+//! use corundum::default::*;
+//! use corundum::stm::cdc::{self, ChangeKind, ChangeRing};
+//!
+//! type P = Allocator;
+//!
+//! let ring = P::open::<ChangeRing<P>>("foo.pool", O_CF).unwrap();
+//! ring.attach();
+//!
+//! P::transaction(|j| {
+//!     let obj = Pbox::new(10, j);
+//!     cdc::tag(1, obj.off(), 8, j);
+//! }).unwrap();
+//!
+//! let changes = P::transaction(|j| ring.poll(usize::MAX, j)).unwrap();
+//! assert!(changes.iter().any(|(_, c)| c.kind == ChangeKind::Tag(1)));
+//! ```
+//!
+//! [attached]: ./struct.ChangeRing.html#method.attach
+//! [`tag()`]: ./fn.tag.html
+//! [commit timestamp]: ../timestamp/index.html
+//! [`poll()`]: ./struct.ChangeRing.html#method.poll
+
+use crate::alloc::MemPool;
+use crate::cell::{LazyCell, PCell};
+use crate::stm::{Journal, LogEnum};
+use crate::sync::PMutex;
+use crate::vec::Vec as PVec;
+use crate::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The default capacity of a [`ChangeRing`](./struct.ChangeRing.html) which
+/// is created as a root object
+pub const DEFAULT_RING_CAPACITY: usize = 1024;
+
+/// The kind of a [`Change`](./struct.Change.html)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChangeKind {
+    /// The object was modified
+    Write,
+
+    /// The object was allocated
+    Alloc,
+
+    /// The object was deallocated
+    Dealloc,
+
+    /// A user-defined event added by [`tag()`](./fn.tag.html)
+    Tag(u64),
+}
+
+/// A record of a committed change
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Change {
+    /// The commit timestamp of the transaction, or 0 if the pool does not
+    /// support timestamps
+    pub ts: u64,
+
+    /// The kind of change
+    pub kind: ChangeKind,
+
+    /// The offset of the object in the pool
+    pub off: u64,
+
+    /// The size of the object in bytes
+    pub len: usize,
+}
+
+impl Default for Change {
+    fn default() -> Self {
+        Self { ts: 0, kind: ChangeKind::Write, off: u64::MAX, len: 0 }
+    }
+}
+
+/// A record in a slot of the ring
+#[derive(Clone, Copy)]
+struct Record {
+    /// The sequence number of the record, or `u64::MAX` if the slot is empty
+    seq: u64,
+    change: Change,
+}
+
+impl Default for Record {
+    fn default() -> Self {
+        Self { seq: u64::MAX, change: Change::default() }
+    }
+}
+
+/// The volatile state of an attached ring
+///
+/// Committing transactions reserve sequence numbers from `head`, write their
+/// slots in their own journals, and then advance `done` in the order of the
+/// reservations. Records below `done` are visible to [`ChangeRing::poll()`].
+struct RingState {
+    head: AtomicU64,
+    done: AtomicU64,
+}
+
+/// Advances `done` past a reservation even if writing the records panics, so
+/// that the later reservations are not blocked
+struct Publish<'a> {
+    state: &'a RingState,
+    first: u64,
+    n: u64,
+}
+
+impl Drop for Publish<'_> {
+    fn drop(&mut self) {
+        while self.state.done.load(Ordering::Acquire) != self.first {
+            std::hint::spin_loop();
+        }
+        self.state.done.store(self.first + self.n, Ordering::Release);
+    }
+}
+
+/// A durable ring of committed changes
+///
+/// See the [module-level documentation](./index.html) for more details.
+pub struct ChangeRing<A: MemPool> {
+    slots: PVec<PCell<Record, A>, A>,
+
+    /// The sequence number of the oldest record which is not polled yet
+    tail: PMutex<u64, A>,
+}
+
+impl<A: MemPool> ChangeRing<A> {
+    /// Creates a new ring which keeps up to `capacity` records
+    pub fn new(capacity: usize, journal: &Journal<A>) -> Self {
+        assert!(capacity > 0, "a change ring needs a non-zero capacity");
+        let mut slots = PVec::with_capacity(capacity, journal);
+        for _ in 0..capacity {
+            slots.push(PCell::new(Record::default()), journal);
+        }
+        Self { slots, tail: PMutex::new(0) }
+    }
+
+    /// Registers the ring to receive the changes of pool `A`
+    ///
+    /// The registration is volatile, so it should be done every time the
+    /// pool is opened. A pool has at most one ring; attaching a new ring
+    /// replaces the previous one. The ring should reside in the pool.
+    pub fn attach(&self) {
+        assert!(A::valid(self), "The change ring is not in the pool's valid range");
+        let off = unsafe { A::off_unchecked(self) };
+        let mut registry = registry();
+        if let Some(e) = registry.get(A::name()) {
+            if e.0 == off && e.1 == A::gen() {
+                return;
+            }
+        }
+        // Every record in the slots is committed when the pool is opened
+        let head = self.head();
+        let state = Arc::new(RingState { head: AtomicU64::new(head), done: AtomicU64::new(head) });
+        if registry.insert(A::name(), (off, A::gen(), state)).is_none() {
+            ATTACHED.fetch_add(1, Ordering::Release);
+        }
+    }
+
+    /// Stops receiving the changes of pool `A`, if this ring is attached
+    pub fn detach(&self) {
+        let off = unsafe { A::off_unchecked(self) };
+        let mut registry = registry();
+        if registry.get(A::name()).map_or(false, |e| e.0 == off) {
+            registry.remove(A::name());
+            ATTACHED.fetch_sub(1, Ordering::Release);
+        }
+    }
+
+    /// Takes up to `max` of the oldest records along with their sequence
+    /// numbers
+    ///
+    /// The records are removed from the ring when the transaction commits.
+    pub fn poll(&self, max: usize, journal: &Journal<A>) -> Vec<(u64, Change)> {
+        let mut tail = self.tail.lock(journal);
+        let cap = self.slots.len() as u64;
+        let done = self.done();
+        let mut seq = (*tail).max(done.saturating_sub(cap));
+        let mut res = vec![];
+        while seq < done && res.len() < max {
+            let r = self.slots[(seq % cap) as usize].get();
+            // A record of a transaction that panicked while appending is
+            // rolled back, and leaves a gap
+            if r.seq == seq {
+                res.push((seq, r.change));
+            }
+            seq += 1;
+        }
+        if seq != *tail {
+            *tail = seq;
+        }
+        res
+    }
+
+    /// Returns the number of records in the ring
+    pub fn len(&self, journal: &Journal<A>) -> usize {
+        let tail = *self.tail.lock(journal);
+        let done = self.done();
+        (done - tail.max(done.saturating_sub(self.slots.len() as u64))) as usize
+    }
+
+    /// Returns the maximum number of records the ring keeps
+    pub fn capacity(&self, _journal: &Journal<A>) -> usize {
+        self.slots.len()
+    }
+
+    /// The sequence number after the newest record in the slots
+    fn head(&self) -> u64 {
+        self.slots.iter()
+            .map(|s| s.get().seq)
+            .filter(|seq| *seq != u64::MAX)
+            .max()
+            .map_or(0, |seq| seq + 1)
+    }
+
+    /// The sequence number after the newest visible record
+    fn done(&self) -> u64 {
+        let off = unsafe { A::off_unchecked(self) };
+        match registry().get(A::name()) {
+            Some((o, gen, state)) if *o == off && *gen == A::gen() => {
+                state.done.load(Ordering::Acquire)
+            }
+            _ => self.head(),
+        }
+    }
+
+    /// Appends `changes` of a transaction which is committing with
+    /// timestamp `ts`, and overwrites the oldest records if the ring is full
+    ///
+    /// The sequence numbers are reserved atomically, so that the committing
+    /// transactions write their own slots without holding a lock.
+    fn append(&self, mut changes: Vec<Change>, ts: u64, state: &RingState, journal: &Journal<A>) {
+        let cap = self.slots.len() as u64;
+        let own = [
+            (unsafe { A::off_unchecked(self) }, mem::size_of::<Self>()),
+            (self.slots.off(), cap as usize * mem::size_of::<PCell<Record, A>>()),
+        ];
+        // The ring does not capture its own updates
+        changes.retain(|c| !own.iter().any(|(off, len)| c.off >= *off && c.off < *off + *len as u64));
+        if changes.len() as u64 > cap {
+            changes.drain(..changes.len() - cap as usize);
+        }
+        if changes.is_empty() {
+            return;
+        }
+        let n = changes.len() as u64;
+        let first = state.head.fetch_add(n, Ordering::AcqRel);
+        let _publish = Publish { state, first, n };
+
+        // The slots of the unpublished reservations are not overwritten
+        while state.done.load(Ordering::Acquire) + cap < first + n {
+            std::hint::spin_loop();
+        }
+        for (seq, mut change) in (first..).zip(changes) {
+            change.ts = ts;
+            self.slots[(seq % cap) as usize].set(Record { seq, change }, journal);
+        }
+    }
+}
+
+impl<A: MemPool> RootObj<A> for ChangeRing<A> {
+    fn init(journal: &Journal<A>) -> Self {
+        Self::new(DEFAULT_RING_CAPACITY, journal)
+    }
+}
+
+/// The number of attached rings, to quickly skip collecting the changes
+static ATTACHED: AtomicUsize = AtomicUsize::new(0);
+
+type Registry = HashMap<&'static str, (u64, u32, Arc<RingState>)>;
+
+/// The offset of the attached ring of every pool along with the pool's
+/// generation and the volatile state of the ring
+static mut RINGS: LazyCell<Mutex<Registry>> =
+    LazyCell::new(|| Mutex::new(HashMap::new()));
+
+fn registry() -> std::sync::MutexGuard<'static, Registry> {
+    unsafe { RINGS.lock().unwrap() }
+}
+
+thread_local! {
+    static PENDING: RefCell<Vec<(&'static str, Vec<Change>)>> = RefCell::new(Vec::new());
+}
+
+fn push(pool: &'static str, change: Change) {
+    PENDING.with(|p| {
+        let mut p = p.borrow_mut();
+        if let Some(e) = p.iter_mut().find(|e| e.0 == pool) {
+            e.1.push(change);
+        } else {
+            p.push((pool, vec![change]));
+        }
+    })
+}
+
+fn take(pool: &'static str) -> Vec<Change> {
+    PENDING.with(|p| {
+        let mut p = p.borrow_mut();
+        match p.iter().position(|e| e.0 == pool) {
+            Some(i) => p.swap_remove(i).1,
+            None => Vec::new(),
+        }
+    })
+}
+
+/// Adds a user-defined event to the changes of the current transaction
+///
+/// `off` and `len` may describe the object which the event is about. The
+/// event is discarded if the transaction does not commit, or if no ring is
+/// attached to the pool.
+pub fn tag<A: MemPool>(tag: u64, off: u64, len: usize, _journal: &Journal<A>) {
+    if ATTACHED.load(Ordering::Acquire) != 0 {
+        push(A::name(), Change { ts: 0, kind: ChangeKind::Tag(tag), off, len });
+    }
+}
+
+/// Collects the change described by a new log in the current transaction
+pub(crate) fn record_log<A: MemPool>(log: &LogEnum) {
+    if ATTACHED.load(Ordering::Acquire) == 0 {
+        return;
+    }
+    let (kind, off, len) = match *log {
+        LogEnum::DataLog(off, _, len) => (ChangeKind::Write, off, len),
+        LogEnum::InlineLog(off, _, len) => (ChangeKind::Write, off, len as usize),
+        LogEnum::DropOnFailure(off, len) => (ChangeKind::Alloc, off, len),
+        LogEnum::DropOnCommit(off, len) => (ChangeKind::Dealloc, off, len),
+        _ => return,
+    };
+    push(A::name(), Change { ts: 0, kind, off, len });
+}
+
+/// Appends the collected changes of the current transaction to the attached
+/// ring of the pool. It is called when the top-level transaction is
+/// committing, while the journal can still take logs.
+pub(crate) unsafe fn flush<A: MemPool>(journal: &Journal<A>, ts: u64) {
+    let changes = take(A::name());
+    if changes.is_empty() {
+        return;
+    }
+    let (ring, state) = match registry().get(A::name()) {
+        Some((off, gen, state)) if *gen == A::gen() => (*off, state.clone()),
+        _ => return,
+    };
+    A::get_unchecked::<ChangeRing<A>>(ring).append(changes, ts, &state, journal);
+
+    // Discards the ring's own updates
+    take(A::name());
+}
+
+/// Discards the collected changes of the current transaction
+pub(crate) fn discard(pool: &'static str) {
+    take(pool);
+}
+
+#[cfg(test)]
+mod test {
+    use crate::default::*;
+    use crate::stm::cdc::{self, ChangeKind, ChangeRing};
+
+    type P = Allocator;
+
+    #[test]
+    fn cdc_committed_only() {
+        let ring = P::open::<ChangeRing<P>>("cdc.pool", O_CF).unwrap();
+        ring.attach();
+        P::transaction(|j| { ring.poll(usize::MAX, j); }).unwrap();
+
+        let ts = P::timestamped_transaction(|j| {
+            let b = Pbox::new(1u64, j);
+            cdc::tag(7, b.off(), 8, j);
+        }).unwrap().commit_ts().unwrap();
+        assert!(P::transaction(|j| {
+            let b = Pbox::new(2u64, j);
+            cdc::tag(8, b.off(), 8, j);
+            panic!("abort");
+        }).is_err());
+
+        let changes = P::transaction(|j| ring.poll(usize::MAX, j)).unwrap();
+        assert!(changes.iter().all(|(_, c)| c.ts == ts));
+        assert!(changes.iter().any(|(_, c)| c.kind == ChangeKind::Alloc));
+        assert!(changes.iter().any(|(_, c)| c.kind == ChangeKind::Tag(7)));
+        assert!(!changes.iter().any(|(_, c)| c.kind == ChangeKind::Tag(8)));
+        assert!(changes.windows(2).all(|w| w[1].0 == w[0].0 + 1));
+        assert_eq!(P::transaction(|j| ring.len(j)).unwrap(), 0);
+        ring.detach();
+    }
+
+    #[test]
+    fn cdc_concurrent_commits() {
+        let ring = P::open::<ChangeRing<P>>("cdc_concurrent.pool", O_CF).unwrap();
+        ring.attach();
+
+        let threads: Vec<_> = (0..4).map(|t| std::thread::spawn(move || {
+            for _ in 0..10 {
+                P::transaction(|j| cdc::tag(t, u64::MAX - 1, 0, j)).unwrap();
+            }
+        })).collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        let changes = P::transaction(|j| ring.poll(usize::MAX, j)).unwrap();
+        assert_eq!(changes.len(), 40);
+        assert!(changes.windows(2).all(|w| w[1].0 == w[0].0 + 1));
+        for t in 0..4 {
+            assert_eq!(changes.iter().filter(|(_, c)| c.kind == ChangeKind::Tag(t)).count(), 10);
+        }
+        ring.detach();
+    }
+}
@@ -90,7 +90,7 @@ fn new_chaperon(filename: &str) -> Result<*mut Chaperon> {
     };
     let tid = thread::current().id();
     if clist.contains_key(&tid) {
-        return Err("Another chaperoned transaction is open".to_string());
+        return Err(crate::Error::ChaperonBusy);
     }
     let c = Chaperon::new(filename.to_string())
         .expect(&format!("could not create chaperon file `{}`", filename));
@@ -362,16 +362,28 @@ impl Chaperon {
         F: panic::UnwindSafe,
         T: panic::UnwindSafe + TxOutSafe,
     {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("chaperon_session", file = filename).entered();
+
         let chaperon = unsafe { &mut *new_chaperon(filename)? };
         let res = panic::catch_unwind(|| body());
-        if let Ok(res) = res {
-            chaperon.execute_delayed_commits();
-            drop_chaperon();
-            Ok(res)
-        } else {
-            chaperon.execute_delayed_rollbacks();
-            drop_chaperon();
-            Err("Unsuccessful chaperoned transaction".to_string())
+        match res {
+            Ok(res) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("commit session");
+
+                chaperon.execute_delayed_commits();
+                drop_chaperon();
+                Ok(res)
+            }
+            Err(e) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("abort session");
+
+                chaperon.execute_delayed_rollbacks();
+                drop_chaperon();
+                Err(crate::Error::from_panic(&*e))
+            }
         }
     }
 }
@@ -0,0 +1,201 @@
+//! The memory allocated by the running transaction
+//!
+//! The memory which a transaction allocates is dropped on abort or failure,
+//! so its old contents never need to be restored. The journal keeps the
+//! ranges of the allocations of the running transaction, and the loggers
+//! skip the objects which lie in them. This cuts the logs of the code which
+//! builds new objects, e.g., the constructors which fill a new `Pbox` or a
+//! new buffer of a `PVec`.
+//!
+//! A skipped log does not flush the object at commit; instead, the skipped
+//! ranges are kept and flushed when the transaction commits. The ranges are
+//! volatile and belong to the thread which runs the transaction.
+
+use crate::alloc::MemPool;
+use crate::ll::*;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+
+struct Fresh {
+    pool: &'static str,
+
+    /// The allocated ranges, from the start to the end offset
+    allocated: BTreeMap<u64, u64>,
+
+    /// The ranges which are changed without logs, and have to be flushed at
+    /// commit
+    dirty: BTreeMap<u64, usize>,
+}
+
+thread_local! {
+    static CURRENT: RefCell<Vec<Fresh>> = RefCell::new(Vec::new());
+    static KEEP: Cell<usize> = Cell::new(0);
+}
+
+/// A scope in which the values are moved out of the pool by bitwise copy
+///
+/// The pool keeps the old bits while the scope lives, and the rollback
+/// restores them if the thread unwinds. So, the blocks which a moved value
+/// owns, and which the transaction did not allocate, should not be dropped
+/// while unwinding; otherwise, the restored value would point to freed
+/// memory.
+pub(crate) struct Keep;
+
+impl Keep {
+    #[inline]
+    pub(crate) fn enter() -> Self {
+        KEEP.with(|k| k.set(k.get() + 1));
+        Keep
+    }
+}
+
+impl Drop for Keep {
+    #[inline]
+    fn drop(&mut self) {
+        let _ = KEEP.try_with(|k| k.set(k.get() - 1));
+    }
+}
+
+/// Returns true if the block at `off` which is dropped while unwinding should
+/// be kept, i.e., it is dropped in a [`Keep`] scope and is not allocated by
+/// the current thread's transaction on pool `A`
+pub(crate) fn keep<A: MemPool>(off: u64, len: usize) -> bool {
+    if KEEP.try_with(|k| k.get()).unwrap_or(0) == 0 {
+        return false;
+    }
+    !CURRENT.try_with(|c| {
+        c.borrow().iter().find(|f| f.pool == A::name()).map_or(false, |f| {
+            f.allocated.range(..=off).next_back()
+                .map_or(false, |(_, end)| off + len as u64 <= *end)
+        })
+    }).unwrap_or(false)
+}
+
+/// Records a new allocation of the current thread's transaction on pool `A`
+pub(crate) fn allocated<A: MemPool>(off: u64, len: usize) {
+    let _ = CURRENT.try_with(|c| {
+        let mut c = c.borrow_mut();
+        let pos = match c.iter().position(|f| f.pool == A::name()) {
+            Some(pos) => pos,
+            None => {
+                c.push(Fresh {
+                    pool: A::name(),
+                    allocated: BTreeMap::new(),
+                    dirty: BTreeMap::new(),
+                });
+                c.len() - 1
+            }
+        };
+        c[pos].allocated.insert(off, off + len as u64);
+    });
+}
+
+/// Returns true if `len` bytes at `off` are allocated by the current
+/// thread's transaction on pool `A`, in which case they are marked to be
+/// flushed at commit
+pub(crate) fn skip_log<A: MemPool>(off: u64, len: usize) -> bool {
+    let skip = CURRENT.try_with(|c| {
+        let mut c = c.borrow_mut();
+        if let Some(f) = c.iter_mut().find(|f| f.pool == A::name()) {
+            let fresh = f.allocated.range(..=off).next_back()
+                .map_or(false, |(_, end)| off + len as u64 <= *end);
+            if fresh {
+                let dirty = f.dirty.entry(off).or_insert(0);
+                *dirty = usize::max(*dirty, len);
+            }
+            fresh
+        } else {
+            false
+        }
+    }).unwrap_or(false);
+
+    // The change data capture still sees the write
+    #[cfg(feature = "cdc")] {
+        if skip {
+            crate::stm::cdc::record_log::<A>(&crate::stm::LogEnum::DataLog(off, u64::MAX, len));
+        }
+    }
+    skip
+}
+
+/// Flushes the ranges which are changed without logs, and forgets the
+/// allocations of the current thread's transaction on pool `A`
+///
+/// It is called on the thread of the transaction when it commits, so the
+/// flushes are fenced here rather than by the thread which finishes the
+/// commit.
+pub(crate) fn flush<A: MemPool>() {
+    if let Some(f) = take::<A>() {
+        if !f.dirty.is_empty() {
+            for (off, len) in f.dirty {
+                unsafe {
+                    persist_with_log::<u8, A>(A::get_mut_unchecked(off), len, false);
+                }
+            }
+            sfence();
+        }
+    }
+}
+
+/// Forgets the allocations of the current thread's transaction on pool `A`
+pub(crate) fn discard<A: MemPool>() {
+    let _ = take::<A>();
+}
+
+fn take<A: MemPool>() -> Option<Fresh> {
+    CURRENT.try_with(|c| {
+        let mut c = c.borrow_mut();
+        let pos = c.iter().position(|f| f.pool == A::name())?;
+        Some(c.swap_remove(pos))
+    }).unwrap_or(None)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::default::*;
+    use crate::ptr::Ptr;
+    use crate::stm::{Journal, LogEnum, Logger, Notifier};
+
+    type P = Allocator;
+
+    fn data_logs(j: &Journal<P>) -> usize {
+        let mut n = 0;
+        j.for_each_log(|log| if let LogEnum::DataLog(..) | LogEnum::InlineLog(..) = log {
+            n += 1;
+        });
+        n
+    }
+
+    #[test]
+    fn new_objects_are_not_logged() {
+        let root = P::open::<PCell<u64>>("fresh_test.pool", O_CF).unwrap();
+        P::transaction(|j| {
+            let new = Pbox::new(PCell::new(0u64), j);
+            new.set(1, j);
+            assert_eq!(data_logs(j), 0);
+            root.set(1, j);
+            assert_eq!(data_logs(j), 1);
+            assert_eq!(new.get() + root.get(), 2);
+        }).unwrap();
+        assert!(P::transaction(|j| {
+            let new = Pbox::new(PCell::new(0u64), j);
+            new.set(2, j);
+            root.set(2, j);
+            panic!("abort");
+        }).is_err());
+        assert_eq!(root.get(), 1);
+    }
+
+    #[test]
+    fn skipped_logs_set_the_flag() {
+        let _root = P::open::<PCell<u64>>("fresh_flag_test.pool", O_CF).unwrap();
+        P::transaction(|j| {
+            let new = Pbox::new((0u64, 0u8), j);
+            unsafe {
+                new.0.create_log(j, Notifier::NonAtomic(Ptr::from_ref(&new.1)));
+            }
+            assert_eq!(data_logs(j), 0);
+            assert_eq!(new.1, 1);
+        }).unwrap();
+    }
+}
@@ -0,0 +1,199 @@
+//! Group commit for concurrent transactions
+//!
+//! A committing transaction flushes its changes, and issues a fence before
+//! and after it sets the commit flag. At high thread counts, these ordering
+//! points limit the throughput. With group commit, the transactions which
+//! commit at about the same time form a batch. The first one becomes the
+//! leader: it waits for the batching window, or until all running
+//! transactions of the pool have joined, then flushes the changes of the
+//! whole batch and sets their commit flags with only two fences. The other
+//! members wait for the leader, and then clear their own journals and
+//! release their locks, as usual.
+//!
+//! The members of a batch hold their locks until the batch is durable;
+//! hence, they are independent of each other, and a crash in the middle of a
+//! batch leaves each of them either committed or rolled back.
+//!
+//! Group commit is disabled by default. It is enabled per pool with
+//! [`MemPoolTraits::set_group_commit_window()`]. A longer window makes
+//! larger batches, at the cost of commit latency.
+//!
+//! # Examples
+//!
+//! ```
+//! use corundum::default::*;
+//! use std::time::Duration;
+//!
+//! type P = Allocator;
+//!
+//! let root = P::open::<Parc<PMutex<u64>>>("group.pool", O_CF).unwrap();
+//! P::set_group_commit_window(Duration::from_micros(50));
+//!
+//! let threads: Vec<_> = (0..4).map(|_| {
+//!     let root = Parc::demote(&root);
+//!     std::thread::spawn(move || {
+//!         for _ in 0..10 {
+//!             P::transaction(|j| {
+//!                 let root = root.promote(j).unwrap();
+//!                 *root.lock(j) += 1;
+//!             }).unwrap();
+//!         }
+//!     })
+//! }).collect();
+//! for t in threads {
+//!     t.join().unwrap();
+//! }
+//!
+//! P::transaction(|j| assert_eq!(*root.lock(j), 40)).unwrap();
+//! P::set_group_commit_window(Duration::from_secs(0));
+//! ```
+//!
+//! [`MemPoolTraits::set_group_commit_window()`]: ../../alloc/trait.MemPoolTraits.html#method.set_group_commit_window
+
+use crate::alloc::MemPool;
+use crate::cell::LazyCell;
+use crate::ll::sfence;
+use crate::ptr::Ptr;
+use crate::stm::Journal;
+use crate::utils;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+struct Batch {
+    window: Duration,
+    queue: Vec<u64>,
+    leader: bool,
+    collecting: u64,
+    done: u64,
+}
+
+struct Group {
+    batch: Mutex<Batch>,
+    done: Condvar,
+    round: Mutex<()>,
+}
+
+/// The number of pools with group commit, to keep the commit path free of
+/// the registry lock
+static ENABLED: AtomicUsize = AtomicUsize::new(0);
+
+static mut GROUPS: LazyCell<Mutex<HashMap<&'static str, Arc<Group>>>> =
+    LazyCell::new(|| Mutex::new(HashMap::new()));
+
+fn lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    match m.lock() {
+        Ok(g) => g,
+        Err(p) => p.into_inner()
+    }
+}
+
+fn group(pool: &'static str) -> Arc<Group> {
+    let mut map = match unsafe { GROUPS.lock() } {
+        Ok(g) => g,
+        Err(p) => p.into_inner()
+    };
+    map.entry(pool).or_insert_with(|| Arc::new(Group {
+        batch: Mutex::new(Batch {
+            window: Duration::from_secs(0),
+            queue: vec![],
+            leader: false,
+            collecting: 1,
+            done: 0,
+        }),
+        done: Condvar::new(),
+        round: Mutex::new(()),
+    })).clone()
+}
+
+/// Sets the batching window of pool `A`; zero disables group commit
+pub(crate) fn set_window<A: MemPool>(window: Duration) {
+    let g = group(A::name());
+    let mut b = lock(&g.batch);
+    let was_enabled = b.window != Duration::from_secs(0);
+    let enabled = window != Duration::from_secs(0);
+    b.window = window;
+    if enabled && !was_enabled {
+        ENABLED.fetch_add(1, Ordering::AcqRel);
+    } else if was_enabled && !enabled {
+        ENABLED.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Returns the batching window of pool `A`
+pub(crate) fn window<A: MemPool>() -> Duration {
+    if ENABLED.load(Ordering::Acquire) == 0 {
+        Duration::from_secs(0)
+    } else {
+        lock(&group(A::name()).batch).window
+    }
+}
+
+/// Commits the top-level transaction of the current thread as a member of a
+/// batch, if group commit is enabled for the pool. It returns `false` if the
+/// journal should be committed individually. The journal is not cleared.
+pub(crate) unsafe fn commit<A: MemPool>(journal: &mut Journal<A>) -> bool {
+    if ENABLED.load(Ordering::Acquire) == 0 {
+        return false;
+    }
+    let g = group(A::name());
+    let window = lock(&g.batch).window;
+    if window == Duration::from_secs(0) {
+        return false;
+    }
+
+    journal.prepare_commit();
+    journal.drop_on_commit(
+        #[cfg(feature = "check_double_free")]
+        &mut *A::dealloc_history()
+    );
+
+    let mut b = lock(&g.batch);
+    b.queue.push(A::off_unchecked(journal));
+    let id = b.collecting;
+    if b.leader {
+        while b.done < id {
+            b = match g.done.wait(b) {
+                Ok(g) => g,
+                Err(p) => p.into_inner()
+            };
+        }
+        return true;
+    }
+    b.leader = true;
+    drop(b);
+
+    // Waits for the other running transactions to join, but not longer
+    // than the window
+    let deadline = Instant::now() + window;
+    while Instant::now() < deadline {
+        let joined = lock(&g.batch).queue.len();
+        if joined >= A::journals(|journals| journals.len()) {
+            break;
+        }
+        std::thread::yield_now();
+    }
+
+    let _round = lock(&g.round);
+    let batch = {
+        let mut b = lock(&g.batch);
+        b.leader = false;
+        b.collecting += 1;
+        std::mem::take(&mut b.queue)
+    };
+    let member = |off: u64| utils::as_mut(Ptr::<Journal<A>, A>::from_off_unchecked(off).as_ptr());
+    for off in &batch {
+        member(*off).flush_data();
+    }
+    sfence();
+    for off in &batch {
+        member(*off).mark_committed();
+    }
+    sfence();
+
+    let mut b = lock(&g.batch);
+    b.done = id;
+    g.done.notify_all();
+    true
+}
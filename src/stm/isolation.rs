@@ -0,0 +1,377 @@
+//! Isolation levels of transactions
+//!
+//! The exclusive guards of [`PMutex`] and [`PRwLock`] are held until the
+//! transaction is done, so the data they protect is never seen in an
+//! uncommitted state. The shared guards of [`PRwLock::read()`], however, are
+//! released as soon as they are dropped, which lets readers of different
+//! transactions proceed concurrently with each other and with upgradable
+//! readers. The isolation level of a transaction determines what these
+//! short-lived reads guarantee:
+//!
+//! * [`Isolation::ReadCommitted`] (default): every read observes committed
+//!   data, but two reads of the same lock may observe different commits.
+//! * [`Isolation::Serializable`]: every lock has a version which changes
+//!   when a writer takes it. The transaction remembers the versions it has
+//!   read, and validates them when it commits. If a concurrent transaction
+//!   has written to any of them in the meantime, the transaction is rolled
+//!   back and returns [`Error::SerializationFailure`], so it can be retried.
+//!
+//! The same applies to [`PRefCell`], which may be shared by the readers of a
+//! lock. Since a cell has no room for a version, the cells are versioned in
+//! groups by their addresses. A transaction which mutably borrows a cell
+//! holds its group until it is done, and then changes its version. A
+//! serializable transaction validates the groups of the cells it has
+//! borrowed immutably or [read], and fails if any of them is held or changed
+//! by another transaction. Two cells may share a group, so a transaction may
+//! fail because of a change to an unrelated cell.
+//!
+//! The level is chosen per transaction with
+//! [`MemPoolTraits::transaction_with_isolation()`]. It only applies to
+//! top-level transactions; a nested transaction follows the level of its
+//! parent. Chaperoned transactions are not validated.
+//!
+//! # Examples
+//!
+//! ```
+//! use corundum::default::*;
+//! use corundum::stm::isolation::Isolation;
+//! use corundum::Error;
+//!
+//! type P = Allocator;
+//!
+//! let index = P::open::<Parc<PRwLock<PVec<u64>>>>("isolation.pool", O_CF).unwrap();
+//!
+//! let sum = loop {
+//!     let res = P::transaction_with_isolation(Isolation::Serializable, |j| {
+//!         let a: u64 = index.read(j).iter().sum();
+//!         let b = index.read(j).len() as u64;
+//!         a + b // Both reads observe the same commit
+//!     });
+//!     match res {
+//!         Err(Error::SerializationFailure) => continue,
+//!         res => break res.unwrap(),
+//!     }
+//! };
+//! assert_eq!(sum, 0);
+//! ```
+//!
+//! [`PRefCell`]: ../../cell/struct.PRefCell.html
+//! [read]: ../../cell/struct.PRefCell.html#method.read
+//! [`PMutex`]: ../../sync/struct.PMutex.html
+//! [`PRwLock`]: ../../sync/struct.PRwLock.html
+//! [`PRwLock::read()`]: ../../sync/struct.PRwLock.html#method.read
+//! [`Error::SerializationFailure`]: ../../enum.Error.html#variant.SerializationFailure
+//! [`MemPoolTraits::transaction_with_isolation()`]: ../../alloc/trait.MemPoolTraits.html#method.transaction_with_isolation
+
+use crate::alloc::MemPool;
+use crate::stm::Journal;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// The isolation level of a transaction
+///
+/// See the [module-level documentation](./index.html) for more details.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Isolation {
+    /// Reads observe committed data (default)
+    ReadCommitted,
+
+    /// Reads are validated at commit time, as if the transactions ran one
+    /// after another
+    Serializable,
+}
+
+impl Default for Isolation {
+    fn default() -> Self {
+        Isolation::ReadCommitted
+    }
+}
+
+/// A versioned object whose reads can be validated
+pub(crate) trait Versioned {
+    /// Checks that the version is still `seen`. If so, it may keep the
+    /// writers of other transactions out, and returns `Some(true)` if it has
+    /// to be [unpinned](#tymethod.unpin) afterwards.
+    fn pin(&self, seen: u64) -> Option<bool>;
+
+    /// Lets the writers in again
+    fn unpin(&self);
+}
+
+struct Scope {
+    pool: &'static str,
+    level: Isolation,
+    reads: Vec<(*const dyn Versioned, u64)>,
+
+    /// The groups of the cells which are read, and their versions
+    cells: Vec<(usize, u64)>,
+}
+
+/// The number of groups of `PRefCell`s
+const GROUPS: usize = 1024;
+
+/// A group of `PRefCell`s which share a version
+struct Group {
+    version: AtomicU64,
+
+    /// The number of running transactions which have written to the group
+    writers: AtomicUsize,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NEW_GROUP: Group = Group { version: AtomicU64::new(0), writers: AtomicUsize::new(0) };
+
+static CELLS: [Group; GROUPS] = [NEW_GROUP; GROUPS];
+
+thread_local! {
+    static CURRENT: RefCell<Vec<Scope>> = RefCell::new(Vec::new());
+
+    /// The groups which the running transaction of this thread on every pool
+    /// has written, as bit sets
+    static WRITES: RefCell<Vec<(&'static str, [u64; GROUPS / 64])>> = RefCell::new(Vec::new());
+}
+
+fn group_of(addr: *const u8) -> usize {
+    (addr as usize >> 3) % GROUPS
+}
+
+fn written<A: MemPool>(g: usize) -> bool {
+    WRITES.try_with(|w| {
+        w.borrow().iter().find(|(p, _)| *p == A::name())
+            .map_or(false, |(_, bits)| bits[g / 64] & (1 << (g % 64)) != 0)
+    }).unwrap_or(false)
+}
+
+/// Sets the isolation level of the next top-level transaction of the current
+/// thread on pool `A` while it lives
+pub(crate) struct IsolationGuard(bool);
+
+impl IsolationGuard {
+    pub(crate) fn enter<A: MemPool>(level: Isolation) -> Self {
+        let nested = unsafe {
+            Journal::<A>::current(false).map_or(false, |j| *j.1 > 0)
+        };
+        if !nested {
+            CURRENT.with(|c| c.borrow_mut().push(Scope {
+                pool: A::name(),
+                level,
+                reads: vec![],
+                cells: vec![],
+            }));
+        }
+        IsolationGuard(!nested)
+    }
+}
+
+impl Drop for IsolationGuard {
+    fn drop(&mut self) {
+        if self.0 {
+            let _ = CURRENT.try_with(|c| c.borrow_mut().pop());
+        }
+    }
+}
+
+/// Remembers that the current transaction on pool `A` has read version
+/// `seen` of `obj`, if it is serializable
+pub(crate) fn record<A: MemPool>(obj: &(dyn Versioned + 'static), seen: u64) {
+    let _ = CURRENT.try_with(|c| {
+        let mut c = c.borrow_mut();
+        if let Some(s) = c.iter_mut().rev().find(|s| s.pool == A::name()) {
+            let obj = obj as *const dyn Versioned;
+            if s.level == Isolation::Serializable
+                && !s.reads.iter().any(|(o, _)| *o as *const u8 == obj as *const u8) {
+                s.reads.push((obj, seen));
+            }
+        }
+    });
+}
+
+/// Moves the reads of `obj` at version `old` to version `new`, when the
+/// current transaction changes the version itself
+pub(crate) fn rebase(obj: &(dyn Versioned + 'static), old: u64, new: u64) {
+    let _ = CURRENT.try_with(|c| {
+        let obj = obj as *const dyn Versioned as *const u8;
+        for s in c.borrow_mut().iter_mut() {
+            for r in s.reads.iter_mut() {
+                if r.0 as *const u8 == obj && r.1 == old {
+                    r.1 = new;
+                }
+            }
+        }
+    });
+}
+
+/// Remembers that the current transaction on pool `A` has read the
+/// `PRefCell` at `addr`, if it is serializable
+pub(crate) fn read_cell<A: MemPool>(addr: *const u8) {
+    let _ = CURRENT.try_with(|c| {
+        let mut c = c.borrow_mut();
+        if let Some(s) = c.iter_mut().rev().find(|s| s.pool == A::name()) {
+            let g = group_of(addr);
+            if s.level == Isolation::Serializable && !s.cells.iter().any(|(o, _)| *o == g) {
+                s.cells.push((g, CELLS[g].version.load(Ordering::SeqCst)));
+            }
+        }
+    });
+}
+
+/// Holds the group of the `PRefCell` at `addr` until the current transaction
+/// on pool `A` is done
+pub(crate) fn write_cell<A: MemPool>(addr: *const u8) {
+    let g = group_of(addr);
+    let _ = WRITES.try_with(|w| {
+        let mut w = w.borrow_mut();
+        let pos = match w.iter().position(|(p, _)| *p == A::name()) {
+            Some(pos) => pos,
+            None => {
+                w.push((A::name(), [0; GROUPS / 64]));
+                w.len() - 1
+            }
+        };
+        let bits = &mut w[pos].1;
+        if bits[g / 64] & (1 << (g % 64)) == 0 {
+            bits[g / 64] |= 1 << (g % 64);
+            CELLS[g].writers.fetch_add(1, Ordering::SeqCst);
+        }
+    });
+}
+
+/// Releases the groups which the current transaction on pool `A` has
+/// written, after it is committed or rolled back, and changes their versions
+pub(crate) fn release<A: MemPool>() {
+    let _ = WRITES.try_with(|w| {
+        if let Some((_, bits)) = w.borrow_mut().iter_mut().find(|(p, _)| *p == A::name()) {
+            for (i, word) in bits.iter_mut().enumerate() {
+                while *word != 0 {
+                    let g = i * 64 + word.trailing_zeros() as usize;
+                    CELLS[g].version.fetch_add(1, Ordering::SeqCst);
+                    CELLS[g].writers.fetch_sub(1, Ordering::SeqCst);
+                    *word &= *word - 1;
+                }
+            }
+        }
+    });
+}
+
+/// Keeps the validated objects pinned until the transaction is committed
+pub(crate) struct Pinned(Vec<*const dyn Versioned>);
+
+impl Drop for Pinned {
+    fn drop(&mut self) {
+        for obj in &self.0 {
+            unsafe { (**obj).unpin(); }
+        }
+    }
+}
+
+/// Validates the reads of the current top-level transaction on pool `A`
+pub(crate) fn validate<A: MemPool>() -> Result<Pinned, ()> {
+    let top = unsafe {
+        Journal::<A>::current(false).map_or(false, |j| *j.1 == 1)
+    };
+    let mut pinned = Pinned(vec![]);
+    if !top {
+        return Ok(pinned);
+    }
+    CURRENT.with(|c| {
+        let c = c.borrow();
+        if let Some(s) = c.iter().rev().find(|s| s.pool == A::name()) {
+            for (obj, seen) in &s.reads {
+                match unsafe { (**obj).pin(*seen) } {
+                    Some(true) => pinned.0.push(*obj),
+                    Some(false) => {}
+                    None => return Err(()),
+                }
+            }
+            for (g, seen) in &s.cells {
+                let own = written::<A>(*g) as usize;
+                if CELLS[*g].writers.load(Ordering::SeqCst) != own
+                    || CELLS[*g].version.load(Ordering::SeqCst) != *seen {
+                    return Err(());
+                }
+            }
+        }
+        Ok(())
+    })?;
+    Ok(pinned)
+}
+
+#[cfg(test)]
+mod test {
+    use super::Isolation;
+    use crate::default::*;
+    use crate::Error;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    type P = Allocator;
+
+    static STAGE: AtomicUsize = AtomicUsize::new(0);
+    static CELL_STAGE: AtomicUsize = AtomicUsize::new(0);
+
+    fn wait(stage: usize) {
+        while STAGE.load(Ordering::Acquire) != stage {
+            std::thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn serializable_read_conflict() {
+        let root = P::open::<Parc<PRwLock<u64>>>("isolation_test.pool", O_CF).unwrap();
+        let other = Parc::demote(&root);
+        let writer = std::thread::spawn(move || {
+            wait(1);
+            P::transaction(|j| {
+                let lock = other.promote(j).unwrap();
+                *lock.write(j) += 1;
+            }).unwrap();
+            STAGE.store(2, Ordering::Release);
+        });
+
+        let res = P::transaction_with_isolation(Isolation::Serializable, |j| {
+            let v = *root.read(j);
+            STAGE.store(1, Ordering::Release);
+            wait(2);
+            v
+        });
+        writer.join().unwrap();
+        assert_eq!(res, Err(Error::SerializationFailure));
+
+        let res = P::transaction_with_isolation(Isolation::Serializable, |j| *root.read(j));
+        assert_eq!(res, Ok(1));
+    }
+
+    #[test]
+    fn serializable_cell_conflict() {
+        let root = P::open::<Parc<PRwLock<PRefCell<u64>>>>("isolation_cell_test.pool", O_CF).unwrap();
+        let other = Parc::demote(&root);
+        let writer = std::thread::spawn(move || {
+            while CELL_STAGE.load(Ordering::Acquire) != 1 {
+                std::thread::yield_now();
+            }
+            P::transaction(|j| {
+                let lock = other.promote(j).unwrap();
+                *lock.read(j).borrow_mut(j) += 1;
+            }).unwrap();
+            CELL_STAGE.store(2, Ordering::Release);
+        });
+
+        // The lock is only read, so the conflict is on the cell
+        let res = P::transaction_with_isolation(Isolation::Serializable, |j| {
+            let v = *root.read(j).borrow();
+            CELL_STAGE.store(1, Ordering::Release);
+            while CELL_STAGE.load(Ordering::Acquire) != 2 {
+                std::thread::yield_now();
+            }
+            v
+        });
+        writer.join().unwrap();
+        assert_eq!(res, Err(Error::SerializationFailure));
+
+        let res = P::transaction_with_isolation(Isolation::Serializable, |j| {
+            let cell = root.read(j);
+            *cell.borrow_mut(j) += 1;
+            *cell.borrow()
+        });
+        assert_eq!(res, Ok(2));
+    }
+}
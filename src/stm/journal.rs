@@ -22,6 +22,10 @@ use crate::stm::vspd::Scratchpad;
 /// Determines that the changes are committed
 pub const JOURNAL_COMMITTED: u64 = 0x0000_0001;
 
+/// Determines that the journal belongs to a [multi-pool
+/// transaction](./multi/index.html) whose decision is kept by a coordinator
+pub const JOURNAL_MULTI: u64 = 0x0000_0002;
+
 /// A Journal object to be used for writing logs onto
 ///
 /// Each transaction, hence each thread, may have only one journal for every
@@ -65,12 +69,22 @@ pub struct Journal<A: MemPool> {
     #[cfg(any(feature = "use_pspd", feature = "use_vspd"))]
     spd: Scratchpad<A>,
 
+    scratch: scratch::Chunks<A>,
     gen: u32,
     flags: u64,
+    commit_ts: u64,
     sec_id: u64,
     prev_off: u64,
     next_off: u64,
     chaperon: [u8;64],
+    coord_off: u64,
+    coord: [u8; multi::COORD_PATH_LEN],
+}
+
+impl<A: MemPool> Journal<A> {
+    /// Persistent memory footprint of a journal with a single page of logs
+    pub const FOOTPRINT: usize =
+        std::mem::size_of::<Journal<A>>() + std::mem::size_of::<Page<A>>();
 }
 
 impl<A: MemPool> !PSafe for Journal<A> {}
@@ -87,6 +101,10 @@ struct Page<A: MemPool> {
     len: usize,
     head: usize,
     next: Ptr<Page<A>, A>,
+
+    /// The offset of the [arena](./arena/index.html) which the page belongs
+    /// to, or `u64::MAX` if it is allocated from the pool
+    home: u64,
     logs: [Log<A>; PAGE_LOG_SLOTS],
 }
 
@@ -109,7 +127,7 @@ impl<A: MemPool> Page<A> {
 
     #[inline]
     fn is_full(&self) -> bool {
-        self.len == PAGE_LOG_SLOTS
+        self.len >= A::CONFIG.get_page_log_slots()
     }
 
     unsafe fn notify(&mut self) {
@@ -218,12 +236,16 @@ impl<A: MemPool> Journal<A> {
             #[cfg(any(feature = "use_pspd", feature = "use_vspd"))]
             spd: Scratchpad::new(),
 
+            scratch: scratch::Chunks::new(),
             gen,
             flags: 0,
+            commit_ts: 0,
             sec_id: 0,
             next_off: u64::MAX,
             prev_off: u64::MAX,
             chaperon: [0; 64],
+            coord_off: 0,
+            coord: [0; multi::COORD_PATH_LEN],
         }
     }
 
@@ -237,14 +259,26 @@ impl<A: MemPool> Journal<A> {
         self.is_set(JOURNAL_COMMITTED)
     }
 
+    /// Returns the commit timestamp of the journal, if it is committed and
+    /// the pool supports [timestamps](./timestamp/index.html)
+    pub fn commit_ts(&self) -> Option<u64> {
+        if self.is_committed() && self.commit_ts != 0 {
+            Some(self.commit_ts)
+        } else {
+            None
+        }
+    }
+
     /// Sets a flag
     pub unsafe fn set(&mut self, flag: u64) {
+        let _trusted = wcheck::Trusted::enter();
         self.flags |= flag;
         persist_obj_with_log::<_,A>(&self.flags, true);
     }
 
     /// Resets a flag
     pub unsafe fn unset(&mut self, flag: u64) {
+        let _trusted = wcheck::Trusted::enter();
         self.flags &= !flag;
     }
 
@@ -284,6 +318,12 @@ impl<A: MemPool> Journal<A> {
     /// Writes a new log to the journal
     #[cfg(feature = "pin_journals")]
     pub(crate) fn write(&self, log: LogEnum, notifier: Notifier<A>) -> Ptr<Log<A>, A> {
+        watchdog::observe(A::name(), &log);
+        limits::charge::<A>(&log);
+        crate::stat::count_logged(std::mem::size_of::<Log<A>>() + match log {
+            LogEnum::DataLog(_, _, len) => len,
+            _ => 0,
+        });
         let mut page = self.next_page(self.current);
         page.as_mut().write(log, notifier)
     }
@@ -297,8 +337,26 @@ impl<A: MemPool> Journal<A> {
                 len: 0,
                 head: 0,
                 next: self.pages,
+                home: u64::MAX,
                 logs: [Default::default(); PAGE_LOG_SLOTS]
             };
+            if let Some((home, slot)) = arena::take::<A>(std::mem::size_of::<Page<A>>()) {
+                // The page is not reachable until it is linked, and the arena
+                // keeps it from leaking if it crashes in the meantime
+                let slot = &mut *(slot as *mut Page<A>);
+                *slot = Page { home, ..page };
+                persist_obj_with_log::<_,A>(slot, false);
+                let me = utils::as_mut(self);
+                me.pages = Ptr::new_unchecked(slot);
+                persist_obj_with_log::<_,A>(&me.pages, true);
+
+                #[cfg(feature = "pin_journals")] {
+                    me.current = me.pages;
+                    persist_obj_with_log::<_,A>(&me.current, true);
+                }
+
+                return self.pages;
+            }
             let (_, off, _, z) = A::atomic_new(page);
             A::log64(A::off_unchecked(self.pages.off_ref()), off, z);
             
@@ -313,9 +371,32 @@ impl<A: MemPool> Journal<A> {
         }
     }
 
+    /// Unlinks the first page, and returns it to its arena or to the pool
+    unsafe fn pop_page(&mut self) {
+        if let Some(page) = self.pages.clone().as_option() {
+            let nxt = page.next;
+            if page.home != u64::MAX {
+                let (home, off) = (page.home, page.off());
+                self.pages = nxt;
+                persist_obj_with_log::<_,A>(&self.pages, true);
+                arena::give::<A>(home, off, std::mem::size_of::<Page<A>>());
+            } else {
+                let z = A::pre_dealloc(page.as_mut_ptr() as *mut u8, std::mem::size_of::<Page<A>>());
+                A::log64(A::off_unchecked(self.pages.off_ref()), nxt.off(), z);
+                A::perform(z);
+            }
+        }
+    }
+
     /// Writes a new log to the journal
     #[cfg(not(feature = "pin_journals"))]
     pub(crate) fn write(&self, log: LogEnum, notifier: Notifier<A>) -> Ptr<Log<A>, A> {
+        watchdog::observe(A::name(), &log);
+        limits::charge::<A>(&log);
+        crate::stat::count_logged(std::mem::size_of::<Log<A>>() + match log {
+            LogEnum::DataLog(_, _, len) => len,
+            _ => 0,
+        });
         let mut page = if self.pages.is_dangling() {
             self.new_page()
         } else if self.pages.is_full() {
@@ -329,11 +410,8 @@ impl<A: MemPool> Journal<A> {
     /// Writes a new log to the journal
     #[cfg(feature = "pin_journals")]
     pub unsafe fn drop_pages(&mut self) {
-        while let Some(page) = self.pages.clone().as_option() {
-            let nxt = page.next;
-            let z = A::pre_dealloc(page.as_mut_ptr() as *mut u8, std::mem::size_of::<Page<A>>());
-            A::log64(A::off_unchecked(self.pages.off_ref()), nxt.off(), z);
-            A::perform(z);
+        while !self.pages.is_dangling() {
+            self.pop_page();
         }
         self.current = Ptr::dangling();
         self.pages = Ptr::dangling();
@@ -352,6 +430,70 @@ impl<A: MemPool> Journal<A> {
         }
     }
 
+    /// Allocates a buffer of `len` copies of `value` in the pool, which lives
+    /// until the transaction commits or rolls back
+    ///
+    /// The buffer is not logged. See [`stm::scratch`] for more details. It
+    /// returns an [`AllocError`] if the size of the buffer overflows, or if
+    /// the pool is exhausted.
+    ///
+    /// [`stm::scratch`]: ./scratch/index.html
+    /// [`AllocError`]: ../struct.AllocError.html
+    pub fn scratch<T: PSafe + Copy>(&self, len: usize, value: T)
+        -> std::result::Result<scratch::Scratch<'_, T, A>, AllocError> {
+        let size = std::mem::size_of::<T>().checked_mul(len)
+            .ok_or_else(|| AllocError::new(usize::MAX))?;
+        unsafe {
+            if size == 0 {
+                let buf = std::slice::from_raw_parts_mut(std::ptr::NonNull::dangling().as_ptr(), len);
+                return Ok(scratch::Scratch::new(buf));
+            }
+            let p = utils::as_mut(self).scratch
+                .alloc(size, std::mem::align_of::<T>())? as *mut T;
+            for i in 0..len {
+                p.add(i).write(value);
+            }
+            Ok(scratch::Scratch::new(std::slice::from_raw_parts_mut(p, len)))
+        }
+    }
+
+    /// Runs `f` in which the data logs share a single fence
+    ///
+    /// The fence after each data log is deferred to the return of `f` (see
+    /// [`Batch`]). It is meant for logging several objects before updating
+    /// them.
+    ///
+    /// # Safety
+    ///
+    /// `f` may take logs, but it should not update any object which is
+    /// logged in it; otherwise, the update may become durable before its log
+    /// and cannot be rolled back after a crash.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::default::*;
+    /// use corundum::stm::{Logger, Notifier};
+    ///
+    /// let root = Allocator::open::<[PCell<u64>; 2]>("foo.pool", O_CF).unwrap();
+    ///
+    /// Allocator::transaction(|j| unsafe {
+    ///     let (a, b) = (root[0].as_mut(), root[1].as_mut());
+    ///     j.batch(|| {
+    ///         a.create_log(j, Notifier::None);
+    ///         b.create_log(j, Notifier::None);
+    ///     });
+    ///     *a += 1;
+    ///     *b += 1;
+    /// }).unwrap();
+    /// ```
+    ///
+    /// [`Batch`]: ../log/struct.Batch.html
+    pub unsafe fn batch<R, F: FnOnce() -> R>(&self, f: F) -> R {
+        let _batch = Batch::enter();
+        f()
+    }
+
     /// Returns a string containing the logging information
     pub fn recovery_info(&self, info_level: u32) -> String {
         let mut i = 1;
@@ -425,19 +567,76 @@ impl<A: MemPool> Journal<A> {
         #[cfg(feature = "check_double_free")]
         check_double_free: &mut HashSet<u64>
     ) {
+        self.prepare_commit();
+        self.finish_commit(
+            #[cfg(feature = "check_double_free")]
+            check_double_free
+        );
+    }
+
+    /// Takes the commit timestamp and notifies the owners of the logs, so
+    /// that the changes are visible to the next transactions. The changes are
+    /// not durable until [`finish_commit()`](#method.finish_commit).
+    pub(crate) unsafe fn prepare_commit(&mut self) {
+        let ts = A::next_commit_ts();
+        if let Some(ts) = ts {
+            self.commit_ts = ts;
+            persist_obj_with_log::<_,A>(&self.commit_ts, false);
+            timestamp::record(A::name(), ts);
+        }
+        // The captured changes are appended while the journal can still take
+        // logs, so that they commit or roll back with the transaction
+        #[cfg(feature = "cdc")]
+        cdc::flush(self, ts.unwrap_or(0));
+
+        #[cfg(feature = "audit")]
+        audit::record(self, ts.unwrap_or(0));
+
         #[cfg(any(feature = "use_pspd", feature = "use_vspd"))] {
             self.spd.commit();
         }
+
+        #[cfg(feature = "wal")]
+        wal::commit(self, ts);
+
+        fresh::flush::<A>();
+
         let mut curr = self.pages;
         while let Some(page) = curr.as_option() {
             page.notify();
             curr = page.next;
         }
+    }
+
+    /// Flushes the changes, reclaims the memory which is dropped by the
+    /// transaction, and marks the journal as committed
+    pub(crate) unsafe fn finish_commit(&mut self, 
+        #[cfg(feature = "check_double_free")]
+        check_double_free: &mut HashSet<u64>
+    ) {
+        self.flush_data();
+        self.drop_on_commit(
+            #[cfg(feature = "check_double_free")]
+            check_double_free
+        );
+        sfence();
+        self.set(JOURNAL_COMMITTED);
+    }
+
+    /// Flushes the changes without a fence
+    pub(crate) unsafe fn flush_data(&mut self) {
         let mut curr = self.pages;
         while let Some(page) = curr.as_option() {
             page.commit_data();
             curr = page.next;
         }
+    }
+
+    /// Reclaims the memory which is dropped by the transaction
+    pub(crate) unsafe fn drop_on_commit(&mut self, 
+        #[cfg(feature = "check_double_free")]
+        check_double_free: &mut HashSet<u64>
+    ) {
         let mut curr = self.pages;
         while let Some(page) = curr.as_option() {
             page.commit_dealloc(
@@ -446,8 +645,57 @@ impl<A: MemPool> Journal<A> {
             );
             curr = page.next;
         }
-        sfence();
-        self.set(JOURNAL_COMMITTED);
+    }
+
+    /// Marks the journal as committed without a fence; the flag is durable
+    /// after the next `sfence`
+    pub(crate) unsafe fn mark_committed(&mut self) {
+        self.flags |= JOURNAL_COMMITTED;
+        persist_obj_with_log::<_,A>(&self.flags, false);
+    }
+
+    /// Releases the locks which are held by the transaction before the
+    /// journal is cleared
+    pub(crate) unsafe fn release_locks(&mut self) {
+        let mut curr = self.pages;
+        while let Some(page) = curr.as_option() {
+            for i in page.head..page.len {
+                page.logs[i].unlock();
+            }
+            curr = page.next;
+        }
+    }
+
+    /// Calls `f` for every log in the journal which is not cleared
+    pub(crate) fn for_each_log<F: FnMut(LogEnum)>(&self, mut f: F) {
+        let mut curr = self.pages;
+        while let Some(page) = curr.as_option() {
+            for i in page.head..page.len {
+                f(page.logs[i].inner());
+            }
+            curr = page.next;
+        }
+    }
+
+    /// Returns the number of logs and the number of logged bytes
+    pub(crate) fn logged(&self) -> (usize, usize) {
+        let (mut logs, mut bytes) = (0, 0);
+        self.for_each_log(|log| {
+            logs += 1;
+            bytes += match log {
+                LogEnum::DataLog(_, _, len) => len,
+                LogEnum::InlineLog(_, _, len) => len as usize,
+                _ => 0,
+            };
+        });
+        (logs, bytes)
+    }
+
+    /// Returns the commit timestamp of the journal, whether or not it is
+    /// committed; zero means that the commit is not started
+    #[doc(hidden)]
+    pub fn raw_commit_ts(&self) -> u64 {
+        self.commit_ts
     }
 
     /// Reverts all changes
@@ -455,6 +703,10 @@ impl<A: MemPool> Journal<A> {
         #[cfg(feature = "check_double_free")]
         check_double_free: &mut HashSet<u64>
     ) {
+        #[cfg(feature = "cdc")]
+        cdc::discard(A::name());
+        fresh::discard::<A>();
+
         #[cfg(any(feature = "use_pspd", feature = "use_vspd"))] {
             self.spd.rollback();
         }
@@ -485,11 +737,30 @@ impl<A: MemPool> Journal<A> {
         #[cfg(feature = "check_double_free")]
         check_double_free: &mut HashSet<u64>
     ) {
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            pool = A::name(),
+            committed = self.is_set(JOURNAL_COMMITTED),
+            multi = self.is_set(JOURNAL_MULTI),
+            "recovering journal"
+        );
+
         let mut curr = self.pages;
         while let Some(page) = curr.as_option() {
             page.notify();
             curr = page.next;
         }
+        // A participant of a multi-pool transaction follows the decision of
+        // the coordinator
+        let multi = if self.is_set(JOURNAL_MULTI) {
+            if !self.is_set(JOURNAL_COMMITTED) &&
+                multi::decided(&self.coord, self.coord_off, self.sec_id) {
+                self.set(JOURNAL_COMMITTED);
+            }
+            Some((self.coord, self.coord_off, self.sec_id))
+        } else {
+            None
+        };
         let mut curr = self.pages;
         let resume = self.resume();
         if !self.is_set(JOURNAL_COMMITTED) || resume {
@@ -510,6 +781,14 @@ impl<A: MemPool> Journal<A> {
             }
             self.set(JOURNAL_COMMITTED);
         }
+        if let Some((coord, off, sid)) = multi {
+            self.unbind_coordinator();
+            // The participant is recovered either way; a failed
+            // acknowledgment only keeps the decision in the coordinator
+            if let Err(_e) = multi::acknowledge(&coord, off, sid) {
+                log!(A, Red, "MULTI", "cannot acknowledge session {}: {}", sid, _e);
+            }
+        }
     }
 
     /// Clears all logs and drops itself from the memory pool
@@ -520,6 +799,9 @@ impl<A: MemPool> Journal<A> {
         #[cfg(any(feature = "use_pspd", feature = "use_vspd"))] {
             self.spd.clear();
         }
+        self.scratch.clear();
+        fresh::discard::<A>();
+        isolation::release::<A>();
         #[cfg(feature = "pin_journals")]
         {
             let mut page = self.pages.as_option();
@@ -535,14 +817,11 @@ impl<A: MemPool> Journal<A> {
 
         #[cfg(not(feature = "pin_journals"))] {
             while let Some(page) = self.pages.as_option() {
-                let nxt = page.next;
                 page.clear(
                     #[cfg(feature = "check_double_free")]
                     check_double_free
                 );
-                let z = A::pre_dealloc(page.as_mut_ptr() as *mut u8, std::mem::size_of::<Page<A>>());
-                A::log64(A::off_unchecked(self.pages.off_ref()), nxt.off(), z);
-                A::perform(z);
+                self.pop_page();
 
                 #[cfg(feature = "check_allocator_cyclic_links")]
                 debug_assert!(A::verify());
@@ -557,9 +836,15 @@ impl<A: MemPool> Journal<A> {
         self.complete();
 
         #[cfg(not(feature = "pin_journals"))] {
+            let off = A::off_unchecked(self);
             A::drop_journal(self);
             A::journals(|journals| {
-                journals.remove(&std::thread::current().id());
+                // The journal may be cleared by another thread (e.g., a
+                // relaxed commit which is finished by the flusher)
+                let tid = std::thread::current().id();
+                if journals.get(&tid).map(|(j, _)| *j) == Some(off) {
+                    journals.remove(&tid);
+                }
             });
         }
     }
@@ -593,7 +878,7 @@ impl<A: MemPool> Journal<A> {
         if !self.is_set(JOURNAL_COMMITTED) {
             false
         } else {
-            if self.sec_id != 0 && !self.chaperon.is_empty() {
+            if self.sec_id != 0 && !self.chaperon.is_empty() && !self.is_set(JOURNAL_MULTI) {
                 let s = String::from_utf8(self.chaperon.to_vec()).unwrap();
                 let c = unsafe { Chaperon::load(&s)
                     .expect(&format!("Missing chaperon file `{}`", s)) };
@@ -605,6 +890,7 @@ impl<A: MemPool> Journal<A> {
     }
 
     pub(crate) fn start_session(&mut self, chaperon: &mut Chaperon) {
+        let _trusted = wcheck::Trusted::enter();
         let mut filename = [0u8; 64]; 
         let s = chaperon.filename().as_bytes();
         for i in 0..usize::min(64,s.len()) {
@@ -620,8 +906,29 @@ impl<A: MemPool> Journal<A> {
         self.sec_id = chaperon.new_section() as u64;
     }
 
+    /// Records the location of the decision of the multi-pool transaction
+    /// which the journal belongs to; it is durable after the next `sfence`
+    pub(crate) unsafe fn bind_coordinator(&mut self, sid: u64,
+        coord: &[u8; multi::COORD_PATH_LEN], off: u64
+    ) {
+        self.sec_id = sid;
+        self.coord_off = off;
+        self.coord = *coord;
+        self.flags |= JOURNAL_MULTI;
+        persist_obj_with_log::<_,A>(self, false);
+    }
+
+    unsafe fn unbind_coordinator(&mut self) {
+        self.sec_id = 0;
+        self.flags &= !JOURNAL_MULTI;
+        persist_obj_with_log::<_,A>(&self.flags, false);
+        persist_obj_with_log::<_,A>(&self.sec_id, true);
+    }
+
     pub(crate) fn complete(&mut self) {
-        if self.sec_id != 0 && !self.chaperon.is_empty() {
+        if self.is_set(JOURNAL_MULTI) {
+            unsafe { self.unbind_coordinator(); }
+        } else if self.sec_id != 0 && !self.chaperon.is_empty() {
             unsafe {
                 let s = String::from_utf8(self.chaperon.to_vec()).unwrap();
                 if let Ok(c) = Chaperon::load(&s) {
@@ -673,6 +980,7 @@ impl<A: MemPool> Journal<A> {
         Self: Sized,
     {
         let tid = std::thread::current().id();
+        let _trusted = wcheck::Trusted::enter();
         A::journals(|journals| {
             if !journals.contains_key(&tid) && create {
                 #[cfg(feature = "stat_perf")]
@@ -691,7 +999,33 @@ impl<A: MemPool> Journal<A> {
         })
     }
 
-    /// Returns true if there is a running transaction on the current thread
+    /// Returns true if the [`CancellationToken`] of the running transaction
+    /// is cancelled
+    ///
+    /// It is a cancellation point: once it returns true, the transaction is
+    /// rolled back when the closure returns. Transactions which are not
+    /// started with [`cancellable_transaction()`] are never cancelled.
+    ///
+    /// [`CancellationToken`]: ./cancel/struct.CancellationToken.html
+    /// [`cancellable_transaction()`]: ../trait.MemPoolTraits.html#method.cancellable_transaction
+    pub fn is_cancelled(&self) -> bool {
+        cancel::poll(A::name())
+    }
+
+    /// Returns true if the current thread is inside a transaction of pool `A`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::default::*;
+    ///
+    /// let _pool = Allocator::open_no_root("foo.pool", O_CF).unwrap();
+    ///
+    /// assert!(!Journal::is_running());
+    /// Allocator::transaction(|_| {
+    ///     assert!(Journal::is_running());
+    /// }).unwrap();
+    /// ```
     pub fn is_running() -> bool {
         if let Some((_, cnt)) = Self::try_current() {
             unsafe {*cnt != 0}
@@ -700,6 +1034,55 @@ impl<A: MemPool> Journal<A> {
         }
     }
 
+    /// Calls `f` with the journal of the running transaction of the current
+    /// thread, and returns its result. It returns `None` if the current thread
+    /// is not inside a transaction of pool `A`.
+    ///
+    /// This is useful in code that is called from a transaction without
+    /// being passed its journal, e.g., for debugging or for choosing between
+    /// a transactional and a non-transactional path. The journal cannot
+    /// escape `f`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::default::*;
+    ///
+    /// fn logged_bytes() -> Option<usize> {
+    ///     Journal::with_current(|j| j.stats().logged_bytes)
+    /// }
+    ///
+    /// let root = Allocator::open::<PCell<i32>>("foo.pool", O_CF).unwrap();
+    ///
+    /// assert_eq!(logged_bytes(), None);
+    /// Allocator::transaction(|j| {
+    ///     root.set(10, j);
+    ///     assert!(logged_bytes().unwrap() >= 4);
+    /// }).unwrap();
+    /// ```
+    pub fn with_current<R, F: FnOnce(&Journal<A>) -> R>(f: F) -> Option<R> {
+        let (journal, cnt) = Self::try_current()?;
+        unsafe {
+            if *cnt == 0 {
+                None
+            } else {
+                Some(f(&*journal))
+            }
+        }
+    }
+
+    /// Returns the statistics of the logs which are kept in the journal
+    pub fn stats(&self) -> JournalStats {
+        let mut pages = 0;
+        let mut curr = self.pages;
+        while let Some(page) = curr.as_option() {
+            pages += 1;
+            curr = page.next;
+        }
+        let (logs, logged_bytes) = self.logged();
+        JournalStats { pages, logs, logged_bytes }
+    }
+
     /// Returns a journal for the current thread. If there is no `Journal`
     /// object for the running thread, it may create a new journal and returns
     /// its mutable reference. Each thread may have only one journal.
@@ -735,6 +1118,21 @@ impl<A: MemPool> Journal<A> {
     }
 }
 
+/// A summary of the logs of a journal, obtained from [`Journal::stats()`]
+///
+/// [`Journal::stats()`]: ./struct.Journal.html#method.stats
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JournalStats {
+    /// The number of log pages allocated for the journal
+    pub pages: usize,
+
+    /// The number of logs in the journal, including the drop logs
+    pub logs: usize,
+
+    /// The number of bytes of the undo logs
+    pub logged_bytes: usize,
+}
+
 impl<A: MemPool> Debug for Journal<A> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         writeln!(f, "LOGS:")?;
@@ -0,0 +1,232 @@
+//! Per-transaction resource limits
+//!
+//! A runaway transaction, e.g., one that loops over an unbounded input, can
+//! fill the pool with its logs and allocations before it fails, and the
+//! other tenants of the pool run out of memory in the meantime. A
+//! transaction which is started with
+//! [`MemPoolTraits::transaction_with_config()`] is bounded by the limits of
+//! its [`TxConfig`]:
+//!
+//! * `max_log_bytes`: the bytes which the logs take in the journal,
+//!   including the copies of the old data
+//! * `max_allocations`: the number of allocations
+//!
+//! Once a limit is exceeded, the next log panics to stop the transaction. It
+//! is rolled back, and it returns [`Error::LimitExceeded`] with the limit
+//! which was exceeded. The nested transactions on the same pool count
+//! towards the limits of their parents.
+//!
+//! # Examples
+//!
+//! ```
+//! use corundum::default::*;
+//! use corundum::stm::{TxConfig, TxLimit};
+//! use corundum::Error;
+//!
+//! type P = Allocator;
+//!
+//! let root = P::open::<PRefCell<PVec<u64>>>("limits.pool", O_CF).unwrap();
+//! let config = TxConfig::new().max_allocations(100);
+//!
+//! let res = P::transaction_with_config(config, |j| {
+//!     for i in 0.. {
+//!         let _ = Pbox::new(i, j); // never ends
+//!     }
+//! });
+//!
+//! assert_eq!(res, Err(Error::LimitExceeded(TxLimit::Allocations(100))));
+//! ```
+//!
+//! [`MemPoolTraits::transaction_with_config()`]: ../../alloc/trait.MemPoolTraits.html#method.transaction_with_config
+//! [`TxConfig`]: ./struct.TxConfig.html
+//! [`Error::LimitExceeded`]: ../../enum.Error.html#variant.LimitExceeded
+
+use crate::alloc::MemPool;
+use crate::stm::{Log, LogEnum};
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The configuration of a transaction
+///
+/// See the [module-level documentation](./index.html) for more details.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TxConfig {
+    max_log_bytes: usize,
+    max_allocations: usize,
+}
+
+impl TxConfig {
+    /// Creates a configuration without limits
+    pub const fn new() -> Self {
+        Self {
+            max_log_bytes: usize::MAX,
+            max_allocations: usize::MAX,
+        }
+    }
+
+    /// Sets the maximum number of bytes which the logs of the transaction
+    /// may take in the journal
+    pub const fn max_log_bytes(mut self, bytes: usize) -> Self {
+        self.max_log_bytes = bytes;
+        self
+    }
+
+    /// Sets the maximum number of allocations of the transaction
+    pub const fn max_allocations(mut self, count: usize) -> Self {
+        self.max_allocations = count;
+        self
+    }
+}
+
+impl Default for TxConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A limit of a [`TxConfig`](./struct.TxConfig.html) which a transaction
+/// has exceeded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TxLimit {
+    /// The maximum number of log bytes
+    LogBytes(usize),
+
+    /// The maximum number of allocations
+    Allocations(usize),
+}
+
+impl fmt::Display for TxLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxLimit::LogBytes(n) => write!(f, "{} log bytes", n),
+            TxLimit::Allocations(n) => write!(f, "{} allocations", n),
+        }
+    }
+}
+
+struct Scope {
+    pool: &'static str,
+    config: TxConfig,
+    log_bytes: Cell<usize>,
+    allocations: Cell<usize>,
+    exceeded: Cell<Option<TxLimit>>,
+}
+
+thread_local! {
+    static CURRENT: RefCell<Vec<Scope>> = RefCell::new(Vec::new());
+}
+
+/// The number of scopes in all threads, so that the transactions without
+/// limits do not look them up
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// Applies `config` to the transactions of the current thread on pool `A`
+/// while it lives
+pub(crate) struct LimitGuard;
+
+impl LimitGuard {
+    pub(crate) fn enter<A: MemPool>(config: TxConfig) -> Self {
+        CURRENT.with(|c| c.borrow_mut().push(Scope {
+            pool: A::name(),
+            config,
+            log_bytes: Cell::new(0),
+            allocations: Cell::new(0),
+            exceeded: Cell::new(None),
+        }));
+        ACTIVE.fetch_add(1, Ordering::Relaxed);
+        LimitGuard
+    }
+}
+
+impl Drop for LimitGuard {
+    fn drop(&mut self) {
+        ACTIVE.fetch_sub(1, Ordering::Relaxed);
+        let _ = CURRENT.try_with(|c| {
+            let mut c = c.borrow_mut();
+            if let Some(s) = c.pop() {
+                // The parent transaction fails because of the nested one
+                if let Some(limit) = s.exceeded.get() {
+                    if let Some(p) = c.iter().rev().find(|p| p.pool == s.pool) {
+                        if p.exceeded.get().is_none() {
+                            p.exceeded.set(Some(limit));
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Accounts a new log of the current thread's transaction on pool `A`, and
+/// aborts the transaction if it exceeds a limit
+#[inline]
+pub(crate) fn charge<A: MemPool>(log: &LogEnum) {
+    if ACTIVE.load(Ordering::Relaxed) == 0 || std::thread::panicking() {
+        return;
+    }
+    let bytes = std::mem::size_of::<Log<A>>() + match log {
+        LogEnum::DataLog(_, _, len) => *len,
+        _ => 0,
+    };
+    let alloc = match log {
+        LogEnum::DropOnFailure(..) | LogEnum::DropOnAbort(..) => 1,
+        _ => 0,
+    };
+    let exceeded = CURRENT.with(|c| {
+        let mut exceeded = None;
+        for s in c.borrow().iter().filter(|s| s.pool == A::name()) {
+            s.log_bytes.set(s.log_bytes.get().saturating_add(bytes));
+            s.allocations.set(s.allocations.get() + alloc);
+            let limit = if s.log_bytes.get() > s.config.max_log_bytes {
+                Some(TxLimit::LogBytes(s.config.max_log_bytes))
+            } else if s.allocations.get() > s.config.max_allocations {
+                Some(TxLimit::Allocations(s.config.max_allocations))
+            } else {
+                None
+            };
+            if limit.is_some() && s.exceeded.get().is_none() {
+                s.exceeded.set(limit);
+            }
+            exceeded = exceeded.or(limit);
+        }
+        exceeded
+    });
+    if let Some(limit) = exceeded {
+        panic!("transaction exceeded the limit of {}", limit);
+    }
+}
+
+/// Returns the limit which the current thread's transaction on pool `pool`
+/// has exceeded, if any
+pub(crate) fn exceeded(pool: &'static str) -> Option<TxLimit> {
+    CURRENT.with(|c| {
+        c.borrow().iter().rev().find(|s| s.pool == pool)
+            .and_then(|s| s.exceeded.get())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TxConfig, TxLimit};
+    use crate::alloc::heap::*;
+    use crate::Error;
+
+    #[test]
+    fn nested_limit() {
+        let config = TxConfig::new().max_log_bytes(1 << 20);
+        let res = Heap::transaction_with_config(config, |_| {
+            let _ = Heap::transaction(|j| {
+                let b = Pbox::new([0u8; 4096], j);
+                for _ in 0..1000 {
+                    unsafe { crate::stm::Log::create(&*b, j, crate::stm::Notifier::None); }
+                }
+            });
+        });
+        assert_eq!(res, Err(Error::LimitExceeded(TxLimit::LogBytes(1 << 20))));
+        assert_eq!(res.unwrap_err().code(), -19);
+
+        let res = Heap::transaction_with_config(config, |j| *Pbox::new(1, j));
+        assert_eq!(res, Ok(1));
+    }
+}
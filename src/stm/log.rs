@@ -13,6 +13,11 @@ use std::collections::HashSet;
 
 type Offset = u64;
 
+/// The maximum size of an object which is logged inline in the journal
+///
+/// See [`InlineLog`](./enum.LogEnum.html#variant.InlineLog).
+pub const INLINE_LOG_SIZE: usize = 16;
+
 /// Log Types
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub enum LogEnum {
@@ -20,6 +25,14 @@ pub enum LogEnum {
     /// `log..log+len`.
     DataLog(u64, u64, usize),
 
+    /// `(src, data, len)`: An undo log of a small `Copy` object at
+    /// `src..src+len` kept inline in the log entry. Unlike [`DataLog`], it
+    /// does not allocate a separate buffer for the old data, so it needs a
+    /// single flush of the log entry.
+    ///
+    /// [`DataLog`]: #variant.DataLog
+    InlineLog(u64, [u8; INLINE_LOG_SIZE], u8),
+
     /// `(u64, usize)`: Similar to [`DropOnFailure`] except that it
     /// drops the allocation when the high-level transaction is aborted. This is
     /// useful for temporarily unowned allocations, such as slices, because they
@@ -57,6 +70,7 @@ impl Debug for LogEnum {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> std::result::Result<(), fmt::Error> {
         match *self {
             DataLog(off, _, _)       => write!(f, "DataLog         ({})", offset_to_str(off)),
+            InlineLog(off, _, _)     => write!(f, "InlineLog       ({})", offset_to_str(off)),
             DropOnAbort(off, _)      => write!(f, "DropOnAbort     ({})", offset_to_str(off)),
             DropOnCommit(off, _)     => write!(f, "DropOnCommit    ({})", offset_to_str(off)),
             DropOnFailure(off, _)    => write!(f, "DropOnFailure   ({})", offset_to_str(off)),
@@ -67,6 +81,117 @@ impl Debug for LogEnum {
     }
 }
 
+impl LogEnum {
+    /// Returns the name of the log type
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DataLog(..)          => "DataLog",
+            InlineLog(..)        => "InlineLog",
+            DropOnAbort(..)      => "DropOnAbort",
+            DropOnCommit(..)     => "DropOnCommit",
+            DropOnFailure(..)    => "DropOnFailure",
+            RecountOnFailure(..) => "RecountOnFailure",
+            UnlockOnCommit(..)   => "UnlockOnCommit",
+            None                 => "None",
+        }
+    }
+
+    /// Returns true if the log should be durable before the next store
+    ///
+    /// A data log should be durable before the data is updated in place. The
+    /// logs which take effect only with the commit are ordered by the fence
+    /// before the commit record, and the placeholder of an allocation log is
+    /// ordered by the fence before the allocator applies the allocation. The
+    /// fences of the data logs which are taken in a [`Batch`] are deferred to
+    /// the end of the batch.
+    ///
+    /// [`Batch`]: ./struct.Batch.html
+    pub(crate) fn is_ordered(&self) -> bool {
+        match self {
+            DropOnCommit(..) | UnlockOnCommit(..) => false,
+            DropOnAbort(off, _) | DropOnFailure(off, _) => *off != u64::MAX,
+            _ => true,
+        }
+    }
+}
+
+thread_local! {
+    /// The depth of the log batches of the current thread, and whether a
+    /// fence is deferred to the end of the outermost one
+    static BATCH: std::cell::Cell<(usize, bool)> = std::cell::Cell::new((0, false));
+}
+
+/// A scope in which the logs share a single fence
+///
+/// Every data log is followed by a fence, so that it is durable before the
+/// logged object is updated in place. When several objects are logged before
+/// any of them is updated, the logs may be taken in a batch: the fences are
+/// deferred to the end of the outermost batch, which issues one fence. With
+/// the data flushes of the commit, which are already issued without fences,
+/// a transaction which logs its objects in one batch takes a constant number
+/// of fences.
+///
+/// The batch cannot be the default for every log: the library does not see
+/// the store which follows a log in user code, e.g., through the reference
+/// that a `PRefCell` hands out, so the fence is the only way to make the log
+/// durable before it. The library batches its own logs which it takes
+/// together, such as in `PCell::swap()` and the updates of the persistent
+/// trees and queues.
+///
+/// A batch is entered by [`Journal::batch()`].
+///
+/// [`Journal::batch()`]: ./journal/struct.Journal.html#method.batch
+pub struct Batch(());
+
+impl Batch {
+    /// Enters a batch; the batch ends when the returned value is dropped
+    ///
+    /// # Safety
+    ///
+    /// No object which is logged in the batch may be updated before the end
+    /// of the batch.
+    pub(crate) unsafe fn enter() -> Self {
+        BATCH.with(|b| {
+            let (depth, pending) = b.get();
+            b.set((depth + 1, pending));
+        });
+        Batch(())
+    }
+
+    /// Issues a fence, or defers it if a batch is open
+    #[inline]
+    pub(crate) fn fence() {
+        let deferred = BATCH.try_with(|b| {
+            let (depth, _) = b.get();
+            if depth > 0 {
+                b.set((depth, true));
+            }
+            depth > 0
+        }).unwrap_or(false);
+        if !deferred {
+            sfence();
+        }
+    }
+}
+
+impl Drop for Batch {
+    fn drop(&mut self) {
+        let pending = BATCH.with(|b| {
+            let (depth, pending) = b.get();
+            if depth == 1 {
+                b.set((0, false));
+                pending
+            } else {
+                b.set((depth - 1, pending));
+                false
+            }
+        });
+        if pending {
+            sfence();
+        }
+    }
+}
+
 /// A data-log notification type
 /// 
 /// This is used to notify the owner that the underlying data is logged, so that
@@ -253,12 +378,25 @@ impl<A: MemPool> Log<A> {
     /// [`validate()`]: ../alloc/trait.MemPool.html#method.validate
     pub fn set(&mut self, off: u64, len: usize, zone: usize) {
         debug_assert_ne!(len, 0);
+        let _trusted = crate::stm::wcheck::Trusted::enter();
 
         log!(A, Yellow, "CHNGE LOG", "TO:          ({:>6}:{:<6}) = {:<6} {:?}",
             offset_to_str(off), offset_to_str((off as usize + (len - 1)) as u64),
             len, self.0
         );
 
+        match &self.0 {
+            DropOnAbort(_, _) | DropOnFailure(_, _) => {
+                crate::stat::count_allocated(len);
+
+                #[cfg(feature = "stat_alloc_sites")]
+                crate::stat::record_alloc_site(len);
+
+                crate::stm::fresh::allocated::<A>(off, len);
+                crate::stm::wcheck::allow(A::start() + off, len);
+            }
+            _ => {}
+        }
         match &self.0 {
             DropOnAbort(offset, length) |
             DropOnFailure(offset, length) |
@@ -277,6 +415,7 @@ impl<A: MemPool> Log<A> {
     pub fn kind(&self) -> String {
         match self.0 {
             DataLog(_, _, _) => "DataLog",
+            InlineLog(_, _, _) => "InlineLog",
             DropOnAbort(_, _) => "DropOnAbort",
             DropOnCommit(_, _) => "DropOnCommit",
             DropOnFailure(_, _) => "DropOnFailure",
@@ -343,10 +482,14 @@ impl<A: MemPool> Log<A> {
         #[cfg(feature = "stat_perf")]
         let _perf = crate::stat::Measure::<A>::DataLog(std::time::Instant::now());
 
+        let _trusted = crate::stm::wcheck::Trusted::enter();
         let len = std::mem::size_of_val(x);
         if len == 0 {
             notifier.update(1);
             Ptr::dangling()
+        } else if crate::stm::fresh::skip_log::<A>(unsafe { A::off_unchecked(x) }, len) {
+            notifier.update(1);
+            Ptr::dangling()
         } else {
             let pointer = unsafe { Ptr::<T, A>::new_unchecked(x) };
 
@@ -376,6 +519,47 @@ impl<A: MemPool> Log<A> {
         }
     }
 
+    /// Creates an [`InlineLog`] of `x` into `journal` and notifies the owner
+    /// that log is created if `notifier` is specified.
+    ///
+    /// It is the fast path for small `Copy` objects which is taken by
+    /// [`Logger::create_log()`] when `x` fits in [`INLINE_LOG_SIZE`] bytes.
+    ///
+    /// [`InlineLog`]: ./enum.LogEnum.html#variant.InlineLog
+    /// [`Logger::create_log()`]: ./trait.Logger.html#tymethod.create_log
+    /// [`INLINE_LOG_SIZE`]: ./constant.INLINE_LOG_SIZE.html
+    pub fn create_inline<T: PSafe + Copy>(
+        x: &T,
+        journal: &Journal<A>,
+        mut notifier: Notifier<A>,
+    ) -> Ptr<Log<A>, A> {
+        #[cfg(feature = "stat_perf")]
+        let _perf = crate::stat::Measure::<A>::DataLog(std::time::Instant::now());
+
+        let len = std::mem::size_of::<T>();
+        assert!(len <= INLINE_LOG_SIZE, "The object is too large to be logged inline");
+        if len == 0 {
+            notifier.update(1);
+            Ptr::dangling()
+        } else if crate::stm::fresh::skip_log::<A>(unsafe { A::off_unchecked(x) }, len) {
+            notifier.update(1);
+            Ptr::dangling()
+        } else {
+            let pointer = unsafe { Ptr::<T, A>::new_unchecked(x) };
+
+            log!(A, Yellow, "LOG", "FOR:         ({:>6}:{:<6}) = {:<6} InlineLog  TYPE: {}",
+                offset_to_str(pointer.off()), offset_to_str((pointer.off() as usize + (len - 1)) as u64),
+                len, std::any::type_name::<T>()
+            );
+
+            let mut data = [0u8; INLINE_LOG_SIZE];
+            unsafe {
+                ptr::copy_nonoverlapping(x as *const T as *const u8, data.as_mut_ptr(), len);
+            }
+            Self::write_on_journal(InlineLog(pointer.off(), data, len as u8), journal, notifier)
+        }
+    }
+
     /// Creates a log of `&[x]` into `journal` and notifies the owner that log is
     /// created if `notifier` is specified.
     pub fn create_slice<T: PSafe>(
@@ -386,10 +570,14 @@ impl<A: MemPool> Log<A> {
         #[cfg(feature = "stat_perf")]
         let _perf = crate::stat::Measure::<A>::DataLog(std::time::Instant::now());
 
+        let _trusted = crate::stm::wcheck::Trusted::enter();
         let len = std::mem::size_of_val(x);
         if len == 0 {
             notifier.update(1);
             Ptr::dangling()
+        } else if crate::stm::fresh::skip_log::<A>(unsafe { A::off_unchecked(x) }, len) {
+            notifier.update(1);
+            Ptr::dangling()
         } else {
             let slice = unsafe { Slice::<T, A>::new(x) };
 
@@ -401,9 +589,9 @@ impl<A: MemPool> Log<A> {
                 dump_data::<A>("DATA", slice.off(), len);
             }
 
+            // The copy is flushed by `memcpy_persist()`
             let log = unsafe { slice.dup() };
 
-            crate::ll::persist_obj_with_log::<_,A>(log.as_ref(), false);
             Self::create_impl(slice.off(), log.off(), len, journal, notifier)
             // }
         }
@@ -415,9 +603,21 @@ impl<A: MemPool> Log<A> {
         journal: &Journal<A>,
         mut notifier: Notifier<A>,
     ) -> Ptr<Log<A>, A> {
+        #[cfg(feature = "stat_flamegraph")]
+        crate::stat::sample_log_stack(log.kind());
+
+        #[cfg(feature = "cdc")]
+        crate::stm::cdc::record_log::<A>(&log);
+
+        let _trusted = crate::stm::wcheck::Trusted::enter();
+        crate::stm::wcheck::observe::<A>(&log);
+
+        let ordered = log.is_ordered();
         let log = journal.write(log, notifier.clone());
         notifier.update(1);
-        sfence();
+        if ordered {
+            Batch::fence();
+        }
         log
     }
 
@@ -490,11 +690,11 @@ impl<A: MemPool> Log<A> {
 
         log!(A, Yellow, "NEW LOG", "FOR:         v@{:<18} UnlockOnCommit", virt_addr);
         
-        #[cfg(any(feature = "no_pthread", windows))] {
-            let b = &mut *(virt_addr as *mut (bool, u64));
+        #[cfg(any(feature = "no_pthread", windows, miri, loom))] {
+            let b = &mut *(virt_addr as *mut (bool, crate::sync::LockWord));
             if b.0 { return; }
         }
-        #[cfg(not(any(feature = "no_pthread", windows)))] {
+        #[cfg(not(any(feature = "no_pthread", windows, miri, loom)))] {
             let b = &mut *(virt_addr as *mut (bool, libc::pthread_mutex_t, 
                 libc::pthread_mutexattr_t));
             if b.0 { return; }
@@ -539,6 +739,21 @@ impl<A: MemPool> Log<A> {
         }
     }
 
+    fn rollback_inlinelog(src: &mut u64, data: &[u8; INLINE_LOG_SIZE], len: u8) {
+        debug_assert_ne!(len, 0);
+
+        if *src != u64::MAX {
+            log!(A, Magenta, "ROLLBACK", "FOR:         ({:>6x}:{:<6x}) = {:<6} InlineLog",
+                *src, *src as usize + (len as usize - 1), len
+            );
+            unsafe {
+                let src = A::get_mut_unchecked::<u8>(*src);
+                ptr::copy_nonoverlapping(data.as_ptr(), src, len as usize);
+                persist_with_log::<_,A>(src, len as usize, false);
+            }
+        }
+    }
+
     pub(crate) unsafe fn rollback(&mut self) {
         #[cfg(feature = "stat_perf")]
         let _perf = crate::stat::Measure::<A>::RollbackLog(std::time::Instant::now());
@@ -552,6 +767,11 @@ impl<A: MemPool> Log<A> {
                 #[cfg(feature = "check_allocator_cyclic_links")]
                 debug_assert!(A::verify());
             }
+            InlineLog(src, data, len) => {
+                Self::rollback_inlinelog(src, data, *len);
+                self.notify(0);
+                self.1 = Notifier::None;
+            }
             _ => {}
         }
     }
@@ -602,6 +822,14 @@ impl<A: MemPool> Log<A> {
                     debug_assert!(A::verify());
                 }
             }
+            InlineLog(src, data, len) => {
+                if rollback {
+                    debug_assert!(A::allocated(*src, 1), "Access Violation at address 0x{:x}", *src);
+                    Self::rollback_inlinelog(src, data, *len);
+                    self.notify(0);
+                    self.1 = Notifier::None;
+                }
+            }
             DropOnFailure(src, len) => {
                 if rollback {
                     if *src != u64::MAX {
@@ -663,6 +891,14 @@ impl<A: MemPool> Log<A> {
                     persist_with_log::<u8,A>(A::get_mut_unchecked(*_src), *_len, false);
                 }
             }
+            InlineLog(_src, _, _len) => {
+                debug_assert!(A::allocated(*_src, 1), "Access Violation at address 0x{:x}", *_src);
+
+                #[cfg(not(feature = "no_flush_updates"))]
+                unsafe {
+                    persist_with_log::<u8,A>(A::get_mut_unchecked(*_src), *_len as usize, false);
+                }
+            }
             _ => {}
         }
     }
@@ -737,40 +973,50 @@ impl<A: MemPool> Log<A> {
                     debug_assert!(A::verify());
                 }
             }
-            UnlockOnCommit(src) => {
-                if *src != u64::MAX {
-                    log!(A, Magenta, "UNLOCK", "FOR:          v@{}", *src);
-                    #[cfg(not(any(feature = "no_pthread", windows)))] {
-                        let b = &mut *(*src as *mut (bool, libc::pthread_mutex_t, libc::pthread_mutexattr_t));
-                        b.0 = false;
-                        let lock = &mut b.1;
-                        let attr = &mut b.2;
-                        let result = libc::pthread_mutex_unlock(lock);
-                        if result != 0 {
-                            crate::sync::init_lock(lock, attr);
-                        }
-                    }
-                    #[cfg(any(feature = "no_pthread", windows))] {
-                        let b = &mut *(*src as *mut (bool, u64));
-                        b.0 = false;
-                        let lock = &mut b.1;
-                        std::intrinsics::atomic_store_rel(lock, 0);
-                    }
+            UnlockOnCommit(_) => {
+                self.unlock();
+            }
+            _ => {}
+        }
+    }
 
-                    *src = u64::MAX;
+    /// Unlocks the mutex if it is an
+    /// [`UnlockOnCommit`](./enum.LogEnum.html#variant.UnlockOnCommit) log
+    pub(crate) unsafe fn unlock(&mut self) {
+        if let UnlockOnCommit(src) = &mut self.0 {
+            if *src != u64::MAX {
+                log!(A, Magenta, "UNLOCK", "FOR:          v@{}", *src);
+                #[cfg(not(any(feature = "no_pthread", windows, miri, loom)))] {
+                    let b = &mut *(*src as *mut (bool, libc::pthread_mutex_t, libc::pthread_mutexattr_t));
+                    b.0 = false;
+                    let lock = &mut b.1;
+                    let attr = &mut b.2;
+                    let result = libc::pthread_mutex_unlock(lock);
+                    if result != 0 {
+                        crate::sync::init_lock(lock, attr);
+                    }
                 }
+                #[cfg(any(feature = "no_pthread", windows, miri, loom))] {
+                    let b = &mut *(*src as *mut (bool, crate::sync::LockWord));
+                    b.0 = false;
+                    b.1.release();
+                }
+
+                *src = u64::MAX;
             }
-            _ => {}
         }
     }
 
     /// Notify the owner that the log is created/cleared according to `v`
     #[inline]
     pub unsafe fn notify(&mut self, v: u8) {
-        if let DataLog(src, _, _) = self.0 {
-            if src != u64::MAX {
-                self.1.update(v);
+        match self.0 {
+            DataLog(src, _, _) | InlineLog(src, _, _) => {
+                if src != u64::MAX {
+                    self.1.update(v);
+                }
             }
+            _ => {}
         }
     }
 }
@@ -787,8 +1033,54 @@ impl<T: PSafe + ?Sized, A: MemPool> Logger<A> for T {
     }
 }
 
+impl<T: PSafe + Copy, A: MemPool> Logger<A> for T {
+    #[inline]
+    unsafe fn create_log(&self, journal: &Journal<A>, notifier: Notifier<A>) -> Ptr<Log<A>, A> {
+        if std::mem::size_of::<T>() <= INLINE_LOG_SIZE {
+            Log::create_inline(self, journal, notifier)
+        } else {
+            Log::create(self, journal, notifier)
+        }
+    }
+}
+
 impl<T: PSafe, A: MemPool> Logger<A> for [T] {
     unsafe fn create_log(&self, journal: &Journal<A>, notifier: Notifier<A>) -> Ptr<Log<A>, A> {
         Log::create_slice(self, journal, notifier)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::default::*;
+    use crate::stat::local_fences;
+    use crate::stm::{Logger, Notifier};
+
+    type P = Allocator;
+
+    #[test]
+    fn batch_takes_one_fence() {
+        let root = P::open::<[PCell<u64>; 8]>("log_batch.pool", O_CF).unwrap();
+        P::transaction(|j| unsafe {
+            // The first log may allocate a journal page
+            root[0].as_mut().create_log(j, Notifier::None);
+
+            let before = local_fences();
+            root[1].as_mut().create_log(j, Notifier::None);
+            assert_eq!(local_fences() - before, 1);
+
+            let before = local_fences();
+            j.batch(|| {
+                for c in &root[2..] {
+                    c.as_mut().create_log(j, Notifier::None);
+                }
+                assert_eq!(local_fences(), before);
+            });
+            assert_eq!(local_fences() - before, 1);
+            for c in root.iter() {
+                *c.as_mut() += 1;
+            }
+        }).unwrap();
+        assert!(root.iter().all(|c| c.get() == 1));
+    }
+}
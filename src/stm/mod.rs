@@ -1,24 +1,93 @@
 //! Software transactional memory APIs
 
 mod chaperon;
+pub(crate) mod fresh;
 mod journal;
 mod log;
+pub mod arena;
+#[cfg(feature = "audit")]
+pub mod audit;
+pub mod cancel;
+#[cfg(feature = "cdc")]
+pub mod cdc;
+pub mod group;
+pub mod isolation;
+pub mod limits;
+pub mod multi;
 pub mod pspd;
+pub mod relaxed;
+pub mod scratch;
+pub mod timestamp;
 pub mod vspd;
+#[cfg(feature = "wal")]
+pub mod wal;
+pub mod watchdog;
+pub mod wcheck;
 
 use crate::alloc::MemPool;
 use crate::result::Result;
 use crate::{TxInSafe,TxOutSafe};
 use std::panic::UnwindSafe;
 
+pub use cancel::CancellationToken;
+pub use limits::{TxConfig, TxLimit};
 pub use chaperon::*;
 pub use journal::*;
 pub use log::*;
+pub use timestamp::Committed;
+
+/// Stops the background activities of pool `A` which need the pool, such
+/// as the relaxed-durability flusher and the write-ahead log
+///
+/// It is called when the pool is closed.
+#[doc(hidden)]
+pub fn on_close<A: MemPool>() {
+    relaxed::shutdown::<A>();
+    #[cfg(feature = "wal")] {
+        let _ = wal::stop::<A>();
+    }
+}
 
 /// Atomically executes commands
 /// 
 /// See [`MemPool::transaction()`](../alloc/trait.MemPool.html#method.transaction)
-/// for more details.
+/// for more details. A function or a `&self` method can be made a transaction
+/// using the [`transactional`] attribute, which injects the journal as `j`.
+/// 
+/// # Examples
+/// 
+/// ```
+/// use corundum::default::*;
+/// use corundum::transactional;
+/// 
+/// type P = Allocator;
+/// 
+/// #[derive(Root)]
+/// struct Counter {
+///     value: PRefCell<i32>
+/// }
+/// 
+/// impl Counter {
+///     #[transactional(pool = P)]
+///     fn inc(&self) -> i32 {
+///         let mut v = self.value.borrow_mut(j);
+///         *v += 1;
+///         *v
+///     }
+/// }
+/// 
+/// #[transactional(pool = P, journal = journal)]
+/// fn boxed(v: i32) -> i32 {
+///     *Pbox::new(v, journal)
+/// }
+/// 
+/// let root = P::open::<Counter>("foo.pool", O_CF).unwrap();
+/// assert_eq!(root.inc(), Ok(1));
+/// assert_eq!(root.inc(), Ok(2));
+/// assert_eq!(boxed(3), Ok(3));
+/// ```
+/// 
+/// [`transactional`]: ../attr.transactional.html
 pub fn transaction<T, F: FnOnce(&'static Journal<A>) -> T, A: MemPool>(body: F) -> Result<T>
 where
     F: TxInSafe + UnwindSafe,
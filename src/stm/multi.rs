@@ -0,0 +1,468 @@
+//! Multi-pool transactions without a chaperon file
+//!
+//! A [`Chaperon`] session makes the transactions of multiple pools atomic with
+//! the help of a separate session file, which has to be kept next to the pools
+//! for recovery. A multi-pool transaction ([`transaction_multi!`]) does not
+//! need any external file. The first pool in the list is the *coordinator*: it
+//! keeps a small table of commit decisions in the pool itself, and the journal
+//! of every participant refers to its entry in that table.
+//!
+//! The commit follows a two-phase protocol. The changes of all participants
+//! are flushed first, and every journal durably records the session id and
+//! the location of the decision. Then the coordinator records the decision,
+//! which is the commit point, and the participants are marked as committed
+//! and cleared. If a crash happens in between, the recovery procedure of each
+//! participant reads the decision from the coordinator's file, and either
+//! completes or rolls back its part. The decision is kept in the coordinator
+//! until all participants have recovered, in whatever order the pools are
+//! opened.
+//!
+//! The coordinator must be file-backed, and its file should stay at the same
+//! path until the participants are recovered. The decision table has
+//! [`MULTI_SLOTS`] entries; a multi-pool transaction fails if all of them are
+//! held by sessions whose participants are not recovered yet. The
+//! participants commit strictly, regardless of the [durability
+//! mode](../relaxed/index.html) and [group commit](../group/index.html)
+//! settings of their pools. A transaction on a pool which is not in the list
+//! is independent of the multi-pool transaction.
+//!
+//! # Examples
+//!
+//! ```
+//! use corundum::alloc::heap::*;
+//! use corundum::cell::PCell;
+//! use corundum::transaction_multi;
+//!
+//! corundum::pool!(pool1);
+//! corundum::pool!(pool2);
+//!
+//! type P1 = pool1::Allocator;
+//! type P2 = pool2::Allocator;
+//!
+//! let a = P1::open::<PCell<i32, P1>>("multi1.pool", O_CF).unwrap();
+//! let b = P2::open::<PCell<i32, P2>>("multi2.pool", O_CF).unwrap();
+//!
+//! transaction_multi![(P1, P2) |j1, j2| {
+//!     a.set(a.get() - 10, j1);
+//!     b.set(b.get() + 10, j2);
+//! }].unwrap();
+//!
+//! // Either both changes or none of them persist
+//! let res = transaction_multi![(P1, P2) |j1, j2| {
+//!     a.set(a.get() - 10, j1);
+//!     b.set(b.get() + 10, j2);
+//!     panic!("abort");
+//! }];
+//! assert!(res.is_err());
+//! assert_eq!(a.get() + b.get(), 0);
+//! assert_eq!(b.get(), 10);
+//! ```
+//!
+//! [`Chaperon`]: ../struct.Chaperon.html
+//! [`transaction_multi!`]: ../../macro.transaction_multi.html
+
+use crate::alloc::MemPool;
+use crate::cell::LazyCell;
+use crate::ll::{persist_obj, sfence};
+use crate::result::Result;
+use crate::stm::{Chaperon, Journal, JOURNAL_COMMITTED};
+use crate::{utils, Error, TxInSafe, TxOutSafe};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::panic::{self, AssertUnwindSafe, UnwindSafe};
+use std::sync::{Mutex, MutexGuard};
+
+/// The number of entries in the decision table of a coordinator pool
+pub const MULTI_SLOTS: usize = 64;
+
+/// The maximum length of the canonical path of a coordinator pool
+pub const COORD_PATH_LEN: usize = 256;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Slot {
+    sid: u64,
+    remaining: u64,
+}
+
+/// The persistent decision table of multi-pool transactions, which resides
+/// in every pool
+#[doc(hidden)]
+#[repr(C)]
+pub struct Decisions {
+    next_sid: u64,
+    slots: [Slot; MULTI_SLOTS],
+}
+
+impl Decisions {
+    #[doc(hidden)]
+    pub fn init(&mut self) {
+        self.next_sid = 0;
+        self.slots = [Slot { sid: 0, remaining: 0 }; MULTI_SLOTS];
+    }
+}
+
+/// The slots of every coordinator which are reserved by running sessions
+///
+/// The lock also serializes the decisions with the acknowledgements of
+/// recovering participants.
+static mut RESERVED: LazyCell<Mutex<HashMap<&'static str, u64>>> =
+    LazyCell::new(|| Mutex::new(HashMap::new()));
+
+fn reserved() -> MutexGuard<'static, HashMap<&'static str, u64>> {
+    match unsafe { RESERVED.lock() } {
+        Ok(g) => g,
+        Err(p) => p.into_inner()
+    }
+}
+
+struct Coordinator {
+    pool: &'static str,
+    path: [u8; COORD_PATH_LEN],
+    decisions: *mut Decisions,
+
+    /// The address of the beginning of the pool, which is mapped from the
+    /// beginning of the file
+    base: u64,
+}
+
+struct Member {
+    pool: &'static str,
+    prepare: unsafe fn(),
+    bind: unsafe fn(u64, &[u8; COORD_PATH_LEN], u64),
+    commit: unsafe fn(),
+    clear: unsafe fn(),
+    rollback: unsafe fn(),
+}
+
+unsafe fn prepare<A: MemPool>() {
+    if let Some((journal, _)) = Journal::<A>::current(false) {
+        let journal = utils::as_mut(journal);
+        journal.prepare_commit();
+        journal.flush_data();
+    }
+}
+
+unsafe fn bind<A: MemPool>(sid: u64, coord: &[u8; COORD_PATH_LEN], off: u64) {
+    if let Some((journal, _)) = Journal::<A>::current(false) {
+        utils::as_mut(journal).bind_coordinator(sid, coord, off);
+    }
+}
+
+unsafe fn commit<A: MemPool>() {
+    if let Some((journal, _)) = Journal::<A>::current(false) {
+        utils::as_mut(journal).finish_commit(
+            #[cfg(feature = "check_double_free")]
+            &mut *A::dealloc_history()
+        );
+    }
+}
+
+unsafe fn clear<A: MemPool>() {
+    if let Some((journal, count)) = Journal::<A>::current(false) {
+        *count -= 1;
+        if *count == 0 {
+            utils::as_mut(journal).clear(
+                #[cfg(feature = "check_double_free")]
+                &mut *A::dealloc_history()
+            );
+        }
+    }
+}
+
+unsafe fn rollback<A: MemPool>() {
+    A::rollback();
+}
+
+/// A running multi-pool transaction
+///
+/// It is created by [`transaction_multi!`](../../macro.transaction_multi.html)
+/// to hand out the journals of the participating pools.
+pub struct Session {
+    coordinator: RefCell<Option<Coordinator>>,
+    members: RefCell<Vec<Member>>,
+    error: RefCell<Option<Error>>,
+}
+
+impl Session {
+    fn fail(&self, err: Error) -> ! {
+        let msg = err.to_string();
+        *self.error.borrow_mut() = Some(err);
+        panic!("{}", msg);
+    }
+
+    /// Adds pool `A` to the transaction, and returns its journal
+    ///
+    /// The first pool becomes the coordinator. It panics (and the
+    /// transaction fails) if the pool is not open, it is already in a
+    /// transaction on the current thread, or it cannot be a coordinator.
+    pub fn join<A: MemPool>(&self) -> &'static Journal<A> {
+        unsafe {
+            if let Some((journal, count)) = Journal::<A>::current(false) {
+                if self.members.borrow().iter().any(|m| m.pool == A::name()) {
+                    return &*journal;
+                }
+                if *count > 0 {
+                    self.fail(Error::InvalidArgument(format!(
+                        "pool `{}` is already in a transaction", A::name())));
+                }
+            }
+            if !A::is_open() {
+                self.fail(Error::NotOpen);
+            }
+            if self.coordinator.borrow().is_none() {
+                let coordinator = coordinator::<A>().unwrap_or_else(|e| self.fail(e));
+                *self.coordinator.borrow_mut() = Some(coordinator);
+            }
+            let journal = Journal::<A>::current(true).unwrap();
+            *journal.1 += 1;
+            utils::as_mut(journal.0).unset(JOURNAL_COMMITTED);
+            self.members.borrow_mut().push(Member {
+                pool: A::name(),
+                prepare: prepare::<A>,
+                bind: bind::<A>,
+                commit: commit::<A>,
+                clear: clear::<A>,
+                rollback: rollback::<A>,
+            });
+            &*journal.0
+        }
+    }
+}
+
+fn coordinator<A: MemPool>() -> Result<Coordinator> {
+    let path = A::path().ok_or_else(|| Error::InvalidArgument(
+        format!("coordinator pool `{}` is not file-backed", A::name())))?;
+    let path = std::fs::canonicalize(&path)?;
+    let bytes = path.to_string_lossy().into_owned().into_bytes();
+    if bytes.len() >= COORD_PATH_LEN {
+        return Err(Error::InvalidArgument(format!(
+            "the path of coordinator pool `{}` is longer than {} bytes",
+            A::name(), COORD_PATH_LEN - 1)));
+    }
+    let mut path = [0u8; COORD_PATH_LEN];
+    path[..bytes.len()].copy_from_slice(&bytes);
+    Ok(Coordinator {
+        pool: A::name(),
+        path,
+        decisions: unsafe { A::multi_decisions() },
+        base: A::start(),
+    })
+}
+
+/// Executes a multi-pool transaction
+///
+/// It is the function behind
+/// [`transaction_multi!`](../../macro.transaction_multi.html), which should
+/// be used instead.
+pub fn transaction<T, F>(body: F) -> Result<T>
+where
+    F: FnOnce(&Session) -> T + TxInSafe + UnwindSafe,
+    T: TxOutSafe,
+{
+    if Chaperon::current().is_some() {
+        return Err(Error::ChaperonBusy);
+    }
+    let session = Session {
+        coordinator: RefCell::new(None),
+        members: RefCell::new(vec![]),
+        error: RefCell::new(None),
+    };
+    let res = panic::catch_unwind(AssertUnwindSafe(|| body(&session)));
+    let members = session.members.into_inner();
+    let err = match res {
+        Ok(res) => match session.coordinator.into_inner() {
+            Some(c) => match unsafe { decide(&c, &members) } {
+                Ok(()) => return Ok(res),
+                Err(e) => e,
+            },
+            None => return Ok(res),
+        },
+        Err(e) => session.error.into_inner().unwrap_or_else(|| Error::from_panic(&*e)),
+    };
+    for m in members.iter().rev() {
+        unsafe { (m.rollback)(); }
+    }
+    Err(err)
+}
+
+/// Commits the participants atomically; it leaves them intact if it fails
+unsafe fn decide(c: &Coordinator, members: &[Member]) -> Result<()> {
+    let slot = {
+        let mut reserved = reserved();
+        let mask = reserved.entry(c.pool).or_insert(0);
+        let d = &*c.decisions;
+        match (0..MULTI_SLOTS).find(|i| d.slots[*i].sid == 0 && *mask & (1 << i) == 0) {
+            Some(i) => {
+                *mask |= 1 << i;
+                i
+            }
+            None => return Err(Error::Other(format!(
+                "coordinator pool `{}` has no free decision slot", c.pool))),
+        }
+    };
+
+    for m in members {
+        (m.prepare)();
+    }
+
+    let mut reserved = reserved();
+    let d = &mut *c.decisions;
+    d.next_sid += 1;
+    let sid = d.next_sid;
+    persist_obj(&d.next_sid, false);
+    let off = &d.slots[slot] as *const Slot as u64 - c.base;
+    for m in members {
+        (m.bind)(sid, &c.path, off);
+    }
+    sfence();
+
+    // The commit point
+    let s = &mut d.slots[slot];
+    s.remaining = members.len() as u64;
+    persist_obj(&s.remaining, true);
+    s.sid = sid;
+    persist_obj(&s.sid, true);
+
+    for m in members {
+        (m.commit)();
+    }
+    s.sid = 0;
+    persist_obj(&s.sid, true);
+    if let Some(mask) = reserved.get_mut(c.pool) {
+        *mask &= !(1 << slot);
+    }
+    drop(reserved);
+
+    for m in members {
+        (m.clear)();
+    }
+    Ok(())
+}
+
+fn coord_path(coord: &[u8; COORD_PATH_LEN]) -> String {
+    let len = coord.iter().position(|b| *b == 0).unwrap_or(COORD_PATH_LEN);
+    String::from_utf8_lossy(&coord[..len]).into_owned()
+}
+
+fn read_slot(path: &str, off: u64) -> std::io::Result<Slot> {
+    let mut file = OpenOptions::new().read(true).open(path)?;
+    let mut buf = [0u8; 16];
+    file.seek(SeekFrom::Start(off))?;
+    file.read_exact(&mut buf)?;
+    let mut sid = [0u8; 8];
+    let mut remaining = [0u8; 8];
+    sid.copy_from_slice(&buf[..8]);
+    remaining.copy_from_slice(&buf[8..]);
+    Ok(Slot { sid: u64::from_ne_bytes(sid), remaining: u64::from_ne_bytes(remaining) })
+}
+
+/// Returns true if the coordinator decided to commit session `sid`
+pub(crate) fn decided(coord: &[u8; COORD_PATH_LEN], off: u64, sid: u64) -> bool {
+    let path = coord_path(coord);
+    let _lock = reserved();
+    let slot = read_slot(&path, off)
+        .expect(&format!("Missing coordinator pool `{}`", path));
+    slot.sid == sid
+}
+
+/// Tells the coordinator that a participant of session `sid` is recovered,
+/// so that the decision is dropped after the last one
+///
+/// It returns an error if the coordinator's file cannot be updated. The
+/// decision is then kept, and its slot stays held.
+pub(crate) fn acknowledge(coord: &[u8; COORD_PATH_LEN], off: u64, sid: u64) -> std::io::Result<()> {
+    let path = coord_path(coord);
+    let _lock = reserved();
+    let slot = match read_slot(&path, off) {
+        Ok(slot) if slot.sid == sid => slot,
+        _ => return Ok(()),
+    };
+    let remaining = slot.remaining.saturating_sub(1);
+    let mut file = OpenOptions::new().write(true).open(&path)?;
+    file.seek(SeekFrom::Start(off + 8))?;
+    file.write_all(&remaining.to_ne_bytes())?;
+    if remaining == 0 {
+        file.seek(SeekFrom::Start(off))?;
+        file.write_all(&0u64.to_ne_bytes())?;
+    }
+    file.sync_data()
+}
+
+/// Executes a transaction on multiple pools atomically
+///
+/// It takes a list of pool types and a closure-like body with one journal
+/// per pool. The first pool is the coordinator, which keeps the commit
+/// decision; no session file is needed. It returns the result of the body,
+/// or an error if any part of the transaction failed, in which case the
+/// changes in all pools are rolled back. See the
+/// [`multi`](./stm/multi/index.html) module for more details.
+///
+/// # Examples
+///
+/// ```
+/// # use corundum::alloc::heap::*;
+/// # use corundum::cell::PCell;
+/// # use corundum::transaction_multi;
+/// # corundum::pool!(pool1);
+/// # corundum::pool!(pool2);
+/// # type P1 = pool1::Allocator;
+/// # type P2 = pool2::Allocator;
+/// let a = P1::open::<PCell<i32, P1>>("tm1.pool", O_CF).unwrap();
+/// let b = P2::open::<PCell<i32, P2>>("tm2.pool", O_CF).unwrap();
+///
+/// let sum = transaction_multi![(P1, P2) |j1, j2| {
+///     a.set(1, j1);
+///     b.set(2, j2);
+///     a.get() + b.get()
+/// }].unwrap();
+/// assert_eq!(sum, 3);
+/// ```
+#[macro_export]
+macro_rules! transaction_multi {
+    (($($p:ty),+ $(,)?) |$($j:ident),+ $(,)?| $body:expr) => {
+        $crate::stm::multi::transaction(|__session| {
+            $( let $j = __session.join::<$p>(); )+
+            $body
+        })
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alloc::heap::*;
+    use crate::cell::PCell;
+
+    crate::pool!(mp1);
+    crate::pool!(mp2);
+
+    type P1 = mp1::Allocator;
+    type P2 = mp2::Allocator;
+
+    #[test]
+    fn multi_commit_and_abort() {
+        let a = P1::open::<PCell<u64, P1>>("multi_test1.pool", O_CF).unwrap();
+        let b = P2::open::<PCell<u64, P2>>("multi_test2.pool", O_CF).unwrap();
+
+        crate::transaction_multi![(P1, P2) |j1, j2| {
+            a.set(1, j1);
+            P2::transaction(|j| b.set(1, j)).unwrap();
+            b.set(b.get() + 1, j2);
+        }].unwrap();
+        assert_eq!((a.get(), b.get()), (1, 2));
+
+        assert!(crate::transaction_multi![(P1, P2) |j1, j2| {
+            a.set(10, j1);
+            b.set(10, j2);
+            panic!("abort");
+        }].is_err());
+        assert_eq!((a.get(), b.get()), (1, 2));
+
+        // Joining a pool which is already in a transaction fails
+        assert!(P1::transaction(|_| {
+            crate::transaction_multi![(P1, P2) |_j1, _j2| {}].is_err()
+        }).unwrap());
+    }
+}
@@ -1,8 +1,14 @@
 //! Persistent Scratchpad Memory
-//! 
-//! This features is still under development. To enable it, you need to add
-//! `"use_pspd"` to the feature list.
-//! 
+//!
+//! The scratchpad keeps the redo drafts of a transaction when the
+//! `"use_pspd"` feature is enabled. Instead of logging the original data, the
+//! changes are written to a persistent buffer in the pool. At commit, the
+//! drafts are applied to the original locations; on rollback, they are
+//! discarded.
+//!
+//! This feature is still under development, and it is internal to the
+//! journal. For temporary buffers which live as long as a transaction, see
+//! [`stm::scratch`](../scratch/index.html).
 
 use crate::cell::LazyCell;
 use crate::alloc::MemPool;
@@ -0,0 +1,336 @@
+//! Relaxed-durability transactions with a background flusher
+//!
+//! By default, a transaction is durable when it returns: the changes are
+//! flushed and the journal is committed before the locks are released. Some
+//! workloads tolerate losing the last few milliseconds of work in exchange
+//! for a shorter commit path. With [`TxDurability::Relaxed`], a committing
+//! transaction only releases its locks and hands its journal to a background
+//! flusher. The flusher periodically flushes the pending transactions in
+//! their commit order, marks them as committed, and advances the *stable
+//! epoch* of the pool.
+//!
+//! A crash loses a suffix of the relaxed transactions, but never a
+//! transaction without the ones committed before it: the recovery procedure
+//! rolls back the unflushed transactions in the reverse order of their
+//! commits, and the pool is restored to the state after the last flushed
+//! one. [`MemPoolTraits::quiesce()`] flushes all pending transactions on
+//! demand.
+//!
+//! The durability mode is pool-wide, and it requires [commit
+//! timestamps](../timestamp/index.html) to order the transactions; pools
+//! without timestamps, chaperoned transactions, and the `pin_journals`
+//! feature always commit strictly. Closing the pool quiesces it and
+//! restores the strict mode.
+//!
+//! # Examples
+//!
+//! ```
+//! use corundum::default::*;
+//! use corundum::stm::relaxed::TxDurability;
+//! use std::time::Duration;
+//!
+//! type P = Allocator;
+//!
+//! let root = P::open::<PCell<u64>>("relaxed.pool", O_CF).unwrap();
+//! P::set_tx_durability(TxDurability::Relaxed(Duration::from_millis(5)));
+//!
+//! for i in 0..100 {
+//!     P::transaction(|j| root.set(i, j)).unwrap();
+//! }
+//!
+//! // All transactions above are durable after `quiesce()`
+//! let epoch = P::current_epoch();
+//! P::quiesce();
+//! assert!(P::stable_epoch() >= epoch);
+//! assert_eq!(root.get(), 99);
+//! ```
+//!
+//! [`MemPoolTraits::quiesce()`]: ../../alloc/trait.MemPoolTraits.html#method.quiesce
+
+use crate::alloc::MemPool;
+use crate::cell::LazyCell;
+use crate::ptr::Ptr;
+use crate::stm::Journal;
+use crate::utils;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// The durability mode of the transactions of a pool
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TxDurability {
+    /// A transaction is durable when it returns (default)
+    Strict,
+
+    /// A transaction is visible to other threads when it returns, and
+    /// becomes durable when the background flusher runs, at most about the
+    /// given interval later
+    Relaxed(Duration),
+}
+
+impl Default for TxDurability {
+    fn default() -> Self {
+        TxDurability::Strict
+    }
+}
+
+struct State {
+    mode: TxDurability,
+    pending: Vec<u64>,
+    current: u64,
+    stable: u64,
+    stop: bool,
+    flusher: Option<JoinHandle<()>>,
+}
+
+struct Epochs {
+    state: Mutex<State>,
+    wake: Condvar,
+    round: Mutex<()>,
+}
+
+/// The number of pools in relaxed mode, to keep the strict commit path free
+/// of the registry lock
+static RELAXED: AtomicUsize = AtomicUsize::new(0);
+
+static mut EPOCHS: LazyCell<Mutex<HashMap<&'static str, Arc<Epochs>>>> =
+    LazyCell::new(|| Mutex::new(HashMap::new()));
+
+fn lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    match m.lock() {
+        Ok(g) => g,
+        Err(p) => p.into_inner()
+    }
+}
+
+fn epochs(pool: &'static str) -> Arc<Epochs> {
+    let mut map = match unsafe { EPOCHS.lock() } {
+        Ok(g) => g,
+        Err(p) => p.into_inner()
+    };
+    map.entry(pool).or_insert_with(|| Arc::new(Epochs {
+        state: Mutex::new(State {
+            mode: TxDurability::Strict,
+            pending: vec![],
+            current: 1,
+            stable: 0,
+            stop: false,
+            flusher: None,
+        }),
+        wake: Condvar::new(),
+        round: Mutex::new(()),
+    })).clone()
+}
+
+/// Returns the durability mode of pool `A`
+pub(crate) fn mode<A: MemPool>() -> TxDurability {
+    if RELAXED.load(Ordering::Acquire) == 0 {
+        TxDurability::Strict
+    } else {
+        lock(&epochs(A::name()).state).mode
+    }
+}
+
+/// Sets the durability mode of pool `A`
+///
+/// Switching to the strict mode stops the flusher and quiesces the pool. The
+/// mode changes only after the pending journals are flushed, so that a strict
+/// commit never becomes durable before the relaxed commits it may observe.
+pub(crate) fn set_mode<A: MemPool>(mode: TxDurability) {
+    let e = epochs(A::name());
+    let mut s = lock(&e.state);
+    match mode {
+        TxDurability::Relaxed(_) => {
+            if s.mode == TxDurability::Strict {
+                RELAXED.fetch_add(1, Ordering::AcqRel);
+            }
+            s.mode = mode;
+            if s.flusher.is_none() {
+                let e = e.clone();
+                s.flusher = Some(thread::Builder::new()
+                    .name(format!("{}-flusher", A::name()))
+                    .spawn(move || run::<A>(e))
+                    .expect("cannot spawn the flusher thread"));
+            }
+            e.wake.notify_all();
+        }
+        TxDurability::Strict => {
+            s.stop = true;
+            let flusher = s.flusher.take();
+            e.wake.notify_all();
+            drop(s);
+            if let Some(flusher) = flusher {
+                let _ = flusher.join();
+            }
+
+            // The relaxed commits go on until the mode changes; it changes
+            // while holding the round lock, once nothing is pending
+            let _round = lock(&e.round);
+            loop {
+                drain::<A>(&e);
+                let mut s = lock(&e.state);
+                if s.pending.is_empty() {
+                    if s.mode != TxDurability::Strict {
+                        s.mode = TxDurability::Strict;
+                        RELAXED.fetch_sub(1, Ordering::AcqRel);
+                    }
+                    s.stop = false;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn run<A: MemPool>(e: Arc<Epochs>) {
+    loop {
+        {
+            let s = lock(&e.state);
+            let interval = match s.mode {
+                TxDurability::Relaxed(interval) if !s.stop => interval,
+                _ => return,
+            };
+            let s = match e.wake.wait_timeout(s, interval) {
+                Ok((g, _)) => g,
+                Err(p) => p.into_inner().0
+            };
+            if s.stop {
+                return;
+            }
+        }
+        flush::<A>(&e);
+    }
+}
+
+/// Finishes the commit of all pending transactions in their commit order,
+/// and returns the stable epoch
+fn flush<A: MemPool>(e: &Epochs) -> u64 {
+    let _round = lock(&e.round);
+    drain::<A>(e)
+}
+
+/// Finishes the commit of the transactions which are pending so far; the
+/// caller holds the round lock
+fn drain<A: MemPool>(e: &Epochs) -> u64 {
+    let (batch, epoch) = {
+        let mut s = lock(&e.state);
+        let epoch = s.current;
+        s.current += 1;
+        (std::mem::take(&mut s.pending), epoch)
+    };
+    for off in batch {
+        unsafe {
+            let journal = utils::as_mut(Ptr::<Journal<A>, A>::from_off_unchecked(off).as_ptr());
+            journal.finish_commit(
+                #[cfg(feature = "check_double_free")]
+                &mut *A::dealloc_history()
+            );
+            journal.clear(
+                #[cfg(feature = "check_double_free")]
+                &mut *A::dealloc_history()
+            );
+        }
+    }
+    let mut s = lock(&e.state);
+    s.stable = epoch;
+    epoch
+}
+
+/// Commits the top-level transaction of the current thread with the relaxed
+/// durability, if the pool is in the relaxed mode. It returns `false` if the
+/// journal should be committed strictly.
+///
+/// The journal is detached from the thread, so that the next transaction
+/// takes a new one, and it is queued for the flusher. The locks are released
+/// only after the journal is queued; hence, the queue follows the order in
+/// which the transactions may observe each other.
+pub(crate) unsafe fn commit<A: MemPool>(journal: &mut Journal<A>) -> bool {
+    if RELAXED.load(Ordering::Acquire) == 0 {
+        return false;
+    }
+    let e = epochs(A::name());
+    {
+        let mut s = lock(&e.state);
+        if cfg!(feature = "pin_journals") || s.mode == TxDurability::Strict {
+            // A strict commit may observe the pending ones, so they become
+            // durable first
+            if !s.pending.is_empty() {
+                drop(s);
+                flush::<A>(&e);
+            }
+            return false;
+        }
+        journal.prepare_commit();
+        if journal.raw_commit_ts() == 0 {
+            // Without a timestamp, the commit order cannot be recovered, so
+            // it commits strictly after the pending ones
+            drop(s);
+            flush::<A>(&e);
+            journal.finish_commit(
+                #[cfg(feature = "check_double_free")]
+                &mut *A::dealloc_history()
+            );
+            journal.clear(
+                #[cfg(feature = "check_double_free")]
+                &mut *A::dealloc_history()
+            );
+            return true;
+        }
+        s.pending.push(A::off_unchecked(journal));
+    }
+    A::journals(|journals| {
+        journals.remove(&thread::current().id());
+    });
+    journal.release_locks();
+    true
+}
+
+/// Flushes all pending transactions of pool `A`, and returns the stable epoch
+pub(crate) fn quiesce<A: MemPool>() -> u64 {
+    flush::<A>(&epochs(A::name()))
+}
+
+/// Returns the latest epoch of pool `A` whose transactions are durable
+pub(crate) fn stable_epoch<A: MemPool>() -> u64 {
+    lock(&epochs(A::name()).state).stable
+}
+
+/// Returns the epoch of pool `A` which the next relaxed commit belongs to
+pub(crate) fn current_epoch<A: MemPool>() -> u64 {
+    lock(&epochs(A::name()).state).current
+}
+
+/// Stops the flusher of pool `A` and makes all of its transactions durable
+///
+/// It is called when the pool is closed.
+#[doc(hidden)]
+pub fn shutdown<A: MemPool>() {
+    if RELAXED.load(Ordering::Acquire) != 0 || !lock(&epochs(A::name()).state).pending.is_empty() {
+        set_mode::<A>(TxDurability::Strict);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TxDurability;
+    use crate::default::*;
+    use std::time::Duration;
+
+    #[test]
+    fn abort_keeps_pending_commits() {
+        let root = Allocator::open::<PCell<u64>>("relaxed_abort.pool", O_CF).unwrap();
+        Allocator::set_tx_durability(TxDurability::Relaxed(Duration::from_secs(60)));
+        Allocator::transaction(|j| root.set(1, j)).unwrap();
+        assert!(Allocator::transaction(|j| {
+            root.set(2, j);
+            panic!("abort");
+        }).is_err());
+        assert_eq!(root.get(), 1);
+        let epoch = Allocator::current_epoch();
+        assert!(Allocator::quiesce() >= epoch);
+        Allocator::set_tx_durability(TxDurability::Strict);
+        assert_eq!(root.get(), 1);
+    }
+}
@@ -0,0 +1,205 @@
+//! Transaction-lifetime scratch buffers
+//!
+//! Some operations need large temporary buffers while they run, e.g., the
+//! runs of an external merge sort, or a staging area for a bulk update. They
+//! do not fit in DRAM, or they should not be lost if the transaction is
+//! interrupted by a crash midway. [`Journal::scratch()`] allocates such a
+//! buffer in the pool, and returns a [`Scratch`] guard to it. The buffers of a
+//! transaction live until it commits or rolls back, and then they are freed
+//! all together. If the program crashes, they are freed by the recovery
+//! procedure. The guard is not [`TxOutSafe`], so the buffer cannot be used
+//! after the transaction body returns.
+//!
+//! The buffers are carved out of large chunks which the journal allocates
+//! from the pool, so allocating a buffer usually costs a pointer bump, and
+//! freeing it costs nothing. The contents of a buffer are neither logged nor
+//! flushed; they are not a part of the transaction.
+//!
+//! # Scratchpads of the journal
+//!
+//! The [`pspd`] and [`vspd`] modules are unrelated to these buffers. They
+//! hold the redo drafts of the experimental `use_pspd` and `use_vspd`
+//! features, which keep the changes of a transaction away from the original
+//! data until it commits.
+//!
+//! # Examples
+//!
+//! ```
+//! use corundum::default::*;
+//!
+//! type P = Allocator;
+//!
+//! let root = P::open::<PRefCell<PVec<u64>>>("scratch.pool", O_CF).unwrap();
+//!
+//! P::transaction(|j| {
+//!     let input = [5u64, 3, 9, 1];
+//!     let mut tmp = j.scratch(input.len(), 0u64).unwrap(); // freed when the transaction ends
+//!     tmp.copy_from_slice(&input);
+//!     tmp.sort_unstable();
+//!     root.borrow_mut(j).extend_from_slice(&tmp, j);
+//! }).unwrap();
+//!
+//! assert_eq!(root.borrow().as_slice(), &[1, 3, 5, 9]);
+//! ```
+//!
+//! [`Journal::scratch()`]: ../struct.Journal.html#method.scratch
+//! [`Scratch`]: ./struct.Scratch.html
+//! [`TxOutSafe`]: ../../trait.TxOutSafe.html
+//! [`pspd`]: ../pspd/index.html
+//! [`vspd`]: ../vspd/index.html
+
+use crate::alloc::MemPool;
+use crate::ll::*;
+use crate::ptr::Ptr;
+use crate::{AllocError, TxOutSafe};
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::mem;
+
+/// The minimum capacity of a chunk in bytes
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[repr(C)]
+struct Chunk<A: MemPool> {
+    next: Ptr<Chunk<A>, A>,
+    cap: usize,
+    len: usize,
+}
+
+impl<A: MemPool> Chunk<A> {
+    #[inline]
+    fn bump(&mut self, size: usize, align: usize) -> Option<*mut u8> {
+        let base = self as *mut Self as usize + mem::size_of::<Self>();
+        let start = (base + self.len + align - 1) & !(align - 1);
+        if start + size > base + self.cap {
+            None
+        } else {
+            self.len = start + size - base;
+            Some(start as *mut u8)
+        }
+    }
+}
+
+/// A scratch buffer of a transaction
+///
+/// It is returned by [`Journal::scratch()`], and dereferences to the slice of
+/// the buffer. The buffer is freed when the transaction ends, so the guard
+/// cannot be returned from the transaction body.
+///
+/// [`Journal::scratch()`]: ../struct.Journal.html#method.scratch
+pub struct Scratch<'a, T, A: MemPool> {
+    buf: &'a mut [T],
+    phantom: PhantomData<A>,
+}
+
+impl<T, A: MemPool> !TxOutSafe for Scratch<'_, T, A> {}
+
+impl<'a, T, A: MemPool> Scratch<'a, T, A> {
+    pub(crate) fn new(buf: &'a mut [T]) -> Self {
+        Self { buf, phantom: PhantomData }
+    }
+}
+
+impl<T, A: MemPool> Deref for Scratch<'_, T, A> {
+    type Target = [T];
+
+    #[inline]
+    fn deref(&self) -> &[T] {
+        self.buf
+    }
+}
+
+impl<T, A: MemPool> DerefMut for Scratch<'_, T, A> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.buf
+    }
+}
+
+/// The list of the scratch chunks of a journal
+pub(crate) struct Chunks<A: MemPool> {
+    head: Ptr<Chunk<A>, A>,
+}
+
+impl<A: MemPool> Chunks<A> {
+    pub(crate) fn new() -> Self {
+        Self { head: Ptr::dangling() }
+    }
+
+    /// Allocates `size` bytes aligned to `align` from the last chunk, or from
+    /// a new chunk if it is full
+    pub(crate) unsafe fn alloc(&mut self, size: usize, align: usize) -> Result<*mut u8, AllocError> {
+        if let Some(chunk) = self.head.as_option() {
+            if let Some(p) = chunk.bump(size, align) {
+                return Ok(p);
+            }
+        }
+        let cap = size.checked_add(align)
+            .filter(|n| n.checked_add(mem::size_of::<Chunk<A>>()).is_some())
+            .ok_or_else(|| AllocError::new(size))?;
+        let cap = usize::max(CHUNK_SIZE, cap);
+        let (p, off, len, z) = A::pre_alloc(mem::size_of::<Chunk<A>>() + cap);
+        if p.is_null() {
+            return Err(AllocError::new(size));
+        }
+        let chunk = &mut *(p as *mut Chunk<A>);
+        chunk.next = self.head;
+        chunk.cap = len - mem::size_of::<Chunk<A>>();
+        chunk.len = 0;
+        persist_obj_with_log::<_,A>(chunk, false);
+        A::log64(A::off_unchecked(self.head.off_ref()), off, z);
+        A::perform(z);
+        Ok(chunk.bump(size, align).unwrap())
+    }
+
+    /// Frees all chunks
+    pub(crate) unsafe fn clear(&mut self) {
+        while let Some(chunk) = self.head.clone().as_option() {
+            let nxt = chunk.next;
+            let size = mem::size_of::<Chunk<A>>() + chunk.cap;
+            let z = A::pre_dealloc(chunk.as_mut_ptr() as *mut u8, size);
+            A::log64(A::off_unchecked(self.head.off_ref()), nxt.off(), z);
+            A::perform(z);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::default::*;
+
+    type P = Allocator;
+
+    #[test]
+    fn scratch_is_freed() {
+        let _pool = P::open_no_root("scratch_test.pool", O_CF).unwrap();
+        P::transaction(|j| {
+            let _ = j.scratch(1, 0u8).unwrap();
+        }).unwrap();
+        let used = P::used();
+
+        P::transaction(|j| {
+            let a = j.scratch(1000, 1u32).unwrap();
+            let b = j.scratch(100_000, 2u64).unwrap();
+            assert!(a.iter().all(|v| *v == 1));
+            assert!(b.iter().all(|v| *v == 2));
+            assert_eq!(b.as_ptr() as usize % std::mem::align_of::<u64>(), 0);
+        }).unwrap();
+        assert_eq!(P::used(), used);
+
+        assert!(P::transaction(|j| {
+            let _ = j.scratch(10, 0u8).unwrap();
+            panic!("abort");
+        }).is_err());
+        assert_eq!(P::used(), used);
+    }
+
+    #[test]
+    fn scratch_overflow_is_an_error() {
+        let _pool = P::open_no_root("scratch_overflow.pool", O_CF).unwrap();
+        P::transaction(|j| {
+            assert!(j.scratch(usize::MAX / 2, 0u64).is_err());
+            assert!(j.scratch(usize::MAX - 8, 0u8).is_err());
+        }).unwrap();
+    }
+}
@@ -0,0 +1,120 @@
+//! Durable commit timestamps
+//!
+//! Every pool which supports timestamps keeps a durable counter which is
+//! incremented once per committed top-level transaction. The assigned value is
+//! the commit timestamp of the transaction. It is persisted before the
+//! transaction is marked as committed, so timestamps are monotonically
+//! increasing across restarts and recoveries, although there may be gaps
+//! (e.g., if a crash happens in the middle of a commit).
+//!
+//! The timestamp of a transaction is known only after it commits. Use
+//! [`MemPool::timestamped_transaction()`] to obtain it along with the result
+//! of the transaction. It can be used to order the transactions of different
+//! threads, e.g., for building external change feeds.
+//!
+//! # Examples
+//!
+//! ```
+//! use corundum::default::*;
+//!
+//! type P = Allocator;
+//!
+//! let root = P::open::<PCell<i32>>("foo.pool", O_CF).unwrap();
+//!
+//! let first = P::timestamped_transaction(|j| root.set(1, j)).unwrap();
+//! let second = P::timestamped_transaction(|j| root.set(2, j)).unwrap();
+//!
+//! assert!(first.commit_ts() < second.commit_ts());
+//! assert_eq!(second.commit_ts(), Some(P::commit_ts()));
+//! ```
+//!
+//! [`MemPool::timestamped_transaction()`]: ../../alloc/trait.MemPoolTraits.html#method.timestamped_transaction
+
+use std::cell::RefCell;
+use std::ops::Deref;
+
+thread_local! {
+    static LAST: RefCell<Vec<(&'static str, u64)>> = RefCell::new(Vec::new());
+}
+
+/// Remembers the commit timestamp of the current thread's transaction on
+/// pool `pool`
+pub(crate) fn record(pool: &'static str, ts: u64) {
+    LAST.with(|l| {
+        let mut l = l.borrow_mut();
+        if let Some(e) = l.iter_mut().find(|e| e.0 == pool) {
+            e.1 = ts;
+        } else {
+            l.push((pool, ts));
+        }
+    })
+}
+
+/// Takes the commit timestamp of the current thread's last transaction on
+/// pool `pool`, if it is not taken yet
+pub(crate) fn take(pool: &'static str) -> Option<u64> {
+    LAST.with(|l| {
+        let mut l = l.borrow_mut();
+        let i = l.iter().position(|e| e.0 == pool)?;
+        Some(l.swap_remove(i).1)
+    })
+}
+
+/// The result of a committed transaction along with its commit timestamp
+///
+/// It is returned by [`MemPool::timestamped_transaction()`], and dereferences
+/// to the result of the transaction. See the [module-level
+/// documentation](./index.html) for more details.
+///
+/// [`MemPool::timestamped_transaction()`]: ../../alloc/trait.MemPoolTraits.html#method.timestamped_transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Committed<T> {
+    value: T,
+    ts: Option<u64>,
+}
+
+impl<T> Committed<T> {
+    pub(crate) fn new(value: T, ts: Option<u64>) -> Self {
+        Self { value, ts }
+    }
+
+    /// Returns the commit timestamp of the transaction
+    ///
+    /// It is `None` if the transaction was nested in another transaction, in
+    /// which case the commit is postponed to the outermost transaction, or if
+    /// the pool does not support timestamps.
+    pub fn commit_ts(&self) -> Option<u64> {
+        self.ts
+    }
+
+    /// Consumes the wrapper and returns the result of the transaction
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T> Deref for Committed<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::default::*;
+
+    #[test]
+    fn nested_commit_ts() {
+        let _pool = Allocator::open_no_root("commit_ts.pool", O_CF).unwrap();
+        let outer = Allocator::timestamped_transaction(|_| {
+            Allocator::timestamped_transaction(|_| ()).unwrap().commit_ts()
+        }).unwrap();
+        assert_eq!(*outer, None);
+        assert!(outer.commit_ts().is_some());
+
+        let next = Allocator::timestamped_transaction(|_| ()).unwrap();
+        assert!(next.commit_ts() > outer.commit_ts());
+    }
+}
@@ -0,0 +1,448 @@
+//! Write-ahead value log and point-in-time recovery
+//!
+//! Once it is [started], the write-ahead log (WAL) of a pool keeps a base
+//! image of the pool, and appends a redo record of every committed
+//! transaction to a separate file: the new contents of the logged objects,
+//! the new allocations, and the reference counters which the transaction
+//! changed, followed by a commit record with the transaction id (the [commit
+//! timestamp]) and the wall-clock time. The allocator's changes are
+//! appended as they happen. Together, the base image and the log are a
+//! backup of the pool, and [`restore()`] reconstructs the pool as of any
+//! committed transaction, like the point-in-time recovery of a database.
+//!
+//! A restored pool is consistent, but it may leak the memory which the
+//! transactions running at the restore point had allocated. The log is
+//! written through a buffer and flushed at every commit, but it is not
+//! synchronized with the storage until the log is [stopped].
+//!
+//! The records of concurrent transactions are appended in the order in
+//! which they finish, which may differ from the order of their ids. A
+//! restore point therefore selects the longest prefix of the log in which
+//! all transactions qualify.
+//!
+//! It is enabled by the `wal` feature.
+//!
+//! # Examples
+//!
+//! ```
+//! use corundum::default::*;
+//! use corundum::stm::wal::RestorePoint;
+//!
+//! type P = Allocator;
+//!
+//! let root = P::open::<PCell<u64>>("wal.pool", O_CF).unwrap();
+//! P::start_wal("wal.log", "wal.base").unwrap();
+//!
+//! P::transaction(|j| root.set(1, j)).unwrap();
+//! let tx = P::commit_ts();
+//! P::transaction(|j| root.set(2, j)).unwrap();
+//!
+//! P::stop_wal().unwrap();
+//! drop(root);
+//!
+//! let info = P::restore_to("wal.base", "wal.log", RestorePoint::Tx(tx), "restored.pool").unwrap();
+//! assert_eq!(info.tx, tx);
+//!
+//! let root = P::open::<PCell<u64>>("restored.pool", 0).unwrap();
+//! assert_eq!(root.get(), 1);
+//! ```
+//!
+//! [started]: ../../alloc/trait.MemPoolTraits.html#method.start_wal
+//! [stopped]: ../../alloc/trait.MemPoolTraits.html#method.stop_wal
+//! [commit timestamp]: ../timestamp/index.html
+//! [`restore()`]: ./fn.restore.html
+
+use crate::alloc::MemPool;
+use crate::cell::LazyCell;
+use crate::result::Result;
+use crate::stm::{Journal, LogEnum};
+use crate::Error;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MAGIC: &[u8; 8] = b"CRNDWAL\0";
+const VERSION: u64 = 1;
+
+const WORD: u8 = 1;
+const BYTES: u8 = 2;
+const COMMIT: u8 = 3;
+
+/// A point in the history of a pool to restore
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RestorePoint {
+    /// Right after the transaction with the given id
+    Tx(u64),
+
+    /// The last transaction which committed at or before the given time
+    Time(SystemTime),
+
+    /// The last transaction in the log
+    Latest,
+}
+
+/// The result of a [`restore()`](./fn.restore.html)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RestoreInfo {
+    /// The id of the last restored transaction, or 0 if none is restored
+    pub tx: u64,
+
+    /// The commit time of the last restored transaction
+    pub time: Option<SystemTime>,
+
+    /// The number of restored transactions
+    pub commits: usize,
+}
+
+struct Wal {
+    out: BufWriter<File>,
+    seq: u64,
+    error: Option<io::Error>,
+}
+
+impl Wal {
+    fn write(&mut self, buf: &[u8]) {
+        if self.error.is_none() {
+            if let Err(e) = self.out.write_all(buf) {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    fn word(&mut self, off: u64, val: u64) {
+        self.write(&[WORD]);
+        self.write(&off.to_le_bytes());
+        self.write(&val.to_le_bytes());
+    }
+
+    fn bytes(&mut self, off: u64, data: &[u8]) {
+        self.write(&[BYTES]);
+        self.write(&off.to_le_bytes());
+        self.write(&(data.len() as u64).to_le_bytes());
+        self.write(data);
+    }
+
+    fn commit(&mut self, tx: u64, time_ns: u64) {
+        self.write(&[COMMIT]);
+        self.write(&tx.to_le_bytes());
+        self.write(&time_ns.to_le_bytes());
+        if self.error.is_none() {
+            if let Err(e) = self.out.flush() {
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+/// The number of active logs, to quickly skip recording
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+static mut WALS: LazyCell<Mutex<HashMap<&'static str, Wal>>> =
+    LazyCell::new(|| Mutex::new(HashMap::new()));
+
+fn registry() -> std::sync::MutexGuard<'static, HashMap<&'static str, Wal>> {
+    match unsafe { WALS.lock() } {
+        Ok(g) => g,
+        Err(p) => p.into_inner()
+    }
+}
+
+/// Indicates if any pool has an active log
+#[inline]
+pub(crate) fn is_active() -> bool {
+    ACTIVE.load(Ordering::Acquire) != 0
+}
+
+/// Starts the write-ahead log of pool `A`
+///
+/// It writes the current image of the pool to `base`, and creates the log
+/// file `wal`. It should be called while no other thread runs a
+/// transaction on the pool.
+///
+/// # Errors
+///
+/// It returns [`NotOpen`] if the pool is not open, [`UncommittedTransaction`]
+/// if a transaction is running, [`InvalidArgument`] if the pool already has
+/// an active log, or an [`Io`] error if a file cannot be written.
+///
+/// [`NotOpen`]: ../../enum.Error.html#variant.NotOpen
+/// [`UncommittedTransaction`]: ../../enum.Error.html#variant.UncommittedTransaction
+/// [`InvalidArgument`]: ../../enum.Error.html#variant.InvalidArgument
+/// [`Io`]: ../../enum.Error.html#variant.Io
+pub fn start<A: MemPool>(wal: &str, base: &str) -> Result<()> {
+    if !A::is_open() {
+        return Err(Error::NotOpen);
+    }
+    crate::stm::relaxed::quiesce::<A>();
+
+    let mut map = registry();
+    if map.contains_key(A::name()) {
+        return Err(Error::InvalidArgument(
+            format!("pool `{}` already has an active write-ahead log", A::name())));
+    }
+    if unsafe { !A::journals(|journals| journals.is_empty()) } {
+        return Err(Error::UncommittedTransaction);
+    }
+
+    // The allocator's changes wait for the registry, and they are recorded
+    // after the base image is taken
+    ACTIVE.fetch_add(1, Ordering::AcqRel);
+    let res = (|| -> io::Result<Wal> {
+        let len = A::end() - A::start();
+        let image = unsafe { std::slice::from_raw_parts(A::start() as *const u8, len as usize) };
+        std::fs::write(base, image)?;
+        let mut out = BufWriter::new(File::create(wal)?);
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        out.write_all(&len.to_le_bytes())?;
+        out.write_all(&unsafe { A::off_unchecked(A::journals_head()) }.to_le_bytes())?;
+        out.flush()?;
+        Ok(Wal { out, seq: 0, error: None })
+    })();
+    match res {
+        Ok(wal) => {
+            map.insert(A::name(), wal);
+            Ok(())
+        }
+        Err(e) => {
+            ACTIVE.fetch_sub(1, Ordering::AcqRel);
+            Err(e.into())
+        }
+    }
+}
+
+/// Stops the write-ahead log of pool `A`, and synchronizes it with the
+/// storage
+///
+/// # Errors
+///
+/// It returns an [`Io`] error if any record could not be written.
+///
+/// [`Io`]: ../../enum.Error.html#variant.Io
+pub fn stop<A: MemPool>() -> Result<()> {
+    let wal = registry().remove(A::name());
+    match wal {
+        Some(mut wal) => {
+            ACTIVE.fetch_sub(1, Ordering::AcqRel);
+            if let Some(e) = wal.error.take() {
+                return Err(e.into());
+            }
+            wal.out.flush()?;
+            wal.out.get_ref().sync_all()?;
+            Ok(())
+        }
+        None => Ok(())
+    }
+}
+
+/// Records the allocator's changes to 64-bit words of pool `A`
+pub(crate) fn record_words<A: MemPool>(words: &[(u64, u64)]) {
+    if let Some(wal) = registry().get_mut(A::name()) {
+        for (off, val) in words {
+            wal.word(*off, *val);
+        }
+    }
+}
+
+/// Records the new contents of the objects which the committing transaction
+/// changed, followed by a commit record
+pub(crate) unsafe fn commit<A: MemPool>(journal: &Journal<A>, ts: Option<u64>) {
+    if !is_active() {
+        return;
+    }
+    let mut map = registry();
+    let wal = match map.get_mut(A::name()) {
+        Some(wal) => wal,
+        None => return,
+    };
+    journal.for_each_log(|log| {
+        let (off, len) = match log {
+            LogEnum::DataLog(off, _, len) => (off, len),
+            LogEnum::InlineLog(off, _, len) => (off, len as usize),
+            LogEnum::DropOnFailure(off, len) => (off, len),
+            LogEnum::RecountOnFailure(off, _) => (off, 8),
+            _ => return,
+        };
+        if off != u64::MAX {
+            let data = std::slice::from_raw_parts((A::start() + off) as *const u8, len);
+            wal.bytes(off, data);
+        }
+    });
+    wal.seq += 1;
+    let tx = ts.unwrap_or(wal.seq);
+    let time_ns = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64);
+    wal.commit(tx, time_ns);
+}
+
+/// Reads exactly `buf.len()` bytes, and returns `false` at the end of the
+/// log, including a record which is cut short
+fn read(input: &mut impl Read, buf: &mut [u8]) -> io::Result<bool> {
+    match input.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn u64_at(buf: &[u8], i: usize) -> u64 {
+    let mut b = [0u8; 8];
+    b.copy_from_slice(&buf[i..i + 8]);
+    u64::from_le_bytes(b)
+}
+
+/// Reconstructs a pool file at `out` as of `point`, from the base image
+/// `base` and the write-ahead log `wal`
+///
+/// # Errors
+///
+/// It returns [`InvalidArgument`] if the files do not belong together or
+/// the log is corrupted, or an [`Io`] error if a file cannot be accessed.
+///
+/// [`InvalidArgument`]: ../../enum.Error.html#variant.InvalidArgument
+/// [`Io`]: ../../enum.Error.html#variant.Io
+pub fn restore(base: &str, wal: &str, point: RestorePoint, out: &str) -> Result<RestoreInfo> {
+    let corrupted = || Error::InvalidArgument(format!("`{}` is corrupted", wal));
+    let mut input = BufReader::new(File::open(wal)?);
+    let mut header = [0u8; 32];
+    if !read(&mut input, &mut header)? || &header[..8] != MAGIC || u64_at(&header, 8) != VERSION {
+        return Err(Error::InvalidArgument(format!("`{}` is not a write-ahead log", wal)));
+    }
+    let (len, journals_head) = (u64_at(&header, 16), u64_at(&header, 24));
+    if std::fs::metadata(base)?.len() != len {
+        return Err(Error::InvalidArgument(
+            format!("`{}` is not the base image of `{}`", base, wal)));
+    }
+
+    std::fs::copy(base, out)?;
+    let mut image = OpenOptions::new().write(true).open(out)?;
+    let mut pending: Vec<(u64, Vec<u8>)> = vec![];
+    let mut info = RestoreInfo { tx: 0, time: None, commits: 0 };
+    let mut tag = [0u8; 1];
+    while read(&mut input, &mut tag)? {
+        match tag[0] {
+            WORD => {
+                let mut rec = [0u8; 16];
+                if !read(&mut input, &mut rec)? {
+                    break;
+                }
+                pending.push((u64_at(&rec, 0), rec[8..].to_vec()));
+            }
+            BYTES => {
+                let mut rec = [0u8; 16];
+                if !read(&mut input, &mut rec)? {
+                    break;
+                }
+                // The length is checked against the image before reading,
+                // and the buffer grows only with the bytes in the log
+                let size = u64_at(&rec, 8);
+                if size > len {
+                    return Err(corrupted());
+                }
+                let mut data = vec![];
+                if (&mut input).take(size).read_to_end(&mut data)? as u64 != size {
+                    break;
+                }
+                pending.push((u64_at(&rec, 0), data));
+            }
+            COMMIT => {
+                let mut rec = [0u8; 16];
+                if !read(&mut input, &mut rec)? {
+                    break;
+                }
+                let tx = u64_at(&rec, 0);
+                let time = UNIX_EPOCH + Duration::from_nanos(u64_at(&rec, 8));
+                let stop = match point {
+                    RestorePoint::Tx(t) => tx > t,
+                    RestorePoint::Time(t) => time > t,
+                    RestorePoint::Latest => false,
+                };
+                if stop {
+                    break;
+                }
+                for (off, data) in pending.drain(..) {
+                    if off.checked_add(data.len() as u64).map_or(true, |end| end > len) {
+                        return Err(corrupted());
+                    }
+                    image.seek(SeekFrom::Start(off))?;
+                    image.write_all(&data)?;
+                }
+                info = RestoreInfo { tx, time: Some(time), commits: info.commits + 1 };
+            }
+            _ => return Err(corrupted()),
+        }
+    }
+
+    // The journals of the transactions which were running at the restore
+    // point are not in the log; they are dropped, and their allocations leak
+    image.seek(SeekFrom::Start(journals_head))?;
+    image.write_all(&u64::MAX.to_le_bytes())?;
+    image.sync_all()?;
+    Ok(info)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{restore, RestorePoint, BYTES, MAGIC, VERSION};
+    use crate::default::*;
+
+    type P = Allocator;
+
+    #[test]
+    fn restore_points() {
+        let root = P::open::<PCell<u64>>("wal_test.pool", O_CF).unwrap();
+        P::start_wal("wal_test.log", "wal_test.base").unwrap();
+        for i in 1..=5 {
+            P::transaction(|j| root.set(i, j)).unwrap();
+        }
+        P::stop_wal().unwrap();
+        drop(root);
+
+        let info = P::restore_to("wal_test.base", "wal_test.log",
+            RestorePoint::Tx(0), "wal_test.restored").unwrap();
+        assert_eq!(info.commits, 0);
+        let root = P::open::<PCell<u64>>("wal_test.restored", 0).unwrap();
+        assert_eq!(root.get(), 0);
+        drop(root);
+
+        let info = P::restore_to("wal_test.base", "wal_test.log",
+            RestorePoint::Latest, "wal_test.restored").unwrap();
+        assert_eq!(info.commits, 5);
+        let root = P::open::<PCell<u64>>("wal_test.restored", 0).unwrap();
+        assert_eq!(root.get(), 5);
+    }
+
+    #[test]
+    fn bounded_record_length() {
+        std::fs::write("wal_len_test.base", vec![0u8; 64]).unwrap();
+        let mut log = MAGIC.to_vec();
+        for v in &[VERSION, 64, 0] {
+            log.extend_from_slice(&v.to_le_bytes());
+        }
+        log.push(BYTES);
+        log.extend_from_slice(&0u64.to_le_bytes());
+
+        // A length larger than the image is rejected before reading
+        let mut huge = log.clone();
+        huge.extend_from_slice(&u64::MAX.to_le_bytes());
+        std::fs::write("wal_len_test.log", &huge).unwrap();
+        assert!(restore("wal_len_test.base", "wal_len_test.log",
+            RestorePoint::Latest, "wal_len_test.restored").is_err());
+
+        // A record cut short is the end of the log
+        log.extend_from_slice(&32u64.to_le_bytes());
+        log.extend_from_slice(&[1; 8]);
+        std::fs::write("wal_len_test.log", &log).unwrap();
+        let info = restore("wal_len_test.base", "wal_len_test.log",
+            RestorePoint::Latest, "wal_len_test.restored").unwrap();
+        assert_eq!(info.commits, 0);
+
+        for f in &["wal_len_test.base", "wal_len_test.log", "wal_len_test.restored"] {
+            let _ = std::fs::remove_file(f);
+        }
+    }
+}
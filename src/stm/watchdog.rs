@@ -0,0 +1,403 @@
+//! Transaction heartbeat and long-transaction watchdog
+//!
+//! A stuck transaction may hold [`PMutex`]es and its journal indefinitely. The
+//! watchdog runs a background thread that periodically inspects all running
+//! (top-level) transactions and reports those which exceed the configured
+//! duration, or those which have not sent a [`heartbeat`] for a while. A
+//! report contains the owning thread, the number of locks held by the
+//! transaction, and the number of logged bytes. Optionally, the watchdog can
+//! abort the offending transaction. Aborting is cooperative: the transaction
+//! panics on its next log or [`heartbeat`], and rolls back.
+//!
+//! # Examples
+//!
+//! ```
+//! use corundum::alloc::heap::*;
+//! use corundum::stm::watchdog::*;
+//! use std::time::Duration;
+//!
+//! let _wd = Watchdog::new(Duration::from_millis(10))
+//!     .interval(Duration::from_millis(5))
+//!     .abort(true)
+//!     .start();
+//!
+//! let res = Heap::transaction(|j| {
+//!     let b = Pbox::new(PCell::new(0), j);
+//!     loop {
+//!         b.set(b.get() + 1, j);
+//!         std::thread::sleep(Duration::from_millis(1));
+//!         heartbeat();
+//!     }
+//! });
+//! assert!(res.is_err());
+//! ```
+//!
+//! [`PMutex`]: ../../sync/struct.PMutex.html
+//! [`heartbeat`]: ./fn.heartbeat.html
+
+use crate::alloc::MemPool;
+use crate::cell::LazyCell;
+use crate::stm::{Journal, LogEnum};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle, ThreadId};
+use std::time::{Duration, Instant};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+static mut REGISTRY: LazyCell<Mutex<HashMap<u64, Arc<TxStatus>>>> =
+    LazyCell::new(|| Mutex::new(HashMap::new()));
+
+thread_local! {
+    static CURRENT: RefCell<Vec<Arc<TxStatus>>> = RefCell::new(Vec::new());
+}
+
+/// Status of a running transaction as seen by the watchdog
+struct TxStatus {
+    id: u64,
+    pool: &'static str,
+    thread: ThreadId,
+    thread_name: Option<String>,
+    start: Instant,
+    heartbeat: Mutex<Instant>,
+    locks: AtomicUsize,
+    logs: AtomicUsize,
+    logged_bytes: AtomicUsize,
+    reported: AtomicBool,
+    abort: AtomicBool,
+}
+
+fn registry() -> std::sync::MutexGuard<'static, HashMap<u64, Arc<TxStatus>>> {
+    match unsafe { REGISTRY.lock() } {
+        Ok(g) => g,
+        Err(p) => p.into_inner()
+    }
+}
+
+/// A report of a transaction which exceeded the watchdog limits
+#[derive(Clone, Debug)]
+pub struct TxReport {
+    /// The owning thread
+    pub thread: ThreadId,
+
+    /// Name of the owning thread, if any
+    pub thread_name: Option<String>,
+
+    /// Name of the pool type
+    pub pool: &'static str,
+
+    /// Time since the beginning of the transaction
+    pub elapsed: Duration,
+
+    /// Time since the last heartbeat (or the beginning of the transaction)
+    pub since_heartbeat: Duration,
+
+    /// Number of locks held by the transaction
+    pub locks: usize,
+
+    /// Number of logs written on the journal
+    pub logs: usize,
+
+    /// Total number of bytes taken as data logs
+    pub logged_bytes: usize,
+
+    /// Indicates that the transaction is requested to abort
+    pub aborting: bool,
+}
+
+impl fmt::Display for TxReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "long transaction on {} ({:?}{}): elapsed {:?}, last heartbeat {:?} ago, \
+            {} lock(s), {} log(s), {} logged byte(s){}",
+            self.pool,
+            self.thread,
+            if let Some(name) = &self.thread_name {
+                format!(" '{}'", name)
+            } else {
+                String::new()
+            },
+            self.elapsed,
+            self.since_heartbeat,
+            self.locks,
+            self.logs,
+            self.logged_bytes,
+            if self.aborting { ", aborting" } else { "" }
+        )
+    }
+}
+
+impl TxStatus {
+    fn report(&self, now: Instant) -> TxReport {
+        let beat = *match self.heartbeat.lock() {
+            Ok(g) => g,
+            Err(p) => p.into_inner()
+        };
+        TxReport {
+            thread: self.thread,
+            thread_name: self.thread_name.clone(),
+            pool: self.pool,
+            elapsed: now.duration_since(self.start),
+            since_heartbeat: now.duration_since(beat),
+            locks: self.locks.load(Ordering::Relaxed),
+            logs: self.logs.load(Ordering::Relaxed),
+            logged_bytes: self.logged_bytes.load(Ordering::Relaxed),
+            aborting: self.abort.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Registers the top-level transaction of the current thread on pool `A`
+/// while it lives
+pub(crate) struct TxGuard(Option<u64>);
+
+impl TxGuard {
+    #[inline]
+    pub(crate) fn enter<A: MemPool>() -> Self {
+        if !ENABLED.load(Ordering::Relaxed) || Journal::<A>::is_running() {
+            return TxGuard(None);
+        }
+        let pool = A::name();
+        let now = Instant::now();
+        let current = thread::current();
+        let status = Arc::new(TxStatus {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            pool,
+            thread: current.id(),
+            thread_name: current.name().map(|s| s.to_string()),
+            start: now,
+            heartbeat: Mutex::new(now),
+            locks: AtomicUsize::new(0),
+            logs: AtomicUsize::new(0),
+            logged_bytes: AtomicUsize::new(0),
+            reported: AtomicBool::new(false),
+            abort: AtomicBool::new(false),
+        });
+        let id = status.id;
+        registry().insert(id, status.clone());
+        CURRENT.with(|c| c.borrow_mut().push(status));
+        TxGuard(Some(id))
+    }
+}
+
+impl Drop for TxGuard {
+    fn drop(&mut self) {
+        if let Some(id) = self.0 {
+            registry().remove(&id);
+            let _ = CURRENT.try_with(|c| c.borrow_mut().retain(|s| s.id != id));
+        }
+    }
+}
+
+/// Accounts a new log of the current thread's transaction on pool `pool`, and
+/// aborts the transaction if the watchdog requested so
+#[inline]
+pub(crate) fn observe(pool: &'static str, log: &LogEnum) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let abort = CURRENT.with(|c| {
+        if let Some(s) = c.borrow().iter().rev().find(|s| s.pool == pool) {
+            s.logs.fetch_add(1, Ordering::Relaxed);
+            match log {
+                LogEnum::DataLog(_, _, len) => {
+                    s.logged_bytes.fetch_add(*len, Ordering::Relaxed);
+                }
+                LogEnum::InlineLog(_, _, len) => {
+                    s.logged_bytes.fetch_add(*len as usize, Ordering::Relaxed);
+                }
+                LogEnum::UnlockOnCommit(_) => {
+                    s.locks.fetch_add(1, Ordering::Relaxed);
+                }
+                _ => {}
+            }
+            s.abort.load(Ordering::Relaxed)
+        } else {
+            false
+        }
+    });
+    // Logs taken while unwinding (e.g., dropping a `Pbox`) should not panic
+    // again, as that aborts the process
+    if abort && !thread::panicking() {
+        panic!("transaction aborted by the watchdog");
+    }
+}
+
+/// Signals the watchdog that the running transactions of the current thread
+/// are making progress
+///
+/// Long transactions which periodically call `heartbeat()` are not reported
+/// for a missing heartbeat, but are still subject to the maximum duration.
+/// It is also a cancellation point: if the watchdog requested to abort any of
+/// the running transactions of the current thread, it panics to roll them
+/// back.
+pub fn heartbeat() {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let now = Instant::now();
+    let abort = CURRENT.with(|c| {
+        let mut abort = false;
+        for s in c.borrow().iter() {
+            *match s.heartbeat.lock() {
+                Ok(g) => g,
+                Err(p) => p.into_inner()
+            } = now;
+            abort |= s.abort.load(Ordering::Relaxed);
+        }
+        abort
+    });
+    if abort && !thread::panicking() {
+        panic!("transaction aborted by the watchdog");
+    }
+}
+
+/// Returns reports of all running transactions
+pub fn running() -> Vec<TxReport> {
+    let now = Instant::now();
+    registry().values().map(|s| s.report(now)).collect()
+}
+
+/// Long-transaction watchdog configuration
+///
+/// See the [module-level documentation](./index.html) for more details.
+pub struct Watchdog {
+    max_duration: Duration,
+    heartbeat_timeout: Option<Duration>,
+    interval: Duration,
+    abort: bool,
+    handler: Box<dyn Fn(&TxReport) + Send + Sync>,
+}
+
+impl Watchdog {
+    /// Creates a new watchdog configuration which reports transactions
+    /// exceeding `max_duration`
+    pub fn new(max_duration: Duration) -> Self {
+        Self {
+            max_duration,
+            heartbeat_timeout: None,
+            interval: Duration::from_millis(100),
+            abort: false,
+            handler: Box::new(|r| eprintln!("warning: {}", r)),
+        }
+    }
+
+    /// Also reports transactions which have not sent a [`heartbeat`] for
+    /// `timeout`
+    ///
+    /// [`heartbeat`]: ./fn.heartbeat.html
+    pub fn heartbeat_timeout(mut self, timeout: Duration) -> Self {
+        self.heartbeat_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the inspection interval (default: 100ms)
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Aborts the offending transactions if `abort` is true
+    pub fn abort(mut self, abort: bool) -> Self {
+        self.abort = abort;
+        self
+    }
+
+    /// Sets a handler to be called for each offending transaction. The
+    /// default handler prints the report on the standard error.
+    pub fn on_report<F: Fn(&TxReport) + Send + Sync + 'static>(mut self, f: F) -> Self {
+        self.handler = Box::new(f);
+        self
+    }
+
+    fn exceeds(&self, r: &TxReport) -> bool {
+        r.elapsed > self.max_duration ||
+            self.heartbeat_timeout.map_or(false, |t| r.since_heartbeat > t)
+    }
+
+    /// Starts the watchdog thread. The watchdog stops when the returned handle
+    /// is dropped. Only one watchdog should be running at a time.
+    pub fn start(self) -> WatchdogHandle {
+        let stop = Arc::new(AtomicBool::new(false));
+        ENABLED.store(true, Ordering::Release);
+        let handle = {
+            let stop = stop.clone();
+            thread::Builder::new()
+                .name("corundum-watchdog".to_string())
+                .spawn(move || {
+                    while !stop.load(Ordering::Acquire) {
+                        thread::park_timeout(self.interval);
+                        let now = Instant::now();
+                        let reg = registry().values().cloned().collect::<Vec<_>>();
+                        for s in reg {
+                            if s.reported.load(Ordering::Relaxed) {
+                                continue;
+                            }
+                            let mut r = s.report(now);
+                            if self.exceeds(&r) {
+                                s.reported.store(true, Ordering::Relaxed);
+                                if self.abort {
+                                    s.abort.store(true, Ordering::Relaxed);
+                                    r.aborting = true;
+                                }
+                                (self.handler)(&r);
+                            }
+                        }
+                    }
+                })
+                .expect("could not spawn the watchdog thread")
+        };
+        WatchdogHandle {
+            stop,
+            handle: Some(handle),
+        }
+    }
+}
+
+/// A handle to the running watchdog thread
+///
+/// Dropping the handle stops the watchdog.
+pub struct WatchdogHandle {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for WatchdogHandle {
+    fn drop(&mut self) {
+        ENABLED.store(false, Ordering::Release);
+        self.stop.store(true, Ordering::Release);
+        if let Some(h) = self.handle.take() {
+            h.thread().unpark();
+            let _ = h.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CURRENT, ENABLED};
+    use crate::alloc::heap::*;
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn abort_with_owning_values() {
+        ENABLED.store(true, Ordering::Release);
+        let res = Heap::transaction(|j| {
+            let b = Pbox::new(PCell::new(0), j);
+            let _v = Pbox::new([1u64; 8], j);
+            // Requests the abort the way the watchdog thread does
+            CURRENT.with(|c| c.borrow().iter().for_each(|s| s.abort.store(true, Ordering::Relaxed)));
+            loop {
+                b.set(b.get() + 1, j);
+                super::heartbeat();
+            }
+        });
+        ENABLED.store(false, Ordering::Release);
+        assert!(res.is_err());
+    }
+}
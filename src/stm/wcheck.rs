@@ -0,0 +1,508 @@
+//! Detection of unlogged writes
+//!
+//! An update of persistent data which bypasses the logs (e.g., through a raw
+//! pointer or an `unsafe` cast) is not rolled back on failure, and it is
+//! rarely caught by tests. With the `check_unlogged_writes` feature, the
+//! user area of every file-backed pool is mapped read-only while a
+//! transaction runs on it. A write to a protected page traps; the trap handler
+//! checks if the written address belongs to
+//!
+//! * a range which the running transaction logged (see [`Logger`]),
+//! * a block which the running transaction allocated,
+//! * a volatile cell ([`VCell`] or [`TCell`]) which the transaction accessed,
+//!   or
+//! * the journal or the allocator metadata, while the library updates them.
+//!
+//! Otherwise, it records the pool, the offset, and the address of the
+//! offending instruction, which are reported on the standard error when a
+//! transaction ends or [`violations()`] is called; the handler itself only
+//! does async-signal-safe work. Either way, the page is unprotected for a
+//! single instruction, so the program proceeds and every write is checked. If
+//! the `WCHECK_ABORT` environment variable is set, the process aborts at the
+//! first violation, which shows its backtrace under a debugger.
+//!
+//! Only the writes in transactions are checked. Since the protection is
+//! process-wide, the other threads run slower while a pool is protected, and
+//! a write may be missed if another thread writes the same page at the same
+//! time. The feature is only meant for debugging, and is only supported on
+//! x86_64 Linux.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use corundum::default::*;
+//! use corundum::stm::wcheck;
+//!
+//! type P = Allocator;
+//!
+//! let root = P::open::<PCell<i32>>("foo.pool", O_CF).unwrap();
+//!
+//! P::transaction(|_| unsafe {
+//!     // Bypasses the logs; it is reported with the backtrace
+//!     *root.as_mut() = 10;
+//! }).unwrap();
+//!
+//! assert_eq!(wcheck::violations(), 1);
+//! ```
+//!
+//! [`violations()`]: fn.violations.html
+//! [`Logger`]: ../trait.Logger.html
+//! [`VCell`]: ../../cell/struct.VCell.html
+//! [`TCell`]: ../../cell/struct.TCell.html
+
+use crate::alloc::MemPool;
+use crate::stm::LogEnum;
+
+/// Returns the number of unlogged writes which are detected so far, and
+/// reports the ones which are not reported yet
+///
+/// It is always zero if the `check_unlogged_writes` feature is disabled.
+pub fn violations() -> usize {
+    #[cfg(feature = "check_unlogged_writes")] {
+        imp::flush(&imp::lock());
+        imp::VIOLATIONS.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    #[cfg(not(feature = "check_unlogged_writes"))] {
+        0
+    }
+}
+
+/// Protects the pool of the top-level transaction of the current thread on
+/// pool `A` while it lives
+pub(crate) struct Protection(#[allow(dead_code)] Option<usize>);
+
+impl Protection {
+    #[inline]
+    pub(crate) fn enter<A: MemPool>() -> Self {
+        #[cfg(feature = "check_unlogged_writes")] {
+            Protection(imp::enter::<A>())
+        }
+
+        #[cfg(not(feature = "check_unlogged_writes"))] {
+            Protection(None)
+        }
+    }
+}
+
+impl Drop for Protection {
+    #[inline]
+    fn drop(&mut self) {
+        #[cfg(feature = "check_unlogged_writes")] {
+            if let Some(slot) = self.0 {
+                imp::exit(slot);
+            }
+        }
+    }
+}
+
+/// Marks the library code which updates the pool without data logs (e.g.,
+/// the allocator and the journal) while it lives
+#[doc(hidden)]
+pub struct Trusted(());
+
+impl Trusted {
+    #[inline]
+    pub fn enter() -> Self {
+        #[cfg(feature = "check_unlogged_writes")]
+        let _ = imp::TRUSTED.try_with(|t| t.set(t.get() + 1));
+
+        Trusted(())
+    }
+}
+
+impl Drop for Trusted {
+    #[inline]
+    fn drop(&mut self) {
+        #[cfg(feature = "check_unlogged_writes")]
+        let _ = imp::TRUSTED.try_with(|t| t.set(t.get() - 1));
+    }
+}
+
+/// Allows the current thread's transaction to write `len` bytes at virtual
+/// address `addr`
+#[inline]
+pub(crate) fn allow(_addr: u64, _len: usize) {
+    #[cfg(feature = "check_unlogged_writes")]
+    imp::allow(_addr, _len);
+}
+
+/// Allows the current thread's transaction to write the range which `log`
+/// protects
+#[inline]
+pub(crate) fn observe<A: MemPool>(_log: &LogEnum) {
+    #[cfg(feature = "check_unlogged_writes")] {
+        let (off, len) = match *_log {
+            LogEnum::DataLog(off, _, len) => (off, len),
+            LogEnum::InlineLog(off, _, len) => (off, len as usize),
+            LogEnum::DropOnAbort(off, len) | LogEnum::DropOnFailure(off, len) => (off, len),
+            LogEnum::RecountOnFailure(off, _) => (off, std::mem::size_of::<usize>()),
+            _ => return,
+        };
+        if off != u64::MAX {
+            allow(A::start() + off, len);
+        }
+    }
+}
+
+#[cfg(feature = "check_unlogged_writes")]
+mod imp {
+    #[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+    compile_error!("`check_unlogged_writes` is only supported on x86_64 Linux");
+
+    use crate::alloc::MemPool;
+    use crate::cell::LazyCell;
+    use crate::stm::Journal;
+    use std::cell::{Cell, RefCell};
+    use std::collections::BTreeMap;
+    use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+    use std::sync::{Mutex, Once};
+
+    const MAX_POOLS: usize = 8;
+    const PAGE: u64 = 4096;
+    const TRAP_FLAG: i64 = 0x100;
+
+    /// A protected range of a pool
+    struct Range {
+        /// The beginning of the pool, for reporting the offsets
+        base: AtomicU64,
+
+        /// The page-aligned protected range
+        start: AtomicU64,
+        end: AtomicU64,
+
+        /// The number of top-level transactions which keep it protected
+        users: AtomicUsize,
+    }
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY: Range = Range {
+        base: AtomicU64::new(0),
+        start: AtomicU64::new(0),
+        end: AtomicU64::new(0),
+        users: AtomicUsize::new(0),
+    };
+
+    /// The ranges are read by the signal handlers, so they are lock-free
+    static RANGES: [Range; MAX_POOLS] =
+        [EMPTY, EMPTY, EMPTY, EMPTY, EMPTY, EMPTY, EMPTY, EMPTY];
+
+    /// The names of the pools, which also serializes the changes of the
+    /// protection
+    static mut NAMES: LazyCell<Mutex<[&'static str; MAX_POOLS]>> =
+        LazyCell::new(|| Mutex::new([""; MAX_POOLS]));
+
+    pub(super) static VIOLATIONS: AtomicUsize = AtomicUsize::new(0);
+
+    /// The states of an [`Event`]
+    const FREE: u8 = 0;
+    const WRITING: u8 = 1;
+    const READY: u8 = 2;
+
+    /// A violation which is recorded by the signal handler, to be reported
+    /// outside of it
+    struct Event {
+        state: AtomicU8,
+        seq: AtomicUsize,
+        slot: AtomicUsize,
+        addr: AtomicU64,
+        ip: AtomicU64,
+    }
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    const NO_EVENT: Event = Event {
+        state: AtomicU8::new(FREE),
+        seq: AtomicUsize::new(0),
+        slot: AtomicUsize::new(0),
+        addr: AtomicU64::new(0),
+        ip: AtomicU64::new(0),
+    };
+
+    /// The violations which are not reported yet; the handler cannot
+    /// allocate, so the ones which do not fit are only counted
+    static EVENTS: [Event; 8] =
+        [NO_EVENT, NO_EVENT, NO_EVENT, NO_EVENT, NO_EVENT, NO_EVENT, NO_EVENT, NO_EVENT];
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+    /// If set, the handler aborts at the first violation
+    static ABORT: AtomicBool = AtomicBool::new(false);
+
+    static INSTALL: Once = Once::new();
+    static mut OLD_SEGV: Option<libc::sigaction> = None;
+    static mut OLD_TRAP: Option<libc::sigaction> = None;
+
+    thread_local! {
+        /// The number of protected top-level transactions of this thread
+        static DEPTH: Cell<usize> = Cell::new(0);
+
+        /// The depth of the trusted sections of this thread
+        pub(super) static TRUSTED: Cell<usize> = Cell::new(0);
+
+        /// The ranges which this thread's transactions may write, from the
+        /// start to the end address
+        static ALLOWED: RefCell<BTreeMap<u64, u64>> = RefCell::new(BTreeMap::new());
+
+        /// The pages which are unprotected for a single instruction
+        static STEPPING: Cell<[u64; 2]> = Cell::new([0; 2]);
+    }
+
+    pub(super) fn lock() -> std::sync::MutexGuard<'static, [&'static str; MAX_POOLS]> {
+        match unsafe { NAMES.lock() } {
+            Ok(g) => g,
+            Err(p) => p.into_inner(),
+        }
+    }
+
+    unsafe fn mprotect(start: u64, end: u64, prot: libc::c_int) -> bool {
+        end <= start
+            || libc::mprotect(start as *mut libc::c_void, (end - start) as usize, prot) == 0
+    }
+
+    unsafe fn install() {
+        INSTALL.call_once(|| {
+            ABORT.store(std::env::var_os("WCHECK_ABORT").is_some(), Ordering::Relaxed);
+            let mut act: libc::sigaction = std::mem::zeroed();
+            act.sa_flags = libc::SA_SIGINFO | libc::SA_NODEFER;
+            libc::sigemptyset(&mut act.sa_mask);
+
+            let mut old: libc::sigaction = std::mem::zeroed();
+            act.sa_sigaction = on_segv as usize;
+            libc::sigaction(libc::SIGSEGV, &act, &mut old);
+            OLD_SEGV = Some(old);
+
+            act.sa_sigaction = on_trap as usize;
+            libc::sigaction(libc::SIGTRAP, &act, &mut old);
+            OLD_TRAP = Some(old);
+        });
+    }
+
+    pub(super) fn enter<A: MemPool>() -> Option<usize> {
+        if A::path().is_none() || Journal::<A>::is_running() {
+            return None;
+        }
+        let base = A::start();
+        let start = (A::rng().start + PAGE - 1) & !(PAGE - 1);
+        let end = A::end();
+
+        let mut names = lock();
+        let slot = RANGES.iter().position(|r| r.base.load(Ordering::Acquire) == base)
+            .or_else(|| RANGES.iter().position(|r| r.users.load(Ordering::Acquire) == 0))?;
+        names[slot] = A::name();
+        let r = &RANGES[slot];
+        unsafe {
+            install();
+            // The pool may be reopened at another address
+            if r.users.load(Ordering::Acquire) == 0 {
+                r.start.store(start, Ordering::Release);
+                r.end.store(end, Ordering::Release);
+                r.base.store(base, Ordering::Release);
+            }
+            if r.users.fetch_add(1, Ordering::AcqRel) == 0 {
+                let _ = mprotect(start, end, libc::PROT_READ);
+            }
+        }
+        // Initializes the thread-local map here, since the handler cannot
+        // allocate
+        let _ = ALLOWED.try_with(|a| a.borrow().len());
+        let _ = DEPTH.try_with(|d| d.set(d.get() + 1));
+        Some(slot)
+    }
+
+    pub(super) fn exit(slot: usize) {
+        let names = lock();
+        let r = &RANGES[slot];
+        if r.users.fetch_sub(1, Ordering::AcqRel) == 1 {
+            unsafe {
+                let _ = mprotect(
+                    r.start.load(Ordering::Acquire),
+                    r.end.load(Ordering::Acquire),
+                    libc::PROT_READ | libc::PROT_WRITE,
+                );
+            }
+        }
+        flush(&names);
+        let _ = DEPTH.try_with(|d| {
+            d.set(d.get() - 1);
+            if d.get() == 0 {
+                let _ = ALLOWED.try_with(|a| a.borrow_mut().clear());
+            }
+        });
+    }
+
+    pub(super) fn allow(addr: u64, len: usize) {
+        if DEPTH.try_with(|d| d.get()).unwrap_or(0) == 0 {
+            return;
+        }
+        let end = addr + len as u64;
+        let _ = ALLOWED.try_with(|a| {
+            let mut a = a.borrow_mut();
+            let e = a.entry(addr).or_insert(end);
+            if *e < end {
+                *e = end;
+            }
+        });
+    }
+
+    /// Returns the range which contains `addr`
+    fn range_of(addr: u64) -> Option<&'static Range> {
+        RANGES.iter().find(|r| {
+            r.start.load(Ordering::Acquire) <= addr && addr < r.end.load(Ordering::Acquire)
+        })
+    }
+
+    fn permitted(addr: u64) -> bool {
+        if DEPTH.try_with(|d| d.get()).unwrap_or(0) == 0
+            || TRUSTED.try_with(|t| t.get()).unwrap_or(1) != 0
+        {
+            return true;
+        }
+        ALLOWED.try_with(|a| match a.try_borrow() {
+            Ok(a) => a.range(..=addr).rev().any(|(_, end)| addr < *end),
+            Err(_) => true,
+        }).unwrap_or(true)
+    }
+
+    /// Records a violation; it runs in the signal handler, so it only uses
+    /// atomics and async-signal-safe calls
+    fn record(r: &Range, addr: u64, ip: u64) {
+        let seq = VIOLATIONS.fetch_add(1, Ordering::Relaxed);
+        if ABORT.load(Ordering::Relaxed) {
+            let msg = b"unlogged write to a protected pool; aborting\n";
+            unsafe {
+                libc::write(2, msg.as_ptr() as *const libc::c_void, msg.len());
+                libc::abort();
+            }
+        }
+        let e = EVENTS.iter().find(|e| {
+            e.state.compare_exchange(FREE, WRITING, Ordering::Acquire, Ordering::Relaxed).is_ok()
+        });
+        match e {
+            Some(e) => {
+                let slot = RANGES.iter().position(|x| std::ptr::eq(x, r)).unwrap_or(0);
+                e.seq.store(seq, Ordering::Relaxed);
+                e.slot.store(slot, Ordering::Relaxed);
+                e.addr.store(addr - r.base.load(Ordering::Acquire), Ordering::Relaxed);
+                e.ip.store(ip, Ordering::Relaxed);
+                e.state.store(READY, Ordering::Release);
+            }
+            None => {
+                DROPPED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Reports the recorded violations in order; `names` is the lock on the
+    /// names of the pools
+    pub(super) fn flush(names: &[&'static str; MAX_POOLS]) {
+        let mut ready: Vec<_> = EVENTS.iter()
+            .filter(|e| e.state.load(Ordering::Acquire) == READY)
+            .map(|e| {
+                let v = (
+                    e.seq.load(Ordering::Relaxed),
+                    e.slot.load(Ordering::Relaxed),
+                    e.addr.load(Ordering::Relaxed),
+                    e.ip.load(Ordering::Relaxed),
+                );
+                e.state.store(FREE, Ordering::Release);
+                v
+            })
+            .collect();
+        ready.sort_unstable();
+        for (_, slot, off, ip) in ready {
+            eprintln!("unlogged write to {} at offset {} by the instruction at 0x{:x}",
+                names[slot], off, ip);
+        }
+        let dropped = DROPPED.swap(0, Ordering::Relaxed);
+        if dropped != 0 {
+            eprintln!("{} more unlogged writes are not recorded", dropped);
+        }
+    }
+
+    /// Restores the previous handler of `sig` and returns, so that the
+    /// faulting instruction runs again with it
+    unsafe fn chain(sig: libc::c_int, old: &Option<libc::sigaction>) {
+        match old {
+            Some(old) => libc::sigaction(sig, old, std::ptr::null_mut()),
+            None => libc::signal(sig, libc::SIG_DFL) as libc::c_int,
+        };
+    }
+
+    unsafe extern "C" fn on_segv(
+        _sig: libc::c_int,
+        info: *mut libc::siginfo_t,
+        ctx: *mut libc::c_void,
+    ) {
+        let addr = (*info).si_addr() as u64;
+        let r = match range_of(addr) {
+            Some(r) => r,
+            None => return chain(libc::SIGSEGV, &OLD_SEGV),
+        };
+        let page = addr & !(PAGE - 1);
+        if r.users.load(Ordering::Acquire) == 0 {
+            // A stale protection left by a racing single-step, or a fault of
+            // a pool which is closed
+            if !mprotect(page, page + PAGE, libc::PROT_READ | libc::PROT_WRITE) {
+                chain(libc::SIGSEGV, &OLD_SEGV);
+            }
+            return;
+        }
+        let uc = &mut *(ctx as *mut libc::ucontext_t);
+        if !permitted(addr) {
+            record(r, addr, uc.uc_mcontext.gregs[libc::REG_RIP as usize] as u64);
+        }
+        let _ = STEPPING.try_with(|s| {
+            let mut p = s.get();
+            if p[0] == 0 { p[0] = page; } else { p[1] = page; }
+            s.set(p);
+        });
+        let _ = mprotect(page, page + PAGE, libc::PROT_READ | libc::PROT_WRITE);
+        uc.uc_mcontext.gregs[libc::REG_EFL as usize] |= TRAP_FLAG;
+    }
+
+    unsafe extern "C" fn on_trap(
+        _sig: libc::c_int,
+        _info: *mut libc::siginfo_t,
+        ctx: *mut libc::c_void,
+    ) {
+        let pages = STEPPING.try_with(|s| s.replace([0; 2])).unwrap_or([0; 2]);
+        if pages[0] == 0 {
+            return chain(libc::SIGTRAP, &OLD_TRAP);
+        }
+        for page in pages.iter().filter(|p| **p != 0) {
+            if let Some(r) = range_of(*page) {
+                if r.users.load(Ordering::Acquire) != 0 {
+                    let _ = mprotect(*page, *page + PAGE, libc::PROT_READ);
+                }
+            }
+        }
+        let uc = &mut *(ctx as *mut libc::ucontext_t);
+        uc.uc_mcontext.gregs[libc::REG_EFL as usize] &= !TRAP_FLAG;
+    }
+}
+
+#[cfg(all(test, feature = "check_unlogged_writes"))]
+mod test {
+    use crate::default::*;
+
+    type P = Allocator;
+
+    #[test]
+    fn unlogged_writes() {
+        let root = P::open::<PCell<i32>>("wcheck_test.pool", O_CF).unwrap();
+        let before = super::violations();
+
+        P::transaction(|j| root.set(5, j)).unwrap();
+        assert_eq!(super::violations(), before);
+
+        P::transaction(|_| unsafe { *root.as_mut() = 10 }).unwrap();
+        assert_eq!(super::violations(), before + 1);
+        assert_eq!(root.get(), 10);
+
+        // Outside transactions, the pool is not protected
+        unsafe { *root.as_mut() = 20; }
+        assert_eq!(super::violations(), before + 1);
+
+        drop(root);
+        let _ = std::fs::remove_file("wcheck_test.pool");
+    }
+}
@@ -631,6 +631,35 @@ impl<A: MemPool> String<A> {
         self.vec.reserve(additional, j)
     }
 
+    /// Ensures that this `String`'s capacity is `additional` bytes
+    /// larger than its length.
+    ///
+    /// Consider using the [`reserve`] method unless you absolutely know
+    /// better than the allocator.
+    ///
+    /// [`reserve`]: #method.reserve
+    ///
+    /// # Panics
+    ///
+    /// Panics if the new capacity overflows `usize`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::alloc::heap::*;
+    /// Heap::transaction(|j| {
+    ///     let mut s = PString::new();
+    ///
+    ///     s.reserve_exact(10, j);
+    ///
+    ///     assert_eq!(s.capacity(), 10);
+    /// }).unwrap();
+    /// ```
+    #[inline]
+    pub fn reserve_exact(&mut self, additional: usize, j: &Journal<A>) {
+        self.vec.reserve_exact(additional, j)
+    }
+
     /// Shrinks the capacity of this `String` to match its length.
     ///
     /// # Examples
@@ -1352,6 +1381,8 @@ impl<A: MemPool> PartialEq for String<A> {
     }
 }
 
+impl<A: MemPool> Eq for String<A> {}
+
 macro_rules! impl_eq {
     ($lhs:ty, $rhs: ty) => {
         #[allow(unused_lifetimes)]
@@ -1718,6 +1749,13 @@ impl<A: MemPool> StdToString for String<A> {
         self.as_str().to_owned()
     }
 }
+impl<A: MemPool> std::borrow::Borrow<str> for String<A> {
+    #[inline]
+    fn borrow(&self) -> &str {
+        self
+    }
+}
+
 impl<A: MemPool> AsRef<str> for String<A> {
     #[inline]
     fn as_ref(&self) -> &str {
@@ -1941,6 +1979,40 @@ impl<A: MemPool> fmt::Write for String<A> {
 // }
 // impl<A: MemPool> FusedIterator for Drain<'_, A> {}
 
+/// Creates a persistent [`String`] from a literal or a format string
+///
+/// The first argument is the journal object. The rest of the arguments are
+/// either a single string expression, or the same arguments as `format!`.
+///
+/// # Examples
+///
+/// ```
+/// # use corundum::alloc::heap::*;
+/// # use corundum::pstring;
+/// Heap::transaction(|j| {
+///     let s = pstring!(j, "hello");
+///     assert_eq!(s, "hello");
+///
+///     let s = pstring!(j, "{} {}", "hello", 42);
+///     assert_eq!(s, "hello 42");
+/// }).unwrap();
+/// ```
+///
+/// [`String`]: ./struct.PString.html
+#[macro_export]
+macro_rules! pstring {
+    ($j:expr) => {{
+        let _ = $j;
+        $crate::PString::new()
+    }};
+    ($j:expr, $s:expr $(,)?) => {
+        $crate::PString::from_str(::std::convert::AsRef::<str>::as_ref(&$s), $j)
+    };
+    ($j:expr, $fmt:expr, $($arg:tt)+) => {
+        $crate::PString::from_str(&::std::format!($fmt, $($arg)+), $j)
+    };
+}
+
 #[cfg(test)]
 mod test {
     use crate::default::*;
@@ -0,0 +1,238 @@
+use crate::alloc::MemPool;
+use crate::cell::VCell;
+use crate::clone::PClone;
+use crate::stm::{Journal, Logger, Notifier};
+use crate::utils::SpinLock;
+use crate::*;
+use super::txlock::{Holder, TxLock};
+use super::Parc;
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::mem;
+use std::panic::{RefUnwindSafe, UnwindSafe};
+
+struct Slots<T: PSafe, A: MemPool> {
+    /// The latest value, which may not be committed yet
+    current: Parc<T, A>,
+
+    /// The value before the current owner transaction, which is loaded by
+    /// the other transactions
+    committed: Option<Parc<T, A>>,
+}
+
+/// A cell which atomically swaps a [`Parc`]
+///
+/// It lets many readers [`load`] the shared value without taking a lock,
+/// while writers replace it with [`swap`] or [`store`]. Writers own the cell
+/// until their transactions commit or roll back, and concurrent readers keep
+/// loading the last committed value in the meantime. Hence, readers never
+/// observe an uncommitted value and never block on writers' transactions.
+///
+/// Both reference counts are journaled: the loaded pointer is accounted for in
+/// the reader's journal, and the cell holds a reference to the replaced value
+/// until the next writer comes along. Therefore, the replaced value is kept
+/// alive until then.
+///
+/// # Examples
+///
+/// ```
+/// use corundum::default::*;
+/// use std::thread;
+///
+/// type P = Allocator;
+///
+/// let config = P::open::<Parc<PArcSwap<u64>>>("foo.pool", O_CF).unwrap();
+///
+/// let reader = Parc::demote(&config);
+/// let t = thread::spawn(move || {
+///     transaction(|j| {
+///         let config = reader.promote(j).unwrap();
+///         let snapshot = config.load(j); // never blocks
+///         assert!(*snapshot == 0 || *snapshot == 10);
+///     }).unwrap();
+/// });
+///
+/// transaction(|j| {
+///     config.store(Parc::new(10, j), j);
+/// }).unwrap();
+///
+/// t.join().unwrap();
+/// ```
+///
+/// [`Parc`]: ./struct.Parc.html
+/// [`load`]: #method.load
+/// [`swap`]: #method.swap
+/// [`store`]: #method.store
+pub struct PArcSwap<T: PSafe, A: MemPool> {
+    slots: UnsafeCell<Slots<T, A>>,
+    guard: VCell<u8, A>,
+    owner: VCell<TxLock, A>,
+}
+
+impl<T: PSafe, A: MemPool> !TxOutSafe for PArcSwap<T, A> {}
+impl<T: PSafe, A: MemPool> UnwindSafe for PArcSwap<T, A> {}
+impl<T: PSafe, A: MemPool> RefUnwindSafe for PArcSwap<T, A> {}
+
+unsafe impl<T: PSafe, A: MemPool> TxInSafe for PArcSwap<T, A> {}
+unsafe impl<T: PSafe, A: MemPool> PSafe for PArcSwap<T, A> {}
+unsafe impl<T: PSafe + Send + Sync, A: MemPool> Send for PArcSwap<T, A> {}
+unsafe impl<T: PSafe + Send + Sync, A: MemPool> Sync for PArcSwap<T, A> {}
+unsafe impl<T: PSafe, A: MemPool> PSend for PArcSwap<T, A> {}
+
+impl<T: PSafe, A: MemPool> PArcSwap<T, A> {
+    /// Creates a new cell containing `value`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::alloc::heap::*;
+    ///
+    /// Heap::transaction(|j| {
+    ///     let cell = PArcSwap::new(Parc::new(10, j));
+    ///     assert_eq!(*cell.load(j), 10);
+    /// }).unwrap();
+    /// ```
+    pub fn new(value: Parc<T, A>) -> Self {
+        Self {
+            slots: UnsafeCell::new(Slots { current: value, committed: None }),
+            guard: VCell::new(0),
+            owner: VCell::default(),
+        }
+    }
+
+    #[inline]
+    fn slots(&self) -> &Slots<T, A> {
+        unsafe { &*self.slots.get() }
+    }
+
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    fn slots_mut(&self, journal: &Journal<A>) -> &mut Slots<T, A> {
+        unsafe {
+            let slots = &mut *self.slots.get();
+            assert!(A::valid(slots), "The object is not in the pool's valid range");
+            slots.create_log(journal, Notifier::None);
+            slots
+        }
+    }
+
+    /// Loads the value without blocking
+    ///
+    /// If another transaction has swapped the value but is not committed yet,
+    /// the value before that transaction is returned. The current transaction
+    /// observes its own updates.
+    pub fn load(&self, journal: &Journal<A>) -> Parc<T, A> {
+        let _guard = SpinLock::acquire(self.guard.as_mut());
+        let slots = self.slots();
+        match self.owner.holder(|| ()) {
+            Holder::Other => slots.committed.as_ref().unwrap_or(&slots.current).pclone(journal),
+            _ => slots.current.pclone(journal),
+        }
+    }
+
+    /// Makes the current transaction the owner of the cell, and keeps the
+    /// committed value for the readers of other transactions
+    fn own(&self, journal: &Journal<A>) {
+        let mut backoff = 1;
+        loop {
+            let stale = {
+                let _guard = SpinLock::acquire(self.guard.as_mut());
+                match self.owner.try_own(journal) {
+                    Some(true) => {
+                        let slots = self.slots_mut(journal);
+                        let committed = slots.current.pclone(journal);
+                        Some(mem::replace(&mut slots.committed, Some(committed)))
+                    }
+                    Some(false) => Some(None),
+                    None => None,
+                }
+            };
+            if let Some(stale) = stale {
+                // Readers have their own references, if any
+                drop(stale);
+                return;
+            }
+            if backoff <= 64 {
+                for _ in 0..backoff {
+                    std::hint::spin_loop();
+                }
+                backoff <<= 1;
+            } else {
+                std::thread::yield_now();
+            }
+        }
+    }
+
+    /// Replaces the value with `new`, and returns the old value
+    ///
+    /// It blocks while another transaction owns the cell. The current
+    /// transaction then owns the cell until it commits or rolls back.
+    pub fn swap(&self, new: Parc<T, A>, journal: &Journal<A>) -> Parc<T, A> {
+        self.own(journal);
+        mem::replace(&mut self.slots_mut(journal).current, new)
+    }
+
+    /// Replaces the value with `new`, and drops the old value
+    pub fn store(&self, new: Parc<T, A>, journal: &Journal<A>) {
+        drop(self.swap(new, journal));
+    }
+
+    /// Replaces the value with `new` if it is the same allocation as
+    /// `current`. It returns the old value if it is replaced; otherwise, it
+    /// gives `new` back.
+    pub fn compare_and_swap(&self, current: &Parc<T, A>, new: Parc<T, A>,
+        journal: &Journal<A>) -> Result<Parc<T, A>, Parc<T, A>>
+    {
+        self.own(journal);
+        if Parc::ptr_eq(&self.slots().current, current) {
+            Ok(mem::replace(&mut self.slots_mut(journal).current, new))
+        } else {
+            Err(new)
+        }
+    }
+}
+
+impl<T: PSafe + RootObj<A>, A: MemPool> RootObj<A> for PArcSwap<T, A> {
+    fn init(journal: &Journal<A>) -> Self {
+        PArcSwap::new(Parc::new(T::init(journal), journal))
+    }
+}
+
+impl<T: PSafe + fmt::Debug, A: MemPool> fmt::Debug for PArcSwap<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PArcSwap").field(&self.slots().current).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::default::*;
+    use std::sync::mpsc;
+    use std::thread;
+
+    #[test]
+    fn arcswap_isolation() {
+        let root = Allocator::open::<Parc<PArcSwap<i32>>>("arcswap.pool", O_CF).unwrap();
+        Allocator::transaction(|j| root.store(Parc::new(1, j), j)).unwrap();
+
+        let (swapped_tx, swapped_rx) = mpsc::channel();
+        let (done_tx, done_rx) = mpsc::channel::<()>();
+        let writer = Parc::demote(&root);
+        let t = thread::spawn(move || {
+            transaction(AssertTxInSafe(|j| {
+                let root = writer.promote(j).unwrap();
+                assert_eq!(*root.swap(Parc::new(2, j), j), 1);
+                assert_eq!(*root.load(j), 2);
+                swapped_tx.send(()).unwrap();
+                done_rx.recv().unwrap();
+            })).unwrap();
+        });
+
+        swapped_rx.recv().unwrap();
+        Allocator::transaction(|j| assert_eq!(*root.load(j), 1)).unwrap();
+        done_tx.send(()).unwrap();
+        t.join().unwrap();
+
+        Allocator::transaction(|j| assert_eq!(*root.load(j), 2)).unwrap();
+    }
+}
@@ -0,0 +1,159 @@
+//! The waiting strategy of the spinning lock path
+//!
+//! With the `no_pthread` feature (and on Windows, or under Miri and loom), a
+//! [`PMutex`] is acquired with an atomic compare-and-swap loop instead of a
+//! pthread mutex. A thread that finds the lock taken first spins with an
+//! exponential back-off, then yields its time slice, and finally parks for
+//! exponentially growing periods, so that waiting threads do not burn the CPU
+//! on oversubscribed machines. Under loom, it only yields.
+//! [`PMutex::lock_timeout()`] waits the same way between its attempts on all
+//! platforms. The budget of every phase is process-wide and can be tuned with
+//! [`set_wait_policy()`], and the contention is reported by [`wait_stats()`].
+//!
+//! # Examples
+//!
+//! ```
+//! use corundum::sync::{set_wait_policy, wait_policy, WaitPolicy};
+//! use std::time::Duration;
+//!
+//! // Spin less and park sooner on a busy machine
+//! set_wait_policy(WaitPolicy {
+//!     spins: 4,
+//!     yields: 8,
+//!     max_park: Duration::from_micros(500),
+//! });
+//! assert_eq!(wait_policy().spins, 4);
+//! # set_wait_policy(WaitPolicy::default());
+//! ```
+//!
+//! [`PMutex`]: ./struct.PMutex.html
+//! [`PMutex::lock_timeout()`]: ./struct.PMutex.html#method.lock_timeout
+//! [`set_wait_policy()`]: ./fn.set_wait_policy.html
+//! [`wait_stats()`]: ./fn.wait_stats.html
+
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// The budget of each phase of waiting for a contended lock
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WaitPolicy {
+    /// The number of spinning rounds, each twice as long as the previous one
+    /// (up to 64 spin-loop hints)
+    pub spins: u32,
+
+    /// The number of times to yield the time slice after spinning
+    pub yields: u32,
+
+    /// The longest period to park the thread for, after yielding. The first
+    /// period is 1 µs, and it doubles every time.
+    pub max_park: Duration,
+}
+
+impl Default for WaitPolicy {
+    fn default() -> Self {
+        Self {
+            spins: 10,
+            yields: 16,
+            max_park: Duration::from_millis(1),
+        }
+    }
+}
+
+/// The contention counters of the spinning lock path since the process
+/// started, or since the last [`reset_wait_stats()`](./fn.reset_wait_stats.html)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WaitStats {
+    /// The number of acquisitions which found the lock taken
+    pub contended: u64,
+
+    /// The number of spinning rounds
+    pub spins: u64,
+
+    /// The number of yields
+    pub yields: u64,
+
+    /// The number of times a thread was parked
+    pub parks: u64,
+}
+
+static SPINS: AtomicU32 = AtomicU32::new(10);
+static YIELDS: AtomicU32 = AtomicU32::new(16);
+static MAX_PARK_NS: AtomicU64 = AtomicU64::new(1_000_000);
+
+static CONTENDED: AtomicU64 = AtomicU64::new(0);
+static SPIN_ROUNDS: AtomicU64 = AtomicU64::new(0);
+static YIELD_ROUNDS: AtomicU64 = AtomicU64::new(0);
+static PARK_ROUNDS: AtomicU64 = AtomicU64::new(0);
+
+/// Sets the process-wide waiting strategy of the spinning lock path
+pub fn set_wait_policy(policy: WaitPolicy) {
+    SPINS.store(policy.spins, Ordering::Relaxed);
+    YIELDS.store(policy.yields, Ordering::Relaxed);
+    MAX_PARK_NS.store(policy.max_park.as_nanos().min(u64::MAX as u128) as u64, Ordering::Relaxed);
+}
+
+/// Returns the current waiting strategy of the spinning lock path
+pub fn wait_policy() -> WaitPolicy {
+    WaitPolicy {
+        spins: SPINS.load(Ordering::Relaxed),
+        yields: YIELDS.load(Ordering::Relaxed),
+        max_park: Duration::from_nanos(MAX_PARK_NS.load(Ordering::Relaxed)),
+    }
+}
+
+/// Returns the contention counters of the spinning lock path
+pub fn wait_stats() -> WaitStats {
+    WaitStats {
+        contended: CONTENDED.load(Ordering::Relaxed),
+        spins: SPIN_ROUNDS.load(Ordering::Relaxed),
+        yields: YIELD_ROUNDS.load(Ordering::Relaxed),
+        parks: PARK_ROUNDS.load(Ordering::Relaxed),
+    }
+}
+
+/// Resets the contention counters of the spinning lock path
+pub fn reset_wait_stats() {
+    CONTENDED.store(0, Ordering::Relaxed);
+    SPIN_ROUNDS.store(0, Ordering::Relaxed);
+    YIELD_ROUNDS.store(0, Ordering::Relaxed);
+    PARK_ROUNDS.store(0, Ordering::Relaxed);
+}
+
+/// The waiting state of a single lock acquisition
+pub(crate) struct Backoff {
+    step: u32,
+    policy: WaitPolicy,
+}
+
+impl Backoff {
+    pub(crate) fn new() -> Self {
+        Self { step: 0, policy: wait_policy() }
+    }
+
+    /// Waits before the next attempt to acquire the lock
+    pub(crate) fn snooze(&mut self) {
+        #[cfg(loom)] {
+            crate::ll::model::spin_loop();
+            return;
+        }
+        let WaitPolicy { spins, yields, max_park } = self.policy;
+        if self.step == 0 {
+            CONTENDED.fetch_add(1, Ordering::Relaxed);
+        }
+        if self.step < spins {
+            for _ in 0..1u32 << self.step.min(6) {
+                std::hint::spin_loop();
+            }
+            SPIN_ROUNDS.fetch_add(1, Ordering::Relaxed);
+        } else if self.step - spins < yields {
+            thread::yield_now();
+            YIELD_ROUNDS.fetch_add(1, Ordering::Relaxed);
+        } else {
+            let exp = (self.step - spins - yields).min(30);
+            thread::park_timeout(Duration::from_micros(1 << exp).min(max_park));
+            PARK_ROUNDS.fetch_add(1, Ordering::Relaxed);
+        }
+        self.step = self.step.saturating_add(1);
+    }
+}
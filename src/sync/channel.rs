@@ -0,0 +1,363 @@
+use crate::alloc::MemPool;
+use crate::cell::VCell;
+use crate::clone::PClone;
+use crate::stm::{Journal, Logger, Notifier};
+use crate::sync::{Parc, VWeak};
+use crate::vec::Vec as PVec;
+use crate::*;
+use super::backoff::Backoff;
+use super::txlock::{Holder, TxLock};
+use std::cell::UnsafeCell;
+use std::panic::{RefUnwindSafe, UnwindSafe};
+
+/// Creates a persistent bounded channel with room for `capacity` items, and
+/// returns its sending and receiving halves
+///
+/// The items are kept in a ring buffer in the pool. The halves can be stored
+/// in the root object, or anywhere in the pool, so a pipeline can resume
+/// after a restart with its in-flight items intact. Both halves can be
+/// cloned with [`pclone`] to have multiple producers and consumers.
+///
+/// An item is sent when the transaction of the sender commits, and it is
+/// received when the transaction of the receiver commits. If any of them
+/// rolls back, the item stays where it was. The senders do not wait for
+/// the receivers to commit, nor vice versa, unless the channel is full or
+/// empty.
+///
+/// # Panics
+///
+/// It panics if `capacity` is zero.
+///
+/// # Examples
+///
+/// ```
+/// use corundum::default::*;
+/// use corundum::sync::{pchannel, PSender, PReceiver};
+/// use std::thread;
+///
+/// type P = Allocator;
+///
+/// struct Root {
+///     tx: PSender<u64, P>,
+///     rx: PReceiver<u64, P>,
+/// }
+///
+/// impl RootObj<P> for Root {
+///     fn init(j: &Journal) -> Self {
+///         let (tx, rx) = pchannel(16, j);
+///         Root { tx, rx }
+///     }
+/// }
+///
+/// let root = P::open::<Root>("channel.pool", O_CF).unwrap();
+///
+/// let tx = root.tx.demote();
+/// let producer = thread::spawn(move || {
+///     for i in 0..100 {
+///         P::transaction(|j| tx.promote(j).unwrap().send(i, j)).unwrap();
+///     }
+/// });
+///
+/// let mut sum = 0;
+/// for _ in 0..100 {
+///     sum += P::transaction(|j| root.rx.recv(j)).unwrap();
+/// }
+/// producer.join().unwrap();
+/// assert_eq!(sum, (0..100).sum());
+/// ```
+///
+/// [`pclone`]: ../clone/trait.PClone.html#tymethod.pclone
+pub fn pchannel<T: PSafe, A: MemPool>(
+    capacity: usize,
+    journal: &Journal<A>,
+) -> (PSender<T, A>, PReceiver<T, A>) {
+    let chan = Parc::new(Channel::new(capacity, journal), journal);
+    (PSender(chan.pclone(journal)), PReceiver(chan))
+}
+
+/// A slot of the ring buffer
+struct Slot<T, A: MemPool> {
+    value: Option<T>,
+
+    /// The lock of the last transaction which filled or emptied the slot,
+    /// which is owned until it commits
+    owner: VCell<TxLock, A>,
+}
+
+impl<T, A: MemPool> Slot<T, A> {
+    /// Returns true if the transaction which changed the slot last has not
+    /// committed yet and it is not the current transaction
+    #[inline]
+    fn is_pending(&self) -> bool {
+        matches!(self.owner.holder(|| {}), Holder::Other)
+    }
+
+    #[inline]
+    fn is_ready(&self, full: bool) -> bool {
+        self.value.is_some() == full && !self.is_pending()
+    }
+}
+
+#[derive(Default)]
+struct ChannelLocks {
+    send: TxLock,
+    recv: TxLock,
+}
+
+/// The shared ring buffer of a channel
+///
+/// The senders own the send position, and the receivers own the receive
+/// position, until their transactions commit or roll back. A slot may be
+/// filled only if it is empty and its last receiver has committed, and it
+/// may be emptied only if it is full and its last sender has committed.
+struct Channel<T, A: MemPool> {
+    locks: VCell<ChannelLocks, A>,
+    send_pos: UnsafeCell<u64>,
+    recv_pos: UnsafeCell<u64>,
+    slots: PVec<Slot<T, A>, A>,
+}
+
+impl<T: ?Sized, A: MemPool> !TxOutSafe for Channel<T, A> {}
+impl<T, A: MemPool> UnwindSafe for Channel<T, A> {}
+impl<T, A: MemPool> RefUnwindSafe for Channel<T, A> {}
+
+unsafe impl<T, A: MemPool> TxInSafe for Channel<T, A> {}
+unsafe impl<T: PSafe, A: MemPool> PSafe for Channel<T, A> {}
+unsafe impl<T: Send, A: MemPool> Send for Channel<T, A> {}
+unsafe impl<T: Send, A: MemPool> Sync for Channel<T, A> {}
+unsafe impl<T, A: MemPool> PSend for Channel<T, A> {}
+
+impl<T: PSafe, A: MemPool> Channel<T, A> {
+    fn new(capacity: usize, journal: &Journal<A>) -> Self {
+        assert_ne!(capacity, 0, "channel capacity should be non-zero");
+        let mut slots = PVec::with_capacity(capacity, journal);
+        for _ in 0..capacity {
+            slots.push(Slot { value: None, owner: VCell::default() }, journal);
+        }
+        Channel {
+            locks: VCell::default(),
+            send_pos: UnsafeCell::new(0),
+            recv_pos: UnsafeCell::new(0),
+            slots,
+        }
+    }
+
+    /// Returns the slot at `pos` and advances `pos` with a log
+    #[inline]
+    unsafe fn advance(&self, pos: &UnsafeCell<u64>, journal: &Journal<A>) -> &mut Slot<T, A> {
+        let pos = &mut *pos.get();
+        let slot = utils::as_mut(&self.slots[(*pos % self.slots.len() as u64) as usize]);
+        pos.create_log(journal, Notifier::None);
+        *pos += 1;
+        slot.owner.own(journal);
+        slot.value.create_log(journal, Notifier::None);
+        slot
+    }
+
+    fn try_send(&self, value: T, journal: &Journal<A>) -> Result<(), T> {
+        self.locks.send.own(journal);
+        unsafe {
+            let pos = *self.send_pos.get();
+            if !self.slots[(pos % self.slots.len() as u64) as usize].is_ready(false) {
+                return Err(value);
+            }
+            self.advance(&self.send_pos, journal).value = Some(value);
+        }
+        Ok(())
+    }
+
+    fn try_recv(&self, journal: &Journal<A>) -> Option<T> {
+        self.locks.recv.own(journal);
+        unsafe {
+            let pos = *self.recv_pos.get();
+            if !self.slots[(pos % self.slots.len() as u64) as usize].is_ready(true) {
+                return None;
+            }
+            self.advance(&self.recv_pos, journal).value.take()
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.slots.iter().filter(|s| s.value.is_some()).count()
+    }
+}
+
+/// The sending half of a persistent channel
+///
+/// It is created by [`pchannel()`], and it can be cloned with [`pclone`].
+///
+/// [`pchannel()`]: ./fn.pchannel.html
+/// [`pclone`]: ../clone/trait.PClone.html#tymethod.pclone
+pub struct PSender<T: PSafe, A: MemPool>(Parc<Channel<T, A>, A>);
+
+/// The receiving half of a persistent channel
+///
+/// It is created by [`pchannel()`], and it can be cloned with [`pclone`].
+///
+/// [`pchannel()`]: ./fn.pchannel.html
+/// [`pclone`]: ../clone/trait.PClone.html#tymethod.pclone
+pub struct PReceiver<T: PSafe, A: MemPool>(Parc<Channel<T, A>, A>);
+
+/// A volatile handle to a [`PSender`] which can be sent to other threads
+///
+/// [`PSender`]: ./struct.PSender.html
+pub struct VSender<T: PSafe, A: MemPool>(VWeak<Channel<T, A>, A>);
+
+/// A volatile handle to a [`PReceiver`] which can be sent to other threads
+///
+/// [`PReceiver`]: ./struct.PReceiver.html
+pub struct VReceiver<T: PSafe, A: MemPool>(VWeak<Channel<T, A>, A>);
+
+impl<T: PSafe, A: MemPool> PSender<T, A> {
+    /// Sends `value`, or returns it back if the channel is full
+    ///
+    /// The senders of other transactions wait for the current transaction
+    /// to commit or roll back.
+    pub fn try_send(&self, value: T, journal: &Journal<A>) -> Result<(), T> {
+        self.0.try_send(value, journal)
+    }
+
+    /// Sends `value`, and waits for a free slot if the channel is full
+    ///
+    /// A transaction which waits here should not hold anything that the
+    /// receivers need to commit, otherwise it never returns.
+    pub fn send(&self, value: T, journal: &Journal<A>) {
+        let mut value = value;
+        let mut backoff = Backoff::new();
+        while let Err(v) = self.0.try_send(value, journal) {
+            value = v;
+            backoff.snooze();
+        }
+    }
+
+    /// Returns the number of items in the channel, including the ones which
+    /// are not committed yet
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns true if there is no item in the channel
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Creates a volatile handle to this sender which can be sent to other
+    /// threads
+    ///
+    /// Like [`Parc::demote()`], it panics if it gets called inside a
+    /// transaction.
+    ///
+    /// [`Parc::demote()`]: ./struct.Parc.html#method.demote
+    pub fn demote(&self) -> VSender<T, A> {
+        VSender(self.0.demote())
+    }
+}
+
+impl<T: PSafe, A: MemPool> PReceiver<T, A> {
+    /// Receives the next committed item, or returns `None` if there is none
+    ///
+    /// The receivers of other transactions wait for the current transaction
+    /// to commit or roll back.
+    pub fn try_recv(&self, journal: &Journal<A>) -> Option<T> {
+        self.0.try_recv(journal)
+    }
+
+    /// Receives the next item, and waits for a sender to commit one if there
+    /// is none
+    ///
+    /// A transaction which waits here should not hold anything that the
+    /// senders need to commit, otherwise it never returns.
+    pub fn recv(&self, journal: &Journal<A>) -> T {
+        let mut backoff = Backoff::new();
+        loop {
+            if let Some(v) = self.0.try_recv(journal) {
+                return v;
+            }
+            backoff.snooze();
+        }
+    }
+
+    /// Returns the number of items in the channel, including the ones which
+    /// are not committed yet
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns true if there is no item in the channel
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Creates a volatile handle to this receiver which can be sent to other
+    /// threads
+    ///
+    /// Like [`Parc::demote()`], it panics if it gets called inside a
+    /// transaction.
+    ///
+    /// [`Parc::demote()`]: ./struct.Parc.html#method.demote
+    pub fn demote(&self) -> VReceiver<T, A> {
+        VReceiver(self.0.demote())
+    }
+}
+
+impl<T: PSafe, A: MemPool> VSender<T, A> {
+    /// Promotes the handle to a [`PSender`](./struct.PSender.html), or
+    /// returns `None` if the channel is dropped
+    pub fn promote(&self, journal: &Journal<A>) -> Option<PSender<T, A>> {
+        self.0.promote(journal).map(PSender)
+    }
+}
+
+impl<T: PSafe, A: MemPool> VReceiver<T, A> {
+    /// Promotes the handle to a [`PReceiver`](./struct.PReceiver.html), or
+    /// returns `None` if the channel is dropped
+    pub fn promote(&self, journal: &Journal<A>) -> Option<PReceiver<T, A>> {
+        self.0.promote(journal).map(PReceiver)
+    }
+}
+
+impl<T: PSafe, A: MemPool> PClone<A> for PSender<T, A> {
+    fn pclone(&self, journal: &Journal<A>) -> Self {
+        PSender(self.0.pclone(journal))
+    }
+}
+
+impl<T: PSafe, A: MemPool> PClone<A> for PReceiver<T, A> {
+    fn pclone(&self, journal: &Journal<A>) -> Self {
+        PReceiver(self.0.pclone(journal))
+    }
+}
+
+impl<T: PSafe, A: MemPool> Clone for VSender<T, A> {
+    fn clone(&self) -> Self {
+        VSender(self.0.clone())
+    }
+}
+
+impl<T: PSafe, A: MemPool> Clone for VReceiver<T, A> {
+    fn clone(&self) -> Self {
+        VReceiver(self.0.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alloc::heap::*;
+    use crate::sync::pchannel;
+
+    #[test]
+    fn channel_is_bounded_and_transactional() {
+        Heap::transaction(|j| {
+            let (tx, rx) = pchannel::<i32, Heap>(2, j);
+            assert_eq!(tx.try_send(1, j), Ok(()));
+            assert_eq!(tx.try_send(2, j), Ok(()));
+            assert_eq!(tx.try_send(3, j), Err(3));
+            assert_eq!(rx.try_recv(j), Some(1));
+            assert_eq!(tx.try_send(3, j), Ok(()));
+            assert_eq!(rx.len(), 2);
+            assert_eq!(rx.try_recv(j), Some(2));
+            assert_eq!(rx.try_recv(j), Some(3));
+            assert_eq!(rx.try_recv(j), None);
+        }).unwrap();
+    }
+}
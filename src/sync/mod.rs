@@ -1,7 +1,23 @@
 //! Useful synchronization primitives
 
+mod arcswap;
+mod backoff;
+mod channel;
 mod mutex;
 mod parc;
+mod queue;
+mod rwlock;
+mod seqcell;
+mod txlock;
 
+pub use arcswap::*;
+pub use backoff::*;
+pub use channel::*;
 pub use mutex::*;
 pub use parc::*;
+pub use queue::*;
+pub use rwlock::*;
+pub use seqcell::*;
+
+#[cfg(any(feature = "no_pthread", windows, miri, loom))]
+pub(crate) use txlock::LockWord;
@@ -2,15 +2,16 @@ use crate::alloc::MemPool;
 use crate::cell::VCell;
 use crate::ptr::Ptr;
 use crate::stm::{Journal, Log, Notifier, Logger};
+use crate::sync::Backoff;
 use crate::*;
 use std::cell::UnsafeCell;
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
 use std::panic::{RefUnwindSafe, UnwindSafe};
 use std::sync::{TryLockError, TryLockResult};
+use std::time::{Duration, Instant};
 
-#[allow(unused_imports)]
-use std::{fmt, intrinsics};
+use std::fmt;
 
 /// A transaction-wide recursive mutual exclusion primitive useful for
 /// protecting shared data while transaction is open. Further locking in the
@@ -93,16 +94,16 @@ pub struct PMutex<T, A: MemPool> {
 struct MutexInner {
     borrowed: bool,
 
-    #[cfg(not(any(feature = "no_pthread", windows)))]
+    #[cfg(not(any(feature = "no_pthread", windows, miri, loom)))]
     lock: (bool, libc::pthread_mutex_t, libc::pthread_mutexattr_t),
 
-    #[cfg(any(feature = "no_pthread", windows))]
-    lock: (bool, u64)
+    #[cfg(any(feature = "no_pthread", windows, miri, loom))]
+    lock: (bool, super::LockWord)
 }
 
 impl Default for MutexInner {
 
-    #[cfg(not(any(feature = "no_pthread", windows)))]
+    #[cfg(not(any(feature = "no_pthread", windows, miri, loom)))]
     fn default() -> Self {
         use std::mem::MaybeUninit;
         let mut attr = MaybeUninit::<libc::pthread_mutexattr_t>::uninit();
@@ -111,9 +112,9 @@ impl Default for MutexInner {
         MutexInner { borrowed: false, lock: (false, lock, unsafe { attr.assume_init() }) }
     }
 
-    #[cfg(any(feature = "no_pthread", windows))]
+    #[cfg(any(feature = "no_pthread", windows, miri, loom))]
     fn default() -> Self {
-        MutexInner { borrowed: false, lock: (false, 0) }
+        MutexInner { borrowed: false, lock: (false, super::LockWord::new()) }
     }
 }
 
@@ -194,22 +195,29 @@ impl<T, A: MemPool> PMutex<T, A> {
     fn raw_lock(&self, journal: &Journal<A>) {
         unsafe {
             // Log::unlock_on_failure(self.inner.get(), journal);
+            #[cfg(not(any(feature = "no_pthread", windows, miri, loom)))]
             let lock = &self.inner.lock.1 as *const _ as *mut _;
-            #[cfg(not(any(feature = "no_pthread", windows)))] {
+
+            #[cfg(feature = "stat_perf")]
+            let _timer = crate::stat::PhaseTimer::start(crate::stat::TxPhase::LockWait);
+
+            #[cfg(not(any(feature = "no_pthread", windows, miri, loom)))] {
                 libc::pthread_mutex_lock(lock);
             }
-            #[cfg(any(feature = "no_pthread", windows))] {
-                let tid = std::thread::current().id().as_u64().get();
-                while intrinsics::atomic_cxchg_acqrel(lock, 0, tid).0 != tid {}
+            #[cfg(any(feature = "no_pthread", windows, miri, loom))] {
+                let mut backoff = Backoff::new();
+                while !self.inner.lock.1.try_acquire() {
+                    backoff.snooze();
+                }
             }
             if self.inner.acquire() {
                 Log::unlock_on_commit(&self.inner.lock as *const _ as u64, journal);
             } else {
-                #[cfg(not(any(feature = "no_pthread", windows)))]
+                #[cfg(not(any(feature = "no_pthread", windows, miri, loom)))]
                 libc::pthread_mutex_unlock(lock);
 
-                #[cfg(any(feature = "no_pthread", windows))] 
-                intrinsics::atomic_store_rel(lock, 0);
+                #[cfg(any(feature = "no_pthread", windows, miri, loom))] 
+                self.inner.lock.1.release();
 
                 panic!("Cannot have multiple instances of MutexGuard");
             }
@@ -263,27 +271,25 @@ impl<T, A: MemPool> PMutex<T, A> {
     #[inline]
     fn raw_trylock(&self, journal: &Journal<A>) -> bool {
         unsafe {
+            #[cfg(not(any(feature = "no_pthread", windows, miri, loom)))]
             let lock = &self.inner.lock.1 as *const _ as *mut _;
 
-            #[cfg(not(any(feature = "no_pthread", windows)))]
+            #[cfg(not(any(feature = "no_pthread", windows, miri, loom)))]
             let result = libc::pthread_mutex_trylock(lock) == 0;
 
-            #[cfg(any(feature = "no_pthread", windows))]
-            let result = {
-                let tid = std::thread::current().id().as_u64().get();
-                intrinsics::atomic_cxchg_acqrel(lock, 0, tid).0 == tid
-            };
+            #[cfg(any(feature = "no_pthread", windows, miri, loom))]
+            let result = self.inner.lock.1.try_acquire();
 
             if result {
                 if self.inner.acquire() {
                     Log::unlock_on_commit(&self.inner.lock as *const _ as u64, journal);
                     true
                 } else {
-                    #[cfg(not(any(feature = "no_pthread", windows)))] 
+                    #[cfg(not(any(feature = "no_pthread", windows, miri, loom)))] 
                     libc::pthread_mutex_unlock(lock);
 
-                    #[cfg(any(feature = "no_pthread", windows))] 
-                    intrinsics::atomic_store_rel(lock, 0);
+                    #[cfg(any(feature = "no_pthread", windows, miri, loom))] 
+                    self.inner.lock.1.release();
 
                     panic!("Cannot have multiple instances of MutexGuard");
                 }
@@ -344,8 +350,73 @@ impl<T, A: MemPool> PMutex<T, A> {
             Err(TryLockError::WouldBlock)
         }
     }
+
+    /// Attempts to acquire this lock within the given `timeout`.
+    ///
+    /// It retries to acquire the lock with an exponential back-off until the
+    /// timeout expires, in which case a [`Timeout`] error is returned. This
+    /// lets transactional code implement back-off strategies (e.g., abort and
+    /// retry the whole transaction) instead of risking a deadlock. Otherwise,
+    /// it behaves like [`lock`](#method.lock).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::default::*;
+    /// use std::time::Duration;
+    ///
+    /// type P = Allocator;
+    ///
+    /// let obj = P::open::<Parc<PMutex<i32>>>("foo.pool", O_CF).unwrap();
+    ///
+    /// transaction(|j| {
+    ///     match obj.lock_timeout(j, Duration::from_millis(10)) {
+    ///         Ok(mut v) => *v += 1,
+    ///         Err(e) => panic!("{}", e), // rolls back and releases all locks
+    ///     }
+    /// }).unwrap();
+    /// ```
+    ///
+    /// [`Timeout`]: ./struct.Timeout.html
+    pub fn lock_timeout<'a>(&'a self, journal: &'a Journal<A>, timeout: Duration)
+        -> Result<MutexGuard<'a, T, A>, Timeout>
+    {
+        let start = Instant::now();
+        let mut backoff = Backoff::new();
+        loop {
+            if self.raw_trylock(journal) {
+                return unsafe { Ok(MutexGuard::new(self, journal)) };
+            }
+            if start.elapsed() >= timeout {
+                return Err(Timeout(timeout));
+            }
+            backoff.snooze();
+        }
+    }
+}
+
+/// The error type of [`PMutex::lock_timeout()`] indicating that the lock
+/// could not be acquired within the given duration
+///
+/// [`PMutex::lock_timeout()`]: ./struct.PMutex.html#method.lock_timeout
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timeout(Duration);
+
+impl Timeout {
+    /// Returns the duration which was waited for the lock
+    pub fn duration(&self) -> Duration {
+        self.0
+    }
 }
 
+impl fmt::Display for Timeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "lock timed out after {:?}", self.0)
+    }
+}
+
+impl std::error::Error for Timeout {}
+
 impl<T: RootObj<A>, A: MemPool> RootObj<A> for PMutex<T, A> {
     fn init(journal: &Journal<A>) -> Self {
         PMutex::new(T::init(journal))
@@ -409,7 +480,7 @@ impl<T, A: MemPool> Drop for MutexGuard<'_, T, A> {
     }
 }
 
-#[cfg(not(any(feature = "no_pthread", windows)))]
+#[cfg(not(any(feature = "no_pthread", windows, miri, loom)))]
 pub unsafe fn init_lock(mutex: *mut libc::pthread_mutex_t, attr: *mut libc::pthread_mutexattr_t) {
     *mutex = libc::PTHREAD_MUTEX_INITIALIZER;
     let result = libc::pthread_mutexattr_init(attr);
@@ -422,3 +493,31 @@ pub unsafe fn init_lock(mutex: *mut libc::pthread_mutex_t, attr: *mut libc::pthr
     let result = libc::pthread_mutexattr_destroy(attr);
     debug_assert_eq!(result, 0);
 }
+
+#[cfg(test)]
+mod test {
+    use crate::default::*;
+    use std::thread;
+    use std::time::Duration;
+
+    type P = Allocator;
+
+    #[test]
+    fn lock_timeout_zero() {
+        let obj = P::open::<Parc<PMutex<i32>>>("lock_timeout.pool", O_CF).unwrap();
+        P::transaction(|j| {
+            let mut v = obj.lock_timeout(j, Duration::from_secs(0)).unwrap();
+            *v += 1;
+        }).unwrap();
+
+        // The lock is released when the transaction commits
+        let a = Parc::demote(&obj);
+        thread::spawn(move || {
+            P::transaction(|j| {
+                if let Some(obj) = a.promote(j) {
+                    assert!(obj.lock_timeout(j, Duration::from_secs(0)).is_ok());
+                }
+            }).unwrap();
+        }).join().expect("thread::spawn failed");
+    }
+}
@@ -7,6 +7,7 @@ use crate::clone::*;
 use crate::ptr::Ptr;
 use crate::stm::*;
 use crate::*;
+use crate::AllocError;
 use std::clone::Clone as StdClone;
 use std::cmp::Ordering;
 use std::hash::Hash;
@@ -14,7 +15,7 @@ use std::hash::Hasher;
 use std::marker::PhantomData;
 use std::mem::MaybeUninit;
 use std::ops::Deref;
-use std::sync::atomic::{self, AtomicBool, Ordering::*};
+use crate::ll::model::atomic::{self, AtomicBool, AtomicUsize, Ordering::*};
 use std::*;
 
 const MAX_REFCOUNT: usize = (isize::MAX) as usize;
@@ -38,6 +39,9 @@ pub struct ParcInner<T: ?Sized, A: MemPool> {
     #[cfg(not(feature = "no_volatile_pointers"))]
     vlist: VCell<VWeakList, A>,
 
+    #[cfg(feature = "alloc_tags")]
+    tag: u64,
+
     marker: PhantomData<A>,
     value: T,
 }
@@ -188,8 +192,31 @@ impl<T: PSafe, A: MemPool> Parc<T, A> {
     /// }).unwrap();
     /// ```
     pub fn new(value: T, journal: &Journal<A>) -> Parc<T, A> {
+        match Self::try_new(value, journal) {
+            Ok(arc) => arc,
+            Err(e) => panic!("{}", e)
+        }
+    }
+
+    /// Tries to construct a new `Parc<T>`. It returns an [`AllocError`]
+    /// instead of panicking if the pool is exhausted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::alloc::heap::*;
+    /// use corundum::sync::Parc;
+    ///
+    /// Heap::transaction(|j| {
+    ///     let five = Parc::try_new(5, j).unwrap();
+    ///     assert_eq!(*five, 5);
+    /// }).unwrap();
+    /// ```
+    ///
+    /// [`AllocError`]: ../struct.AllocError.html
+    pub fn try_new(value: T, journal: &Journal<A>) -> Result<Parc<T, A>, AllocError> {
         unsafe {
-            let ptr = Ptr::new_unchecked(A::new(
+            let ptr = Ptr::new_unchecked(A::try_new(
                 ParcInner::<T, A> {
                     counter: Counter {
                         strong: 1,
@@ -200,12 +227,15 @@ impl<T: PSafe, A: MemPool> Parc<T, A> {
                     #[cfg(not(feature = "no_volatile_pointers"))]
                     vlist: VCell::new(VWeakList::default()),
 
+                    #[cfg(feature = "alloc_tags")]
+                    tag: crate::prc::new_tag::<A>(),
+
                     marker: PhantomData,
                     value,
                 },
                 journal,
-            ));
-            Self::from_inner(ptr)
+            )?);
+            Ok(Self::from_inner(ptr))
         }
     }
 
@@ -243,6 +273,9 @@ impl<T: PSafe, A: MemPool> Parc<T, A> {
                     #[cfg(not(feature = "no_volatile_pointers"))]
                     vlist: VCell::new(VWeakList::default()),
 
+                    #[cfg(feature = "alloc_tags")]
+                    tag: crate::prc::new_tag::<A>(),
+
                     marker: PhantomData,
                     value: MaybeUninit::<T>::uninit(),
                 },
@@ -306,6 +339,13 @@ impl<T: PSafe + ?Sized, A: MemPool> Parc<T, A> {
 
     #[inline(never)]
     unsafe fn drop_slow(&mut self, j: &Journal<A>) {
+        // Wait for the readers which pinned the value before the last strong
+        // reference went away
+        #[cfg(not(feature = "no_volatile_pointers"))]
+        while self.inner().vlist.pins.load(Acquire) != 0 {
+            crate::ll::model::thread::yield_now();
+        }
+
         // Destroy the data at this time, even though we may not free the box
         // allocation itself (there may still be weak pointers lying around).
         std::ptr::drop_in_place(&mut self.ptr.as_mut().value);
@@ -450,7 +490,12 @@ impl<T: PSafe + ?Sized, A: MemPool> Parc<T, A> {
     /// }).unwrap()
     /// ```
     /// 
+    /// Since the weak count is persistent, it is updated in the journal. If
+    /// the weak pointer is not stored in the pool, use [`demote`] instead,
+    /// which does not change the counters.
+    ///
     /// [`upgrade`]: ./struct.Weak.html#method.upgrade
+    /// [`demote`]: #method.demote
     pub fn downgrade(this: &Self, j: &Journal<A>) -> Weak<T, A> {
         let inner = this.inner();
         let _lock = SpinLock::acquire(inner.counter.lock.as_mut());
@@ -505,6 +550,22 @@ impl<T: PSafe + ?Sized, A: MemPool> Parc<T, A> {
         VWeak::new(self)
     }
 
+    /// Borrows the `Parc` as a [`ScopedParc`] which can be shared with
+    /// scoped threads
+    ///
+    /// Like [`demote()`], it panics if it gets called inside a transaction.
+    ///
+    /// [`ScopedParc`]: ./struct.ScopedParc.html
+    /// [`demote()`]: #method.demote
+    pub fn scoped(&self) -> ScopedParc<'_, T, A> {
+        debug_assert!(!self.ptr.is_dangling());
+        assert!(
+            !Journal::<A>::is_running(),
+            "Parc::scoped() cannot be called from a transaction"
+        );
+        ScopedParc { parc: self }
+    }
+
     #[inline]
     /// Gets the number of `Weak` pointers to this allocation.
     ///
@@ -554,6 +615,39 @@ impl<T: PSafe + ?Sized, A: MemPool> Parc<T, A> {
         load(inner.counter.lock.as_mut(), &inner.counter.strong)
     }
 
+    #[inline]
+    /// Gets a consistent snapshot of the number of `Parc` and `Weak` pointers
+    /// to this allocation as a `(strong, weak)` pair.
+    ///
+    /// Unlike calling [`strong_count`] and [`weak_count`] separately, both
+    /// counts are read at once, so that no other thread changes them in
+    /// between.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::alloc::heap::*;
+    /// use corundum::sync::Parc;
+    /// use corundum::clone::PClone;
+    ///
+    /// Heap::transaction(|j| {
+    ///     let five = Parc::new(5, j);
+    ///     let _also_five = Parc::pclone(&five, j);
+    ///     let _weak_five = Parc::downgrade(&five, j);
+    ///     assert_eq!((2, 1), Parc::counts(&five));
+    /// }).unwrap();
+    /// ```
+    ///
+    /// [`strong_count`]: #method.strong_count
+    /// [`weak_count`]: #method.weak_count
+    pub fn counts(this: &Self) -> (usize, usize) {
+        let inner = this.inner();
+        let _lock = SpinLock::acquire(inner.counter.lock.as_mut());
+        let weak = inner.counter.weak;
+        // See `weak_count` for the locked weak count
+        (inner.counter.strong, if weak == usize::MAX { 0 } else { weak - 1 })
+    }
+
     #[inline]
     fn is_unique(this: &Self) -> bool {
         Parc::weak_count(this) == 0 && Parc::strong_count(this) == 1
@@ -636,7 +730,7 @@ impl<T: PSafe, A: MemPool> Parc<T, A> {
             "Parc::initialize() cannot be used inside a transaction"
         );
         match arc {
-            Some(_) => Err("already initialized".to_string()),
+            Some(_) => Err(crate::Error::AlreadyInitialized),
             None => if A::valid(arc) {
                 unsafe {
                     let new = A::atomic_new(
@@ -649,6 +743,9 @@ impl<T: PSafe, A: MemPool> Parc<T, A> {
         
                             #[cfg(not(feature = "no_volatile_pointers"))]
                             vlist: VCell::new(VWeakList::default()),
+
+                            #[cfg(feature = "alloc_tags")]
+                            tag: crate::prc::new_tag::<A>(),
         
                             marker: PhantomData,
                             value,
@@ -664,7 +761,7 @@ impl<T: PSafe, A: MemPool> Parc<T, A> {
                 }
                 Ok(())
             } else {
-                Err("The object is not in the PM".to_string())
+                Err(crate::Error::OutOfRange(arc as *const _ as u64))
             }
         }
     }
@@ -1227,6 +1324,83 @@ impl<T: PSafe + ?Sized, A: MemPool> AsRef<T> for Parc<T, A> {
 
 impl<T: PSafe + ?Sized, A: MemPool> Unpin for Parc<T, A> {}
 
+/// A borrowed [`Parc`] which can be sent to scoped threads
+///
+/// A `Parc` cannot cross thread boundaries, so it is normally shared via
+/// [`demote()`] and [`promote()`], which check that the value is still alive.
+/// Scoped threads (e.g., [`std::thread::scope`]) cannot outlive the borrowed
+/// `Parc`, so a `ScopedParc` gives them direct access to the value without
+/// promoting it. Mutation still requires a transaction in the worker thread,
+/// e.g., through a [`PMutex`] inside the `Parc`.
+///
+/// It is obtained by calling [`Parc::scoped()`] outside a transaction.
+///
+/// # Examples
+///
+/// ```
+/// use corundum::default::*;
+/// use std::thread;
+///
+/// type P = Allocator;
+///
+/// let p = P::open::<Parc<PMutex<i32>>>("foo.pool", O_CF).unwrap();
+/// let shared = p.scoped();
+///
+/// thread::scope(|s| {
+///     for _ in 0..4 {
+///         s.spawn(|| {
+///             transaction(|j| *shared.lock(j) += 1).unwrap();
+///         });
+///     }
+/// });
+///
+/// assert_eq!(transaction(|j| *p.lock(j)).unwrap(), 4);
+/// ```
+///
+/// [`Parc`]: ./struct.Parc.html
+/// [`demote()`]: ./struct.Parc.html#method.demote
+/// [`promote()`]: ./struct.VWeak.html#method.promote
+/// [`PMutex`]: ./struct.PMutex.html
+/// [`Parc::scoped()`]: ./struct.Parc.html#method.scoped
+pub struct ScopedParc<'a, T: PSafe + ?Sized, A: MemPool> {
+    parc: &'a Parc<T, A>,
+}
+
+unsafe impl<T: PSafe + PSend + Sync + ?Sized, A: MemPool> Send for ScopedParc<'_, T, A> {}
+unsafe impl<T: PSafe + PSend + Sync + ?Sized, A: MemPool> Sync for ScopedParc<'_, T, A> {}
+impl<T: PSafe + ?Sized, A: MemPool> !PSend for ScopedParc<'_, T, A> {}
+impl<T: PSafe + ?Sized, A: MemPool> !TxOutSafe for ScopedParc<'_, T, A> {}
+
+impl<T: PSafe + ?Sized, A: MemPool> ScopedParc<'_, T, A> {
+    /// Creates a new `Parc` to the value in the current thread's transaction
+    pub fn pclone(&self, journal: &Journal<A>) -> Parc<T, A> {
+        self.parc.pclone(journal)
+    }
+}
+
+impl<T: PSafe + ?Sized, A: MemPool> Clone for ScopedParc<'_, T, A> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: PSafe + ?Sized, A: MemPool> Copy for ScopedParc<'_, T, A> {}
+
+impl<T: PSafe + ?Sized, A: MemPool> Deref for ScopedParc<'_, T, A> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.parc
+    }
+}
+
+impl<T: fmt::Debug + PSafe + ?Sized, A: MemPool> fmt::Debug for ScopedParc<'_, T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
 unsafe fn data_offset<T, A: MemPool>(ptr: *const T) -> isize {
     data_offset_align::<A>(mem::align_of_val(&*ptr))
 }
@@ -1265,6 +1439,9 @@ pub struct VWeak<T: ?Sized, A: MemPool> {
     ptr: *mut ParcInner<T, A>,
     valid: *mut VWeakValid,
     gen: u32,
+
+    #[cfg(feature = "alloc_tags")]
+    tag: u64,
 }
 
 impl<T: ?Sized, A: MemPool> UnwindSafe for VWeak<T, A> {}
@@ -1282,6 +1459,9 @@ impl<T: PSafe + ?Sized, A: MemPool> VWeak<T, A> {
             ptr: parc.ptr.get_mut_ptr(),
             valid: list.append(),
             gen: A::gen(),
+
+            #[cfg(feature = "alloc_tags")]
+            tag: parc.ptr.tag,
         }
     }
 
@@ -1338,10 +1518,71 @@ impl<T: PSafe + ?Sized, A: MemPool> VWeak<T, A> {
         Some(Parc::from_inner(unsafe { Ptr::from_raw(self.ptr) }))
     }
 
+    /// Calls `f` with a shared reference to the inner value without a
+    /// transaction, if the value is not dropped yet
+    ///
+    /// It is useful for read-only access, as it neither needs a journal nor
+    /// changes the reference counters. The value is pinned while `f` is
+    /// running: a transaction which drops the last [`Parc`] in the meantime
+    /// waits for `f` to return before dropping the value. Therefore, `f`
+    /// should not drop the last `Parc` itself. Any modification should still
+    /// be done in a transaction after [`promote`]ing the pointer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::default::*;
+    /// use std::thread;
+    ///
+    /// type P = Allocator;
+    ///
+    /// let root = P::open::<Parc<i32>>("foo.pool", O_CF).unwrap();
+    /// let vweak = Parc::demote(&root);
+    ///
+    /// thread::spawn(move || {
+    ///     // No transaction is needed to read the value
+    ///     assert_eq!(vweak.peek(|v| *v), Some(0));
+    /// }).join().unwrap();
+    /// ```
+    ///
+    /// [`Parc`]: ./struct.Parc.html
+    /// [`promote`]: #method.promote
+    pub fn peek<R, F: FnOnce(&T) -> R>(&self, f: F) -> Option<R> {
+        let inner = self.inner()?;
+        {
+            let _lock = SpinLock::acquire(inner.counter.lock.as_mut());
+            if inner.counter.strong == 0 {
+                return None;
+            }
+            inner.vlist.pins.fetch_add(1, Acquire);
+        }
+        let _pin = PeekPin(&inner.vlist.pins);
+        Some(f(&inner.value))
+    }
+
+    /// Returns `true` if the two `VWeak`s point to the same allocation
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::default::*;
+    ///
+    /// type P = Allocator;
+    ///
+    /// let root = P::open::<Parc<i32>>("foo.pool", O_CF).unwrap();
+    ///
+    /// assert!(Parc::demote(&root).ptr_eq(&root.demote()));
+    /// ```
+    #[inline]
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.ptr as *const u8, other.ptr as *const u8)
+    }
+
     #[inline]
     fn inner(&self) -> Option<&mut ParcInner<T, A>> {
         unsafe {
-            if !(*self.valid).valid.load(Acquire) || self.gen != A::gen() {
+            if !(*self.valid).valid.load(Acquire) || self.gen != A::gen()
+                || !self.same_tag() {
                 None
             } else {
                 Some(&mut *self.ptr)
@@ -1350,9 +1591,23 @@ impl<T: PSafe + ?Sized, A: MemPool> VWeak<T, A> {
     }
 }
 
+impl<T: ?Sized, A: MemPool> VWeak<T, A> {
+    /// Indicates if the block is still tagged the same as when the `VWeak`
+    /// was created. See [`prc::VWeak`](../prc/struct.VWeak.html).
+    #[inline]
+    fn same_tag(&self) -> bool {
+        #[cfg(feature = "alloc_tags")] {
+            unsafe { (*self.ptr).tag == self.tag }
+        }
+        #[cfg(not(feature = "alloc_tags"))] {
+            true
+        }
+    }
+}
+
 impl<T: PSafe + ?Sized, A: MemPool> Clone for VWeak<T, A> {
     fn clone(&self) -> Self {
-        if self.gen == A::gen() {
+        if self.gen == A::gen() && self.same_tag() {
             unsafe { 
                 if (*self.valid).valid.load(Acquire) {
                     let list = (*self.ptr).vlist.as_mut();
@@ -1360,6 +1615,9 @@ impl<T: PSafe + ?Sized, A: MemPool> Clone for VWeak<T, A> {
                         ptr: self.ptr,
                         valid: list.append(),
                         gen: self.gen,
+
+                        #[cfg(feature = "alloc_tags")]
+                        tag: self.tag,
                     };  
                 }
             }
@@ -1368,6 +1626,9 @@ impl<T: PSafe + ?Sized, A: MemPool> Clone for VWeak<T, A> {
             ptr: self.ptr,
             valid: self.valid,
             gen: self.gen,
+
+            #[cfg(feature = "alloc_tags")]
+            tag: self.tag,
         }
     }
 }
@@ -1376,7 +1637,9 @@ impl<T: ?Sized, A: MemPool> Drop for VWeak<T, A> {
     fn drop(&mut self) {
         unsafe {
             let this = &mut *self.valid;
-            if !this.list.is_null() {
+
+            // The list of a re-tagged block belongs to another object
+            if !this.list.is_null() && (self.gen != A::gen() || self.same_tag()) {
                 let mut head = match (*this.list).head.lock() {
                     Ok(g) => g,
                     Err(p) => p.into_inner(),
@@ -1401,10 +1664,21 @@ struct VWeakValid {
     list: *mut VWeakList,
 }
 
-use std::sync::Mutex as StdMutex;
+use crate::ll::model::Mutex as StdMutex;
 
 struct VWeakList {
     head: StdMutex<*mut VWeakValid>,
+
+    /// The number of ongoing `VWeak::peek` calls
+    pins: AtomicUsize,
+}
+
+struct PeekPin<'a>(&'a AtomicUsize);
+
+impl Drop for PeekPin<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Release);
+    }
 }
 
 impl VWeakList {
@@ -1434,6 +1708,7 @@ impl Default for VWeakList {
     fn default() -> Self {
         VWeakList {
             head: StdMutex::new(std::ptr::null_mut()),
+            pins: AtomicUsize::new(0),
         }
     }
 }
@@ -0,0 +1,285 @@
+use crate::alloc::MemPool;
+use crate::cell::VCell;
+use crate::ptr::Ptr;
+use crate::stm::{Journal, Logger, Notifier};
+use crate::*;
+use super::txlock::{Holder, TxLock};
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::fmt;
+
+/// A persistent FIFO queue with separate locks for its two ends
+///
+/// It is the two-lock queue of Michael and Scott: the nodes make a singly
+/// linked list which always starts with a dummy node, so that the producers
+/// only touch the tail and the consumers only touch the head. An
+/// [`enqueue`] owns the tail, and a [`dequeue`] owns the head, until their
+/// transactions commit or roll back. Producers are serialized among
+/// themselves, and so are consumers, but a producer never waits for a
+/// consumer, nor vice versa.
+///
+/// The links are logged like any other data of the transaction, and a
+/// crash rolls back the partial updates of both ends. The nodes which a
+/// running transaction has enqueued are not visible to the consumers of
+/// the other transactions; they appear when the producer commits.
+///
+/// # Examples
+///
+/// ```
+/// use corundum::default::*;
+/// use std::thread;
+///
+/// type P = Allocator;
+///
+/// let root = P::open::<Parc<PQueue<u64>>>("queue.pool", O_CF).unwrap();
+///
+/// let producers: Vec<_> = (0..4).map(|t| {
+///     let q = Parc::demote(&root);
+///     thread::spawn(move || {
+///         for i in 0..100 {
+///             P::transaction(|j| {
+///                 let q = q.promote(j).unwrap();
+///                 q.enqueue(t * 100 + i, j);
+///             }).unwrap();
+///         }
+///     })
+/// }).collect();
+///
+/// let mut sum = 0;
+/// let mut count = 0;
+/// while count < 400 {
+///     if let Some(v) = P::transaction(|j| root.dequeue(j)).unwrap() {
+///         sum += v;
+///         count += 1;
+///     }
+/// }
+/// for p in producers {
+///     p.join().unwrap();
+/// }
+/// assert_eq!(sum, (0..400).sum());
+/// ```
+///
+/// [`enqueue`]: #method.enqueue
+/// [`dequeue`]: #method.dequeue
+pub struct PQueue<T: PSafe, A: MemPool> {
+    heap: PhantomData<A>,
+    locks: VCell<QueueLocks, A>,
+
+    /// The dummy node, owned by the consumers
+    head: UnsafeCell<Ptr<Node<T, A>, A>>,
+
+    /// The last node, owned by the producers
+    tail: UnsafeCell<Ptr<Node<T, A>, A>>,
+}
+
+struct Node<T: PSafe, A: MemPool> {
+    next: Ptr<Node<T, A>, A>,
+    value: Option<T>,
+
+    /// The lock of the producer, which is owned until it commits
+    owner: VCell<TxLock, A>,
+}
+
+#[derive(Default)]
+struct QueueLocks {
+    head: TxLock,
+    tail: TxLock,
+}
+
+impl<T: PSafe, A: MemPool> Node<T, A> {
+    fn new(value: Option<T>) -> Self {
+        Node {
+            next: Ptr::dangling(),
+            value,
+            owner: VCell::new(TxLock::default()),
+        }
+    }
+
+    /// Returns true if the producer of this node has not committed yet and
+    /// it is not the current transaction
+    #[inline]
+    fn is_pending(&self) -> bool {
+        matches!(self.owner.holder(|| {}), Holder::Other)
+    }
+
+    /// Reads the link which a producer may be writing concurrently
+    #[inline]
+    fn next(&self) -> Ptr<Node<T, A>, A> {
+        let off = unsafe { &*(self.next.off_ref() as *const u64 as *const AtomicU64) };
+        unsafe { Ptr::from_off_unchecked(off.load(Ordering::Acquire)) }
+    }
+
+    /// Logs and publishes the link to `node`
+    #[inline]
+    fn link(&mut self, node: Ptr<Node<T, A>, A>, journal: &Journal<A>) {
+        unsafe {
+            self.next.create_log(journal, Notifier::None);
+            let off = &*(self.next.off_ref() as *const u64 as *const AtomicU64);
+            off.store(node.off(), Ordering::Release);
+        }
+    }
+}
+
+impl<T: PSafe, A: MemPool> !TxOutSafe for PQueue<T, A> {}
+impl<T: PSafe, A: MemPool> UnwindSafe for PQueue<T, A> {}
+impl<T: PSafe, A: MemPool> RefUnwindSafe for PQueue<T, A> {}
+
+unsafe impl<T: PSafe, A: MemPool> TxInSafe for PQueue<T, A> {}
+unsafe impl<T: PSafe, A: MemPool> PSafe for PQueue<T, A> {}
+unsafe impl<T: PSafe + Send, A: MemPool> Send for PQueue<T, A> {}
+unsafe impl<T: PSafe + Send, A: MemPool> Sync for PQueue<T, A> {}
+unsafe impl<T: PSafe, A: MemPool> PSend for PQueue<T, A> {}
+
+impl<T: PSafe, A: MemPool> PQueue<T, A> {
+    /// Creates an empty queue
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::alloc::heap::*;
+    /// Heap::transaction(|j| {
+    ///     let q = Parc::new(PQueue::<i32>::new(j), j);
+    /// }).unwrap();
+    /// ```
+    pub fn new(journal: &Journal<A>) -> Self {
+        let dummy = unsafe {
+            Ptr::from_mut(A::new(Node::new(None), journal))
+        };
+        PQueue {
+            heap: PhantomData,
+            locks: VCell::new(QueueLocks::default()),
+            head: UnsafeCell::new(dummy),
+            tail: UnsafeCell::new(dummy),
+        }
+    }
+
+    /// Appends `value` to the end of the queue
+    ///
+    /// The tail is owned by the transaction until it commits or rolls back;
+    /// the other producers wait for it, but the consumers do not.
+    pub fn enqueue(&self, value: T, journal: &Journal<A>) {
+        unsafe {
+            let node = A::new(Node::new(Some(value)), journal);
+            node.owner.own(journal);
+            let node = Ptr::from_mut(node);
+            self.locks.tail.own(journal);
+            let tail = &mut *self.tail.get();
+            tail.get_mut().link(node, journal);
+            tail.create_log(journal, Notifier::None);
+            *tail = node;
+        }
+    }
+
+    /// Removes the first committed item of the queue, and returns it, or
+    /// `None` if there is no such item
+    ///
+    /// The head is owned by the transaction until it commits or rolls back;
+    /// the other consumers wait for it, but the producers do not.
+    pub fn dequeue(&self, journal: &Journal<A>) -> Option<T> {
+        self.locks.head.own(journal);
+        unsafe {
+            let head = &mut *self.head.get();
+            let mut next = head.as_ref().next();
+            let node = next.as_option()?.get_mut();
+
+            // The nodes after a pending node are pending too
+            if node.is_pending() {
+                return None;
+            }
+
+            journal.batch(|| {
+                node.value.create_log(journal, Notifier::None);
+                head.create_log(journal, Notifier::None);
+            });
+            let value = node.value.take();
+            A::free(head.get_mut());
+            *head = next;
+            value
+        }
+    }
+
+    /// Returns true if the queue has no committed item
+    ///
+    /// It may be out of date as soon as it returns, unless the current
+    /// transaction owns the head.
+    pub fn is_empty(&self) -> bool {
+        let mut next = unsafe { (*self.head.get()).as_ref().next() };
+        next.as_option().map_or(true, |n| n.as_ref().is_pending())
+    }
+}
+
+impl<T: PSafe, A: MemPool> Drop for PQueue<T, A> {
+    fn drop(&mut self) {
+        unsafe {
+            let mut curr = *self.head.get();
+            while let Some(node) = curr.as_option() {
+                let node = node.as_mut();
+                curr = node.next;
+                std::ptr::drop_in_place(&mut node.value);
+                A::free(node);
+            }
+        }
+    }
+}
+
+impl<T: PSafe, A: MemPool> RootObj<A> for PQueue<T, A> {
+    fn init(journal: &Journal<A>) -> Self {
+        PQueue::new(journal)
+    }
+}
+
+impl<T: PSafe + fmt::Debug, A: MemPool> fmt::Debug for PQueue<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut list = f.debug_list();
+        let mut curr = unsafe { (*self.head.get()).as_ref().next() };
+        while let Some(node) = curr.as_option() {
+            let node = node.as_ref();
+            if node.is_pending() {
+                break;
+            }
+            if let Some(v) = &node.value {
+                list.entry(v);
+            }
+            curr = node.next();
+        }
+        list.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::default::*;
+    use std::thread;
+
+    type P = Allocator;
+
+    #[test]
+    fn queue_hides_uncommitted_items() {
+        let root = P::open::<Parc<PQueue<i32>>>("queue_test.pool", O_CF).unwrap();
+        P::transaction(|j| while root.dequeue(j).is_some() {}).unwrap();
+
+        assert!(P::transaction(|j| {
+            root.enqueue(1, j);
+            root.enqueue(2, j);
+            assert_eq!(root.dequeue(j), Some(1));
+            panic!("abort");
+        }).is_err());
+        assert!(root.is_empty());
+
+        let q = Parc::demote(&root);
+        P::transaction(|j| {
+            root.enqueue(3, j);
+
+            // Another transaction cannot see the item yet
+            let q = q.clone();
+            assert_eq!(thread::spawn(move || P::transaction(|j| {
+                q.promote(j).unwrap().dequeue(j)
+            }).unwrap()).join().unwrap(), None);
+        }).unwrap();
+
+        assert_eq!(P::transaction(|j| root.dequeue(j)).unwrap(), Some(3));
+        assert!(root.is_empty());
+    }
+}
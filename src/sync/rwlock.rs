@@ -0,0 +1,500 @@
+use crate::alloc::MemPool;
+use crate::cell::VCell;
+use crate::ptr::Ptr;
+use crate::stm::{Journal, Notifier, Logger};
+use crate::stm::isolation::{self, Versioned};
+use crate::*;
+use super::txlock::{Holder, TxLock};
+use std::cell::{Cell, UnsafeCell};
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::fmt;
+
+/// A transaction-wide reader-writer lock
+///
+/// This type of lock allows a number of readers or at most one writer at any
+/// point in time. Similar to [`PMutex`], the exclusive access is held until
+/// the transaction commits or rolls back, so that other transactions never
+/// observe uncommitted data. There are three kinds of guards:
+///
+/// * [`read`] returns a shared guard which is released as soon as it is
+///   dropped. Readers of different transactions may proceed concurrently.
+/// * [`upgradable_read`] returns a shared guard which also excludes other
+///   writers and upgradable readers until the end of the transaction. Plain
+///   readers can still proceed. It can be atomically [upgraded] to a write
+///   guard without giving other writers a chance to change the data.
+/// * [`write`] returns an exclusive guard. Once the data is written to, other
+///   transactions cannot read it until the transaction is done, even if the
+///   guard is [downgraded].
+///
+/// Further locking in the same transaction is non-blocking, but borrow rules
+/// are checked dynamically: a write guard cannot coexist with other guards of
+/// the same lock in the same transaction.
+///
+/// # Examples
+///
+/// ```
+/// use corundum::default::*;
+///
+/// type P = Allocator;
+///
+/// let index = P::open::<Parc<PRwLock<PVec<u64>>>>("foo.pool", O_CF).unwrap();
+///
+/// transaction(|j| {
+///     let keys = index.upgradable_read(j);
+///     if !keys.contains(&10) {
+///         // No other writer could insert 10 in the meantime
+///         let mut keys = keys.upgrade();
+///         keys.push(10, j);
+///     }
+/// }).unwrap();
+///
+/// transaction(|j| {
+///     assert!(index.read(j).contains(&10));
+/// }).unwrap();
+/// ```
+///
+/// [`PMutex`]: ./struct.PMutex.html
+/// [`read`]: #method.read
+/// [`upgradable_read`]: #method.upgradable_read
+/// [`write`]: #method.write
+/// [upgraded]: ./struct.UpgradableReadGuard.html#method.upgrade
+/// [downgraded]: ./struct.RwLockWriteGuard.html#method.downgrade
+pub struct PRwLock<T, A: MemPool> {
+    heap: PhantomData<A>,
+    inner: VCell<RwLockInner, A>,
+    data: UnsafeCell<(u8, T)>,
+}
+
+#[derive(Default)]
+struct RwLockInner {
+    /// Number of read guards of the transactions which do not own the lock
+    readers: AtomicUsize,
+
+    /// Indicates that the owner transaction has written to the data
+    dirty: AtomicBool,
+
+    /// The version of the data, which changes whenever a writer takes the
+    /// lock; it is used to validate serializable reads
+    version: AtomicU64,
+
+    /// Number of read guards of the owner transaction
+    local_readers: Cell<usize>,
+
+    /// Indicates that the owner transaction has a write guard
+    writing: Cell<bool>,
+
+    /// Indicates that the owner transaction has an upgradable guard
+    upgradable: Cell<bool>,
+
+    /// The transaction-wide lock of the owner
+    owner: TxLock,
+}
+
+impl RwLockInner {
+    /// Makes the current transaction the owner of the lock
+    fn own<A: MemPool>(&self, journal: &Journal<A>) {
+        if self.owner.own(journal) {
+            // The data is committed by the previous owner
+            self.dirty.store(false, Ordering::SeqCst);
+        }
+    }
+
+    /// Probes the owner of the lock without blocking. If no transaction owns
+    /// it, the dirty flag of the previous owner is cleared.
+    fn holder(&self) -> Holder {
+        self.owner.holder(|| self.dirty.store(false, Ordering::SeqCst))
+    }
+
+    /// Waits for the readers of other transactions to release the lock
+    fn wait_for_readers(&self) {
+        #[cfg(feature = "stat_perf")]
+        let _timer = crate::stat::PhaseTimer::start(crate::stat::TxPhase::LockWait);
+
+        let mut backoff = 1;
+        while self.readers.load(Ordering::SeqCst) != 0 {
+            if backoff <= 64 {
+                for _ in 0..backoff {
+                    crate::ll::model::spin_loop();
+                }
+                backoff <<= 1;
+            } else {
+                crate::ll::model::thread::yield_now();
+            }
+        }
+    }
+
+    /// Marks the data as modified and waits for the readers of other
+    /// transactions to leave
+    fn begin_write(&self) {
+        if self.local_readers.get() != 0 {
+            panic!("Cannot have a RwLockWriteGuard while other guards are alive");
+        }
+        self.dirty.store(true, Ordering::SeqCst);
+        let v = self.version.fetch_add(1, Ordering::SeqCst);
+        isolation::rebase(self, v, v + 1);
+        self.wait_for_readers();
+        self.writing.set(true);
+    }
+}
+
+impl Versioned for RwLockInner {
+    fn pin(&self, seen: u64) -> Option<bool> {
+        match self.holder() {
+            Holder::Current => {
+                if self.version.load(Ordering::SeqCst) == seen {
+                    Some(false)
+                } else {
+                    None
+                }
+            }
+            _ => {
+                self.readers.fetch_add(1, Ordering::SeqCst);
+                if !self.dirty.load(Ordering::SeqCst) && self.version.load(Ordering::SeqCst) == seen {
+                    Some(true)
+                } else {
+                    self.readers.fetch_sub(1, Ordering::SeqCst);
+                    None
+                }
+            }
+        }
+    }
+
+    fn unpin(&self) {
+        self.readers.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl<T: ?Sized, A: MemPool> !TxOutSafe for PRwLock<T, A> {}
+impl<T, A: MemPool> UnwindSafe for PRwLock<T, A> {}
+impl<T, A: MemPool> RefUnwindSafe for PRwLock<T, A> {}
+
+unsafe impl<T, A: MemPool> TxInSafe for PRwLock<T, A> {}
+unsafe impl<T, A: MemPool> PSafe for PRwLock<T, A> {}
+unsafe impl<T: Send, A: MemPool> Send for PRwLock<T, A> {}
+unsafe impl<T: Send + Sync, A: MemPool> Sync for PRwLock<T, A> {}
+unsafe impl<T, A: MemPool> PSend for PRwLock<T, A> {}
+
+impl<T, A: MemPool> PRwLock<T, A> {
+    /// Creates a new `PRwLock`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::alloc::heap::*;
+    ///
+    /// Heap::transaction(|j| {
+    ///     let p = Parc::new(PRwLock::new(10), j);
+    /// }).unwrap();
+    /// ```
+    pub fn new(data: T) -> PRwLock<T, A> {
+        PRwLock {
+            heap: PhantomData,
+            inner: VCell::new(RwLockInner::default()),
+            data: UnsafeCell::new((0, data)),
+        }
+    }
+
+    /// Locks this lock with shared read access, blocking the current thread
+    /// until it can be acquired.
+    ///
+    /// The guard is released as soon as it is dropped, so a read-only
+    /// transaction may observe different committed values through different
+    /// guards. Use [`upgradable_read`](#method.upgradable_read) to keep the
+    /// data stable for the rest of the transaction, or run it with the
+    /// [serializable](../stm/isolation/index.html) isolation level to
+    /// validate the reads when it commits.
+    ///
+    /// # Panics
+    ///
+    /// It panics if the current transaction holds a write guard of this lock.
+    pub fn read<'a>(&'a self, _journal: &'a Journal<A>) -> RwLockReadGuard<'a, T, A> {
+        #[cfg(feature = "stat_perf")]
+        let _timer = crate::stat::PhaseTimer::start(crate::stat::TxPhase::LockWait);
+
+        let inner = &*self.inner;
+        let mut backoff = 1;
+        loop {
+            match inner.holder() {
+                Holder::Current => {
+                    if inner.writing.get() {
+                        panic!("Cannot have a RwLockReadGuard while a RwLockWriteGuard is alive");
+                    }
+                    inner.local_readers.set(inner.local_readers.get() + 1);
+                    return RwLockReadGuard { lock: self, local: true };
+                }
+                _ => {
+                    inner.readers.fetch_add(1, Ordering::SeqCst);
+                    let seen = inner.version.load(Ordering::SeqCst);
+                    if !inner.dirty.load(Ordering::SeqCst) {
+                        isolation::record::<A>(inner, seen);
+                        return RwLockReadGuard { lock: self, local: false };
+                    }
+                    inner.readers.fetch_sub(1, Ordering::SeqCst);
+                }
+            }
+            if backoff <= 64 {
+                for _ in 0..backoff {
+                    crate::ll::model::spin_loop();
+                }
+                backoff <<= 1;
+            } else {
+                crate::ll::model::thread::yield_now();
+            }
+        }
+    }
+
+    /// Locks this lock with shared read access which can be upgraded to
+    /// exclusive write access.
+    ///
+    /// It blocks the current thread until no other transaction owns the lock.
+    /// The ownership is then held until the transaction commits or rolls back,
+    /// excluding other writers and upgradable readers. Plain readers of other
+    /// transactions can proceed until the guard is
+    /// [upgraded](./struct.UpgradableReadGuard.html#method.upgrade).
+    ///
+    /// # Panics
+    ///
+    /// It panics if the current transaction holds a write guard or another
+    /// upgradable guard of this lock.
+    pub fn upgradable_read<'a>(&'a self, journal: &'a Journal<A>) -> UpgradableReadGuard<'a, T, A> {
+        let inner = &*self.inner;
+        inner.own(journal);
+        if inner.writing.get() || inner.upgradable.get() {
+            panic!("Cannot have multiple instances of UpgradableReadGuard");
+        }
+        inner.upgradable.set(true);
+        UpgradableReadGuard { lock: self, journal }
+    }
+
+    /// Locks this lock with exclusive write access, blocking the current
+    /// thread until it can be acquired.
+    ///
+    /// The ownership is held until the transaction commits or rolls back.
+    /// Readers of other transactions are blocked from now on, and existing
+    /// ones are waited for.
+    ///
+    /// # Panics
+    ///
+    /// It panics if the current transaction holds any other guard of this
+    /// lock.
+    pub fn write<'a>(&'a self, journal: &'a Journal<A>) -> RwLockWriteGuard<'a, T, A> {
+        let inner = &*self.inner;
+        inner.own(journal);
+        if inner.writing.get() || inner.upgradable.get() {
+            panic!("Cannot have multiple instances of RwLockWriteGuard");
+        }
+        inner.begin_write();
+        RwLockWriteGuard { lock: self, journal }
+    }
+}
+
+impl<T: PSafe, A: MemPool> PRwLock<T, A> {
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    #[track_caller]
+    /// Takes a log and returns a `&mut T` for interior mutability
+    fn get_mut(&self, journal: &Journal<A>) -> &mut T {
+        unsafe {
+            let inner = &mut *self.data.get();
+            if inner.0 == 0 {
+                assert!(A::valid(inner), "The object is not in the pool's valid range");
+                inner.1.create_log(journal, Notifier::NonAtomic(Ptr::from_ref(&inner.0)));
+            }
+            &mut inner.1
+        }
+    }
+}
+
+impl<T: RootObj<A>, A: MemPool> RootObj<A> for PRwLock<T, A> {
+    fn init(journal: &Journal<A>) -> Self {
+        PRwLock::new(T::init(journal))
+    }
+}
+
+impl<T: fmt::Debug, A: MemPool> fmt::Debug for PRwLock<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.data.fmt(f)
+    }
+}
+
+/// RAII structure used to release the shared read access of a [`PRwLock`]
+/// when dropped
+///
+/// [`PRwLock`]: ./struct.PRwLock.html
+pub struct RwLockReadGuard<'a, T: 'a, A: MemPool> {
+    lock: &'a PRwLock<T, A>,
+    local: bool,
+}
+
+/// A shared guard of a [`PRwLock`] which excludes other writers until the
+/// end of the transaction, and can be atomically upgraded to a write guard
+///
+/// [`PRwLock`]: ./struct.PRwLock.html
+pub struct UpgradableReadGuard<'a, T: 'a, A: MemPool> {
+    lock: &'a PRwLock<T, A>,
+    journal: &'a Journal<A>,
+}
+
+/// An exclusive guard of a [`PRwLock`]
+///
+/// The ownership of the lock is held until the end of the transaction, even
+/// if the guard is dropped.
+///
+/// [`PRwLock`]: ./struct.PRwLock.html
+pub struct RwLockWriteGuard<'a, T: 'a, A: MemPool> {
+    lock: &'a PRwLock<T, A>,
+    journal: &'a Journal<A>,
+}
+
+impl<T: ?Sized, A: MemPool> !TxOutSafe for RwLockReadGuard<'_, T, A> {}
+impl<T: ?Sized, A: MemPool> !Send for RwLockReadGuard<'_, T, A> {}
+impl<T: ?Sized, A: MemPool> !TxOutSafe for UpgradableReadGuard<'_, T, A> {}
+impl<T: ?Sized, A: MemPool> !Send for UpgradableReadGuard<'_, T, A> {}
+impl<T: ?Sized, A: MemPool> !TxOutSafe for RwLockWriteGuard<'_, T, A> {}
+impl<T: ?Sized, A: MemPool> !Send for RwLockWriteGuard<'_, T, A> {}
+
+impl<'a, T, A: MemPool> UpgradableReadGuard<'a, T, A> {
+    /// Atomically upgrades the guard to a write guard
+    ///
+    /// No other writer can acquire the lock in between. It waits for the
+    /// readers of other transactions to leave.
+    ///
+    /// # Panics
+    ///
+    /// It panics if the current transaction holds a read guard of this lock.
+    pub fn upgrade(self) -> RwLockWriteGuard<'a, T, A> {
+        let (lock, journal) = (self.lock, self.journal);
+        mem::forget(self);
+        lock.inner.upgradable.set(false);
+        lock.inner.begin_write();
+        RwLockWriteGuard { lock, journal }
+    }
+}
+
+impl<'a, T, A: MemPool> RwLockWriteGuard<'a, T, A> {
+    /// Downgrades the guard to an upgradable read guard
+    ///
+    /// The lock is still owned by the current transaction. If the data was
+    /// written to, other transactions cannot read it until the transaction is
+    /// done.
+    pub fn downgrade(self) -> UpgradableReadGuard<'a, T, A> {
+        let (lock, journal) = (self.lock, self.journal);
+        mem::forget(self);
+        lock.inner.writing.set(false);
+        lock.inner.upgradable.set(true);
+        UpgradableReadGuard { lock, journal }
+    }
+}
+
+impl<T, A: MemPool> Deref for RwLockReadGuard<'_, T, A> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &(*self.lock.data.get()).1 }
+    }
+}
+
+impl<T, A: MemPool> Deref for UpgradableReadGuard<'_, T, A> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &(*self.lock.data.get()).1 }
+    }
+}
+
+impl<T, A: MemPool> Deref for RwLockWriteGuard<'_, T, A> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &(*self.lock.data.get()).1 }
+    }
+}
+
+impl<T: PSafe, A: MemPool> DerefMut for RwLockWriteGuard<'_, T, A> {
+    #[track_caller]
+    fn deref_mut(&mut self) -> &mut T {
+        self.lock.get_mut(self.journal)
+    }
+}
+
+impl<T, A: MemPool> Drop for RwLockReadGuard<'_, T, A> {
+    fn drop(&mut self) {
+        let inner = &self.lock.inner;
+        if self.local {
+            inner.local_readers.set(inner.local_readers.get() - 1);
+        } else {
+            inner.readers.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+impl<T, A: MemPool> Drop for UpgradableReadGuard<'_, T, A> {
+    fn drop(&mut self) {
+        self.lock.inner.upgradable.set(false);
+    }
+}
+
+impl<T, A: MemPool> Drop for RwLockWriteGuard<'_, T, A> {
+    fn drop(&mut self) {
+        self.lock.inner.writing.set(false);
+    }
+}
+
+impl<T: fmt::Debug, A: MemPool> fmt::Debug for RwLockReadGuard<'_, T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: fmt::Debug, A: MemPool> fmt::Debug for UpgradableReadGuard<'_, T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: fmt::Debug, A: MemPool> fmt::Debug for RwLockWriteGuard<'_, T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::default::*;
+    use std::thread;
+
+    #[test]
+    fn rwlock_upgrade() {
+        let root = Allocator::open::<Parc<PRwLock<i32>>>("rwlock.pool", O_CF).unwrap();
+
+        let mut threads = vec![];
+        for _ in 0..4 {
+            let r = Parc::demote(&root);
+            threads.push(thread::spawn(move || {
+                for _ in 0..10 {
+                    transaction(|j| {
+                        let r = r.promote(j).unwrap();
+                        let g = r.upgradable_read(j);
+                        let v = *g;
+                        let _ = *r.read(j); // local reader
+                        let mut g = g.upgrade();
+                        *g = v + 1;
+                        let g = g.downgrade();
+                        assert_eq!(*g, v + 1);
+                    }).unwrap();
+                }
+            }));
+        }
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        Allocator::transaction(|j| {
+            assert_eq!(*root.read(j), 40);
+        }).unwrap();
+    }
+}
@@ -0,0 +1,240 @@
+use crate::alloc::MemPool;
+use crate::cell::VCell;
+use crate::ptr::Ptr;
+use crate::stm::{Journal, Notifier, Logger};
+use crate::*;
+use super::txlock::{Holder, TxLock};
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+use std::{fmt, ptr};
+
+/// A persistent cell with lock-free reads
+///
+/// It is a sequence lock: the cell has a volatile sequence number which is
+/// odd while a transaction is writing to it. Readers do not need a
+/// transaction or a lock; [`get`] copies the value and retries if the
+/// sequence number has changed in the meantime. Writers take a log and
+/// own the cell until their transaction commits or rolls back, so readers
+/// never observe uncommitted data, and the changes are flushed with the
+/// rest of the transaction.
+///
+/// It suits small [`Copy`] values which are read much more often than they
+/// are written, such as counters, configuration words, and pointers. Readers
+/// may spin while a writer transaction is running.
+///
+/// # Examples
+///
+/// ```
+/// use corundum::default::*;
+/// use std::thread;
+///
+/// type P = Allocator;
+///
+/// let root = P::open::<Parc<PSeqCell<(u64, u64)>>>("seqcell.pool", O_CF).unwrap();
+///
+/// let readers: Vec<_> = (0..4).map(|_| {
+///     let cell = Parc::demote(&root);
+///     thread::spawn(move || P::transaction(|j| {
+///         let cell = cell.promote(j).unwrap();
+///         for _ in 0..100 {
+///             let (a, b) = cell.get(); // no log, no lock
+///             assert_eq!(a, b);
+///         }
+///     }).unwrap())
+/// }).collect();
+///
+/// for i in 1..=100 {
+///     P::transaction(|j| root.set((i, i), j)).unwrap();
+/// }
+/// for r in readers {
+///     r.join().unwrap();
+/// }
+/// assert_eq!(root.get(), (100, 100));
+/// ```
+///
+/// [`get`]: #method.get
+/// [`Copy`]: std::marker::Copy
+pub struct PSeqCell<T, A: MemPool> {
+    heap: PhantomData<A>,
+    inner: VCell<SeqInner, A>,
+    data: UnsafeCell<(u8, T)>,
+}
+
+#[derive(Default)]
+struct SeqInner {
+    /// The sequence number, which is odd while a transaction owns the cell
+    seq: AtomicU64,
+
+    /// The transaction-wide lock of the writer
+    owner: TxLock,
+}
+
+impl SeqInner {
+    /// Probes the owner of the cell. If no transaction owns it, the writing
+    /// of the previous owner is over, and the sequence number is made even.
+    fn holder(&self) -> Holder {
+        self.owner.holder(|| {
+            let seq = self.seq.load(Ordering::Relaxed);
+            if seq & 1 == 1 {
+                self.seq.store(seq + 1, Ordering::Release);
+            }
+        })
+    }
+}
+
+impl<T: ?Sized, A: MemPool> !TxOutSafe for PSeqCell<T, A> {}
+impl<T, A: MemPool> UnwindSafe for PSeqCell<T, A> {}
+impl<T, A: MemPool> RefUnwindSafe for PSeqCell<T, A> {}
+
+unsafe impl<T, A: MemPool> TxInSafe for PSeqCell<T, A> {}
+unsafe impl<T, A: MemPool> PSafe for PSeqCell<T, A> {}
+unsafe impl<T: Send, A: MemPool> Send for PSeqCell<T, A> {}
+unsafe impl<T: Send + Sync, A: MemPool> Sync for PSeqCell<T, A> {}
+unsafe impl<T, A: MemPool> PSend for PSeqCell<T, A> {}
+
+impl<T: PSafe + Copy, A: MemPool> PSeqCell<T, A> {
+    /// Creates a new `PSeqCell`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::alloc::heap::*;
+    /// Heap::transaction(|j| {
+    ///     let p = Parc::new(PSeqCell::new(10), j);
+    /// }).unwrap();
+    /// ```
+    pub fn new(value: T) -> Self {
+        PSeqCell {
+            heap: PhantomData,
+            inner: VCell::new(SeqInner::default()),
+            data: UnsafeCell::new((0, value)),
+        }
+    }
+
+    /// Returns a copy of the committed value without a transaction
+    ///
+    /// It retries while a transaction of another thread is writing to the
+    /// cell. Within the writer transaction, it returns the new value.
+    pub fn get(&self) -> T {
+        let inner = &*self.inner;
+        let mut backoff = 1;
+        loop {
+            let seq = inner.seq.load(Ordering::Acquire);
+            if seq & 1 == 0 {
+                let value = unsafe { ptr::read_volatile(&(*self.data.get()).1) };
+                fence(Ordering::Acquire);
+                if inner.seq.load(Ordering::Relaxed) == seq {
+                    return value;
+                }
+            } else {
+                match inner.holder() {
+                    Holder::Current => return unsafe { (*self.data.get()).1 },
+                    Holder::None => continue,
+                    Holder::Other => {}
+                }
+            }
+            if backoff <= 64 {
+                for _ in 0..backoff {
+                    std::hint::spin_loop();
+                }
+                backoff <<= 1;
+            } else {
+                std::thread::yield_now();
+            }
+        }
+    }
+
+    /// Sets the value of the cell
+    ///
+    /// The cell is owned by the transaction until it commits or rolls back;
+    /// the writers of other transactions wait for it.
+    pub fn set(&self, value: T, journal: &Journal<A>) {
+        *self.get_mut(journal) = value;
+    }
+
+    /// Replaces the value of the cell, and returns the old value
+    pub fn replace(&self, value: T, journal: &Journal<A>) -> T {
+        std::mem::replace(self.get_mut(journal), value)
+    }
+
+    /// Updates the value of the cell using a function, and returns the new
+    /// value
+    pub fn update<F: FnOnce(T) -> T>(&self, f: F, journal: &Journal<A>) -> T {
+        let value = self.get_mut(journal);
+        *value = f(*value);
+        *value
+    }
+
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    #[track_caller]
+    /// Owns the cell, takes a log, and returns a `&mut T` for interior
+    /// mutability
+    fn get_mut(&self, journal: &Journal<A>) -> &mut T {
+        let inner = &*self.inner;
+        inner.owner.own(journal);
+        let seq = inner.seq.load(Ordering::Relaxed);
+        if seq & 1 == 0 {
+            inner.seq.store(seq + 1, Ordering::Relaxed);
+            fence(Ordering::Release);
+        }
+        unsafe {
+            let data = &mut *self.data.get();
+            if data.0 == 0 {
+                assert!(A::valid(data), "The object is not in the pool's valid range");
+                data.1.create_log(journal, Notifier::NonAtomic(Ptr::from_ref(&data.0)));
+            }
+            &mut data.1
+        }
+    }
+}
+
+impl<T: PSafe + Copy + Default, A: MemPool> RootObj<A> for PSeqCell<T, A> {
+    fn init(_journal: &Journal<A>) -> Self {
+        PSeqCell::new(T::default())
+    }
+}
+
+impl<T: PSafe + Copy + fmt::Debug, A: MemPool> fmt::Debug for PSeqCell<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.get(), f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::default::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    static WRITING: AtomicBool = AtomicBool::new(false);
+
+    #[test]
+    fn seqcell_rollback_is_invisible() {
+        let root = Allocator::open::<Parc<PSeqCell<u64>>>("seqcell_test.pool", O_CF).unwrap();
+        Allocator::transaction(|j| root.set(1, j)).unwrap();
+
+        let cell = Parc::demote(&root);
+        let reader = thread::spawn(move || Allocator::transaction(|j| {
+            let cell = cell.promote(j).unwrap();
+            while !WRITING.load(Ordering::Acquire) {
+                thread::yield_now();
+            }
+            cell.get()
+        }).unwrap());
+
+        assert!(Allocator::transaction(|j| {
+            root.set(2, j);
+            assert_eq!(root.get(), 2);
+            WRITING.store(true, Ordering::Release);
+            thread::sleep(Duration::from_millis(10));
+            panic!("abort");
+        }).is_err());
+
+        assert_eq!(reader.join().unwrap(), 1);
+        assert_eq!(root.get(), 1);
+    }
+}
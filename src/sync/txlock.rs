@@ -0,0 +1,217 @@
+use crate::alloc::MemPool;
+use crate::stm::{Journal, Log};
+use crate::utils;
+
+#[cfg(any(feature = "no_pthread", windows, miri, loom))]
+use crate::ll::model::{self, atomic::{AtomicU64, Ordering::*}};
+
+/// The lock word of [`TxLock`] and [`PMutex`] without pthread
+///
+/// It is zero when the lock is free, or the id of the owner thread. It goes
+/// through `ll::model`, so that loom checks the interleavings of the locks.
+///
+/// [`PMutex`]: ./struct.PMutex.html
+#[cfg(any(feature = "no_pthread", windows, miri, loom))]
+#[repr(transparent)]
+pub(crate) struct LockWord(AtomicU64);
+
+#[cfg(any(feature = "no_pthread", windows, miri, loom))]
+impl LockWord {
+    pub(crate) fn new() -> Self {
+        LockWord(AtomicU64::new(0))
+    }
+
+    /// Tries to take the lock, and returns true if the current thread owns
+    /// it, either now or from before
+    pub(crate) fn try_acquire(&self) -> bool {
+        let tid = model::thread_id();
+        match self.0.compare_exchange(0, tid, AcqRel, Acquire) {
+            Ok(_) => true,
+            Err(owner) => owner == tid,
+        }
+    }
+
+    pub(crate) fn release(&self) {
+        self.0.store(0, Release);
+    }
+}
+
+/// The result of probing a [`TxLock`]
+pub(crate) enum Holder {
+    /// No transaction owns the lock
+    None,
+
+    /// The current transaction owns the lock
+    Current,
+
+    /// Another transaction owns the lock
+    Other,
+}
+
+/// A volatile lock which is owned by a transaction until it commits or rolls
+/// back
+///
+/// It uses the same layout as the lock of [`PMutex`] so that it can be
+/// released by an [`UnlockOnCommit`] log. Unlike [`PMutex`], it is owned
+/// only once per transaction, which allows other threads to probe whether
+/// the current owner is a different transaction.
+///
+/// [`PMutex`]: ./struct.PMutex.html
+/// [`UnlockOnCommit`]: ../stm/enum.LogEnum.html#variant.UnlockOnCommit
+pub(crate) struct TxLock {
+    /// The first item indicates if the lock is owned until the end of the
+    /// owner transaction
+    #[cfg(not(any(feature = "no_pthread", windows, miri, loom)))]
+    lock: (bool, libc::pthread_mutex_t, libc::pthread_mutexattr_t),
+
+    #[cfg(any(feature = "no_pthread", windows, miri, loom))]
+    lock: (bool, LockWord)
+}
+
+impl Default for TxLock {
+
+    #[cfg(not(any(feature = "no_pthread", windows, miri, loom)))]
+    fn default() -> Self {
+        use std::mem::MaybeUninit;
+        let mut attr = MaybeUninit::<libc::pthread_mutexattr_t>::uninit();
+        let mut lock = libc::PTHREAD_MUTEX_INITIALIZER;
+        unsafe { super::init_lock(&mut lock, attr.as_mut_ptr()); }
+        TxLock { lock: (false, lock, unsafe { attr.assume_init() }) }
+    }
+
+    #[cfg(any(feature = "no_pthread", windows, miri, loom))]
+    fn default() -> Self {
+        TxLock { lock: (false, LockWord::new()) }
+    }
+}
+
+impl TxLock {
+    #[cfg(not(any(feature = "no_pthread", windows, miri, loom)))]
+    fn acquire(&self) {
+        unsafe { libc::pthread_mutex_lock(&self.lock.1 as *const _ as *mut _); }
+    }
+
+    #[cfg(any(feature = "no_pthread", windows, miri, loom))]
+    fn acquire(&self) {
+        while !self.lock.1.try_acquire() {
+            model::spin_loop();
+        }
+    }
+
+    #[cfg(not(any(feature = "no_pthread", windows, miri, loom)))]
+    fn try_acquire(&self) -> bool {
+        unsafe { libc::pthread_mutex_trylock(&self.lock.1 as *const _ as *mut _) == 0 }
+    }
+
+    #[cfg(any(feature = "no_pthread", windows, miri, loom))]
+    fn try_acquire(&self) -> bool {
+        self.lock.1.try_acquire()
+    }
+
+    /// Releases a lock which was not owned before acquiring it
+    #[cfg(not(any(feature = "no_pthread", windows, miri, loom)))]
+    fn release(&self) {
+        unsafe { libc::pthread_mutex_unlock(&self.lock.1 as *const _ as *mut _); }
+    }
+
+    #[cfg(any(feature = "no_pthread", windows, miri, loom))]
+    fn release(&self) {
+        self.lock.1.release();
+    }
+
+    /// Drops the extra level of recursion of a lock which is already owned
+    #[cfg(not(any(feature = "no_pthread", windows, miri, loom)))]
+    fn release_recursive(&self) {
+        self.release();
+    }
+
+    #[cfg(any(feature = "no_pthread", windows, miri, loom))]
+    fn release_recursive(&self) {}
+
+    /// Takes the ownership after acquiring the lock, and returns true if it
+    /// was not owned by the current transaction
+    fn take<A: MemPool>(&self, journal: &Journal<A>) -> bool {
+        if self.lock.0 {
+            self.release_recursive();
+            false
+        } else {
+            unsafe {
+                Log::unlock_on_commit(&self.lock as *const _ as u64, journal);
+                utils::as_mut(self).lock.0 = true;
+            }
+            true
+        }
+    }
+
+    /// Blocks until the current transaction owns the lock. It returns true if
+    /// the lock was not owned by the current transaction before.
+    pub(crate) fn own<A: MemPool>(&self, journal: &Journal<A>) -> bool {
+        {
+            #[cfg(feature = "stat_perf")]
+            let _timer = crate::stat::PhaseTimer::start(crate::stat::TxPhase::LockWait);
+
+            self.acquire();
+        }
+        self.take(journal)
+    }
+
+    /// Attempts to own the lock without blocking. It returns `None` if
+    /// another transaction owns it; otherwise, it returns whether the lock
+    /// was not owned by the current transaction before.
+    pub(crate) fn try_own<A: MemPool>(&self, journal: &Journal<A>) -> Option<bool> {
+        if self.try_acquire() {
+            Some(self.take(journal))
+        } else {
+            None
+        }
+    }
+
+    /// Probes the owner of the lock without blocking. If no transaction owns
+    /// it, `on_free` is called while the lock is briefly held.
+    pub(crate) fn holder<F: FnOnce()>(&self, on_free: F) -> Holder {
+        if !self.try_acquire() {
+            Holder::Other
+        } else if self.lock.0 {
+            self.release_recursive();
+            Holder::Current
+        } else {
+            on_free();
+            self.release();
+            Holder::None
+        }
+    }
+}
+
+#[cfg(all(test, loom))]
+mod test {
+    use super::LockWord;
+    use crate::ll::model::{self, atomic::{AtomicUsize, Ordering::Relaxed}, thread};
+    use loom::sync::Arc;
+
+    /// The lock word of `PMutex` and `TxLock` excludes the other threads, and
+    /// it is recursive for the owner thread
+    #[test]
+    fn lock_word_excludes() {
+        loom::model(|| {
+            let shared = Arc::new((LockWord::new(), AtomicUsize::new(0)));
+            let threads: Vec<_> = (0..2).map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    while !shared.0.try_acquire() {
+                        model::spin_loop();
+                    }
+                    assert!(shared.0.try_acquire());
+
+                    // Without exclusion, one of the increments may be lost
+                    let n = shared.1.load(Relaxed);
+                    shared.1.store(n + 1, Relaxed);
+                    shared.0.release();
+                })
+            }).collect();
+            for t in threads {
+                t.join().unwrap();
+            }
+            assert_eq!(shared.1.load(Relaxed), 2);
+        });
+    }
+}
@@ -0,0 +1,677 @@
+//! Property-based testing of persistent collections
+//!
+//! This module is enabled by the `testing` feature. It provides [`proptest`]
+//! strategies which generate random sequences of operations, and
+//! [`check_model()`] which runs them on a persistent collection and on its
+//! counterpart in `std` side by side, comparing their outputs and contents
+//! after every step.
+//!
+//! Some steps are simulated crashes ([`Step::Crash`]): the operation is
+//! applied in a transaction, and the pool file is copied before it commits.
+//! The copy is the image of a crash in the middle of the transaction; it is
+//! opened in place of the pool, which runs the recovery procedure, and the
+//! recovered collection should be exactly as it was before the step. The
+//! live transaction is then aborted, and the test goes on with the pool.
+//!
+//! The collections that are checked implement [`Model`]. The implementations
+//! for [`PVec`], [`HashMap`], [`PBPlusTree`], [`PArt`], [`PLruCache`],
+//! [`PBitVec`], [`PLog`], and [`PRope`] are included.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # #[cfg(feature = "testing")] {
+//! use corundum::default::*;
+//! use corundum::testing::{self, check_model};
+//! use proptest::test_runner::TestRunner;
+//!
+//! type P = Allocator;
+//!
+//! let _ = P::open::<PRefCell<PVec<u32>>>("foo.pool", O_CF).unwrap();
+//!
+//! TestRunner::default().run(&testing::steps(testing::seq_ops::<u32>(), 0..64), |steps| {
+//!     check_model::<PRefCell<PVec<u32>>, _, P>("foo.pool", |root| root, &steps)
+//! }).unwrap();
+//! # }
+//! ```
+//!
+//! [`proptest`]: https://docs.rs/proptest
+//! [`check_model()`]: ./fn.check_model.html
+//! [`Step::Crash`]: ./enum.Step.html#variant.Crash
+//! [`Model`]: ./trait.Model.html
+//! [`PVec`]: ../vec/struct.Vec.html
+//! [`HashMap`]: ../stl/struct.HashMap.html
+//! [`PBPlusTree`]: ../stl/struct.PBPlusTree.html
+//! [`PArt`]: ../stl/struct.PArt.html
+//! [`PLruCache`]: ../stl/struct.PLruCache.html
+//! [`PBitVec`]: ../stl/struct.PBitVec.html
+//! [`PLog`]: ../stl/struct.PLog.html
+//! [`PRope`]: ../stl/struct.PRope.html
+
+use crate::alloc::MemPool;
+use crate::cell::PRefCell;
+use crate::stl::{HashMap, PArt, PBPlusTree, PBitVec, PLog, PLruCache, PRope};
+use crate::stm::Journal;
+use crate::vec::Vec as PVec;
+use crate::{PSafe, RootObj, TxInSafe, TxOutSafe};
+use proptest::collection::SizeRange;
+use proptest::prelude::*;
+use proptest::test_runner::TestCaseError;
+use std::collections::{BTreeMap, HashMap as StdHashMap};
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::panic::{self, RefUnwindSafe};
+
+/// An operation on a key-value map
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MapOp<K, V> {
+    /// Inserts or replaces the value of a key
+    Insert(K, V),
+
+    /// Removes a key
+    Remove(K),
+
+    /// Looks a key up
+    Get(K),
+
+    /// Removes all keys
+    Clear,
+}
+
+/// An operation on a sequence
+///
+/// The indices are taken modulo the length of the sequence (plus one for
+/// `Insert`), so that every generated operation is valid.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SeqOp<T> {
+    /// Appends an element
+    Push(T),
+
+    /// Removes the last element
+    Pop,
+
+    /// Inserts an element at an index
+    Insert(usize, T),
+
+    /// Removes the element at an index
+    Remove(usize),
+
+    /// Keeps the first elements
+    Truncate(usize),
+
+    /// Removes all elements
+    Clear,
+}
+
+/// An operation on a bit vector
+///
+/// The indices are taken modulo the length; `Set` and `Flip` do nothing on an
+/// empty bit vector.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BitOp {
+    /// Appends a bit
+    Push(bool),
+
+    /// Sets the bit at an index
+    Set(usize, bool),
+
+    /// Flips the bit at an index
+    Flip(usize),
+
+    /// Resizes the bit vector; the new bits are unset
+    Resize(usize),
+}
+
+/// An operation on an append-only log
+///
+/// The indices are offsets from the first entry which is not truncated,
+/// taken modulo the length plus one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LogOp<T> {
+    /// Appends an entry
+    Append(T),
+
+    /// Looks an entry up
+    Get(usize),
+
+    /// Drops the entries before an index
+    TruncateBefore(usize),
+}
+
+/// An operation on a text
+///
+/// The byte indices are taken modulo the length plus one, and the generated
+/// strings are ASCII, so that every index is a `char` boundary.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TextOp {
+    /// Appends a string
+    Push(String),
+
+    /// Inserts a string at an index
+    Insert(usize, String),
+
+    /// Removes a number of bytes from an index
+    Remove(usize, usize),
+
+    /// Removes all text
+    Clear,
+}
+
+/// A step of a test case
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Step<Op> {
+    /// Applies the operation in a transaction which commits
+    Apply(Op),
+
+    /// Applies the operation in a transaction which crashes before it
+    /// commits; the operation should have no effect after recovery
+    Crash(Op),
+}
+
+/// The payload of the unwinding which simulates a crash
+struct SimulatedCrash;
+
+/// Generates operations on maps
+pub fn map_ops<K, V>() -> impl Strategy<Value = MapOp<K, V>>
+where
+    K: Arbitrary + Clone + 'static,
+    V: Arbitrary + Clone + 'static,
+{
+    prop_oneof![
+        4 => (any::<K>(), any::<V>()).prop_map(|(k, v)| MapOp::Insert(k, v)),
+        2 => any::<K>().prop_map(MapOp::Remove),
+        2 => any::<K>().prop_map(MapOp::Get),
+        1 => Just(MapOp::Clear),
+    ]
+}
+
+/// Generates operations on sequences
+pub fn seq_ops<T>() -> impl Strategy<Value = SeqOp<T>>
+where
+    T: Arbitrary + Clone + 'static,
+{
+    prop_oneof![
+        4 => any::<T>().prop_map(SeqOp::Push),
+        2 => Just(SeqOp::Pop),
+        2 => (any::<usize>(), any::<T>()).prop_map(|(i, v)| SeqOp::Insert(i, v)),
+        2 => any::<usize>().prop_map(SeqOp::Remove),
+        1 => any::<usize>().prop_map(SeqOp::Truncate),
+        1 => Just(SeqOp::Clear),
+    ]
+}
+
+/// Generates operations on bit vectors
+pub fn bit_ops() -> impl Strategy<Value = BitOp> {
+    prop_oneof![
+        3 => any::<bool>().prop_map(BitOp::Push),
+        3 => (any::<usize>(), any::<bool>()).prop_map(|(i, v)| BitOp::Set(i, v)),
+        3 => any::<usize>().prop_map(BitOp::Flip),
+        1 => (0..256usize).prop_map(BitOp::Resize),
+    ]
+}
+
+/// Generates operations on logs
+pub fn log_ops<T>() -> impl Strategy<Value = LogOp<T>>
+where
+    T: Arbitrary + Clone + 'static,
+{
+    prop_oneof![
+        4 => any::<T>().prop_map(LogOp::Append),
+        2 => any::<usize>().prop_map(LogOp::Get),
+        1 => any::<usize>().prop_map(LogOp::TruncateBefore),
+    ]
+}
+
+/// Generates operations on texts
+pub fn text_ops() -> impl Strategy<Value = TextOp> {
+    prop_oneof![
+        3 => "[a-z]{0,16}".prop_map(TextOp::Push),
+        3 => (any::<usize>(), "[a-z]{0,16}").prop_map(|(i, s)| TextOp::Insert(i, s)),
+        2 => (any::<usize>(), any::<usize>()).prop_map(|(i, n)| TextOp::Remove(i, n)),
+        1 => Just(TextOp::Clear),
+    ]
+}
+
+/// Generates sequences of steps with operations from `op`, where one in ten
+/// steps is a simulated crash
+pub fn steps<S: Strategy>(op: S, len: impl Into<SizeRange>) -> impl Strategy<Value = Vec<Step<S::Value>>> {
+    proptest::collection::vec(
+        (op, 0..10u8).prop_map(|(op, c)| if c == 0 { Step::Crash(op) } else { Step::Apply(op) }),
+        len,
+    )
+}
+
+/// A persistent collection which is checked against a volatile model
+pub trait Model<A: MemPool>: PSafe {
+    /// The operations on the collection
+    type Op: Clone + Debug + TxInSafe + RefUnwindSafe;
+
+    /// The model of the collection, which is also the type of its snapshot
+    type Std: Debug + PartialEq;
+
+    /// The output of an operation
+    type Out: Debug + PartialEq + TxOutSafe;
+
+    /// Applies `op` on the persistent collection
+    fn apply(&mut self, op: &Self::Op, j: &Journal<A>) -> Self::Out;
+
+    /// Applies `op` on the model
+    fn apply_std(model: &mut Self::Std, op: &Self::Op) -> Self::Out;
+
+    /// Returns the contents of the persistent collection as a model
+    fn snapshot(&self) -> Self::Std;
+}
+
+/// Runs `steps` on the collection which `field` selects in the root object
+/// of the pool at `path`, and on its model, and fails at the first step in
+/// which their outputs or contents differ
+///
+/// The pool should exist, and it should not be open; it is opened here, and
+/// it is closed and reopened around every simulated crash. The model starts
+/// from the current contents of the collection, so the same collection may be
+/// used for several test cases.
+pub fn check_model<R, M, A>(
+    path: &str,
+    field: fn(&R) -> &PRefCell<M, A>,
+    steps: &[Step<M::Op>],
+) -> std::result::Result<(), TestCaseError>
+where
+    R: PSafe + RootObj<A>,
+    M: Model<A>,
+    A: MemPool,
+{
+    let fail = |i: usize, e: crate::Error| TestCaseError::fail(format!("step {}: {}", i, e));
+    let image = format!("{}.crash", path);
+    let mut root = A::open::<R>(path, 0).map_err(|e| fail(0, e))?;
+    let mut model = field(&root).borrow().snapshot();
+    for (i, step) in steps.iter().enumerate() {
+        match step {
+            Step::Apply(op) => {
+                let out = A::transaction(|j| field(&root).borrow_mut(j).apply(op, j))
+                    .map_err(|e| fail(i, e))?;
+                prop_assert_eq!(out, M::apply_std(&mut model, op), "step {}: {:?}", i, step);
+            }
+            Step::Crash(op) => {
+                let res = A::transaction(|j| -> () {
+                    field(&root).borrow_mut(j).apply(op, j);
+                    // Opening the image fails below if it is not copied
+                    let _ = std::fs::copy(path, &image);
+                    panic::resume_unwind(Box::new(SimulatedCrash))
+                });
+                prop_assert!(res.is_err(), "step {}: the crashed transaction committed", i);
+                drop(root);
+                {
+                    let crashed = A::open::<R>(&image, 0).map_err(|e| fail(i, e))?;
+                    prop_assert_eq!(&field(&crashed).borrow().snapshot(), &model,
+                        "step {}: recovered from {:?}", i, step);
+                }
+                let _ = std::fs::remove_file(&image);
+                root = A::open::<R>(path, 0).map_err(|e| fail(i, e))?;
+            }
+        }
+        prop_assert_eq!(&field(&root).borrow().snapshot(), &model, "step {}: {:?}", i, step);
+    }
+    Ok(())
+}
+
+impl<T, A: MemPool> Model<A> for PVec<T, A>
+where
+    T: PSafe + Clone + Debug + PartialEq + TxInSafe + TxOutSafe + RefUnwindSafe,
+{
+    type Op = SeqOp<T>;
+    type Std = Vec<T>;
+    type Out = Option<T>;
+
+    fn apply(&mut self, op: &SeqOp<T>, j: &Journal<A>) -> Option<T> {
+        match op {
+            SeqOp::Push(v) => self.push(v.clone(), j),
+            SeqOp::Pop => return self.pop(),
+            SeqOp::Insert(i, v) => self.insert(i % (self.len() + 1), v.clone(), j),
+            SeqOp::Remove(i) if !self.is_empty() => return Some(self.remove(i % self.len(), j)),
+            SeqOp::Remove(_) => {}
+            SeqOp::Truncate(n) => self.truncate(n % (self.len() + 1)),
+            SeqOp::Clear => self.clear(),
+        }
+        None
+    }
+
+    fn apply_std(model: &mut Vec<T>, op: &SeqOp<T>) -> Option<T> {
+        match op {
+            SeqOp::Push(v) => model.push(v.clone()),
+            SeqOp::Pop => return model.pop(),
+            SeqOp::Insert(i, v) => model.insert(i % (model.len() + 1), v.clone()),
+            SeqOp::Remove(i) if !model.is_empty() => return Some(model.remove(i % model.len())),
+            SeqOp::Remove(_) => {}
+            SeqOp::Truncate(n) => model.truncate(n % (model.len() + 1)),
+            SeqOp::Clear => model.clear(),
+        }
+        None
+    }
+
+    fn snapshot(&self) -> Vec<T> {
+        self.as_slice().to_vec()
+    }
+}
+
+impl<K, V, A: MemPool> Model<A> for HashMap<K, V, A>
+where
+    K: PSafe + Clone + Debug + Eq + Hash + TxInSafe + RefUnwindSafe,
+    V: PSafe + Clone + Debug + PartialEq + TxInSafe + TxOutSafe + RefUnwindSafe,
+{
+    type Op = MapOp<K, V>;
+    type Std = StdHashMap<K, V>;
+    type Out = Option<V>;
+
+    fn apply(&mut self, op: &MapOp<K, V>, j: &Journal<A>) -> Option<V> {
+        match op {
+            MapOp::Insert(k, v) => self.put(k.clone(), v.clone(), j),
+            MapOp::Remove(k) => return self.remove(k, j),
+            MapOp::Get(k) => return self.get(k.clone()).cloned(),
+            MapOp::Clear => self.clear(j),
+        }
+        None
+    }
+
+    fn apply_std(model: &mut StdHashMap<K, V>, op: &MapOp<K, V>) -> Option<V> {
+        match op {
+            MapOp::Insert(k, v) => { model.insert(k.clone(), v.clone()); }
+            MapOp::Remove(k) => return model.remove(k),
+            MapOp::Get(k) => return model.get(k).cloned(),
+            MapOp::Clear => model.clear(),
+        }
+        None
+    }
+
+    fn snapshot(&self) -> StdHashMap<K, V> {
+        self.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+    }
+}
+
+impl<K, V, A: MemPool> Model<A> for PBPlusTree<K, V, A>
+where
+    K: PSafe + Ord + Copy + Hash + Debug + TxInSafe + RefUnwindSafe,
+    V: PSafe + Clone + Debug + PartialEq + TxInSafe + TxOutSafe + RefUnwindSafe,
+{
+    type Op = MapOp<K, V>;
+    type Std = BTreeMap<K, V>;
+    type Out = Option<V>;
+
+    fn apply(&mut self, op: &MapOp<K, V>, j: &Journal<A>) -> Option<V> {
+        match op {
+            MapOp::Insert(k, v) => self.insert(*k, v.clone(), j),
+            MapOp::Remove(k) => self.remove(k, j),
+            MapOp::Get(k) => self.get(k).cloned(),
+            MapOp::Clear => {
+                let keys: Vec<K> = self.keys().copied().collect();
+                for k in keys {
+                    self.remove(&k, j);
+                }
+                None
+            }
+        }
+    }
+
+    fn apply_std(model: &mut BTreeMap<K, V>, op: &MapOp<K, V>) -> Option<V> {
+        match op {
+            MapOp::Insert(k, v) => model.insert(*k, v.clone()),
+            MapOp::Remove(k) => model.remove(k),
+            MapOp::Get(k) => model.get(k).cloned(),
+            MapOp::Clear => {
+                model.clear();
+                None
+            }
+        }
+    }
+
+    fn snapshot(&self) -> BTreeMap<K, V> {
+        self.iter().map(|(k, v)| (*k, v.clone())).collect()
+    }
+}
+
+impl<V, A: MemPool> Model<A> for PArt<V, A>
+where
+    V: PSafe + Clone + Debug + PartialEq + TxInSafe + TxOutSafe + RefUnwindSafe,
+{
+    type Op = MapOp<Vec<u8>, V>;
+    type Std = BTreeMap<Vec<u8>, V>;
+    type Out = Option<V>;
+
+    fn apply(&mut self, op: &MapOp<Vec<u8>, V>, j: &Journal<A>) -> Option<V> {
+        match op {
+            MapOp::Insert(k, v) => self.insert(k, v.clone(), j),
+            MapOp::Remove(k) => self.remove(k, j),
+            MapOp::Get(k) => self.get(k).cloned(),
+            MapOp::Clear => {
+                let keys: Vec<Vec<u8>> = self.keys().map(|k| k.to_vec()).collect();
+                for k in keys {
+                    self.remove(k, j);
+                }
+                None
+            }
+        }
+    }
+
+    fn apply_std(model: &mut BTreeMap<Vec<u8>, V>, op: &MapOp<Vec<u8>, V>) -> Option<V> {
+        match op {
+            MapOp::Insert(k, v) => model.insert(k.clone(), v.clone()),
+            MapOp::Remove(k) => model.remove(k),
+            MapOp::Get(k) => model.get(k).cloned(),
+            MapOp::Clear => {
+                model.clear();
+                None
+            }
+        }
+    }
+
+    fn snapshot(&self) -> BTreeMap<Vec<u8>, V> {
+        self.iter().map(|(k, v)| (k.to_vec(), v.clone())).collect()
+    }
+}
+
+/// The model does not evict entries, so the test cases should use fewer
+/// distinct keys than the capacity of the cache; an eviction fails the check.
+impl<K, V, A: MemPool> Model<A> for PLruCache<K, V, A>
+where
+    K: PSafe + Clone + Debug + Eq + Hash + TxInSafe + RefUnwindSafe,
+    V: PSafe + Clone + Debug + PartialEq + TxInSafe + TxOutSafe + RefUnwindSafe,
+{
+    type Op = MapOp<K, V>;
+    type Std = StdHashMap<K, V>;
+    type Out = Option<V>;
+
+    fn apply(&mut self, op: &MapOp<K, V>, j: &Journal<A>) -> Option<V> {
+        match op {
+            MapOp::Insert(k, v) => self.put(k.clone(), v.clone(), j),
+            MapOp::Remove(k) => self.remove(k, j),
+            MapOp::Get(k) => self.get(k).cloned(),
+            MapOp::Clear => {
+                let keys: Vec<K> = self.keys().cloned().collect();
+                for k in keys {
+                    self.remove(&k, j);
+                }
+                None
+            }
+        }
+    }
+
+    fn apply_std(model: &mut StdHashMap<K, V>, op: &MapOp<K, V>) -> Option<V> {
+        match op {
+            MapOp::Insert(k, v) => model.insert(k.clone(), v.clone()),
+            MapOp::Remove(k) => model.remove(k),
+            MapOp::Get(k) => model.get(k).cloned(),
+            MapOp::Clear => {
+                model.clear();
+                None
+            }
+        }
+    }
+
+    fn snapshot(&self) -> StdHashMap<K, V> {
+        self.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+    }
+}
+
+impl<A: MemPool> Model<A> for PBitVec<A> {
+    type Op = BitOp;
+    type Std = Vec<bool>;
+    type Out = Option<bool>;
+
+    fn apply(&mut self, op: &BitOp, j: &Journal<A>) -> Option<bool> {
+        match op {
+            BitOp::Push(v) => self.push(*v, j),
+            BitOp::Set(i, v) if !self.is_empty() => self.set(i % self.len(), *v, j),
+            BitOp::Flip(i) if !self.is_empty() => return Some(self.flip(i % self.len(), j)),
+            BitOp::Set(..) | BitOp::Flip(_) => {}
+            BitOp::Resize(n) => self.resize(*n, j),
+        }
+        None
+    }
+
+    fn apply_std(model: &mut Vec<bool>, op: &BitOp) -> Option<bool> {
+        match op {
+            BitOp::Push(v) => model.push(*v),
+            BitOp::Set(i, v) if !model.is_empty() => {
+                let len = model.len();
+                model[i % len] = *v;
+            }
+            BitOp::Flip(i) if !model.is_empty() => {
+                let len = model.len();
+                let bit = &mut model[i % len];
+                *bit = !*bit;
+                return Some(*bit);
+            }
+            BitOp::Set(..) | BitOp::Flip(_) => {}
+            BitOp::Resize(n) => model.resize(*n, false),
+        }
+        None
+    }
+
+    fn snapshot(&self) -> Vec<bool> {
+        (0..self.len()).map(|i| self.get(i).unwrap()).collect()
+    }
+}
+
+/// The model is the index of the first entry and the entries which are not
+/// truncated.
+impl<T, A: MemPool> Model<A> for PLog<T, A>
+where
+    T: PSafe + Clone + Debug + PartialEq + TxInSafe + TxOutSafe + RefUnwindSafe,
+{
+    type Op = LogOp<T>;
+    type Std = (u64, Vec<T>);
+    type Out = Option<T>;
+
+    fn apply(&mut self, op: &LogOp<T>, j: &Journal<A>) -> Option<T> {
+        let at = |i: usize| self.first_index() + (i % (self.len() + 1)) as u64;
+        match op {
+            LogOp::Append(v) => { self.append(v.clone(), j); }
+            LogOp::Get(i) => return self.get(at(*i)).cloned(),
+            LogOp::TruncateBefore(i) => self.truncate_before(at(*i), j),
+        }
+        None
+    }
+
+    fn apply_std(model: &mut (u64, Vec<T>), op: &LogOp<T>) -> Option<T> {
+        let (first, entries) = model;
+        match op {
+            LogOp::Append(v) => entries.push(v.clone()),
+            LogOp::Get(i) => return entries.get(i % (entries.len() + 1)).cloned(),
+            LogOp::TruncateBefore(i) => {
+                let n = i % (entries.len() + 1);
+                entries.drain(..n);
+                *first += n as u64;
+            }
+        }
+        None
+    }
+
+    fn snapshot(&self) -> (u64, Vec<T>) {
+        (self.first_index(), self.iter().map(|(_, v)| v.clone()).collect())
+    }
+}
+
+impl<A: MemPool> Model<A> for PRope<A> {
+    type Op = TextOp;
+    type Std = String;
+    type Out = ();
+
+    fn apply(&mut self, op: &TextOp, j: &Journal<A>) {
+        match op {
+            TextOp::Push(s) => self.push_str(s, j),
+            TextOp::Insert(i, s) => self.insert_str(i % (self.len() + 1), s, j),
+            TextOp::Remove(i, n) => {
+                let start = i % (self.len() + 1);
+                self.remove(start..start + n % (self.len() - start + 1), j);
+            }
+            TextOp::Clear => self.clear(j),
+        }
+    }
+
+    fn apply_std(model: &mut String, op: &TextOp) {
+        match op {
+            TextOp::Push(s) => model.push_str(s),
+            TextOp::Insert(i, s) => model.insert_str(i % (model.len() + 1), s),
+            TextOp::Remove(i, n) => {
+                let start = i % (model.len() + 1);
+                model.replace_range(start..start + n % (model.len() - start + 1), "");
+            }
+            TextOp::Clear => model.clear(),
+        }
+    }
+
+    fn snapshot(&self) -> String {
+        self.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::RootObj;
+    use crate::default::*;
+    use proptest::test_runner::{Config, TestRunner};
+
+    type P = Allocator;
+
+    struct Root {
+        vec: PRefCell<PVec<u8>>,
+        map: PRefCell<HashMap<u8, u32, P>>,
+        tree: PRefCell<PBPlusTree<u8, u32, P>>,
+        art: PRefCell<PArt<u32, P>>,
+        lru: PRefCell<PLruCache<u8, u32, P>>,
+        bits: PRefCell<PBitVec<P>>,
+        log: PRefCell<PLog<u32, P>>,
+        rope: PRefCell<PRope<P>>,
+    }
+
+    impl RootObj<P> for Root {
+        fn init(j: &Journal) -> Self {
+            Self {
+                vec: PRefCell::new(PVec::new()),
+                map: PRefCell::new(HashMap::new(j)),
+                tree: PRefCell::new(PBPlusTree::new()),
+                art: PRefCell::new(PArt::new()),
+                lru: PRefCell::new(PLruCache::new(256, j)),
+                bits: PRefCell::new(PBitVec::new()),
+                log: PRefCell::new(PLog::new()),
+                rope: PRefCell::new(PRope::new()),
+            }
+        }
+    }
+
+    #[test]
+    fn model_check_collections() {
+        const PATH: &str = "testing.pool";
+        drop(P::open::<Root>(PATH, O_CF).unwrap());
+
+        let mut runner = TestRunner::new(Config::with_cases(32));
+        runner.run(&steps(seq_ops::<u8>(), 0..64), |steps| check_model(PATH, |r: &Root| &r.vec, &steps)).unwrap();
+        runner.run(&steps(map_ops::<u8, u32>(), 0..128), |steps| check_model(PATH, |r: &Root| &r.map, &steps)).unwrap();
+        runner.run(&steps(map_ops::<u8, u32>(), 0..128), |steps| check_model(PATH, |r: &Root| &r.tree, &steps)).unwrap();
+        runner.run(&steps(map_ops::<Vec<u8>, u32>(), 0..128), |steps| check_model(PATH, |r: &Root| &r.art, &steps)).unwrap();
+        runner.run(&steps(map_ops::<u8, u32>(), 0..128), |steps| check_model(PATH, |r: &Root| &r.lru, &steps)).unwrap();
+        runner.run(&steps(bit_ops(), 0..128), |steps| check_model(PATH, |r: &Root| &r.bits, &steps)).unwrap();
+        runner.run(&steps(log_ops::<u32>(), 0..128), |steps| check_model(PATH, |r: &Root| &r.log, &steps)).unwrap();
+        runner.run(&steps(text_ops(), 0..64), |steps| check_model(PATH, |r: &Root| &r.rope, &steps)).unwrap();
+    }
+}
@@ -41,6 +41,37 @@ pub(crate) mod problems {
         P::print_info();
     }
 
+    #[test]
+    fn inline_log_rollback() {
+        use crate::default::*;
+        type P = Allocator;
+
+        struct Root {
+            counter: PCell<u64>,
+            words: PRefCell<[u32; 4]>,
+        }
+
+        impl RootObj<P> for Root {
+            fn init(_j: &Journal<P>) -> Self {
+                Root {
+                    counter: PCell::new(1),
+                    words: PRefCell::new([1, 2, 3, 4]),
+                }
+            }
+        }
+
+        let root = P::open::<Root>("inline_log.pool", O_CF).unwrap();
+        let res = P::transaction(|j| {
+            root.counter.set(2, j);
+            root.counter.set(3, j);
+            *root.words.borrow_mut(j) = [5, 6, 7, 8];
+            panic!("abort");
+        });
+        assert!(res.is_err());
+        assert_eq!(root.counter.get(), 1);
+        assert_eq!(*root.words.borrow(), [1, 2, 3, 4]);
+    }
+
     #[test]
     #[ignore]
     fn challenge_mt() {
@@ -2010,11 +2041,12 @@ mod test_btree {
             }
         }
 
+        /// Inserts `item` into the subtree of `p`. If `p` splits, it returns
+        /// the separator key and the new right sibling.
         fn insert(
             p: &Rc<RefCell<BTreeNode<K, V>>>,
             item: NodeItem<K, V>,
-        ) -> Option<BTreeNode<K, V>> {
-            // TODO: do not doublicate key (for publication)
+        ) -> Option<(K, Rc<RefCell<BTreeNode<K, V>>>)> {
             let mut this = p.borrow_mut();
             match &mut *this {
                 Leaf(this) => {
@@ -2026,98 +2058,92 @@ mod test_btree {
                             }
                         }
                     }
-                    if this.len < N - 2 {
+                    if this.len < N - 1 {
+                        this.add(item);
+                        return None;
+                    }
+
+                    // should split
+                    let mut new = LeafNode::<K, V> {
+                        len: 0,
+                        left: Rc::downgrade(p),
+                        right: this.right.clone(),
+                        values: Default::default(),
+                    };
+                    let mid = N / 2;
+                    let cmp = this.values[mid - 1].as_ref().unwrap().key;
+                    for i in mid..N - 1 {
+                        new.values[i - mid] = this.values[i].take();
+                    }
+                    new.len = N - 1 - mid;
+                    this.len = mid;
+                    if item.key <= cmp {
                         this.add(item);
-                        None
                     } else {
-                        if let Some(left) = this.left.upgrade() {
-                            let sib = left.borrow();
-                            if sib.len() < N - 1 {
-                                let smallest = this.values[0].as_ref().unwrap().clone();
-                                if item.key <= smallest.key {
-                                    Self::insert(&left, item);
-                                } else {
-                                    Self::insert(&left, smallest);
-                                    this.values.rotate_left(1);
-                                    this.values[N - 2] = None;
-                                    this.len -= 1;
-                                    this.add(item);
-                                }
-                                return None;
-                            }
-                        }
-                        if let Some(right) = this.right.upgrade() {
-                            let sib = right.borrow();
-                            if sib.len() < N - 1 {
-                                let largest = this.values[N - 2].as_ref().unwrap().clone();
-                                if item.key >= largest.key {
-                                    Self::insert(&right, item);
-                                } else {
-                                    Self::insert(&right, largest);
-                                    this.values[N - 2] = None;
-                                    this.len -= 1;
-                                    this.add(item);
-                                }
-                                return None;
-                            }
+                        new.add(item);
+                    }
+                    let key = new.key();
+                    let new = Rc::new(RefCell::new(Leaf(new)));
+                    if let Some(right) = this.right.upgrade() {
+                        if let Leaf(right) = &mut *right.borrow_mut() {
+                            right.left = Rc::downgrade(&new);
                         }
+                    }
+                    this.right = Rc::downgrade(&new);
+                    Some((key, new))
+                }
+                Internal(this) => {
+                    let i = this.keys[..this.len].iter().take_while(|k| **k <= item.key).count();
+                    let child = this.slots[i].clone().unwrap();
+                    let (key, node) = Self::insert(&child, item)?;
+                    if this.len < N - 1 {
+                        this.keys[i..].rotate_right(1);
+                        this.keys[i] = key;
+                        this.slots[i + 1..].rotate_right(1);
+                        this.slots[i + 1] = Some(node);
+                        this.len += 1;
+                        return None;
+                    }
 
-                        // should split
-                        let mut new = LeafNode::<K, V> {
-                            len: 0,
-                            left: Weak::new(),
-                            right: Weak::new(),
-                            values: Default::default(),
-                        };
-                        let mid = N / 2;
-                        let cmp = this.values[mid - 1].as_ref().unwrap().key;
-                        for i in 0..mid {
-                            new.values[i] = this.values[mid + i].clone();
-                            this.values[mid + i] = None;
-                        }
-                        this.len = mid;
-                        new.len = mid;
-                        if item.key <= cmp {
-                            this.add(item);
-                        } else {
-                            new.add(item);
-                        }
-                        let mut parent = IntNode::<K, V> {
-                            len: 1,
-                            left: Weak::new(),
-                            right: Weak::new(),
-                            keys: Default::default(),
-                            slots: Default::default(),
-                        };
-                        let new_key = new.key();
-                        let this_key = this.key();
-                        if this_key <= new_key {
-                            new.left = Rc::downgrade(p);
-                            new.right = this.right.clone();
-                        } else {
-                            new.left = this.left.clone();
-                            new.right = Rc::downgrade(p);
-                        }
-                        let new = Rc::new(RefCell::new(Leaf(new)));
-                        if this_key <= new_key {
-                            this.right = Rc::downgrade(&new);
-                        } else {
-                            this.left = Rc::downgrade(&new);
-                        }
-                        let slf = Rc::new(RefCell::new(Leaf(this.clone())));
-                        parent.keys[0] = if this_key <= new_key {
-                            parent.slots[0] = Some(slf);
-                            parent.slots[1] = Some(new);
-                            new_key
-                        } else {
-                            parent.slots[0] = Some(new);
-                            parent.slots[1] = Some(slf);
-                            this_key
-                        };
-                        Some(Internal(parent))
+                    // should split
+                    let mut keys = this.keys.to_vec();
+                    let mut slots: Vec<_> = this.slots.iter_mut().map(Option::take).collect();
+                    keys.insert(i, key);
+                    slots.insert(i + 1, Some(node));
+                    let mid = keys.len() / 2;
+                    let mut new = IntNode::<K, V> {
+                        len: keys.len() - mid - 1,
+                        left: Rc::downgrade(p),
+                        right: this.right.clone(),
+                        keys: Default::default(),
+                        slots: Default::default(),
+                    };
+                    new.keys[..new.len].copy_from_slice(&keys[mid + 1..]);
+                    for (k, slot) in slots.drain(mid + 1..).enumerate() {
+                        new.slots[k] = slot;
                     }
+                    this.len = mid;
+                    this.keys = Default::default();
+                    this.keys[..mid].copy_from_slice(&keys[..mid]);
+                    for (k, slot) in slots.into_iter().enumerate() {
+                        this.slots[k] = slot;
+                    }
+                    let new = Rc::new(RefCell::new(Internal(new)));
+                    this.right = Rc::downgrade(&new);
+                    Some((keys[mid], new))
+                }
+            }
+        }
+
+        fn get(&self, key: K) -> Option<V> {
+            match self {
+                Leaf(this) => this.values[..this.len].iter()
+                    .find(|v| v.as_ref().unwrap().key == key)
+                    .map(|v| v.as_ref().unwrap().val),
+                Internal(this) => {
+                    let i = this.keys[..this.len].iter().take_while(|k| **k <= key).count();
+                    this.slots[i].as_ref().unwrap().borrow().get(key)
                 }
-                Internal(_) => None,
             }
         }
     }
@@ -2156,7 +2182,7 @@ mod test_btree {
                     }
                 }
                 Internal(this) => {
-                    for i in 0..N - 1 as usize {
+                    for i in 0..N {
                         if let Some(child) = &this.slots[i] {
                             child.borrow().fmt(f)?;
                         } else {
@@ -2180,8 +2206,25 @@ mod test_btree {
     }
 
     impl<K: PartialOrd + Default + Copy, V: Default + Copy> BTree<K, V> {
-        pub fn insert(&mut self, _key: K, _val: V) {
-            // BTreeNode::insert(&self.root, key, val);
+        pub fn insert(&mut self, key: K, val: V) {
+            if let Some((key, right)) = BTreeNode::insert(&self.root, NodeItem { key, val }) {
+                // the tree grows in height
+                let mut root = IntNode::<K, V> {
+                    len: 1,
+                    left: Weak::new(),
+                    right: Weak::new(),
+                    keys: Default::default(),
+                    slots: Default::default(),
+                };
+                root.keys[0] = key;
+                root.slots[0] = Some(self.root.clone());
+                root.slots[1] = Some(right);
+                self.root = Rc::new(RefCell::new(Internal(root)));
+            }
+        }
+
+        pub fn get(&self, key: K) -> Option<V> {
+            self.root.borrow().get(key)
         }
     }
 
@@ -2199,7 +2242,17 @@ mod test_btree {
         btree.insert(20, 0);
         btree.insert(25, 0);
         btree.insert(50, 0);
+        btree.insert(20, 1);
+        assert_eq!(format!("{}", btree), "10 20 25 30 50 ");
+        assert_eq!(btree.get(20), Some(1));
 
+        for i in 0..100 {
+            btree.insert((i * 37) % 101, i);
+        }
+        for i in 0..100 {
+            assert_eq!(btree.get((i * 37) % 101), Some(i));
+        }
+        assert_eq!(btree.get(1000), None);
         println!("{}", btree);
     }
 }
\ No newline at end of file
@@ -280,7 +280,11 @@ pub struct SpinLock {
 
 impl SpinLock {
     pub fn acquire(lock: *mut u8) -> Self {
-        unsafe { while std::intrinsics::atomic_cxchg_acqrel(lock, 0, 1).0 == 1 {} }
+        unsafe {
+            while std::intrinsics::atomic_cxchg_acqrel(lock, 0, 1).0 == 1 {
+                crate::ll::model::spin_loop();
+            }
+        }
         Self { lock }
     }
 }
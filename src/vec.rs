@@ -1,6 +1,6 @@
 //! A contiguous growable array type with heap-allocated contents, written Vec<T>
 
-use crate::convert::PFrom;
+use crate::convert::{PFrom, PFromIterator};
 use crate::alloc::get_idx;
 use crate::alloc::MemPool;
 use crate::clone::PClone;
@@ -14,7 +14,7 @@ use std::marker::PhantomData;
 use std::ops::Index;
 use std::slice::SliceIndex;
 use std::vec::Vec as StdVec;
-use std::{mem, ptr, slice};
+use std::{mem, ptr};
 
 /// A contiguous growable persistent array type, written `Vec<T>` but pronounced
 /// 'vector'.
@@ -402,6 +402,30 @@ impl<T: PSafe, A: MemPool> Vec<T, A> {
         self.to_slice_mut()
     }
 
+    #[inline]
+    /// Returns an iterator which allows modifying the elements
+    ///
+    /// Like [`as_slice_mut()`], it logs the elements once before iterating.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::vec::Vec;
+    /// # use corundum::alloc::heap::*;
+    /// Heap::transaction(|j| {
+    ///     let mut vec = Vec::from_slice(&[1,2,3], j);
+    ///     for x in vec.iter_mut(j) {
+    ///         *x *= 2;
+    ///     }
+    ///     assert_eq!(vec, [2, 4, 6]);
+    /// }).unwrap();
+    /// ```
+    ///
+    /// [`as_slice_mut()`]: #method.as_slice_mut
+    pub fn iter_mut(&mut self, j: &Journal<A>) -> std::slice::IterMut<'_, T> {
+        self.as_slice_mut(j).iter_mut()
+    }
+
     #[inline]
     /// Consumes the `corundum::vec::Vec` and converts it into a standard [`std::vec::Vec`](std::vec::Vec)
     pub(crate) unsafe fn as_vec(&mut self) -> StdVec<T> {
@@ -445,8 +469,7 @@ impl<T: PSafe, A: MemPool> Vec<T, A> {
         if other.len() != 0 {
             unsafe {
                 let len = self.len;
-                let new_cap = self.capacity().max(len + other.len());
-                self.reserve(new_cap - self.capacity(), j);
+                self.reserve(other.len(), j);
                 let ptr = self.buf.as_mut_ptr();
                 ptr::copy(other.as_ptr(), ptr.add(len), other.len());
                 self.len += other.len();
@@ -454,31 +477,122 @@ impl<T: PSafe, A: MemPool> Vec<T, A> {
         }
     }
 
-    #[inline]
-    pub fn shrink_to(&mut self, new_cap: usize, j: &Journal<A>) {
-        let cap = self.capacity();
-        eprintln!("shrink_to");
+    /// Appends all items of `iter` with a single allocation and flush
+    ///
+    /// The items are gathered in volatile memory first; then, the current
+    /// elements and the new items are copied into a new buffer, which is
+    /// flushed once and swapped with the old one. Unlike [`push()`]ing the
+    /// items one by one, the old buffer is reallocated at most once.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::vec::Vec;
+    /// # use corundum::alloc::heap::*;
+    /// Heap::transaction(|j| {
+    ///     let mut vec = Vec::from_slice(&[1, 2], j);
+    ///     vec.extend_bulk(3..1000, j);
+    ///     assert_eq!(vec.len(), 999);
+    ///     assert_eq!(vec[998], 999);
+    /// }).unwrap();
+    /// ```
+    ///
+    /// [`push()`]: #method.push
+    pub fn extend_bulk<I: IntoIterator<Item = T>>(&mut self, iter: I, j: &Journal<A>) {
+        let mut items: StdVec<T> = iter.into_iter().collect();
+        if items.is_empty() {
+            return;
+        }
+        let len = self.len;
+        let new_len = len.checked_add(items.len()).expect("capacity overflow");
+        if mem::size_of::<T>() == 0 {
+            // There is nothing to copy; the items are moved by taking over
+            // their count
+            self.reserve(items.len(), j);
+            unsafe { items.set_len(0); }
+            self.len = new_len;
+            return;
+        }
+        unsafe {
+            let layout = Layout::array::<T>(new_len).unwrap();
+            let buf = A::new_uninit_for_layout(layout.size(), j) as *mut T;
+            if len != 0 {
+                ptr::copy_nonoverlapping(self.buf.as_mut_ptr(), buf, len);
+            }
+            ptr::copy_nonoverlapping(items.as_ptr(), buf.add(len), items.len());
+            items.set_len(0);
+            crate::ll::persist(buf, layout.size(), true);
+
+            // The elements are moved; the old buffer is freed without
+            // dropping them when the transaction commits
+            if self.capacity() != 0 {
+                A::free_slice(Self::__to_slice_mut(self.off(), self.capacity()));
+            }
+            self.buf = Slice::from_off_cap(A::off_unchecked(buf), new_len);
+            self.len = new_len;
+        }
+    }
 
-        // Prevent shrinking to smaller than data
-        let new_cap = new_cap.max(self.len);
-        if get_idx(new_cap * mem::size_of::<T>()) != get_idx(cap * mem::size_of::<T>()) {
+    /// Moves the elements to a buffer of exactly `new_cap` elements, or
+    /// returns an [`AllocError`] leaving the vector unchanged.
+    ///
+    /// Only the header of the vector (offset, capacity, and length) changes;
+    /// the elements are never logged. If the new capacity fits in the
+    /// current block, it only updates the capacity. Otherwise, the allocator
+    /// either resizes the block in place, or copies the elements into a new
+    /// block and frees the old one when the transaction commits.
+    ///
+    /// [`AllocError`]: ../struct.AllocError.html
+    fn try_realloc_buf(&mut self, new_cap: usize, j: &Journal<A>) -> Result<(), AllocError> {
+        debug_assert!(new_cap >= self.len);
+        let cap = self.capacity();
+        if new_cap == cap {
+            return Ok(());
+        }
+        let size = mem::size_of::<T>();
+        Layout::array::<T>(new_cap).map_err(|_| AllocError::new(usize::MAX))?;
+        if size == 0 || (cap != 0 && get_idx(new_cap * size) == get_idx(cap * size)) {
+            self.buf.set_cap(new_cap);
+        } else {
             unsafe {
-                let buf = self.to_slice_mut();
-                let (rem, left) = buf.split_at_mut(buf.len().min(new_cap));
-                if !left.is_empty() {
-                    ptr::drop_in_place(left);
-                    // FIXME: use power of 2 sizes padding to be able to free memory
-                    // of each individual item
-                }
-                if rem.is_empty() {
-                    self.buf = Slice::null();
-                } else {
-                    let new = A::new_slice(rem, j);
-                    self.buf = Slice::from_off_cap(A::off_unchecked(new), new.len());
-                }
+                let old = Self::__to_slice_mut(self.off(), cap);
+                let new = A::try_realloc(old, new_cap, j)?;
+                self.buf = Slice::new(new);
             }
         }
-        self.buf.set_cap(new_cap);
+        Ok(())
+    }
+
+    /// Shrinks the capacity of the vector with a lower bound.
+    ///
+    /// The capacity will remain at least as large as both the length and the
+    /// supplied value. If the current capacity is less than the lower limit,
+    /// this is a no-op. Like [`reserve`], it only logs the header of the
+    /// vector.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::vec::Vec;
+    /// # use corundum::alloc::heap::*;
+    /// Heap::transaction(|j| {
+    ///     let mut vec = Vec::with_capacity(100, j);
+    ///     vec.extend_from_slice(&[1, 2, 3], j);
+    ///     vec.shrink_to(10, j);
+    ///     assert!(vec.capacity() >= 10);
+    ///     vec.shrink_to(0, j);
+    ///     assert!(vec.capacity() >= 3);
+    /// }).unwrap();
+    /// ```
+    ///
+    /// [`reserve`]: #method.reserve
+    pub fn shrink_to(&mut self, min_capacity: usize, j: &Journal<A>) {
+        let new_cap = min_capacity.max(self.len);
+        if new_cap < self.capacity() {
+            // If there is no room for the smaller buffer, the vector keeps
+            // the larger one
+            let _ = self.try_realloc_buf(new_cap, j);
+        }
     }
 
     /// Shrinks the capacity of the vector as much as possible.
@@ -486,7 +600,7 @@ impl<T: PSafe, A: MemPool> Vec<T, A> {
     /// It will drop down as close as possible to the length but the allocator
     /// may still inform the vector that there is space for a few more elements.
     ///
-    /// # ExamplesSlice
+    /// # Examples
     ///
     /// ```
     /// # use corundum::vec::Vec;
@@ -500,16 +614,22 @@ impl<T: PSafe, A: MemPool> Vec<T, A> {
     /// }).unwrap();
     /// ```
     pub fn shrink_to_fit(&mut self, j: &Journal<A>) {
-        if self.capacity() != self.len {
-            self.shrink_to(self.len, j);
-        }
+        self.shrink_to(self.len, j);
     }
 
-    /// Copy all the elements of `other` into `Self`
+    /// Reserves capacity for at least `additional` more elements to be
+    /// inserted in the vector. The capacity grows geometrically to avoid
+    /// frequent reallocations; after calling `reserve`, the capacity is
+    /// greater than or equal to `self.len() + additional`. Does nothing if
+    /// the capacity is already sufficient.
+    ///
+    /// The elements are not logged; only the header of the vector changes,
+    /// and the old buffer is freed when the transaction commits.
     ///
     /// # Panics
     ///
-    /// Panics if the number of elements in the vector overflows a `usize`.
+    /// Panics if the new capacity overflows `usize`, or if the pool is
+    /// exhausted.
     ///
     /// # Examples
     ///
@@ -517,33 +637,89 @@ impl<T: PSafe, A: MemPool> Vec<T, A> {
     /// # use corundum::vec::Vec;
     /// # use corundum::alloc::heap::*;
     /// Heap::transaction(|j| {
-    ///     let mut vec = Vec::from_slice(&[1, 2, 3], j);
-    ///     let mut other = vec![4, 5, 6];
-    ///     vec.extend_from_slice(&other, j);
-    ///     assert_eq!(vec.as_slice(), [1, 2, 3, 4, 5, 6]);
+    ///     let mut vec = Vec::from_slice(&[1], j);
+    ///     vec.reserve(10, j);
+    ///     assert!(vec.capacity() >= 11);
     /// }).unwrap();
     /// ```
     #[inline]
     pub fn reserve(&mut self, additional: usize, j: &Journal<A>) {
-        if additional == 0 {
-            return;
+        if let Err(e) = self.try_reserve(additional, j) {
+            panic!("{}", e);
         }
+    }
 
-        let cap = self.buf.capacity();
-        let len = self.len;
-        let new_cap = cap.max(len + additional);
-        if get_idx(new_cap * mem::size_of::<T>()) == get_idx(len * mem::size_of::<T>()) {
-            self.buf.set_cap(new_cap);
-        } else {
-            unsafe {
-                let old = self.to_slice_mut();
-                let layout = Layout::array::<T>(new_cap).unwrap();
-                let new = A::new_uninit_for_layout(layout.size(), j).cast();
-                ptr::copy(old.as_ptr(), new, len);
-                A::free_slice(Self::__to_slice_mut(self.off(), self.capacity()));
-                self.buf = Slice::new(slice::from_raw_parts(new, new_cap));
-            }
+    /// Reserves the minimum capacity for exactly `additional` more elements
+    /// to be inserted in the vector. After calling `reserve_exact`, the
+    /// capacity is greater than or equal to `self.len() + additional`. Does
+    /// nothing if the capacity is already sufficient.
+    ///
+    /// Prefer [`reserve`] if future insertions are expected.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the new capacity overflows `usize`, or if the pool is
+    /// exhausted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::vec::Vec;
+    /// # use corundum::alloc::heap::*;
+    /// Heap::transaction(|j| {
+    ///     let mut vec = Vec::from_slice(&[1], j);
+    ///     vec.reserve_exact(10, j);
+    ///     assert_eq!(vec.capacity(), 11);
+    /// }).unwrap();
+    /// ```
+    ///
+    /// [`reserve`]: #method.reserve
+    #[inline]
+    pub fn reserve_exact(&mut self, additional: usize, j: &Journal<A>) {
+        if let Err(e) = self.try_reserve_exact(additional, j) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Tries to reserve capacity for at least `additional` more elements. It
+    /// returns an [`AllocError`] instead of panicking if the pool is
+    /// exhausted, in which case the vector is left unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::vec::Vec;
+    /// # use corundum::alloc::heap::*;
+    /// Heap::transaction(|j| {
+    ///     let mut vec = Vec::from_slice(&[1, 2, 3], j);
+    ///     vec.try_reserve(10, j).unwrap();
+    ///     assert!(vec.capacity() >= 13);
+    /// }).unwrap();
+    /// ```
+    ///
+    /// [`AllocError`]: ../struct.AllocError.html
+    pub fn try_reserve(&mut self, additional: usize, j: &Journal<A>) -> Result<(), AllocError> {
+        let cap = self.capacity();
+        let required = self.len.checked_add(additional)
+            .ok_or_else(|| AllocError::new(usize::MAX))?;
+        if required <= cap {
+            return Ok(());
+        }
+        self.try_realloc_buf(required.max(cap.saturating_mul(2)), j)
+    }
+
+    /// Tries to reserve the minimum capacity for exactly `additional` more
+    /// elements. It returns an [`AllocError`] instead of panicking if the pool
+    /// is exhausted, in which case the vector is left unchanged.
+    ///
+    /// [`AllocError`]: ../struct.AllocError.html
+    pub fn try_reserve_exact(&mut self, additional: usize, j: &Journal<A>) -> Result<(), AllocError> {
+        let required = self.len.checked_add(additional)
+            .ok_or_else(|| AllocError::new(usize::MAX))?;
+        if required <= self.capacity() {
+            return Ok(());
         }
+        self.try_realloc_buf(required, j)
     }
 
     /// Shortens the vector, keeping the first `len` elements and dropping
@@ -691,6 +867,7 @@ impl<T: PSafe, A: MemPool> Vec<T, A> {
         if len == self.buf.capacity() {
             self.reserve(1, j);
         }
+        self.log_range(index..len, j);
 
         unsafe {
             // infallible
@@ -722,13 +899,14 @@ impl<T: PSafe, A: MemPool> Vec<T, A> {
     /// # use corundum::alloc::heap::*;
     /// Heap::transaction(|j| {
     ///     let mut v = Vec::from_slice(&[1, 2, 3], j);
-    ///     assert_eq!(v.remove(1), 2);
+    ///     assert_eq!(v.remove(1, j), 2);
     ///     assert_eq!(v, [1, 3]);
     /// }).unwrap();
     /// ```
-    pub fn remove(&mut self, index: usize) -> T {
+    pub fn remove(&mut self, index: usize, j: &Journal<A>) -> T {
         let len = self.len();
         assert!(index < len);
+        self.log_range(index..len - 1, j);
         unsafe {
             // infallible
             let ret;
@@ -747,6 +925,16 @@ impl<T: PSafe, A: MemPool> Vec<T, A> {
         }
     }
 
+    /// Logs the elements in `range` before they are overwritten, unless the
+    /// whole vector is already logged by [`as_slice_mut()`]
+    ///
+    /// [`as_slice_mut()`]: #method.as_slice_mut
+    pub(crate) fn log_range(&mut self, range: std::ops::Range<usize>, j: &Journal<A>) {
+        if self.has_log == 0 && !range.is_empty() {
+            unsafe { self.to_slice_mut()[range].create_log(j, Notifier::None); }
+        }
+    }
+
     /// Retains only the elements specified by the predicate.
     ///
     /// In other words, remove all elements `e` such that `f(&e)` returns `false`.
@@ -1204,6 +1392,42 @@ impl<'a, T> Iterator for IterHelper<'a, T> {
     }
 }
 
+#[cfg(feature = "rayon")]
+impl<T: PSafe + Sync, A: MemPool> Vec<T, A> {
+    /// Returns a parallel iterator over the elements
+    ///
+    /// It is available with the `rayon` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::vec::Vec;
+    /// # use corundum::alloc::heap::*;
+    /// use rayon::prelude::*;
+    ///
+    /// Heap::transaction(|j| {
+    ///     let mut vec = Vec::new();
+    ///     vec.extend_bulk(0..1000u64, j);
+    ///     assert_eq!(vec.par_iter().sum::<u64>(), 999 * 1000 / 2);
+    /// }).unwrap();
+    /// ```
+    pub fn par_iter(&self) -> rayon::slice::Iter<'_, T> {
+        use rayon::prelude::*;
+        self.as_slice().par_iter()
+    }
+}
+
+impl<T: PSafe, A: MemPool> PFromIterator<T, A> for Vec<T, A> {
+    fn pfrom_iter<I: IntoIterator<Item = T>>(iter: I, j: &Journal<A>) -> Self {
+        let iter = iter.into_iter();
+        let mut res = Vec::with_capacity(iter.size_hint().0, j);
+        for x in iter {
+            res.push(x, j);
+        }
+        res
+    }
+}
+
 impl<T: PSafe, A: MemPool> AsRef<Vec<T, A>> for Vec<T, A> {
     fn as_ref(&self) -> &Vec<T, A> {
         self
@@ -1283,6 +1507,79 @@ impl<A: MemPool> Vec<u8, A> {
     }
 }
 
+/// Creates a persistent [`Vec`] containing the arguments
+///
+/// `pvec!` allows `Vec`s to be defined with the same syntax as array
+/// expressions, similar to `vec!`, except that the journal object comes first.
+/// There are three forms of this macro:
+///
+/// - Create an empty [`Vec`]:
+///
+/// ```
+/// # use corundum::alloc::heap::*;
+/// # use corundum::pvec;
+/// Heap::transaction(|j| {
+///     let v: PVec<i32> = pvec![j];
+///     assert!(v.is_empty());
+/// }).unwrap();
+/// ```
+///
+/// - Create a [`Vec`] containing a given list of elements:
+///
+/// ```
+/// # use corundum::alloc::heap::*;
+/// # use corundum::pvec;
+/// Heap::transaction(|j| {
+///     let v = pvec![j; 1, 2, 3];
+///     assert_eq!(v, [1, 2, 3]);
+/// }).unwrap();
+/// ```
+///
+/// - Create a [`Vec`] from a given element and size:
+///
+/// ```
+/// # use corundum::alloc::heap::*;
+/// # use corundum::pvec;
+/// Heap::transaction(|j| {
+///     let v = pvec![j; 1; 3];
+///     assert_eq!(v, [1, 1, 1]);
+/// }).unwrap();
+/// ```
+///
+/// Note that unlike array expressions this syntax supports all elements
+/// which implement [`Clone`] and the number of elements doesn't have to be
+/// a constant.
+///
+/// [`Vec`]: ./vec/struct.Vec.html
+#[macro_export]
+macro_rules! pvec {
+    (@unit $x:expr) => { () };
+    ($j:expr) => {{
+        let _ = $j;
+        $crate::vec::Vec::new()
+    }};
+    ($j:expr; $elem:expr; $n:expr) => {{
+        let __j = $j;
+        let __n = $n;
+        let __e = $elem;
+        let mut __v = $crate::vec::Vec::with_capacity(__n, __j);
+        for _ in 1..__n {
+            __v.push(::std::clone::Clone::clone(&__e), __j);
+        }
+        if __n > 0 {
+            __v.push(__e, __j);
+        }
+        __v
+    }};
+    ($j:expr; $($x:expr),+ $(,)?) => {{
+        let __j = $j;
+        let mut __v = $crate::vec::Vec::with_capacity(
+            <[()]>::len(&[$($crate::pvec!(@unit $x)),+]), __j);
+        $( __v.push($x, __j); )+
+        __v
+    }};
+}
+
 #[cfg(test)]
 mod test {
     use crate::RootObj;
@@ -1365,6 +1662,60 @@ mod test {
         println!("post usage = {} bytes", A::used());
     }
 
+    #[test]
+    fn test_pvec_macro() {
+        crate::heap::Heap::transaction(|j| {
+            let v = pvec![j; 1, 2, 3];
+            assert_eq!(v, [1, 2, 3]);
+            let v = pvec![j; 7u8; 2];
+            assert_eq!(v, [7, 7]);
+            let v: crate::heap::PVec<u8> = pvec![j];
+            assert!(v.is_empty());
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_try_reserve() {
+        let _pool = A::open_no_root("sb6.pool", O_CFNE).unwrap();
+        A::transaction(|j| {
+            let mut v = PVec::<u64>::new();
+            assert_eq!(v.try_reserve(1 << 30, j).unwrap_err().size(), 8 << 30);
+            assert!(v.try_reserve(16, j).is_ok());
+            assert!(v.capacity() >= 16);
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_capacity() {
+        let _pool = A::open_no_root("sb6.pool", O_CFNE).unwrap();
+        A::transaction(|j| {
+            let mut v = PVec::<u64>::new();
+            v.reserve_exact(3, j);
+            assert_eq!(v.capacity(), 3);
+            for i in 0..100 {
+                v.push(i, j);
+            }
+            assert!(v.capacity() >= 100);
+            v.reserve(200, j);
+            assert!(v.capacity() >= 300);
+            v.reserve_exact(1000, j);
+            assert_eq!(v.capacity(), 1100);
+            v.shrink_to(10, j);
+            assert_eq!(v.capacity(), 100);
+            v.truncate(50);
+            v.shrink_to_fit(j);
+            assert_eq!(v.capacity(), 50);
+            assert_eq!(v.len(), 50);
+            assert_eq!(v[49], 49);
+            v.clear();
+            v.shrink_to_fit(j);
+            assert_eq!(v.capacity(), 0);
+        })
+        .unwrap();
+    }
+
     #[test]
     fn test_clear() {
         use crate::vec::Vec;
@@ -1375,4 +1726,17 @@ mod test {
         })
         .unwrap();
     }
+
+    #[test]
+    fn test_extend_bulk_zst() {
+        use crate::vec::Vec;
+        crate::heap::Heap::transaction::<_, _>(|j| {
+            let mut vec = Vec::from_slice(&[(), ()], j);
+            vec.extend_bulk(std::iter::repeat(()).take(1000), j);
+            assert_eq!(vec.len(), 1002);
+            assert!(vec.capacity() >= 1002);
+            assert_eq!(vec.pop(), Some(()));
+        })
+        .unwrap();
+    }
 }
@@ -0,0 +1,178 @@
+//! Tests of the C API generated by `#[derive(Export)]`, `#[export]`, and
+//! `carbide!`
+//!
+//! The functions are called through their C symbols, which take the objects
+//! by their handles. The headers are written
+//! only if the tests are built with `CBINDGEN=1`; otherwise, their checks are
+//! skipped.
+
+#![cfg(feature = "cbindings")]
+
+use corundum::*;
+use corundum::open_flags::O_CF;
+use std::marker::PhantomData;
+
+// The generated `open` functions take the root type of the pool
+use __p::__p_root_t;
+
+#[derive(Export)]
+#[mods(p)]
+#[attrs(allow_no_generics)]
+pub struct Counter<P: MemPool> {
+    count: u64,
+    phantom: PhantomData<P>
+}
+
+impl<P: MemPool> Counter<P> {
+    pub(crate) fn new(_j: &Journal<P>) -> Self {
+        Self { count: 3, phantom: PhantomData }
+    }
+}
+
+#[export]
+impl<P: MemPool> Counter<P> {
+    pub const CAPACITY: u64 = 8;
+
+    pub fn reset(&mut self) -> bool {
+        self.count = 0;
+        true
+    }
+
+    pub fn counts(&self, n: u64) -> impl Iterator<Item = u64> + '_ {
+        std::iter::repeat(&self.count).take(n as usize).copied()
+    }
+}
+
+#[derive(Export)]
+#[mods(p)]
+#[attrs(allow_no_generics)]
+pub enum Shape<P: MemPool> {
+    Circle(u64),
+    Rect { w: u64, h: u64 },
+    Empty(PhantomData<P>)
+}
+
+impl<P: MemPool> Shape<P> {
+    pub(crate) fn new(_j: &Journal<P>) -> Self {
+        Shape::Circle(5)
+    }
+}
+
+carbide! {
+    mods(p);
+    types(Counter, Shape);
+    output("target/carbide/export");
+    allow(overwrite)
+}
+
+extern "C" {
+    fn __p_counter_new(j: *const c_void) -> u64;
+    fn __p_counter_drop(obj: u64) -> bool;
+    fn __p_counter_handle(obj: *const c_void) -> u64;
+    fn __p_counter_resolve(obj: u64) -> *const c_void;
+    #[allow(non_snake_case)]
+    fn __p_counter_CAPACITY() -> u64;
+    fn __p_counter_reset(obj: u64) -> bool;
+    fn __p_counter_counts_iter_new(obj: u64, n: u64) -> *mut c_void;
+    fn __p_counter_counts_iter_next(cursor: *mut c_void, out: *mut u64) -> bool;
+    fn __p_counter_counts_iter_free(cursor: *mut c_void);
+    fn __p_shape_new(j: *const c_void) -> u64;
+    fn __p_shape_drop(obj: u64) -> bool;
+    fn __p_shape_tag(obj: u64) -> u32;
+    fn __p_shape_circle_0(obj: u64) -> *const c_void;
+    fn __p_shape_rect_w(obj: u64) -> *const c_void;
+}
+
+const PATH: &str = "export.pool";
+
+fn new_obj(f: unsafe extern "C" fn(*const c_void) -> u64) -> u64 {
+    p::transaction(|j| unsafe {
+        f(j as *const p::Journal as *const c_void)
+    }).unwrap()
+}
+
+#[test]
+fn exported_functions() {
+    let pool = p::Allocator::open_no_root(PATH, O_CF).unwrap();
+    unsafe {
+        assert_eq!(__p_counter_CAPACITY(), 8);
+
+        let obj = new_obj(__p_counter_new);
+        assert_ne!(obj, 0);
+        let ptr = __p_counter_resolve(obj);
+        assert!(!ptr.is_null());
+        assert_eq!(__p_counter_handle(ptr), obj);
+        assert_eq!(__p_counter_handle(std::ptr::null()), 0);
+
+        let cursor = __p_counter_counts_iter_new(obj, 2);
+        assert!(!cursor.is_null());
+
+        // The object is borrowed by the cursor
+        assert!(!__p_counter_reset(obj));
+        assert!(!__p_counter_drop(obj));
+
+        let mut out = 0;
+        assert!(!__p_counter_counts_iter_next(cursor, std::ptr::null_mut()));
+        assert!(__p_counter_counts_iter_next(cursor, &mut out));
+        assert_eq!(out, 3);
+        assert!(__p_counter_counts_iter_next(cursor, &mut out));
+        assert!(!__p_counter_counts_iter_next(cursor, &mut out));
+        __p_counter_counts_iter_free(cursor);
+
+        assert!(__p_counter_reset(obj));
+        let cursor = __p_counter_counts_iter_new(obj, 1);
+        assert!(__p_counter_counts_iter_next(cursor, &mut out));
+        assert_eq!(out, 0);
+        __p_counter_counts_iter_free(cursor);
+
+        assert!(!__p_counter_counts_iter_next(std::ptr::null_mut(), &mut out));
+        assert!(!__p_counter_reset(0));
+        assert!(__p_counter_counts_iter_new(0, 1).is_null());
+        assert!(!__p_counter_drop(0));
+        assert!(__p_counter_drop(obj));
+
+        // The handle is stale once the object is dropped
+        assert!(__p_counter_resolve(obj).is_null());
+        assert!(!__p_counter_reset(obj));
+        assert!(!__p_counter_drop(obj));
+
+        let obj = new_obj(__p_shape_new);
+        assert_eq!(__p_shape_tag(obj), 0);
+        assert_eq!(*(__p_shape_circle_0(obj) as *const u64), 5);
+        assert!(__p_shape_rect_w(obj).is_null());
+
+        assert_eq!(__p_shape_tag(0), u32::MAX);
+        assert!(__p_shape_circle_0(0).is_null());
+        assert!(__p_shape_drop(obj));
+    }
+    drop(pool);
+    let _ = std::fs::remove_file(PATH);
+}
+
+#[test]
+fn exported_headers() {
+    if option_env!("CBINDGEN") != Some("1") {
+        return;
+    }
+    let read = |name| std::fs::read_to_string(format!("target/carbide/export/{}.hpp", name)).unwrap();
+
+    let counter = read("counter");
+    assert!(counter.contains("static bool drop(__Counter<_P> *obj);"));
+    assert!(counter.contains("object is borrowed by a live cursor"));
+    assert!(counter.contains("CAPACITY("));
+    assert!(counter.contains("counts_iter_new("));
+    assert!(counter.contains("counts_iter_next("));
+    assert!(counter.contains("counts_iter_free("));
+
+    let shape = read("shape");
+    assert!(shape.contains("enum class tag_t : u_int32_t { Circle = 0, Rect = 1, Empty = 2 };"));
+    assert!(shape.contains("static u_int32_t tag(const __Shape<_P> *obj);"));
+    assert!(shape.contains("static const void *circle_0(const __Shape<_P> *obj);"));
+    assert!(shape.contains("const u_int64_t *w; const u_int64_t *h;"));
+
+    // The specializations for pool `p` convert the pointers to handles
+    assert!(counter.contains("static bool drop(__Counter<p> *obj) {"));
+    assert!(counter.contains("return __p_counter_resolve(__p_counter_new(j));"));
+    assert!(counter.contains("__p_counter_reset(__p_counter_handle(__self));"));
+    assert!(shape.contains("return __p_shape_tag(__p_shape_handle(obj));"));
+}